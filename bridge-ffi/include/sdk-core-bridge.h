@@ -43,6 +43,17 @@ typedef struct tmprl_bytes_t {
   bool disable_free;
 } tmprl_bytes_t;
 
+/**
+ * Callback passed to [tmprl_runtime_set_log_callback]. Like [tmprl_callback], but may be null.
+ */
+typedef void (*tmprl_log_callback)(void *user_data, const struct tmprl_bytes_t *core);
+
+/**
+ * Callback passed to [tmprl_runtime_set_metrics_callback]. Like [tmprl_callback], but may be
+ * null.
+ */
+typedef void (*tmprl_metrics_callback)(void *user_data, const struct tmprl_bytes_t *core);
+
 /**
  * Callback called by [tmprl_worker_init] on completion. The first parameter of the
  * callback is user data passed into the original function. The second
@@ -88,6 +99,40 @@ struct tmprl_runtime_t *tmprl_runtime_new(void);
  */
 void tmprl_runtime_free(struct tmprl_runtime_t *runtime);
 
+/**
+ * Push buffered logs to lang instead of requiring it to call [tmprl_fetch_buffered_logs].
+ *
+ * Every `interval_millis` milliseconds (or every 100 milliseconds if zero), any logs buffered
+ * since the last call are drained and the callback is invoked from a thread of `runtime` with a
+ * [bridge::FetchBufferedLogsResponse] protobuf message, which must be freed via
+ * [tmprl_bytes_free]. The callback is not invoked when there are no new logs. Logs are only
+ * buffered if telemetry was initialized with log forwarding enabled.
+ *
+ * Calling this again replaces the previous callback. Passing a null callback stops pushing logs.
+ * Logs stop being pushed when the runtime is freed.
+ */
+void tmprl_runtime_set_log_callback(struct tmprl_runtime_t *runtime,
+                                    uint64_t interval_millis,
+                                    void *user_data,
+                                    tmprl_log_callback callback);
+
+/**
+ * Push metrics to lang, so it can export them with its own metrics library.
+ *
+ * Every `interval_millis` milliseconds (or every second if zero), the current value of every
+ * metric is collected and the callback is invoked from a thread of `runtime` with a
+ * [bridge::FetchMetricsResponse] protobuf message, which must be freed via [tmprl_bytes_free].
+ * Values are cumulative since the process started. The callback is not invoked before any metric
+ * is recorded. Metrics are only collected if telemetry was initialized with metrics forwarding.
+ *
+ * Calling this again replaces the previous callback. Passing a null callback stops pushing
+ * metrics. Metrics stop being pushed when the runtime is freed.
+ */
+void tmprl_runtime_set_metrics_callback(struct tmprl_runtime_t *runtime,
+                                        uint64_t interval_millis,
+                                        void *user_data,
+                                        tmprl_metrics_callback callback);
+
 /**
  * Create a new worker instance.
  *
@@ -16,11 +16,18 @@ mod wrappers;
 
 use bridge::{init_response, CreateWorkerRequest, InitResponse};
 use prost::Message;
-use std::sync::Arc;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use temporal_sdk_core::{
-    fetch_global_buffered_logs, telemetry_init, Client, ClientOptions, RetryClient,
+    fetch_global_buffered_logs, fetch_global_metrics, telemetry_init, Client, ClientOptions,
+    RetryClient,
+};
+use temporal_sdk_core_api::{
+    metrics::{CoreMetricValue, MetricValue},
+    Worker,
 };
-use temporal_sdk_core_api::Worker;
 use temporal_sdk_core_protos::coresdk::{
     bridge,
     bridge::{CreateClientRequest, InitTelemetryRequest},
@@ -99,7 +106,7 @@ pub extern "C" fn tmprl_bytes_free(worker: *mut tmprl_worker_t, bytes: *const tm
         worker.return_buf(vec);
     }
     unsafe {
-        Box::from_raw(bytes);
+        drop(Box::from_raw(bytes));
     }
 }
 
@@ -152,6 +159,21 @@ lazy_static::lazy_static! {
 pub struct tmprl_runtime_t {
     // This is the same runtime shared with worker instances
     tokio_runtime: Arc<tokio::runtime::Runtime>,
+    // Task pushing buffered logs to lang, see [tmprl_runtime_set_log_callback]
+    log_forwarder: Mutex<Option<tokio::task::JoinHandle<()>>>,
+    // Task pushing metrics to lang, see [tmprl_runtime_set_metrics_callback]
+    metrics_forwarder: Mutex<Option<tokio::task::JoinHandle<()>>>,
+}
+
+impl Drop for tmprl_runtime_t {
+    fn drop(&mut self) {
+        // Workers may keep the tokio runtime alive, so stop forwarding explicitly
+        for forwarder in [&self.log_forwarder, &self.metrics_forwarder] {
+            if let Some(task) = forwarder.lock().unwrap().take() {
+                task.abort();
+            }
+        }
+    }
 }
 
 /// Create a new runtime. The result is never null and must be freed via
@@ -166,6 +188,8 @@ pub extern "C" fn tmprl_runtime_new() -> *mut tmprl_runtime_t {
                 .build()
                 .unwrap(),
         ),
+        log_forwarder: Mutex::new(None),
+        metrics_forwarder: Mutex::new(None),
     }))
 }
 
@@ -174,8 +198,121 @@ pub extern "C" fn tmprl_runtime_new() -> *mut tmprl_runtime_t {
 pub extern "C" fn tmprl_runtime_free(runtime: *mut tmprl_runtime_t) {
     if !runtime.is_null() {
         unsafe {
-            Box::from_raw(runtime);
+            drop(Box::from_raw(runtime));
+        }
+    }
+}
+
+/// Callback passed to [tmprl_runtime_set_log_callback]. Like [tmprl_callback], but may be null.
+type tmprl_log_callback =
+    Option<unsafe extern "C" fn(user_data: *mut libc::c_void, core: *const tmprl_bytes_t)>;
+
+/// Callback passed to [tmprl_runtime_set_metrics_callback]. Like [tmprl_callback], but may be
+/// null.
+type tmprl_metrics_callback =
+    Option<unsafe extern "C" fn(user_data: *mut libc::c_void, core: *const tmprl_bytes_t)>;
+
+/// Push buffered logs to lang instead of requiring it to call [tmprl_fetch_buffered_logs].
+///
+/// Every `interval_millis` milliseconds (or every 100 milliseconds if zero), any logs buffered
+/// since the last call are drained and the callback is invoked from a thread of `runtime` with a
+/// [bridge::FetchBufferedLogsResponse] protobuf message, which must be freed via
+/// [tmprl_bytes_free]. The callback is not invoked when there are no new logs. Logs are only
+/// buffered if telemetry was initialized with log forwarding enabled.
+///
+/// Calling this again replaces the previous callback. Passing a null callback stops pushing logs.
+/// Logs stop being pushed when the runtime is freed.
+#[no_mangle]
+pub extern "C" fn tmprl_runtime_set_log_callback(
+    runtime: *mut tmprl_runtime_t,
+    interval_millis: u64,
+    user_data: *mut libc::c_void,
+    callback: tmprl_log_callback,
+) {
+    let runtime = unsafe { &*runtime };
+    let interval = Duration::from_millis(if interval_millis == 0 {
+        100
+    } else {
+        interval_millis
+    });
+    runtime.forward(
+        &runtime.log_forwarder,
+        interval,
+        user_data,
+        callback,
+        || {
+            let resp = buffered_logs_response();
+            (!resp.entries.is_empty()).then(|| resp.encode_to_vec())
+        },
+    );
+}
+
+/// Push metrics to lang, so it can export them with its own metrics library.
+///
+/// Every `interval_millis` milliseconds (or every second if zero), the current value of every
+/// metric is collected and the callback is invoked from a thread of `runtime` with a
+/// [bridge::FetchMetricsResponse] protobuf message, which must be freed via [tmprl_bytes_free].
+/// Values are cumulative since the process started. The callback is not invoked before any metric
+/// is recorded. Metrics are only collected if telemetry was initialized with metrics forwarding.
+///
+/// Calling this again replaces the previous callback. Passing a null callback stops pushing
+/// metrics. Metrics stop being pushed when the runtime is freed.
+#[no_mangle]
+pub extern "C" fn tmprl_runtime_set_metrics_callback(
+    runtime: *mut tmprl_runtime_t,
+    interval_millis: u64,
+    user_data: *mut libc::c_void,
+    callback: tmprl_metrics_callback,
+) {
+    let runtime = unsafe { &*runtime };
+    let interval = Duration::from_millis(if interval_millis == 0 {
+        1000
+    } else {
+        interval_millis
+    });
+    runtime.forward(
+        &runtime.metrics_forwarder,
+        interval,
+        user_data,
+        callback,
+        || {
+            let resp = metrics_response();
+            (!resp.metrics.is_empty()).then(|| resp.encode_to_vec())
+        },
+    );
+}
+
+impl tmprl_runtime_t {
+    /// Replace the task in `forwarder` with one invoking `callback` every `interval` with whatever
+    /// `collect` returns, if anything. A null callback only stops the previous task.
+    fn forward(
+        &self,
+        forwarder: &Mutex<Option<tokio::task::JoinHandle<()>>>,
+        interval: Duration,
+        user_data: *mut libc::c_void,
+        callback: Option<unsafe extern "C" fn(*mut libc::c_void, *const tmprl_bytes_t)>,
+        collect: fn() -> Option<Vec<u8>>,
+    ) {
+        let mut forwarder = forwarder.lock().unwrap();
+        if let Some(prev) = forwarder.take() {
+            prev.abort();
         }
+        let callback = match callback {
+            Some(cb) => cb,
+            None => return,
+        };
+        let user_data = UserDataHandle(user_data);
+        *forwarder = Some(self.tokio_runtime.spawn(async move {
+            // Capture the whole handle, rather than just the pointer inside it which isn't Send
+            let user_data = user_data;
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                if let Some(bytes) = collect() {
+                    unsafe { callback(user_data.0, tmprl_bytes_t::from_vec(bytes).into_raw()) };
+                }
+            }
+        }));
     }
 }
 
@@ -512,7 +649,10 @@ pub extern "C" fn tmprl_complete_workflow_activation(
         Ok(req) => req,
         Err(message) => {
             let resp = bridge::CompleteWorkflowActivationResponse {
-                error: Some(bridge::complete_workflow_activation_response::Error { message }),
+                error: Some(bridge::complete_workflow_activation_response::Error {
+                    message,
+                    ..Default::default()
+                }),
             };
             unsafe {
                 callback(user_data, worker.encode_proto(&resp).into_raw());
@@ -684,9 +824,20 @@ pub extern "C" fn tmprl_fetch_buffered_logs(
     callback: tmprl_callback,
 ) {
     let user_data = UserDataHandle(user_data);
-    // We intentionally spawn even though the core call is not async so the
-    // callback can be made in the tokio runtime
-    let resp = bridge::FetchBufferedLogsResponse {
+    let resp = buffered_logs_response();
+
+    unsafe {
+        callback(
+            user_data.into(),
+            // TODO: Creates vec every time since no worker/core instance. Can be fixed with a
+            //   pool if optimizations needed.
+            tmprl_bytes_t::from_vec(resp.encode_to_vec()).into_raw(),
+        )
+    };
+}
+
+fn buffered_logs_response() -> bridge::FetchBufferedLogsResponse {
+    bridge::FetchBufferedLogsResponse {
         entries: fetch_global_buffered_logs()
             .into_iter()
             .map(|log| bridge::fetch_buffered_logs_response::LogEntry {
@@ -701,16 +852,50 @@ pub extern "C" fn tmprl_fetch_buffered_logs(
                 },
             })
             .collect(),
-    };
+    }
+}
 
-    unsafe {
-        callback(
-            user_data.into(),
-            // TODO: Creates vec every time since no worker/core instance. Can be fixed with a
-            //   pool if optimizations needed.
-            tmprl_bytes_t::from_vec(resp.encode_to_vec()).into_raw(),
-        )
-    };
+fn metrics_response() -> bridge::FetchMetricsResponse {
+    use bridge::fetch_metrics_response::{metric::Value, Histogram, Metric};
+
+    bridge::FetchMetricsResponse {
+        metrics: fetch_global_metrics()
+            .into_iter()
+            .map(|metric| Metric {
+                name: metric.name,
+                description: metric.description,
+                unit: metric.unit,
+                attributes: metric
+                    .attributes
+                    .into_iter()
+                    .map(|kv| {
+                        let value = match kv.value {
+                            MetricValue::String(v) => v,
+                            MetricValue::Int(v) => v.to_string(),
+                            MetricValue::Float(v) => v.to_string(),
+                            MetricValue::Bool(v) => v.to_string(),
+                        };
+                        (kv.key, value)
+                    })
+                    .collect(),
+                value: Some(match metric.value {
+                    CoreMetricValue::Sum(v) => Value::Sum(v),
+                    CoreMetricValue::LastValue(v) => Value::LastValue(v),
+                    CoreMetricValue::Histogram {
+                        count,
+                        sum,
+                        bucket_boundaries,
+                        bucket_counts,
+                    } => Value::Histogram(Histogram {
+                        count,
+                        sum,
+                        bucket_boundaries,
+                        bucket_counts,
+                    }),
+                }),
+            })
+            .collect(),
+    }
 }
 
 impl tmprl_worker_t {
@@ -769,6 +954,10 @@ impl tmprl_worker_t {
             .await
             .map_err(|err| bridge::complete_workflow_activation_response::Error {
                 message: format!("{}", err),
+                activation_not_outstanding: matches!(
+                    err,
+                    temporal_sdk_core_api::errors::CompleteWfError::ActivationNotOutstanding { .. }
+                ),
             })
     }
 
@@ -823,3 +1012,79 @@ impl tmprl_worker_t {
             .map_err(|err| format!("failed decoding proto: {}", err))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::LevelFilter;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use temporal_sdk_core::{Logger, MetricsExporter, TelemetryOptionsBuilder};
+    use temporal_sdk_core_api::CoreTelemetry;
+
+    unsafe extern "C" fn send_bytes(user_data: *mut libc::c_void, bytes: *const tmprl_bytes_t) {
+        let tx = &*(user_data as *const Mutex<Sender<Vec<u8>>>);
+        let data = std::slice::from_raw_parts((*bytes).bytes, (*bytes).len).to_vec();
+        tmprl_bytes_free(std::ptr::null_mut(), bytes);
+        let _ = tx.lock().unwrap().send(data);
+    }
+
+    fn recv_until<T: Message + Default>(rx: &Receiver<Vec<u8>>, done: impl Fn(&T) -> bool) {
+        loop {
+            let bytes = rx
+                .recv_timeout(Duration::from_secs(5))
+                .expect("Callback must be invoked");
+            if done(&T::decode(bytes.as_slice()).unwrap()) {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn logs_and_metrics_are_pushed_to_callbacks() {
+        let telem = telemetry_init(
+            &TelemetryOptionsBuilder::default()
+                .logging(Logger::Forward(LevelFilter::Info))
+                .metrics(MetricsExporter::Forward)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+        let runtime = tmprl_runtime_new();
+        let (log_tx, log_rx) = channel();
+        let log_tx = Mutex::new(log_tx);
+        let (metrics_tx, metrics_rx) = channel();
+        let metrics_tx = Mutex::new(metrics_tx);
+        tmprl_runtime_set_log_callback(
+            runtime,
+            10,
+            &log_tx as *const _ as *mut libc::c_void,
+            Some(send_bytes),
+        );
+        tmprl_runtime_set_metrics_callback(
+            runtime,
+            10,
+            &metrics_tx as *const _ as *mut libc::c_void,
+            Some(send_bytes),
+        );
+
+        log::info!("Forward me");
+        let meter = telem.get_core_meter().unwrap();
+        meter
+            .counter("bridge_forwarded_counter".into())
+            .add(3, &meter.new_attributes(vec![]));
+
+        recv_until(&log_rx, |resp: &bridge::FetchBufferedLogsResponse| {
+            resp.entries
+                .iter()
+                .any(|e| e.message.contains("Forward me"))
+        });
+        recv_until(&metrics_rx, |resp: &bridge::FetchMetricsResponse| {
+            resp.metrics.iter().any(|m| {
+                m.name == "bridge_forwarded_counter"
+                    && m.value == Some(bridge::fetch_metrics_response::metric::Value::Sum(3.))
+            })
+        });
+
+        tmprl_runtime_free(runtime);
+    }
+}
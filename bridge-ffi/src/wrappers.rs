@@ -52,27 +52,48 @@ impl TryFrom<InitTelemetryRequest> for temporal_sdk_core::TelemetryOptions {
                         .map_err(|err| format!("invalid Prometheus address: {}", err))?,
                 ));
             }
+            Some(bridge::init_telemetry_request::Metrics::ForwardMetrics(_)) => {
+                telemetry_opts.metrics(MetricsExporter::Forward);
+            }
             Some(bridge::init_telemetry_request::Metrics::OtelMetrics(
-                bridge::init_telemetry_request::OtelCollectorOptions { url, headers },
+                bridge::init_telemetry_request::OtelCollectorOptions {
+                    url,
+                    headers,
+                    metric_periodicity,
+                    resource_attributes,
+                },
             )) => {
+                let metric_periodicity = metric_periodicity
+                    .map(|v| v.try_into())
+                    .transpose()
+                    .map_err(|_| "invalid metric periodicity".to_string())?;
                 telemetry_opts.metrics(MetricsExporter::Otel(OtelCollectorOptions {
                     url: Url::parse(&url).map_err(|err| {
                         format!("invalid OpenTelemetry collector URL for metrics: {}", err)
                     })?,
                     headers,
+                    metric_periodicity,
+                    resource_attributes,
                 }));
             }
         }
         match req.tracing {
             None => {}
             Some(bridge::init_telemetry_request::Tracing::OtelTracing(
-                bridge::init_telemetry_request::OtelCollectorOptions { url, headers },
+                bridge::init_telemetry_request::OtelCollectorOptions {
+                    url,
+                    headers,
+                    resource_attributes,
+                    ..
+                },
             )) => {
                 telemetry_opts.tracing(TraceExporter::Otel(OtelCollectorOptions {
                     url: Url::parse(&url).map_err(|err| {
                         format!("invalid OpenTelemetry collector URL for tracing: {}", err)
                     })?,
                     headers,
+                    metric_periodicity: None,
+                    resource_attributes,
                 }));
             }
         }
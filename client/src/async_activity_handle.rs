@@ -0,0 +1,214 @@
+use crate::{InterceptedMetricsSvc, RawClientLike, WorkflowService};
+use temporal_sdk_core_protos::{
+    temporal::api::{
+        common::v1::Payloads,
+        failure::v1::Failure,
+        workflowservice::v1::{
+            RecordActivityTaskHeartbeatByIdRequest, RecordActivityTaskHeartbeatRequest,
+            RespondActivityTaskCanceledByIdRequest, RespondActivityTaskCanceledRequest,
+            RespondActivityTaskCompletedByIdRequest, RespondActivityTaskCompletedRequest,
+            RespondActivityTaskFailedByIdRequest, RespondActivityTaskFailedRequest,
+        },
+    },
+    TaskToken,
+};
+use tonic::Status;
+
+/// Identifies an activity which is being completed outside of the worker that received it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ActivityIdentifier {
+    /// The task token the worker received the activity with
+    TaskToken(TaskToken),
+    /// The activity's id within a workflow execution
+    ById {
+        /// The id of the workflow which scheduled the activity
+        workflow_id: String,
+        /// The run of the workflow which scheduled the activity. May be left blank to target the
+        /// latest run.
+        run_id: String,
+        /// The activity's id
+        activity_id: String,
+    },
+}
+
+impl From<TaskToken> for ActivityIdentifier {
+    fn from(tt: TaskToken) -> Self {
+        Self::TaskToken(tt)
+    }
+}
+
+/// A handle to an activity whose worker completed it with `will_complete_async`, which can be
+/// used to heartbeat and resolve the activity from anywhere the client is available.
+pub struct AsyncActivityHandle<CT> {
+    client: CT,
+    namespace: String,
+    identity: String,
+    identifier: ActivityIdentifier,
+}
+
+impl<CT> AsyncActivityHandle<CT>
+where
+    CT: RawClientLike<SvcType = InterceptedMetricsSvc> + Clone,
+{
+    pub(crate) fn new(
+        client: CT,
+        namespace: String,
+        identity: String,
+        identifier: ActivityIdentifier,
+    ) -> Self {
+        Self {
+            client,
+            namespace,
+            identity,
+            identifier,
+        }
+    }
+
+    /// The activity this handle refers to
+    pub fn identifier(&self) -> &ActivityIdentifier {
+        &self.identifier
+    }
+
+    /// Record a heartbeat for the activity. Returns true if cancellation of the activity has been
+    /// requested, in which case it should be resolved with [Self::report_cancellation].
+    pub async fn heartbeat(&self, details: Option<Payloads>) -> Result<bool, Status> {
+        let mut client = self.client.clone();
+        let cancel_requested = match &self.identifier {
+            ActivityIdentifier::TaskToken(tt) => {
+                client
+                    .record_activity_task_heartbeat(RecordActivityTaskHeartbeatRequest {
+                        task_token: tt.0.clone(),
+                        details,
+                        identity: self.identity.clone(),
+                        namespace: self.namespace.clone(),
+                    })
+                    .await?
+                    .into_inner()
+                    .cancel_requested
+            }
+            ActivityIdentifier::ById {
+                workflow_id,
+                run_id,
+                activity_id,
+            } => {
+                client
+                    .record_activity_task_heartbeat_by_id(RecordActivityTaskHeartbeatByIdRequest {
+                        namespace: self.namespace.clone(),
+                        workflow_id: workflow_id.clone(),
+                        run_id: run_id.clone(),
+                        activity_id: activity_id.clone(),
+                        details,
+                        identity: self.identity.clone(),
+                    })
+                    .await?
+                    .into_inner()
+                    .cancel_requested
+            }
+        };
+        Ok(cancel_requested)
+    }
+
+    /// Complete the activity successfully with the provided result
+    pub async fn complete(&self, result: Option<Payloads>) -> Result<(), Status> {
+        let mut client = self.client.clone();
+        match &self.identifier {
+            ActivityIdentifier::TaskToken(tt) => {
+                client
+                    .respond_activity_task_completed(RespondActivityTaskCompletedRequest {
+                        task_token: tt.0.clone(),
+                        result,
+                        identity: self.identity.clone(),
+                        namespace: self.namespace.clone(),
+                    })
+                    .await?;
+            }
+            ActivityIdentifier::ById {
+                workflow_id,
+                run_id,
+                activity_id,
+            } => {
+                client
+                    .respond_activity_task_completed_by_id(
+                        RespondActivityTaskCompletedByIdRequest {
+                            namespace: self.namespace.clone(),
+                            workflow_id: workflow_id.clone(),
+                            run_id: run_id.clone(),
+                            activity_id: activity_id.clone(),
+                            result,
+                            identity: self.identity.clone(),
+                        },
+                    )
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fail the activity with the provided failure
+    pub async fn fail(&self, failure: Failure) -> Result<(), Status> {
+        let mut client = self.client.clone();
+        match &self.identifier {
+            ActivityIdentifier::TaskToken(tt) => {
+                client
+                    .respond_activity_task_failed(RespondActivityTaskFailedRequest {
+                        task_token: tt.0.clone(),
+                        failure: Some(failure),
+                        identity: self.identity.clone(),
+                        namespace: self.namespace.clone(),
+                    })
+                    .await?;
+            }
+            ActivityIdentifier::ById {
+                workflow_id,
+                run_id,
+                activity_id,
+            } => {
+                client
+                    .respond_activity_task_failed_by_id(RespondActivityTaskFailedByIdRequest {
+                        namespace: self.namespace.clone(),
+                        workflow_id: workflow_id.clone(),
+                        run_id: run_id.clone(),
+                        activity_id: activity_id.clone(),
+                        failure: Some(failure),
+                        identity: self.identity.clone(),
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Report that the activity has been cancelled, in response to a cancellation request
+    pub async fn report_cancellation(&self, details: Option<Payloads>) -> Result<(), Status> {
+        let mut client = self.client.clone();
+        match &self.identifier {
+            ActivityIdentifier::TaskToken(tt) => {
+                client
+                    .respond_activity_task_canceled(RespondActivityTaskCanceledRequest {
+                        task_token: tt.0.clone(),
+                        details,
+                        identity: self.identity.clone(),
+                        namespace: self.namespace.clone(),
+                    })
+                    .await?;
+            }
+            ActivityIdentifier::ById {
+                workflow_id,
+                run_id,
+                activity_id,
+            } => {
+                client
+                    .respond_activity_task_canceled_by_id(RespondActivityTaskCanceledByIdRequest {
+                        namespace: self.namespace.clone(),
+                        workflow_id: workflow_id.clone(),
+                        run_id: run_id.clone(),
+                        activity_id: activity_id.clone(),
+                        details,
+                        identity: self.identity.clone(),
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,508 @@
+//! Keeps clients connected to a healthy endpoint, replacing broken connections and failing over
+//! when multiple endpoints are configured, see [crate::HealthCheckConfig] and
+//! [crate::FailoverConfig]
+
+use crate::{ClientInitError, ClientOptions, ConnectionState, TlsConfig};
+use http::uri::PathAndQuery;
+use parking_lot::RwLock;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Weak,
+    },
+    time::Duration,
+};
+use tokio::sync::watch;
+use tonic::{codec::ProstCodec, transport::Channel};
+use url::Url;
+
+/// How long connecting to an endpoint, or checking its health, may take before it is considered
+/// unhealthy
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The connection shared by all clones of a client
+#[derive(Debug)]
+pub(crate) struct Connection {
+    /// Index into [ClientOptions::endpoint_urls] of the endpoint currently connected to
+    current: AtomicUsize,
+    /// The TLS config in use, which may have been changed since the client was created
+    tls_cfg: RwLock<Option<TlsConfig>>,
+    /// Used to swap out the underlying connection for all clones of the client
+    channel_updater: watch::Sender<Channel>,
+    /// Publishes the state of the connection as determined by health checks
+    state: watch::Sender<ConnectionState>,
+}
+
+impl Connection {
+    pub(crate) fn new(
+        current: usize,
+        tls_cfg: Option<TlsConfig>,
+        channel_updater: watch::Sender<Channel>,
+    ) -> Self {
+        Self {
+            current: AtomicUsize::new(current),
+            tls_cfg: RwLock::new(tls_cfg),
+            channel_updater,
+            state: watch::channel(ConnectionState::Healthy).0,
+        }
+    }
+
+    pub(crate) fn state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.subscribe()
+    }
+
+    /// Start using the provided (healthy) connection to the endpoint at `idx`
+    fn replace_channel(&self, idx: usize, url: &Url, channel: Channel) {
+        self.current.store(idx, Ordering::Release);
+        self.channel_updater.send_replace(channel);
+        self.state
+            .send_replace(ConnectionState::Reconnected(url.clone()));
+    }
+
+    fn mark_unhealthy(&self) {
+        self.state.send_if_modified(|s| {
+            let changed = *s != ConnectionState::Unhealthy;
+            *s = ConnectionState::Unhealthy;
+            changed
+        });
+    }
+
+    /// A connection which was unhealthy has recovered by itself
+    fn mark_recovered(&self) {
+        self.state.send_if_modified(|s| {
+            let changed = *s == ConnectionState::Unhealthy;
+            if changed {
+                *s = ConnectionState::Healthy;
+            }
+            changed
+        });
+    }
+
+    /// Reconnect to the current endpoint with the provided TLS config, and use it from now on
+    pub(crate) async fn reload_tls_config(
+        &self,
+        opts: &ClientOptions,
+        tls_cfg: Option<TlsConfig>,
+    ) -> Result<(), ClientInitError> {
+        let url = opts.endpoint_urls()[self.current.load(Ordering::Acquire)];
+        let channel = opts.connect_channel(url, tls_cfg.as_ref()).await?;
+        *self.tls_cfg.write() = tls_cfg;
+        self.channel_updater.send_replace(channel);
+        Ok(())
+    }
+}
+
+/// Periodically checks the health of the endpoint the client is connected to. If it is unhealthy,
+/// a new connection to it is made, and if that is unhealthy too the client switches to the first
+/// healthy endpoint in the configured order. The client also switches if an endpoint earlier in
+/// the order has become healthy again. Stops once every clone of the client has been dropped.
+pub(crate) fn spawn_health_monitor(
+    opts: Arc<ClientOptions>,
+    connection: Weak<Connection>,
+    interval: Duration,
+) {
+    tokio::spawn(async move {
+        // Connections to endpoints other than the one in use, kept so they're only made once.
+        // Channels reconnect by themselves if their connection breaks.
+        let mut standby: Vec<Option<Channel>> = vec![None; opts.endpoint_urls().len()];
+        loop {
+            tokio::time::sleep(interval).await;
+            let connection = match connection.upgrade() {
+                Some(c) => c,
+                None => return,
+            };
+            let current = connection.current.load(Ordering::Acquire);
+            let tls_cfg = connection.tls_cfg.read().clone();
+            for (i, url) in opts.endpoint_urls().into_iter().enumerate() {
+                let channel = if i == current {
+                    connection.channel_updater.borrow().clone()
+                } else if let Some(channel) = standby[i].as_ref() {
+                    channel.clone()
+                } else {
+                    let connecting = opts.connect_channel(url, tls_cfg.as_ref());
+                    match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, connecting).await {
+                        Ok(Ok(c)) => standby[i].insert(c).clone(),
+                        _ => continue,
+                    }
+                };
+                if !is_healthy(channel.clone()).await {
+                    if i == current {
+                        warn!(endpoint = %url, "Server endpoint is unhealthy");
+                        connection.mark_unhealthy();
+                        if let Some(channel) = reconnect(&opts, url, tls_cfg.as_ref()).await {
+                            warn!(endpoint = %url, "Reconnected client to server endpoint");
+                            connection.replace_channel(i, url, channel);
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                if i != current {
+                    warn!(endpoint = %url, "Switching client to server endpoint");
+                    standby[current] = Some(connection.channel_updater.borrow().clone());
+                    standby[i] = None;
+                    connection.replace_channel(i, url, channel);
+                } else {
+                    connection.mark_recovered();
+                }
+                break;
+            }
+        }
+    });
+}
+
+/// Make a new connection to the endpoint at the provided URL, returning it if it is healthy
+async fn reconnect(
+    opts: &ClientOptions,
+    url: &Url,
+    tls_cfg: Option<&TlsConfig>,
+) -> Option<Channel> {
+    let connecting = opts.connect_channel(url, tls_cfg);
+    let channel = tokio::time::timeout(HEALTH_CHECK_TIMEOUT, connecting)
+        .await
+        .ok()?
+        .ok()?;
+    if is_healthy(channel.clone()).await {
+        Some(channel)
+    } else {
+        None
+    }
+}
+
+/// The service whose health is checked, which servers report as serving once they can handle
+/// calls to it
+const HEALTH_CHECK_SERVICE: &str = "temporal.api.workflowservice.v1.WorkflowService";
+/// Status reported by the standard gRPC health checking protocol for services which are serving
+const HEALTH_SERVING: i32 = 1;
+
+/// Request of the standard gRPC health checking protocol (`grpc.health.v1.Health/Check`)
+#[derive(Clone, PartialEq, prost::Message)]
+struct HealthCheckRequest {
+    #[prost(string, tag = "1")]
+    service: String,
+}
+
+/// Response of the standard gRPC health checking protocol (`grpc.health.v1.Health/Check`)
+#[derive(Clone, PartialEq, prost::Message)]
+struct HealthCheckResponse {
+    #[prost(int32, tag = "1")]
+    status: i32,
+}
+
+/// An endpoint is healthy only if the server reports the workflow service as serving
+async fn is_healthy(channel: Channel) -> bool {
+    let check = async move {
+        let mut grpc = tonic::client::Grpc::new(channel);
+        grpc.ready().await.map_err(|_| ())?;
+        grpc.unary(
+            tonic::Request::new(HealthCheckRequest {
+                service: HEALTH_CHECK_SERVICE.to_owned(),
+            }),
+            PathAndQuery::from_static("/grpc.health.v1.Health/Check"),
+            ProstCodec::<HealthCheckRequest, HealthCheckResponse>::default(),
+        )
+        .await
+        .map_err(|_| ())
+    };
+    match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, check).await {
+        Ok(Ok(resp)) => resp.into_inner().status == HEALTH_SERVING,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::{
+        ClientInitError, ClientOptionsBuilder, ConnectionState, FailoverConfig, HealthCheckConfig,
+        RetryClient, TlsConfigFiles, WorkflowService,
+    };
+    use hyper::{
+        header::HeaderValue,
+        service::{make_service_fn, service_fn},
+        Body, HeaderMap, Request, Response, Server,
+    };
+    use std::{
+        convert::Infallible,
+        net::{SocketAddr, TcpListener},
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use temporal_sdk_core_protos::temporal::api::workflowservice::v1::ListNamespacesRequest;
+    use tonic::Code;
+    use url::Url;
+
+    /// Start a server which reports itself as serving to health checks and responds to every other
+    /// gRPC call as unimplemented, counting the calls to list namespaces. While `break_first_conn`
+    /// is set, calls made on the first connection to the server are responded to as unavailable
+    /// instead.
+    pub(crate) fn fake_server(
+        listener: TcpListener,
+        break_first_conn: Arc<AtomicBool>,
+    ) -> Arc<AtomicUsize> {
+        fake_server_with_health(listener, break_first_conn, true).0
+    }
+
+    /// Like [fake_server], but health checks are only responded to as serving if `serving` is set.
+    /// Also returns how many connections were made to the server.
+    fn fake_server_with_health(
+        listener: TcpListener,
+        break_first_conn: Arc<AtomicBool>,
+        serving: bool,
+    ) -> (Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls_clone = calls.clone();
+        let conns = Arc::new(AtomicUsize::new(0));
+        let conns_clone = conns.clone();
+        let make_svc = make_service_fn(move |_| {
+            let calls = calls_clone.clone();
+            let break_first_conn = break_first_conn.clone();
+            let is_first_conn = conns_clone.fetch_add(1, Ordering::SeqCst) == 0;
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    if req.uri().path().ends_with("/ListNamespaces") {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                    }
+                    let response = if is_first_conn && break_first_conn.load(Ordering::SeqCst) {
+                        grpc_status_response("14")
+                    } else if serving && req.uri().path() == "/grpc.health.v1.Health/Check" {
+                        serving_response()
+                    } else {
+                        grpc_status_response("12")
+                    };
+                    async move { Ok::<_, Infallible>(response) }
+                }))
+            }
+        });
+        listener.set_nonblocking(true).unwrap();
+        let server = Server::from_tcp(listener)
+            .unwrap()
+            .http2_only(true)
+            .serve(make_svc);
+        tokio::spawn(server);
+        (calls, conns)
+    }
+
+    fn grpc_status_response(status: &'static str) -> Response<Body> {
+        Response::builder()
+            .header("content-type", "application/grpc")
+            .header("grpc-status", status)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    /// A health check response with a `SERVING` status
+    fn serving_response() -> Response<Body> {
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            // Uncompressed, 2 byte long message with field 1 set to 1
+            let _ = sender
+                .send_data(vec![0, 0, 0, 0, 2, 0x08, 0x01].into())
+                .await;
+            let mut trailers = HeaderMap::new();
+            trailers.insert("grpc-status", HeaderValue::from_static("0"));
+            let _ = sender.send_trailers(trailers).await;
+        });
+        Response::builder()
+            .header("content-type", "application/grpc")
+            .body(body)
+            .unwrap()
+    }
+
+    fn url(addr: SocketAddr) -> Url {
+        Url::parse(&format!("http://{}", addr)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn fails_over_and_back() {
+        // Reserve an address for the primary, which isn't up yet
+        let primary_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let primary_addr = primary_listener.local_addr().unwrap();
+        drop(primary_listener);
+        let secondary_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let secondary_addr = secondary_listener.local_addr().unwrap();
+        let secondary_calls = fake_server(secondary_listener, Default::default());
+
+        let opts = ClientOptionsBuilder::default()
+            .target_url(url(primary_addr))
+            .client_name("test".to_string())
+            .client_version("0.1.0".to_string())
+            .worker_binary_id("test".to_string())
+            .failover(FailoverConfig {
+                target_urls: vec![url(secondary_addr)],
+                health_check_interval: Duration::from_millis(50),
+            })
+            .build()
+            .unwrap();
+        let client = opts.connect_no_namespace(None, None).await.unwrap();
+        let mut client = RetryClient::new(client.into_inner(), Default::default());
+        let list = ListNamespacesRequest::default();
+
+        let _ = client.list_namespaces(list.clone()).await;
+        assert_eq!(secondary_calls.load(Ordering::SeqCst), 1);
+
+        let primary_calls =
+            fake_server(TcpListener::bind(primary_addr).unwrap(), Default::default());
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let _ = client.list_namespaces(list).await;
+        assert_eq!(primary_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(secondary_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn responding_endpoints_which_are_not_serving_are_unhealthy() {
+        let primary_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let primary_addr = primary_listener.local_addr().unwrap();
+        let (primary_calls, primary_conns) =
+            fake_server_with_health(primary_listener, Default::default(), false);
+        let secondary_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let secondary_addr = secondary_listener.local_addr().unwrap();
+        let secondary_calls = fake_server(secondary_listener, Default::default());
+
+        let opts = ClientOptionsBuilder::default()
+            .target_url(url(primary_addr))
+            .client_name("test".to_string())
+            .client_version("0.1.0".to_string())
+            .worker_binary_id("test".to_string())
+            .lazy_connect(true)
+            .failover(FailoverConfig {
+                target_urls: vec![url(secondary_addr)],
+                health_check_interval: Duration::from_millis(50),
+            })
+            .build()
+            .unwrap();
+        let client = opts.connect_no_namespace(None, None).await.unwrap();
+        let mut client = RetryClient::new(client.into_inner(), Default::default());
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        let _ = client
+            .list_namespaces(ListNamespacesRequest::default())
+            .await;
+        assert_eq!(primary_calls.load(Ordering::SeqCst), 0);
+        assert_eq!(secondary_calls.load(Ordering::SeqCst), 1);
+        // Checking the primary's health every interval reuses one connection to it
+        assert!(primary_conns.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[tokio::test]
+    async fn replaces_broken_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let break_first_conn = Arc::new(AtomicBool::new(false));
+        fake_server(listener, break_first_conn.clone());
+
+        let opts = ClientOptionsBuilder::default()
+            .target_url(url(addr))
+            .client_name("test".to_string())
+            .client_version("0.1.0".to_string())
+            .worker_binary_id("test".to_string())
+            .health_check(HealthCheckConfig {
+                interval: Duration::from_millis(50),
+            })
+            .build()
+            .unwrap();
+        let client = opts.connect_no_namespace(None, None).await.unwrap();
+        let mut state = client.get_client().connection_state();
+        assert_eq!(*state.borrow(), ConnectionState::Healthy);
+
+        break_first_conn.store(true, Ordering::SeqCst);
+        state.changed().await.unwrap();
+        assert_eq!(*state.borrow(), ConnectionState::Unhealthy);
+        // The health monitor connects again, and the new connection is healthy
+        state.changed().await.unwrap();
+        assert_eq!(*state.borrow(), ConnectionState::Reconnected(url(addr)));
+    }
+
+    #[tokio::test]
+    async fn reloading_tls_config_swaps_channel_for_all_clones() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let break_first_conn = Arc::new(AtomicBool::new(false));
+        let (_, conns) = fake_server_with_health(listener, break_first_conn.clone(), true);
+
+        let opts = ClientOptionsBuilder::default()
+            .target_url(url(addr))
+            .client_name("test".to_string())
+            .client_version("0.1.0".to_string())
+            .worker_binary_id("test".to_string())
+            .build()
+            .unwrap();
+        let mut client = opts.connect_no_namespace(None, None).await.unwrap();
+        let mut clone = client.get_client().clone();
+        break_first_conn.store(true, Ordering::SeqCst);
+        let err = clone
+            .list_namespaces(ListNamespacesRequest::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::Unavailable);
+
+        client
+            .get_client_mut()
+            .reload_tls_config(None)
+            .await
+            .unwrap();
+        assert_eq!(conns.load(Ordering::SeqCst), 2);
+        // The clone made before the reload now calls through the new connection
+        let err = clone
+            .list_namespaces(ListNamespacesRequest::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::Unimplemented);
+    }
+
+    #[tokio::test]
+    async fn failed_tls_config_reload_keeps_options() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        fake_server(listener, Default::default());
+        let opts = ClientOptionsBuilder::default()
+            .target_url(url(addr))
+            .client_name("test".to_string())
+            .client_version("0.1.0".to_string())
+            .worker_binary_id("test".to_string())
+            .build()
+            .unwrap();
+        let mut client = opts.connect_no_namespace(None, None).await.unwrap();
+        let files = TlsConfigFiles {
+            server_root_ca_cert: Some("/nonexistent/ca.pem".into()),
+            ..Default::default()
+        };
+        let err = client
+            .get_client_mut()
+            .reload_tls_config_from_files(&files)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ClientInitError::TlsFileReadError { .. }));
+        assert!(client.get_client().options().tls_cfg.is_none());
+    }
+
+    #[test]
+    fn zero_health_check_intervals_are_rejected() {
+        let builder = || {
+            let mut b = ClientOptionsBuilder::default();
+            b.target_url(Url::parse("http://localhost:7233").unwrap())
+                .client_name("test".to_string())
+                .client_version("0.1.0".to_string())
+                .worker_binary_id("test".to_string());
+            b
+        };
+        assert!(builder()
+            .health_check(HealthCheckConfig {
+                interval: Duration::ZERO,
+            })
+            .build()
+            .is_err());
+        assert!(builder()
+            .failover(FailoverConfig {
+                target_urls: vec![],
+                health_check_interval: Duration::ZERO,
+            })
+            .build()
+            .is_err());
+        assert!(builder()
+            .health_check(HealthCheckConfig::default())
+            .build()
+            .is_ok());
+    }
+}
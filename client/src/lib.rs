@@ -1,4 +1,7 @@
-#![warn(missing_docs)] // error if there are missing docs
+#![warn(missing_docs)]
+// error if there are missing docs
+// Calls to the server fail with `tonic::Status`, which is large but not worth boxing everywhere
+#![allow(clippy::result_large_err)]
 
 //! This crate contains client implementations that can be used to contact the Temporal service.
 //!
@@ -7,22 +10,42 @@
 #[macro_use]
 extern crate tracing;
 
+mod async_activity_handle;
+mod failover;
 mod metrics;
+mod namespace;
+mod oauth;
 mod raw;
+mod resolver;
 mod retry;
+mod visibility;
 mod workflow_handle;
 
 pub use crate::retry::{CallType, RetryClient};
+pub use async_activity_handle::{ActivityIdentifier, AsyncActivityHandle};
+pub use namespace::{ArchivalConfig, RegisterNamespaceOptions, UpdateNamespaceOptions};
+pub use oauth::{
+    AccessToken, ClientCredentials, OAuth2TokenProvider, TokenHttpClient, TokenSource,
+};
 pub use raw::WorkflowService;
-pub use workflow_handle::{WorkflowExecutionInfo, WorkflowExecutionResult};
+pub use resolver::{DnsResolver, EndpointResolver, LoadBalancingPolicy};
+pub use visibility::{Comparison, QueryValue, VisibilityQuery};
+pub use workflow_handle::{
+    GetWorkflowResultOpts, UntypedWorkflowHandle, WorkflowExecutionDescription,
+    WorkflowExecutionInfo, WorkflowExecutionResult, WorkflowHandle,
+};
 
 use crate::{
+    failover::{spawn_health_monitor, Connection},
     metrics::{GrpcMetricSvc, MetricsContext},
-    raw::{sealed::RawClientLike, AttachMetricLabels},
+    raw::{fetch_capabilities, get_serialized_history, sealed::RawClientLike, AttachMetricLabels},
     sealed::{RawClientLikeUser, WfHandleClient},
-    workflow_handle::UntypedWorkflowHandle,
 };
 use backoff::{ExponentialBackoff, SystemClock};
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
 use http::uri::InvalidUri;
 use opentelemetry::metrics::Meter;
 use parking_lot::RwLock;
@@ -30,6 +53,7 @@ use std::{
     collections::HashMap,
     fmt::{Debug, Formatter},
     ops::{Deref, DerefMut},
+    path::PathBuf,
     str::FromStr,
     sync::Arc,
     time::{Duration, Instant},
@@ -38,21 +62,26 @@ use temporal_sdk_core_protos::{
     coresdk::{common::Payload, workflow_commands::QueryResult, IntoPayloadsExt},
     temporal::api::{
         command::v1::Command,
-        common::v1::{Payloads, WorkflowExecution, WorkflowType},
-        enums::v1::{TaskQueueKind, WorkflowTaskFailedCause},
+        common::v1::{Payloads, RetryPolicy, WorkflowExecution, WorkflowType},
+        enums::v1::{
+            ResetReapplyType, TaskQueueKind, TaskQueueType, WorkflowIdReusePolicy,
+            WorkflowTaskFailedCause,
+        },
         failure::v1::Failure,
         query::v1::{WorkflowQuery, WorkflowQueryResult},
         taskqueue::v1::{StickyExecutionAttributes, TaskQueue, TaskQueueMetadata},
+        workflow::v1 as workflow,
         workflowservice::v1::{workflow_service_client::WorkflowServiceClient, *},
     },
-    TaskToken,
+    SearchAttributeRegistry, SerializedHistoryPage, TaskToken,
 };
+use tokio::sync::{watch, OnceCell};
 use tonic::{
     codegen::InterceptedService,
-    metadata::{MetadataKey, MetadataValue},
+    metadata::{MetadataKey, MetadataMap, MetadataValue},
     service::Interceptor,
     transport::{Certificate, Channel, Endpoint, Identity},
-    Code, Status,
+    Status,
 };
 use tower::ServiceBuilder;
 use url::Url;
@@ -67,6 +96,7 @@ type Result<T, E = tonic::Status> = std::result::Result<T, E>;
 
 /// Options for the connection to the temporal server. Construct with [ClientOptionsBuilder]
 #[derive(Clone, Debug, derive_builder::Builder)]
+#[builder(build_fn(validate = "Self::validate"))]
 #[non_exhaustive]
 pub struct ClientOptions {
     /// The URL of the Temporal server to connect to
@@ -97,6 +127,77 @@ pub struct ClientOptions {
     /// Retry configuration for the server client. Default is [RetryConfig::default]
     #[builder(default)]
     pub retry_config: RetryConfig,
+
+    /// Retry configuration used for long polls, which by default are retried forever with
+    /// exponential backoff. Default is [RetryConfig::poll_retry_policy]
+    #[builder(default = "RetryConfig::poll_retry_policy()")]
+    pub poll_retry_config: RetryConfig,
+
+    /// Retry configuration used for calls which complete or fail tasks. If unset, `retry_config`
+    /// is used.
+    #[builder(setter(strip_option), default)]
+    pub completion_retry_config: Option<RetryConfig>,
+
+    /// If set, long polls which persistently fail with server errors will trip a circuit breaker
+    /// which pauses all polling on the client for a while, rather than continuing to retry.
+    #[builder(setter(strip_option), default)]
+    pub poll_circuit_breaker: Option<PollCircuitBreakerConfig>,
+
+    /// If set, is invoked around every call the client makes to the server. See
+    /// [ClientInterceptor].
+    #[builder(setter(strip_option), default)]
+    pub interceptor: Option<Arc<dyn ClientInterceptor>>,
+
+    /// If set, is asked for headers to attach to every call the client makes to the server. See
+    /// [HeadersProvider].
+    #[builder(setter(strip_option), default)]
+    pub headers_provider: Option<Arc<dyn HeadersProvider>>,
+
+    /// HTTP2 keep-alive settings for the connection to the server. Keep-alive pings stop idle
+    /// connections (like those used by long polls) from being silently dropped by NATs and load
+    /// balancers. Enabled with [ClientKeepAliveConfig::default] unless set to `None`.
+    #[builder(default = "Some(ClientKeepAliveConfig::default())")]
+    pub keep_alive: Option<ClientKeepAliveConfig>,
+
+    /// If true, connecting returns a client immediately without establishing a connection to the
+    /// server. The connection is made, and the server's capabilities are fetched, when the first
+    /// call is made. Connection problems are then reported as errors from that call rather than
+    /// from connecting.
+    #[builder(default)]
+    pub lazy_connect: bool,
+
+    /// If set, limits how often the client makes calls other than long polls, like completions,
+    /// heartbeats, and history fetches. Bursts of such calls are smoothed out rather than all
+    /// being sent at once, which could run into the server's rate limits. Retries count against
+    /// the limit too.
+    #[builder(setter(strip_option), default)]
+    pub rate_limit: Option<ClientRateLimitConfig>,
+
+    /// If set, the client can fail over to other server endpoints when `target_url` is
+    /// unavailable. See [FailoverConfig].
+    #[builder(setter(strip_option), default)]
+    pub failover: Option<FailoverConfig>,
+
+    /// If set, the client periodically checks that the server is responding on its connection,
+    /// and replaces the connection if it is not. This detects dead connections much sooner than
+    /// long polls timing out would. See [ConfiguredClient::connection_state].
+    #[builder(setter(strip_option), default)]
+    pub health_check: Option<HealthCheckConfig>,
+
+    /// If set, used to resolve the hosts of server endpoints to addresses, instead of leaving that
+    /// to the system resolver when connecting. See [EndpointResolver].
+    #[builder(setter(strip_option), default)]
+    pub resolver: Option<Arc<dyn EndpointResolver>>,
+
+    /// How calls are spread across the addresses server endpoints resolve to. Default is
+    /// [LoadBalancingPolicy::PickFirst].
+    #[builder(default)]
+    pub load_balancing: LoadBalancingPolicy,
+
+    /// How often endpoints are resolved again when calls are balanced across their addresses, to
+    /// pick up servers which have been added or removed. Default is 30 seconds.
+    #[builder(default = "Duration::from_secs(30)")]
+    pub re_resolve_interval: Duration,
 }
 
 /// Configuration options for TLS
@@ -122,6 +223,45 @@ pub struct ClientTlsConfig {
     pub client_private_key: Vec<u8>,
 }
 
+/// Where to read the files making up a [TlsConfig] from. Useful with
+/// [ConfiguredClient::reload_tls_config_from_files] when short-lived certificates are rotated on
+/// disk.
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfigFiles {
+    /// Path to the root CA certificate used by the server, see [TlsConfig::server_root_ca_cert]
+    pub server_root_ca_cert: Option<PathBuf>,
+    /// See [TlsConfig::domain]
+    pub domain: Option<String>,
+    /// Paths to the client's certificate and private key, in that order, if using mTLS
+    pub client_cert_and_key: Option<(PathBuf, PathBuf)>,
+}
+
+impl TlsConfigFiles {
+    /// Read the files into a [TlsConfig]
+    pub fn load(&self) -> Result<TlsConfig, ClientInitError> {
+        let read = |path: &PathBuf| {
+            std::fs::read(path).map_err(|source| ClientInitError::TlsFileReadError {
+                path: path.clone(),
+                source,
+            })
+        };
+        Ok(TlsConfig {
+            server_root_ca_cert: self.server_root_ca_cert.as_ref().map(read).transpose()?,
+            domain: self.domain.clone(),
+            client_tls_config: self
+                .client_cert_and_key
+                .as_ref()
+                .map(|(cert, key)| {
+                    Ok::<_, ClientInitError>(ClientTlsConfig {
+                        client_cert: read(cert)?,
+                        client_private_key: read(key)?,
+                    })
+                })
+                .transpose()?,
+        })
+    }
+}
+
 /// Configuration for retrying requests to the server
 #[derive(Clone, Debug)]
 pub struct RetryConfig {
@@ -155,7 +295,9 @@ impl Default for RetryConfig {
 }
 
 impl RetryConfig {
-    pub(crate) const fn poll_retry_policy() -> Self {
+    /// The default policy for long polls. Retries forever, backing off up to 10 seconds between
+    /// attempts.
+    pub const fn poll_retry_policy() -> Self {
         Self {
             initial_interval: Duration::from_millis(200),
             randomization_factor: 0.2,
@@ -167,6 +309,114 @@ impl RetryConfig {
     }
 }
 
+/// Configuration for the circuit breaker which can pause long polling when the server is
+/// persistently returning errors (ex: it is overloaded or unavailable)
+#[derive(Clone, Debug)]
+pub struct PollCircuitBreakerConfig {
+    /// The circuit opens after this many consecutive long poll attempts fail with a server error.
+    /// Attempts from all pollers using the same client count towards the limit.
+    pub failure_threshold: usize,
+    /// How long polling is paused for once the circuit opens. After this time a poll is allowed
+    /// through, and if it also fails the circuit opens again immediately.
+    pub open_duration: Duration,
+}
+
+impl Default for PollCircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 10,
+            open_duration: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Configuration for failing over between multiple server endpoints. The client connects to the
+/// first endpoint which is available, in order of preference. While connected it periodically
+/// checks the endpoint's health, switching to the next available endpoint if it becomes unhealthy,
+/// and back to a more preferred endpoint once that has recovered.
+#[derive(Clone, Debug)]
+pub struct FailoverConfig {
+    /// Endpoints to use when `target_url` is unavailable, in order of preference. They use the
+    /// same TLS configuration.
+    pub target_urls: Vec<Url>,
+    /// How often the health of the endpoint in use is checked. Must be greater than zero. If
+    /// [ClientOptions::health_check] is set, its interval is used instead.
+    pub health_check_interval: Duration,
+}
+
+/// Configuration for periodically checking the health of the client's connection
+#[derive(Clone, Debug)]
+pub struct HealthCheckConfig {
+    /// How often the health of the connection is checked. Must be greater than zero.
+    pub interval: Duration,
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+        }
+    }
+}
+
+/// The state of the client's connection to the server, as determined by health checks. Health
+/// checks only run if [ClientOptions::health_check] or [ClientOptions::failover] is set, otherwise
+/// the state is always [ConnectionState::Healthy].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// The connection in use passed its most recent health check, or has not been checked yet
+    Healthy,
+    /// The connection in use failed its most recent health check, and the client has not yet been
+    /// able to replace it. Calls are likely to fail.
+    Unhealthy,
+    /// The client replaced the connection in use with a new, healthy connection to the provided
+    /// endpoint, either because the old one was unhealthy or to fail over
+    Reconnected(Url),
+}
+
+/// Configuration for limiting the rate of calls made by the client
+#[derive(Clone, Debug)]
+pub struct ClientRateLimitConfig {
+    /// The average number of calls which may be made per second. Must be greater than 0.
+    pub max_requests_per_second: f64,
+    /// How many calls may be made at once before being limited, after the client has not been
+    /// making calls for a while. Must be at least 1.
+    pub burst: usize,
+}
+
+impl ClientRateLimitConfig {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if !(self.max_requests_per_second > 0.0 && self.max_requests_per_second.is_finite()) {
+            return Err("Rate limit `max_requests_per_second` must be greater than 0".to_owned());
+        }
+        if self.burst == 0 {
+            return Err("Rate limit `burst` must be at least 1".to_owned());
+        }
+        Ok(())
+    }
+}
+
+/// HTTP2 keep-alive settings for the client's connection
+#[derive(Clone, Debug)]
+pub struct ClientKeepAliveConfig {
+    /// How often a keep-alive ping is sent on the connection
+    pub interval: Duration,
+    /// If a ping is not acknowledged within this time, the connection is closed
+    pub timeout: Duration,
+    /// If true, pings are sent even when there are no calls in flight on the connection
+    pub permit_without_stream: bool,
+}
+
+impl Default for ClientKeepAliveConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            timeout: Duration::from_secs(15),
+            permit_without_stream: true,
+        }
+    }
+}
+
 impl From<RetryConfig> for ExponentialBackoff {
     fn from(c: RetryConfig) -> Self {
         Self {
@@ -202,6 +452,22 @@ pub enum ClientInitError {
     /// server capabilities / verify server is responding.
     #[error("`get_system_info` call error after connection: {0:?}")]
     SystemInfoCallError(tonic::Status),
+    /// The server endpoint could not be resolved to any addresses
+    #[error("Failed to resolve server endpoint {url}: {source}")]
+    ResolutionError {
+        /// The endpoint which could not be resolved
+        url: String,
+        /// Why resolution failed
+        source: std::io::Error,
+    },
+    /// A file making up the TLS config could not be read
+    #[error("Failed to read TLS file {path:?}: {source}")]
+    TlsFileReadError {
+        /// The file which could not be read
+        path: PathBuf,
+        /// Why reading failed
+        source: std::io::Error,
+    },
 }
 
 #[doc(hidden)]
@@ -243,15 +509,25 @@ impl From<ConfiguredClient<WorkflowServiceClientWithMetrics>> for AnyClient {
     }
 }
 
+/// Holds server capabilities once they have been fetched. Servers which don't support fetching
+/// them have none.
+pub(crate) type CapabilitiesCell = Arc<OnceCell<Option<get_system_info_response::Capabilities>>>;
+
 /// A client with [ClientOptions] attached, which can be passed to initialize workers,
 /// or can be used directly.
 #[derive(Clone, Debug)]
 pub struct ConfiguredClient<C> {
     client: C,
-    options: ClientOptions,
+    options: Arc<ClientOptions>,
     headers: Arc<RwLock<HashMap<String, String>>>,
-    /// Capabilities as read from the `get_system_info` RPC call made on client connection
-    capabilities: Option<get_system_info_response::Capabilities>,
+    /// Capabilities as read from the `get_system_info` RPC call made on client connection, or
+    /// before the first call if the client connects lazily
+    capabilities: CapabilitiesCell,
+    /// The connection to the server, which may be swapped out for all clones of this client
+    connection: Arc<Connection>,
+    /// The service `client` calls through, for calls whose responses the generated client can't
+    /// decode the way we'd like
+    service: InterceptedMetricsSvc,
 }
 
 impl<C> ConfiguredClient<C> {
@@ -267,14 +543,51 @@ impl<C> ConfiguredClient<C> {
     }
 
     /// Returns the server capabilities we (may have) learned about when establishing an initial
-    /// connection
+    /// connection. Always `None` for a lazily connected client which has not made any calls yet.
     pub fn capabilities(&self) -> Option<&get_system_info_response::Capabilities> {
-        self.capabilities.as_ref()
+        self.capabilities.get().and_then(Option::as_ref)
+    }
+
+    /// Establish a new connection to the server using the provided TLS configuration (or no TLS,
+    /// if `None`), and switch this client and all its clones over to it. Useful when certificates
+    /// are short-lived and need to be rotated without recreating the client or any workers using
+    /// it. Calls which are already in flight complete on the old connection. The new config is
+    /// also used when failing over to other endpoints.
+    ///
+    /// The TLS config returned by this client's [ConfiguredClient::options] is updated, but like
+    /// with [Client::set_identity], the options of clones made beforehand are not.
+    pub async fn reload_tls_config(
+        &mut self,
+        tls_cfg: Option<TlsConfig>,
+    ) -> Result<(), ClientInitError> {
+        self.connection
+            .reload_tls_config(&self.options, tls_cfg.clone())
+            .await?;
+        Arc::make_mut(&mut self.options).tls_cfg = tls_cfg;
+        Ok(())
+    }
+
+    /// Read the TLS config from `files` again and reconnect using it, see
+    /// [ConfiguredClient::reload_tls_config]. Meant to be called whenever rotated certificates
+    /// have been written to disk.
+    pub async fn reload_tls_config_from_files(
+        &mut self,
+        files: &TlsConfigFiles,
+    ) -> Result<(), ClientInitError> {
+        let tls_cfg = files.load()?;
+        self.reload_tls_config(Some(tls_cfg)).await
+    }
+
+    /// Returns a receiver which observes the state of this client's connection to the server as
+    /// health checks run. It is shared by all clones of the client.
+    pub fn connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.connection.state()
     }
 
     /// De-constitute this type
     pub fn into_parts(self) -> (C, ClientOptions) {
-        (self.client, self.options)
+        let options = Arc::try_unwrap(self.options).unwrap_or_else(|o| (*o).clone());
+        (self.client, options)
     }
 }
 
@@ -292,6 +605,25 @@ impl<C> DerefMut for ConfiguredClient<C> {
     }
 }
 
+impl ClientOptionsBuilder {
+    fn validate(&self) -> Result<(), String> {
+        if let Some(Some(rate_limit)) = &self.rate_limit {
+            rate_limit.validate()?;
+        }
+        if let Some(Some(health_check)) = &self.health_check {
+            if health_check.interval.is_zero() {
+                return Err("Health check `interval` must be greater than 0".to_owned());
+            }
+        }
+        if let Some(Some(failover)) = &self.failover {
+            if failover.health_check_interval.is_zero() {
+                return Err("Failover `health_check_interval` must be greater than 0".to_owned());
+            }
+        }
+        Ok(())
+    }
+}
+
 impl ClientOptions {
     /// Attempt to establish a connection to the Temporal server in a specific namespace. The
     /// returned client is bound to that namespace.
@@ -305,9 +637,9 @@ impl ClientOptions {
             .connect_no_namespace(metrics_meter, headers)
             .await?
             .into_inner();
+        let capabilities = client.capabilities.clone();
         let client = Client::new(client, namespace.into());
-        let retry_client = RetryClient::new(client, self.retry_config.clone());
-        Ok(retry_client)
+        Ok(self.wrap_with_retry(client, capabilities, metrics_meter))
     }
 
     /// Attempt to establish a connection to the Temporal server and return a gRPC client which is
@@ -320,12 +652,19 @@ impl ClientOptions {
         headers: Option<Arc<RwLock<HashMap<String, String>>>>,
     ) -> Result<RetryClient<ConfiguredClient<WorkflowServiceClientWithMetrics>>, ClientInitError>
     {
-        let channel = Channel::from_shared(self.target_url.to_string())?;
-        let channel = self.add_tls_to_channel(channel).await?;
-        let channel = channel.connect().await?;
+        let (endpoint_idx, channel) = if self.lazy_connect {
+            let channel = self
+                .make_channel(&self.target_url, self.tls_cfg.as_ref(), true)
+                .await?;
+            (0, channel)
+        } else {
+            self.connect_first_available().await?
+        };
+        let (channel_updater, channel_updates) = watch::channel(channel.clone());
         let service = ServiceBuilder::new()
             .layer_fn(|channel| GrpcMetricSvc {
                 inner: channel,
+                channel_updates: channel_updates.clone(),
                 metrics: metrics_meter.map(|mm| MetricsContext::new(vec![], mm)),
             })
             .service(channel);
@@ -335,34 +674,126 @@ impl ClientOptions {
             headers: headers.clone(),
         };
 
-        let mut client = ConfiguredClient {
+        let service = InterceptedService::new(service, interceptor);
+        let client = ConfiguredClient {
             headers,
-            client: WorkflowServiceClient::with_interceptor(service, interceptor),
-            options: self.clone(),
-            capabilities: None,
+            client: WorkflowServiceClient::new(service.clone()),
+            service,
+            options: Arc::new(self.clone()),
+            capabilities: Default::default(),
+            connection: Arc::new(Connection::new(
+                endpoint_idx,
+                self.tls_cfg.clone(),
+                channel_updater,
+            )),
         };
-        match client
-            .get_system_info(GetSystemInfoRequest::default())
-            .await
-        {
-            Ok(sysinfo) => {
-                client.capabilities = sysinfo.into_inner().capabilities;
+        let health_check_interval = self
+            .health_check
+            .as_ref()
+            .map(|hc| hc.interval)
+            .or_else(|| self.failover.as_ref().map(|f| f.health_check_interval));
+        if let Some(interval) = health_check_interval {
+            spawn_health_monitor(
+                client.options.clone(),
+                Arc::downgrade(&client.connection),
+                interval,
+            );
+        }
+        if !self.lazy_connect {
+            let capabilities = fetch_capabilities(Some(&client.options), &client.client)
+                .await
+                .map_err(ClientInitError::SystemInfoCallError)?;
+            let _ = client.capabilities.set(capabilities);
+        }
+        let capabilities = client.capabilities.clone();
+        Ok(self.wrap_with_retry(client, capabilities, metrics_meter))
+    }
+
+    /// Wrap a client with retries as configured by these options
+    fn wrap_with_retry<SG>(
+        &self,
+        client: SG,
+        capabilities: CapabilitiesCell,
+        metrics_meter: Option<&Meter>,
+    ) -> RetryClient<SG> {
+        let mut retry_client = RetryClient::new(client, self.retry_config.clone())
+            .with_poll_retry_config(self.poll_retry_config.clone())
+            .with_capabilities(capabilities);
+        if let Some(completion_cfg) = self.completion_retry_config.clone() {
+            retry_client = retry_client.with_completion_retry_config(completion_cfg);
+        }
+        if let Some(rate_limit) = self.rate_limit.clone() {
+            retry_client = retry_client.with_rate_limit(rate_limit);
+        }
+        if let Some(mm) = metrics_meter {
+            retry_client = retry_client.with_metrics(MetricsContext::new(vec![], mm));
+        }
+        if let Some(cb_cfg) = self.poll_circuit_breaker.clone() {
+            retry_client.with_poll_circuit_breaker(
+                cb_cfg,
+                metrics_meter.map(|mm| MetricsContext::new(vec![], mm)),
+            )
+        } else {
+            retry_client
+        }
+    }
+
+    /// All the server endpoints the client may connect to, in order of preference
+    pub(crate) fn endpoint_urls(&self) -> Vec<&Url> {
+        let failover_urls = self.failover.iter().flat_map(|f| f.target_urls.iter());
+        std::iter::once(&self.target_url)
+            .chain(failover_urls)
+            .collect()
+    }
+
+    /// Connect to the first endpoint which is available, returning its index in
+    /// [ClientOptions::endpoint_urls]. If none are, the error from the last one is returned.
+    async fn connect_first_available(&self) -> Result<(usize, Channel), ClientInitError> {
+        let urls = self.endpoint_urls();
+        let last = urls.len() - 1;
+        for (i, url) in urls.into_iter().enumerate() {
+            match self.connect_channel(url, self.tls_cfg.as_ref()).await {
+                Ok(channel) => return Ok((i, channel)),
+                Err(e) if i == last => return Err(e),
+                Err(e) => warn!(error=?e, endpoint=%url, "Failed to connect to server endpoint"),
             }
-            Err(status) => match status.code() {
-                Code::Unimplemented => {}
-                _ => return Err(ClientInitError::SystemInfoCallError(status)),
-            },
-        };
-        Ok(RetryClient::new(client, self.retry_config.clone()))
+        }
+        unreachable!("There is always at least one endpoint")
+    }
+
+    /// Connect to the server at the provided URL, using the provided TLS options if set
+    pub(crate) async fn connect_channel(
+        &self,
+        url: &Url,
+        tls_cfg: Option<&TlsConfig>,
+    ) -> Result<Channel, ClientInitError> {
+        self.make_channel(url, tls_cfg, false).await
+    }
+
+    /// Configure the endpoint for the server at the provided URL, using the provided TLS options
+    /// if set
+    async fn endpoint(
+        &self,
+        url: &Url,
+        tls_cfg: Option<&TlsConfig>,
+    ) -> Result<Endpoint, ClientInitError> {
+        let mut channel = Channel::from_shared(url.to_string())?;
+        if let Some(keep_alive) = self.keep_alive.as_ref() {
+            channel = channel
+                .http2_keep_alive_interval(keep_alive.interval)
+                .keep_alive_timeout(keep_alive.timeout)
+                .keep_alive_while_idle(keep_alive.permit_without_stream);
+        }
+        Ok(Self::add_tls_to_channel(tls_cfg, channel).await?)
     }
 
     /// If TLS is configured, set the appropriate options on the provided channel and return it.
     /// Passes it through if TLS options not set.
     async fn add_tls_to_channel(
-        &self,
+        tls_cfg: Option<&TlsConfig>,
         channel: Endpoint,
     ) -> Result<Endpoint, tonic::transport::Error> {
-        if let Some(tls_cfg) = &self.tls_cfg {
+        if let Some(tls_cfg) = tls_cfg {
             let mut tls = tonic::transport::ClientTlsConfig::new();
 
             if let Some(root_cert) = &tls_cfg.server_root_ca_cert {
@@ -404,6 +835,79 @@ pub struct WorkflowTaskCompletion {
     pub force_create_new_workflow_task: bool,
 }
 
+/// Allows inspecting and modifying every call the client makes to the server. Useful for things
+/// like custom authentication, tracing, or tagging requests.
+///
+/// Both methods are invoked once per attempt, so a call which is retried will be seen more than
+/// once.
+pub trait ClientInterceptor: Send + Sync + Debug {
+    /// Called before a call is sent, with the name of the call (ex: `poll_workflow_task_queue`).
+    /// The request's metadata, timeout, and extensions may be modified. Returning an error
+    /// short-circuits the call: it is not sent, and the error is handled as though the server had
+    /// returned it (and hence may be retried).
+    fn on_request(&self, call_name: &str, request: &mut tonic::Request<()>) -> Result<(), Status> {
+        let _ = (call_name, request);
+        Ok(())
+    }
+
+    /// Called with the outcome of every call which was sent - the response metadata if it
+    /// succeeded, or the error if it failed.
+    fn on_response(&self, call_name: &str, response: Result<&MetadataMap, &Status>) {
+        let _ = (call_name, response);
+    }
+}
+
+/// Provides headers which are attached to every call the client makes to the server, for example
+/// to authenticate with credentials that are refreshed over time.
+///
+/// Headers set with [ConfiguredClient::set_headers] take precedence over provided headers with
+/// the same name.
+#[async_trait::async_trait]
+pub trait HeadersProvider: Send + Sync + Debug {
+    /// Called before every attempt of every call. Returning an error fails the attempt with that
+    /// error, without it being sent.
+    async fn headers(&self) -> Result<HashMap<String, String>, Status>;
+
+    /// Called when the server rejects a call as unauthenticated. If this returns true, the
+    /// provider has discarded the credentials it was using and the call is attempted once more
+    /// with newly provided headers.
+    async fn credentials_rejected(&self) -> bool {
+        false
+    }
+}
+
+/// Authenticates calls with a static API key, which is sent as a bearer token
+#[derive(Clone)]
+pub struct ApiKey {
+    header_value: String,
+}
+
+impl ApiKey {
+    /// Authenticate with the provided key
+    pub fn new(key: impl Into<String>) -> Self {
+        Self {
+            header_value: format!("Bearer {}", key.into()),
+        }
+    }
+}
+
+impl Debug for ApiKey {
+    // Intentionally omit the key so that it can't leak
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ApiKey(..)")
+    }
+}
+
+#[async_trait::async_trait]
+impl HeadersProvider for ApiKey {
+    async fn headers(&self) -> Result<HashMap<String, String>, Status> {
+        Ok(HashMap::from([(
+            "authorization".to_string(),
+            self.header_value.clone(),
+        )]))
+    }
+}
+
 /// Interceptor which attaches common metadata (like "client-name") to every outgoing call
 #[derive(Clone)]
 pub struct ServiceCallInterceptor {
@@ -475,11 +979,11 @@ impl Client {
     ///
     /// Note that it is reasonably cheap to clone the returned type if you need to own it. Such
     /// clones will keep re-using the same channel.
-    pub fn raw_retry_client(&self) -> RetryClient<WorkflowServiceClientWithMetrics> {
-        RetryClient::new(
-            self.raw_client().clone(),
-            self.inner.options.retry_config.clone(),
-        )
+    pub fn raw_retry_client(
+        &self,
+    ) -> RetryClient<ConfiguredClient<WorkflowServiceClientWithMetrics>> {
+        self.options()
+            .wrap_with_retry(self.inner.clone(), self.inner.capabilities.clone(), None)
     }
 
     /// Access the underling grpc client. This raw client is not bound to a specific namespace.
@@ -494,6 +998,44 @@ impl Client {
     pub fn options(&self) -> &ClientOptions {
         &self.inner.options
     }
+
+    /// Identify as `identity` in requests made by this client, rather than the identity it was
+    /// initialized with. Clones of this client made beforehand are unaffected.
+    pub fn set_identity(&mut self, identity: String) {
+        Arc::make_mut(&mut self.inner.options).identity = identity;
+    }
+
+    /// Returns a client bound to `namespace` which shares this client's connection, options, and
+    /// server capabilities, so processes working with many namespaces don't need a connection for
+    /// each of them. Nothing is connected or fetched, so this is cheap.
+    pub fn with_namespace(&self, namespace: impl Into<String>) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            namespace: namespace.into(),
+        }
+    }
+
+    /// Reconnect using new TLS options. See [ConfiguredClient::reload_tls_config]
+    pub async fn reload_tls_config(
+        &mut self,
+        tls_cfg: Option<TlsConfig>,
+    ) -> Result<(), ClientInitError> {
+        self.inner.reload_tls_config(tls_cfg).await
+    }
+
+    /// Reconnect using TLS options read from disk. See
+    /// [ConfiguredClient::reload_tls_config_from_files]
+    pub async fn reload_tls_config_from_files(
+        &mut self,
+        files: &TlsConfigFiles,
+    ) -> Result<(), ClientInitError> {
+        self.inner.reload_tls_config_from_files(files).await
+    }
+
+    /// Observe the state of the connection. See [ConfiguredClient::connection_state]
+    pub fn connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.inner.connection_state()
+    }
 }
 
 /// This trait provides higher-level friendlier interaction with the server.
@@ -596,6 +1138,13 @@ pub trait WorkflowClientTrait {
         payloads: Option<Payloads>,
     ) -> Result<SignalWorkflowExecutionResponse>;
 
+    /// Send a signal to a workflow, atomically starting it first if there is no running workflow
+    /// with the provided id
+    async fn signal_with_start_workflow_execution(
+        &self,
+        options: SignalWithStartOptions,
+    ) -> Result<SignalWithStartWorkflowExecutionResponse>;
+
     /// Request a query of a certain workflow instance
     async fn query_workflow_execution(
         &self,
@@ -619,6 +1168,15 @@ pub trait WorkflowClientTrait {
         page_token: Vec<u8>,
     ) -> Result<GetWorkflowExecutionHistoryResponse>;
 
+    /// Get a page of history for a particular workflow like
+    /// [WorkflowClientTrait::get_workflow_execution_history], but leave its events serialized
+    async fn get_serialized_workflow_execution_history(
+        &self,
+        workflow_id: String,
+        run_id: Option<String>,
+        page_token: Vec<u8>,
+    ) -> Result<SerializedHistoryPage>;
+
     /// Respond to a legacy query-only workflow task
     async fn respond_legacy_query(
         &self,
@@ -640,14 +1198,153 @@ pub trait WorkflowClientTrait {
         run_id: Option<String>,
     ) -> Result<TerminateWorkflowExecutionResponse>;
 
+    /// Reset a workflow run to the end of the workflow task finished by the provided event,
+    /// terminating it and starting a new run which continues from that point. `request_id` is
+    /// used to deduplicate resets.
+    async fn reset_workflow_execution(
+        &self,
+        workflow_id: String,
+        run_id: Option<String>,
+        workflow_task_finish_event_id: i64,
+        reason: String,
+        reset_reapply_type: ResetReapplyType,
+        request_id: String,
+    ) -> Result<ResetWorkflowExecutionResponse>;
+
     /// Lists all available namespaces
     async fn list_namespaces(&self) -> Result<ListNamespacesResponse>;
 
+    /// Register a new namespace
+    async fn register_namespace(
+        &self,
+        options: RegisterNamespaceOptions,
+    ) -> Result<RegisterNamespaceResponse>;
+
+    /// Describe a namespace, which need not be the one this client is bound to
+    async fn describe_namespace(&self, namespace: String) -> Result<DescribeNamespaceResponse>;
+
+    /// Change settings of an existing namespace, which need not be the one this client is bound to
+    async fn update_namespace(
+        &self,
+        namespace: String,
+        options: UpdateNamespaceOptions,
+    ) -> Result<UpdateNamespaceResponse>;
+
+    /// Deprecate a namespace, after which no new workflows may be started in it
+    async fn deprecate_namespace(&self, namespace: String) -> Result<DeprecateNamespaceResponse>;
+
+    /// Fetch the search attributes registered on the server and their types, which can be used to
+    /// validate and decode typed search attributes
+    async fn get_search_attributes(&self) -> Result<SearchAttributeRegistry>;
+
+    /// Describe a task queue, including the pollers which have recently polled it. If
+    /// `include_status` is true, the response also includes the queue's backlog status.
+    async fn describe_task_queue(
+        &self,
+        task_queue: String,
+        task_queue_type: TaskQueueType,
+        include_status: bool,
+    ) -> Result<DescribeTaskQueueResponse>;
+
+    /// Lists workflow executions matching a visibility query. An empty query matches all
+    /// executions. Pass the `next_page_token` from a response to fetch the following page. See
+    /// [VisibilityQuery] for help building queries.
+    async fn list_workflow_executions(
+        &self,
+        page_size: i32,
+        next_page_token: Vec<u8>,
+        query: String,
+    ) -> Result<ListWorkflowExecutionsResponse>;
+
+    /// Counts workflow executions matching a visibility query. An empty query matches all
+    /// executions. See [VisibilityQuery] for help building queries.
+    async fn count_workflow_executions(
+        &self,
+        query: String,
+    ) -> Result<CountWorkflowExecutionsResponse>;
+
+    /// Lists all workflow executions matching a visibility query, fetching pages of up to
+    /// `page_size` executions as the stream is consumed. The stream ends after the first error.
+    fn list_workflow_executions_stream(
+        &self,
+        page_size: i32,
+        query: String,
+    ) -> BoxStream<'_, Result<workflow::WorkflowExecutionInfo>>
+    where
+        Self: Sync,
+    {
+        stream::try_unfold(Some(vec![]), move |page_token| {
+            let query = query.clone();
+            async move {
+                let page_token = match page_token {
+                    Some(t) => t,
+                    None => return Ok::<_, Status>(None),
+                };
+                let resp = self
+                    .list_workflow_executions(page_size, page_token, query)
+                    .await?;
+                let next_page_token = Some(resp.next_page_token).filter(|t| !t.is_empty());
+                Ok(Some((
+                    stream::iter(resp.executions.into_iter().map(Ok)),
+                    next_page_token,
+                )))
+            }
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    /// Returns approximate backlog statistics for a task queue, which can be used to decide how
+    /// many workers should be polling it.
+    async fn task_queue_backlog_stats(
+        &self,
+        task_queue: String,
+        task_queue_type: TaskQueueType,
+    ) -> Result<TaskQueueBacklogStats>
+    where
+        Self: Sync,
+    {
+        Ok(self
+            .describe_task_queue(task_queue, task_queue_type, true)
+            .await?
+            .into())
+    }
+
     /// Returns options that were used to initialize the client
     fn get_options(&self) -> &ClientOptions;
 
     /// Returns the namespace this client is bound to
     fn namespace(&self) -> &str;
+
+    /// Returns the server's capabilities, if they are known. They are not known before a lazily
+    /// connected client has made any calls, or if the server doesn't report them.
+    fn capabilities(&self) -> Option<get_system_info_response::Capabilities>;
+
+    /// Subscribe to be told how long polling is paused for every time the client's poll circuit
+    /// breaker opens. Returns `None` if the client has no circuit breaker.
+    fn subscribe_poll_pauses(&self) -> Option<tokio::sync::broadcast::Receiver<Duration>> {
+        None
+    }
+
+    /// Returns a client sharing this client's connection which identifies itself to the server
+    /// with `identity` rather than [ClientOptions::identity]. Returns `None` if the client does not
+    /// support overriding its identity.
+    fn with_identity(
+        &self,
+        _identity: String,
+    ) -> Option<Arc<dyn WorkflowClientTrait + Send + Sync>> {
+        None
+    }
+
+    /// Like [WorkflowClientTrait::with_identity], but returns a client of the same type. Clients
+    /// which wrap other clients (like [RetryClient]) support overriding their identity whenever
+    /// the client they wrap implements this.
+    fn clone_with_identity(&self, _identity: String) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
 }
 
 /// Optional fields supplied at the start of workflow execution
@@ -656,8 +1353,107 @@ pub struct WorkflowOptions {
     /// Optionally indicates the default task timeout for workflow tasks
     pub task_timeout: Option<Duration>,
 
-    /// Optionally associate extra search attributes with a workflow
+    /// Optionally associate extra search attributes with a workflow. Typed attributes can be
+    /// provided by converting a [temporal_sdk_core_protos::TypedSearchAttributes] into these.
     pub search_attributes: Option<HashMap<String, Payload>>,
+
+    /// Optionally control whether a workflow may be started with the same id as a previous one.
+    /// The server allows duplicates by default.
+    pub id_reuse_policy: Option<WorkflowIdReusePolicy>,
+
+    /// Optionally set a retry policy for the workflow
+    pub retry_policy: Option<RetryPolicy>,
+}
+
+/// Everything needed to signal a workflow, starting it first if it isn't already running. See
+/// [WorkflowClientTrait::signal_with_start_workflow_execution].
+#[derive(Debug, Clone, Default)]
+pub struct SignalWithStartOptions {
+    /// Input to the workflow, if it is started
+    pub input: Option<Payloads>,
+    /// The task queue the workflow is started on, if it is started
+    pub task_queue: String,
+    /// The id of the workflow to signal or start
+    pub workflow_id: String,
+    /// The type of the workflow, if it is started
+    pub workflow_type: String,
+    /// Used by the server to de-duplicate requests. Generated if not set, and reused if the
+    /// request is retried.
+    pub request_id: Option<String>,
+    /// The signal to send
+    pub signal_name: String,
+    /// Input to the signal
+    pub signal_input: Option<Payloads>,
+    /// Options used if the workflow is started
+    pub workflow_options: WorkflowOptions,
+}
+
+/// Approximate statistics about a task queue's backlog, as returned by
+/// [WorkflowClientTrait::task_queue_backlog_stats].
+///
+/// The server only reports the size of the backlog and how far dispatching has progressed through
+/// it, so the dispatch rate and backlog age are derived from two sets of stats fetched some time
+/// apart. Use [TaskQueueBacklogStats::since] on each newly fetched set to fill them in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskQueueBacklogStats {
+    /// The approximate number of tasks waiting to be dispatched
+    pub approximate_backlog_count: i64,
+    /// The maximum rate, in tasks per second, at which the server will dispatch tasks from the
+    /// queue
+    pub dispatch_rate_limit: f64,
+    /// How many pollers have recently polled the queue
+    pub num_pollers: usize,
+    /// The approximate rate, in tasks per second, at which tasks were dispatched from the queue
+    /// between the previous stats and these
+    pub dispatch_rate: Option<f64>,
+    /// Approximately how long the oldest task in the backlog has been waiting, assuming tasks
+    /// keep being dispatched at [Self::dispatch_rate]. `None` if nothing was dispatched.
+    pub backlog_age: Option<Duration>,
+    /// The id of the last task the server has finished dispatching
+    ack_level: i64,
+    fetched_at: Instant,
+}
+
+impl TaskQueueBacklogStats {
+    /// Fills in the dispatch rate and backlog age from how far dispatching progressed since
+    /// `previous` stats for the same task queue were fetched
+    pub fn since(mut self, previous: &Self) -> Self {
+        let elapsed = self
+            .fetched_at
+            .saturating_duration_since(previous.fetched_at)
+            .as_secs_f64();
+        if elapsed <= 0.0 {
+            return self;
+        }
+        let dispatched = (self.ack_level - previous.ack_level).max(0);
+        let rate = dispatched as f64 / elapsed;
+        self.dispatch_rate = Some(rate);
+        self.backlog_age = if self.approximate_backlog_count <= 0 {
+            Some(Duration::ZERO)
+        } else if rate > 0.0 {
+            Some(Duration::from_secs_f64(
+                self.approximate_backlog_count as f64 / rate,
+            ))
+        } else {
+            None
+        };
+        self
+    }
+}
+
+impl From<DescribeTaskQueueResponse> for TaskQueueBacklogStats {
+    fn from(resp: DescribeTaskQueueResponse) -> Self {
+        let status = resp.task_queue_status.unwrap_or_default();
+        Self {
+            approximate_backlog_count: status.backlog_count_hint,
+            dispatch_rate_limit: status.rate_per_second,
+            num_pollers: resp.pollers.len(),
+            dispatch_rate: None,
+            backlog_age: None,
+            ack_level: status.ack_level,
+            fetched_at: Instant::now(),
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -688,6 +1484,8 @@ impl WorkflowClientTrait for Client {
                 request_id,
                 workflow_task_timeout: options.task_timeout.map(Into::into),
                 search_attributes: options.search_attributes.map(Into::into),
+                workflow_id_reuse_policy: options.id_reuse_policy.unwrap_or_default() as i32,
+                retry_policy: options.retry_policy,
                 ..Default::default()
             })
             .await?
@@ -912,6 +1710,40 @@ impl WorkflowClientTrait for Client {
             .into_inner())
     }
 
+    async fn signal_with_start_workflow_execution(
+        &self,
+        options: SignalWithStartOptions,
+    ) -> Result<SignalWithStartWorkflowExecutionResponse> {
+        let wf_opts = options.workflow_options;
+        Ok(self
+            .wf_svc()
+            .signal_with_start_workflow_execution(SignalWithStartWorkflowExecutionRequest {
+                namespace: self.namespace.clone(),
+                workflow_id: options.workflow_id,
+                workflow_type: Some(WorkflowType {
+                    name: options.workflow_type,
+                }),
+                task_queue: Some(TaskQueue {
+                    name: options.task_queue,
+                    kind: TaskQueueKind::Normal as i32,
+                }),
+                input: options.input,
+                workflow_task_timeout: wf_opts.task_timeout.map(Into::into),
+                identity: self.inner.options.identity.clone(),
+                request_id: options
+                    .request_id
+                    .unwrap_or_else(|| Uuid::new_v4().to_string()),
+                workflow_id_reuse_policy: wf_opts.id_reuse_policy.unwrap_or_default() as i32,
+                signal_name: options.signal_name,
+                signal_input: options.signal_input,
+                retry_policy: wf_opts.retry_policy,
+                search_attributes: wf_opts.search_attributes.map(Into::into),
+                ..Default::default()
+            })
+            .await?
+            .into_inner())
+    }
+
     async fn query_workflow_execution(
         &self,
         workflow_id: String,
@@ -972,6 +1804,28 @@ impl WorkflowClientTrait for Client {
             .into_inner())
     }
 
+    async fn get_serialized_workflow_execution_history(
+        &self,
+        workflow_id: String,
+        run_id: Option<String>,
+        page_token: Vec<u8>,
+    ) -> Result<SerializedHistoryPage> {
+        Ok(get_serialized_history(
+            &self.inner,
+            GetWorkflowExecutionHistoryRequest {
+                namespace: self.namespace.clone(),
+                execution: Some(WorkflowExecution {
+                    workflow_id,
+                    run_id: run_id.unwrap_or_default(),
+                }),
+                next_page_token: page_token,
+                ..Default::default()
+            },
+        )
+        .await?
+        .into_inner())
+    }
+
     async fn respond_legacy_query(
         &self,
         task_token: TaskToken,
@@ -1034,6 +1888,32 @@ impl WorkflowClientTrait for Client {
             .into_inner())
     }
 
+    async fn reset_workflow_execution(
+        &self,
+        workflow_id: String,
+        run_id: Option<String>,
+        workflow_task_finish_event_id: i64,
+        reason: String,
+        reset_reapply_type: ResetReapplyType,
+        request_id: String,
+    ) -> Result<ResetWorkflowExecutionResponse> {
+        Ok(self
+            .wf_svc()
+            .reset_workflow_execution(ResetWorkflowExecutionRequest {
+                namespace: self.namespace.clone(),
+                workflow_execution: Some(WorkflowExecution {
+                    workflow_id,
+                    run_id: run_id.unwrap_or_default(),
+                }),
+                reason,
+                workflow_task_finish_event_id,
+                request_id,
+                reset_reapply_type: reset_reapply_type as i32,
+            })
+            .await?
+            .into_inner())
+    }
+
     async fn list_namespaces(&self) -> Result<ListNamespacesResponse> {
         Ok(self
             .wf_svc()
@@ -1042,6 +1922,113 @@ impl WorkflowClientTrait for Client {
             .into_inner())
     }
 
+    async fn register_namespace(
+        &self,
+        options: RegisterNamespaceOptions,
+    ) -> Result<RegisterNamespaceResponse> {
+        Ok(self
+            .wf_svc()
+            .register_namespace(options.into_request())
+            .await?
+            .into_inner())
+    }
+
+    async fn describe_namespace(&self, namespace: String) -> Result<DescribeNamespaceResponse> {
+        Ok(self
+            .wf_svc()
+            .describe_namespace(DescribeNamespaceRequest {
+                namespace,
+                ..Default::default()
+            })
+            .await?
+            .into_inner())
+    }
+
+    async fn update_namespace(
+        &self,
+        namespace: String,
+        options: UpdateNamespaceOptions,
+    ) -> Result<UpdateNamespaceResponse> {
+        Ok(self
+            .wf_svc()
+            .update_namespace(options.into_request(namespace))
+            .await?
+            .into_inner())
+    }
+
+    async fn deprecate_namespace(&self, namespace: String) -> Result<DeprecateNamespaceResponse> {
+        Ok(self
+            .wf_svc()
+            .deprecate_namespace(DeprecateNamespaceRequest {
+                namespace,
+                ..Default::default()
+            })
+            .await?
+            .into_inner())
+    }
+
+    async fn get_search_attributes(&self) -> Result<SearchAttributeRegistry> {
+        Ok(self
+            .wf_svc()
+            .get_search_attributes(GetSearchAttributesRequest {})
+            .await?
+            .into_inner()
+            .into())
+    }
+
+    async fn list_workflow_executions(
+        &self,
+        page_size: i32,
+        next_page_token: Vec<u8>,
+        query: String,
+    ) -> Result<ListWorkflowExecutionsResponse> {
+        Ok(self
+            .wf_svc()
+            .list_workflow_executions(ListWorkflowExecutionsRequest {
+                namespace: self.namespace.clone(),
+                page_size,
+                next_page_token,
+                query,
+            })
+            .await?
+            .into_inner())
+    }
+
+    async fn count_workflow_executions(
+        &self,
+        query: String,
+    ) -> Result<CountWorkflowExecutionsResponse> {
+        Ok(self
+            .wf_svc()
+            .count_workflow_executions(CountWorkflowExecutionsRequest {
+                namespace: self.namespace.clone(),
+                query,
+            })
+            .await?
+            .into_inner())
+    }
+
+    async fn describe_task_queue(
+        &self,
+        task_queue: String,
+        task_queue_type: TaskQueueType,
+        include_status: bool,
+    ) -> Result<DescribeTaskQueueResponse> {
+        Ok(self
+            .wf_svc()
+            .describe_task_queue(DescribeTaskQueueRequest {
+                namespace: self.namespace.clone(),
+                task_queue: Some(TaskQueue {
+                    name: task_queue,
+                    kind: TaskQueueKind::Normal as i32,
+                }),
+                task_queue_type: task_queue_type as i32,
+                include_task_queue_status: include_status,
+            })
+            .await?
+            .into_inner())
+    }
+
     fn get_options(&self) -> &ClientOptions {
         &self.inner.options
     }
@@ -1049,6 +2036,23 @@ impl WorkflowClientTrait for Client {
     fn namespace(&self) -> &str {
         &self.namespace
     }
+
+    fn capabilities(&self) -> Option<get_system_info_response::Capabilities> {
+        self.inner.capabilities().cloned()
+    }
+
+    fn with_identity(
+        &self,
+        identity: String,
+    ) -> Option<Arc<dyn WorkflowClientTrait + Send + Sync>> {
+        Some(Arc::new(self.clone_with_identity(identity)?))
+    }
+
+    fn clone_with_identity(&self, identity: String) -> Option<Self> {
+        let mut client = self.clone();
+        client.set_identity(identity);
+        Some(client)
+    }
 }
 
 mod sealed {
@@ -1065,10 +2069,10 @@ mod sealed {
 }
 
 impl RawClientLikeUser for Client {
-    type RawClientT = WorkflowServiceClientWithMetrics;
+    type RawClientT = ConfiguredClient<WorkflowServiceClientWithMetrics>;
 
     fn wf_svc(&self) -> Self::RawClientT {
-        self.raw_client().clone()
+        self.inner.clone()
     }
 }
 
@@ -1094,5 +2098,60 @@ pub trait WfClientExt: WfHandleClient + Sized {
             },
         )
     }
+
+    /// Create a handle for an activity which will be completed asynchronously, identified either
+    /// by its task token or by its id within a workflow execution.
+    fn get_async_activity_handle(
+        &self,
+        identifier: impl Into<ActivityIdentifier>,
+    ) -> AsyncActivityHandle<Self::RawClientT>
+    where
+        Self::RawClientT: Clone,
+    {
+        AsyncActivityHandle::new(
+            self.wf_svc(),
+            self.namespace().to_string(),
+            self.get_options().identity.clone(),
+            identifier.into(),
+        )
+    }
 }
 impl<T> WfClientExt for T where T: WfHandleClient + Sized {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use temporal_sdk_core_protos::temporal::api::taskqueue::v1::TaskQueueStatus;
+
+    fn stats(backlog: i64, ack_level: i64) -> TaskQueueBacklogStats {
+        DescribeTaskQueueResponse {
+            pollers: vec![],
+            task_queue_status: Some(TaskQueueStatus {
+                backlog_count_hint: backlog,
+                ack_level,
+                ..Default::default()
+            }),
+        }
+        .into()
+    }
+
+    #[test]
+    fn backlog_rates_derived_from_previous_stats() {
+        let first = stats(100, 1000);
+        assert_eq!(first.dispatch_rate, None);
+        assert_eq!(first.backlog_age, None);
+
+        let mut second = stats(50, 1020);
+        second.fetched_at = first.fetched_at + Duration::from_secs(2);
+        let second = second.since(&first);
+        assert_eq!(second.dispatch_rate, Some(10.0));
+        assert_eq!(second.backlog_age, Some(Duration::from_secs(5)));
+
+        // Nothing dispatched while there's a backlog means its age can't be estimated
+        let mut stalled = stats(50, 1020);
+        stalled.fetched_at = second.fetched_at + Duration::from_secs(1);
+        let stalled = stalled.since(&second);
+        assert_eq!(stalled.dispatch_rate, Some(0.0));
+        assert_eq!(stalled.backlog_age, None);
+    }
+}
@@ -5,10 +5,14 @@ use opentelemetry::{
     KeyValue,
 };
 use std::{
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
     task::{Context, Poll},
     time::{Duration, Instant},
 };
+use tokio::sync::watch;
 use tonic::{body::BoxBody, transport::Channel};
 use tower::Service;
 
@@ -24,9 +28,12 @@ pub struct MetricsContext {
     svc_request_failed: Counter<u64>,
     long_svc_request: Counter<u64>,
     long_svc_request_failed: Counter<u64>,
+    poll_circuit_breaker_opened: Counter<u64>,
 
     svc_request_latency: ValueRecorder<u64>,
     long_svc_request_latency: ValueRecorder<u64>,
+
+    rpc_attempt_failed: Counter<u64>,
 }
 
 impl MetricsContext {
@@ -38,8 +45,10 @@ impl MetricsContext {
             svc_request_failed: meter.u64_counter("request_failure").init(),
             long_svc_request: meter.u64_counter("long_request").init(),
             long_svc_request_failed: meter.u64_counter("long_request_failure").init(),
+            poll_circuit_breaker_opened: meter.u64_counter("poll_circuit_breaker_opened").init(),
             svc_request_latency: meter.u64_value_recorder("request_latency").init(),
             long_svc_request_latency: meter.u64_value_recorder("long_request_latency").init(),
+            rpc_attempt_failed: meter.u64_counter("rpc_attempt_failure").init(),
         }
     }
 
@@ -77,6 +86,11 @@ impl MetricsContext {
         }
     }
 
+    /// Long polling was paused because polls were persistently failing
+    pub(crate) fn poll_circuit_breaker_opened(&self) {
+        self.poll_circuit_breaker_opened.add(1, &self.kvs);
+    }
+
     /// Record service request latency
     pub(crate) fn record_svc_req_latency(&self, dur: Duration) {
         if self.poll_is_long {
@@ -87,11 +101,68 @@ impl MetricsContext {
                 .record(dur.as_millis() as u64, &self.kvs);
         }
     }
+
+    /// An attempt of a (possibly retried) call to the temporal service failed. Latency of every
+    /// attempt is already recorded as the request latency.
+    fn rpc_attempt_failed(&self) {
+        self.rpc_attempt_failed.add(1, &self.kvs);
+    }
+}
+
+/// Records failures of the attempts made by one call through a [crate::RetryClient], tagged with
+/// the call's name and namespace, the status the attempt ended with, and whether it was a retry
+pub(crate) struct RpcAttemptRecorder {
+    metrics: MetricsContext,
+    attempts: AtomicUsize,
+}
+
+impl RpcAttemptRecorder {
+    pub(crate) fn new(metrics: &MetricsContext, call_name: &'static str) -> Self {
+        Self {
+            metrics: metrics.with_new_attrs([svc_operation(call_name.to_string())]),
+            attempts: AtomicUsize::new(0),
+        }
+    }
+
+    /// Tag everything recorded with the provided attributes too
+    pub(crate) fn with_new_attrs(mut self, new_kvs: impl IntoIterator<Item = KeyValue>) -> Self {
+        self.metrics.add_new_attrs(new_kvs);
+        self
+    }
+
+    /// Must be called as an attempt begins. The returned value records the attempt once it ends.
+    pub(crate) fn start_attempt(&self) -> RpcAttempt {
+        RpcAttempt {
+            metrics: self.metrics.clone(),
+            is_retry: self.attempts.fetch_add(1, Ordering::Relaxed) > 0,
+        }
+    }
+}
+
+/// An in-progress attempt of a call, see [RpcAttemptRecorder]
+pub(crate) struct RpcAttempt {
+    metrics: MetricsContext,
+    is_retry: bool,
+}
+
+impl RpcAttempt {
+    pub(crate) fn finished<R>(self, result: &Result<R, tonic::Status>) {
+        if let Err(status) = result {
+            self.metrics
+                .with_new_attrs([
+                    KeyValue::new(KEY_STATUS_CODE, format!("{:?}", status.code())),
+                    KeyValue::new(KEY_IS_RETRY, self.is_retry),
+                ])
+                .rpc_attempt_failed();
+        }
+    }
 }
 
 const KEY_NAMESPACE: &str = "namespace";
 const KEY_SVC_METHOD: &str = "operation";
 const KEY_TASK_QUEUE: &str = "task_queue";
+const KEY_STATUS_CODE: &str = "status_code";
+const KEY_IS_RETRY: &str = "is_retry";
 
 pub(crate) fn namespace_kv(ns: String) -> KeyValue {
     KeyValue::new(KEY_NAMESPACE, ns)
@@ -109,6 +180,8 @@ pub(crate) fn svc_operation(op: String) -> KeyValue {
 #[derive(Debug, Clone)]
 pub struct GrpcMetricSvc {
     pub(crate) inner: Channel,
+    /// Receives a new channel whenever the connection is re-established, ex: to reload TLS certs
+    pub(crate) channel_updates: watch::Receiver<Channel>,
     // If set to none, metrics are a no-op
     pub(crate) metrics: Option<MetricsContext>,
 }
@@ -119,6 +192,10 @@ impl Service<http::Request<BoxBody>> for GrpcMetricSvc {
     type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Only swapped here, since the channel must be ready before it can be called
+        if self.channel_updates.has_changed().unwrap_or_default() {
+            self.inner = self.channel_updates.borrow_and_update().clone();
+        }
         self.inner.poll_ready(cx).map_err(Into::into)
     }
 
@@ -0,0 +1,171 @@
+//! Typed options for managing namespaces, see [crate::WorkflowClientTrait::register_namespace]
+
+use std::{collections::HashMap, time::Duration};
+use temporal_sdk_core_protos::temporal::api::{
+    enums::v1::ArchivalState,
+    namespace::v1::{NamespaceConfig, UpdateNamespaceInfo},
+    workflowservice::v1::{RegisterNamespaceRequest, UpdateNamespaceRequest},
+};
+
+/// Where and whether a namespace archives closed workflows' histories or visibility records
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchivalConfig {
+    /// Whether archival is enabled
+    pub state: ArchivalState,
+    /// Where archived data is written, ex: `file:///tmp/archival`. May be left blank to use the
+    /// cluster's default.
+    pub uri: String,
+}
+
+impl ArchivalConfig {
+    /// Archive to the provided uri
+    pub fn enabled(uri: impl Into<String>) -> Self {
+        Self {
+            state: ArchivalState::Enabled,
+            uri: uri.into(),
+        }
+    }
+
+    /// Don't archive
+    pub fn disabled() -> Self {
+        Self {
+            state: ArchivalState::Disabled,
+            uri: "".to_string(),
+        }
+    }
+}
+
+/// Options for registering a new namespace
+#[derive(Debug, Clone, Default)]
+pub struct RegisterNamespaceOptions {
+    /// The namespace's name
+    pub namespace: String,
+    /// A human readable description of the namespace
+    pub description: String,
+    /// Email address of the namespace's owner
+    pub owner_email: String,
+    /// How long closed workflows' histories are retained. The server's default is used if unset.
+    pub workflow_execution_retention_period: Option<Duration>,
+    /// Arbitrary data to attach to the namespace
+    pub data: HashMap<String, String>,
+    /// Whether and where histories are archived. The cluster's default is used if unset.
+    pub history_archival: Option<ArchivalConfig>,
+    /// Whether and where visibility records are archived. The cluster's default is used if unset.
+    pub visibility_archival: Option<ArchivalConfig>,
+}
+
+impl RegisterNamespaceOptions {
+    pub(crate) fn into_request(self) -> RegisterNamespaceRequest {
+        let (history_archival_state, history_archival_uri) = archival_fields(self.history_archival);
+        let (visibility_archival_state, visibility_archival_uri) =
+            archival_fields(self.visibility_archival);
+        RegisterNamespaceRequest {
+            namespace: self.namespace,
+            description: self.description,
+            owner_email: self.owner_email,
+            workflow_execution_retention_period: self
+                .workflow_execution_retention_period
+                .map(Into::into),
+            data: self.data,
+            history_archival_state,
+            history_archival_uri,
+            visibility_archival_state,
+            visibility_archival_uri,
+            ..Default::default()
+        }
+    }
+}
+
+/// Changes to make to an existing namespace. Anything left unset is not changed.
+#[derive(Debug, Clone, Default)]
+pub struct UpdateNamespaceOptions {
+    /// A new description for the namespace
+    pub description: Option<String>,
+    /// A new owner email for the namespace
+    pub owner_email: Option<String>,
+    /// Data to add to the namespace's data, replacing existing values for the same keys
+    pub data: HashMap<String, String>,
+    /// How long closed workflows' histories are retained
+    pub workflow_execution_retention_period: Option<Duration>,
+    /// Whether and where histories are archived
+    pub history_archival: Option<ArchivalConfig>,
+    /// Whether and where visibility records are archived
+    pub visibility_archival: Option<ArchivalConfig>,
+}
+
+impl UpdateNamespaceOptions {
+    pub(crate) fn into_request(self, namespace: String) -> UpdateNamespaceRequest {
+        let update_info =
+            (self.description.is_some() || self.owner_email.is_some() || !self.data.is_empty())
+                .then(|| UpdateNamespaceInfo {
+                    description: self.description.unwrap_or_default(),
+                    owner_email: self.owner_email.unwrap_or_default(),
+                    data: self.data,
+                    ..Default::default()
+                });
+        let config = (self.workflow_execution_retention_period.is_some()
+            || self.history_archival.is_some()
+            || self.visibility_archival.is_some())
+        .then(|| {
+            let (history_archival_state, history_archival_uri) =
+                archival_fields(self.history_archival);
+            let (visibility_archival_state, visibility_archival_uri) =
+                archival_fields(self.visibility_archival);
+            NamespaceConfig {
+                workflow_execution_retention_ttl: self
+                    .workflow_execution_retention_period
+                    .map(Into::into),
+                history_archival_state,
+                history_archival_uri,
+                visibility_archival_state,
+                visibility_archival_uri,
+                ..Default::default()
+            }
+        });
+        UpdateNamespaceRequest {
+            namespace,
+            update_info,
+            config,
+            ..Default::default()
+        }
+    }
+}
+
+/// An unset config leaves the state unspecified, which the server treats as "use the default"
+/// or "don't change" as appropriate
+fn archival_fields(cfg: Option<ArchivalConfig>) -> (i32, String) {
+    cfg.map(|c| (c.state as i32, c.uri))
+        .unwrap_or((ArchivalState::Unspecified as i32, "".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_only_sends_what_changed() {
+        let req = UpdateNamespaceOptions::default().into_request("ns".to_string());
+        assert_eq!(req.namespace, "ns");
+        assert!(req.update_info.is_none());
+        assert!(req.config.is_none());
+
+        let req = UpdateNamespaceOptions {
+            workflow_execution_retention_period: Some(Duration::from_secs(86400)),
+            history_archival: Some(ArchivalConfig::enabled("file:///tmp/archival")),
+            ..Default::default()
+        }
+        .into_request("ns".to_string());
+        assert!(req.update_info.is_none());
+        let config = req.config.unwrap();
+        assert_eq!(
+            config.workflow_execution_retention_ttl,
+            Some(Duration::from_secs(86400).into())
+        );
+        assert_eq!(config.history_archival_state, ArchivalState::Enabled as i32);
+        assert_eq!(config.history_archival_uri, "file:///tmp/archival");
+        assert_eq!(
+            config.visibility_archival_state,
+            ArchivalState::Unspecified as i32
+        );
+    }
+}
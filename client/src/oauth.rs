@@ -0,0 +1,370 @@
+//! Support for authenticating with OAuth2 bearer tokens which are fetched, and refreshed before
+//! they expire, automatically.
+
+use crate::HeadersProvider;
+use anyhow::bail;
+use parking_lot::RwLock;
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Formatter},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Weak,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+use tonic::Status;
+use url::{form_urlencoded, Url};
+
+/// How long to wait before trying again when refreshing a token in the background fails
+const REFRESH_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// An access token, and how long it is valid for
+#[derive(Clone)]
+pub struct AccessToken {
+    /// The bearer token attached to calls
+    pub token: String,
+    /// How long after being fetched the token expires. If unset, the token is used until the server
+    /// rejects it.
+    pub expires_in: Option<Duration>,
+}
+
+impl Debug for AccessToken {
+    // Intentionally omit the token so that it can't leak
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccessToken")
+            .field("expires_in", &self.expires_in)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Something which can fetch new OAuth2 access tokens
+#[async_trait::async_trait]
+pub trait TokenSource: Send + Sync + Debug {
+    /// Fetch a new access token
+    async fn fetch_token(&self) -> Result<AccessToken, anyhow::Error>;
+}
+
+/// Fetches tokens from an OAuth2 (or OIDC) token endpoint using the client credentials grant
+#[derive(Clone)]
+pub struct ClientCredentials {
+    /// The token endpoint, ex: `https://auth.example.com/oauth2/token`
+    pub token_url: Url,
+    /// The client id to authenticate as
+    pub client_id: String,
+    /// The secret for the client
+    pub client_secret: String,
+    /// Scopes to request. May be empty, in which case the server's defaults are used.
+    pub scopes: Vec<String>,
+    /// Some providers require the audience (the API tokens are for) to be specified
+    pub audience: Option<String>,
+    /// Sends requests to the token endpoint
+    pub http_client: Arc<dyn TokenHttpClient>,
+}
+
+/// Sends the requests [ClientCredentials] makes to token endpoints. Implement this with whatever
+/// HTTP client the application already uses, so that proxies, redirects, and TLS are handled
+/// the same way as for its other requests.
+#[async_trait::async_trait]
+pub trait TokenHttpClient: Send + Sync + Debug {
+    /// POST the url-encoded `form` to `url`, returning the response's status code and body
+    async fn post_form(&self, url: &Url, form: String) -> Result<(u16, Vec<u8>), anyhow::Error>;
+}
+
+impl Debug for ClientCredentials {
+    // Intentionally omit the secret so that it can't leak
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientCredentials")
+            .field("token_url", &self.token_url.as_str())
+            .field("client_id", &self.client_id)
+            .field("scopes", &self.scopes)
+            .field("audience", &self.audience)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+#[async_trait::async_trait]
+impl TokenSource for ClientCredentials {
+    async fn fetch_token(&self) -> Result<AccessToken, anyhow::Error> {
+        let form = {
+            let mut form = form_urlencoded::Serializer::new(String::new());
+            form.append_pair("grant_type", "client_credentials")
+                .append_pair("client_id", &self.client_id)
+                .append_pair("client_secret", &self.client_secret);
+            if !self.scopes.is_empty() {
+                form.append_pair("scope", &self.scopes.join(" "));
+            }
+            if let Some(audience) = self.audience.as_ref() {
+                form.append_pair("audience", audience);
+            }
+            form.finish()
+        };
+
+        let (status, body) = self.http_client.post_form(&self.token_url, form).await?;
+        if !(200..300).contains(&status) {
+            bail!(
+                "Token endpoint responded with {}: {}",
+                status,
+                String::from_utf8_lossy(&body)
+            );
+        }
+        let resp: TokenResponse = serde_json::from_slice(&body)?;
+        Ok(AccessToken {
+            token: resp.access_token,
+            expires_in: resp.expires_in.map(Duration::from_secs),
+        })
+    }
+}
+
+/// Attaches OAuth2 bearer tokens from a [TokenSource] to every call. Tokens are cached, and
+/// refreshed in the background shortly before they expire, whether or not calls are being made.
+/// If the server rejects a token it is discarded and a new one is fetched.
+#[derive(Debug)]
+pub struct OAuth2TokenProvider {
+    inner: Arc<ProviderState>,
+}
+
+#[derive(Debug)]
+struct ProviderState {
+    source: Arc<dyn TokenSource>,
+    refresh_before_expiry: Duration,
+    current: RwLock<Option<CachedToken>>,
+    /// Held while fetching a token, so that only one fetch happens at a time
+    fetching: Mutex<()>,
+    /// Set while a background task is waiting to refresh the current token
+    refresh_scheduled: AtomicBool,
+}
+
+#[derive(Debug, Clone)]
+struct CachedToken {
+    header_value: String,
+    expires_at: Option<Instant>,
+}
+
+impl CachedToken {
+    /// True if the token has not expired as of `at`
+    fn valid_at(&self, at: Instant) -> bool {
+        match self.expires_at {
+            Some(e) => e > at,
+            None => true,
+        }
+    }
+}
+
+impl OAuth2TokenProvider {
+    /// Create a provider which will fetch tokens from the provided source, refreshing them 30
+    /// seconds before they expire.
+    pub fn new(source: impl TokenSource + 'static) -> Self {
+        Self {
+            inner: Arc::new(ProviderState {
+                source: Arc::new(source),
+                refresh_before_expiry: Duration::from_secs(30),
+                current: RwLock::new(None),
+                fetching: Mutex::new(()),
+                refresh_scheduled: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Change how long before a token expires it is refreshed
+    pub fn with_refresh_before_expiry(mut self, refresh_before_expiry: Duration) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("Provider state is only shared once tokens are fetched")
+            .refresh_before_expiry = refresh_before_expiry;
+        self
+    }
+}
+
+impl ProviderState {
+    async fn fetch(&self) -> Result<CachedToken, anyhow::Error> {
+        let fetched_at = Instant::now();
+        let token = self.source.fetch_token().await?;
+        let cached = CachedToken {
+            header_value: format!("Bearer {}", token.token),
+            expires_at: token.expires_in.map(|e| fetched_at + e),
+        };
+        *self.current.write() = Some(cached.clone());
+        Ok(cached)
+    }
+
+    /// Fetch a token, and make sure it will be refreshed before it expires
+    async fn fetch_and_schedule_refresh(self: &Arc<Self>) -> Result<CachedToken, anyhow::Error> {
+        let cached = self.fetch().await?;
+        if cached.expires_at.is_some() && !self.refresh_scheduled.swap(true, Ordering::AcqRel) {
+            tokio::spawn(Self::refresh_before_expiry(Arc::downgrade(self)));
+        }
+        Ok(cached)
+    }
+
+    /// Keeps refreshing the current token shortly before it expires, until the provider is
+    /// dropped or a token which never expires is fetched
+    async fn refresh_before_expiry(state: Weak<Self>) {
+        loop {
+            let (expires_at, refresh_at) = match state.upgrade() {
+                Some(s) => match s.current.read().as_ref().and_then(|t| t.expires_at) {
+                    Some(e) => (e, e.checked_sub(s.refresh_before_expiry).unwrap_or(e)),
+                    None => {
+                        s.refresh_scheduled.store(false, Ordering::Release);
+                        return;
+                    }
+                },
+                None => return,
+            };
+            tokio::time::sleep_until(refresh_at.into()).await;
+            let s = match state.upgrade() {
+                Some(s) => s,
+                None => return,
+            };
+            let guard = s.fetching.lock().await;
+            // The token may have been replaced (ex: after being rejected) while we waited
+            let current_expiry = s.current.read().as_ref().and_then(|t| t.expires_at);
+            if current_expiry != Some(expires_at) {
+                continue;
+            }
+            if let Err(e) = s.fetch().await {
+                warn!(error=?e, "Failed to refresh OAuth2 token");
+                drop(guard);
+                drop(s);
+                tokio::time::sleep(REFRESH_RETRY_INTERVAL).await;
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl HeadersProvider for OAuth2TokenProvider {
+    async fn headers(&self) -> Result<HashMap<String, String>, Status> {
+        let cached = self.inner.current.read().clone();
+        let token = match cached {
+            Some(t) if t.valid_at(Instant::now()) => t,
+            _ => {
+                let _guard = self.inner.fetching.lock().await;
+                // Someone else may have fetched a token while we waited
+                let cached = self.inner.current.read().clone();
+                match cached {
+                    Some(t) if t.valid_at(Instant::now()) => t,
+                    _ => self.inner.fetch_and_schedule_refresh().await.map_err(|e| {
+                        Status::unavailable(format!("Failed to fetch OAuth2 token: {:?}", e))
+                    })?,
+                }
+            }
+        };
+        Ok(HashMap::from([(
+            "authorization".to_string(),
+            token.header_value,
+        )]))
+    }
+
+    async fn credentials_rejected(&self) -> bool {
+        *self.inner.current.write() = None;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[derive(Debug, Default)]
+    struct CountingSource {
+        fetches: Arc<AtomicUsize>,
+        expires_in: Option<Duration>,
+    }
+    #[async_trait::async_trait]
+    impl TokenSource for CountingSource {
+        async fn fetch_token(&self) -> Result<AccessToken, anyhow::Error> {
+            let n = self.fetches.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(AccessToken {
+                token: format!("token-{}", n),
+                expires_in: self.expires_in,
+            })
+        }
+    }
+
+    async fn auth_header(provider: &OAuth2TokenProvider) -> String {
+        provider.headers().await.unwrap()["authorization"].clone()
+    }
+
+    #[tokio::test]
+    async fn tokens_are_cached_until_rejected() {
+        let provider = OAuth2TokenProvider::new(CountingSource::default());
+        assert_eq!(auth_header(&provider).await, "Bearer token-1");
+        assert_eq!(auth_header(&provider).await, "Bearer token-1");
+        assert!(provider.credentials_rejected().await);
+        assert_eq!(auth_header(&provider).await, "Bearer token-2");
+    }
+
+    #[tokio::test]
+    async fn tokens_are_refreshed_before_expiry_without_being_used() {
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let provider = OAuth2TokenProvider::new(CountingSource {
+            fetches: fetches.clone(),
+            expires_in: Some(Duration::from_millis(400)),
+        })
+        .with_refresh_before_expiry(Duration::from_millis(300));
+        assert_eq!(auth_header(&provider).await, "Bearer token-1");
+        // The first token is refreshed 100ms after being fetched, well before it expires
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        assert_eq!(auth_header(&provider).await, "Bearer token-2");
+
+        // Refreshing stops once the provider is gone
+        drop(provider);
+        let fetched = fetches.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(fetches.load(Ordering::SeqCst), fetched);
+    }
+
+    #[derive(Debug, Default)]
+    struct FakeTokenEndpoint {
+        forms: parking_lot::Mutex<Vec<(Url, String)>>,
+    }
+    #[async_trait::async_trait]
+    impl TokenHttpClient for FakeTokenEndpoint {
+        async fn post_form(
+            &self,
+            url: &Url,
+            form: String,
+        ) -> Result<(u16, Vec<u8>), anyhow::Error> {
+            self.forms.lock().push((url.clone(), form));
+            Ok((
+                200,
+                br#"{"access_token":"tok","token_type":"Bearer","expires_in":60}"#.to_vec(),
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn client_credentials_fetches_from_token_endpoint() {
+        let endpoint = Arc::new(FakeTokenEndpoint::default());
+        let creds = ClientCredentials {
+            token_url: Url::parse("https://10.0.0.1/oauth2/token").unwrap(),
+            client_id: "id".to_string(),
+            client_secret: "secret".to_string(),
+            scopes: vec!["a".to_string(), "b".to_string()],
+            audience: None,
+            http_client: endpoint.clone(),
+        };
+        let token = creds.fetch_token().await.unwrap();
+        assert_eq!(token.token, "tok");
+        assert_eq!(token.expires_in, Some(Duration::from_secs(60)));
+
+        let forms = endpoint.forms.lock();
+        assert_eq!(forms[0].0, creds.token_url);
+        let form: HashMap<_, _> = form_urlencoded::parse(forms[0].1.as_bytes())
+            .into_owned()
+            .collect();
+        assert_eq!(form["grant_type"], "client_credentials");
+        assert_eq!(form["client_id"], "id");
+        assert_eq!(form["client_secret"], "secret");
+        assert_eq!(form["scope"], "a b");
+    }
+}
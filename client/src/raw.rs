@@ -5,18 +5,31 @@
 use crate::{
     metrics::{namespace_kv, task_queue_kv},
     raw::sealed::RawClientLike,
+    CapabilitiesCell, ClientOptions, ConfiguredClient, WorkflowServiceClientWithMetrics,
     LONG_POLL_TIMEOUT,
 };
 use futures::{future::BoxFuture, FutureExt};
+use http::uri::PathAndQuery;
+use std::{future::Future, str::FromStr, sync::Arc};
 use temporal_sdk_core_protos::temporal::api::{
-    taskqueue::v1::TaskQueue, workflowservice::v1::workflow_service_client::WorkflowServiceClient,
+    taskqueue::v1::TaskQueue,
+    workflowservice::v1::{
+        get_system_info_response::Capabilities, workflow_service_client::WorkflowServiceClient,
+        GetSystemInfoRequest, GetWorkflowExecutionHistoryRequest,
+    },
+};
+use temporal_sdk_core_protos::SerializedHistoryPage;
+use tonic::{
+    body::BoxBody,
+    client::GrpcService,
+    codec::ProstCodec,
+    metadata::{KeyAndValueRef, MetadataKey, MetadataValue},
+    Code,
 };
-use tonic::{body::BoxBody, client::GrpcService, metadata::KeyAndValueRef};
 
 pub(super) mod sealed {
     use super::*;
     use crate::{Client, ConfiguredClient, InterceptedMetricsSvc, RetryClient};
-    use futures::TryFutureExt;
     use tonic::{Request, Response, Status};
 
     /// Something that has a workflow service client
@@ -27,6 +40,17 @@ pub(super) mod sealed {
         /// Return the actual client instance
         fn client(&mut self) -> &mut WorkflowServiceClient<Self::SvcType>;
 
+        /// Return the options which configure hooks invoked around calls, if there are any
+        fn client_options(&self) -> Option<Arc<ClientOptions>> {
+            None
+        }
+
+        /// Return the cell server capabilities are stored in, if this client tracks them. They
+        /// are fetched before the call if they haven't been already.
+        fn capabilities_cell(&self) -> Option<CapabilitiesCell> {
+            None
+        }
+
         async fn do_call<F, Req, Resp>(
             &mut self,
             _call_name: &'static str,
@@ -58,6 +82,14 @@ pub(super) mod sealed {
             self.get_client_mut().client()
         }
 
+        fn client_options(&self) -> Option<Arc<ClientOptions>> {
+            self.get_client().client_options()
+        }
+
+        fn capabilities_cell(&self) -> Option<CapabilitiesCell> {
+            self.get_client().capabilities_cell()
+        }
+
         async fn do_call<F, Req, Resp>(
             &mut self,
             call_name: &'static str,
@@ -72,14 +104,14 @@ pub(super) mod sealed {
             ) -> BoxFuture<'static, Result<Response<Resp>, Status>>,
             F: Send + Sync + Unpin + 'static,
         {
-            let rtc = self.get_retry_config(call_name);
+            let mut retrier = self.retrier_for(call_name);
+            if let Some(labels) = req.extensions().get::<AttachMetricLabels>() {
+                retrier = retrier.with_metric_labels(labels.labels.clone());
+            }
             let req = req_cloner(&req);
-            let fact = || {
-                let req_clone = req_cloner(&req);
-                callfn(self.client(), req_clone)
-            };
-            let res = Self::make_future_retry(rtc, fact, call_name);
-            res.map_err(|(e, _attempt)| e).map_ok(|x| x.0).await
+            retrier
+                .call(|| callfn(self.client(), req_cloner(&req)))
+                .await
         }
     }
 
@@ -103,6 +135,14 @@ pub(super) mod sealed {
         fn client(&mut self) -> &mut WorkflowServiceClient<Self::SvcType> {
             &mut self.client
         }
+
+        fn client_options(&self) -> Option<Arc<ClientOptions>> {
+            Some(self.options.clone())
+        }
+
+        fn capabilities_cell(&self) -> Option<CapabilitiesCell> {
+            Some(self.capabilities.clone())
+        }
     }
 
     impl RawClientLike for Client {
@@ -111,6 +151,14 @@ pub(super) mod sealed {
         fn client(&mut self) -> &mut WorkflowServiceClient<Self::SvcType> {
             &mut self.inner
         }
+
+        fn client_options(&self) -> Option<Arc<ClientOptions>> {
+            Some(self.inner.options.clone())
+        }
+
+        fn capabilities_cell(&self) -> Option<CapabilitiesCell> {
+            Some(self.inner.capabilities.clone())
+        }
     }
 }
 
@@ -133,6 +181,120 @@ fn req_cloner<T: Clone>(cloneme: &tonic::Request<T>) -> tonic::Request<T> {
     new_req
 }
 
+/// Attaches headers from the headers provider and invokes the interceptor (if either are
+/// configured) around a single attempt of a call. If the server rejects the provided credentials
+/// and the provider says it has new ones, the call is made once more.
+async fn call_intercepted<Req, Resp, Fut>(
+    opts: Option<&ClientOptions>,
+    call_name: &'static str,
+    req: tonic::Request<Req>,
+    mut call: impl FnMut(tonic::Request<Req>) -> Fut,
+) -> Result<tonic::Response<Resp>, tonic::Status>
+where
+    Req: Clone,
+    Fut: Future<Output = Result<tonic::Response<Resp>, tonic::Status>>,
+{
+    let provider = match opts.and_then(|o| o.headers_provider.as_ref()) {
+        Some(p) => p,
+        None => return call_once(opts, call_name, req, &mut call).await,
+    };
+    let retry_req = req_cloner(&req);
+    match call_once(opts, call_name, req, &mut call).await {
+        Err(e) if e.code() == Code::Unauthenticated && provider.credentials_rejected().await => {
+            call_once(opts, call_name, retry_req, &mut call).await
+        }
+        res => res,
+    }
+}
+
+async fn call_once<Req, Resp, Fut>(
+    opts: Option<&ClientOptions>,
+    call_name: &'static str,
+    mut req: tonic::Request<Req>,
+    call: &mut impl FnMut(tonic::Request<Req>) -> Fut,
+) -> Result<tonic::Response<Resp>, tonic::Status>
+where
+    Fut: Future<Output = Result<tonic::Response<Resp>, tonic::Status>>,
+{
+    if let Some(provider) = opts.and_then(|o| o.headers_provider.as_ref()) {
+        let metadata = req.metadata_mut();
+        for (k, v) in provider.headers().await? {
+            if let (Ok(k), Ok(v)) = (MetadataKey::from_str(&k), MetadataValue::from_str(&v)) {
+                metadata.insert(k, v);
+            }
+        }
+    }
+    let interceptor = match opts.and_then(|o| o.interceptor.as_ref()) {
+        Some(i) => i,
+        None => return call(req).await,
+    };
+    // Interceptors only get to see the request without its message, like tonic's own
+    let mut parts = tonic::Request::new(());
+    std::mem::swap(parts.metadata_mut(), req.metadata_mut());
+    std::mem::swap(parts.extensions_mut(), req.extensions_mut());
+    interceptor.on_request(call_name, &mut parts)?;
+    std::mem::swap(parts.metadata_mut(), req.metadata_mut());
+    std::mem::swap(parts.extensions_mut(), req.extensions_mut());
+
+    let res = call(req).await;
+    interceptor.on_response(call_name, res.as_ref().map(|r| r.metadata()));
+    res
+}
+
+/// Ask the server for its capabilities. Servers which don't implement `get_system_info` have none.
+pub(crate) async fn fetch_capabilities<T>(
+    opts: Option<&ClientOptions>,
+    client: &WorkflowServiceClient<T>,
+) -> Result<Option<Capabilities>, tonic::Status>
+where
+    T: GrpcService<BoxBody> + Send + Clone + 'static,
+    T::ResponseBody: tonic::codegen::Body + Send + 'static,
+    T::Error: Into<tonic::codegen::StdError>,
+    T::Future: Send,
+    <T::ResponseBody as tonic::codegen::Body>::Error: Into<tonic::codegen::StdError> + Send,
+{
+    let req = tonic::Request::new(GetSystemInfoRequest::default());
+    let res = call_intercepted(opts, "get_system_info", req, |req| {
+        let mut c = client.clone();
+        async move { c.get_system_info(req).await }
+    })
+    .await;
+    match res {
+        Ok(sysinfo) => Ok(sysinfo.into_inner().capabilities),
+        Err(status) if status.code() == Code::Unimplemented => Ok(None),
+        Err(status) => Err(status),
+    }
+}
+
+/// Fetch a page of history with its events left serialized. The generated client can only decode
+/// the response fully, so the call is made on the client's underlying service instead.
+pub(crate) async fn get_serialized_history(
+    client: &ConfiguredClient<WorkflowServiceClientWithMetrics>,
+    request: GetWorkflowExecutionHistoryRequest,
+) -> Result<tonic::Response<SerializedHistoryPage>, tonic::Status> {
+    let opts = Some(client.options.as_ref());
+    client
+        .capabilities
+        .get_or_try_init(|| fetch_capabilities(opts, &client.client))
+        .await?;
+    let mut req = tonic::Request::new(request);
+    let labels = AttachMetricLabels::namespace(req.get_ref().namespace.clone());
+    req.extensions_mut().insert(labels);
+    call_intercepted(opts, "get_workflow_execution_history", req, |req| {
+        let mut grpc = tonic::client::Grpc::new(client.service.clone());
+        async move {
+            grpc.ready()
+                .await
+                .map_err(|e| tonic::Status::unknown(format!("Service was not ready: {}", e)))?;
+            let path = PathAndQuery::from_static(
+                "/temporal.api.workflowservice.v1.WorkflowService/GetWorkflowExecutionHistory",
+            );
+            grpc.unary(req, path, ProstCodec::default()).await
+        }
+    })
+    .await
+}
+
 #[derive(Debug)]
 pub(super) struct AttachMetricLabels {
     pub(super) labels: Vec<opentelemetry::KeyValue>,
@@ -172,14 +334,30 @@ macro_rules! proxy {
         fn $method(
             &mut self,
             request: impl tonic::IntoRequest<super::$req>,
-        ) -> BoxFuture<Result<tonic::Response<super::$resp>, tonic::Status>> {
+        ) -> BoxFuture<'_, Result<tonic::Response<super::$resp>, tonic::Status>> {
+            let opts = self.client_options();
+            let capabilities = self.capabilities_cell();
             #[allow(unused_mut)]
-            let fact = |c: &mut WorkflowServiceClient<Self::SvcType>, mut req: tonic::Request<super::$req>| {
+            let fact = move |c: &mut WorkflowServiceClient<Self::SvcType>, mut req: tonic::Request<super::$req>| {
                 $( type_closure_arg(&mut req, $closure); )*
                 let mut c = c.clone();
-                async move { c.$method(req).await }.boxed()
+                let opts = opts.clone();
+                let capabilities = capabilities.clone();
+                async move {
+                    if let Some(cell) = capabilities.as_ref() {
+                        cell.get_or_try_init(|| fetch_capabilities(opts.as_deref(), &c)).await?;
+                    }
+                    call_intercepted(opts.as_deref(), stringify!($method), req, |req| {
+                        let mut c = c.clone();
+                        async move { c.$method(req).await }
+                    }).await
+                }.boxed()
             };
-            self.do_call(stringify!($method), fact, request.into_request())
+            // Labels are attached to the original request too, for the retry client's metrics
+            #[allow(unused_mut)]
+            let mut request = request.into_request();
+            $( type_closure_arg(&mut request, $closure); )*
+            self.do_call(stringify!($method), fact, request)
         }
     };
 }
@@ -551,8 +729,26 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{ClientOptionsBuilder, RetryClient, WorkflowServiceClientWithMetrics};
-    use temporal_sdk_core_protos::temporal::api::workflowservice::v1::ListNamespacesRequest;
+    use crate::{
+        ApiKey, ClientInterceptor, ClientOptionsBuilder, HeadersProvider, RetryClient,
+        WorkflowClientTrait, WorkflowServiceClientWithMetrics,
+    };
+    use hyper::{
+        header::HeaderValue,
+        service::{make_service_fn, service_fn},
+        Body, HeaderMap, Server,
+    };
+    use prost::Message;
+    use std::{
+        collections::HashMap,
+        convert::Infallible,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+    use temporal_sdk_core_protos::temporal::api::{
+        history::v1::{History, HistoryEvent},
+        workflowservice::v1::{GetWorkflowExecutionHistoryResponse, ListNamespacesRequest},
+    };
+    use url::Url;
 
     // Just to help make sure some stuff compiles. Not run.
     #[allow(dead_code)]
@@ -571,4 +767,230 @@ mod tests {
             .await
             .unwrap();
     }
+
+    fn test_opts() -> ClientOptionsBuilder {
+        let mut opts = ClientOptionsBuilder::default();
+        opts.target_url(Url::parse("http://localhost:7233").unwrap())
+            .client_name("test".to_string())
+            .client_version("0.1.0".to_string())
+            .worker_binary_id("binary".to_string());
+        opts
+    }
+
+    #[derive(Debug, Default)]
+    struct TaggingInterceptor {
+        responses: parking_lot::Mutex<Vec<(String, bool)>>,
+    }
+    impl ClientInterceptor for TaggingInterceptor {
+        fn on_request(
+            &self,
+            call_name: &str,
+            request: &mut tonic::Request<()>,
+        ) -> Result<(), tonic::Status> {
+            if call_name == "forbidden" {
+                return Err(tonic::Status::permission_denied("nope"));
+            }
+            request
+                .metadata_mut()
+                .insert("tag", "tagged".parse().unwrap());
+            Ok(())
+        }
+
+        fn on_response(
+            &self,
+            call_name: &str,
+            response: Result<&tonic::metadata::MetadataMap, &tonic::Status>,
+        ) {
+            self.responses
+                .lock()
+                .push((call_name.to_string(), response.is_ok()));
+        }
+    }
+
+    #[tokio::test]
+    async fn interceptor_can_modify_and_observe_calls() {
+        let interceptor = Arc::new(TaggingInterceptor::default());
+        let opts = test_opts()
+            .interceptor(interceptor.clone())
+            .build()
+            .unwrap();
+        let mut req = tonic::Request::new(5);
+        req.metadata_mut()
+            .insert("existing", "still_here".parse().unwrap());
+        let res = call_intercepted(Some(&opts), "some_call", req, |req| async move {
+            assert_eq!(req.metadata().get("tag").unwrap(), "tagged");
+            assert_eq!(req.metadata().get("existing").unwrap(), "still_here");
+            Ok(tonic::Response::new(*req.get_ref() + 1))
+        })
+        .await
+        .unwrap();
+        assert_eq!(res.into_inner(), 6);
+        assert_eq!(
+            interceptor.responses.lock().as_slice(),
+            &[("some_call".to_string(), true)]
+        );
+    }
+
+    #[tokio::test]
+    async fn interceptor_can_short_circuit_calls() {
+        let interceptor = Arc::new(TaggingInterceptor::default());
+        let opts = test_opts()
+            .interceptor(interceptor.clone())
+            .build()
+            .unwrap();
+        let res = call_intercepted(
+            Some(&opts),
+            "forbidden",
+            tonic::Request::new(()),
+            |_| async { Err::<tonic::Response<()>, _>(tonic::Status::internal("Call was made")) },
+        )
+        .await;
+        assert_eq!(res.unwrap_err().code(), tonic::Code::PermissionDenied);
+        assert!(interceptor.responses.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn provided_headers_are_attached_before_interceptor_runs() {
+        let interceptor = Arc::new(TaggingInterceptor::default());
+        let opts = test_opts()
+            .interceptor(interceptor)
+            .headers_provider(Arc::new(ApiKey::new("secret")))
+            .build()
+            .unwrap();
+        let mut req = tonic::Request::new(());
+        req.metadata_mut()
+            .insert("tag", "overwritten".parse().unwrap());
+        call_intercepted(Some(&opts), "some_call", req, |req| async move {
+            assert_eq!(
+                req.metadata().get("authorization").unwrap(),
+                "Bearer secret"
+            );
+            assert_eq!(req.metadata().get("tag").unwrap(), "tagged");
+            Ok(tonic::Response::new(()))
+        })
+        .await
+        .unwrap();
+    }
+
+    #[derive(Debug, Default)]
+    struct RotatingProvider {
+        rotations: AtomicUsize,
+    }
+    #[async_trait::async_trait]
+    impl HeadersProvider for RotatingProvider {
+        async fn headers(&self) -> Result<HashMap<String, String>, tonic::Status> {
+            let n = self.rotations.load(Ordering::SeqCst);
+            Ok(HashMap::from([("token".to_string(), n.to_string())]))
+        }
+        async fn credentials_rejected(&self) -> bool {
+            self.rotations.fetch_add(1, Ordering::SeqCst);
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn rejected_credentials_are_retried_once() {
+        let provider = Arc::new(RotatingProvider::default());
+        let opts = test_opts()
+            .headers_provider(provider.clone())
+            .build()
+            .unwrap();
+        let calls = AtomicUsize::new(0);
+        let res = call_intercepted(Some(&opts), "some_call", tonic::Request::new(()), |req| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                match req.metadata().get("token").unwrap().to_str().unwrap() {
+                    "1" => Ok(tonic::Response::new(())),
+                    _ => Err(tonic::Status::unauthenticated("bad token")),
+                }
+            }
+        })
+        .await;
+        assert!(res.is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Still rejected after the retry, so the error is returned rather than retrying forever
+        calls.store(0, Ordering::SeqCst);
+        let res = call_intercepted(Some(&opts), "some_call", tonic::Request::new(()), |_| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async { Err::<tonic::Response<()>, _>(tonic::Status::unauthenticated("nope")) }
+        })
+        .await;
+        assert_eq!(res.unwrap_err().code(), tonic::Code::Unauthenticated);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn history_events_are_left_serialized() {
+        let history = History {
+            events: vec![
+                HistoryEvent {
+                    event_id: 1,
+                    ..Default::default()
+                },
+                HistoryEvent {
+                    event_id: 2,
+                    ..Default::default()
+                },
+            ],
+        };
+        let resp = GetWorkflowExecutionHistoryResponse {
+            history: Some(history.clone()),
+            next_page_token: vec![1],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let make_svc = make_service_fn(move |_| {
+            let resp = resp.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: hyper::Request<Body>| {
+                    let (mut sender, body) = Body::channel();
+                    let mut builder =
+                        hyper::Response::builder().header("content-type", "application/grpc");
+                    if req.uri().path().ends_with("/GetWorkflowExecutionHistory") {
+                        let mut framed = vec![0];
+                        framed.extend((resp.len() as u32).to_be_bytes());
+                        framed.extend(&resp);
+                        tokio::spawn(async move {
+                            let _ = sender.send_data(framed.into()).await;
+                            let mut trailers = HeaderMap::new();
+                            trailers.insert("grpc-status", HeaderValue::from_static("0"));
+                            let _ = sender.send_trailers(trailers).await;
+                        });
+                    } else {
+                        builder = builder.header("grpc-status", "12");
+                    }
+                    async move { Ok::<_, Infallible>(builder.body(body).unwrap()) }
+                }))
+            }
+        });
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        listener.set_nonblocking(true).unwrap();
+        tokio::spawn(
+            Server::from_tcp(listener)
+                .unwrap()
+                .http2_only(true)
+                .serve(make_svc),
+        );
+
+        let client = test_opts()
+            .target_url(Url::parse(&format!("http://{}", addr)).unwrap())
+            .build()
+            .unwrap()
+            .connect("ns", None, None)
+            .await
+            .unwrap();
+        let page = client
+            .get_serialized_workflow_execution_history("wf".to_string(), None, vec![])
+            .await
+            .unwrap();
+        assert_eq!(page.next_page_token, vec![1]);
+        let events = page.history.unwrap().events;
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            HistoryEvent::decode(events[1].as_slice()).unwrap(),
+            history.events[1]
+        );
+    }
 }
@@ -0,0 +1,357 @@
+//! Support for resolving server endpoints to addresses, and for spreading calls across those
+//! addresses. See [crate::ClientOptions::resolver] and [crate::ClientOptions::load_balancing].
+
+use crate::{ClientInitError, ClientOptions, TlsConfig};
+use std::{collections::HashSet, fmt::Debug, io, net::SocketAddr, sync::Arc};
+use tonic::transport::{Channel, Endpoint};
+use tower::discover::Change;
+use url::Url;
+
+/// Resolves the host of a server endpoint to the addresses the client may connect to. Useful
+/// when the system resolver should be bypassed, or when a name resolves to many servers (ex: a
+/// headless service in front of several frontend pods) and calls should be spread across them.
+#[async_trait::async_trait]
+pub trait EndpointResolver: Send + Sync + Debug {
+    /// Return the addresses at which the server with the provided host name and port can be
+    /// reached, in order of preference
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>>;
+}
+
+/// Resolves endpoints using the system's DNS resolver
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DnsResolver;
+
+#[async_trait::async_trait]
+impl EndpointResolver for DnsResolver {
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        Ok(tokio::net::lookup_host((host, port)).await?.collect())
+    }
+}
+
+/// How the client uses the addresses a server endpoint resolves to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoadBalancingPolicy {
+    /// Connect to the first address which accepts a connection, and make all calls on it
+    #[default]
+    PickFirst,
+    /// Connect to every address and spread calls across all of them. Each call goes to whichever
+    /// of two randomly chosen connections has fewer calls in flight. The endpoint is re-resolved
+    /// every [ClientOptions::re_resolve_interval], connecting to new addresses and dropping ones
+    /// which are gone.
+    PowerOfTwoChoices,
+}
+
+impl ClientOptions {
+    /// Make a channel to the server at the provided URL. If `lazy` is set, no connection is
+    /// attempted until the channel is first used, but addresses are still resolved up front when
+    /// using a custom resolver or balancing.
+    pub(crate) async fn make_channel(
+        &self,
+        url: &Url,
+        tls_cfg: Option<&TlsConfig>,
+        lazy: bool,
+    ) -> Result<Channel, ClientInitError> {
+        if self.load_balancing == LoadBalancingPolicy::PowerOfTwoChoices {
+            return self.make_balanced_channel(url, tls_cfg).await;
+        }
+        let endpoints = match self.resolver.as_ref() {
+            None => vec![self.endpoint(url, tls_cfg).await?],
+            Some(resolver) => {
+                let target = ResolutionTarget::new(url, tls_cfg, resolver.as_ref())?;
+                let mut endpoints = vec![];
+                for addr in target.resolve().await? {
+                    endpoints.push(target.endpoint(self, addr).await?);
+                }
+                endpoints
+            }
+        };
+        let last = endpoints.len() - 1;
+        for (i, endpoint) in endpoints.into_iter().enumerate() {
+            if lazy {
+                return Ok(endpoint.connect_lazy());
+            }
+            match endpoint.connect().await {
+                Ok(channel) => return Ok(channel),
+                Err(e) if i == last => return Err(e.into()),
+                Err(e) => {
+                    warn!(error=?e, endpoint=%endpoint.uri(), "Failed to connect to server address")
+                }
+            }
+        }
+        unreachable!("Resolution always produces at least one endpoint")
+    }
+
+    /// Make a channel balanced across every address the URL resolves to. A background task keeps
+    /// the set of addresses up to date until the channel is dropped.
+    async fn make_balanced_channel(
+        &self,
+        url: &Url,
+        tls_cfg: Option<&TlsConfig>,
+    ) -> Result<Channel, ClientInitError> {
+        let resolver = self
+            .resolver
+            .clone()
+            .unwrap_or_else(|| Arc::new(DnsResolver));
+        let target = ResolutionTarget::new(url, tls_cfg, resolver.as_ref())?;
+        let addrs = target.resolve().await?;
+        let (channel, changes) = Channel::balance_channel(addrs.len().max(16));
+        let mut current = HashSet::new();
+        for addr in addrs {
+            let endpoint = target.endpoint(self, addr).await?;
+            changes
+                .send(Change::Insert(addr, endpoint))
+                .await
+                .expect("Channel was just created");
+            current.insert(addr);
+        }
+
+        let opts = self.clone();
+        let (url, tls_cfg) = (url.clone(), tls_cfg.cloned());
+        let interval = self.re_resolve_interval;
+        tokio::spawn(async move {
+            let target = ResolutionTarget::new(&url, tls_cfg.as_ref(), resolver.as_ref())
+                .expect("Target was already valid");
+            loop {
+                tokio::time::sleep(interval).await;
+                if changes.is_closed() {
+                    return;
+                }
+                let addrs: HashSet<_> = match target.resolve().await {
+                    Ok(addrs) => addrs.into_iter().collect(),
+                    // Keep using the addresses we have until resolution works again
+                    Err(e) => {
+                        warn!(error=?e, "Failed to re-resolve server addresses");
+                        continue;
+                    }
+                };
+                for &gone in current.difference(&addrs) {
+                    if changes.send(Change::Remove(gone)).await.is_err() {
+                        return;
+                    }
+                }
+                for &new in addrs.difference(&current) {
+                    let endpoint = match target.endpoint(&opts, new).await {
+                        Ok(e) => e,
+                        Err(e) => {
+                            warn!(error=?e, "Failed to make endpoint for server address");
+                            continue;
+                        }
+                    };
+                    if changes.send(Change::Insert(new, endpoint)).await.is_err() {
+                        return;
+                    }
+                }
+                current = addrs;
+            }
+        });
+        Ok(channel)
+    }
+}
+
+/// A server URL whose host is resolved to addresses with an [EndpointResolver]
+struct ResolutionTarget<'a> {
+    url: &'a Url,
+    host: &'a str,
+    port: u16,
+    tls_cfg: Option<TlsConfig>,
+    resolver: &'a dyn EndpointResolver,
+}
+
+impl<'a> ResolutionTarget<'a> {
+    fn new(
+        url: &'a Url,
+        tls_cfg: Option<&TlsConfig>,
+        resolver: &'a dyn EndpointResolver,
+    ) -> Result<Self, ClientInitError> {
+        let (host, port) = url
+            .host_str()
+            .zip(url.port_or_known_default())
+            .ok_or_else(|| {
+                resolution_err(
+                    url,
+                    io::Error::new(io::ErrorKind::InvalidInput, "URL has no host or port"),
+                )
+            })?;
+        // The server's certificate must still be verified against the name we resolved, rather
+        // than the address we connect to
+        let tls_cfg = tls_cfg.cloned().map(|mut tls| {
+            tls.domain.get_or_insert_with(|| host.to_string());
+            tls
+        });
+        Ok(Self {
+            url,
+            host,
+            port,
+            tls_cfg,
+            resolver,
+        })
+    }
+
+    async fn resolve(&self) -> Result<Vec<SocketAddr>, ClientInitError> {
+        let addrs = self
+            .resolver
+            .resolve(self.host, self.port)
+            .await
+            .map_err(|e| resolution_err(self.url, e))?;
+        if addrs.is_empty() {
+            return Err(resolution_err(
+                self.url,
+                io::Error::new(io::ErrorKind::NotFound, "No addresses resolved"),
+            ));
+        }
+        Ok(addrs)
+    }
+
+    async fn endpoint(
+        &self,
+        opts: &ClientOptions,
+        addr: SocketAddr,
+    ) -> Result<Endpoint, ClientInitError> {
+        let mut addr_url = self.url.clone();
+        addr_url
+            .set_ip_host(addr.ip())
+            .and_then(|_| addr_url.set_port(Some(addr.port())))
+            .expect("URLs with a host can have their host and port set");
+        opts.endpoint(&addr_url, self.tls_cfg.as_ref()).await
+    }
+}
+
+fn resolution_err(url: &Url, source: io::Error) -> ClientInitError {
+    ClientInitError::ResolutionError {
+        url: url.to_string(),
+        source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{failover::tests::fake_server, ClientOptionsBuilder, RetryClient, WorkflowService};
+    use parking_lot::Mutex;
+    use std::{net::TcpListener, sync::atomic::Ordering, time::Duration};
+    use temporal_sdk_core_protos::temporal::api::workflowservice::v1::ListNamespacesRequest;
+
+    #[derive(Debug)]
+    struct FixedResolver(Vec<SocketAddr>);
+
+    #[async_trait::async_trait]
+    impl EndpointResolver for FixedResolver {
+        async fn resolve(&self, host: &str, _: u16) -> io::Result<Vec<SocketAddr>> {
+            assert_eq!(host, "temporal.fake");
+            Ok(self.0.clone())
+        }
+    }
+
+    /// Resolves to whatever addresses it currently holds
+    #[derive(Debug)]
+    struct ChangingResolver(Arc<Mutex<Vec<SocketAddr>>>);
+
+    #[async_trait::async_trait]
+    impl EndpointResolver for ChangingResolver {
+        async fn resolve(&self, _: &str, _: u16) -> io::Result<Vec<SocketAddr>> {
+            Ok(self.0.lock().clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn connects_to_resolved_addresses() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let calls = fake_server(listener, Default::default());
+        // Nothing listens at the first address, so the client must fall back to the second
+        let dead_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let dead_addr = dead_listener.local_addr().unwrap();
+        drop(dead_listener);
+
+        let opts = ClientOptionsBuilder::default()
+            .target_url(Url::parse("http://temporal.fake:7233").unwrap())
+            .client_name("test".to_string())
+            .client_version("0.1.0".to_string())
+            .worker_binary_id("test".to_string())
+            .resolver(Arc::new(FixedResolver(vec![dead_addr, addr])))
+            .build()
+            .unwrap();
+        let client = opts.connect_no_namespace(None, None).await.unwrap();
+        let mut client = RetryClient::new(client.into_inner(), Default::default());
+
+        let _ = client
+            .list_namespaces(ListNamespacesRequest::default())
+            .await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn balancing_uses_every_address() {
+        let mut addrs = vec![];
+        let mut calls = vec![];
+        for _ in 0..2 {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            addrs.push(listener.local_addr().unwrap());
+            calls.push(fake_server(listener, Default::default()));
+        }
+
+        let opts = ClientOptionsBuilder::default()
+            .target_url(Url::parse("http://temporal.fake:7233").unwrap())
+            .client_name("test".to_string())
+            .client_version("0.1.0".to_string())
+            .worker_binary_id("test".to_string())
+            .resolver(Arc::new(FixedResolver(addrs)))
+            .load_balancing(LoadBalancingPolicy::PowerOfTwoChoices)
+            .build()
+            .unwrap();
+        let client = opts.connect_no_namespace(None, None).await.unwrap();
+        let mut client = RetryClient::new(client.into_inner(), Default::default());
+
+        // Which connection each call goes to is random, but with this many calls every server is
+        // all but certain to see some of them
+        for _ in 0..50 {
+            let _ = client
+                .list_namespaces(ListNamespacesRequest::default())
+                .await;
+        }
+        let calls: Vec<_> = calls.iter().map(|c| c.load(Ordering::SeqCst)).collect();
+        assert_eq!(calls.iter().sum::<usize>(), 50);
+        assert!(calls.iter().all(|&c| c > 0), "calls were {:?}", calls);
+    }
+
+    #[tokio::test]
+    async fn balancing_follows_re_resolved_addresses() {
+        let mut addrs = vec![];
+        let mut calls = vec![];
+        for _ in 0..2 {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            addrs.push(listener.local_addr().unwrap());
+            calls.push(fake_server(listener, Default::default()));
+        }
+        let resolved = Arc::new(Mutex::new(vec![addrs[0]]));
+
+        let opts = ClientOptionsBuilder::default()
+            .target_url(Url::parse("http://temporal.fake:7233").unwrap())
+            .client_name("test".to_string())
+            .client_version("0.1.0".to_string())
+            .worker_binary_id("test".to_string())
+            .resolver(Arc::new(ChangingResolver(resolved.clone())))
+            .load_balancing(LoadBalancingPolicy::PowerOfTwoChoices)
+            .re_resolve_interval(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        let client = opts.connect_no_namespace(None, None).await.unwrap();
+        let mut client = RetryClient::new(client.into_inner(), Default::default());
+        let _ = client
+            .list_namespaces(ListNamespacesRequest::default())
+            .await;
+        assert_eq!(calls[0].load(Ordering::SeqCst), 1);
+
+        // Once the first server is no longer resolved, calls must move to the second one
+        *resolved.lock() = vec![addrs[1]];
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let before = calls[0].load(Ordering::SeqCst);
+        for _ in 0..10 {
+            let _ = client
+                .list_namespaces(ListNamespacesRequest::default())
+                .await;
+        }
+        assert_eq!(calls[0].load(Ordering::SeqCst), before);
+        assert_eq!(calls[1].load(Ordering::SeqCst), 10);
+    }
+}
@@ -1,19 +1,33 @@
 use crate::{
-    ClientOptions, RawClientLikeUser, Result, RetryConfig, WorkflowClientTrait, WorkflowOptions,
-    WorkflowTaskCompletion,
+    metrics::{namespace_kv, MetricsContext, RpcAttemptRecorder},
+    CapabilitiesCell, Client, ClientOptions, ClientRateLimitConfig, PollCircuitBreakerConfig,
+    RawClientLikeUser, RegisterNamespaceOptions, Result, RetryConfig, SignalWithStartOptions,
+    UpdateNamespaceOptions, WorkflowClientTrait, WorkflowOptions, WorkflowTaskCompletion,
 };
 use backoff::{backoff::Backoff, ExponentialBackoff};
 use futures_retry::{ErrorHandler, FutureRetry, RetryPolicy};
-use std::{fmt::Debug, future::Future, time::Duration};
+use opentelemetry::KeyValue;
+use parking_lot::Mutex;
+use std::{
+    fmt::Debug,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use temporal_sdk_core_protos::{
     coresdk::{common::Payload, workflow_commands::QueryResult},
     temporal::api::{
-        common::v1::Payloads, enums::v1::WorkflowTaskFailedCause, failure::v1::Failure,
-        query::v1::WorkflowQuery, workflowservice::v1::*,
+        common::v1::Payloads,
+        enums::v1::{ResetReapplyType, TaskQueueType, WorkflowTaskFailedCause},
+        failure::v1::Failure,
+        query::v1::WorkflowQuery,
+        workflowservice::v1::*,
     },
-    TaskToken,
+    SearchAttributeRegistry, SerializedHistoryPage, TaskToken,
 };
+use tokio::sync::broadcast;
 use tonic::Code;
+use uuid::Uuid;
 
 /// List of gRPC error codes that client will retry.
 pub const RETRYABLE_ERROR_CODES: [Code; 7] = [
@@ -32,6 +46,16 @@ pub const RETRYABLE_ERROR_CODES: [Code; 7] = [
 pub struct RetryClient<SG> {
     client: SG,
     retry_config: RetryConfig,
+    poll_retry_config: RetryConfig,
+    completion_retry_config: Option<RetryConfig>,
+    /// Shared by all clones of this client, so that polls from every poller count towards it
+    poll_circuit_breaker: Option<Arc<PollCircuitBreaker>>,
+    /// Shared by all clones of this client, so that the limit applies to the client as a whole
+    rate_limiter: Option<Arc<RateLimiter>>,
+    /// Capabilities of the server the client is connected to, which change what is retried
+    capabilities: Option<CapabilitiesCell>,
+    /// If set, every attempt of every call is recorded
+    metrics: Option<MetricsContext>,
 }
 
 impl<SG> RetryClient<SG> {
@@ -40,26 +64,80 @@ impl<SG> RetryClient<SG> {
         Self {
             client,
             retry_config,
+            poll_retry_config: RetryConfig::poll_retry_policy(),
+            completion_retry_config: None,
+            poll_circuit_breaker: None,
+            rate_limiter: None,
+            capabilities: None,
+            metrics: None,
         }
     }
-}
 
-impl<SG> RetryClient<SG> {
-    /// Return the inner client type
-    pub fn get_client(&self) -> &SG {
-        &self.client
+    /// Use the provided retry config for long polls, rather than
+    /// [RetryConfig::poll_retry_policy]
+    pub fn with_poll_retry_config(mut self, poll_retry_config: RetryConfig) -> Self {
+        self.poll_retry_config = poll_retry_config;
+        self
     }
 
-    /// Return the inner client type mutably
-    pub fn get_client_mut(&mut self) -> &mut SG {
-        &mut self.client
+    /// Use the provided retry config for calls which complete (or fail) tasks, rather than the
+    /// config used for all other calls
+    pub fn with_completion_retry_config(mut self, completion_retry_config: RetryConfig) -> Self {
+        self.completion_retry_config = Some(completion_retry_config);
+        self
     }
 
-    /// Disable retry and return the inner client type
-    pub fn into_inner(self) -> SG {
-        self.client
+    /// Pause long polling when polls persistently fail, as configured by the provided config.
+    pub(crate) fn with_poll_circuit_breaker(
+        mut self,
+        cfg: PollCircuitBreakerConfig,
+        metrics: Option<MetricsContext>,
+    ) -> Self {
+        self.poll_circuit_breaker = Some(Arc::new(PollCircuitBreaker::new(cfg, metrics)));
+        self
+    }
+
+    /// Limit the rate at which attempts of calls other than long polls are made, as configured by
+    /// the provided config
+    ///
+    /// # Panics
+    /// If the rate isn't greater than zero or the burst is zero
+    pub fn with_rate_limit(mut self, cfg: ClientRateLimitConfig) -> Self {
+        if let Err(e) = cfg.validate() {
+            panic!("{}", e);
+        }
+        self.rate_limiter = Some(Arc::new(RateLimiter::new(cfg)));
+        self
     }
 
+    /// Take the server's capabilities into account when deciding what to retry
+    pub(crate) fn with_capabilities(mut self, capabilities: CapabilitiesCell) -> Self {
+        self.capabilities = Some(capabilities);
+        self
+    }
+
+    /// Record the latency and outcome of every attempt of every call
+    pub(crate) fn with_metrics(mut self, metrics: MetricsContext) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+}
+
+impl RetryClient<Client> {
+    /// Returns a client bound to `namespace` which shares this client's connection. See
+    /// [Client::with_namespace]. Calls are retried the same way, and the rate limiter and poll
+    /// circuit breaker are shared with this client, since they protect the connection as a whole.
+    pub fn with_namespace(&self, namespace: impl Into<String>) -> Self {
+        let mut client = self.clone();
+        client.client = self.client.with_namespace(namespace);
+        client
+    }
+}
+
+impl<SG> RetryClient<SG>
+where
+    SG: WorkflowClientTrait,
+{
     /// Wraps a call to the underlying client with retry capability.
     ///
     /// This is the "old" path used by higher-level [WorkflowClientTrait] implementors
@@ -72,57 +150,212 @@ impl<SG> RetryClient<SG> {
         F: Fn() -> Fut + Unpin,
         Fut: Future<Output = Result<R>>,
     {
-        let rtc = self.get_retry_config(call_name);
-        let res = Self::make_future_retry(rtc, factory, call_name).await;
-        Ok(res.map_err(|(e, _attempt)| e)?.0)
+        let mut retrier = self.retrier_for(call_name);
+        if self.metrics.is_some() {
+            retrier =
+                retrier.with_metric_labels([namespace_kv(self.client.namespace().to_owned())]);
+        }
+        retrier.call(factory).await
+    }
+}
+
+impl<SG> RetryClient<SG> {
+    /// Return the inner client type
+    pub fn get_client(&self) -> &SG {
+        &self.client
+    }
+
+    /// Return the inner client type mutably
+    pub fn get_client_mut(&mut self) -> &mut SG {
+        &mut self.client
+    }
+
+    /// Disable retry and return the inner client type
+    pub fn into_inner(self) -> SG {
+        self.client
+    }
+
+    /// Returns what retries the call, and applies the rate limiter, circuit breaker, and metrics
+    /// to each of its attempts. Every call made through this client goes through it.
+    pub(crate) fn retrier_for(&self, call_name: &'static str) -> CallRetrier {
+        CallRetrier {
+            call_name,
+            call_type: Self::determine_call_type(call_name),
+            retry_config: self.get_retry_config(call_name),
+            circuit_breaker: self.get_circuit_breaker(call_name),
+            rate_limiter: self.get_rate_limiter(call_name),
+            recorder: self.get_attempt_recorder(call_name),
+            retry_internal: self.retries_internal_errors(),
+        }
     }
 
     pub(crate) fn get_retry_config(&self, call_name: &'static str) -> RetryConfig {
         let call_type = Self::determine_call_type(call_name);
         match call_type {
             CallType::Normal => self.retry_config.clone(),
-            CallType::LongPoll => RetryConfig::poll_retry_policy(),
+            CallType::LongPoll => self.poll_retry_config.clone(),
+            CallType::TaskCompletion => self
+                .completion_retry_config
+                .clone()
+                .unwrap_or_else(|| self.retry_config.clone()),
         }
     }
 
-    pub(crate) fn make_future_retry<R, F, Fut>(
-        rtc: RetryConfig,
-        factory: F,
+    /// Returns the circuit breaker which applies to the call, if there is one
+    pub(crate) fn get_circuit_breaker(
+        &self,
         call_name: &'static str,
-    ) -> FutureRetry<F, TonicErrorHandler>
-    where
-        F: FnMut() -> Fut + Unpin,
-        Fut: Future<Output = Result<R>>,
-    {
-        let call_type = Self::determine_call_type(call_name);
-        FutureRetry::new(factory, TonicErrorHandler::new(rtc, call_type, call_name))
+    ) -> Option<Arc<PollCircuitBreaker>> {
+        match Self::determine_call_type(call_name) {
+            CallType::LongPoll => self.poll_circuit_breaker.clone(),
+            _ => None,
+        }
+    }
+
+    /// Servers which differentiate internal errors from other kinds of errors only return them
+    /// when retrying won't help. Older servers, or those whose capabilities are unknown, may
+    /// return them for transient problems.
+    pub(crate) fn retries_internal_errors(&self) -> bool {
+        let capabilities = self.capabilities.as_ref().and_then(|c| c.get());
+        !matches!(capabilities, Some(Some(c)) if c.internal_error_differentiation)
+    }
+
+    /// Returns something to record each attempt of the call with, if metrics are enabled
+    pub(crate) fn get_attempt_recorder(
+        &self,
+        call_name: &'static str,
+    ) -> Option<RpcAttemptRecorder> {
+        self.metrics
+            .as_ref()
+            .map(|m| RpcAttemptRecorder::new(m, call_name))
+    }
+
+    /// Returns the rate limiter which applies to the call, if there is one
+    pub(crate) fn get_rate_limiter(&self, call_name: &'static str) -> Option<Arc<RateLimiter>> {
+        match Self::determine_call_type(call_name) {
+            CallType::LongPoll => None,
+            _ => self.rate_limiter.clone(),
+        }
     }
 
     fn determine_call_type(call_name: &str) -> CallType {
         match call_name {
-            "poll_workflow_task" | "poll_activity_task" => CallType::LongPoll,
+            "poll_workflow_task"
+            | "poll_activity_task"
+            | "poll_workflow_task_queue"
+            | "poll_activity_task_queue" => CallType::LongPoll,
+            "complete_workflow_task"
+            | "fail_workflow_task"
+            | "complete_activity_task"
+            | "fail_activity_task"
+            | "cancel_activity_task"
+            | "respond_legacy_query"
+            | "respond_workflow_task_completed"
+            | "respond_workflow_task_failed"
+            | "respond_activity_task_completed"
+            | "respond_activity_task_completed_by_id"
+            | "respond_activity_task_failed"
+            | "respond_activity_task_failed_by_id"
+            | "respond_activity_task_canceled"
+            | "respond_activity_task_canceled_by_id"
+            | "respond_query_task_completed" => CallType::TaskCompletion,
             _ => CallType::Normal,
         }
     }
 }
 
+/// Everything which applies to the attempts of one call, see [RetryClient::retrier_for]
+pub(crate) struct CallRetrier {
+    call_name: &'static str,
+    call_type: CallType,
+    retry_config: RetryConfig,
+    circuit_breaker: Option<Arc<PollCircuitBreaker>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    recorder: Option<RpcAttemptRecorder>,
+    retry_internal: bool,
+}
+
+impl CallRetrier {
+    /// Tag the metrics recorded about the call's attempts with the provided attributes too
+    pub(crate) fn with_metric_labels(mut self, labels: impl IntoIterator<Item = KeyValue>) -> Self {
+        self.recorder = self.recorder.map(|r| r.with_new_attrs(labels));
+        self
+    }
+
+    /// Makes attempts created by `factory` until one succeeds or the error can't be retried
+    pub(crate) async fn call<R, F, Fut>(self, mut factory: F) -> Result<R>
+    where
+        F: FnMut() -> Fut + Unpin,
+        Fut: Future<Output = Result<R>>,
+    {
+        if let Some(b) = self.circuit_breaker.as_ref() {
+            b.wait_until_closed().await;
+        }
+        let recorder = self.recorder.as_ref();
+        let rate_limiter = self.rate_limiter.as_ref();
+        let attempt_factory = || {
+            let fut = factory();
+            async move {
+                if let Some(l) = rate_limiter {
+                    l.acquire().await;
+                }
+                let attempt = recorder.map(RpcAttemptRecorder::start_attempt);
+                let res = fut.await;
+                if let Some(a) = attempt {
+                    a.finished(&res);
+                }
+                res
+            }
+        };
+        let res = FutureRetry::new(
+            attempt_factory,
+            TonicErrorHandler::new(
+                self.retry_config,
+                self.call_type,
+                self.call_name,
+                self.circuit_breaker.clone(),
+                self.retry_internal,
+            ),
+        )
+        .await;
+        if let (Some(b), Ok(_)) = (self.circuit_breaker.as_ref(), res.as_ref()) {
+            b.record_success();
+        }
+        res.map(|(r, _attempt)| r).map_err(|(e, _attempt)| e)
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct TonicErrorHandler {
     backoff: ExponentialBackoff,
     max_retries: usize,
     call_type: CallType,
     call_name: &'static str,
+    circuit_breaker: Option<Arc<PollCircuitBreaker>>,
+    retry_internal: bool,
 }
 impl TonicErrorHandler {
-    fn new(cfg: RetryConfig, call_type: CallType, call_name: &'static str) -> Self {
+    fn new(
+        cfg: RetryConfig,
+        call_type: CallType,
+        call_name: &'static str,
+        circuit_breaker: Option<Arc<PollCircuitBreaker>>,
+        retry_internal: bool,
+    ) -> Self {
         Self {
             max_retries: cfg.max_retries,
             backoff: cfg.into(),
             call_type,
             call_name,
+            circuit_breaker,
+            retry_internal,
         }
     }
 
+    fn is_retryable(&self, code: Code) -> bool {
+        RETRYABLE_ERROR_CODES.contains(&code) && (self.retry_internal || code != Code::Internal)
+    }
+
     const fn should_log_retry_warning(&self, cur_attempt: usize) -> bool {
         // Warn on more than 5 retries for unlimited retrying
         if self.max_retries == 0 && cur_attempt > 5 {
@@ -140,12 +373,21 @@ impl TonicErrorHandler {
 pub enum CallType {
     Normal,
     LongPoll,
+    TaskCompletion,
 }
 
 impl ErrorHandler<tonic::Status> for TonicErrorHandler {
     type OutError = tonic::Status;
 
     fn handle(&mut self, current_attempt: usize, e: tonic::Status) -> RetryPolicy<tonic::Status> {
+        let circuit_open_for = if self.is_retryable(e.code()) {
+            self.circuit_breaker
+                .as_ref()
+                .and_then(|b| b.record_failure())
+        } else {
+            None
+        };
+
         // 0 max retries means unlimited retries
         if self.max_retries > 0 && current_attempt >= self.max_retries {
             return RetryPolicy::ForwardError(e);
@@ -156,7 +398,7 @@ impl ErrorHandler<tonic::Status> for TonicErrorHandler {
         let long_poll_allowed = self.call_type == CallType::LongPoll
             && [Code::Cancelled, Code::DeadlineExceeded].contains(&e.code());
 
-        if RETRYABLE_ERROR_CODES.contains(&e.code()) || long_poll_allowed {
+        if self.is_retryable(e.code()) || long_poll_allowed {
             if current_attempt == 1 {
                 debug!(error=?e, "gRPC call {} failed on first attempt", self.call_name);
             } else if self.should_log_retry_warning(current_attempt) {
@@ -166,13 +408,16 @@ impl ErrorHandler<tonic::Status> for TonicErrorHandler {
             match self.backoff.next_backoff() {
                 None => RetryPolicy::ForwardError(e), // None is returned when we've ran out of time
                 Some(backoff) => {
-                    if cfg!(test) {
+                    let backoff = if cfg!(test) {
                         // Allow unit tests to do lots of retries quickly. This does *not* apply
                         // during integration testing, importantly.
-                        RetryPolicy::WaitRetry(Duration::from_millis(1))
+                        Duration::from_millis(1)
                     } else {
-                        RetryPolicy::WaitRetry(backoff)
-                    }
+                        backoff
+                    };
+                    // Attempts made while the circuit is open still count against the retry
+                    // limits, they just wait out the pause first
+                    RetryPolicy::WaitRetry(circuit_open_for.map_or(backoff, |p| p.max(backoff)))
                 }
             }
         } else {
@@ -181,6 +426,156 @@ impl ErrorHandler<tonic::Status> for TonicErrorHandler {
     }
 }
 
+/// How many unreceived poll pause notifications are kept for each subscriber
+const POLL_PAUSE_EVENTS_BUFFER: usize = 16;
+
+/// Counts consecutive long poll attempts which failed with server errors. Once there have been too
+/// many, the circuit opens and all polling through the client is paused for a while, rather than
+/// every poller continuing to retry against a server which is overloaded or unavailable.
+#[derive(Debug)]
+pub(crate) struct PollCircuitBreaker {
+    cfg: PollCircuitBreakerConfig,
+    state: Mutex<CircuitState>,
+    metrics: Option<MetricsContext>,
+    /// Sent how long polling is paused for every time the circuit opens
+    opened: broadcast::Sender<Duration>,
+}
+
+#[derive(Debug, Default)]
+struct CircuitState {
+    consecutive_failures: usize,
+    open_until: Option<Instant>,
+}
+
+impl PollCircuitBreaker {
+    fn new(cfg: PollCircuitBreakerConfig, metrics: Option<MetricsContext>) -> Self {
+        Self {
+            cfg,
+            state: Mutex::new(CircuitState::default()),
+            metrics,
+            opened: broadcast::channel(POLL_PAUSE_EVENTS_BUFFER).0,
+        }
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<Duration> {
+        self.opened.subscribe()
+    }
+
+    /// Returns how much longer polling should be paused for, if the circuit is open
+    fn open_for(&self) -> Option<Duration> {
+        self.state
+            .lock()
+            .open_until
+            .and_then(|until| until.checked_duration_since(Instant::now()))
+    }
+
+    pub(crate) async fn wait_until_closed(&self) {
+        while let Some(pause) = self.open_for() {
+            tokio::time::sleep(pause).await;
+        }
+    }
+
+    /// Record a failed attempt, returning how long polling should be paused for if the circuit is
+    /// (now) open
+    fn record_failure(&self) -> Option<Duration> {
+        let mut state = self.state.lock();
+        state.consecutive_failures += 1;
+        let now = Instant::now();
+        if let Some(remaining) = state
+            .open_until
+            .and_then(|until| until.checked_duration_since(now))
+        {
+            return Some(remaining);
+        }
+        if state.consecutive_failures < self.cfg.failure_threshold {
+            return None;
+        }
+        warn!(
+            consecutive_failures = state.consecutive_failures,
+            "Long polls are persistently failing, pausing polling for {:?}", self.cfg.open_duration
+        );
+        state.open_until = Some(now + self.cfg.open_duration);
+        if let Some(m) = self.metrics.as_ref() {
+            m.poll_circuit_breaker_opened();
+        }
+        let _ = self.opened.send(self.cfg.open_duration);
+        Some(self.cfg.open_duration)
+    }
+
+    pub(crate) fn record_success(&self) {
+        let mut state = self.state.lock();
+        state.consecutive_failures = 0;
+        state.open_until = None;
+    }
+}
+
+/// Token bucket which limits how often calls may be made. Callers reserve a token and then wait
+/// until it would have been available, so they're let through in the order they arrived.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    cfg: ClientRateLimitConfig,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    /// May go negative, in which case that many tokens have already been reserved by waiters
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    fn new(cfg: ClientRateLimitConfig) -> Self {
+        Self {
+            state: Mutex::new(BucketState {
+                tokens: cfg.burst as f64,
+                last_refill: Instant::now(),
+            }),
+            cfg,
+        }
+    }
+
+    /// Returns how long the caller must wait before it may make its call
+    fn reserve(&self) -> Duration {
+        let mut state = self.state.lock();
+        let now = Instant::now();
+        let refilled =
+            now.duration_since(state.last_refill).as_secs_f64() * self.cfg.max_requests_per_second;
+        state.tokens = (state.tokens + refilled).min(self.cfg.burst as f64);
+        state.last_refill = now;
+        state.tokens -= 1.0;
+        if state.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-state.tokens / self.cfg.max_requests_per_second)
+        }
+    }
+
+    /// Give back a reserved token whose caller stopped waiting for it
+    fn release(&self) {
+        let mut state = self.state.lock();
+        state.tokens = (state.tokens + 1.0).min(self.cfg.burst as f64);
+    }
+
+    pub(crate) async fn acquire(&self) {
+        let wait = self.reserve();
+        if !wait.is_zero() {
+            let reservation = Reservation(self);
+            tokio::time::sleep(wait).await;
+            std::mem::forget(reservation);
+        }
+    }
+}
+
+/// Returns its token to the limiter if dropped, which happens when the waiting call is cancelled
+struct Reservation<'a>(&'a RateLimiter);
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
 macro_rules! retry_call {
     ($myself:ident, $call_name:ident) => { retry_call!($myself, $call_name,) };
     ($myself:ident, $call_name:ident, $($args:expr),*) => {{
@@ -343,6 +738,17 @@ where
         )
     }
 
+    async fn signal_with_start_workflow_execution(
+        &self,
+        mut options: SignalWithStartOptions,
+    ) -> Result<SignalWithStartWorkflowExecutionResponse> {
+        // Every attempt must use the same request id so the server can de-duplicate them
+        options
+            .request_id
+            .get_or_insert_with(|| Uuid::new_v4().to_string());
+        retry_call!(self, signal_with_start_workflow_execution, options.clone())
+    }
+
     async fn query_workflow_execution(
         &self,
         workflow_id: String,
@@ -386,6 +792,21 @@ where
         )
     }
 
+    async fn get_serialized_workflow_execution_history(
+        &self,
+        workflow_id: String,
+        run_id: Option<String>,
+        page_token: Vec<u8>,
+    ) -> Result<SerializedHistoryPage> {
+        retry_call!(
+            self,
+            get_serialized_workflow_execution_history,
+            workflow_id.clone(),
+            run_id.clone(),
+            page_token.clone()
+        )
+    }
+
     async fn respond_legacy_query(
         &self,
         task_token: TaskToken,
@@ -425,10 +846,95 @@ where
         )
     }
 
+    async fn reset_workflow_execution(
+        &self,
+        workflow_id: String,
+        run_id: Option<String>,
+        workflow_task_finish_event_id: i64,
+        reason: String,
+        reset_reapply_type: ResetReapplyType,
+        request_id: String,
+    ) -> Result<ResetWorkflowExecutionResponse> {
+        retry_call!(
+            self,
+            reset_workflow_execution,
+            workflow_id.clone(),
+            run_id.clone(),
+            workflow_task_finish_event_id,
+            reason.clone(),
+            reset_reapply_type,
+            request_id.clone()
+        )
+    }
+
     async fn list_namespaces(&self) -> Result<ListNamespacesResponse> {
         retry_call!(self, list_namespaces,)
     }
 
+    async fn register_namespace(
+        &self,
+        options: RegisterNamespaceOptions,
+    ) -> Result<RegisterNamespaceResponse> {
+        retry_call!(self, register_namespace, options.clone())
+    }
+
+    async fn describe_namespace(&self, namespace: String) -> Result<DescribeNamespaceResponse> {
+        retry_call!(self, describe_namespace, namespace.clone())
+    }
+
+    async fn update_namespace(
+        &self,
+        namespace: String,
+        options: UpdateNamespaceOptions,
+    ) -> Result<UpdateNamespaceResponse> {
+        retry_call!(self, update_namespace, namespace.clone(), options.clone())
+    }
+
+    async fn deprecate_namespace(&self, namespace: String) -> Result<DeprecateNamespaceResponse> {
+        retry_call!(self, deprecate_namespace, namespace.clone())
+    }
+
+    async fn get_search_attributes(&self) -> Result<SearchAttributeRegistry> {
+        retry_call!(self, get_search_attributes,)
+    }
+
+    async fn list_workflow_executions(
+        &self,
+        page_size: i32,
+        next_page_token: Vec<u8>,
+        query: String,
+    ) -> Result<ListWorkflowExecutionsResponse> {
+        retry_call!(
+            self,
+            list_workflow_executions,
+            page_size,
+            next_page_token.clone(),
+            query.clone()
+        )
+    }
+
+    async fn count_workflow_executions(
+        &self,
+        query: String,
+    ) -> Result<CountWorkflowExecutionsResponse> {
+        retry_call!(self, count_workflow_executions, query.clone())
+    }
+
+    async fn describe_task_queue(
+        &self,
+        task_queue: String,
+        task_queue_type: TaskQueueType,
+        include_status: bool,
+    ) -> Result<DescribeTaskQueueResponse> {
+        retry_call!(
+            self,
+            describe_task_queue,
+            task_queue.clone(),
+            task_queue_type,
+            include_status
+        )
+    }
+
     fn get_options(&self) -> &ClientOptions {
         self.client.get_options()
     }
@@ -436,13 +942,74 @@ where
     fn namespace(&self) -> &str {
         self.client.namespace()
     }
+
+    fn capabilities(&self) -> Option<get_system_info_response::Capabilities> {
+        self.client.capabilities()
+    }
+
+    fn subscribe_poll_pauses(&self) -> Option<broadcast::Receiver<Duration>> {
+        self.poll_circuit_breaker.as_ref().map(|cb| cb.subscribe())
+    }
+
+    fn with_identity(
+        &self,
+        identity: String,
+    ) -> Option<Arc<dyn WorkflowClientTrait + Send + Sync>> {
+        Some(Arc::new(self.clone_with_identity(identity)?))
+    }
+
+    /// Keeps the retry behavior, and the circuit breaker and rate limiter shared with this client,
+    /// if the wrapped client supports overriding its identity
+    fn clone_with_identity(&self, identity: String) -> Option<Self> {
+        Some(RetryClient {
+            client: self.client.clone_with_identity(identity)?,
+            retry_config: self.retry_config.clone(),
+            poll_retry_config: self.poll_retry_config.clone(),
+            completion_retry_config: self.completion_retry_config.clone(),
+            poll_circuit_breaker: self.poll_circuit_breaker.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            capabilities: self.capabilities.clone(),
+            metrics: self.metrics.clone(),
+        })
+    }
+}
+
+impl<C> RawClientLikeUser for RetryClient<C>
+where
+    C: RawClientLikeUser,
+{
+    type RawClientT = C::RawClientT;
+
+    fn wf_svc(&self) -> Self::RawClientT {
+        self.client.wf_svc()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::MockWorkflowClientTrait;
+    use crate::{ClientOptionsBuilder, MockWorkflowClientTrait};
     use tonic::Status;
+    use url::Url;
+
+    #[tokio::test]
+    async fn namespace_clients_share_connection() {
+        let opts = ClientOptionsBuilder::default()
+            .target_url(Url::parse("http://localhost:7233").unwrap())
+            .client_name("test".to_string())
+            .client_version("0.1".to_string())
+            .worker_binary_id("bin".to_string())
+            .lazy_connect(true)
+            .build()
+            .unwrap();
+        let client = opts.connect("ns1", None, None).await.unwrap();
+        let other = client.with_namespace("ns2");
+        assert_eq!(client.namespace(), "ns1");
+        assert_eq!(other.namespace(), "ns2");
+        let (inner, other_inner) = (&client.get_client().inner, &other.get_client().inner);
+        assert!(Arc::ptr_eq(&inner.connection, &other_inner.connection));
+        assert!(Arc::ptr_eq(&inner.capabilities, &other_inner.capabilities));
+    }
 
     #[tokio::test]
     async fn non_retryable_errors() {
@@ -591,15 +1158,404 @@ mod tests {
             assert!(result.is_ok());
         }
     }
-}
 
-impl<C> RawClientLikeUser for RetryClient<C>
-where
-    C: RawClientLikeUser,
-{
-    type RawClientT = C::RawClientT;
+    #[tokio::test]
+    async fn completion_retry_config_is_used() {
+        let mut mock_client = MockWorkflowClientTrait::new();
+        mock_client
+            .expect_complete_activity_task()
+            .returning(|_, _| Err(Status::new(Code::Unavailable, "retryable failure")))
+            .times(2);
+        mock_client
+            .expect_cancel_activity_task()
+            .returning(|_, _| Err(Status::new(Code::Unavailable, "retryable failure")))
+            .times(2);
+        // Other calls still use the normal config
+        mock_client
+            .expect_record_activity_heartbeat()
+            .returning(|_, _| Err(Status::new(Code::Unavailable, "retryable failure")))
+            .times(5);
+        let retry_client = RetryClient::new(
+            mock_client,
+            RetryConfig {
+                max_retries: 5,
+                ..Default::default()
+            },
+        )
+        .with_completion_retry_config(RetryConfig {
+            max_retries: 2,
+            ..Default::default()
+        });
+        assert!(retry_client
+            .complete_activity_task(vec![1].into(), None)
+            .await
+            .is_err());
+        assert!(retry_client
+            .cancel_activity_task(vec![1].into(), None)
+            .await
+            .is_err());
+        assert!(retry_client
+            .record_activity_heartbeat(vec![1].into(), None)
+            .await
+            .is_err());
+    }
 
-    fn wf_svc(&self) -> Self::RawClientT {
-        self.client.wf_svc()
+    #[tokio::test]
+    async fn internal_errors_not_retried_when_server_differentiates_them() {
+        for (differentiated, expected_attempts) in [(false, 3), (true, 1)] {
+            let mut mock_client = MockWorkflowClientTrait::new();
+            mock_client
+                .expect_record_activity_heartbeat()
+                .returning(|_, _| Err(Status::new(Code::Internal, "internal failure")))
+                .times(expected_attempts);
+            let capabilities = CapabilitiesCell::default();
+            capabilities
+                .set(Some(get_system_info_response::Capabilities {
+                    internal_error_differentiation: differentiated,
+                    ..Default::default()
+                }))
+                .unwrap();
+            let retry_client = RetryClient::new(
+                mock_client,
+                RetryConfig {
+                    max_retries: 3,
+                    ..Default::default()
+                },
+            )
+            .with_capabilities(capabilities);
+            assert!(retry_client
+                .record_activity_heartbeat(vec![1].into(), None)
+                .await
+                .is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn list_stream_paginates_and_retries() {
+        use futures::TryStreamExt;
+        use temporal_sdk_core_protos::temporal::api::{
+            common::v1::WorkflowExecution, workflow::v1::WorkflowExecutionInfo,
+        };
+
+        let page = |ids: &[&str], next_page_token: &[u8]| ListWorkflowExecutionsResponse {
+            executions: ids
+                .iter()
+                .map(|id| WorkflowExecutionInfo {
+                    execution: Some(WorkflowExecution {
+                        workflow_id: id.to_string(),
+                        run_id: "".to_string(),
+                    }),
+                    ..Default::default()
+                })
+                .collect(),
+            next_page_token: next_page_token.to_vec(),
+        };
+        let mut mock_client = MockWorkflowClientTrait::new();
+        let mut seq = mockall::Sequence::new();
+        mock_client
+            .expect_list_workflow_executions()
+            .withf(|_, token, _| token.is_empty())
+            .returning(move |_, _, _| Ok(page(&["a", "b"], b"next")))
+            .times(1)
+            .in_sequence(&mut seq);
+        mock_client
+            .expect_list_workflow_executions()
+            .returning(|_, _, _| Err(Status::new(Code::ResourceExhausted, "rate limited")))
+            .times(1)
+            .in_sequence(&mut seq);
+        mock_client
+            .expect_list_workflow_executions()
+            .withf(|_, token, query| token == b"next" && query == "WorkflowType='foo'")
+            .returning(move |_, _, _| Ok(page(&["c"], b"")))
+            .times(1)
+            .in_sequence(&mut seq);
+        let retry_client = RetryClient::new(mock_client, Default::default());
+        let ids: Vec<_> = retry_client
+            .list_workflow_executions_stream(2, "WorkflowType='foo'".to_string())
+            .map_ok(|info| info.execution.unwrap().workflow_id)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(ids, ["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn signal_with_start_retries_reuse_request_id() {
+        let seen_ids = Arc::new(Mutex::new(vec![]));
+        let seen_ids_clone = seen_ids.clone();
+        let mut mock_client = MockWorkflowClientTrait::new();
+        mock_client
+            .expect_signal_with_start_workflow_execution()
+            .returning(move |opts| {
+                let mut seen = seen_ids_clone.lock();
+                seen.push(opts.request_id.unwrap());
+                if seen.len() < 3 {
+                    Err(Status::new(Code::Unavailable, "retryable failure"))
+                } else {
+                    Ok(Default::default())
+                }
+            })
+            .times(3);
+        let retry_client = RetryClient::new(mock_client, Default::default());
+        retry_client
+            .signal_with_start_workflow_execution(SignalWithStartOptions {
+                workflow_id: "wf".to_string(),
+                signal_name: "sig".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let seen = seen_ids.lock();
+        assert!(!seen[0].is_empty());
+        assert!(seen.iter().all(|id| id == &seen[0]));
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_allows_burst_then_limits() {
+        let limiter = RateLimiter::new(ClientRateLimitConfig {
+            max_requests_per_second: 100.0,
+            burst: 3,
+        });
+        for _ in 0..3 {
+            assert_eq!(limiter.reserve(), Duration::ZERO);
+        }
+        // Each further caller waits one more interval than the last
+        let first = limiter.reserve();
+        let second = limiter.reserve();
+        assert!(first > Duration::ZERO && first <= Duration::from_millis(10));
+        assert!(second > Duration::from_millis(10) && second <= Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn cancelled_waiters_return_their_tokens() {
+        let limiter = RateLimiter::new(ClientRateLimitConfig {
+            max_requests_per_second: 1.0,
+            burst: 1,
+        });
+        limiter.acquire().await;
+        let waiting = tokio::time::timeout(Duration::from_millis(10), limiter.acquire()).await;
+        assert!(waiting.is_err());
+        // Had the cancelled waiter kept its token, this would be a two second wait
+        assert!(limiter.reserve() <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn invalid_rate_limits_are_rejected() {
+        let build = |max_requests_per_second, burst| {
+            ClientOptionsBuilder::default()
+                .target_url(Url::parse("http://localhost:7233").unwrap())
+                .client_name("test".to_string())
+                .client_version("0.1".to_string())
+                .worker_binary_id("bin".to_string())
+                .rate_limit(ClientRateLimitConfig {
+                    max_requests_per_second,
+                    burst,
+                })
+                .build()
+        };
+        assert!(build(1.0, 1).is_ok());
+        for (max_requests_per_second, burst) in [(0.0, 1), (-1.0, 1), (f64::NAN, 1), (1.0, 0)] {
+            assert!(build(max_requests_per_second, burst).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limit_does_not_apply_to_polls() {
+        let mut mock_client = MockWorkflowClientTrait::new();
+        mock_client
+            .expect_record_activity_heartbeat()
+            .returning(|_, _| Ok(Default::default()))
+            .times(3);
+        mock_client
+            .expect_poll_activity_task()
+            .returning(|_, _| Ok(Default::default()))
+            .times(3);
+        let retry_client = RetryClient::new(mock_client, Default::default()).with_rate_limit(
+            ClientRateLimitConfig {
+                max_requests_per_second: 20.0,
+                burst: 1,
+            },
+        );
+        let start = Instant::now();
+        for _ in 0..3 {
+            retry_client
+                .poll_activity_task("tq".to_string(), None)
+                .await
+                .unwrap();
+        }
+        assert!(start.elapsed() < Duration::from_millis(50));
+        for _ in 0..3 {
+            retry_client
+                .record_activity_heartbeat(vec![1].into(), None)
+                .await
+                .unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn long_poll_retry_config_is_used() {
+        let mut mock_client = MockWorkflowClientTrait::new();
+        mock_client
+            .expect_poll_activity_task()
+            .returning(|_, _| Err(Status::new(Code::Unavailable, "retryable failure")))
+            .times(3);
+        let retry_client = RetryClient::new(mock_client, Default::default())
+            .with_poll_retry_config(RetryConfig {
+                max_retries: 3,
+                ..RetryConfig::poll_retry_policy()
+            });
+        let result = retry_client
+            .poll_activity_task("tq".to_string(), None)
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn identity_overridden_when_wrapped_client_supports_it() {
+        let mut mock_client = MockWorkflowClientTrait::new();
+        mock_client
+            .expect_clone_with_identity()
+            .withf(|id| id == "other")
+            .returning(|_| Some(MockWorkflowClientTrait::new()));
+        let retry_client = RetryClient::new(mock_client, Default::default());
+        assert!(retry_client.with_identity("other".to_string()).is_some());
+
+        let mut mock_client = MockWorkflowClientTrait::new();
+        mock_client.expect_clone_with_identity().returning(|_| None);
+        let retry_client = RetryClient::new(mock_client, Default::default());
+        assert!(retry_client.with_identity("other".to_string()).is_none());
+    }
+
+    #[tokio::test]
+    async fn failed_attempts_are_recorded_with_namespace_and_retry() {
+        use opentelemetry::{
+            metrics::{MeterProvider, NumberKind},
+            sdk::{
+                export::metrics::{CheckpointSet, ExportKindSelector, Sum},
+                metrics::{aggregators::SumAggregator, controllers, selectors},
+            },
+        };
+
+        let mut controller = controllers::pull(
+            Box::new(selectors::simple::Selector::Exact),
+            Box::new(ExportKindSelector::Cumulative),
+        )
+        .with_cache_period(Duration::ZERO)
+        .with_memory(true)
+        .build();
+        let meter = controller.provider().meter("test", None);
+        let mut mock_client = MockWorkflowClientTrait::new();
+        mock_client
+            .expect_namespace()
+            .return_const("ns".to_string());
+        mock_client
+            .expect_list_namespaces()
+            .times(2)
+            .returning(|| Err(Status::new(Code::Unavailable, "retryable failure")));
+        mock_client
+            .expect_list_namespaces()
+            .returning(|| Ok(Default::default()));
+        let retry_client = RetryClient::new(mock_client, Default::default())
+            .with_metrics(MetricsContext::new(vec![], &meter));
+        retry_client.list_namespaces().await.unwrap();
+
+        controller.collect().unwrap();
+        let mut failures = vec![];
+        controller
+            .try_for_each(&ExportKindSelector::Cumulative, &mut |record| {
+                assert_ne!(record.descriptor().name(), "rpc_attempt_latency");
+                if record.descriptor().name() == "rpc_attempt_failure" {
+                    let attr = |key: &str| {
+                        record
+                            .attributes()
+                            .iter()
+                            .find(|(k, _)| k.as_str() == key)
+                            .map(|(_, v)| v.to_string())
+                    };
+                    assert_eq!(attr("namespace").as_deref(), Some("ns"));
+                    assert_eq!(attr("operation").as_deref(), Some("list_namespaces"));
+                    assert_eq!(attr("status_code").as_deref(), Some("Unavailable"));
+                    let sum = record
+                        .aggregator()
+                        .unwrap()
+                        .as_any()
+                        .downcast_ref::<SumAggregator>()
+                        .unwrap()
+                        .sum()
+                        .unwrap()
+                        .to_u64(&NumberKind::U64);
+                    failures.push((attr("is_retry").unwrap(), sum));
+                }
+                Ok(())
+            })
+            .unwrap();
+        failures.sort();
+        assert_eq!(
+            failures,
+            vec![("false".to_string(), 1), ("true".to_string(), 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn poll_circuit_breaker_pauses_polling() {
+        let mut mock_client = MockWorkflowClientTrait::new();
+        mock_client
+            .expect_poll_workflow_task()
+            .returning(|_, _| Err(Status::new(Code::ResourceExhausted, "retryable failure")))
+            .times(3);
+        mock_client
+            .expect_poll_workflow_task()
+            .returning(|_, _| Ok(Default::default()))
+            .times(1);
+        let open_duration = Duration::from_millis(100);
+        let retry_client = RetryClient::new(mock_client, Default::default())
+            .with_poll_circuit_breaker(
+                PollCircuitBreakerConfig {
+                    failure_threshold: 2,
+                    open_duration,
+                },
+                None,
+            );
+        let mut pauses = retry_client.subscribe_poll_pauses().unwrap();
+        let start = Instant::now();
+        let result = retry_client
+            .poll_workflow_task("tq".to_string(), false)
+            .await;
+        assert!(result.is_ok());
+        // The circuit opened on the second failure, and immediately re-opened when the first
+        // attempt after the pause failed too
+        assert!(start.elapsed() >= open_duration * 2);
+        assert_eq!(pauses.try_recv().unwrap(), open_duration);
+        assert_eq!(pauses.try_recv().unwrap(), open_duration);
+        assert!(pauses.try_recv().is_err());
+        let breaker = retry_client.poll_circuit_breaker.unwrap();
+        assert_eq!(breaker.state.lock().consecutive_failures, 0);
+        assert!(breaker.open_for().is_none());
+    }
+
+    #[tokio::test]
+    async fn poll_circuit_breaker_pauses_count_against_max_retries() {
+        let mut mock_client = MockWorkflowClientTrait::new();
+        mock_client
+            .expect_poll_workflow_task()
+            .returning(|_, _| Err(Status::new(Code::ResourceExhausted, "retryable failure")))
+            .times(3);
+        let retry_client = RetryClient::new(mock_client, Default::default())
+            .with_poll_retry_config(RetryConfig {
+                max_retries: 3,
+                ..RetryConfig::poll_retry_policy()
+            })
+            .with_poll_circuit_breaker(
+                PollCircuitBreakerConfig {
+                    failure_threshold: 1,
+                    open_duration: Duration::from_millis(10),
+                },
+                None,
+            );
+        let result = retry_client
+            .poll_workflow_task("tq".to_string(), false)
+            .await;
+        assert!(result.is_err());
     }
 }
@@ -0,0 +1,229 @@
+//! Helpers for building visibility queries, as used when listing or counting workflow executions
+
+use std::{
+    fmt::{Display, Formatter},
+    time::SystemTime,
+};
+use temporal_sdk_core_protos::{
+    temporal::api::enums::v1::WorkflowExecutionStatus, utilities::rfc3339,
+};
+
+/// Builds a visibility query string out of clauses which must all match. An empty query matches
+/// all executions.
+///
+/// ```
+/// use temporal_client::{Comparison, VisibilityQuery};
+/// use temporal_sdk_core_protos::temporal::api::enums::v1::WorkflowExecutionStatus;
+///
+/// let query = VisibilityQuery::new()
+///     .workflow_type("order")
+///     .status(WorkflowExecutionStatus::Running)
+///     .search_attribute("CustomerId", Comparison::Eq, "it's-me");
+/// assert_eq!(
+///     query.to_string(),
+///     r"WorkflowType = 'order' AND ExecutionStatus = 'Running' AND CustomerId = 'it\'s-me'"
+/// );
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VisibilityQuery {
+    clauses: Vec<String>,
+}
+
+impl VisibilityQuery {
+    /// Create a query which matches all executions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only match executions with this workflow id
+    pub fn workflow_id(self, workflow_id: impl Into<String>) -> Self {
+        self.search_attribute("WorkflowId", Comparison::Eq, workflow_id.into())
+    }
+
+    /// Only match executions of this workflow type
+    pub fn workflow_type(self, workflow_type: impl Into<String>) -> Self {
+        self.search_attribute("WorkflowType", Comparison::Eq, workflow_type.into())
+    }
+
+    /// Only match executions on this task queue
+    pub fn task_queue(self, task_queue: impl Into<String>) -> Self {
+        self.search_attribute("TaskQueue", Comparison::Eq, task_queue.into())
+    }
+
+    /// Only match executions with this status
+    pub fn status(self, status: WorkflowExecutionStatus) -> Self {
+        let name = match status {
+            WorkflowExecutionStatus::Unspecified => "Unspecified",
+            WorkflowExecutionStatus::Running => "Running",
+            WorkflowExecutionStatus::Completed => "Completed",
+            WorkflowExecutionStatus::Failed => "Failed",
+            WorkflowExecutionStatus::Canceled => "Canceled",
+            WorkflowExecutionStatus::Terminated => "Terminated",
+            WorkflowExecutionStatus::ContinuedAsNew => "ContinuedAsNew",
+            WorkflowExecutionStatus::TimedOut => "TimedOut",
+        };
+        self.search_attribute("ExecutionStatus", Comparison::Eq, name)
+    }
+
+    /// Only match executions started at or after this time
+    pub fn started_after(self, time: SystemTime) -> Self {
+        self.search_attribute("StartTime", Comparison::GreaterOrEq, time)
+    }
+
+    /// Only match executions started before this time
+    pub fn started_before(self, time: SystemTime) -> Self {
+        self.search_attribute("StartTime", Comparison::Less, time)
+    }
+
+    /// Only match executions which closed at or after this time
+    pub fn closed_after(self, time: SystemTime) -> Self {
+        self.search_attribute("CloseTime", Comparison::GreaterOrEq, time)
+    }
+
+    /// Only match executions which closed before this time
+    pub fn closed_before(self, time: SystemTime) -> Self {
+        self.search_attribute("CloseTime", Comparison::Less, time)
+    }
+
+    /// Only match executions whose search attribute compares with the value as specified. Works
+    /// for both built-in and custom search attributes.
+    pub fn search_attribute(
+        mut self,
+        name: impl Into<String>,
+        comparison: Comparison,
+        value: impl Into<QueryValue>,
+    ) -> Self {
+        self.clauses
+            .push(format!("{} {} {}", name.into(), comparison, value.into()));
+        self
+    }
+
+    /// Add a clause which is used as-is, for anything the other helpers can't express
+    pub fn raw(mut self, clause: impl Into<String>) -> Self {
+        self.clauses.push(clause.into());
+        self
+    }
+}
+
+impl Display for VisibilityQuery {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.clauses.join(" AND "))
+    }
+}
+
+impl From<VisibilityQuery> for String {
+    fn from(q: VisibilityQuery) -> Self {
+        q.to_string()
+    }
+}
+
+/// How a search attribute is compared with a value in a [VisibilityQuery]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Comparison {
+    /// `=`
+    Eq,
+    /// `!=`
+    NotEq,
+    /// `>`
+    Greater,
+    /// `>=`
+    GreaterOrEq,
+    /// `<`
+    Less,
+    /// `<=`
+    LessOrEq,
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Comparison::Eq => "=",
+            Comparison::NotEq => "!=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEq => ">=",
+            Comparison::Less => "<",
+            Comparison::LessOrEq => "<=",
+        })
+    }
+}
+
+/// A value search attributes can be compared with in a [VisibilityQuery]
+#[derive(Clone, Debug, PartialEq)]
+pub enum QueryValue {
+    /// Strings are quoted and escaped
+    String(String),
+    /// An integer
+    Int(i64),
+    /// A floating point number
+    Double(f64),
+    /// A boolean
+    Bool(bool),
+    /// Times are formatted as RFC 3339 timestamps in UTC
+    Time(SystemTime),
+}
+
+impl Display for QueryValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryValue::String(s) => {
+                write!(f, "'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+            }
+            QueryValue::Int(i) => write!(f, "{}", i),
+            QueryValue::Double(d) => write!(f, "{}", d),
+            QueryValue::Bool(b) => write!(f, "{}", b),
+            QueryValue::Time(t) => write!(f, "'{}'", rfc3339(*t)),
+        }
+    }
+}
+
+impl From<&str> for QueryValue {
+    fn from(s: &str) -> Self {
+        Self::String(s.to_string())
+    }
+}
+impl From<String> for QueryValue {
+    fn from(s: String) -> Self {
+        Self::String(s)
+    }
+}
+impl From<i64> for QueryValue {
+    fn from(i: i64) -> Self {
+        Self::Int(i)
+    }
+}
+impl From<f64> for QueryValue {
+    fn from(d: f64) -> Self {
+        Self::Double(d)
+    }
+}
+impl From<bool> for QueryValue {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+impl From<SystemTime> for QueryValue {
+    fn from(t: SystemTime) -> Self {
+        Self::Time(t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn builds_queries() {
+        assert_eq!(VisibilityQuery::new().to_string(), "");
+        let query = VisibilityQuery::new()
+            .workflow_id(r"a\b")
+            .started_after(UNIX_EPOCH)
+            .search_attribute("Count", Comparison::LessOrEq, 5)
+            .search_attribute("Done", Comparison::NotEq, true)
+            .raw("CloseTime IS NULL");
+        assert_eq!(
+            query.to_string(),
+            r"WorkflowId = 'a\\b' AND StartTime >= '1970-01-01T00:00:00Z' AND Count <= 5 AND Done != true AND CloseTime IS NULL"
+        );
+    }
+}
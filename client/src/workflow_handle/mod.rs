@@ -1,14 +1,23 @@
-use crate::{InterceptedMetricsSvc, RawClientLike};
+use crate::{InterceptedMetricsSvc, RawClientLike, WorkflowService};
 use anyhow::{anyhow, bail};
-use std::marker::PhantomData;
+use std::{collections::HashMap, marker::PhantomData, time::SystemTime};
 use temporal_sdk_core_protos::{
     coresdk::{common::Payload, FromPayloadsExt},
     temporal::api::{
-        common::v1::WorkflowExecution, enums::v1::HistoryEventFilterType, failure::v1::Failure,
-        history::v1::history_event::Attributes,
-        workflowservice::v1::GetWorkflowExecutionHistoryRequest,
+        common::v1::{self as common, Payloads, WorkflowExecution},
+        enums::v1::{HistoryEventFilterType, WorkflowExecutionStatus},
+        failure::v1::Failure,
+        history::v1::{history_event::Attributes, History},
+        workflow::v1::{PendingActivityInfo, PendingChildExecutionInfo},
+        workflowservice::v1::{
+            DescribeWorkflowExecutionRequest, DescribeWorkflowExecutionResponse,
+            GetWorkflowExecutionHistoryRequest, RequestCancelWorkflowExecutionRequest,
+            TerminateWorkflowExecutionRequest,
+        },
     },
+    utilities::TryIntoOrNone,
 };
+use tonic::Status;
 
 /// Enumerates terminal states for a particular workflow execution
 // TODO: Add non-proto failure types, flesh out details, etc.
@@ -52,6 +61,7 @@ pub struct WorkflowHandle<ClientT, ResultT> {
 }
 
 /// Holds needed information to refer to a specific workflow run, or workflow execution chain
+#[derive(Debug, Clone)]
 pub struct WorkflowExecutionInfo {
     /// Namespace the workflow lives in
     pub namespace: String,
@@ -71,6 +81,60 @@ impl WorkflowExecutionInfo {
     }
 }
 
+/// A description of a workflow execution, as returned by the server when describing it
+#[derive(Debug, Clone)]
+pub struct WorkflowExecutionDescription {
+    /// The workflow's id
+    pub workflow_id: String,
+    /// The run described
+    pub run_id: String,
+    /// The workflow's type
+    pub workflow_type: String,
+    /// The task queue the workflow runs on
+    pub task_queue: String,
+    /// The run's status
+    pub status: WorkflowExecutionStatus,
+    /// When the run started
+    pub start_time: Option<SystemTime>,
+    /// When the run closed, if it has
+    pub close_time: Option<SystemTime>,
+    /// The number of events in the run's history
+    pub history_length: i64,
+    /// Activities which have been scheduled and not yet resolved
+    pub pending_activities: Vec<PendingActivityInfo>,
+    /// Child workflows which have been started and not yet resolved
+    pub pending_children: Vec<PendingChildExecutionInfo>,
+    /// The workflow's memo
+    pub memo: HashMap<String, common::Payload>,
+    /// The workflow's search attributes
+    pub search_attributes: HashMap<String, common::Payload>,
+}
+
+impl From<DescribeWorkflowExecutionResponse> for WorkflowExecutionDescription {
+    fn from(resp: DescribeWorkflowExecutionResponse) -> Self {
+        let info = resp.workflow_execution_info.unwrap_or_default();
+        let execution = info.execution.unwrap_or_default();
+        Self {
+            workflow_id: execution.workflow_id,
+            run_id: execution.run_id,
+            workflow_type: info.r#type.map(|t| t.name).unwrap_or_default(),
+            task_queue: info.task_queue,
+            status: WorkflowExecutionStatus::from_i32(info.status)
+                .unwrap_or(WorkflowExecutionStatus::Unspecified),
+            start_time: info.start_time.try_into_or_none(),
+            close_time: info.close_time.try_into_or_none(),
+            history_length: info.history_length,
+            pending_activities: resp.pending_activities,
+            pending_children: resp.pending_children,
+            memo: info.memo.map(|m| m.fields).unwrap_or_default(),
+            search_attributes: info
+                .search_attributes
+                .map(|sa| sa.indexed_fields)
+                .unwrap_or_default(),
+        }
+    }
+}
+
 /// A workflow handle to a workflow with unknown types. Uses raw payloads.
 pub type UntypedWorkflowHandle<CT> = WorkflowHandle<CT, Vec<Payload>>;
 
@@ -88,6 +152,101 @@ where
         }
     }
 
+    /// The workflow execution this handle refers to
+    pub fn info(&self) -> &WorkflowExecutionInfo {
+        &self.info
+    }
+
+    /// Describe the workflow execution, including its status and anything pending in it
+    pub async fn describe(&self) -> Result<WorkflowExecutionDescription, Status> {
+        Ok(self
+            .client
+            .clone()
+            .describe_workflow_execution(DescribeWorkflowExecutionRequest {
+                namespace: self.info.namespace.clone(),
+                execution: Some(self.execution()),
+            })
+            .await?
+            .into_inner()
+            .into())
+    }
+
+    /// Request cancellation of the workflow execution
+    pub async fn cancel(&self) -> Result<(), Status> {
+        self.client
+            .clone()
+            .request_cancel_workflow_execution(RequestCancelWorkflowExecutionRequest {
+                namespace: self.info.namespace.clone(),
+                workflow_execution: Some(self.execution()),
+                identity: self.identity(),
+                ..Default::default()
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Terminate the workflow execution, recording the reason and details in its history
+    pub async fn terminate(
+        &self,
+        reason: impl Into<String>,
+        details: Option<Payloads>,
+    ) -> Result<(), Status> {
+        self.client
+            .clone()
+            .terminate_workflow_execution(TerminateWorkflowExecutionRequest {
+                namespace: self.info.namespace.clone(),
+                workflow_execution: Some(self.execution()),
+                reason: reason.into(),
+                details,
+                identity: self.identity(),
+                ..Default::default()
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Fetch the complete history of the workflow execution, following as many pages as needed.
+    /// The history may be serialized with [History::to_json] or as protobuf, and replayed later.
+    pub async fn fetch_history(&self) -> Result<History, Status> {
+        let mut history = History::default();
+        let mut next_page_token = vec![];
+        loop {
+            let resp = self
+                .client
+                .clone()
+                .get_workflow_execution_history(GetWorkflowExecutionHistoryRequest {
+                    namespace: self.info.namespace.clone(),
+                    execution: Some(self.execution()),
+                    next_page_token,
+                    ..Default::default()
+                })
+                .await?
+                .into_inner();
+            history
+                .events
+                .extend(resp.history.into_iter().flat_map(|h| h.events));
+            if resp.next_page_token.is_empty() {
+                return Ok(history);
+            }
+            next_page_token = resp.next_page_token;
+        }
+    }
+
+    fn execution(&self) -> WorkflowExecution {
+        WorkflowExecution {
+            workflow_id: self.info.workflow_id.clone(),
+            run_id: self.info.run_id.clone().unwrap_or_default(),
+        }
+    }
+
+    fn identity(&self) -> String {
+        self.client
+            .client_options()
+            .map(|o| o.identity.clone())
+            .unwrap_or_default()
+    }
+
+    /// Wait for the workflow execution to finish, returning how it finished
     pub async fn get_workflow_result(
         &self,
         opts: GetWorkflowResultOpts,
@@ -181,3 +340,46 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+    use temporal_sdk_core_protos::temporal::api::{
+        common::v1::{Memo, WorkflowType},
+        workflow::v1::WorkflowExecutionInfo as ProtoExecutionInfo,
+    };
+
+    #[test]
+    fn description_from_response() {
+        let start = UNIX_EPOCH + Duration::from_secs(100);
+        let desc = WorkflowExecutionDescription::from(DescribeWorkflowExecutionResponse {
+            workflow_execution_info: Some(ProtoExecutionInfo {
+                execution: Some(WorkflowExecution {
+                    workflow_id: "wid".to_string(),
+                    run_id: "rid".to_string(),
+                }),
+                r#type: Some(WorkflowType {
+                    name: "wf".to_string(),
+                }),
+                start_time: Some(start.into()),
+                status: WorkflowExecutionStatus::Running as i32,
+                memo: Some(Memo {
+                    fields: HashMap::from([("k".to_string(), common::Payload::default())]),
+                }),
+                ..Default::default()
+            }),
+            pending_activities: vec![PendingActivityInfo::default()],
+            ..Default::default()
+        });
+        assert_eq!(desc.workflow_id, "wid");
+        assert_eq!(desc.run_id, "rid");
+        assert_eq!(desc.workflow_type, "wf");
+        assert_eq!(desc.status, WorkflowExecutionStatus::Running);
+        assert_eq!(desc.start_time, Some(start));
+        assert_eq!(desc.close_time, None);
+        assert_eq!(desc.pending_activities.len(), 1);
+        assert!(desc.memo.contains_key("k"));
+        assert!(desc.search_attributes.is_empty());
+    }
+}
@@ -1,10 +1,13 @@
 //! Error types exposed by public APIs
 
 use prost_types::TimestampOutOfSystemRangeError;
-use temporal_sdk_core_protos::coresdk::{
-    activity_result::ActivityExecutionResult,
-    workflow_activation::remove_from_cache::EvictionReason,
-    workflow_completion::WorkflowActivationCompletion,
+use temporal_sdk_core_protos::{
+    coresdk::{
+        activity_result::ActivityExecutionResult,
+        workflow_activation::remove_from_cache::EvictionReason,
+        workflow_completion::WorkflowActivationCompletion,
+    },
+    TaskToken,
 };
 
 /// Errors thrown by [crate::Worker::poll_workflow_activation]
@@ -58,6 +61,14 @@ pub enum CompleteWfError {
     /// errors, so lang should consider this fatal.
     #[error("Unhandled grpc error when completing workflow task: {0:?}")]
     TonicError(#[from] tonic::Status),
+    /// Lang completed an activation for a run which has no outstanding activation. This happens
+    /// when a completion races with the run being evicted, or the same activation is completed more
+    /// than once. The completion was ignored, and lang may safely drop it.
+    #[error("Run {run_id} has no outstanding activation, its completion was ignored")]
+    ActivationNotOutstanding {
+        /// The run the completion was for
+        run_id: String,
+    },
 }
 
 /// Errors thrown by [crate::Worker::complete_activity_task]
@@ -80,6 +91,54 @@ pub enum CompleteActivityError {
     NoWorkerForQueue(String),
 }
 
+/// Payloads which core refused to send to the server because they exceed
+/// [crate::worker::WorkerConfig::payload_size_limit_bytes]. Workflow task completions containing
+/// such payloads are failed with this error as the failure message, and activity results are
+/// replaced with a failure containing it.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error(
+    "PayloadTooLarge: payloads of {origin} are {size} bytes, exceeding the limit of {limit} bytes"
+)]
+pub struct PayloadTooLarge {
+    /// What the payloads belong to, ex: `command #2 (ScheduleActivityTask, activity_id 3)`
+    pub origin: String,
+    /// Total size of the payloads, as they would have been sent
+    pub size: usize,
+    /// The configured limit
+    pub limit: usize,
+}
+
+/// Errors thrown by [crate::Worker::soft_restart]
+#[derive(thiserror::Error, Debug)]
+pub enum WorkerRestartError {
+    /// The worker has begun shutting down, and so won't resume polling
+    #[error("Worker is shutting down")]
+    ShuttingDown,
+    /// The worker was built with pollers core doesn't know how to recreate (ex: in tests)
+    #[error("This worker's pollers cannot be recreated")]
+    Unsupported,
+}
+
+/// Errors thrown by the activity slot management functions of [crate::Worker]
+#[derive(thiserror::Error, Debug)]
+pub enum ActivitySlotError {
+    /// The worker does not poll for activities, so has no activity slots
+    #[error("This worker does not poll for activities")]
+    NoActivitySlots,
+    /// The worker has begun shutting down, and so won't hand out slots
+    #[error("Worker is shutting down")]
+    ShuttingDown,
+    /// A reserved slot was released, but none were reserved
+    #[error("No activity slots are reserved")]
+    NoneReserved,
+    /// The activity task isn't outstanding, ex: because it was already completed
+    #[error("Activity task {0} is not outstanding")]
+    UnknownTask(TaskToken),
+    /// The activity task's slot was already released
+    #[error("The slot of activity task {0} was already released")]
+    AlreadyReleased(TaskToken),
+}
+
 /// Errors thrown inside of workflow machines
 #[derive(thiserror::Error, Debug)]
 pub enum WFMachinesError {
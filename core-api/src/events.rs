@@ -0,0 +1,30 @@
+//! Structured events describing conditions inside a worker which are otherwise only visible in
+//! debug logs, so that they can be alerted on.
+
+use std::time::Duration;
+use temporal_sdk_core_protos::{
+    coresdk::workflow_activation::remove_from_cache::EvictionReason, TaskToken,
+};
+
+/// Something notable happened inside a worker
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum CoreEvent {
+    /// A workflow run was queued to be evicted from the cache
+    EvictionRequested {
+        run_id: String,
+        reason: EvictionReason,
+        message: String,
+    },
+    /// A workflow task for a new run arrived while the workflow cache was full, so
+    /// `evicting_run_id` is being evicted to make room for it
+    CacheFull { evicting_run_id: String },
+    /// A workflow task was buffered rather than issued, since the run it belongs to still has
+    /// outstanding work (ex: it's waiting for an eviction to complete)
+    WftBuffered { run_id: String },
+    /// Long polls persistently failed, and the client paused all polling for `pause`
+    PollBackoff { pause: Duration },
+    /// A heartbeat for the activity was held back (and will be sent later with the latest details)
+    /// because the activity's heartbeat throttle interval had not yet elapsed
+    HeartbeatThrottled { task_token: TaskToken },
+}
@@ -1,13 +1,26 @@
 pub mod errors;
+pub mod events;
+pub mod metrics;
 pub mod worker;
 
 use crate::{
-    errors::{CompleteActivityError, CompleteWfError, PollActivityError, PollWfError},
-    worker::WorkerConfig,
+    errors::{
+        ActivitySlotError, CompleteActivityError, CompleteWfError, PollActivityError, PollWfError,
+        WorkerRestartError,
+    },
+    metrics::CoreMeter,
+    worker::{ShutdownOptions, ShutdownProgress, WorkerConfig, WorkerStatus},
+};
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt,
 };
 use log::Level;
 use opentelemetry::metrics::Meter;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use temporal_sdk_core_protos::coresdk::{
     activity_task::ActivityTask, workflow_activation::WorkflowActivation,
     workflow_completion::WorkflowActivationCompletion, ActivityHeartbeat, ActivityTaskCompletion,
@@ -47,6 +60,35 @@ pub trait Worker: Send + Sync {
     /// concurrently internally.
     async fn poll_activity_task(&self) -> Result<ActivityTask, PollActivityError>;
 
+    /// An alternative to calling [Worker::poll_workflow_activation] in a loop. Each item is the
+    /// result of one poll, and the stream ends once the worker is shut down and there are no more
+    /// activations to deliver (rather than yielding [PollWfError::ShutDown]).
+    ///
+    /// Activations must still be completed with [Worker::complete_workflow_activation], which may
+    /// be called concurrently with consuming the stream. Only one stream (or poll loop) should be
+    /// used per worker.
+    fn workflow_activation_stream(&self) -> BoxStream<'_, Result<WorkflowActivation, PollWfError>> {
+        stream::unfold(self, |worker| async move {
+            match worker.poll_workflow_activation().await {
+                Err(PollWfError::ShutDown) => None,
+                res => Some((res, worker)),
+            }
+        })
+        .boxed()
+    }
+
+    /// Like [Worker::workflow_activation_stream], but for activity tasks polled with
+    /// [Worker::poll_activity_task]. The stream ends once the worker is shut down.
+    fn activity_task_stream(&self) -> BoxStream<'_, Result<ActivityTask, PollActivityError>> {
+        stream::unfold(self, |worker| async move {
+            match worker.poll_activity_task().await {
+                Err(PollActivityError::ShutDown) => None,
+                res => Some((res, worker)),
+            }
+        })
+        .boxed()
+    }
+
     /// Tell the worker that a workflow activation has completed. May be freely called concurrently.
     async fn complete_workflow_activation(
         &self,
@@ -78,6 +120,12 @@ pub trait Worker: Send + Sync {
     /// immediately. Underlying validation errors are swallowed and logged, this has been agreed to
     /// be optimal behavior for the user as we don't want to break activity execution due to badly
     /// configured heartbeat options.
+    ///
+    /// Heartbeating an activity this worker is not tracking (ex: one which already completed, or
+    /// was never issued by this worker) results in a cancel task with reason `NOT_FOUND` being
+    /// issued for it, so that the activity learns it should stop. The same happens, without any
+    /// heartbeat being needed, for activities whose workflow run core knows has ended, because this
+    /// worker completed it or its history shows it was terminated, timed out, etc.
     fn record_activity_heartbeat(&self, details: ActivityHeartbeat);
 
     /// Request that a workflow be evicted by its run id. This will generate a workflow activation
@@ -87,9 +135,49 @@ pub trait Worker: Send + Sync {
     /// a warning.
     fn request_workflow_eviction(&self, run_id: &str);
 
-    /// Return this worker's config
+    /// Change the maximum number of activity tasks this worker will have outstanding at once.
+    /// Raising the limit takes effect immediately, lowering it takes effect as outstanding
+    /// activities complete. Does nothing if this worker does not poll for activities.
+    fn set_max_outstanding_activities(&self, max: usize);
+
+    /// Change the maximum number of workflow tasks this worker will have outstanding at once.
+    /// Raising the limit takes effect immediately, lowering it takes effect as outstanding
+    /// workflow tasks complete. Values larger than [WorkerConfig::max_cached_workflows] are
+    /// clamped to it when caching is enabled.
+    fn set_max_outstanding_workflow_tasks(&self, max: usize);
+
+    /// Take an activity slot without an activity task, waiting until one is available. Use this
+    /// when lang is busy with work that isn't tied to an activity task (ex: waiting on an external
+    /// resource an activity will need), so that this worker doesn't poll for more activities than
+    /// it can run. The slot counts towards the outstanding activity limit until it is given back
+    /// with [Worker::release_activity_slot].
+    async fn reserve_activity_slot(&self) -> Result<(), ActivitySlotError>;
+
+    /// Give back a slot taken with [Worker::reserve_activity_slot]
+    fn release_activity_slot(&self) -> Result<(), ActivitySlotError>;
+
+    /// Give back the slot an outstanding activity task is using before the task is completed, ex:
+    /// for fire-and-forget activities which will be completed asynchronously. The activity stays
+    /// outstanding, so heartbeats, cancels, and its eventual completion work as usual, but it no
+    /// longer counts towards the outstanding activity limit.
+    fn release_activity_task_slot(&self, task_token: &[u8]) -> Result<(), ActivitySlotError>;
+
+    /// Return this worker's config. Note that this is the config the worker was created with, and
+    /// will not reflect changes made by [Worker::set_max_outstanding_activities] or
+    /// [Worker::set_max_outstanding_workflow_tasks].
     fn get_config(&self) -> &WorkerConfig;
 
+    /// Return a snapshot of this worker's state, ex: to report readiness or liveness
+    fn status(&self) -> WorkerStatus;
+
+    /// Stop polling, wait for all outstanding workflow tasks and activities to be completed, then
+    /// resume polling with newly created pollers, ex: so lang can re-register workflow code or
+    /// rotate credentials. Cached workflows are kept, so they aren't replayed afterward.
+    ///
+    /// As with [Worker::shutdown], lang must keep completing outstanding work for this to resolve.
+    /// Polls made while restarting wait until polling resumes.
+    async fn soft_restart(&self) -> Result<(), WorkerRestartError>;
+
     /// TODO: Will be replaced/fixed/whatever by shutdown refactoring
     fn initiate_shutdown(&self);
 
@@ -102,6 +190,16 @@ pub trait Worker: Send + Sync {
     /// connection.
     async fn shutdown(&self);
 
+    /// Like [Worker::shutdown], but bounded by [ShutdownOptions::deadline]. Outstanding work is
+    /// waited on, then outstanding activities are cancelled once
+    /// [ShutdownOptions::cancel_activities_after] of the deadline has elapsed, and finally
+    /// shutdown stops waiting once the deadline is reached. Returns what was still outstanding
+    /// when it finished, with phase [worker::ShutdownPhase::Complete] or
+    /// [worker::ShutdownPhase::Aborted].
+    ///
+    /// [Worker::finalize_shutdown] should still be called afterward.
+    async fn shutdown_with_deadline(&self, options: ShutdownOptions) -> ShutdownProgress;
+
     /// Completes shutdown and frees all resources. You should avoid simply dropping workers, as
     /// this does not allow async tasks to report any panics that may have occurred cleanly.
     ///
@@ -125,6 +223,13 @@ pub trait CoreTelemetry {
     /// used to create metrics instruments, or passed to things that create/record metrics (ex:
     /// clients).
     fn get_metric_meter(&self) -> Option<&Meter>;
+
+    /// If metrics gathering is enabled, returns a [CoreMeter] lang can use to create its own
+    /// counters, histograms, and gauges. Those metrics are exported by the same exporters as
+    /// core's, with the same globally configured attributes. Returns `None` by default.
+    fn get_core_meter(&self) -> Option<Arc<dyn CoreMeter>> {
+        None
+    }
 }
 
 /// A log line (which ultimately came from a tracing event) exported from Core->Lang
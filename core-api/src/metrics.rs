@@ -0,0 +1,176 @@
+//! Metric instruments lang SDKs may create to record their own metrics. Instruments created by a
+//! [CoreMeter] are exported by the same exporters, and with the same resource, as core's metrics.
+
+use std::{borrow::Cow, fmt::Debug, sync::Arc};
+
+/// Creates metric instruments which are exported alongside core's own metrics. Obtain one with
+/// [crate::CoreTelemetry::get_core_meter].
+pub trait CoreMeter: Send + Sync + Debug {
+    /// Build a set of attributes to record with. Attributes should be built once and reused where
+    /// possible, rather than rebuilt for every recording.
+    fn new_attributes(&self, attributes: Vec<MetricKeyValue>) -> MetricAttributes;
+    /// Create a monotonically increasing counter
+    fn counter(&self, params: MetricParameters) -> Arc<dyn Counter>;
+    /// Create a histogram, which records the distribution of the values recorded to it
+    fn histogram(&self, params: MetricParameters) -> Arc<dyn Histogram>;
+    /// Create a gauge, which reports only the most recently recorded value
+    fn gauge(&self, params: MetricParameters) -> Arc<dyn Gauge>;
+}
+
+/// Describes a metric instrument. Construct with [MetricParametersBuilder].
+#[derive(Debug, Clone, derive_builder::Builder)]
+#[builder(setter(into))]
+pub struct MetricParameters {
+    /// The name of the metric
+    pub name: Cow<'static, str>,
+    /// A human-readable description of what the metric measures
+    #[builder(default = "\"\".into()")]
+    pub description: Cow<'static, str>,
+    /// The unit the metric is measured in, ex: `ms` or `By`
+    #[builder(default = "\"\".into()")]
+    pub unit: Cow<'static, str>,
+}
+
+impl From<&'static str> for MetricParameters {
+    fn from(name: &'static str) -> Self {
+        Self {
+            name: name.into(),
+            description: "".into(),
+            unit: "".into(),
+        }
+    }
+}
+
+/// A counter created by a [CoreMeter]
+pub trait Counter: Send + Sync {
+    /// Add `value` to the counter
+    fn add(&self, value: u64, attributes: &MetricAttributes);
+}
+
+/// A histogram created by a [CoreMeter]
+pub trait Histogram: Send + Sync {
+    /// Record `value` in the histogram
+    fn record(&self, value: u64, attributes: &MetricAttributes);
+}
+
+/// A gauge created by a [CoreMeter]
+pub trait Gauge: Send + Sync {
+    /// Set the gauge to `value`
+    fn record(&self, value: u64, attributes: &MetricAttributes);
+}
+
+/// A set of attributes created by [CoreMeter::new_attributes]. Cheap to clone.
+#[derive(Debug, Clone, Default)]
+pub struct MetricAttributes {
+    kvs: Arc<Vec<MetricKeyValue>>,
+}
+
+impl MetricAttributes {
+    /// Create attributes from key/value pairs. Most users should use [CoreMeter::new_attributes]
+    /// instead, which includes any attributes the meter adds.
+    pub fn new(kvs: Vec<MetricKeyValue>) -> Self {
+        Self { kvs: Arc::new(kvs) }
+    }
+
+    /// Returns a copy of these attributes extended with `new_kvs`
+    pub fn with_new(&self, new_kvs: impl IntoIterator<Item = MetricKeyValue>) -> Self {
+        let mut kvs = self.kvs.clone();
+        Arc::make_mut(&mut kvs).extend(new_kvs);
+        Self { kvs }
+    }
+
+    /// The key/value pairs making up these attributes
+    pub fn kvs(&self) -> &[MetricKeyValue] {
+        &self.kvs
+    }
+}
+
+/// A single metric attribute
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricKeyValue {
+    /// The attribute's key
+    pub key: String,
+    /// The attribute's value
+    pub value: MetricValue,
+}
+
+impl MetricKeyValue {
+    /// Create a new attribute
+    pub fn new(key: impl Into<String>, value: impl Into<MetricValue>) -> Self {
+        Self {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+}
+
+/// The value of a metric attribute
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetricValue {
+    String(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl From<String> for MetricValue {
+    fn from(v: String) -> Self {
+        Self::String(v)
+    }
+}
+impl From<&str> for MetricValue {
+    fn from(v: &str) -> Self {
+        Self::String(v.to_string())
+    }
+}
+impl From<i64> for MetricValue {
+    fn from(v: i64) -> Self {
+        Self::Int(v)
+    }
+}
+impl From<f64> for MetricValue {
+    fn from(v: f64) -> Self {
+        Self::Float(v)
+    }
+}
+impl From<bool> for MetricValue {
+    fn from(v: bool) -> Self {
+        Self::Bool(v)
+    }
+}
+
+/// The current state of one metric for one set of attributes, as collected by core for lang to
+/// export itself. See `temporal_sdk_core::MetricsExporter::Forward`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoreMetric {
+    /// The name of the metric
+    pub name: String,
+    /// A human-readable description of what the metric measures
+    pub description: String,
+    /// The unit the metric is measured in, ex: `ms` or `By`
+    pub unit: String,
+    /// The attributes this value was recorded with
+    pub attributes: Vec<MetricKeyValue>,
+    /// The value aggregated from everything recorded since the process started
+    pub value: CoreMetricValue,
+}
+
+/// The aggregated value of a [CoreMetric]
+#[derive(Debug, Clone, PartialEq)]
+pub enum CoreMetricValue {
+    /// The total of everything added to a counter
+    Sum(f64),
+    /// The most recent value recorded to a gauge
+    LastValue(f64),
+    /// The distribution of values recorded to a histogram
+    Histogram {
+        /// How many values were recorded
+        count: u64,
+        /// The sum of all recorded values
+        sum: f64,
+        /// Exclusive upper bounds of each bucket but the last, which has no upper bound
+        bucket_boundaries: Vec<f64>,
+        /// How many values fell in each bucket. Has one more entry than `bucket_boundaries`.
+        bucket_counts: Vec<u64>,
+    },
+}
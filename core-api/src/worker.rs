@@ -1,4 +1,17 @@
-use std::time::Duration;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use temporal_sdk_core_protos::{
+    coresdk::{
+        activity_result::activity_execution_result, activity_task::ActivityTask,
+        common::Payload as CorePayload,
+    },
+    temporal::api::{common::v1::Payload, enums::v1::ResetReapplyType, failure::v1::Failure},
+};
 
 /// Defines per-worker configuration options
 #[derive(Debug, Clone, derive_builder::Builder)]
@@ -27,15 +40,35 @@ pub struct WorkerConfig {
     /// The maximum number of activity tasks that will ever be given to this worker concurrently
     #[builder(default = "100")]
     pub max_outstanding_activities: usize,
+    /// The maximum number of activity tasks of each listed activity type that will ever be given
+    /// to this worker concurrently, so that one type can't use every slot allowed by
+    /// `max_outstanding_activities`. Tasks of a type at its limit are held by core until an
+    /// activity of that type completes. Held tasks don't count towards
+    /// `max_outstanding_activities`, but their server-side timeouts keep running, so polling stops
+    /// while `max_outstanding_activities` tasks are held. Limits must be at least 1.
+    #[builder(default)]
+    pub max_outstanding_activities_per_type: HashMap<String, usize>,
     /// The maximum number of local activity tasks that will ever be given to this worker
     /// concurrently
     #[builder(default = "100")]
     pub max_outstanding_local_activities: usize,
+    /// The most activations which may be queued for lang while waiting on runs that already have
+    /// an activation outstanding (including legacy query activations). Once reached, the worker
+    /// stops polling the server for new workflow tasks until lang catches up, leaving them to other
+    /// workers on the task queue. Must be at least 1.
+    #[builder(default = "1000")]
+    pub max_pending_activations: usize,
     /// Maximum number of concurrent poll workflow task requests we will perform at a time on this
     /// worker's task queue. See also [WorkerConfig::nonsticky_to_sticky_poll_ratio]. Must be at
     /// least 1.
     #[builder(default = "5")]
     pub max_concurrent_wft_polls: usize,
+    /// If set, the number of concurrent workflow task polls is scaled between this and
+    /// [WorkerConfig::max_concurrent_wft_polls], increasing while polls return work and decreasing
+    /// while they come back empty. It is split between the sticky and nonsticky queues in the same
+    /// way as the maximum. By default there is no scaling, and the maximum is always used.
+    #[builder(setter(strip_option), default)]
+    pub min_concurrent_wft_polls: Option<usize>,
     /// [WorkerConfig::max_concurrent_wft_polls] * this number = the number of max pollers that will
     /// be allowed for the nonsticky queue when sticky tasks are enabled. If both defaults are used,
     /// the sticky queue will allow 4 max pollers while the nonsticky queue will allow one. The
@@ -43,18 +76,57 @@ pub struct WorkerConfig {
     /// enabled, there will be 2 concurrent polls.
     #[builder(default = "0.2")]
     pub nonsticky_to_sticky_poll_ratio: f32,
+    /// If set, the maximum number of concurrent polls on the sticky queue, instead of the share of
+    /// [WorkerConfig::max_concurrent_wft_polls] given by
+    /// [WorkerConfig::nonsticky_to_sticky_poll_ratio]. Useful for biasing polling toward the
+    /// sticky queue when cache hit rates are high.
+    #[builder(setter(strip_option), default)]
+    pub max_concurrent_sticky_wft_polls: Option<usize>,
+    /// If set, the maximum number of concurrent polls on the nonsticky queue when sticky queues are
+    /// enabled, instead of the share of [WorkerConfig::max_concurrent_wft_polls] given by
+    /// [WorkerConfig::nonsticky_to_sticky_poll_ratio].
+    #[builder(setter(strip_option), default)]
+    pub max_concurrent_nonsticky_wft_polls: Option<usize>,
     /// Maximum number of concurrent poll activity task requests we will perform at a time on this
     /// worker's task queue
     #[builder(default = "5")]
     pub max_concurrent_at_polls: usize,
+    /// If set, the number of concurrent activity task polls is scaled between this and
+    /// [WorkerConfig::max_concurrent_at_polls], increasing while polls return work and decreasing
+    /// while they come back empty. By default there is no scaling, and the maximum is always used.
+    #[builder(setter(strip_option), default)]
+    pub min_concurrent_at_polls: Option<usize>,
     /// If set to true this worker will only handle workflow tasks and local activities, it will not
     /// poll for activity tasks.
     #[builder(default = "false")]
     pub no_remote_activities: bool,
+    /// If set, this worker also polls an additional task queue for activity tasks, with its own
+    /// slots. Since only this worker polls that queue, lang can use it to run a series of
+    /// activities on the same host (ex: to implement sessions). See [SessionActivityQueueConfig].
+    #[builder(setter(strip_option), default)]
+    pub session_activity_queue: Option<SessionActivityQueueConfig>,
     /// How long a workflow task is allowed to sit on the sticky queue before it is timed out
     /// and moved to the non-sticky queue where it may be picked up by any worker.
     #[builder(default = "Duration::from_secs(10)")]
     pub sticky_queue_schedule_to_start_timeout: Duration,
+    /// If set, the sticky queue is named according to this template rather than core's default of
+    /// `{identity}-{task_queue}-{unique_id}`. The placeholders `{identity}` (the identity this
+    /// worker polls with), `{task_queue}`, and `{unique_id}` (a random id generated once per
+    /// process) are substituted. A template without `{unique_id}` must still produce a name no
+    /// other running worker uses, otherwise workers will be handed each other's sticky tasks.
+    #[builder(setter(strip_option, into), default)]
+    pub sticky_queue_name_template: Option<String>,
+    /// If set, this worker identifies itself to the server with this string rather than the
+    /// identity its client was configured with. Useful when several workers share a client but
+    /// should be distinguishable (ex: in the UI). Only supported by core's own clients - it is
+    /// ignored with a warning for other `WorkflowClientTrait` implementations.
+    #[builder(setter(strip_option, into), default)]
+    pub client_identity_override: Option<String>,
+
+    /// If set, the name and version of the lang SDK are appended to this worker's identity, so
+    /// the server can attribute tasks to a specific SDK build. See [LangSdkInfo].
+    #[builder(setter(strip_option), default)]
+    pub lang_sdk: Option<LangSdkInfo>,
 
     /// Longest interval for throttling activity heartbeats
     #[builder(default = "Duration::from_secs(60)")]
@@ -67,31 +139,253 @@ pub struct WorkerConfig {
     #[builder(default = "Duration::from_secs(30)")]
     pub default_heartbeat_throttle_interval: Duration,
 
+    /// Heartbeat throttling intervals for specific activity types, keyed by activity type name.
+    /// When an activity's type is present here, its interval is used instead of
+    /// `default_heartbeat_throttle_interval` or `heartbeat_timeout * 0.8`. It is still limited to
+    /// `heartbeat_timeout * 0.8` when a timeout is set (so heartbeats are never throttled past it)
+    /// and to `max_heartbeat_throttle_interval`.
+    #[builder(default)]
+    pub heartbeat_throttle_interval_overrides: HashMap<String, Duration>,
+
+    /// If set, throttled activity heartbeats are not sent on a per-activity timer. Instead, every
+    /// time this interval elapses, heartbeats for all activities whose throttle interval is over
+    /// are sent together. This reduces the number of wakeups and spreads RPCs less finely when
+    /// running many heartbeating activities, at the cost of each throttled heartbeat being
+    /// delayed by up to one extra interval. It should be kept well below the smallest heartbeat
+    /// timeout in use, since throttling already consumes 80% of that timeout.
+    #[builder(setter(strip_option), default)]
+    pub heartbeat_batch_flush_interval: Option<Duration>,
+
+    /// If set, activity tasks whose current attempt was scheduled longer ago than this by the time
+    /// they are received from the server (ex: because of a large backlog, or the worker being
+    /// stalled) are not dispatched to lang. Instead they are immediately failed with a
+    /// schedule-to-start timeout, so they may be retried according to their retry policy.
+    ///
+    /// The server does not tell workers an activity's schedule-to-start timeout, hence this being
+    /// configured per worker. The comparison is made with the local clock, so this should be
+    /// comfortably larger than any expected clock skew between the worker and the server.
+    #[builder(setter(strip_option), default)]
+    pub max_activity_schedule_to_start: Option<Duration>,
+
+    /// If set, this interceptor is invoked whenever a (non-local) activity task is about to be
+    /// handed to lang, and whenever one is completed. See [ActivityInterceptor].
+    #[builder(setter(strip_option), default)]
+    pub activity_interceptor: Option<Arc<dyn ActivityInterceptor>>,
+
+    /// If set, this interceptor is invoked with the headers of everything lang sends which starts
+    /// or signals another execution, and of everything handed to lang which carries headers from
+    /// whoever started or signaled it. See [HeaderInterceptor].
+    #[builder(setter(strip_option), default)]
+    pub header_interceptor: Option<Arc<dyn HeaderInterceptor>>,
+
     /// Sets the maximum number of activities per second the task queue will dispatch, controlled
     /// server-side. Note that this only takes effect upon an activity poll request. If multiple
     /// workers on the same queue have different values set, they will thrash with the last poller
     /// winning.
     #[builder(setter(strip_option), default)]
     pub max_task_queue_activities_per_second: Option<f64>,
+
+    /// If set, tuning values are read from this while the worker runs, and applied as they change.
+    /// Useful to tune a fleet of workers centrally. See [DynamicConfig].
+    #[builder(setter(strip_option), default)]
+    pub dynamic_config: Option<Arc<dyn DynamicConfig>>,
+
+    /// How often [WorkerConfig::dynamic_config] is asked for its current values, in addition to
+    /// whenever it reports they changed
+    #[builder(default = "Duration::from_secs(10)")]
+    pub dynamic_config_poll_interval: Duration,
+
+    /// If set, when a workflow task fails because of nondeterminism, the run is reset to the end
+    /// of the last workflow task before the one which didn't match history, rather than having
+    /// the task retried until new code is deployed. Only tasks core itself finds nondeterministic
+    /// are reset, not ones lang fails. See [NondeterminismResetConfig].
+    #[builder(setter(strip_option), default)]
+    pub reset_on_nondeterminism: Option<NondeterminismResetConfig>,
+
+    /// If set, workflow tasks being retried because a previous attempt failed are held back for a
+    /// while before being processed, so a workflow which fails every attempt (ex: lang crashes
+    /// deterministically) doesn't retry in a tight loop. The attempt is exposed to lang as
+    /// `WorkflowActivation::workflow_task_attempt` either way. See [WftFailureBackoffConfig].
+    #[builder(setter(strip_option), default)]
+    pub wft_failure_backoff: Option<WftFailureBackoffConfig>,
+
+    /// What to do with history events of types core doesn't recognize, which servers newer than
+    /// this version of core may send. By default the workflow task is failed. See
+    /// [UnknownHistoryEventPolicy].
+    #[builder(default)]
+    pub unknown_history_event_policy: UnknownHistoryEventPolicy,
+
+    /// If set, everything core receives from the server and exchanges with lang while processing
+    /// the selected workflow runs is recorded to files, so that bugs can be reproduced
+    /// deterministically without lang or a server. See [ActivationCaptureConfig].
+    #[builder(setter(strip_option), default)]
+    pub activation_capture: Option<ActivationCaptureConfig>,
+
+    /// Attributes added to every metric this worker emits, in addition to any global attributes
+    /// configured for telemetry
+    #[builder(default)]
+    pub metric_attributes: HashMap<String, String>,
+
+    /// If set, every payload holding user data is encoded with this codec before being sent to
+    /// the server, and decoded after being received from it, so neither lang nor workflow
+    /// machinery ever sees encoded payloads. See [PayloadCodec].
+    #[builder(setter(strip_option), default)]
+    pub payload_codec: Option<Arc<dyn PayloadCodec>>,
+
+    /// If set, every failure core sends to the server is converted with this, and every failure
+    /// received from it is converted back, so lang never sees converted failures. See
+    /// [FailureConverter].
+    #[builder(setter(strip_option), default)]
+    pub failure_converter: Option<Arc<dyn FailureConverter>>,
+
+    /// If set, a warning is logged whenever the payloads of a single command or activity result
+    /// are at least this many bytes. Sizes are measured as payloads will be sent, ie: after
+    /// [WorkerConfig::payload_codec] is applied.
+    #[builder(setter(strip_option), default)]
+    pub payload_size_warning_bytes: Option<usize>,
+
+    /// If set, commands or activity results whose payloads are at least this many bytes are never
+    /// sent to the server, which would otherwise reject the entire workflow task without saying
+    /// which command was at fault. Instead, the workflow task is failed (or the activity is
+    /// failed) with a [crate::errors::PayloadTooLarge] error naming the offending command. This
+    /// should be set at or below the server's blob size limit.
+    #[builder(setter(strip_option), default)]
+    pub payload_size_limit_bytes: Option<usize>,
+
+    /// If set, query responses whose payloads are at least this many bytes are never sent to the
+    /// server, which would otherwise reject them without saying which query was at fault. They are
+    /// handled according to [WorkerConfig::oversized_query_response_policy] instead. Sizes are
+    /// measured like for [WorkerConfig::payload_size_limit_bytes], which doesn't apply to queries.
+    #[builder(setter(strip_option), default)]
+    pub max_query_response_bytes: Option<usize>,
+
+    /// What is done with query responses exceeding [WorkerConfig::max_query_response_bytes]
+    #[builder(default)]
+    pub oversized_query_response_policy: OversizedQueryResponsePolicy,
+
+    /// If set, large payloads are stored in a blob store rather than sent to the server, which
+    /// only sees a small reference to them. See [PayloadOffloadConfig].
+    #[builder(setter(strip_option), default)]
+    pub payload_offload: Option<PayloadOffloadConfig>,
 }
 
 impl WorkerConfig {
     pub fn max_nonsticky_polls(&self) -> usize {
-        ((self.max_concurrent_wft_polls as f32 * self.nonsticky_to_sticky_poll_ratio) as usize)
+        self.max_concurrent_nonsticky_wft_polls
+            .unwrap_or(
+                (self.max_concurrent_wft_polls as f32 * self.nonsticky_to_sticky_poll_ratio)
+                    as usize,
+            )
             .max(1)
     }
     pub fn max_sticky_polls(&self) -> usize {
-        self.max_concurrent_wft_polls
-            .saturating_sub(self.max_nonsticky_polls())
+        self.max_concurrent_sticky_wft_polls
+            .unwrap_or_else(|| {
+                self.max_concurrent_wft_polls
+                    .saturating_sub(self.max_nonsticky_polls())
+            })
             .max(1)
     }
+    pub fn min_nonsticky_polls(&self) -> usize {
+        match self.min_concurrent_wft_polls {
+            Some(min) => ((min as f32 * self.nonsticky_to_sticky_poll_ratio) as usize)
+                .clamp(1, self.max_nonsticky_polls()),
+            None => self.max_nonsticky_polls(),
+        }
+    }
+    pub fn min_sticky_polls(&self) -> usize {
+        match self.min_concurrent_wft_polls {
+            Some(min) => min
+                .saturating_sub(self.min_nonsticky_polls())
+                .clamp(1, self.max_sticky_polls()),
+            None => self.max_sticky_polls(),
+        }
+    }
 }
 
 impl WorkerConfigBuilder {
     fn validate(&self) -> Result<(), String> {
+        if self.max_pending_activations == Some(0) {
+            return Err("`max_pending_activations` must be at least 1".to_owned());
+        }
+        if let Some(limits) = &self.max_outstanding_activities_per_type {
+            if limits.values().any(|&max| max == 0) {
+                return Err(
+                    "`max_outstanding_activities_per_type` limits must be at least 1".to_owned(),
+                );
+            }
+        }
+        if self.heartbeat_batch_flush_interval == Some(Some(Duration::ZERO)) {
+            return Err("`heartbeat_batch_flush_interval` must be greater than zero".to_owned());
+        }
+        if let Some(Some(session)) = &self.session_activity_queue {
+            if self.no_remote_activities == Some(true) {
+                return Err(
+                    "`session_activity_queue` cannot be used with `no_remote_activities`"
+                        .to_owned(),
+                );
+            }
+            if Some(&session.task_queue) == self.task_queue.as_ref() {
+                return Err(
+                    "`session_activity_queue` must differ from the worker's task queue".to_owned(),
+                );
+            }
+            if session.max_outstanding_activities == 0 || session.max_concurrent_polls == 0 {
+                return Err(
+                    "`session_activity_queue` must allow at least 1 outstanding activity and poll"
+                        .to_owned(),
+                );
+            }
+        }
+        if let Some(Some(backoff)) = &self.wft_failure_backoff {
+            if backoff.backoff_coefficient < 1.0 {
+                return Err("`wft_failure_backoff` coefficient must be at least 1".to_owned());
+            }
+            if backoff.initial_interval > backoff.max_interval {
+                return Err(
+                    "`wft_failure_backoff` initial interval cannot exceed its max interval"
+                        .to_owned(),
+                );
+            }
+        }
         if self.max_concurrent_wft_polls == Some(0) {
             return Err("`max_concurrent_wft_polls` must be at least 1".to_owned());
         }
+        if self.min_concurrent_wft_polls.flatten().unwrap_or(1)
+            > self.max_concurrent_wft_polls.unwrap_or(5)
+        {
+            return Err(
+                "`min_concurrent_wft_polls` cannot exceed `max_concurrent_wft_polls`".to_owned(),
+            );
+        }
+        if self.min_concurrent_at_polls.flatten().unwrap_or(1)
+            > self.max_concurrent_at_polls.unwrap_or(5)
+        {
+            return Err(
+                "`min_concurrent_at_polls` cannot exceed `max_concurrent_at_polls`".to_owned(),
+            );
+        }
+        if let Some(Some(sdk)) = &self.lang_sdk {
+            if sdk.name.is_empty() || sdk.version.is_empty() {
+                return Err("`lang_sdk` name and version cannot be empty".to_owned());
+            }
+        }
+        if let Some(Some(template)) = &self.sticky_queue_name_template {
+            if template.is_empty() {
+                return Err("`sticky_queue_name_template` cannot be empty".to_owned());
+            }
+        }
+        if let (Some(Some(warn)), Some(Some(limit))) = (
+            self.payload_size_warning_bytes,
+            self.payload_size_limit_bytes,
+        ) {
+            if warn > limit {
+                return Err(
+                    "`payload_size_warning_bytes` cannot exceed `payload_size_limit_bytes`"
+                        .to_owned(),
+                );
+            }
+        }
         if self.max_outstanding_workflow_tasks > self.max_cached_workflows {
             return Err(
                 "Maximum concurrent workflow tasks cannot exceed the maximum number of cached \
@@ -102,3 +396,414 @@ impl WorkerConfigBuilder {
         Ok(())
     }
 }
+
+/// Implementors can inject behavior around the lifecycle of activity tasks inside of Core, which
+/// applies no matter which language SDK is in use. Only activity tasks from the server are
+/// intercepted, not local activities.
+pub trait ActivityInterceptor: Send + Sync + Debug {
+    /// Called with every new activity task before it is returned to lang from
+    /// [crate::Worker::poll_activity_task]. The task may be modified (ex: to add header fields).
+    ///
+    /// Returning an error vetoes the dispatch. Lang never sees the task, and it is immediately
+    /// reported to the server as failed with the returned failure.
+    #[allow(clippy::result_large_err)]
+    fn on_dispatch(&self, _task: &mut ActivityTask) -> Result<(), Failure> {
+        Ok(())
+    }
+
+    /// Called whenever lang completes an activity task, before the result is reported to the
+    /// server. The result may be modified.
+    fn on_complete(
+        &self,
+        _task_token: &[u8],
+        _activity_type: &str,
+        _result: &mut activity_execution_result::Status,
+    ) {
+    }
+}
+
+/// Implementors can read and modify the headers core passes between lang and the server, so that
+/// context (ex: tenancy, auth, or tracing information) is propagated the same way no matter which
+/// language SDK is in use. Headers are never encoded with a [PayloadCodec].
+pub trait HeaderInterceptor: Send + Sync + Debug {
+    /// Called with the headers of every command lang issues which schedules an activity or local
+    /// activity, starts a child workflow, continues as new, or signals an external workflow,
+    /// before the command is processed. Context may be injected here.
+    fn on_outbound(&self, _headers: &mut HashMap<String, CorePayload>) {}
+
+    /// Called with the headers of every workflow start, signal, and query job, and of every
+    /// (local) activity start, before they are handed to lang. Context may be extracted or
+    /// modified here.
+    fn on_inbound(&self, _headers: &mut HashMap<String, CorePayload>) {}
+}
+
+/// Supplies worker tuning values which may change while the worker runs, ex: from a service which
+/// tunes a whole fleet of workers. See [WorkerConfig::dynamic_config].
+#[async_trait::async_trait]
+pub trait DynamicConfig: Send + Sync + Debug {
+    /// Returns the current values. This is called periodically, so it should be cheap.
+    fn current(&self) -> DynamicConfigValues;
+
+    /// Resolves once the values may have changed, so that changes can be applied without waiting
+    /// for the next periodic check. By default never resolves.
+    async fn changed(&self) {
+        futures::future::pending().await
+    }
+}
+
+/// Tuning values from a [DynamicConfig]. Unset values leave the worker's setting as it is, which
+/// is also the case for a value which becomes unset after having been set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DynamicConfigValues {
+    /// See [WorkerConfig::max_outstanding_workflow_tasks]
+    pub max_outstanding_workflow_tasks: Option<usize>,
+    /// See [WorkerConfig::max_outstanding_activities]
+    pub max_outstanding_activities: Option<usize>,
+    /// See [WorkerConfig::max_cached_workflows]. Shrinking the cache evicts the least recently
+    /// used runs. Caching can't be turned on or off this way, so this is ignored for workers
+    /// configured without a cache, and can't be zero.
+    pub max_cached_workflows: Option<usize>,
+    /// See [WorkerConfig::max_concurrent_wft_polls]. Can't exceed the configured value, since
+    /// pollers are created up front.
+    pub max_concurrent_wft_polls: Option<usize>,
+    /// See [WorkerConfig::max_concurrent_at_polls]. Can't exceed the configured value, since
+    /// pollers are created up front.
+    pub max_concurrent_at_polls: Option<usize>,
+    /// See [WorkerConfig::max_task_queue_activities_per_second]. Takes effect with the next
+    /// activity poll.
+    pub max_task_queue_activities_per_second: Option<f64>,
+}
+
+/// Transforms payloads as they travel between core and the server, ex: to compress or encrypt
+/// them. Search attributes and headers are never passed to codecs, since the server and
+/// interceptors need to be able to read them.
+///
+/// Every worker and client using a namespace must use compatible codecs, since each decodes what
+/// the others encoded.
+pub trait PayloadCodec: Send + Sync + Debug {
+    /// Called with every payload core is about to send to the server. Payloads which shouldn't be
+    /// encoded may be left as they are.
+    fn encode(&self, payload: &mut Payload);
+
+    /// Called with every payload core receives from the server. Must undo
+    /// [PayloadCodec::encode], and leave payloads it did not encode (ex: ones written by clients
+    /// without the codec) as they are.
+    fn decode(&self, payload: &mut Payload) -> Result<(), anyhow::Error>;
+}
+
+/// Controls how failures are represented on the server, ex: to keep messages and stack traces,
+/// which may contain sensitive data, out of plain text. Applies to activity failures, workflow
+/// failures, local activity results recorded in markers, and every other failure exchanged with
+/// the server.
+///
+/// Converters are handed each failure whole, and are responsible for its causes. They run before
+/// any [WorkerConfig::payload_codec] on the way to the server, so payloads they produce are
+/// encoded as well.
+pub trait FailureConverter: Send + Sync + Debug {
+    /// Called with every failure core is about to send to the server
+    fn encode_to_server(&self, failure: &mut Failure);
+
+    /// Called with every failure core receives from the server. Must undo
+    /// [FailureConverter::encode_to_server], and leave failures it did not convert as they are.
+    fn decode_from_server(&self, failure: &mut Failure) -> Result<(), anyhow::Error>;
+}
+
+/// Configures offloading payloads to a [BlobStore] (the "claim check" pattern), see
+/// [WorkerConfig::payload_offload].
+///
+/// Payloads at least [PayloadOffloadConfig::threshold_bytes] long (after any
+/// [WorkerConfig::payload_codec] is applied) are written to the store, and replaced with a
+/// reference payload before being sent to the server. References are resolved back into the
+/// original payloads before anything containing them is delivered to lang. Every worker using a
+/// namespace must be able to read the store.
+#[derive(Clone, Debug)]
+pub struct PayloadOffloadConfig {
+    /// Where offloaded payloads are written to and read from
+    pub store: Arc<dyn BlobStore>,
+    /// Payloads whose encoded size is below this are never offloaded
+    pub threshold_bytes: usize,
+}
+
+/// Stores payloads offloaded by [PayloadOffloadConfig]. Keys are derived from blob contents, so
+/// writing the same key twice always writes the same data.
+#[async_trait::async_trait]
+pub trait BlobStore: Send + Sync + Debug {
+    /// Store `data` under `key`
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), anyhow::Error>;
+
+    /// Fetch the data previously stored under `key`
+    async fn get(&self, key: &str) -> Result<Vec<u8>, anyhow::Error>;
+}
+
+/// Configures recording workflow runs for bug reproduction, see
+/// [WorkerConfig::activation_capture]. Captures can be replayed with core's
+/// `replay::replay_capture`.
+#[derive(Debug, Clone)]
+pub struct ActivationCaptureConfig {
+    /// Directory capture files are written to. Each captured run gets its own file, named
+    /// `{run_id}.capture`, which is appended to if the run is captured more than once.
+    pub directory: PathBuf,
+    /// Only runs of workflows with these ids are captured. Every run is captured if empty.
+    pub workflow_ids: HashSet<String>,
+}
+
+/// Identifies the lang SDK using a worker, see [WorkerConfig::lang_sdk]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LangSdkInfo {
+    /// The SDK's name, ex: `temporal-typescript`
+    pub name: String,
+    /// The SDK's version, ex: `1.4.0`
+    pub version: String,
+}
+
+impl LangSdkInfo {
+    /// The identity a worker using this SDK reports, given the one it would otherwise use
+    pub fn stamp_identity(&self, identity: &str) -> String {
+        format!("{} {}/{}", identity, self.name, self.version)
+    }
+}
+
+/// Configures the additional activity task queue a worker polls, see
+/// [WorkerConfig::session_activity_queue]
+#[derive(Debug, Clone)]
+pub struct SessionActivityQueueConfig {
+    /// The task queue to poll. It should be unique to this worker, ex: by including the host name,
+    /// so that no other worker receives its tasks.
+    pub task_queue: String,
+    /// The maximum number of activity tasks from this queue that will ever be given to this worker
+    /// concurrently. These don't count towards [WorkerConfig::max_outstanding_activities], so
+    /// activities of sessions can't be starved by other activities, or vice versa.
+    pub max_outstanding_activities: usize,
+    /// Maximum number of concurrent polls on this queue
+    pub max_concurrent_polls: usize,
+}
+
+/// Configures backing off retried workflow tasks, see [WorkerConfig::wft_failure_backoff]
+#[derive(Debug, Clone)]
+pub struct WftFailureBackoffConfig {
+    /// How long the second attempt of a workflow task is held back for
+    pub initial_interval: Duration,
+    /// How much longer each subsequent attempt is held back than the last
+    pub backoff_coefficient: f64,
+    /// The longest any attempt is held back for. Attempts are also never held back for more than
+    /// a fifth of the workflow task's timeout, which is already running while they wait.
+    pub max_interval: Duration,
+}
+
+impl WftFailureBackoffConfig {
+    /// How long the given attempt of a workflow task should be held back for. The first attempt
+    /// isn't a retry, so it never is.
+    pub fn backoff_for(&self, attempt: u32) -> Duration {
+        if attempt <= 1 {
+            return Duration::ZERO;
+        }
+        let factor = self.backoff_coefficient.powi(attempt as i32 - 2);
+        let secs = self.initial_interval.as_secs_f64() * factor;
+        // Checked here since `Duration::from_secs_f64` panics on values it can't represent
+        if !secs.is_finite() || secs >= self.max_interval.as_secs_f64() {
+            return self.max_interval;
+        }
+        Duration::from_secs_f64(secs.max(0.0))
+    }
+}
+
+impl Default for WftFailureBackoffConfig {
+    fn default() -> Self {
+        Self {
+            initial_interval: Duration::from_secs(1),
+            backoff_coefficient: 2.0,
+            max_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Configures resetting workflow runs which hit nondeterminism, see
+/// [WorkerConfig::reset_on_nondeterminism]
+#[derive(Debug, Clone)]
+pub struct NondeterminismResetConfig {
+    /// Which events recorded after the reset point are reapplied to the new run. If unspecified
+    /// the server reapplies signals.
+    pub reapply_type: ResetReapplyType,
+    /// If set, notified after every reset core performs
+    pub listener: Option<Arc<dyn NondeterminismResetListener>>,
+}
+
+impl Default for NondeterminismResetConfig {
+    fn default() -> Self {
+        Self {
+            reapply_type: ResetReapplyType::Unspecified,
+            listener: None,
+        }
+    }
+}
+
+/// What core does upon encountering a history event whose type it doesn't recognize, see
+/// [WorkerConfig::unknown_history_event_policy]. Core only issues commands it knows the events of,
+/// so such events are never the result of a command, and are ones which inform a workflow of
+/// something rather than ones it must match against the commands it issued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownHistoryEventPolicy {
+    /// Fail the workflow task. It is retried until it is picked up by a worker which understands
+    /// the event.
+    #[default]
+    FailTask,
+    /// Ignore the event and carry on processing history
+    Skip,
+    /// Ignore the event like [UnknownHistoryEventPolicy::Skip], but log a warning and count it in
+    /// the `workflow_unknown_history_event` metric
+    SkipWithWarning,
+}
+
+/// What core does with query responses which are too large, see
+/// [WorkerConfig::oversized_query_response_policy]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OversizedQueryResponsePolicy {
+    /// Fail the query with a [crate::errors::PayloadTooLarge] error naming it
+    #[default]
+    Fail,
+    /// Cut the end off the response's payload data so that it fits, and mark the payload by adding
+    /// [TRUNCATED_QUERY_RESPONSE_METADATA_KEY] to its metadata and changing its encoding to
+    /// [TRUNCATED_QUERY_RESPONSE_ENCODING]. Queries which failed, or whose responses can't be
+    /// made to fit, are failed as with [OversizedQueryResponsePolicy::Fail].
+    Truncate,
+}
+
+/// Metadata key added to the payload of a query response which was truncated, see
+/// [OversizedQueryResponsePolicy::Truncate]. Its value is the length in bytes of the original
+/// payload data.
+pub const TRUNCATED_QUERY_RESPONSE_METADATA_KEY: &str = "temporal-query-response-truncated-from";
+
+/// Encoding given to the payload of a query response which was truncated, see
+/// [OversizedQueryResponsePolicy::Truncate]. The data is the start of the payload's data as it
+/// was sent, after any payload codec, so it can't be decoded with its original encoding.
+pub const TRUNCATED_QUERY_RESPONSE_ENCODING: &str = "binary/truncated";
+
+/// Describes a reset core performed after a run hit nondeterminism
+#[derive(Debug, Clone)]
+pub struct NondeterminismReset {
+    /// The workflow's id
+    pub workflow_id: String,
+    /// The workflow's type
+    pub workflow_type: String,
+    /// The run which hit nondeterminism
+    pub run_id: String,
+    /// The run created by the reset
+    pub new_run_id: String,
+    /// The event the run was reset to
+    pub workflow_task_finish_event_id: i64,
+    /// Describes the nondeterminism which was detected
+    pub cause: String,
+}
+
+/// Implementors are told about resets core performs because of nondeterminism, so operators can
+/// be made aware of them. See [NondeterminismResetConfig::listener].
+pub trait NondeterminismResetListener: Send + Sync + Debug {
+    /// Called after a run has been successfully reset
+    fn on_reset(&self, reset: &NondeterminismReset);
+}
+
+/// Options for [crate::Worker::shutdown_with_deadline]. Construct with [ShutdownOptionsBuilder].
+#[derive(Debug, Clone, derive_builder::Builder)]
+#[builder(setter(into), build_fn(validate = "Self::validate"))]
+pub struct ShutdownOptions {
+    /// How long shutdown may take in total. Once it elapses, shutdown stops waiting for
+    /// outstanding work and returns.
+    pub deadline: Duration,
+    /// The fraction of `deadline` after which core stops waiting for outstanding activities to
+    /// finish on their own and issues cancels for them. Must be between 0 and 1. Defaults to half.
+    #[builder(default = "0.5")]
+    pub cancel_activities_after: f64,
+    /// How often progress is reported to `progress_listener`. Must be greater than zero.
+    #[builder(default = "Duration::from_secs(1)")]
+    pub progress_interval: Duration,
+    /// If set, told how much work remains as shutdown proceeds
+    #[builder(setter(strip_option), default)]
+    pub progress_listener: Option<Arc<dyn ShutdownProgressListener>>,
+}
+
+impl ShutdownOptionsBuilder {
+    fn validate(&self) -> Result<(), String> {
+        if let Some(fraction) = self.cancel_activities_after {
+            if !(0.0..=1.0).contains(&fraction) {
+                return Err("`cancel_activities_after` must be between 0 and 1".to_owned());
+            }
+        }
+        if self.progress_interval == Some(Duration::ZERO) {
+            return Err("`progress_interval` must be greater than zero".to_owned());
+        }
+        Ok(())
+    }
+}
+
+/// How far along a deadline-bounded shutdown is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownPhase {
+    /// Waiting for outstanding work to finish on its own
+    Draining,
+    /// Outstanding activities have been issued cancels, and are being waited on
+    CancellingActivities,
+    /// All outstanding work finished before the deadline
+    Complete,
+    /// The deadline elapsed while work was still outstanding. That work is abandoned.
+    Aborted,
+}
+
+/// Describes the work a shutting down worker is still waiting on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShutdownProgress {
+    /// What shutdown is currently doing
+    pub phase: ShutdownPhase,
+    /// Workflow tasks which have not yet been completed
+    pub outstanding_workflow_tasks: usize,
+    /// Activity tasks lang has not yet completed
+    pub outstanding_activities: usize,
+    /// Local activities which have not yet been resolved
+    pub outstanding_local_activities: usize,
+}
+
+/// Implementors are told how shutdown is progressing. See
+/// [ShutdownOptions::progress_listener].
+pub trait ShutdownProgressListener: Send + Sync + Debug {
+    /// Called every [ShutdownOptions::progress_interval], whenever the phase changes, and once
+    /// more when shutdown finishes
+    fn on_progress(&self, progress: &ShutdownProgress);
+}
+
+/// Whether a worker is polling for new work
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PollingState {
+    /// The worker is polling for new tasks
+    Polling,
+    /// The worker has stopped polling because it is shutting down
+    Stopped,
+    /// Polling is paused while the worker restarts, see [crate::Worker::soft_restart]
+    Restarting,
+}
+
+/// A snapshot of a worker's state, suitable for readiness/liveness probes. See
+/// [crate::Worker::status].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerStatus {
+    /// Whether the worker is polling for new work
+    pub polling: PollingState,
+    /// Workflow tasks which have been received but not yet completed
+    pub outstanding_workflow_tasks: usize,
+    /// Activity tasks issued to lang which have not yet been completed
+    pub outstanding_activities: usize,
+    /// Local activities which have not yet been resolved
+    pub outstanding_local_activities: usize,
+    /// How many more workflow tasks may be outstanding at once
+    pub available_workflow_task_slots: usize,
+    /// How many more activity tasks may be outstanding at once. `None` if the worker does not
+    /// poll for activities.
+    pub available_activity_slots: Option<usize>,
+    /// Workflow runs currently held in the sticky cache
+    pub cached_workflows: usize,
+    /// The most workflow runs the sticky cache will hold
+    pub max_cached_workflows: usize,
+    /// When a poll (of either workflow or activity tasks) last succeeded, including polls which
+    /// timed out without work. `None` if no poll has succeeded yet.
+    pub last_successful_poll: Option<SystemTime>,
+    /// How far along shutdown is. `None` if shutdown has not been started.
+    pub shutdown_phase: Option<ShutdownPhase>,
+}
@@ -0,0 +1,156 @@
+//! Stands in for a lang SDK when running synthetic workloads
+
+use super::Workload;
+use futures::future::join_all;
+use parking_lot::Mutex;
+use std::{collections::HashMap, time::Duration};
+use temporal_sdk_core::{
+    api::{
+        errors::{PollActivityError, PollWfError},
+        Worker as WorkerTrait,
+    },
+    protos::coresdk::{
+        activity_result::ActivityExecutionResult,
+        activity_task::activity_task,
+        workflow_activation::{workflow_activation_job, WorkflowActivation},
+        workflow_commands::{
+            query_result, workflow_command, CompleteWorkflowExecution, QueryResult, QuerySuccess,
+            ScheduleLocalActivity, StartTimer,
+        },
+        workflow_completion::WorkflowActivationCompletion,
+        ActivityTaskCompletion, AsJsonPayloadExt,
+    },
+    Worker,
+};
+
+/// Runs the workload's workflows, which take their steps in order, and completes activities as
+/// soon as they start
+pub(super) struct SyntheticLang<'a> {
+    worker: &'a Worker,
+    workload: &'a Workload,
+    /// Run id -> number of steps the workflow has taken
+    steps_taken: Mutex<HashMap<String, usize>>,
+}
+
+impl<'a> SyntheticLang<'a> {
+    pub(super) fn new(worker: &'a Worker, workload: &'a Workload) -> Self {
+        Self {
+            worker,
+            workload,
+            steps_taken: Default::default(),
+        }
+    }
+
+    /// Process activations and activity tasks until the worker shuts down
+    pub(super) async fn run(&self) {
+        let wf_pollers =
+            join_all((0..self.workload.lang_concurrency).map(|_| self.run_workflows()));
+        let act_pollers =
+            join_all((0..self.workload.lang_concurrency).map(|_| self.run_activities()));
+        tokio::join!(wf_pollers, act_pollers);
+    }
+
+    async fn run_workflows(&self) {
+        loop {
+            let act = match self.worker.poll_workflow_activation().await {
+                Ok(act) => act,
+                Err(PollWfError::ShutDown) => return,
+                Err(e) => panic!("Polling for activation failed: {:?}", e),
+            };
+            let completion = self.complete(act);
+            self.worker
+                .complete_workflow_activation(completion)
+                .await
+                .unwrap();
+        }
+    }
+
+    async fn run_activities(&self) {
+        loop {
+            let task = match self.worker.poll_activity_task().await {
+                Ok(task) => task,
+                Err(PollActivityError::ShutDown) => return,
+                Err(e) => panic!("Polling for activity task failed: {:?}", e),
+            };
+            if let Some(activity_task::Variant::Start(_)) = task.variant {
+                self.worker
+                    .complete_activity_task(ActivityTaskCompletion {
+                        task_token: task.task_token,
+                        result: Some(ActivityExecutionResult::ok(
+                            "done".as_json_payload().unwrap(),
+                        )),
+                    })
+                    .await
+                    .unwrap();
+            }
+        }
+    }
+
+    fn complete(&self, act: WorkflowActivation) -> WorkflowActivationCompletion {
+        let mut commands = vec![];
+        let mut resolved_step = false;
+        for job in act.jobs {
+            match job.variant {
+                Some(workflow_activation_job::Variant::StartWorkflow(_)) => {
+                    // Replaying after an eviction starts from scratch
+                    self.steps_taken.lock().insert(act.run_id.clone(), 0);
+                    resolved_step = true;
+                }
+                Some(workflow_activation_job::Variant::FireTimer(_))
+                | Some(workflow_activation_job::Variant::ResolveActivity(_)) => {
+                    resolved_step = true;
+                }
+                Some(workflow_activation_job::Variant::QueryWorkflow(q)) => {
+                    commands.push(
+                        QueryResult {
+                            query_id: q.query_id,
+                            variant: Some(query_result::Variant::Succeeded(QuerySuccess {
+                                response: Some("running".as_json_payload().unwrap()),
+                            })),
+                        }
+                        .into(),
+                    );
+                }
+                Some(workflow_activation_job::Variant::RemoveFromCache(_)) => {
+                    self.steps_taken.lock().remove(&act.run_id);
+                }
+                _ => {}
+            }
+        }
+        if resolved_step {
+            let step = {
+                let mut steps_taken = self.steps_taken.lock();
+                let taken = steps_taken.entry(act.run_id.clone()).or_default();
+                *taken += 1;
+                *taken - 1
+            };
+            commands.push(self.command_for_step(step));
+        }
+        WorkflowActivationCompletion::from_cmds(act.run_id, commands)
+    }
+
+    /// The command a workflow issues to take its `step`th (starting at 0) step. Must agree with
+    /// the history built by [super::server::WorkloadHistory].
+    fn command_for_step(&self, step: usize) -> workflow_command::Variant {
+        let local_activities = self.workload.local_activities;
+        if step < local_activities {
+            let seq = step as u32 + 1;
+            ScheduleLocalActivity {
+                seq,
+                activity_id: seq.to_string(),
+                activity_type: "synthetic".to_string(),
+                start_to_close_timeout: Some(Duration::from_secs(60).into()),
+                ..Default::default()
+            }
+            .into()
+        } else if step < local_activities + self.workload.timers {
+            StartTimer {
+                seq: (step - local_activities) as u32 + 1,
+                start_to_fire_timeout: Some(Duration::from_secs(1).into()),
+            }
+            .into()
+        } else {
+            CompleteWorkflowExecution { result: None }.into()
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Synthetic workloads for benchmarking core's task management, without a server or lang SDK.
+//!
+//! A [Workload] describes the shape of the load. It is served by [server::FakeServer], which hands
+//! out workflow and activity tasks as fast as the worker asks for them, and driven by
+//! [lang::SyntheticLang], which completes every activation and activity task right away. Hence
+//! nearly all the time spent running a workload is spent in core.
+
+mod lang;
+mod server;
+
+use lang::SyntheticLang;
+use server::{FakeServer, WorkloadHistory};
+use std::sync::Arc;
+use temporal_sdk_core::{
+    api::{worker::WorkerConfigBuilder, Worker as WorkerTrait},
+    init_worker_with_client,
+};
+
+const TASK_QUEUE: &str = "synthetic";
+
+/// The shape of a synthetic load. Every workflow runs the same steps: its local activities one
+/// after another, then its timers one after another, then it completes.
+#[derive(Debug, Clone)]
+pub struct Workload {
+    /// Number of workflows run
+    pub workflows: usize,
+    /// Timers each workflow waits on. Each one adds a workflow task, and grows the history which
+    /// must be replayed whenever the workflow isn't cached.
+    pub timers: usize,
+    /// Local activities each workflow runs, all during its first workflow task
+    pub local_activities: usize,
+    /// Activity tasks handed out by the server, independently of the workflows
+    pub activities: usize,
+    /// If set, every time this many workflow tasks have been completed, the workflow which
+    /// completed the last one is sent a legacy query before its next workflow task
+    pub query_every: Option<usize>,
+    /// Number of workflow and activity tasks lang processes concurrently (each)
+    pub lang_concurrency: usize,
+    /// Used as [WorkerConfig::max_cached_workflows]. Zero means every workflow task replays the
+    /// workflow's entire history.
+    ///
+    /// [WorkerConfig::max_cached_workflows]: temporal_sdk_core::api::worker::WorkerConfig
+    pub max_cached_workflows: usize,
+}
+
+impl Workload {
+    /// Workflows which each wait on `timers` timers, with everything else off
+    pub fn timers(workflows: usize, timers: usize) -> Self {
+        Self {
+            workflows,
+            timers,
+            local_activities: 0,
+            activities: 0,
+            query_every: None,
+            lang_concurrency: 10,
+            max_cached_workflows: workflows,
+        }
+    }
+
+    /// Number of workflow tasks the workload completes, not counting legacy queries
+    pub fn workflow_tasks(&self) -> usize {
+        self.workflows * (1 + self.timers)
+    }
+
+    /// Total number of workflow tasks, local activities, and activity tasks which are processed
+    pub fn tasks(&self) -> usize {
+        self.workflow_tasks() + self.workflows * self.local_activities + self.activities
+    }
+
+    /// Build everything needed to run this workload which can be reused between runs
+    pub fn prepare(&self) -> PreparedWorkload {
+        PreparedWorkload {
+            workload: self.clone(),
+            history: Arc::new(WorkloadHistory::new(self)),
+        }
+    }
+}
+
+/// A [Workload] whose workflow history has been built, ready to be run any number of times
+pub struct PreparedWorkload {
+    workload: Workload,
+    history: Arc<WorkloadHistory>,
+}
+
+impl PreparedWorkload {
+    /// Run the workload against a new worker, returning once it has been entirely processed and
+    /// the worker has shut down
+    pub async fn run(&self) {
+        let server = FakeServer::new(&self.workload, self.history.clone());
+        let mut config = WorkerConfigBuilder::default();
+        config
+            .namespace("default")
+            .task_queue(TASK_QUEUE)
+            .max_cached_workflows(self.workload.max_cached_workflows)
+            .no_remote_activities(self.workload.activities == 0);
+        if self.workload.max_cached_workflows > 0 {
+            config.max_outstanding_workflow_tasks(self.workload.max_cached_workflows.min(100));
+        }
+        let worker = init_worker_with_client(config.build().unwrap(), server.clone());
+        let lang = SyntheticLang::new(&worker, &self.workload);
+
+        tokio::join!(lang.run(), async {
+            server.finished().await;
+            worker.shutdown().await;
+        });
+    }
+}
@@ -0,0 +1,346 @@
+//! A fake server for synthetic workloads. It implements [WorkerClient] directly, so no networking
+//! is involved.
+
+use super::Workload;
+use parking_lot::Mutex;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use temporal_client::WorkflowTaskCompletion;
+use temporal_sdk_core::{
+    protos::{
+        coresdk::{workflow_commands::QueryResult, AsJsonPayloadExt},
+        temporal::api::{
+            common::v1::{ActivityType, Payloads, WorkflowExecution},
+            enums::v1::{EventType, ResetReapplyType, WorkflowTaskFailedCause},
+            failure::v1::Failure,
+            query::v1::WorkflowQuery,
+            workflowservice::v1::*,
+        },
+        SerializedHistoryPage, TestHistoryBuilder,
+    },
+    TaskToken, WorkerClient,
+};
+use tokio::sync::{mpsc, Mutex as AsyncMutex};
+use tokio_util::sync::CancellationToken;
+
+type Result<T, E = tonic::Status> = std::result::Result<T, E>;
+
+/// The history every workflow in a workload shares, as the poll response for each of its workflow
+/// tasks. Only the workflow's id and run id differ between workflows.
+pub(super) struct WorkloadHistory {
+    wft_responses: Vec<PollWorkflowTaskQueueResponse>,
+}
+
+impl WorkloadHistory {
+    pub(super) fn new(workload: &Workload) -> Self {
+        let mut t = TestHistoryBuilder::default();
+        t.add_by_type(EventType::WorkflowExecutionStarted);
+        t.add_full_wf_task();
+        for seq in 1..=workload.local_activities as u32 {
+            t.add_local_activity_result_marker(
+                seq,
+                &seq.to_string(),
+                "done".as_json_payload().unwrap(),
+            );
+        }
+        for seq in 1..=workload.timers {
+            let timer_started_event_id = t.add_get_event_id(EventType::TimerStarted, None);
+            t.add_timer_fired(timer_started_event_id, seq.to_string());
+            t.add_full_wf_task();
+        }
+        t.add_workflow_execution_completed();
+
+        let wft_responses = (1..=workload.timers + 1)
+            .map(|task_num| {
+                t.get_history_info(task_num)
+                    .unwrap()
+                    .as_poll_wft_response(super::TASK_QUEUE)
+            })
+            .collect();
+        Self { wft_responses }
+    }
+}
+
+/// Workflow tasks the server can hand out
+#[derive(Debug, Clone, Copy)]
+enum Wft {
+    /// The workflow's `task_num`th (starting at 1) workflow task
+    Task { wf: usize, task_num: usize },
+    /// A legacy query, sent after the workflow's `task_num`th workflow task was completed
+    LegacyQuery { wf: usize, task_num: usize },
+}
+
+/// Hands out the tasks of a [Workload] as fast as they are polled for. Workflow timers fire as
+/// soon as they are started.
+#[derive(Clone)]
+pub(super) struct FakeServer {
+    inner: Arc<ServerInner>,
+}
+
+struct ServerInner {
+    query_every: Option<usize>,
+    history: Arc<WorkloadHistory>,
+    run_ids: Vec<String>,
+    wft_tx: mpsc::UnboundedSender<Wft>,
+    wft_rx: AsyncMutex<mpsc::UnboundedReceiver<Wft>>,
+    act_rx: AsyncMutex<mpsc::UnboundedReceiver<PollActivityTaskQueueResponse>>,
+    state: Mutex<ServerState>,
+    /// Cancelled once every workflow and activity has completed
+    finished: CancellationToken,
+}
+
+struct ServerState {
+    next_token: u64,
+    outstanding_wfts: HashMap<Vec<u8>, Wft>,
+    wfts_completed: usize,
+    workflows_remaining: usize,
+    activities_remaining: usize,
+}
+
+impl FakeServer {
+    pub(super) fn new(workload: &Workload, history: Arc<WorkloadHistory>) -> Self {
+        let (wft_tx, wft_rx) = mpsc::unbounded_channel();
+        for wf in 0..workload.workflows {
+            wft_tx.send(Wft::Task { wf, task_num: 1 }).unwrap();
+        }
+        let (act_tx, act_rx) = mpsc::unbounded_channel();
+        for i in 0..workload.activities {
+            act_tx
+                .send(PollActivityTaskQueueResponse {
+                    task_token: format!("activity-{}", i).into_bytes(),
+                    activity_id: i.to_string(),
+                    activity_type: Some(ActivityType {
+                        name: "synthetic".to_string(),
+                    }),
+                    workflow_execution: Some(WorkflowExecution {
+                        workflow_id: "synthetic-activities".to_string(),
+                        run_id: "synthetic-activities".to_string(),
+                    }),
+                    start_to_close_timeout: Some(Duration::from_secs(60).into()),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+
+        let server = Self {
+            inner: Arc::new(ServerInner {
+                query_every: workload.query_every,
+                history,
+                run_ids: (0..workload.workflows)
+                    .map(|_| uuid::Uuid::new_v4().to_string())
+                    .collect(),
+                wft_tx,
+                wft_rx: AsyncMutex::new(wft_rx),
+                act_rx: AsyncMutex::new(act_rx),
+                state: Mutex::new(ServerState {
+                    next_token: 0,
+                    outstanding_wfts: HashMap::new(),
+                    wfts_completed: 0,
+                    workflows_remaining: workload.workflows,
+                    activities_remaining: workload.activities,
+                }),
+                finished: CancellationToken::new(),
+            }),
+        };
+        server.inner.finish_if_done(&server.inner.state.lock());
+        server
+    }
+
+    /// Resolves once every workflow and activity in the workload has completed
+    pub(super) async fn finished(&self) {
+        self.inner.finished.cancelled().await
+    }
+}
+
+impl ServerInner {
+    fn wft_response(&self, wft: Wft) -> PollWorkflowTaskQueueResponse {
+        let (wf, task_num, query) = match wft {
+            Wft::Task { wf, task_num } => (wf, task_num, None),
+            Wft::LegacyQuery { wf, task_num } => (
+                wf,
+                task_num,
+                Some(WorkflowQuery {
+                    query_type: "status".to_string(),
+                    ..Default::default()
+                }),
+            ),
+        };
+        let mut resp = self.history.wft_responses[task_num - 1].clone();
+        resp.workflow_execution = Some(WorkflowExecution {
+            workflow_id: format!("synthetic-{}", wf),
+            run_id: self.run_ids[wf].clone(),
+        });
+        resp.query = query;
+        let mut state = self.state.lock();
+        resp.task_token = state.next_token.to_be_bytes().to_vec();
+        state.next_token += 1;
+        state.outstanding_wfts.insert(resp.task_token.clone(), wft);
+        resp
+    }
+
+    fn take_outstanding(&self, task_token: &TaskToken) -> Wft {
+        self.state
+            .lock()
+            .outstanding_wfts
+            .remove(&task_token.0)
+            .expect("Completed workflow task must have been handed out")
+    }
+
+    fn finish_if_done(&self, state: &ServerState) {
+        if state.workflows_remaining == 0 && state.activities_remaining == 0 {
+            self.finished.cancel();
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl WorkerClient for FakeServer {
+    async fn poll_workflow_task(
+        &self,
+        _task_queue: String,
+        _is_sticky: bool,
+    ) -> Result<PollWorkflowTaskQueueResponse> {
+        let wft = tokio::select! {
+            wft = async { self.inner.wft_rx.lock().await.recv().await } => wft,
+            // Like a long poll timing out
+            _ = self.inner.finished.cancelled() => None,
+        };
+        Ok(wft
+            .map(|wft| self.inner.wft_response(wft))
+            .unwrap_or_default())
+    }
+
+    async fn poll_activity_task(
+        &self,
+        _task_queue: String,
+        _max_tasks_per_sec: Option<f64>,
+    ) -> Result<PollActivityTaskQueueResponse> {
+        let task = tokio::select! {
+            task = async { self.inner.act_rx.lock().await.recv().await } => task,
+            _ = self.inner.finished.cancelled() => None,
+        };
+        Ok(task.unwrap_or_default())
+    }
+
+    async fn complete_workflow_task(
+        &self,
+        request: WorkflowTaskCompletion,
+    ) -> Result<RespondWorkflowTaskCompletedResponse> {
+        let (wf, task_num) = match self.inner.take_outstanding(&request.task_token) {
+            Wft::Task { wf, task_num } => (wf, task_num),
+            Wft::LegacyQuery { .. } => panic!("Legacy query was completed as a workflow task"),
+        };
+        let mut state = self.inner.state.lock();
+        state.wfts_completed += 1;
+        if task_num == self.inner.history.wft_responses.len() {
+            state.workflows_remaining -= 1;
+            self.inner.finish_if_done(&state);
+        } else {
+            let next = match self.inner.query_every {
+                Some(n) if state.wfts_completed.is_multiple_of(n) => {
+                    Wft::LegacyQuery { wf, task_num }
+                }
+                _ => Wft::Task {
+                    wf,
+                    task_num: task_num + 1,
+                },
+            };
+            self.inner.wft_tx.send(next).unwrap();
+        }
+        Ok(Default::default())
+    }
+
+    async fn complete_activity_task(
+        &self,
+        _task_token: TaskToken,
+        _result: Option<Payloads>,
+    ) -> Result<RespondActivityTaskCompletedResponse> {
+        let mut state = self.inner.state.lock();
+        state.activities_remaining -= 1;
+        self.inner.finish_if_done(&state);
+        Ok(Default::default())
+    }
+
+    async fn record_activity_heartbeat(
+        &self,
+        _task_token: TaskToken,
+        _details: Option<Payloads>,
+    ) -> Result<RecordActivityTaskHeartbeatResponse> {
+        Ok(Default::default())
+    }
+
+    async fn cancel_activity_task(
+        &self,
+        _task_token: TaskToken,
+        _details: Option<Payloads>,
+    ) -> Result<RespondActivityTaskCanceledResponse> {
+        panic!("Synthetic activities are never cancelled")
+    }
+
+    async fn fail_activity_task(
+        &self,
+        _task_token: TaskToken,
+        failure: Option<Failure>,
+    ) -> Result<RespondActivityTaskFailedResponse> {
+        panic!("Synthetic activity failed: {:?}", failure)
+    }
+
+    async fn fail_workflow_task(
+        &self,
+        _task_token: TaskToken,
+        cause: WorkflowTaskFailedCause,
+        failure: Option<Failure>,
+    ) -> Result<RespondWorkflowTaskFailedResponse> {
+        panic!(
+            "Synthetic workflow task failed ({:?}): {:?}",
+            cause, failure
+        )
+    }
+
+    async fn get_workflow_execution_history(
+        &self,
+        _workflow_id: String,
+        _run_id: Option<String>,
+        _page_token: Vec<u8>,
+    ) -> Result<SerializedHistoryPage> {
+        panic!("Synthetic workflow tasks always include their full history")
+    }
+
+    async fn respond_legacy_query(
+        &self,
+        task_token: TaskToken,
+        _query_result: QueryResult,
+    ) -> Result<RespondQueryTaskCompletedResponse> {
+        let (wf, task_num) = match self.inner.take_outstanding(&task_token) {
+            Wft::LegacyQuery { wf, task_num } => (wf, task_num),
+            Wft::Task { .. } => panic!("Workflow task was answered as a legacy query"),
+        };
+        self.inner
+            .wft_tx
+            .send(Wft::Task {
+                wf,
+                task_num: task_num + 1,
+            })
+            .unwrap();
+        Ok(Default::default())
+    }
+
+    async fn reset_workflow_execution(
+        &self,
+        _workflow_id: String,
+        _run_id: String,
+        _workflow_task_finish_event_id: i64,
+        _reason: String,
+        _reset_reapply_type: ResetReapplyType,
+    ) -> Result<ResetWorkflowExecutionResponse> {
+        panic!("Synthetic workflows are never reset")
+    }
+
+    async fn list_workflow_executions(
+        &self,
+        _page_size: i32,
+        _next_page_token: Vec<u8>,
+        _query: String,
+    ) -> Result<ListWorkflowExecutionsResponse> {
+        panic!("Synthetic workers never list workflows")
+    }
+}
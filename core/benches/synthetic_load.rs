@@ -0,0 +1,60 @@
+//! Measures the throughput of core's task management under synthetic workloads. See the
+//! [synthetic] module for how they are generated.
+
+mod synthetic;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use synthetic::Workload;
+use temporal_sdk_core::{telemetry_init, TelemetryOptionsBuilder};
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let tokio_runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+    telemetry_init(&TelemetryOptionsBuilder::default().build().unwrap()).unwrap();
+    let _g = tokio_runtime.enter();
+
+    let workloads = [
+        ("Cached timers", Workload::timers(100, 10)),
+        (
+            "Uncached timers",
+            Workload {
+                max_cached_workflows: 0,
+                ..Workload::timers(20, 10)
+            },
+        ),
+        (
+            "Local activities",
+            Workload {
+                local_activities: 5,
+                ..Workload::timers(100, 1)
+            },
+        ),
+        (
+            "Legacy queries",
+            Workload {
+                query_every: Some(2),
+                ..Workload::timers(100, 5)
+            },
+        ),
+        (
+            "Activities",
+            Workload {
+                activities: 1000,
+                ..Workload::timers(0, 0)
+            },
+        ),
+    ];
+
+    let mut group = c.benchmark_group("Synthetic load");
+    for (name, workload) in workloads {
+        let prepared = workload.prepare();
+        group.throughput(Throughput::Elements(workload.tasks() as u64));
+        group.bench_function(name, |b| b.iter(|| tokio_runtime.block_on(prepared.run())));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
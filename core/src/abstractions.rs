@@ -1,13 +1,41 @@
 //! This module contains very generic helpers that can be used codebase-wide
 
 use crate::MetricsContext;
-use tokio::sync::{AcquireError, Semaphore, SemaphorePermit};
+use parking_lot::Mutex;
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use tokio::sync::{oneshot, AcquireError, Semaphore};
+
+/// The order in which waiters for a [MeteredSemaphore] permit are served
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum AcquirePriority {
+    /// Served in the order permits were requested
+    #[default]
+    Normal,
+    /// Served before any normal waiter, in the order permits were requested among themselves.
+    /// For work which shouldn't queue up behind new work, ex: slots lang explicitly asked for.
+    High,
+}
 
 /// Wraps a [Semaphore] with a function call that is fed the available permits any time a permit is
-/// acquired or restored through the provided methods
+/// acquired or restored through the provided methods.
+///
+/// Waiters are served fairly: those acquiring with the same [AcquirePriority] get permits in the
+/// order they asked for them, and all high priority waiters are served before normal ones.
 pub(crate) struct MeteredSemaphore {
     pub sem: Semaphore,
-    max_permits: usize,
+    /// The current maximum number of permits. Can be changed at runtime with
+    /// [MeteredSemaphore::resize].
+    max_permits: AtomicUsize,
+    /// When the semaphore is shrunk while permits are held, we can't take those permits back
+    /// right away. Instead, this many returned permits will be swallowed rather than restored.
+    owed_permits: AtomicUsize,
+    /// Waiters acquiring with [AcquirePriority::High]. Permits being restored are handed directly
+    /// to these, in order, before being made available to anyone else. Permits are only added to
+    /// [MeteredSemaphore::sem] while holding this lock, so a high priority waiter can't miss one.
+    priority_waiters: Mutex<VecDeque<oneshot::Sender<()>>>,
     metrics_ctx: MetricsContext,
     record_fn: fn(&MetricsContext, usize),
 }
@@ -20,26 +48,261 @@ impl MeteredSemaphore {
     ) -> Self {
         Self {
             sem: Semaphore::new(inital_permits),
-            max_permits: inital_permits,
+            max_permits: AtomicUsize::new(inital_permits),
+            owed_permits: AtomicUsize::new(0),
+            priority_waiters: Default::default(),
             metrics_ctx,
             record_fn,
         }
     }
 
-    pub async fn acquire(&self) -> Result<SemaphorePermit<'_>, AcquireError> {
-        let res = self.sem.acquire().await;
+    pub async fn acquire(&self) -> Result<MeteredPermit<'_>, AcquireError> {
+        self.acquire_with_priority(AcquirePriority::Normal).await
+    }
+
+    pub async fn acquire_with_priority(
+        &self,
+        priority: AcquirePriority,
+    ) -> Result<MeteredPermit<'_>, AcquireError> {
+        match priority {
+            AcquirePriority::Normal => self.sem.acquire().await?.forget(),
+            AcquirePriority::High => {
+                let rx = {
+                    let mut waiters = self.priority_waiters.lock();
+                    match self.sem.try_acquire() {
+                        Ok(p) => {
+                            p.forget();
+                            None
+                        }
+                        Err(_) => {
+                            let (tx, rx) = oneshot::channel();
+                            waiters.push_back(tx);
+                            Some(rx)
+                        }
+                    }
+                };
+                if let Some(rx) = rx {
+                    let mut waiter = PriorityWaiter {
+                        sem: self,
+                        rx,
+                        received: false,
+                    };
+                    (&mut waiter.rx)
+                        .await
+                        .expect("Senders are only dropped after sending");
+                    waiter.received = true;
+                }
+            }
+        }
         (self.record_fn)(&self.metrics_ctx, self.sem.available_permits());
-        res
+        Ok(MeteredPermit { sem: self })
     }
 
-    /// Adds just one permit. Will not add if already at the initial/max capacity.
+    /// Adds just one permit. Will not add if already at the current max capacity, and will swallow
+    /// the permit instead if the semaphore was shrunk while it was held.
     pub fn add_permit(&self) {
-        if self.sem.available_permits() < self.max_permits {
-            self.sem.add_permits(1);
+        if self
+            .owed_permits
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |o| o.checked_sub(1))
+            .is_ok()
+        {
+            return;
+        }
+        if self.sem.available_permits() < self.max_permits.load(Ordering::Acquire) {
+            self.restore_permits(1);
             (self.record_fn)(&self.metrics_ctx, self.sem.available_permits());
         } else if cfg!(debug_assertions) {
             // Panic only during debug mode if this happens
             panic!("Tried to add permit to a semaphore that already was at capacity!");
         }
     }
+
+    /// Change the maximum number of permits. Growing makes new permits available immediately.
+    /// Shrinking removes as many currently available permits as it can, and the remainder are
+    /// removed as outstanding permits are returned via [MeteredSemaphore::add_permit].
+    pub fn resize(&self, new_max: usize) {
+        let old_max = self.max_permits.swap(new_max, Ordering::AcqRel);
+        if new_max > old_max {
+            let mut to_add = new_max - old_max;
+            // Any permits we were still owed from a previous shrink cancel out first
+            let _ = self
+                .owed_permits
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |o| {
+                    let cancelled = o.min(to_add);
+                    to_add = new_max - old_max - cancelled;
+                    Some(o - cancelled)
+                });
+            self.restore_permits(to_add);
+        } else {
+            let mut to_remove = old_max - new_max;
+            while to_remove > 0 {
+                match self.sem.try_acquire() {
+                    Ok(p) => {
+                        p.forget();
+                        to_remove -= 1;
+                    }
+                    Err(_) => break,
+                }
+            }
+            self.owed_permits.fetch_add(to_remove, Ordering::AcqRel);
+        }
+        (self.record_fn)(&self.metrics_ctx, self.sem.available_permits());
+    }
+
+    /// Hand permits to high priority waiters first, and make the rest available to anyone
+    fn restore_permits(&self, mut count: usize) {
+        let mut waiters = self.priority_waiters.lock();
+        while count > 0 {
+            match waiters.pop_front() {
+                // If the waiter gave up, the permit goes to the next one
+                Some(tx) => {
+                    if tx.send(()).is_ok() {
+                        count -= 1;
+                    }
+                }
+                None => break,
+            }
+        }
+        self.sem.add_permits(count);
+    }
+}
+
+/// A permit acquired from a [MeteredSemaphore]. Dropping it returns the permit, while
+/// [MeteredPermit::forget] keeps it taken until [MeteredSemaphore::add_permit] is called.
+#[must_use]
+pub(crate) struct MeteredPermit<'a> {
+    sem: &'a MeteredSemaphore,
+}
+
+impl MeteredPermit<'_> {
+    pub fn forget(self) {
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for MeteredPermit<'_> {
+    fn drop(&mut self) {
+        self.sem.add_permit();
+    }
+}
+
+/// Returns the permit if a high priority waiter gives up after one was already handed to it
+struct PriorityWaiter<'a> {
+    sem: &'a MeteredSemaphore,
+    rx: oneshot::Receiver<()>,
+    received: bool,
+}
+
+impl Drop for PriorityWaiter<'_> {
+    fn drop(&mut self) {
+        if !self.received {
+            self.rx.close();
+            if self.rx.try_recv().is_ok() {
+                self.sem.add_permit();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_sem(permits: usize) -> MeteredSemaphore {
+        MeteredSemaphore::new(permits, MetricsContext::default(), |_, _| {})
+    }
+
+    #[tokio::test]
+    async fn grow_adds_permits_immediately() {
+        let sem = test_sem(1);
+        sem.acquire().await.unwrap().forget();
+        assert_eq!(sem.sem.available_permits(), 0);
+        sem.resize(3);
+        assert_eq!(sem.sem.available_permits(), 2);
+        sem.add_permit();
+        assert_eq!(sem.sem.available_permits(), 3);
+    }
+
+    #[tokio::test]
+    async fn shrink_waits_for_outstanding_permits() {
+        let sem = test_sem(3);
+        sem.acquire().await.unwrap().forget();
+        sem.acquire().await.unwrap().forget();
+        sem.resize(1);
+        // The one available permit is taken right away, one more is owed
+        assert_eq!(sem.sem.available_permits(), 0);
+        sem.add_permit();
+        assert_eq!(sem.sem.available_permits(), 0);
+        sem.add_permit();
+        assert_eq!(sem.sem.available_permits(), 1);
+    }
+
+    #[tokio::test]
+    async fn grow_after_shrink_cancels_owed_permits() {
+        let sem = test_sem(2);
+        sem.acquire().await.unwrap().forget();
+        sem.acquire().await.unwrap().forget();
+        sem.resize(0);
+        sem.resize(2);
+        assert_eq!(sem.sem.available_permits(), 0);
+        sem.add_permit();
+        sem.add_permit();
+        assert_eq!(sem.sem.available_permits(), 2);
+    }
+
+    #[tokio::test]
+    async fn high_priority_waiters_served_first() {
+        let sem = test_sem(1);
+        sem.acquire().await.unwrap().forget();
+        let mut normal = Box::pin(sem.acquire());
+        assert!(futures::poll!(&mut normal).is_pending());
+        let mut high = Box::pin(sem.acquire_with_priority(AcquirePriority::High));
+        assert!(futures::poll!(&mut high).is_pending());
+
+        sem.add_permit();
+        assert!(futures::poll!(&mut normal).is_pending());
+        let permit = high.await.unwrap();
+        // Dropping a permit returns it, so the normal waiter gets it next
+        drop(permit);
+        normal.await.unwrap().forget();
+        assert_eq!(sem.sem.available_permits(), 0);
+    }
+
+    #[tokio::test]
+    async fn waiters_of_same_priority_served_in_order() {
+        for priority in [AcquirePriority::Normal, AcquirePriority::High] {
+            let sem = test_sem(1);
+            sem.acquire().await.unwrap().forget();
+            let mut first = Box::pin(sem.acquire_with_priority(priority));
+            assert!(futures::poll!(&mut first).is_pending());
+            let mut second = Box::pin(sem.acquire_with_priority(priority));
+            assert!(futures::poll!(&mut second).is_pending());
+
+            sem.add_permit();
+            assert!(futures::poll!(&mut second).is_pending());
+            first.await.unwrap().forget();
+            sem.add_permit();
+            second.await.unwrap().forget();
+        }
+    }
+
+    #[tokio::test]
+    async fn abandoned_high_priority_waiters_dont_lose_permits() {
+        let sem = test_sem(1);
+        sem.acquire().await.unwrap().forget();
+        let mut abandoned = Box::pin(sem.acquire_with_priority(AcquirePriority::High));
+        assert!(futures::poll!(&mut abandoned).is_pending());
+        // The permit is handed over, but the waiter gives up before taking it
+        sem.add_permit();
+        drop(abandoned);
+        assert_eq!(sem.sem.available_permits(), 1);
+
+        let mut abandoned = Box::pin(sem.acquire_with_priority(AcquirePriority::High));
+        sem.acquire().await.unwrap().forget();
+        assert!(futures::poll!(&mut abandoned).is_pending());
+        drop(abandoned);
+        // The waiter gave up before any permit was restored, so the next one is available to all
+        sem.add_permit();
+        assert_eq!(sem.sem.available_permits(), 1);
+    }
 }
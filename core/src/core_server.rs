@@ -0,0 +1,454 @@
+//! Serves the Core API for a worker over gRPC, so lang SDKs can talk to core running as a sidecar
+//! process rather than linking it natively. The service is `CoreService`, defined in
+//! `bridge.proto`, and uses the same request and response messages as the C bridge.
+//!
+//! Each server hosts exactly one worker, which the sidecar process creates (ex: from a
+//! [CreateWorkerRequest](temporal_sdk_core_protos::coresdk::bridge::CreateWorkerRequest) read from
+//! its configuration). Lang SDKs needing several workers run one server for each.
+//!
+//! Anything able to connect to the server can drive the worker, so servers listening on TCP
+//! addresses other than loopback must be given an auth token and TLS config, see
+//! [CoreServerOptions].
+
+use crate::fetch_global_buffered_logs;
+use futures::{Future, Stream};
+use std::{io, net::SocketAddr, sync::Arc};
+use temporal_sdk_core_api::{
+    errors::{CompleteWfError, PollActivityError, PollWfError},
+    Worker,
+};
+use temporal_sdk_core_protos::coresdk::bridge::{
+    self,
+    core_service_server::{CoreService, CoreServiceServer},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+};
+use tonic::{
+    transport::{server::Connected, Server, ServerTlsConfig},
+    Request, Response, Status,
+};
+
+/// Where a core server listens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CoreServerAddr {
+    /// Listen on a TCP socket
+    Tcp(SocketAddr),
+    /// Listen on a Unix domain socket at the provided path, which must not already exist
+    #[cfg(unix)]
+    Uds(std::path::PathBuf),
+}
+
+/// Options for serving a worker
+#[derive(Clone, Default)]
+pub struct CoreServerOptions {
+    /// If set, every call must carry this token in an `authorization: Bearer <token>` header.
+    /// Required to listen on a TCP address other than loopback.
+    pub auth_token: Option<String>,
+    /// If set, connections are served over TLS. Required to listen on a TCP address other than
+    /// loopback, so the auth token isn't sent in plain text.
+    pub tls: Option<ServerTlsConfig>,
+    /// Serve `FetchBufferedLogs`. Logs are buffered for the whole process and each call drains
+    /// them, so at most one server in a process should enable this.
+    pub serve_buffered_logs: bool,
+}
+
+/// Serve `worker` at `addr` until `shutdown_signal` resolves.
+///
+/// Shutting down the server does not shut down the worker. Lang should do that first, with the
+/// `ShutdownWorker` call, so that it can complete any outstanding activations.
+pub async fn serve_worker(
+    worker: Arc<dyn Worker>,
+    addr: CoreServerAddr,
+    options: CoreServerOptions,
+    shutdown_signal: impl Future<Output = ()>,
+) -> Result<(), anyhow::Error> {
+    match addr {
+        CoreServerAddr::Tcp(addr) => {
+            check_tcp_auth(addr, &options)?;
+            let listener = TcpListener::bind(addr).await?;
+            serve_worker_on_listener(worker, listener, options, shutdown_signal).await
+        }
+        #[cfg(unix)]
+        CoreServerAddr::Uds(path) => {
+            let listener = tokio::net::UnixListener::bind(path)?;
+            let incoming = futures::stream::unfold(listener, |listener| async move {
+                let conn = listener.accept().await.map(|(s, _)| uds::UdsStream(s));
+                Some((conn, listener))
+            });
+            serve_incoming(worker, incoming, options, shutdown_signal).await
+        }
+    }
+}
+
+/// Like [serve_worker], but serves on an already bound TCP listener
+pub async fn serve_worker_on_listener(
+    worker: Arc<dyn Worker>,
+    listener: TcpListener,
+    options: CoreServerOptions,
+    shutdown_signal: impl Future<Output = ()>,
+) -> Result<(), anyhow::Error> {
+    check_tcp_auth(listener.local_addr()?, &options)?;
+    let incoming = futures::stream::unfold(listener, |listener| async move {
+        let conn = listener.accept().await.map(|(s, _)| s);
+        Some((conn, listener))
+    });
+    serve_incoming(worker, incoming, options, shutdown_signal).await
+}
+
+fn check_tcp_auth(addr: SocketAddr, options: &CoreServerOptions) -> Result<(), anyhow::Error> {
+    if !addr.ip().is_loopback() && (options.auth_token.is_none() || options.tls.is_none()) {
+        anyhow::bail!(
+            "An auth token and TLS are required to serve a worker on {}, which is not a loopback \
+             address",
+            addr
+        );
+    }
+    Ok(())
+}
+
+/// Compares without stopping at the first difference, so how long a comparison takes doesn't
+/// reveal how much of a guessed token was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn serve_incoming<IO>(
+    worker: Arc<dyn Worker>,
+    incoming: impl Stream<Item = io::Result<IO>>,
+    options: CoreServerOptions,
+    shutdown_signal: impl Future<Output = ()>,
+) -> Result<(), anyhow::Error>
+where
+    IO: AsyncRead + AsyncWrite + Connected + Unpin + Send + 'static,
+{
+    let expected_auth = options.auth_token.map(|t| format!("Bearer {}", t));
+    let svc = CoreServiceServer::with_interceptor(
+        WorkerService {
+            worker,
+            serve_buffered_logs: options.serve_buffered_logs,
+        },
+        move |req: Request<()>| match &expected_auth {
+            Some(expected)
+                if !req
+                    .metadata()
+                    .get("authorization")
+                    .is_some_and(|auth| constant_time_eq(auth.as_bytes(), expected.as_bytes())) =>
+            {
+                Err(Status::unauthenticated("Missing or invalid auth token"))
+            }
+            _ => Ok(req),
+        },
+    );
+    let mut server = Server::builder();
+    if let Some(tls) = options.tls {
+        server = server.tls_config(tls)?;
+    }
+    server
+        .add_service(svc)
+        .serve_with_incoming_shutdown(incoming, shutdown_signal)
+        .await?;
+    Ok(())
+}
+
+struct WorkerService {
+    worker: Arc<dyn Worker>,
+    serve_buffered_logs: bool,
+}
+
+#[tonic::async_trait]
+impl CoreService for WorkerService {
+    async fn poll_workflow_activation(
+        &self,
+        _: Request<bridge::PollWorkflowActivationRequest>,
+    ) -> Result<Response<bridge::PollWorkflowActivationResponse>, Status> {
+        use bridge::poll_workflow_activation_response::{Error, Response as Resp};
+        let resp = match self.worker.poll_workflow_activation().await {
+            Ok(act) => Resp::Activation(act),
+            Err(err) => Resp::Error(Error {
+                message: err.to_string(),
+                shutdown: matches!(err, PollWfError::ShutDown),
+            }),
+        };
+        Ok(Response::new(bridge::PollWorkflowActivationResponse {
+            response: Some(resp),
+        }))
+    }
+
+    async fn poll_activity_task(
+        &self,
+        _: Request<bridge::PollActivityTaskRequest>,
+    ) -> Result<Response<bridge::PollActivityTaskResponse>, Status> {
+        use bridge::poll_activity_task_response::{Error, Response as Resp};
+        let resp = match self.worker.poll_activity_task().await {
+            Ok(task) => Resp::Task(task),
+            Err(err) => Resp::Error(Error {
+                message: err.to_string(),
+                shutdown: matches!(err, PollActivityError::ShutDown),
+            }),
+        };
+        Ok(Response::new(bridge::PollActivityTaskResponse {
+            response: Some(resp),
+        }))
+    }
+
+    async fn complete_workflow_activation(
+        &self,
+        req: Request<bridge::CompleteWorkflowActivationRequest>,
+    ) -> Result<Response<bridge::CompleteWorkflowActivationResponse>, Status> {
+        let error = self
+            .worker
+            .complete_workflow_activation(req.into_inner().completion.unwrap_or_default())
+            .await
+            .err()
+            .map(|err| bridge::complete_workflow_activation_response::Error {
+                message: err.to_string(),
+                activation_not_outstanding: matches!(
+                    err,
+                    CompleteWfError::ActivationNotOutstanding { .. }
+                ),
+            });
+        Ok(Response::new(bridge::CompleteWorkflowActivationResponse {
+            error,
+        }))
+    }
+
+    async fn complete_activity_task(
+        &self,
+        req: Request<bridge::CompleteActivityTaskRequest>,
+    ) -> Result<Response<bridge::CompleteActivityTaskResponse>, Status> {
+        let error = self
+            .worker
+            .complete_activity_task(req.into_inner().completion.unwrap_or_default())
+            .await
+            .err()
+            .map(|err| bridge::complete_activity_task_response::Error {
+                message: err.to_string(),
+            });
+        Ok(Response::new(bridge::CompleteActivityTaskResponse {
+            error,
+        }))
+    }
+
+    async fn record_activity_heartbeat(
+        &self,
+        req: Request<bridge::RecordActivityHeartbeatRequest>,
+    ) -> Result<Response<bridge::RecordActivityHeartbeatResponse>, Status> {
+        self.worker
+            .record_activity_heartbeat(req.into_inner().heartbeat.unwrap_or_default());
+        Ok(Response::new(
+            bridge::RecordActivityHeartbeatResponse::default(),
+        ))
+    }
+
+    async fn request_workflow_eviction(
+        &self,
+        req: Request<bridge::RequestWorkflowEvictionRequest>,
+    ) -> Result<Response<bridge::RequestWorkflowEvictionResponse>, Status> {
+        self.worker
+            .request_workflow_eviction(&req.into_inner().run_id);
+        Ok(Response::new(
+            bridge::RequestWorkflowEvictionResponse::default(),
+        ))
+    }
+
+    async fn shutdown_worker(
+        &self,
+        _: Request<bridge::ShutdownWorkerRequest>,
+    ) -> Result<Response<bridge::ShutdownWorkerResponse>, Status> {
+        self.worker.shutdown().await;
+        Ok(Response::new(bridge::ShutdownWorkerResponse::default()))
+    }
+
+    async fn fetch_buffered_logs(
+        &self,
+        _: Request<bridge::FetchBufferedLogsRequest>,
+    ) -> Result<Response<bridge::FetchBufferedLogsResponse>, Status> {
+        if !self.serve_buffered_logs {
+            return Err(Status::unimplemented(
+                "This server was not configured to serve buffered logs",
+            ));
+        }
+        let entries = fetch_global_buffered_logs()
+            .into_iter()
+            .map(|log| bridge::fetch_buffered_logs_response::LogEntry {
+                message: log.message,
+                timestamp: Some(log.timestamp.into()),
+                level: match log.level {
+                    log::Level::Error => bridge::LogLevel::Error.into(),
+                    log::Level::Warn => bridge::LogLevel::Warn.into(),
+                    log::Level::Info => bridge::LogLevel::Info.into(),
+                    log::Level::Debug => bridge::LogLevel::Debug.into(),
+                    log::Level::Trace => bridge::LogLevel::Trace.into(),
+                },
+            })
+            .collect();
+        Ok(Response::new(bridge::FetchBufferedLogsResponse { entries }))
+    }
+}
+
+#[cfg(unix)]
+mod uds {
+    use std::{
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+    use tokio::{
+        io::{AsyncRead, AsyncWrite, ReadBuf},
+        net::UnixStream,
+    };
+    use tonic::transport::server::Connected;
+
+    /// Tonic only knows how to serve connections over TCP, so unix sockets need to be wrapped
+    pub(super) struct UdsStream(pub(super) UnixStream);
+
+    impl Connected for UdsStream {
+        type ConnectInfo = ();
+
+        fn connect_info(&self) -> Self::ConnectInfo {}
+    }
+
+    impl AsyncRead for UdsStream {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.0).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for UdsStream {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.0).poll_write(cx, buf)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.0).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.0).poll_shutdown(cx)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_help::{build_fake_worker, canned_histories};
+    use std::time::Duration;
+    use temporal_sdk_core_protos::coresdk::{
+        bridge::{core_service_client::CoreServiceClient, poll_workflow_activation_response},
+        workflow_completion::WorkflowActivationCompletion,
+    };
+    use temporal_sdk_core_test_utils::start_timer_cmd;
+    use tokio::{sync::oneshot, task::JoinHandle};
+
+    async fn start_server(
+        options: CoreServerOptions,
+    ) -> (
+        CoreServiceClient<tonic::transport::Channel>,
+        oneshot::Sender<()>,
+        JoinHandle<Result<(), anyhow::Error>>,
+    ) {
+        let t = canned_histories::single_timer("1");
+        let worker = Arc::new(build_fake_worker("fake_wf_id", t, [1]));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(serve_worker_on_listener(worker, listener, options, async {
+            stop_rx.await.ok();
+        }));
+        let client = CoreServiceClient::connect(format!("http://{}", addr))
+            .await
+            .unwrap();
+        (client, stop_tx, server)
+    }
+
+    #[tokio::test]
+    async fn serves_worker_over_tcp() {
+        let (mut client, stop_tx, server) = start_server(Default::default()).await;
+        let resp = client
+            .poll_workflow_activation(bridge::PollWorkflowActivationRequest {})
+            .await
+            .unwrap()
+            .into_inner();
+        let act = match resp.response {
+            Some(poll_workflow_activation_response::Response::Activation(a)) => a,
+            o => panic!("Unexpected poll response {:?}", o),
+        };
+        let resp = client
+            .complete_workflow_activation(bridge::CompleteWorkflowActivationRequest {
+                completion: Some(WorkflowActivationCompletion::from_cmds(
+                    act.run_id,
+                    vec![start_timer_cmd(1, Duration::from_secs(1))],
+                )),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(resp.error, None);
+        // Logs aren't served unless asked for
+        let err = client
+            .fetch_buffered_logs(bridge::FetchBufferedLogsRequest {})
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unimplemented);
+
+        stop_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn calls_require_auth_token_if_set() {
+        let (mut client, stop_tx, server) = start_server(CoreServerOptions {
+            auth_token: Some("secret".to_string()),
+            ..Default::default()
+        })
+        .await;
+        let err = client
+            .poll_workflow_activation(bridge::PollWorkflowActivationRequest {})
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+
+        let mut req = Request::new(bridge::PollWorkflowActivationRequest {});
+        req.metadata_mut()
+            .insert("authorization", "Bearer secret".parse().unwrap());
+        client.poll_workflow_activation(req).await.unwrap();
+
+        stop_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn non_loopback_addresses_require_auth_token_and_tls() {
+        let with_token = CoreServerOptions {
+            auth_token: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let with_token_and_tls = CoreServerOptions {
+            tls: Some(ServerTlsConfig::new()),
+            ..with_token.clone()
+        };
+        let public = "0.0.0.0:7233".parse().unwrap();
+        assert!(check_tcp_auth(public, &Default::default()).is_err());
+        assert!(check_tcp_auth(public, &with_token).is_err());
+        assert!(check_tcp_auth(public, &with_token_and_tls).is_ok());
+        assert!(check_tcp_auth("[::1]:7233".parse().unwrap(), &Default::default()).is_ok());
+    }
+
+    #[test]
+    fn token_comparison() {
+        assert!(constant_time_eq(b"Bearer secret", b"Bearer secret"));
+        assert!(!constant_time_eq(b"Bearer secret", b"Bearer secreT"));
+        assert!(!constant_time_eq(b"Bearer secret", b"Bearer secret2"));
+    }
+}
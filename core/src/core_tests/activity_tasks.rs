@@ -1,37 +1,60 @@
 use crate::{
+    errors::{ActivitySlotError, PollActivityError},
     job_assert,
     test_help::{
-        build_fake_worker, canned_histories, gen_assert_and_reply, mock_manual_poller, mock_poller,
-        mock_worker, poll_and_reply, test_worker_cfg, MockWorker, MocksHolder,
+        build_fake_worker, canned_histories, gen_assert_and_reply, hist_to_poll_resp,
+        mock_manual_poller, mock_poller, mock_worker, poll_and_reply, test_worker_cfg, MockWorker,
+        MocksHolder, TEST_Q,
+    },
+    worker::client::{
+        mocks::{mock_manual_workflow_client, mock_workflow_client},
+        MockWorkerClient,
     },
-    worker::client::mocks::{mock_manual_workflow_client, mock_workflow_client},
     workflow::WorkflowCachingPolicy::NonSticky,
-    ActivityHeartbeat, Worker, WorkerConfigBuilder,
+    ActivityHeartbeat, TaskToken, Worker, WorkerConfigBuilder,
 };
 use futures::FutureExt;
 use std::{
     cell::RefCell,
     collections::{hash_map::Entry, HashMap, VecDeque},
     rc::Rc,
-    sync::atomic::{AtomicUsize, Ordering},
-    time::Duration,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+use temporal_sdk_core_api::{
+    worker::{
+        ActivityInterceptor, SessionActivityQueueConfig, ShutdownOptionsBuilder, ShutdownPhase,
+        ShutdownProgress, ShutdownProgressListener,
+    },
+    Worker as WorkerTrait,
 };
-use temporal_sdk_core_api::Worker as WorkerTrait;
 use temporal_sdk_core_protos::{
     coresdk::{
-        activity_result::{activity_resolution, ActivityExecutionResult, ActivityResolution},
-        activity_task::{activity_task, ActivityTask},
+        activity_result::{
+            self as ar, activity_execution_result as aer, activity_resolution,
+            ActivityExecutionResult, ActivityResolution,
+        },
+        activity_task::{activity_task, ActivityCancelReason, ActivityTask, Cancel},
         workflow_activation::{workflow_activation_job, ResolveActivity, WorkflowActivationJob},
         workflow_commands::{
             ActivityCancellationType, CompleteWorkflowExecution, RequestCancelActivity,
             ScheduleActivity,
         },
+        workflow_completion::WorkflowActivationCompletion,
         ActivityTaskCompletion,
     },
-    temporal::api::workflowservice::v1::{
-        PollActivityTaskQueueResponse, RecordActivityTaskHeartbeatResponse,
-        RespondActivityTaskCanceledResponse, RespondActivityTaskCompletedResponse,
-        RespondActivityTaskFailedResponse,
+    temporal::api::{
+        common::v1::{ActivityType, WorkflowExecution},
+        enums::v1::TimeoutType,
+        failure::v1::{failure::FailureInfo, Failure},
+        workflowservice::v1::{
+            PollActivityTaskQueueResponse, RecordActivityTaskHeartbeatResponse,
+            RespondActivityTaskCanceledResponse, RespondActivityTaskCompletedResponse,
+            RespondActivityTaskFailedResponse, RespondWorkflowTaskCompletedResponse,
+        },
     },
 };
 use temporal_sdk_core_test_utils::{fanout_tasks, start_timer_cmd};
@@ -113,6 +136,34 @@ async fn activity_not_found_returns_ok() {
     core.shutdown().await;
 }
 
+#[tokio::test]
+async fn heartbeat_for_unknown_activity_issues_cancel_once() {
+    let mut mock_client = mock_workflow_client();
+    // Server is never bothered, since we know nothing about the activity
+    mock_client.expect_record_activity_heartbeat().times(0);
+    let core = mock_worker(MocksHolder::from_client_with_responses(mock_client, [], []));
+
+    for _ in 0..2 {
+        core.record_activity_heartbeat(ActivityHeartbeat {
+            task_token: vec![1],
+            details: vec![vec![1_u8, 2, 3].into()],
+        });
+    }
+    // Cancels are delivered via the heartbeat manager's background task
+    sleep(Duration::from_millis(10)).await;
+    let act = core.poll_activity_task().await.unwrap();
+    assert_matches!(
+        act,
+        ActivityTask {
+            task_token,
+            variant: Some(activity_task::Variant::Cancel(Cancel { reason }))
+        } if task_token == vec![1] && reason == ActivityCancelReason::NotFound as i32
+    );
+    // Second heartbeat did not generate another cancel, so we fall through to the (empty) poller
+    assert!(core.poll_activity_task().await.is_err());
+    core.shutdown().await;
+}
+
 #[tokio::test]
 async fn heartbeats_report_cancels_only_once() {
     let mut mock_client = mock_workflow_client();
@@ -425,7 +476,7 @@ async fn many_concurrent_heartbeat_cancels() {
 #[tokio::test]
 async fn activity_timeout_no_double_resolve() {
     let t = canned_histories::activity_double_resolve_repro();
-    let core = build_fake_worker("fake_wf_id", t, &[3]);
+    let core = build_fake_worker("fake_wf_id", t, [3]);
     let activity_id = 1;
 
     poll_and_reply(
@@ -527,6 +578,112 @@ async fn can_heartbeat_acts_during_shutdown() {
     assert_eq!(&complete_order.into_inner(), &[2, 1])
 }
 
+#[derive(Debug, Default)]
+struct PhaseRecorder(parking_lot::Mutex<Vec<ShutdownPhase>>);
+impl ShutdownProgressListener for PhaseRecorder {
+    fn on_progress(&self, progress: &ShutdownProgress) {
+        let mut phases = self.0.lock();
+        if phases.last() != Some(&progress.phase) {
+            phases.push(progress.phase);
+        }
+    }
+}
+
+fn one_activity_worker(mock_client: MockWorkerClient) -> Worker {
+    mock_worker(MocksHolder::from_client_with_responses(
+        mock_client,
+        [],
+        [PollActivityTaskQueueResponse {
+            task_token: vec![1],
+            activity_id: "act1".to_string(),
+            ..Default::default()
+        }],
+    ))
+}
+
+#[tokio::test]
+async fn shutdown_deadline_cancels_outstanding_activities() {
+    let mut mock_client = mock_workflow_client();
+    mock_client
+        .expect_cancel_activity_task()
+        .times(1)
+        .returning(|_, _| Ok(RespondActivityTaskCanceledResponse::default()));
+    let core = one_activity_worker(mock_client);
+    let act = core.poll_activity_task().await.unwrap();
+
+    let phases = Arc::new(PhaseRecorder::default());
+    let opts = ShutdownOptionsBuilder::default()
+        .deadline(Duration::from_secs(5))
+        .cancel_activities_after(0.01)
+        .progress_listener(phases.clone() as Arc<dyn ShutdownProgressListener>)
+        .build()
+        .unwrap();
+    let lang_fut = async {
+        // Cancels are issued after 50ms, and delivered via the heartbeat manager's background task
+        sleep(Duration::from_millis(100)).await;
+        let cancel = core.poll_activity_task().await.unwrap();
+        assert_matches!(
+            cancel,
+            ActivityTask {
+                task_token,
+                variant: Some(activity_task::Variant::Cancel(Cancel { reason }))
+            } if task_token == act.task_token
+                && reason == ActivityCancelReason::WorkerShutdown as i32
+        );
+        core.complete_activity_task(ActivityTaskCompletion {
+            task_token: act.task_token.clone(),
+            result: Some(ActivityExecutionResult::cancel_from_details(None)),
+        })
+        .await
+        .unwrap();
+    };
+    let (progress, _) = join!(core.shutdown_with_deadline(opts), lang_fut);
+    assert_eq!(progress.phase, ShutdownPhase::Complete);
+    assert_eq!(progress.outstanding_activities, 0);
+    assert_eq!(
+        phases.0.lock().as_slice(),
+        [
+            ShutdownPhase::Draining,
+            ShutdownPhase::CancellingActivities,
+            ShutdownPhase::Complete
+        ]
+    );
+}
+
+#[tokio::test]
+async fn shutdown_deadline_abandons_unfinished_work() {
+    let core = one_activity_worker(mock_workflow_client());
+    let _act = core.poll_activity_task().await.unwrap();
+
+    let opts = ShutdownOptionsBuilder::default()
+        .deadline(Duration::from_millis(50))
+        .build()
+        .unwrap();
+    let progress = core.shutdown_with_deadline(opts).await;
+    assert_eq!(
+        progress,
+        ShutdownProgress {
+            phase: ShutdownPhase::Aborted,
+            outstanding_workflow_tasks: 0,
+            outstanding_activities: 1,
+            outstanding_local_activities: 0,
+        }
+    );
+}
+
+#[test]
+fn invalid_shutdown_options_are_rejected() {
+    let opts = || {
+        let mut opts = ShutdownOptionsBuilder::default();
+        opts.deadline(Duration::from_secs(1));
+        opts
+    };
+    assert!(opts().progress_interval(Duration::ZERO).build().is_err());
+    for fraction in [-0.5, 1.5, f64::NAN] {
+        assert!(opts().cancel_activities_after(fraction).build().is_err());
+    }
+}
+
 /// Verifies that if a user has tried to record a heartbeat and then immediately after failed the
 /// activity, that we flush those details before reporting the failure completion.
 #[tokio::test]
@@ -579,7 +736,455 @@ async fn complete_act_with_fail_flushes_heartbeat() {
 
     // Verify the last seen call to record a heartbeat had the last detail payload
     let last_seen_payload = &last_seen_payload.take().unwrap().payloads[0];
-    assert_eq!(last_seen_payload.data, &[last_hb]);
+    assert_eq!(last_seen_payload.data, [last_hb].as_slice());
+}
+
+#[tokio::test]
+async fn heartbeat_throttle_can_be_overridden_per_activity_type() {
+    let mut mock_client = mock_workflow_client();
+    let hb_count = Arc::new(AtomicUsize::new(0));
+    let hbc = hb_count.clone();
+    mock_client
+        .expect_record_activity_heartbeat()
+        .returning(move |_, _| {
+            hbc.fetch_add(1, Ordering::SeqCst);
+            Ok(RecordActivityTaskHeartbeatResponse::default())
+        });
+    mock_client
+        .expect_complete_activity_task()
+        .returning(|_, _| Ok(RespondActivityTaskCompletedResponse::default()));
+
+    let mut mh = MocksHolder::from_client_with_responses(
+        mock_client,
+        [],
+        [PollActivityTaskQueueResponse {
+            task_token: vec![1],
+            activity_id: "act1".to_string(),
+            activity_type: Some(ActivityType {
+                name: "fast".to_string(),
+            }),
+            // Would throttle for 8 seconds without the override
+            heartbeat_timeout: Some(Duration::from_secs(10).into()),
+            ..Default::default()
+        }],
+    );
+    mh.worker_cfg(|wc| {
+        wc.heartbeat_throttle_interval_overrides =
+            HashMap::from([("fast".to_string(), Duration::from_millis(10))])
+    });
+    let core = mock_worker(mh);
+
+    let act = core.poll_activity_task().await.unwrap();
+    for i in 1..=2_u8 {
+        core.record_activity_heartbeat(ActivityHeartbeat {
+            task_token: act.task_token.clone(),
+            details: vec![vec![i].into()],
+        });
+        sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(hb_count.load(Ordering::SeqCst), 2);
+    core.complete_activity_task(ActivityTaskCompletion {
+        task_token: act.task_token,
+        result: Some(ActivityExecutionResult::ok(vec![1].into())),
+    })
+    .await
+    .unwrap();
+    core.shutdown().await;
+}
+
+#[tokio::test]
+async fn activities_past_schedule_to_start_are_failed_not_dispatched() {
+    let mut mock_client = mock_workflow_client();
+    mock_client
+        .expect_fail_activity_task()
+        .withf(|tt, f| {
+            tt == &TaskToken(vec![1])
+                && matches!(f, Some(Failure {
+                    failure_info: Some(FailureInfo::TimeoutFailureInfo(tfi)), ..
+                }) if tfi.timeout_type == TimeoutType::ScheduleToStart as i32)
+        })
+        .times(1)
+        .returning(|_, _| Ok(RespondActivityTaskFailedResponse::default()));
+    mock_client
+        .expect_complete_activity_task()
+        .times(1)
+        .returning(|_, _| Ok(RespondActivityTaskCompletedResponse::default()));
+
+    let mut mh = MocksHolder::from_client_with_responses(
+        mock_client,
+        [],
+        [
+            PollActivityTaskQueueResponse {
+                task_token: vec![1],
+                activity_id: "stale".to_string(),
+                current_attempt_scheduled_time: Some(
+                    (SystemTime::now() - Duration::from_secs(60)).into(),
+                ),
+                ..Default::default()
+            },
+            PollActivityTaskQueueResponse {
+                task_token: vec![2],
+                activity_id: "fresh".to_string(),
+                current_attempt_scheduled_time: Some(SystemTime::now().into()),
+                ..Default::default()
+            },
+        ],
+    );
+    mh.worker_cfg(|wc| wc.max_activity_schedule_to_start = Some(Duration::from_secs(10)));
+    let core = mock_worker(mh);
+
+    // The stale task is skipped over entirely
+    let act = core.poll_activity_task().await.unwrap();
+    assert_eq!(act.task_token, vec![2]);
+    core.complete_activity_task(ActivityTaskCompletion {
+        task_token: act.task_token,
+        result: Some(ActivityExecutionResult::ok(vec![1].into())),
+    })
+    .await
+    .unwrap();
+    core.shutdown().await;
+}
+
+#[derive(Debug)]
+struct TestActInterceptor;
+impl ActivityInterceptor for TestActInterceptor {
+    fn on_dispatch(&self, task: &mut ActivityTask) -> Result<(), Failure> {
+        if let Some(activity_task::Variant::Start(start)) = task.variant.as_mut() {
+            if start.activity_type == "banned" {
+                return Err(Failure::application_failure("Nope".to_string(), true));
+            }
+            start
+                .header_fields
+                .insert("intercepted".to_string(), vec![1].into());
+        }
+        Ok(())
+    }
+
+    fn on_complete(&self, _: &[u8], activity_type: &str, result: &mut aer::Status) {
+        assert_eq!(activity_type, "allowed");
+        *result = aer::Status::Completed(ar::Success {
+            result: Some(vec![2].into()),
+        });
+    }
+}
+
+#[tokio::test]
+async fn activity_interceptor_can_modify_and_veto() {
+    let mut mock_client = mock_workflow_client();
+    mock_client
+        .expect_fail_activity_task()
+        .withf(|tt, f| tt == &TaskToken(vec![1]) && f.as_ref().unwrap().message == "Nope")
+        .times(1)
+        .returning(|_, _| Ok(RespondActivityTaskFailedResponse::default()));
+    mock_client
+        .expect_complete_activity_task()
+        .withf(|tt, res| {
+            tt == &TaskToken(vec![2]) && res.as_ref().unwrap().payloads[0].data == vec![2]
+        })
+        .times(1)
+        .returning(|_, _| Ok(RespondActivityTaskCompletedResponse::default()));
+
+    let mut mh = MocksHolder::from_client_with_responses(
+        mock_client,
+        [],
+        [
+            PollActivityTaskQueueResponse {
+                task_token: vec![1],
+                activity_type: Some(ActivityType {
+                    name: "banned".to_string(),
+                }),
+                ..Default::default()
+            },
+            PollActivityTaskQueueResponse {
+                task_token: vec![2],
+                activity_type: Some(ActivityType {
+                    name: "allowed".to_string(),
+                }),
+                ..Default::default()
+            },
+        ],
+    );
+    mh.worker_cfg(|wc| wc.activity_interceptor = Some(Arc::new(TestActInterceptor)));
+    let core = mock_worker(mh);
+
+    let act = core.poll_activity_task().await.unwrap();
+    assert_eq!(act.task_token, vec![2]);
+    assert_matches!(
+        act.variant,
+        Some(activity_task::Variant::Start(s)) if s.header_fields.contains_key("intercepted")
+    );
+    core.complete_activity_task(ActivityTaskCompletion {
+        task_token: act.task_token,
+        result: Some(ActivityExecutionResult::ok(vec![1].into())),
+    })
+    .await
+    .unwrap();
+    core.shutdown().await;
+}
+
+#[tokio::test]
+async fn activity_slots_can_be_reserved_and_released_early() {
+    let mut mock_client = mock_workflow_client();
+    mock_client
+        .expect_complete_activity_task()
+        .times(2)
+        .returning(|_, _| Ok(RespondActivityTaskCompletedResponse::default()));
+    let act_task = |tt: u8| PollActivityTaskQueueResponse {
+        task_token: vec![tt],
+        activity_type: Some(ActivityType {
+            name: "act".to_string(),
+        }),
+        ..Default::default()
+    };
+    let mut mh =
+        MocksHolder::from_client_with_responses(mock_client, [], [act_task(1), act_task(2)]);
+    mh.worker_cfg(|wc| wc.max_outstanding_activities = 1);
+    let core = mock_worker(mh);
+
+    // With the only slot reserved, nothing can be polled
+    core.reserve_activity_slot().await.unwrap();
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), core.poll_activity_task())
+            .await
+            .is_err()
+    );
+    core.release_activity_slot().unwrap();
+    assert_matches!(
+        core.release_activity_slot(),
+        Err(ActivitySlotError::NoneReserved)
+    );
+
+    let first = core.poll_activity_task().await.unwrap();
+    assert_eq!(first.task_token, vec![1]);
+    // Once the first task's slot is released, the second can be polled while it's outstanding
+    core.release_activity_task_slot(&first.task_token).unwrap();
+    assert_matches!(
+        core.release_activity_task_slot(&first.task_token),
+        Err(ActivitySlotError::AlreadyReleased(_))
+    );
+    let second = core.poll_activity_task().await.unwrap();
+    assert_eq!(second.task_token, vec![2]);
+
+    for tt in [first.task_token, second.task_token] {
+        core.complete_activity_task(ActivityTaskCompletion {
+            task_token: tt,
+            result: Some(ActivityExecutionResult::ok(vec![1].into())),
+        })
+        .await
+        .unwrap();
+    }
+    assert_matches!(
+        core.release_activity_task_slot(&[1]),
+        Err(ActivitySlotError::UnknownTask(_))
+    );
+    core.shutdown().await;
+}
+
+#[tokio::test]
+async fn per_type_activity_limits_hold_tasks() {
+    let mut mock_client = mock_workflow_client();
+    mock_client
+        .expect_complete_activity_task()
+        .times(3)
+        .returning(|_, _| Ok(RespondActivityTaskCompletedResponse::default()));
+    let act_task = |tt: u8, act_type: &str| PollActivityTaskQueueResponse {
+        task_token: vec![tt],
+        activity_type: Some(ActivityType {
+            name: act_type.to_string(),
+        }),
+        ..Default::default()
+    };
+    let mut mh = MocksHolder::from_client_with_responses(
+        mock_client,
+        [],
+        [
+            act_task(1, "limited"),
+            act_task(2, "limited"),
+            act_task(3, "other"),
+        ],
+    );
+    mh.worker_cfg(|wc| {
+        // The held task doesn't take up a slot, so there's still one to poll with below
+        wc.max_outstanding_activities = 3;
+        wc.max_outstanding_activities_per_type = HashMap::from([("limited".to_string(), 1)])
+    });
+    let core = mock_worker(mh);
+    let complete = |tt: Vec<u8>| {
+        core.complete_activity_task(ActivityTaskCompletion {
+            task_token: tt,
+            result: Some(ActivityExecutionResult::ok(vec![1].into())),
+        })
+    };
+
+    let first = core.poll_activity_task().await.unwrap();
+    assert_eq!(first.task_token, vec![1]);
+    // The second task of the limited type is held, so the other type's task comes next
+    let other = core.poll_activity_task().await.unwrap();
+    assert_eq!(other.task_token, vec![3]);
+    assert_matches!(
+        core.poll_activity_task().await,
+        Err(PollActivityError::TonicError(_))
+    );
+
+    complete(first.task_token).await.unwrap();
+    let held = core.poll_activity_task().await.unwrap();
+    assert_eq!(held.task_token, vec![2]);
+    complete(held.task_token).await.unwrap();
+    complete(other.task_token).await.unwrap();
+    core.shutdown().await;
+}
+
+#[tokio::test]
+async fn session_queue_activities_use_their_own_slots() {
+    let mut mock_client = mock_workflow_client();
+    let session_polls = AtomicUsize::new(0);
+    mock_client
+        .expect_poll_activity_task()
+        .withf(|tq, _| tq == "session-q")
+        .returning(move |_, _| {
+            if session_polls.fetch_add(1, Ordering::SeqCst) == 0 {
+                Ok(PollActivityTaskQueueResponse {
+                    task_token: vec![2],
+                    activity_id: "session-act".to_string(),
+                    ..Default::default()
+                })
+            } else {
+                Ok(Default::default())
+            }
+        });
+    mock_client
+        .expect_complete_activity_task()
+        .times(2)
+        .returning(|_, _| Ok(RespondActivityTaskCompletedResponse::default()));
+    let mut mh = MocksHolder::from_client_with_responses(
+        mock_client,
+        [],
+        [PollActivityTaskQueueResponse {
+            task_token: vec![1],
+            activity_id: "act".to_string(),
+            ..Default::default()
+        }],
+    );
+    mh.worker_cfg(|wc| {
+        wc.max_outstanding_activities = 1;
+        wc.session_activity_queue = Some(SessionActivityQueueConfig {
+            task_queue: "session-q".to_string(),
+            max_outstanding_activities: 1,
+            max_concurrent_polls: 1,
+        });
+    });
+    let core = mock_worker(mh);
+
+    // The main queue's only slot is taken by one of these, so the other must use a session slot
+    let mut tokens = vec![
+        core.poll_activity_task().await.unwrap().task_token,
+        core.poll_activity_task().await.unwrap().task_token,
+    ];
+    tokens.sort();
+    assert_eq!(tokens, vec![vec![1], vec![2]]);
+    for tt in tokens {
+        core.complete_activity_task(ActivityTaskCompletion {
+            task_token: tt,
+            result: Some(ActivityExecutionResult::ok(vec![1].into())),
+        })
+        .await
+        .unwrap();
+    }
+    core.shutdown().await;
+}
+
+#[derive(Clone, Copy)]
+enum RunOutcome {
+    Completed,
+    TaskNotFound,
+}
+
+#[rstest::rstest]
+#[case::completed(RunOutcome::Completed)]
+#[case::task_not_found(RunOutcome::TaskNotFound)]
+#[tokio::test]
+async fn activities_cancelled_when_workflow_run_ends(#[case] outcome: RunOutcome) {
+    let t = canned_histories::single_timer("1");
+    let run_id = t.get_orig_run_id().to_string();
+    let mut mock_client = mock_workflow_client();
+    mock_client
+        .expect_complete_workflow_task()
+        .times(1)
+        .returning(move |_| match outcome {
+            RunOutcome::Completed => Ok(RespondWorkflowTaskCompletedResponse::default()),
+            RunOutcome::TaskNotFound => Err(tonic::Status::not_found("Workflow task not found.")),
+        });
+    // Nothing is reported for the activity if the server no longer cares about it
+    let reported = matches!(outcome, RunOutcome::TaskNotFound) as usize;
+    mock_client
+        .expect_cancel_activity_task()
+        .times(reported)
+        .returning(|_, _| Ok(RespondActivityTaskCanceledResponse::default()));
+
+    let core = mock_worker(MocksHolder::from_client_with_responses(
+        mock_client,
+        [hist_to_poll_resp(
+            &t,
+            "fake_wf_id".to_string(),
+            1.into(),
+            TEST_Q.to_string(),
+        )],
+        [PollActivityTaskQueueResponse {
+            task_token: vec![1],
+            activity_id: "act1".to_string(),
+            workflow_execution: Some(WorkflowExecution {
+                workflow_id: "fake_wf_id".to_string(),
+                run_id: run_id.clone(),
+            }),
+            ..Default::default()
+        }],
+    ));
+
+    let act = core.poll_activity_task().await.unwrap();
+    let activation = core.poll_workflow_activation().await.unwrap();
+    let cmd = match outcome {
+        RunOutcome::Completed => CompleteWorkflowExecution { result: None }.into(),
+        _ => start_timer_cmd(1, Duration::from_secs(1)),
+    };
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        activation.run_id,
+        cmd,
+    ))
+    .await
+    .unwrap();
+
+    // Cancels are delivered via the heartbeat manager's background task
+    sleep(Duration::from_millis(10)).await;
+    if let RunOutcome::TaskNotFound = outcome {
+        // The task may have timed out with the run carrying on elsewhere, so the run is only
+        // evicted. If the run did end, the activity learns so when it next heartbeats.
+        // The mock poller errors once it runs out of tasks, rather than a cancel being produced
+        let res = tokio::time::timeout(Duration::from_millis(100), core.poll_activity_task()).await;
+        assert_matches!(res, Err(_) | Ok(Err(_)));
+    } else {
+        let cancel = core.poll_activity_task().await.unwrap();
+        assert_matches!(
+            cancel,
+            ActivityTask {
+                task_token,
+                variant: Some(activity_task::Variant::Cancel(Cancel { reason }))
+            } if task_token == act.task_token && reason == ActivityCancelReason::NotFound as i32
+        );
+    }
+    core.complete_activity_task(ActivityTaskCompletion {
+        task_token: act.task_token,
+        result: Some(ActivityExecutionResult::cancel_from_details(None)),
+    })
+    .await
+    .unwrap();
+    if !matches!(outcome, RunOutcome::Completed) {
+        let eviction = core.poll_workflow_activation().await.unwrap();
+        assert!(eviction.eviction_reason().is_some());
+        core.complete_workflow_activation(WorkflowActivationCompletion::empty(eviction.run_id))
+            .await
+            .unwrap();
+    }
+    core.shutdown().await;
 }
 
 #[tokio::test]
@@ -606,3 +1211,43 @@ async fn max_tq_acts_set_passed_to_poll_properly() {
     let worker = Worker::new_test(cfg, mock_client);
     worker.poll_activity_task().await.unwrap();
 }
+
+#[tokio::test]
+async fn oversized_activity_result_fails_activity() {
+    let mut mock_client = mock_workflow_client();
+    mock_client.expect_complete_activity_task().times(0);
+    mock_client
+        .expect_fail_activity_task()
+        .withf(|tt, f| {
+            tt == &TaskToken(vec![1])
+                && f.as_ref()
+                    .unwrap()
+                    .message
+                    .starts_with("PayloadTooLarge: payloads of activity result are")
+        })
+        .times(1)
+        .returning(|_, _| Ok(RespondActivityTaskFailedResponse::default()));
+
+    let mut mh = MocksHolder::from_client_with_responses(
+        mock_client,
+        [],
+        [PollActivityTaskQueueResponse {
+            task_token: vec![1],
+            activity_type: Some(ActivityType {
+                name: "big_result".to_string(),
+            }),
+            ..Default::default()
+        }],
+    );
+    mh.worker_cfg(|wc| wc.payload_size_limit_bytes = Some(1024));
+    let core = mock_worker(mh);
+
+    let act = core.poll_activity_task().await.unwrap();
+    core.complete_activity_task(ActivityTaskCompletion {
+        task_token: act.task_token,
+        result: Some(ActivityExecutionResult::ok(vec![0; 2048].into())),
+    })
+    .await
+    .unwrap();
+    core.shutdown().await;
+}
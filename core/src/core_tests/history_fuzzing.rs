@@ -0,0 +1,404 @@
+//! Generates valid but unusual histories - concurrent operations resolving in any order, workflow
+//! tasks which produce no commands or time out, signals at arbitrary points, and history pages
+//! which split workflow tasks at odd places - and replays them. Replay must never panic or hang,
+//! and every run must end either completed or with a nondeterminism eviction.
+//!
+//! Every case is generated from a seed, which is included in any failure. A failing case can be
+//! reproduced by calling [run_case] with that seed.
+
+use crate::{
+    replay::{
+        mock_replay_client, new_replay_worker, HistoryInfo, ReplayFailure, TestHistoryBuilder,
+    },
+    test_help::{test_worker_cfg, TEST_Q},
+    worker::client::WorkerClientBag,
+};
+use futures::FutureExt;
+use parking_lot::Mutex;
+use rand::{prelude::SliceRandom, rngs::StdRng, Rng, SeedableRng};
+use std::{collections::HashSet, sync::Arc, time::Duration};
+use temporal_sdk_core_api::{errors::PollWfError, Worker as WorkerTrait};
+use temporal_sdk_core_protos::{
+    coresdk::{
+        workflow_activation::{workflow_activation_job, FireTimer, ResolveActivity},
+        workflow_commands::{
+            workflow_command, CompleteWorkflowExecution, ScheduleActivity, StartTimer,
+        },
+        workflow_completion::WorkflowActivationCompletion,
+    },
+    temporal::api::{
+        common::v1::WorkflowExecution,
+        enums::v1::EventType,
+        history::v1::{History, HistoryEvent},
+        workflowservice::v1::GetWorkflowExecutionHistoryResponse,
+    },
+};
+
+const CASES: u64 = 150;
+const CASE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[tokio::test]
+async fn fuzzed_histories_replay_cleanly() {
+    for seed in 0..CASES {
+        let outcome = run_case(seed, false).await;
+        assert!(
+            outcome.completed && outcome.failure.is_none() && !outcome.evicted,
+            "Seed {} did not replay cleanly: {:?}",
+            seed,
+            outcome
+        );
+    }
+}
+
+#[tokio::test]
+async fn fuzzed_deviations_evict_without_panicking() {
+    for seed in 0..CASES {
+        let outcome = run_case(seed, true).await;
+        // The deviation may land on a step with nothing to change, ex: dropping a command from
+        // the final step which only completes the workflow
+        if outcome.deviated {
+            assert!(
+                matches!(&outcome.failure, Some(f) if f.is_nondeterminism()) && outcome.evicted,
+                "Seed {} deviated without a nondeterminism eviction: {:?}",
+                seed,
+                outcome
+            );
+        } else {
+            assert!(
+                outcome.completed && outcome.failure.is_none() && !outcome.evicted,
+                "Seed {} did not deviate but did not replay cleanly: {:?}",
+                seed,
+                outcome
+            );
+        }
+    }
+}
+
+/// An operation the fuzzed workflow runs, and the sequence number it's started with
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Timer(u32),
+    Activity(u32),
+}
+
+/// Ways the workflow may behave differently from the history it's replaying
+#[derive(Debug, Clone, Copy)]
+enum Deviation {
+    DropCommand,
+    ExtraTimer,
+    SwapOpKind,
+}
+
+#[derive(Debug)]
+struct CaseOutcome {
+    completed: bool,
+    /// Whether the workflow's commands actually differed from the history's
+    deviated: bool,
+    evicted: bool,
+    failure: Option<ReplayFailure>,
+}
+
+/// Generate and replay the case for `seed`. If `deviate` is set, the workflow changes its
+/// commands at some point, as buggy or changed workflow code would.
+async fn run_case(seed: u64, deviate: bool) -> CaseOutcome {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let (t, steps) = gen_history(&mut rng);
+    let deviation = if deviate {
+        let kind = *[
+            Deviation::DropCommand,
+            Deviation::ExtraTimer,
+            Deviation::SwapOpKind,
+        ]
+        .choose(&mut rng)
+        .unwrap();
+        Some((rng.gen_range(0..=steps.len()), kind))
+    } else {
+        None
+    };
+    let pages = paginate(t.as_history().events, &mut rng);
+
+    let run = tokio::spawn(async move {
+        tokio::time::timeout(CASE_TIMEOUT, replay(&t, pages, steps, deviation)).await
+    });
+    match run.await {
+        Ok(Ok(outcome)) => outcome,
+        Ok(Err(_)) => panic!("Seed {} did not finish within {:?}", seed, CASE_TIMEOUT),
+        Err(e) => panic!("Seed {} panicked: {:?}", seed, e),
+    }
+}
+
+/// Builds a history for a workflow which runs a few steps, each of which starts some timers and
+/// activities and waits for all of them to resolve, before completing. Returns it along with the
+/// operations of each step.
+fn gen_history(rng: &mut StdRng) -> (TestHistoryBuilder, Vec<Vec<Op>>) {
+    let mut seq = 0;
+    let steps: Vec<Vec<Op>> = (0..rng.gen_range(1..=4))
+        .map(|_| {
+            (0..rng.gen_range(1..=4))
+                .map(|_| {
+                    seq += 1;
+                    if rng.gen_bool(0.5) {
+                        Op::Timer(seq)
+                    } else {
+                        Op::Activity(seq)
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut t = TestHistoryBuilder::default();
+    t.add_by_type(EventType::WorkflowExecutionStarted);
+    t.add_full_wf_task();
+    for step in &steps {
+        // The commands of the task which just completed, in the order they were issued
+        let mut scheduled: Vec<_> = step
+            .iter()
+            .map(|op| {
+                let id = match op {
+                    Op::Timer(seq) => t.add_timer_started(seq.to_string()),
+                    Op::Activity(seq) => t.add_activity_task_scheduled(seq.to_string()),
+                };
+                (*op, id)
+            })
+            .collect();
+        // Concurrent operations may resolve in any order, spread across any number of tasks
+        scheduled.shuffle(rng);
+        let num_ops = scheduled.len();
+        for (i, (op, scheduled_id)) in scheduled.into_iter().enumerate() {
+            if rng.gen_bool(0.2) {
+                t.add_we_signaled("fuzz", vec![]);
+                // A task for just the signal, in which the workflow does nothing
+                if rng.gen_bool(0.5) {
+                    add_wft(&mut t, rng);
+                }
+            }
+            match op {
+                Op::Timer(seq) => t.add_timer_fired(scheduled_id, seq.to_string()),
+                Op::Activity(_) => {
+                    let started_id = t.add_activity_task_started(scheduled_id);
+                    t.add_activity_task_completed(scheduled_id, started_id, Default::default());
+                }
+            }
+            if i == num_ops - 1 || rng.gen_bool(0.4) {
+                add_wft(&mut t, rng);
+            }
+        }
+    }
+    t.add_workflow_execution_completed();
+    (t, steps)
+}
+
+fn add_wft(t: &mut TestHistoryBuilder, rng: &mut StdRng) {
+    if rng.gen_bool(0.2) {
+        t.add_workflow_task_scheduled_and_started();
+        t.add_workflow_task_timed_out();
+    }
+    t.add_full_wf_task();
+}
+
+/// Split events into pages of random sizes
+fn paginate(mut events: Vec<HistoryEvent>, rng: &mut StdRng) -> Vec<Vec<HistoryEvent>> {
+    let mut pages = vec![];
+    while !events.is_empty() {
+        let rest = events.split_off(rng.gen_range(1..=events.len()));
+        pages.push(events);
+        events = rest;
+    }
+    pages
+}
+
+async fn replay(
+    t: &TestHistoryBuilder,
+    mut pages: Vec<Vec<HistoryEvent>>,
+    steps: Vec<Vec<Op>>,
+    deviation: Option<(usize, Deviation)>,
+) -> CaseOutcome {
+    let history = t.as_history();
+    let hist_info = HistoryInfo::new_from_history(&history, None).unwrap();
+    let run_id = hist_info.orig_run_id().to_string();
+    let last_event = history.last_event_id();
+
+    // The poll response carries the first page, the rest must be fetched
+    let mut wft = hist_info.as_poll_wft_response(TEST_Q);
+    wft.workflow_execution = Some(WorkflowExecution {
+        workflow_id: "fuzzed_wf".to_string(),
+        run_id: run_id.clone(),
+    });
+    wft.history = Some(History {
+        events: pages.remove(0),
+    });
+    wft.next_page_token = page_token(0, &pages);
+
+    let failure = Arc::new(Mutex::new(None));
+    let wft = Mutex::new(Some(wft));
+    let mock_failure = failure.clone();
+    let mut mg = mock_replay_client(
+        move || {
+            let wft = wft.lock().take();
+            async move { wft }.boxed()
+        },
+        |_| {},
+        move |failure| {
+            mock_failure.lock().get_or_insert(failure);
+        },
+    );
+    mg.expect_get_workflow_execution_history()
+        .returning(move |_, _, token| {
+            let ix: usize = String::from_utf8(token).unwrap().parse().unwrap();
+            let resp = GetWorkflowExecutionHistoryResponse {
+                history: Some(History {
+                    events: pages[ix].clone(),
+                }),
+                next_page_token: page_token(ix + 1, &pages),
+                ..Default::default()
+            };
+            async move { Ok(resp.into()) }.boxed()
+        });
+
+    let mut worker = new_replay_worker(
+        test_worker_cfg().build().unwrap(),
+        WorkerClientBag::new(Box::new(mg), "fuzz_namespace".to_string(), None),
+    );
+    let hook_failure = failure.clone();
+    worker.set_post_activate_hook(move |worker| {
+        if hook_failure.lock().is_some() || worker.run_reached_event(&run_id, last_event) {
+            worker.initiate_shutdown();
+        }
+    });
+
+    let mut wf = FuzzedWorkflow {
+        steps,
+        deviation,
+        next_step: 0,
+        unresolved: HashSet::new(),
+        completed: false,
+        deviated: false,
+    };
+    let mut evicted = false;
+    loop {
+        let act = match worker.poll_workflow_activation().await {
+            Err(PollWfError::ShutDown) => break,
+            other => other.expect("Polling must only ever produce activations or shut down"),
+        };
+        let mut cmds = vec![];
+        let mut resolved = false;
+        for job in &act.jobs {
+            match job
+                .variant
+                .as_ref()
+                .expect("Activation jobs must have a variant")
+            {
+                workflow_activation_job::Variant::StartWorkflow(_)
+                | workflow_activation_job::Variant::SignalWorkflow(_) => {}
+                workflow_activation_job::Variant::FireTimer(FireTimer { seq })
+                | workflow_activation_job::Variant::ResolveActivity(ResolveActivity {
+                    seq, ..
+                }) => {
+                    wf.unresolved.remove(seq);
+                    resolved = true;
+                }
+                workflow_activation_job::Variant::RemoveFromCache(_) => {
+                    assert_eq!(act.jobs.len(), 1, "Evictions must be alone: {}", act);
+                    evicted = true;
+                }
+                other => panic!("Unexpected job {:?}", other),
+            }
+        }
+        if !evicted && (resolved || wf.next_step == 0) && wf.unresolved.is_empty() {
+            cmds = wf.next_commands();
+        }
+        worker
+            .complete_workflow_activation(WorkflowActivationCompletion::from_cmds(act.run_id, cmds))
+            .await
+            .expect("Completions must be accepted");
+    }
+    worker.shutdown().await;
+
+    let failure = failure.lock().clone();
+    CaseOutcome {
+        completed: wf.completed,
+        deviated: wf.deviated,
+        evicted,
+        failure,
+    }
+}
+
+fn page_token(ix: usize, pages: &[Vec<HistoryEvent>]) -> Vec<u8> {
+    if ix < pages.len() {
+        ix.to_string().into_bytes()
+    } else {
+        vec![]
+    }
+}
+
+/// Stands in for lang's workflow code. Starts each step's operations at once, moving on to the next
+/// step when all of them have resolved, then completes.
+struct FuzzedWorkflow {
+    steps: Vec<Vec<Op>>,
+    deviation: Option<(usize, Deviation)>,
+    next_step: usize,
+    unresolved: HashSet<u32>,
+    completed: bool,
+    deviated: bool,
+}
+
+impl FuzzedWorkflow {
+    fn next_commands(&mut self) -> Vec<workflow_command::Variant> {
+        if self.completed {
+            return vec![];
+        }
+        let mut ops = self.steps.get(self.next_step).cloned().unwrap_or_default();
+        let mut extra = vec![];
+        match self.deviation {
+            Some((step, Deviation::DropCommand)) if step == self.next_step && !ops.is_empty() => {
+                ops.remove(0);
+                self.deviated = true;
+            }
+            Some((step, Deviation::ExtraTimer)) if step == self.next_step => {
+                extra.push(Op::Timer(1000));
+                self.deviated = true;
+            }
+            Some((step, Deviation::SwapOpKind)) if step == self.next_step => {
+                if let Some(op) = ops.first_mut() {
+                    *op = match *op {
+                        Op::Timer(seq) => Op::Activity(seq),
+                        Op::Activity(seq) => Op::Timer(seq),
+                    };
+                    self.deviated = true;
+                }
+            }
+            _ => {}
+        }
+        ops.extend(extra);
+        let mut cmds: Vec<workflow_command::Variant> = ops
+            .iter()
+            .map(|op| match *op {
+                Op::Timer(seq) => StartTimer {
+                    seq,
+                    start_to_fire_timeout: Some(Duration::from_secs(1).into()),
+                }
+                .into(),
+                Op::Activity(seq) => ScheduleActivity {
+                    seq,
+                    activity_id: seq.to_string(),
+                    start_to_close_timeout: Some(Duration::from_secs(5).into()),
+                    ..Default::default()
+                }
+                .into(),
+            })
+            .collect();
+        self.unresolved = ops
+            .iter()
+            .map(|op| match *op {
+                Op::Timer(seq) | Op::Activity(seq) => seq,
+            })
+            .collect();
+        if self.next_step == self.steps.len() {
+            cmds.push(CompleteWorkflowExecution { result: None }.into());
+            self.completed = true;
+        }
+        self.next_step += 1;
+        cmds
+    }
+}
@@ -1,21 +1,32 @@
 use crate::{
     replay::{default_wes_attribs, TestHistoryBuilder, DEFAULT_WORKFLOW_TYPE},
-    test_help::{mock_sdk, mock_sdk_cfg, MockPollCfg, ResponseType},
+    test_help::{
+        build_mock_pollers, mock_sdk, mock_sdk_cfg, mock_worker, MockPollCfg, ResponseType,
+    },
     worker::client::mocks::mock_workflow_client,
 };
 use anyhow::anyhow;
 use futures::future::join_all;
 use std::{
-    sync::atomic::{AtomicUsize, Ordering},
-    time::Duration,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 use temporal_client::WorkflowOptions;
 use temporal_sdk::{ActContext, LocalActivityOptions, WfContext, WorkflowResult};
+use temporal_sdk_core_api::Worker;
 use temporal_sdk_core_protos::{
-    coresdk::{common::RetryPolicy, AsJsonPayloadExt},
+    coresdk::{
+        common::RetryPolicy,
+        workflow_commands::{CompleteWorkflowExecution, ScheduleLocalActivity},
+        workflow_completion::WorkflowActivationCompletion,
+        AsJsonPayloadExt,
+    },
     temporal::api::{enums::v1::EventType, failure::v1::Failure},
 };
-use tokio::sync::Barrier;
+use tokio::sync::{Barrier, Notify};
 
 async fn echo(_ctx: ActContext, e: String) -> anyhow::Result<String> {
     Ok(e)
@@ -209,6 +220,72 @@ async fn local_act_heartbeat(#[case] shutdown_middle: bool) {
     runres.unwrap();
 }
 
+/// A workflow which completes while local activities are still running must not force a new
+/// workflow task when it heartbeats, since the workflow is closed and there can't be one
+#[tokio::test]
+async fn no_forced_wft_when_completing_with_local_acts_running() {
+    let mut t = TestHistoryBuilder::default();
+    let wft_timeout = Duration::from_secs(1);
+    let mut wes_short_wft_timeout = default_wes_attribs();
+    wes_short_wft_timeout.workflow_task_timeout = Some(wft_timeout.into());
+    t.add(
+        EventType::WorkflowExecutionStarted,
+        wes_short_wft_timeout.into(),
+    );
+    t.add_full_wf_task();
+    t.add_workflow_execution_completed();
+
+    let heartbeat_sent = Arc::new(Notify::new());
+    let hb_sent = heartbeat_sent.clone();
+    let mut mock = mock_workflow_client();
+    mock.expect_complete_workflow_task()
+        .times(1)
+        .returning(move |comp| {
+            assert!(!comp.force_create_new_workflow_task);
+            hb_sent.notify_one();
+            Ok(Default::default())
+        });
+    let mut mh = MockPollCfg::from_resp_batches("fakeid", t, [1], mock);
+    // Polling continues while waiting for the heartbeat deadline
+    mh.enforce_correct_number_of_polls = false;
+    let mut mh = build_mock_pollers(mh);
+    mh.worker_cfg(|wc| wc.max_cached_workflows = 1);
+    let core = mock_worker(mh);
+
+    let act = core.poll_workflow_activation().await.unwrap();
+    // Lang never runs the local activity, so it's still outstanding at the heartbeat deadline
+    let started = Instant::now();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmds(
+        act.run_id,
+        vec![
+            ScheduleLocalActivity {
+                seq: 1,
+                activity_id: "1".to_string(),
+                activity_type: "echo".to_string(),
+                start_to_close_timeout: Some(Duration::from_secs(60).into()),
+                ..Default::default()
+            }
+            .into(),
+            CompleteWorkflowExecution { result: None }.into(),
+        ],
+    ))
+    .await
+    .unwrap();
+    // Completing doesn't wait around for the local activity
+    assert!(started.elapsed() < wft_timeout / 2);
+    // Polling sends the heartbeat once the deadline arrives
+    tokio::select! {
+        _ = heartbeat_sent.notified() => {}
+        _ = async {
+            loop {
+                // The mock server errors once it runs out of responses
+                let _ = core.poll_workflow_activation().await;
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        } => {}
+    }
+}
+
 #[rstest::rstest]
 #[case::retry_then_pass(true)]
 #[case::retry_until_fail(false)]
@@ -460,7 +460,8 @@ async fn query_cache_miss_causes_page_fetch_dont_reply_wft_too_early(
                 Ok(GetWorkflowExecutionHistoryResponse {
                     history: Some(full_hist.clone().into()),
                     ..Default::default()
-                })
+                }
+                .into())
             });
     }
     mock_client
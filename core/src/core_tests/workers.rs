@@ -1,14 +1,19 @@
 use crate::{
+    init_worker_with_client, sticky_q_name_for_worker,
     test_help::{
-        build_fake_worker, build_mock_pollers, canned_histories, mock_manual_poller, mock_worker,
-        MockPollCfg, MockWorker, MocksHolder,
+        build_fake_worker, build_mock_pollers, build_multihist_mock_sg, canned_histories,
+        hist_to_poll_resp, mock_manual_poller, mock_poller_from_resps, mock_worker,
+        test_worker_cfg, FakeWfResponses, MockPollCfg, MockWorker, MocksHolder, ResponseType,
+        TEST_Q,
     },
     worker::client::mocks::mock_workflow_client,
-    PollActivityError, PollWfError,
+    MockManualWorkerClient, PollActivityError, PollWfError, Worker,
+};
+use futures::{FutureExt, StreamExt};
+use std::{cell::RefCell, sync::Arc, time::Duration};
+use temporal_sdk_core_api::{
+    errors::WorkerRestartError, worker::PollingState, Worker as WorkerTrait,
 };
-use futures::FutureExt;
-use std::{cell::RefCell, time::Duration};
-use temporal_sdk_core_api::Worker;
 use temporal_sdk_core_protos::{
     coresdk::{
         workflow_activation::workflow_activation_job,
@@ -23,7 +28,7 @@ use tokio::sync::{watch, Barrier};
 #[tokio::test]
 async fn after_shutdown_of_worker_get_shutdown_err() {
     let t = canned_histories::single_timer("1");
-    let worker = build_fake_worker("fake_wf_id", t, &[1]);
+    let worker = build_fake_worker("fake_wf_id", t, [1]);
     let res = worker.poll_workflow_activation().await.unwrap();
     assert_eq!(res.jobs.len(), 1);
     let run_id = res.run_id;
@@ -57,10 +62,40 @@ async fn after_shutdown_of_worker_get_shutdown_err() {
     });
 }
 
+#[tokio::test]
+async fn activation_stream_ends_after_shutdown() {
+    let t = canned_histories::single_timer("1");
+    let worker = build_fake_worker("fake_wf_id", t, [1]);
+    let mut activations = worker.workflow_activation_stream();
+    let res = activations.next().await.unwrap().unwrap();
+    assert_eq!(res.jobs.len(), 1);
+    let run_id = res.run_id;
+
+    tokio::join!(worker.shutdown(), async {
+        worker
+            .complete_workflow_activation(WorkflowActivationCompletion::from_cmds(
+                run_id.clone(),
+                vec![start_timer_cmd(1, Duration::from_secs(1))],
+            ))
+            .await
+            .unwrap();
+        let res = activations.next().await.unwrap().unwrap();
+        assert_matches!(
+            res.jobs[0].variant,
+            Some(workflow_activation_job::Variant::RemoveFromCache(_))
+        );
+        worker
+            .complete_workflow_activation(WorkflowActivationCompletion::empty(run_id.clone()))
+            .await
+            .unwrap();
+        assert!(activations.next().await.is_none());
+    });
+}
+
 #[tokio::test]
 async fn shutdown_worker_can_complete_pending_activation() {
     let t = canned_histories::single_timer("1");
-    let worker = build_fake_worker("fake_wf_id", t, &[2]);
+    let worker = build_fake_worker("fake_wf_id", t, [2]);
     let res = worker.poll_workflow_activation().await.unwrap();
     assert_eq!(res.jobs.len(), 1);
     // Complete the timer, will queue PA
@@ -208,3 +243,251 @@ async fn complete_with_task_not_found_during_shutdown() {
     // workflow task is marked complete as soon as we get not found back from the server.
     assert_eq!(&complete_order.into_inner(), &[1, 3, 2])
 }
+
+#[tokio::test]
+async fn worker_runs_against_public_mock_client() {
+    let t = canned_histories::single_timer("1");
+    let resp = hist_to_poll_resp(&t, "fake_wf_id".to_owned(), 1.into(), TEST_Q);
+    let mut client = MockManualWorkerClient::new();
+    let mut resp = Some(resp);
+    client.expect_poll_workflow_task().returning(move |_, _| {
+        match resp.take() {
+            Some(r) => async move { Ok(r) }.boxed(),
+            // Nothing else to do, just like an idle server
+            None => futures::future::pending().boxed(),
+        }
+    });
+    client
+        .expect_complete_workflow_task()
+        .times(1)
+        .returning(|_| async { Ok(RespondWorkflowTaskCompletedResponse::default()) }.boxed());
+    let worker = init_worker_with_client(
+        test_worker_cfg()
+            .max_cached_workflows(1_usize)
+            .no_remote_activities(true)
+            .build()
+            .unwrap(),
+        client,
+    );
+
+    let act = worker.poll_workflow_activation().await.unwrap();
+    assert_matches!(
+        act.jobs[0].variant,
+        Some(workflow_activation_job::Variant::StartWorkflow(_))
+    );
+    worker
+        .complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+            act.run_id,
+            start_timer_cmd(1, Duration::from_secs(1)),
+        ))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn no_new_wfts_polled_while_too_many_pending_activations() {
+    let hists = ["wf_1", "wf_2"].map(|wf_id| FakeWfResponses {
+        wf_id: wf_id.to_string(),
+        hist: canned_histories::single_timer("1"),
+        response_batches: vec![1.into()],
+    });
+    let mut mocks = build_multihist_mock_sg(hists, false, None);
+    mocks.worker_cfg(|w| w.max_pending_activations = 1);
+    let worker = mock_worker(mocks);
+
+    let act = worker.poll_workflow_activation().await.unwrap();
+    // The eviction can't be issued until the outstanding activation is completed, so it fills the
+    // pending activation queue
+    worker.request_workflow_eviction(&act.run_id);
+    assert!(
+        tokio::time::timeout(
+            Duration::from_millis(200),
+            worker.poll_workflow_activation()
+        )
+        .await
+        .is_err(),
+        "The other workflow's task should not have been polled for"
+    );
+
+    worker
+        .complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+            act.run_id.clone(),
+            start_timer_cmd(1, Duration::from_secs(1)),
+        ))
+        .await
+        .unwrap();
+    let evict = worker.poll_workflow_activation().await.unwrap();
+    assert_eq!(evict.run_id, act.run_id);
+    assert_matches!(
+        evict.jobs[0].variant,
+        Some(workflow_activation_job::Variant::RemoveFromCache(_))
+    );
+    worker
+        .complete_workflow_activation(WorkflowActivationCompletion::empty(act.run_id.clone()))
+        .await
+        .unwrap();
+    let other = worker.poll_workflow_activation().await.unwrap();
+    assert_ne!(other.run_id, act.run_id);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn completion_during_overloaded_wait_wakes_poll() {
+    let hists = ["wf_1", "wf_2"].map(|wf_id| FakeWfResponses {
+        wf_id: wf_id.to_string(),
+        hist: canned_histories::single_timer("1"),
+        response_batches: vec![1.into()],
+    });
+    let mut mocks = build_multihist_mock_sg(hists, false, None);
+    mocks.worker_cfg(|w| w.max_pending_activations = 1);
+    let worker = Arc::new(mock_worker(mocks));
+
+    let act = worker.poll_workflow_activation().await.unwrap();
+    worker.request_workflow_eviction(&act.run_id);
+    // Complete the activation from another thread while polling is waiting for the pending
+    // activation queue to drain
+    let completer = {
+        let worker = worker.clone();
+        let run_id = act.run_id.clone();
+        tokio::spawn(async move {
+            worker
+                .complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+                    run_id,
+                    start_timer_cmd(1, Duration::from_secs(1)),
+                ))
+                .await
+                .unwrap();
+        })
+    };
+    let evict = tokio::time::timeout(Duration::from_secs(5), worker.poll_workflow_activation())
+        .await
+        .expect("Completing the activation should have woken polling")
+        .unwrap();
+    completer.await.unwrap();
+    assert_eq!(evict.run_id, act.run_id);
+    assert_matches!(
+        evict.jobs[0].variant,
+        Some(workflow_activation_job::Variant::RemoveFromCache(_))
+    );
+}
+
+#[test]
+fn sticky_queue_name_follows_template() {
+    let default_cfg = test_worker_cfg()
+        .max_cached_workflows(10_usize)
+        .build()
+        .unwrap();
+    let default_name = sticky_q_name_for_worker("host-1", &default_cfg).unwrap();
+    assert!(default_name.starts_with(&format!("host-1-{}-", TEST_Q)));
+
+    let cfg = test_worker_cfg()
+        .max_cached_workflows(10_usize)
+        .sticky_queue_name_template("{task_queue}/{identity}")
+        .build()
+        .unwrap();
+    assert_eq!(
+        sticky_q_name_for_worker("host-1", &cfg).unwrap(),
+        format!("{}/host-1", TEST_Q)
+    );
+
+    let cfg = test_worker_cfg()
+        .max_cached_workflows(10_usize)
+        .sticky_queue_name_template("pod-a-sticky")
+        .build()
+        .unwrap();
+    assert_eq!(
+        sticky_q_name_for_worker("host-1", &cfg).unwrap(),
+        "pod-a-sticky"
+    );
+
+    assert!(test_worker_cfg()
+        .max_cached_workflows(10_usize)
+        .sticky_queue_name_template("")
+        .build()
+        .is_err());
+}
+
+#[test]
+fn zero_heartbeat_batch_flush_interval_is_rejected() {
+    assert!(test_worker_cfg()
+        .heartbeat_batch_flush_interval(Duration::ZERO)
+        .build()
+        .is_err());
+}
+
+#[tokio::test]
+async fn soft_restart_keeps_cached_workflows() {
+    let t = canned_histories::single_timer("1");
+    let mut client = mock_workflow_client();
+    client
+        .expect_complete_workflow_task()
+        .times(2)
+        .returning(|_| Ok(RespondWorkflowTaskCompletedResponse::default()));
+    let first_wft = hist_to_poll_resp(&t, "fake_wf_id".to_string(), 1.into(), TEST_Q);
+    // Only the new events are delivered after the restart, which can only be processed if the
+    // run is still cached
+    let second_wft = hist_to_poll_resp(
+        &t,
+        "fake_wf_id".to_string(),
+        ResponseType::OneTask(2),
+        TEST_Q,
+    );
+    let mut worker = Worker::new_with_pollers(
+        test_worker_cfg()
+            .max_cached_workflows(1_usize)
+            .no_remote_activities(true)
+            .build()
+            .unwrap(),
+        None,
+        Arc::new(client.into()),
+        mock_poller_from_resps([first_wft]),
+        None,
+        Default::default(),
+    );
+    worker.set_poller_factory(move || (mock_poller_from_resps([second_wft.clone()]), None));
+
+    let act = worker.poll_workflow_activation().await.unwrap();
+    assert_matches!(
+        act.jobs[0].variant,
+        Some(workflow_activation_job::Variant::StartWorkflow(_))
+    );
+    worker
+        .complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+            act.run_id,
+            start_timer_cmd(1, Duration::from_secs(1)),
+        ))
+        .await
+        .unwrap();
+
+    worker.soft_restart().await.unwrap();
+    assert_eq!(worker.status().polling, PollingState::Polling);
+    assert_eq!(worker.cached_workflows(), 1);
+
+    let act = worker.poll_workflow_activation().await.unwrap();
+    assert_matches!(
+        act.jobs[0].variant,
+        Some(workflow_activation_job::Variant::FireTimer(_))
+    );
+    worker
+        .complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+            act.run_id,
+            CompleteWorkflowExecution { result: None }.into(),
+        ))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn soft_restart_needs_recreatable_pollers() {
+    let worker = Worker::new_with_pollers(
+        test_worker_cfg().build().unwrap(),
+        None,
+        Arc::new(mock_workflow_client().into()),
+        mock_poller_from_resps([]),
+        None,
+        Default::default(),
+    );
+    assert_matches!(
+        worker.soft_restart().await,
+        Err(WorkerRestartError::Unsupported)
+    );
+}
@@ -1,5 +1,5 @@
 use crate::{
-    errors::PollWfError,
+    errors::{CompleteWfError, PollWfError},
     job_assert,
     replay::TestHistoryBuilder,
     test_help::{
@@ -15,10 +15,20 @@ use crate::{
 use rstest::{fixture, rstest};
 use std::{
     collections::VecDeque,
-    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
-    time::Duration,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use temporal_sdk_core_api::{
+    events::CoreEvent,
+    worker::{
+        NondeterminismReset, NondeterminismResetConfig, NondeterminismResetListener,
+        UnknownHistoryEventPolicy, WftFailureBackoffConfig,
+    },
+    Worker as WorkerTrait,
 };
-use temporal_sdk_core_api::Worker as WorkerTrait;
 use temporal_sdk_core_protos::{
     coresdk::{
         activity_result::{self as ar, activity_resolution, ActivityResolution},
@@ -33,11 +43,12 @@ use temporal_sdk_core_protos::{
         workflow_completion::WorkflowActivationCompletion,
     },
     temporal::api::{
-        enums::v1::{EventType, WorkflowTaskFailedCause},
+        enums::v1::{EventType, ResetReapplyType, WorkflowTaskFailedCause},
         failure::v1::Failure,
         history::v1::{history_event, History, TimerFiredEventAttributes},
         workflowservice::v1::{
-            GetWorkflowExecutionHistoryResponse, RespondWorkflowTaskCompletedResponse,
+            GetWorkflowExecutionHistoryResponse, ResetWorkflowExecutionResponse,
+            RespondWorkflowTaskCompletedResponse,
         },
     },
 };
@@ -590,7 +601,7 @@ async fn workflow_update_random_seed_on_workflow_reset() {
         timer_1_id.to_string().as_str(),
         new_run_id,
     );
-    let core = build_fake_worker(wfid, t, &[2]);
+    let core = build_fake_worker(wfid, t, [2]);
 
     poll_and_reply(
         &core,
@@ -644,7 +655,7 @@ async fn cancel_timer_before_sent_wf_bridge() {
     t.add_full_wf_task();
     t.add_workflow_execution_completed();
 
-    let core = build_fake_worker(wfid, t, &[1]);
+    let core = build_fake_worker(wfid, t, [1]);
 
     poll_and_reply(
         &core,
@@ -916,6 +927,62 @@ async fn max_concurrent_wft_respected() {
     core.shutdown().await;
 }
 
+#[tokio::test]
+async fn cache_full_emits_core_events() {
+    let mh = MockPollCfg::new(
+        vec![
+            FakeWfResponses {
+                wf_id: "wf1".to_string(),
+                hist: canned_histories::single_timer("1"),
+                response_batches: vec![ResponseType::ToTaskNum(1)],
+            },
+            FakeWfResponses {
+                wf_id: "wf2".to_string(),
+                hist: canned_histories::single_timer("1"),
+                response_batches: vec![ResponseType::ToTaskNum(1)],
+            },
+        ],
+        false,
+        None,
+    );
+    let mut mock = build_mock_pollers(mh);
+    mock.worker_cfg(|cfg| {
+        cfg.max_cached_workflows = 1;
+        cfg.max_outstanding_workflow_tasks = 1;
+    });
+    let core = mock_worker(mock);
+    let mut events = core.subscribe_events();
+
+    let r1 = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        r1.run_id.clone(),
+        start_timer_cmd(1, Duration::from_secs(1)),
+    ))
+    .await
+    .unwrap();
+    // The second workflow doesn't fit in the cache, so the first is evicted to make room
+    let eviction = core.poll_workflow_activation().await.unwrap();
+    assert_eq!(eviction.run_id, r1.run_id);
+    assert!(eviction.eviction_reason().is_some());
+    core.complete_workflow_activation(WorkflowActivationCompletion::empty(eviction.run_id))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        events.try_recv().unwrap(),
+        CoreEvent::CacheFull {
+            evicting_run_id: r1.run_id.clone()
+        }
+    );
+    assert_matches!(
+        events.try_recv().unwrap(),
+        CoreEvent::EvictionRequested { run_id, reason: EvictionReason::CacheFull, .. }
+        if run_id == r1.run_id
+    );
+    assert_matches!(events.try_recv().unwrap(), CoreEvent::WftBuffered { .. });
+    core.shutdown().await;
+}
+
 #[rstest(hist_batches, case::incremental(&[1, 2]), case::replay(&[3]))]
 #[tokio::test]
 async fn activity_not_canceled_on_replay_repro(hist_batches: &'static [usize]) {
@@ -1009,7 +1076,7 @@ async fn activity_not_canceled_when_also_completed_repro(hist_batches: &'static
 
 #[tokio::test]
 async fn lots_of_workflows() {
-    let hists = (0..500).into_iter().map(|i| {
+    let hists = (0..500).map(|i| {
         let wf_id = format!("fake-wf-{}", i);
         let hist = canned_histories::single_timer("1");
         FakeWfResponses {
@@ -1098,7 +1165,7 @@ async fn complete_after_eviction() {
     let t = canned_histories::single_timer("1");
     let mut mock = mock_workflow_client();
     mock.expect_complete_workflow_task().times(0);
-    let mock = single_hist_mock_sg(wfid, t, &[2], mock, true);
+    let mock = single_hist_mock_sg(wfid, t, [2], mock, true);
     let core = mock_worker(mock);
 
     let activation = core.poll_workflow_activation().await.unwrap();
@@ -1135,6 +1202,67 @@ async fn complete_after_eviction() {
     core.shutdown().await;
 }
 
+#[tokio::test]
+async fn completions_after_eviction_are_ignored() {
+    let wfid = "fake_wf_id";
+    let t = canned_histories::single_timer("1");
+    let mut mock = mock_workflow_client();
+    mock.expect_complete_workflow_task().times(0);
+    mock.expect_fail_workflow_task().times(0);
+    let mock = single_hist_mock_sg(wfid, t, [1], mock, true);
+    let core = mock_worker(mock);
+
+    let activation = core.poll_workflow_activation().await.unwrap();
+    let run_id = activation.run_id;
+    core.request_workflow_eviction(&run_id);
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        run_id.clone(),
+        start_timer_cmd(1, Duration::from_secs(1)),
+    ))
+    .await
+    .unwrap();
+    let eviction = core.poll_workflow_activation().await.unwrap();
+    assert_matches!(
+        eviction.jobs.as_slice(),
+        [WorkflowActivationJob {
+            variant: Some(workflow_activation_job::Variant::RemoveFromCache(_)),
+        }]
+    );
+    core.complete_workflow_activation(WorkflowActivationCompletion::empty(run_id.clone()))
+        .await
+        .unwrap();
+
+    let permits = core.available_wft_permits();
+
+    // Lang racing the eviction may complete the run's activations late, successfully or not
+    let late_success = core
+        .complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+            run_id.clone(),
+            start_timer_cmd(1, Duration::from_secs(1)),
+        ))
+        .await;
+    assert_matches!(
+        late_success,
+        Err(CompleteWfError::ActivationNotOutstanding { run_id: rid }) if rid == run_id
+    );
+    let late_failure = core
+        .complete_workflow_activation(WorkflowActivationCompletion::fail(
+            run_id.clone(),
+            Failure {
+                message: "Oh noooo".to_string(),
+                ..Default::default()
+            },
+        ))
+        .await;
+    assert_matches!(
+        late_failure,
+        Err(CompleteWfError::ActivationNotOutstanding { .. })
+    );
+    // Neither completion handed back a workflow task permit
+    assert_eq!(core.available_wft_permits(), permits);
+    core.shutdown().await;
+}
+
 #[tokio::test]
 async fn sends_appropriate_sticky_task_queue_responses() {
     // This test verifies that when completions are sent with sticky queues enabled, that they
@@ -1147,7 +1275,7 @@ async fn sends_appropriate_sticky_task_queue_responses() {
         .times(1)
         .returning(|_| Ok(Default::default()));
     mock.expect_complete_workflow_task().times(0);
-    let mut mock = single_hist_mock_sg(wfid, t, &[1], mock, false);
+    let mut mock = single_hist_mock_sg(wfid, t, [1], mock, false);
     mock.worker_cfg(|wc| wc.max_cached_workflows = 10);
     let core = mock_worker(mock);
 
@@ -1165,7 +1293,7 @@ async fn sends_appropriate_sticky_task_queue_responses() {
 async fn new_server_work_while_eviction_outstanding_doesnt_overwrite_activation() {
     let wfid = "fake_wf_id";
     let t = canned_histories::single_timer("1");
-    let mock = single_hist_mock_sg(wfid, t, &[1, 2], mock_workflow_client(), false);
+    let mock = single_hist_mock_sg(wfid, t, [1, 2], mock_workflow_client(), false);
     let core = mock_worker(mock);
 
     // Poll for and complete first workflow task
@@ -1217,15 +1345,10 @@ async fn buffered_work_drained_on_shutdown() {
     let mut tasks = VecDeque::from(vec![resp_1]);
     // Extend the task list with the now timeout-included version of the task. We add a bunch of
     // them because the poll loop will spin while new tasks are available and it is buffering them
-    tasks.extend(
-        std::iter::repeat(hist_to_poll_resp(
-            &t,
-            wfid.to_owned(),
-            2.into(),
-            TEST_Q.to_string(),
-        ))
-        .take(50),
-    );
+    tasks.extend(std::iter::repeat_n(
+        hist_to_poll_resp(&t, wfid.to_owned(), 2.into(), TEST_Q.to_string()),
+        50,
+    ));
     let mut mock = mock_workflow_client();
     mock.expect_complete_workflow_task()
         .returning(|_| Ok(RespondWorkflowTaskCompletedResponse::default()));
@@ -1330,6 +1453,96 @@ async fn fail_wft_then_recover() {
     core.shutdown().await;
 }
 
+#[derive(Debug, Default)]
+struct RecordingResetListener {
+    resets: parking_lot::Mutex<Vec<NondeterminismReset>>,
+}
+impl NondeterminismResetListener for RecordingResetListener {
+    fn on_reset(&self, reset: &NondeterminismReset) {
+        self.resets.lock().push(reset.clone());
+    }
+}
+
+#[tokio::test]
+async fn nondeterminism_resets_run_when_configured() {
+    let t = canned_histories::long_sequential_timers(1);
+    let mut mock_client = mock_workflow_client();
+    mock_client
+        .expect_reset_workflow_execution()
+        .times(1)
+        .withf(|wf_id, _, finish_id, _, reapply| {
+            wf_id == "fake_wf_id" && *finish_id == 4 && *reapply == ResetReapplyType::None
+        })
+        .returning(|_, _, _, _, _| {
+            Ok(ResetWorkflowExecutionResponse {
+                run_id: "new_run".to_string(),
+            })
+        });
+    let mut mh = MockPollCfg::from_resp_batches(
+        "fake_wf_id",
+        t,
+        [ResponseType::AllHistory, ResponseType::AllHistory],
+        mock_client,
+    );
+    mh.num_expected_fails = Some(1);
+    let mut mock = build_mock_pollers(mh);
+    let listener = Arc::new(RecordingResetListener::default());
+    let listener_clone = listener.clone();
+    mock.worker_cfg(|wc| {
+        wc.max_cached_workflows = 2;
+        wc.reset_on_nondeterminism = Some(NondeterminismResetConfig {
+            reapply_type: ResetReapplyType::None,
+            listener: Some(listener_clone),
+        });
+    });
+    let core = mock_worker(mock);
+
+    let act = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmds(
+        act.run_id.clone(),
+        vec![ScheduleActivity {
+            activity_id: "fake_activity".to_string(),
+            ..Default::default()
+        }
+        .into()],
+    ))
+    .await
+    .unwrap();
+    let resets = listener.resets.lock().clone();
+    assert_matches!(resets.as_slice(), [reset] => {
+        assert_eq!(reset.run_id, act.run_id);
+        assert_eq!(reset.new_run_id, "new_run");
+        assert_eq!(reset.workflow_task_finish_event_id, 4);
+    });
+
+    let evict_act = core.poll_workflow_activation().await.unwrap();
+    assert_matches!(
+        evict_act.jobs.as_slice(),
+        [WorkflowActivationJob {
+            variant: Some(workflow_activation_job::Variant::RemoveFromCache(_)),
+        }]
+    );
+    core.complete_workflow_activation(WorkflowActivationCompletion::empty(evict_act.run_id))
+        .await
+        .unwrap();
+    // The mock server knows nothing of the reset, so finish the run as it delivers it again
+    let act = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmds(
+        act.run_id,
+        vec![start_timer_cmd(1, Duration::from_secs(1))],
+    ))
+    .await
+    .unwrap();
+    let act = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmds(
+        act.run_id,
+        vec![CompleteWorkflowExecution { result: None }.into()],
+    ))
+    .await
+    .unwrap();
+    core.shutdown().await;
+}
+
 #[tokio::test]
 async fn poll_response_triggers_wf_error() {
     let mut t = TestHistoryBuilder::default();
@@ -1432,7 +1645,7 @@ async fn tries_cancel_of_completed_activity() {
     t.add_workflow_task_scheduled_and_started();
 
     let mock = mock_workflow_client();
-    let mut mock = single_hist_mock_sg("fake_wf_id", t, &[1, 2], mock, true);
+    let mut mock = single_hist_mock_sg("fake_wf_id", t, [1, 2], mock, true);
     mock.worker_cfg(|cfg| cfg.max_cached_workflows = 1);
     let core = mock_worker(mock);
 
@@ -1557,7 +1770,7 @@ async fn cache_miss_will_fetch_history() {
     mh.mock_client
         .expect_get_workflow_execution_history()
         .times(1)
-        .returning(move |_, _, _| Ok(get_exec_resp.clone()));
+        .returning(move |_, _, _| Ok(get_exec_resp.clone().into()));
     let mut mock = build_mock_pollers(mh);
     mock.worker_cfg(|cfg| {
         cfg.max_cached_workflows = 1;
@@ -1692,7 +1905,8 @@ async fn evict_missing_wf_during_poll_doesnt_eat_permit() {
                 raw_history: vec![],
                 next_page_token: vec![],
                 archived: false,
-            })
+            }
+            .into())
         });
     let mut mock = MocksHolder::from_client_with_responses(mock, tasks, []);
     mock.worker_cfg(|wc| {
@@ -1844,7 +2058,7 @@ async fn eviction_waits_until_replay_finished() {
     let wfid = "fake_wf_id";
     let t = canned_histories::long_sequential_timers(3);
     let mock = mock_workflow_client();
-    let mock = single_hist_mock_sg(wfid, t, &[3], mock, true);
+    let mock = single_hist_mock_sg(wfid, t, [3], mock, true);
     let core = mock_worker(mock);
 
     let activation = core.poll_workflow_activation().await.unwrap();
@@ -1893,3 +2107,346 @@ async fn eviction_waits_until_replay_finished() {
 
     core.shutdown().await;
 }
+
+#[tokio::test]
+async fn trace_context_propagates_from_start_headers_to_scheduled_activities() {
+    use crate::telemetry::propagation::TRACING_HEADER_KEY;
+    use opentelemetry::trace::TracerProvider;
+    use parking_lot::Mutex;
+    use std::collections::HashMap;
+    use temporal_sdk_core_protos::{
+        coresdk::{AsJsonPayloadExt, FromJsonPayloadExt},
+        default_wes_attribs,
+        temporal::api::command::v1::command,
+    };
+    use tracing_subscriber::layer::SubscriberExt;
+
+    // Tracers only hold a weak reference to their provider, so it must be kept alive
+    let provider = opentelemetry::sdk::trace::TracerProvider::builder().build();
+    let tracer = provider.tracer("test");
+    let subscriber =
+        tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let trace_id = "0af7651916cd43dd8448eb211c80319c";
+    let start_carrier = HashMap::from([(
+        "traceparent".to_string(),
+        format!("00-{}-b7ad6b7169203331-01", trace_id),
+    )]);
+    let mut t = TestHistoryBuilder::default();
+    let mut start_attrs = default_wes_attribs();
+    start_attrs.header = Some(
+        HashMap::from([(
+            TRACING_HEADER_KEY.to_string(),
+            start_carrier.as_json_payload().unwrap(),
+        )])
+        .into(),
+    );
+    t.add(EventType::WorkflowExecutionStarted, start_attrs.into());
+    t.add_workflow_task_scheduled_and_started();
+
+    let sent_carrier = Arc::new(Mutex::new(None));
+    let sent_carrier_c = sent_carrier.clone();
+    let mut mock_client = mock_workflow_client();
+    mock_client
+        .expect_complete_workflow_task()
+        .times(1)
+        .returning(move |c| {
+            if let Some(command::Attributes::ScheduleActivityTaskCommandAttributes(a)) =
+                c.commands[0].attributes.clone()
+            {
+                let header = a.header.unwrap().fields.remove(TRACING_HEADER_KEY);
+                *sent_carrier_c.lock() =
+                    header.map(|p| HashMap::<String, String>::from_json_payload(&p.into()));
+            }
+            Ok(Default::default())
+        });
+    let core = mock_worker(MocksHolder::from_client_with_responses(
+        mock_client,
+        [hist_to_poll_resp(
+            &t,
+            "fake_wf_id".to_string(),
+            1.into(),
+            TEST_Q.to_string(),
+        )],
+        [],
+    ));
+
+    let activation = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        activation.run_id,
+        ScheduleActivity {
+            seq: 1,
+            activity_id: "act1".to_string(),
+            activity_type: "act".to_string(),
+            start_to_close_timeout: Some(prost_types::Duration::from(Duration::from_secs(60))),
+            ..Default::default()
+        }
+        .into(),
+    ))
+    .await
+    .unwrap();
+
+    let sent_carrier = sent_carrier.lock().take().unwrap().unwrap();
+    let traceparent = &sent_carrier["traceparent"];
+    // The activity is scheduled in the same trace, but under core's own spans
+    assert!(traceparent.contains(trace_id));
+    assert_ne!(traceparent, &start_carrier["traceparent"]);
+    core.shutdown().await;
+}
+
+#[tokio::test]
+async fn header_interceptor_sees_inbound_and_outbound_headers() {
+    use std::collections::HashMap;
+    use temporal_sdk_core_api::worker::HeaderInterceptor;
+    use temporal_sdk_core_protos::{
+        coresdk::common::Payload, default_wes_attribs, temporal::api::command::v1::command,
+    };
+
+    #[derive(Debug)]
+    struct TenantInterceptor;
+    impl HeaderInterceptor for TenantInterceptor {
+        fn on_outbound(&self, headers: &mut HashMap<String, Payload>) {
+            headers.insert("tenant".to_string(), b"acme".into());
+        }
+        fn on_inbound(&self, headers: &mut HashMap<String, Payload>) {
+            if let Some(tenant) = headers.remove("tenant") {
+                headers.insert("extracted-tenant".to_string(), tenant);
+            }
+        }
+    }
+
+    let mut t = TestHistoryBuilder::default();
+    let mut start_attrs = default_wes_attribs();
+    let start_headers: HashMap<String, Payload> =
+        HashMap::from([("tenant".to_string(), b"acme".into())]);
+    start_attrs.header = Some(start_headers.into());
+    t.add(EventType::WorkflowExecutionStarted, start_attrs.into());
+    t.add_workflow_task_scheduled_and_started();
+
+    let mut mock_client = mock_workflow_client();
+    mock_client
+        .expect_complete_workflow_task()
+        .withf(|c| {
+            matches!(
+                &c.commands[0].attributes,
+                Some(command::Attributes::ScheduleActivityTaskCommandAttributes(a))
+                    if a.header.as_ref().unwrap().fields["tenant"].data == b"acme".as_slice()
+            )
+        })
+        .times(1)
+        .returning(|_| Ok(Default::default()));
+    let mut mh = MocksHolder::from_client_with_responses(
+        mock_client,
+        [hist_to_poll_resp(
+            &t,
+            "fake_wf_id".to_string(),
+            1.into(),
+            TEST_Q.to_string(),
+        )],
+        [],
+    );
+    mh.worker_cfg(|wc| wc.header_interceptor = Some(Arc::new(TenantInterceptor)));
+    let core = mock_worker(mh);
+
+    let activation = core.poll_workflow_activation().await.unwrap();
+    assert_matches!(
+        activation.jobs[0].variant.as_ref(),
+        Some(workflow_activation_job::Variant::StartWorkflow(sw))
+            if sw.headers["extracted-tenant"].data == b"acme".as_slice() && !sw.headers.contains_key("tenant")
+    );
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        activation.run_id,
+        ScheduleActivity {
+            seq: 1,
+            activity_id: "act1".to_string(),
+            activity_type: "act".to_string(),
+            start_to_close_timeout: Some(prost_types::Duration::from(Duration::from_secs(60))),
+            ..Default::default()
+        }
+        .into(),
+    ))
+    .await
+    .unwrap();
+    core.shutdown().await;
+}
+
+#[tokio::test]
+async fn retried_wfts_are_backed_off_and_expose_attempt() {
+    let t = canned_histories::single_timer("1");
+    let mut resp = hist_to_poll_resp(&t, "fake_wf_id".to_string(), 1.into(), TEST_Q.to_string());
+    resp.attempt = 3;
+    let mut mh = MocksHolder::from_client_with_responses(mock_workflow_client(), [resp], []);
+    mh.worker_cfg(|wc| {
+        wc.wft_failure_backoff = Some(WftFailureBackoffConfig {
+            initial_interval: Duration::from_millis(100),
+            backoff_coefficient: 2.0,
+            max_interval: Duration::from_secs(1),
+        })
+    });
+    let core = mock_worker(mh);
+
+    let started = Instant::now();
+    // The mock poller errors once out of responses, which lang retries, while the task waits
+    let activation = loop {
+        match core.poll_workflow_activation().await {
+            Err(PollWfError::TonicError(_)) => tokio::time::sleep(Duration::from_millis(10)).await,
+            res => break res.unwrap(),
+        }
+    };
+    // The third attempt is held back for twice the initial interval
+    assert!(started.elapsed() >= Duration::from_millis(200));
+    assert_eq!(activation.workflow_task_attempt, 3);
+}
+
+#[tokio::test]
+async fn wft_backoff_capped_below_task_timeout() {
+    let t = canned_histories::single_timer("1");
+    let mut resp = hist_to_poll_resp(&t, "fake_wf_id".to_string(), 1.into(), TEST_Q.to_string());
+    resp.attempt = 3;
+    for e in resp.history.as_mut().unwrap().events.iter_mut() {
+        if let Some(history_event::Attributes::WorkflowTaskScheduledEventAttributes(a)) =
+            e.attributes.as_mut()
+        {
+            a.start_to_close_timeout = Some(Duration::from_millis(500).into());
+        }
+    }
+    let mut mh = MocksHolder::from_client_with_responses(mock_workflow_client(), [resp], []);
+    mh.worker_cfg(|wc| {
+        wc.wft_failure_backoff = Some(WftFailureBackoffConfig {
+            initial_interval: Duration::from_secs(30),
+            backoff_coefficient: 2.0,
+            max_interval: Duration::from_secs(60),
+        })
+    });
+    let core = mock_worker(mh);
+
+    let started = Instant::now();
+    let activation = loop {
+        match core.poll_workflow_activation().await {
+            Err(PollWfError::TonicError(_)) => tokio::time::sleep(Duration::from_millis(10)).await,
+            res => break res.unwrap(),
+        }
+    };
+    // Held back for a fifth of the 500ms timeout, rather than a minute
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(100));
+    assert!(elapsed < Duration::from_secs(5));
+    assert_eq!(activation.workflow_task_attempt, 3);
+}
+
+#[test]
+fn wft_failure_backoff_grows_to_max() {
+    let backoff = WftFailureBackoffConfig::default();
+    assert_eq!(backoff.backoff_for(1), Duration::ZERO);
+    assert_eq!(backoff.backoff_for(2), Duration::from_secs(1));
+    assert_eq!(backoff.backoff_for(4), Duration::from_secs(4));
+    assert_eq!(backoff.backoff_for(100), Duration::from_secs(60));
+}
+
+#[tokio::test]
+async fn oversized_command_payloads_fail_wft() {
+    use temporal_sdk_core_protos::coresdk::AsJsonPayloadExt;
+
+    let t = canned_histories::single_timer("1");
+    let mut mock_client = mock_workflow_client();
+    mock_client.expect_complete_workflow_task().times(0);
+    mock_client
+        .expect_fail_workflow_task()
+        .withf(|_, cause, f| {
+            *cause == WorkflowTaskFailedCause::Unspecified
+                && f.as_ref().unwrap().message.starts_with(
+                    "PayloadTooLarge: payloads of command #0 (ScheduleActivityTask, activity_id \
+                     act1)",
+                )
+        })
+        .times(1)
+        .returning(|_, _, _| Ok(Default::default()));
+    let mut mock = MocksHolder::from_client_with_responses(
+        mock_client,
+        [hist_to_poll_resp(
+            &t,
+            "fake_wf_id".to_string(),
+            1.into(),
+            TEST_Q.to_string(),
+        )],
+        [],
+    );
+    mock.worker_cfg(|cfg| cfg.payload_size_limit_bytes = Some(1024));
+    let core = mock_worker(mock);
+
+    let activation = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        activation.run_id.clone(),
+        ScheduleActivity {
+            seq: 1,
+            activity_id: "act1".to_string(),
+            activity_type: "act".to_string(),
+            arguments: vec!["a".repeat(2048).as_json_payload().unwrap()],
+            start_to_close_timeout: Some(prost_types::Duration::from(Duration::from_secs(60))),
+            ..Default::default()
+        }
+        .into(),
+    ))
+    .await
+    .unwrap();
+
+    // The run is evicted, telling lang why
+    let activation = core.poll_workflow_activation().await.unwrap();
+    assert_matches!(
+        activation.jobs.as_slice(),
+        [WorkflowActivationJob {
+            variant: Some(workflow_activation_job::Variant::RemoveFromCache(r)),
+        }] if r.message.contains("PayloadTooLarge")
+    );
+}
+
+#[rstest]
+#[case::fail_task(UnknownHistoryEventPolicy::FailTask)]
+#[case::skip(UnknownHistoryEventPolicy::Skip)]
+#[case::skip_with_warning(UnknownHistoryEventPolicy::SkipWithWarning)]
+#[tokio::test]
+async fn unknown_history_events_follow_policy(#[case] policy: UnknownHistoryEventPolicy) {
+    let wfid = "fake_wf_id";
+    let mut t = TestHistoryBuilder::default();
+    t.add_by_type(EventType::WorkflowExecutionStarted);
+    // Stands in for an event added by a newer server, see below
+    t.add_we_signaled("placeholder", vec![]);
+    t.add_full_wf_task();
+    t.add_workflow_execution_completed();
+    let mut resp = hist_to_poll_resp(&t, wfid.to_owned(), ResponseType::AllHistory, TEST_Q);
+    let unknown_event = &mut resp.history.as_mut().unwrap().events[1];
+    unknown_event.event_type = 9999;
+    unknown_event.attributes = None;
+
+    let skips = policy != UnknownHistoryEventPolicy::FailTask;
+    let mut mock_client = mock_workflow_client();
+    mock_client
+        .expect_complete_workflow_task()
+        .times(usize::from(skips))
+        .returning(|_| Ok(Default::default()));
+    let mut mh = MocksHolder::from_client_with_responses(mock_client, [resp], []);
+    mh.worker_cfg(|wc| wc.unknown_history_event_policy = policy);
+    let core = mock_worker(mh);
+
+    let act = core.poll_workflow_activation().await;
+    if skips {
+        let act = act.unwrap();
+        assert_matches!(
+            act.jobs.as_slice(),
+            [WorkflowActivationJob {
+                variant: Some(workflow_activation_job::Variant::StartWorkflow(_)),
+            }]
+        );
+        core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+            act.run_id,
+            CompleteWorkflowExecution { result: None }.into(),
+        ))
+        .await
+        .unwrap();
+    } else {
+        // Applying the poll response fails, so core evicts the run and polls again
+        assert_matches!(act, Err(PollWfError::TonicError(err))
+                        if err.message() == NO_MORE_WORK_ERROR_MSG);
+    }
+}
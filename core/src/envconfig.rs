@@ -0,0 +1,441 @@
+//! Loads client and worker options from a TOML config file and environment variables, so that
+//! every lang SDK configures connections the same way.
+//!
+//! Config files hold named profiles:
+//!
+//! ```toml
+//! [profile.default]
+//! address = "localhost:7233"
+//! namespace = "default"
+//! task_queue = "my-queue"
+//!
+//! [profile.prod]
+//! address = "my-ns.tmprl.cloud:7233"
+//! namespace = "my-ns"
+//! api_key = "..."
+//!
+//! [profile.prod.tls]
+//! server_ca_cert_path = "/path/to/ca.pem"
+//! client_cert_path = "/path/to/client.pem"
+//! client_key_path = "/path/to/client.key"
+//! ```
+//!
+//! Settings are taken, from lowest to highest precedence, from the profile in the config file,
+//! then from `TEMPORAL_*` environment variables, and lastly from whatever lang sets on the builders
+//! this module returns.
+
+use crate::WorkerConfigBuilder;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use temporal_client::{ApiKey, ClientOptionsBuilder, ClientTlsConfig, TlsConfig};
+use url::Url;
+
+/// Environment variable naming the config file to load, if not provided explicitly
+pub const CONFIG_FILE_ENV_VAR: &str = "TEMPORAL_CONFIG_FILE";
+/// Environment variable naming the profile to load, if not provided explicitly
+pub const PROFILE_ENV_VAR: &str = "TEMPORAL_PROFILE";
+/// The profile loaded if none is named
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Errors loading a [ConfigProfile]
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigLoadError {
+    /// A file couldn't be read
+    #[error("Could not read {path}: {source}")]
+    Io {
+        /// The file which couldn't be read
+        path: PathBuf,
+        /// Why
+        source: std::io::Error,
+    },
+    /// The config file isn't valid
+    #[error("Invalid config file {path}: {source}")]
+    InvalidFile {
+        /// The invalid file
+        path: PathBuf,
+        /// Why it is invalid
+        source: toml::de::Error,
+    },
+    /// A profile was explicitly asked for, but isn't in the config file
+    #[error("Profile {0} not found in config file")]
+    ProfileNotFound(String),
+    /// An environment variable has a value which doesn't make sense for it
+    #[error("Environment variable {var} has invalid value {value:?}")]
+    InvalidEnvVar {
+        /// The variable
+        var: String,
+        /// Its value
+        value: String,
+    },
+    /// The configured server address can't be made into a URL
+    #[error("Invalid server address {0:?}")]
+    InvalidAddress(String),
+    /// An API key is set but TLS was explicitly turned off, which would send the key in plain text
+    #[error("An API key cannot be used with TLS disabled")]
+    ApiKeyWithoutTls,
+}
+
+/// Where to load a [ConfigProfile] from. Anything unset is determined from the environment.
+#[derive(Debug, Clone, Default)]
+pub struct LoadConfigOptions {
+    /// The config file to read. If unset, the file named by [CONFIG_FILE_ENV_VAR] is read, and
+    /// otherwise `temporalio/temporal.toml` in the user's config directory, if it exists.
+    pub config_file: Option<PathBuf>,
+    /// The profile to use. If unset, the one named by [PROFILE_ENV_VAR] is used, and otherwise
+    /// [DEFAULT_PROFILE].
+    pub profile: Option<String>,
+    /// Environment variables to use instead of the process' own. Mainly useful for tests.
+    pub env_override: Option<HashMap<String, String>>,
+}
+
+/// Connection and worker settings, after combining a config file profile with the environment
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigProfile {
+    /// Server address, either `host:port` or a URL. Env: `TEMPORAL_ADDRESS`
+    pub address: Option<String>,
+    /// Namespace. Env: `TEMPORAL_NAMESPACE`
+    pub namespace: Option<String>,
+    /// API key sent as a bearer token with every call. TLS is used by default if set.
+    /// Env: `TEMPORAL_API_KEY`
+    pub api_key: Option<String>,
+    /// Task queue workers poll by default. Env: `TEMPORAL_TASK_QUEUE`
+    pub task_queue: Option<String>,
+    /// TLS settings
+    pub tls: Option<TlsProfile>,
+}
+
+/// TLS settings of a [ConfigProfile]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsProfile {
+    /// Explicitly turns TLS on or off. If unset, TLS is used when any other TLS setting or an API
+    /// key is. Env: `TEMPORAL_TLS`
+    pub enabled: Option<bool>,
+    /// File with the server's root CA certificate. Env: `TEMPORAL_TLS_SERVER_CA_CERT_PATH`
+    pub server_ca_cert_path: Option<PathBuf>,
+    /// Domain name the server's certificate is verified against. Env:
+    /// `TEMPORAL_TLS_SERVER_NAME`
+    pub server_name: Option<String>,
+    /// File with the client's certificate, for mTLS. Env: `TEMPORAL_TLS_CLIENT_CERT_PATH`
+    pub client_cert_path: Option<PathBuf>,
+    /// File with the client's private key, for mTLS. Env: `TEMPORAL_TLS_CLIENT_KEY_PATH`
+    pub client_key_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    profile: HashMap<String, ConfigProfile>,
+}
+
+impl ConfigProfile {
+    /// Load a profile as described by `options`. A missing config file is not an error unless it
+    /// was named explicitly, and neither is a missing profile unless it was.
+    pub fn load(options: LoadConfigOptions) -> Result<Self, ConfigLoadError> {
+        let env = |var: &str| match &options.env_override {
+            Some(vars) => vars.get(var).cloned(),
+            None => std::env::var(var).ok(),
+        };
+        let explicit_file = options
+            .config_file
+            .clone()
+            .or_else(|| env(CONFIG_FILE_ENV_VAR).map(PathBuf::from));
+        let file = match &explicit_file {
+            Some(path) => Some(read_config_file(path)?),
+            None => match default_config_file(&env).filter(|p| p.exists()) {
+                Some(path) => Some(read_config_file(&path)?),
+                None => None,
+            },
+        };
+        let explicit_profile = options.profile.clone().or_else(|| env(PROFILE_ENV_VAR));
+        let profile_name = explicit_profile.as_deref().unwrap_or(DEFAULT_PROFILE);
+        let mut profile = match file.and_then(|mut f| f.profile.remove(profile_name)) {
+            Some(p) => p,
+            None if explicit_profile.is_some() => {
+                return Err(ConfigLoadError::ProfileNotFound(profile_name.to_string()))
+            }
+            None => ConfigProfile::default(),
+        };
+        profile.apply_env(&env)?;
+        if profile.api_key.is_some() && !profile.tls_enabled() {
+            return Err(ConfigLoadError::ApiKeyWithoutTls);
+        }
+        Ok(profile)
+    }
+
+    fn apply_env(&mut self, env: &impl Fn(&str) -> Option<String>) -> Result<(), ConfigLoadError> {
+        let set = |field: &mut Option<String>, var: &str| {
+            if let Some(v) = env(var) {
+                *field = Some(v);
+            }
+        };
+        set(&mut self.address, "TEMPORAL_ADDRESS");
+        set(&mut self.namespace, "TEMPORAL_NAMESPACE");
+        set(&mut self.api_key, "TEMPORAL_API_KEY");
+        set(&mut self.task_queue, "TEMPORAL_TASK_QUEUE");
+
+        let had_tls = self.tls.is_some();
+        let mut tls = self.tls.take().unwrap_or_default();
+        if let Some(v) = env("TEMPORAL_TLS") {
+            tls.enabled = Some(match v.to_lowercase().as_str() {
+                "true" | "1" => true,
+                "false" | "0" => false,
+                _ => {
+                    return Err(ConfigLoadError::InvalidEnvVar {
+                        var: "TEMPORAL_TLS".to_string(),
+                        value: v,
+                    })
+                }
+            });
+        }
+        set(&mut tls.server_name, "TEMPORAL_TLS_SERVER_NAME");
+        let set_path = |field: &mut Option<PathBuf>, var: &str| {
+            if let Some(v) = env(var) {
+                *field = Some(v.into());
+            }
+        };
+        set_path(
+            &mut tls.server_ca_cert_path,
+            "TEMPORAL_TLS_SERVER_CA_CERT_PATH",
+        );
+        set_path(&mut tls.client_cert_path, "TEMPORAL_TLS_CLIENT_CERT_PATH");
+        set_path(&mut tls.client_key_path, "TEMPORAL_TLS_CLIENT_KEY_PATH");
+        if had_tls || tls != TlsProfile::default() {
+            self.tls = Some(tls);
+        }
+        Ok(())
+    }
+
+    /// Whether connections should use TLS
+    pub fn tls_enabled(&self) -> bool {
+        match &self.tls {
+            Some(TlsProfile {
+                enabled: Some(enabled),
+                ..
+            }) => *enabled,
+            Some(_) => true,
+            None => self.api_key.is_some(),
+        }
+    }
+
+    /// Client options with the server address, TLS, and API key set from this profile. Lang must
+    /// still set the options which identify it, like `client_name`, before building them.
+    pub fn client_options(&self) -> Result<ClientOptionsBuilder, ConfigLoadError> {
+        let mut opts = ClientOptionsBuilder::default();
+        if let Some(address) = &self.address {
+            opts.target_url(self.target_url(address)?);
+        }
+        if let Some(tls) = self.tls_config()? {
+            opts.tls_cfg(tls);
+        }
+        if let Some(key) = &self.api_key {
+            opts.headers_provider(Arc::new(ApiKey::new(key.clone())));
+        }
+        Ok(opts)
+    }
+
+    /// Worker config with the namespace and task queue set from this profile
+    pub fn worker_config(&self) -> WorkerConfigBuilder {
+        let mut cfg = WorkerConfigBuilder::default();
+        if let Some(namespace) = &self.namespace {
+            cfg.namespace(namespace.clone());
+        }
+        if let Some(task_queue) = &self.task_queue {
+            cfg.task_queue(task_queue.clone());
+        }
+        cfg
+    }
+
+    fn target_url(&self, address: &str) -> Result<Url, ConfigLoadError> {
+        let invalid = || ConfigLoadError::InvalidAddress(address.to_string());
+        if address.contains("://") {
+            return Url::parse(address).map_err(|_| invalid());
+        }
+        let scheme = if self.tls_enabled() { "https" } else { "http" };
+        Url::parse(&format!("{}://{}", scheme, address)).map_err(|_| invalid())
+    }
+
+    fn tls_config(&self) -> Result<Option<TlsConfig>, ConfigLoadError> {
+        if !self.tls_enabled() {
+            return Ok(None);
+        }
+        let tls = self.tls.clone().unwrap_or_default();
+        let client_tls_config = match (&tls.client_cert_path, &tls.client_key_path) {
+            (Some(cert), Some(key)) => Some(ClientTlsConfig {
+                client_cert: read_file(cert)?,
+                client_private_key: read_file(key)?,
+            }),
+            _ => None,
+        };
+        Ok(Some(TlsConfig {
+            server_root_ca_cert: tls
+                .server_ca_cert_path
+                .as_deref()
+                .map(read_file)
+                .transpose()?,
+            domain: tls.server_name,
+            client_tls_config,
+        }))
+    }
+}
+
+fn default_config_file(env: &impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    let config_dir = env("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env("HOME").map(|h| Path::new(&h).join(".config")))?;
+    Some(config_dir.join("temporalio").join("temporal.toml"))
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, ConfigLoadError> {
+    std::fs::read(path).map_err(|source| ConfigLoadError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn read_config_file(path: &Path) -> Result<ConfigFile, ConfigLoadError> {
+    let contents = read_file(path)?;
+    toml::from_slice(&contents).map_err(|source| ConfigLoadError::InvalidFile {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    /// Writes a config file which is deleted once the returned handle is dropped
+    fn write_config(contents: &str) -> NamedTempFile {
+        let file = tempfile::Builder::new().suffix(".toml").tempfile().unwrap();
+        std::fs::write(file.path(), contents).unwrap();
+        file
+    }
+
+    fn env(vars: &[(&str, &str)]) -> Option<HashMap<String, String>> {
+        Some(
+            vars.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    const CONFIG: &str = r#"
+        [profile.default]
+        address = "localhost:7233"
+        namespace = "default"
+        task_queue = "q"
+
+        [profile.prod]
+        address = "prod.example.com:7233"
+        namespace = "prod-ns"
+        api_key = "secret"
+
+        [profile.internal]
+        address = "internal:7233"
+
+        [profile.internal.tls]
+        server_name = "internal.example.com"
+    "#;
+
+    #[test]
+    fn env_overrides_file() {
+        let file = write_config(CONFIG);
+        let profile = ConfigProfile::load(LoadConfigOptions {
+            config_file: Some(file.path().to_path_buf()),
+            env_override: env(&[("TEMPORAL_NAMESPACE", "from-env")]),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(profile.address.as_deref(), Some("localhost:7233"));
+        assert_eq!(profile.namespace.as_deref(), Some("from-env"));
+        assert!(!profile.tls_enabled());
+        let err = profile.client_options().unwrap().build().unwrap_err();
+        // Lang still has to identify itself
+        assert!(err.to_string().contains("client_name"));
+    }
+
+    #[test]
+    fn profile_from_env_with_api_key_uses_tls() {
+        let file = write_config(CONFIG);
+        let profile = ConfigProfile::load(LoadConfigOptions {
+            config_file: Some(file.path().to_path_buf()),
+            env_override: env(&[(PROFILE_ENV_VAR, "prod")]),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(profile.tls_enabled());
+        assert_eq!(
+            profile
+                .target_url("prod.example.com:7233")
+                .unwrap()
+                .as_str(),
+            "https://prod.example.com:7233/"
+        );
+        let err = profile.worker_config().build().unwrap_err();
+        // Only the default profile sets a task queue
+        assert!(err.to_string().contains("task_queue"));
+    }
+
+    #[test]
+    fn explicit_tls_off_wins() {
+        let file = write_config(CONFIG);
+        let profile = ConfigProfile::load(LoadConfigOptions {
+            config_file: Some(file.path().to_path_buf()),
+            profile: Some("internal".to_string()),
+            env_override: env(&[("TEMPORAL_TLS", "false")]),
+        })
+        .unwrap();
+        assert!(!profile.tls_enabled());
+        assert!(profile.tls_config().unwrap().is_none());
+    }
+
+    #[test]
+    fn api_key_with_tls_off_rejected() {
+        let file = write_config(CONFIG);
+        assert_matches!(
+            ConfigProfile::load(LoadConfigOptions {
+                config_file: Some(file.path().to_path_buf()),
+                profile: Some("prod".to_string()),
+                env_override: env(&[("TEMPORAL_TLS", "false")]),
+            }),
+            Err(ConfigLoadError::ApiKeyWithoutTls)
+        );
+    }
+
+    #[test]
+    fn missing_things() {
+        // Neither a missing default file nor the default profile is an error
+        let profile = ConfigProfile::load(LoadConfigOptions {
+            env_override: env(&[("HOME", "/nonexistent"), ("TEMPORAL_ADDRESS", "h:1")]),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(profile.address.as_deref(), Some("h:1"));
+        let file = write_config(CONFIG);
+        assert_matches!(
+            ConfigProfile::load(LoadConfigOptions {
+                config_file: Some(file.path().to_path_buf()),
+                profile: Some("nope".to_string()),
+                env_override: env(&[]),
+            }),
+            Err(ConfigLoadError::ProfileNotFound(p)) if p == "nope"
+        );
+        let bad = write_config("[profile.default]\nbad = 1");
+        assert_matches!(
+            ConfigProfile::load(LoadConfigOptions {
+                config_file: Some(bad.path().to_path_buf()),
+                env_override: env(&[]),
+                ..Default::default()
+            }),
+            Err(ConfigLoadError::InvalidFile { .. })
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! Launches short-lived Temporal servers, either the dev server built into the `temporal` CLI or
+//! the Java test server, so integration test suites built on core can provision their own server.
+//! The server executable must already be installed.
+
+use std::{
+    fs::File,
+    net::TcpListener,
+    path::PathBuf,
+    process::Stdio,
+    time::{Duration, Instant},
+};
+use tokio::process::{Child, Command};
+
+/// Where an ephemeral server's stdout and stderr go
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EphemeralServerOutput {
+    /// Discard the server's output
+    Discard,
+    /// Write the server's output to the same place as this process's
+    Inherit,
+    /// Write the server's output to the provided file, which is created or truncated
+    File(PathBuf),
+}
+
+/// Configuration for the dev server built into the `temporal` CLI
+#[derive(Debug, Clone, derive_builder::Builder)]
+#[builder(setter(into))]
+pub struct TemporalDevServerConfig {
+    /// Path to the `temporal` executable, or just its name if it can be found on the PATH
+    #[builder(default = "\"temporal\".to_string()")]
+    pub exe: String,
+    /// Namespace to register, in addition to `default`
+    #[builder(default = "\"default\".to_string()")]
+    pub namespace: String,
+    /// IP to bind to
+    #[builder(default = "\"127.0.0.1\".to_string()")]
+    pub ip: String,
+    /// Port to listen on. A free port is chosen if unset.
+    #[builder(setter(strip_option), default)]
+    pub port: Option<u16>,
+    /// Sqlite file to persist state to. State is kept in memory if unset.
+    #[builder(setter(strip_option), default)]
+    pub db_filename: Option<String>,
+    /// Whether to serve the web UI as well
+    #[builder(default)]
+    pub ui: bool,
+    /// Where the server's output goes
+    #[builder(default = "EphemeralServerOutput::Discard")]
+    pub output: EphemeralServerOutput,
+    /// How long to wait for the server to start accepting connections
+    #[builder(default = "Duration::from_secs(30)")]
+    pub startup_timeout: Duration,
+    /// Any other arguments to pass to `temporal server start-dev`
+    #[builder(default)]
+    pub extra_args: Vec<String>,
+}
+
+impl TemporalDevServerConfig {
+    /// Start the dev server, resolving once it accepts connections
+    pub async fn start_server(&self) -> Result<EphemeralServer, anyhow::Error> {
+        let port = match self.port {
+            Some(p) => p,
+            None => get_free_port(&self.ip)?,
+        };
+        EphemeralServer::start(EphemeralServerStart {
+            exe: &self.exe,
+            args: self.args(port),
+            output: &self.output,
+            target: format!("{}:{}", self.ip, port),
+            startup_timeout: self.startup_timeout,
+            has_test_service: false,
+        })
+        .await
+    }
+
+    fn args(&self, port: u16) -> Vec<String> {
+        let mut args = vec![
+            "server".to_string(),
+            "start-dev".to_string(),
+            "--port".to_string(),
+            port.to_string(),
+            "--ip".to_string(),
+            self.ip.clone(),
+            "--namespace".to_string(),
+            self.namespace.clone(),
+        ];
+        if let Some(db_filename) = &self.db_filename {
+            args.push("--db-filename".to_string());
+            args.push(db_filename.clone());
+        }
+        if !self.ui {
+            args.push("--headless".to_string());
+        }
+        args.extend(self.extra_args.iter().cloned());
+        args
+    }
+}
+
+/// Configuration for the Java test server, which supports time skipping
+#[derive(Debug, Clone, derive_builder::Builder)]
+#[builder(setter(into))]
+pub struct TestServerConfig {
+    /// Path to the `temporal-test-server` executable, or just its name if it can be found on the
+    /// PATH
+    #[builder(default = "\"temporal-test-server\".to_string()")]
+    pub exe: String,
+    /// Port to listen on. A free port is chosen if unset.
+    #[builder(setter(strip_option), default)]
+    pub port: Option<u16>,
+    /// Where the server's output goes
+    #[builder(default = "EphemeralServerOutput::Discard")]
+    pub output: EphemeralServerOutput,
+    /// How long to wait for the server to start accepting connections
+    #[builder(default = "Duration::from_secs(30)")]
+    pub startup_timeout: Duration,
+    /// Any other arguments to pass to the test server
+    #[builder(default)]
+    pub extra_args: Vec<String>,
+}
+
+impl TestServerConfig {
+    /// Start the test server, resolving once it accepts connections
+    pub async fn start_server(&self) -> Result<EphemeralServer, anyhow::Error> {
+        // The test server always binds to all interfaces
+        let port = match self.port {
+            Some(p) => p,
+            None => get_free_port("0.0.0.0")?,
+        };
+        let mut args = vec![port.to_string()];
+        args.extend(self.extra_args.iter().cloned());
+        EphemeralServer::start(EphemeralServerStart {
+            exe: &self.exe,
+            args,
+            output: &self.output,
+            target: format!("127.0.0.1:{}", port),
+            startup_timeout: self.startup_timeout,
+            has_test_service: true,
+        })
+        .await
+    }
+}
+
+struct EphemeralServerStart<'a> {
+    exe: &'a str,
+    args: Vec<String>,
+    output: &'a EphemeralServerOutput,
+    target: String,
+    startup_timeout: Duration,
+    has_test_service: bool,
+}
+
+/// A running ephemeral server. The server is killed if this is dropped without calling
+/// [EphemeralServer::shutdown].
+#[derive(Debug)]
+pub struct EphemeralServer {
+    /// The `host:port` the server is listening on
+    pub target: String,
+    /// Whether the server implements the test service, which allows skipping time
+    pub has_test_service: bool,
+    child: Child,
+}
+
+impl EphemeralServer {
+    async fn start(opts: EphemeralServerStart<'_>) -> Result<Self, anyhow::Error> {
+        let (stdout, stderr) = match opts.output {
+            EphemeralServerOutput::Discard => (Stdio::null(), Stdio::null()),
+            EphemeralServerOutput::Inherit => (Stdio::inherit(), Stdio::inherit()),
+            EphemeralServerOutput::File(path) => {
+                let file = File::create(path)?;
+                (file.try_clone()?.into(), file.into())
+            }
+        };
+        info!(
+            exe = opts.exe,
+            target = opts.target.as_str(),
+            "Starting ephemeral server"
+        );
+        let child = Command::new(opts.exe)
+            .args(&opts.args)
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(stderr)
+            .kill_on_drop(true)
+            .spawn()?;
+        let mut server = Self {
+            target: opts.target,
+            has_test_service: opts.has_test_service,
+            child,
+        };
+        if let Err(e) = server.wait_until_ready(opts.startup_timeout).await {
+            let _ = server.shutdown().await;
+            return Err(e);
+        }
+        Ok(server)
+    }
+
+    /// Poll the server's port until it accepts connections, the timeout elapses, or the server
+    /// exits
+    async fn wait_until_ready(&mut self, timeout: Duration) -> Result<(), anyhow::Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if tokio::net::TcpStream::connect(&self.target).await.is_ok() {
+                return Ok(());
+            }
+            if let Some(status) = self.child.try_wait()? {
+                anyhow::bail!("Ephemeral server exited before becoming ready: {}", status);
+            }
+            if Instant::now() >= deadline {
+                anyhow::bail!(
+                    "Ephemeral server did not accept connections on {} within {:?}",
+                    self.target,
+                    timeout
+                );
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Stop the server, waiting for it to exit
+    pub async fn shutdown(&mut self) -> Result<(), anyhow::Error> {
+        if self.child.try_wait()?.is_none() {
+            self.child.kill().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Find a port on the provided IP nothing is currently listening on
+fn get_free_port(ip: &str) -> Result<u16, anyhow::Error> {
+    let listener = TcpListener::bind((ip, 0))?;
+    Ok(listener.local_addr()?.port())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dev_server_args() {
+        let cfg = TemporalDevServerConfigBuilder::default()
+            .namespace("my-ns")
+            .db_filename("state.db")
+            .extra_args(vec!["--log-level".to_string(), "error".to_string()])
+            .build()
+            .unwrap();
+        assert_eq!(
+            cfg.args(7233),
+            [
+                "server",
+                "start-dev",
+                "--port",
+                "7233",
+                "--ip",
+                "127.0.0.1",
+                "--namespace",
+                "my-ns",
+                "--db-filename",
+                "state.db",
+                "--headless",
+                "--log-level",
+                "error"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn server_exiting_early_is_an_error() {
+        let err = TemporalDevServerConfigBuilder::default()
+            .exe("false")
+            .build()
+            .unwrap()
+            .start_server()
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("exited before becoming ready"));
+    }
+}
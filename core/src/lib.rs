@@ -1,4 +1,7 @@
-#![warn(missing_docs)] // error if there are missing docs
+#![warn(missing_docs)]
+// error if there are missing docs
+// Calls to the server fail with `tonic::Status`, which is large but not worth boxing everywhere
+#![allow(clippy::result_large_err)]
 #![allow(clippy::upper_case_acronyms)]
 
 //! This crate provides a basis for creating new Temporal SDKs without completely starting from
@@ -11,12 +14,18 @@ pub extern crate assert_matches;
 extern crate tracing;
 
 mod abstractions;
+pub mod core_server;
+pub mod envconfig;
+pub mod ephemeral_server;
 mod log_export;
+pub mod payload_codec;
+pub mod payload_offload;
 mod pending_activations;
 mod pollers;
 mod protosext;
 pub mod replay;
 pub(crate) mod retry_logic;
+pub mod runtime;
 pub(crate) mod telemetry;
 mod worker;
 mod workflow;
@@ -30,21 +39,28 @@ mod test_help;
 pub(crate) use temporal_sdk_core_api::errors;
 
 pub use pollers::{
-    Client, ClientOptions, ClientOptionsBuilder, ClientTlsConfig, RetryClient, RetryConfig,
-    TlsConfig, WorkflowClientTrait,
+    AccessToken, ApiKey, Client, ClientCredentials, ClientInitError, ClientInterceptor,
+    ClientKeepAliveConfig, ClientOptions, ClientOptionsBuilder, ClientRateLimitConfig,
+    ClientTlsConfig, FailoverConfig, HeadersProvider, OAuth2TokenProvider,
+    PollCircuitBreakerConfig, RetryClient, RetryConfig, TlsConfig, TokenHttpClient, TokenSource,
+    WorkflowClientTrait,
 };
+pub use runtime::CoreRuntime;
 pub use telemetry::{
-    fetch_global_buffered_logs, telemetry_init, Logger, MetricsExporter, OtelCollectorOptions,
-    TelemetryOptions, TelemetryOptionsBuilder, TraceExporter,
+    fetch_global_buffered_logs, fetch_global_metrics, telemetry_init, Logger, MetricsExporter,
+    OtelCollectorOptions, TelemetryOptions, TelemetryOptionsBuilder, TraceExporter,
 };
 pub use temporal_sdk_core_api as api;
 pub use temporal_sdk_core_protos as protos;
 pub use temporal_sdk_core_protos::TaskToken;
 pub use url::Url;
-pub use worker::{Worker, WorkerConfig, WorkerConfigBuilder};
+pub use worker::{
+    client::{mocks::MockManualWorkerClient, MockWorkerClient, WorkerClient},
+    Worker, WorkerConfig, WorkerConfigBuilder,
+};
 
 use crate::{
-    replay::mock_client_from_history,
+    replay::{mock_client_from_history, new_replay_worker, ActivationCapturer},
     telemetry::metrics::{MetricsContext, METRIC_METER},
     worker::client::WorkerClientBag,
 };
@@ -54,7 +70,19 @@ use temporal_sdk_core_api::{
     errors::{CompleteActivityError, PollActivityError, PollWfError},
     CoreLog, Worker as WorkerTrait,
 };
-use temporal_sdk_core_protos::{coresdk::ActivityHeartbeat, temporal::api::history::v1::History};
+use temporal_sdk_core_protos::{
+    coresdk::ActivityHeartbeat,
+    temporal::api::{
+        history::v1::History,
+        workflowservice::v1::get_system_info_response::Capabilities as GetSystemInfoCapabilities,
+    },
+};
+
+/// The version of core, which lang may check against the version it was built for
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Used to name sticky queues when [WorkerConfig::sticky_queue_name_template] is unset
+const DEFAULT_STICKY_QUEUE_NAME_TEMPLATE: &str = "{identity}-{task_queue}-{unique_id}";
 
 lazy_static::lazy_static! {
     /// A process-wide unique string, which will be different on every startup
@@ -78,52 +106,120 @@ where
             Arc::new(retry_client)
         }
     };
-    let c_opts = client.get_options().clone();
+    let identity = match &worker_config.lang_sdk {
+        Some(sdk) => Some(
+            sdk.stamp_identity(
+                worker_config
+                    .client_identity_override
+                    .as_deref()
+                    .unwrap_or(&client.get_options().identity),
+            ),
+        ),
+        None => worker_config.client_identity_override.clone(),
+    };
+    let client = match identity {
+        Some(identity) => client.with_identity(identity).unwrap_or_else(|| {
+            warn!("Client does not support overriding its identity, the override is ignored");
+            client
+        }),
+        None => client,
+    };
+    let identity = client.get_options().identity.clone();
     if client.namespace() != worker_config.namespace {
         panic!("Passed in client is not bound to the same namespace as the worker");
     }
-    let client_bag = Arc::new(WorkerClientBag::new(
+    let capabilities = {
+        let client = client.clone();
+        move || client.capabilities()
+    };
+    let poll_pauses = client.subscribe_poll_pauses();
+    let client_bag = worker_client_bag(&worker_config, Box::new(client), identity, capabilities);
+    let sticky_q = sticky_q_name_for_worker(client_bag.identity(), &worker_config);
+    let metrics = worker_metrics(&worker_config);
+    let worker = Worker::new(worker_config, sticky_q, client_bag, metrics);
+    if let Some(pauses) = poll_pauses {
+        worker.forward_poll_pauses(pauses);
+    }
+    worker
+}
+
+/// Initialize a worker which talks to Temporal through the provided [WorkerClient], rather than a
+/// real client. This is mostly useful for testing against a [MockWorkerClient] without a server.
+/// Workers created this way don't use a sticky queue.
+pub fn init_worker_with_client(
+    worker_config: WorkerConfig,
+    client: impl WorkerClient + 'static,
+) -> Worker {
+    let client_bag = worker_client_bag(
+        &worker_config,
         Box::new(client),
-        worker_config.namespace.clone(),
-    ));
-    let sticky_q = sticky_q_name_for_worker(&c_opts.identity, &worker_config);
-    let metrics = MetricsContext::top_level(worker_config.namespace.clone())
-        .with_task_q(worker_config.task_queue.clone());
-    Worker::new(worker_config, sticky_q, client_bag, metrics)
+        worker_config
+            .client_identity_override
+            .clone()
+            .unwrap_or_default(),
+        || None,
+    );
+    let metrics = worker_metrics(&worker_config);
+    Worker::new(worker_config, None, client_bag, metrics)
 }
 
 /// Create a worker for replaying a specific history. It will auto-shutdown as soon as the history
 /// has finished being replayed. The provided client should be a mock, and this should only be used
 /// for workflow testing purposes.
 pub fn init_replay_worker(
-    mut config: WorkerConfig,
+    config: WorkerConfig,
     history: &History,
 ) -> Result<Worker, anyhow::Error> {
-    info!(
-        task_queue = config.task_queue.as_str(),
-        "Registering replay worker"
-    );
-    config.max_cached_workflows = 1;
-    config.max_concurrent_wft_polls = 1;
-    config.no_remote_activities = true;
     // Could possibly just use mocked pollers here?
-    let client = mock_client_from_history(history, &config.task_queue);
+    let client = mock_client_from_history(history, &config.task_queue, None);
     let run_id = history.extract_run_id_from_start()?.to_string();
     let last_event = history.last_event_id();
-    let mut worker = Worker::new(config, None, Arc::new(client), MetricsContext::default());
+    let mut worker = new_replay_worker(config, client);
     worker.set_shutdown_on_run_reaches_event(run_id, last_event);
     Ok(worker)
 }
 
+fn worker_client_bag(
+    config: &WorkerConfig,
+    client: Box<dyn WorkerClient>,
+    identity: String,
+    capabilities: impl Fn() -> Option<GetSystemInfoCapabilities> + Send + Sync + 'static,
+) -> Arc<WorkerClientBag> {
+    let mut client_bag = WorkerClientBag::new(client, config.namespace.clone(), None)
+        .with_capabilities_source(capabilities);
+    client_bag.set_identity(identity);
+    if let Some(capture) = &config.activation_capture {
+        client_bag.set_activation_capture(ActivationCapturer::new(capture.clone()));
+    }
+    Arc::new(client_bag.with_payload_transforms(config))
+}
+
+pub(crate) fn worker_metrics(config: &WorkerConfig) -> MetricsContext {
+    MetricsContext::top_level(config.namespace.clone())
+        .with_task_q(config.task_queue.clone())
+        .with_new_attrs(
+            config
+                .metric_attributes
+                .iter()
+                .map(|(k, v)| opentelemetry::KeyValue::new(k.clone(), v.clone())),
+        )
+}
+
 pub(crate) fn sticky_q_name_for_worker(
     process_identity: &str,
     config: &WorkerConfig,
 ) -> Option<String> {
     if config.max_cached_workflows > 0 {
-        Some(format!(
-            "{}-{}-{}",
-            &process_identity, &config.task_queue, *PROCCESS_UNIQ_ID
-        ))
+        let template = config
+            .sticky_queue_name_template
+            .as_deref()
+            .unwrap_or(DEFAULT_STICKY_QUEUE_NAME_TEMPLATE);
+        Some(
+            template
+                .replace("{identity}", process_identity)
+                .replace("{task_queue}", &config.task_queue)
+                .replace("{unique_id}", &PROCCESS_UNIQ_ID),
+        )
     } else {
         None
     }
@@ -0,0 +1,194 @@
+//! Payload codecs shipped with core. See [temporal_sdk_core_api::worker::PayloadCodec].
+
+use anyhow::anyhow;
+use prost::Message;
+use std::io::{Read, Write};
+use temporal_sdk_core_api::worker::PayloadCodec;
+use temporal_sdk_core_protos::temporal::api::common::v1::Payload;
+
+/// The metadata key payload encodings are recorded under
+pub const ENCODING_METADATA_KEY: &str = "encoding";
+/// Default value for [CompressionCodec::threshold_bytes]
+pub const DEFAULT_COMPRESSION_THRESHOLD_BYTES: usize = 4 * 1024;
+
+/// Algorithms [CompressionCodec] may compress payloads with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionAlgorithm {
+    /// Zstandard, at its default level. Faster and smaller than gzip for most payloads.
+    Zstd,
+    /// Gzip, at its default level. Useful when other tools need to read compressed payloads.
+    Gzip,
+}
+
+impl CompressionAlgorithm {
+    /// The encoding recorded in the metadata of payloads compressed with this algorithm
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            CompressionAlgorithm::Zstd => "binary/zstd",
+            CompressionAlgorithm::Gzip => "binary/gzip",
+        }
+    }
+
+    fn from_encoding(encoding: &[u8]) -> Option<Self> {
+        [CompressionAlgorithm::Zstd, CompressionAlgorithm::Gzip]
+            .into_iter()
+            .find(|a| a.encoding().as_bytes() == encoding)
+    }
+
+    fn compress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            CompressionAlgorithm::Zstd => zstd::encode_all(data, 0),
+            CompressionAlgorithm::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+
+    fn decompress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            CompressionAlgorithm::Zstd => zstd::decode_all(data),
+            CompressionAlgorithm::Gzip => {
+                let mut out = vec![];
+                flate2::read::GzDecoder::new(data).read_to_end(&mut out)?;
+                Ok(out)
+            }
+        }
+    }
+}
+
+/// Compresses payloads whose data is at least [CompressionCodec::threshold_bytes] long.
+///
+/// The entire original payload (including its metadata) is compressed into the data of a new
+/// payload, whose `encoding` metadata names the algorithm used. Decoding restores the original
+/// payload, regardless of which algorithm this codec is configured to compress with, so the
+/// algorithm may be changed without breaking histories written before the change. Payloads which
+/// do not shrink when compressed are sent as they are.
+#[derive(Debug, Clone)]
+pub struct CompressionCodec {
+    /// Algorithm newly encoded payloads are compressed with
+    pub algorithm: CompressionAlgorithm,
+    /// Payloads with less data than this are not compressed
+    pub threshold_bytes: usize,
+}
+
+impl CompressionCodec {
+    /// Create a codec compressing payloads of at least `threshold_bytes` with `algorithm`
+    pub fn new(algorithm: CompressionAlgorithm, threshold_bytes: usize) -> Self {
+        Self {
+            algorithm,
+            threshold_bytes,
+        }
+    }
+}
+
+impl Default for CompressionCodec {
+    fn default() -> Self {
+        Self::new(
+            CompressionAlgorithm::Zstd,
+            DEFAULT_COMPRESSION_THRESHOLD_BYTES,
+        )
+    }
+}
+
+impl PayloadCodec for CompressionCodec {
+    fn encode(&self, payload: &mut Payload) {
+        if payload.data.len() < self.threshold_bytes {
+            return;
+        }
+        let compressed = match self.algorithm.compress(&payload.encode_to_vec()) {
+            Ok(c) => c,
+            Err(e) => {
+                // Compression is an optimization, so sending the payload as-is is always fine
+                warn!(error=?e, "Failed to compress payload, sending it uncompressed");
+                return;
+            }
+        };
+        if compressed.len() >= payload.data.len() {
+            return;
+        }
+        *payload = Payload {
+            metadata: [(
+                ENCODING_METADATA_KEY.to_string(),
+                self.algorithm.encoding().as_bytes().to_vec(),
+            )]
+            .into_iter()
+            .collect(),
+            data: compressed.into(),
+        };
+    }
+
+    fn decode(&self, payload: &mut Payload) -> Result<(), anyhow::Error> {
+        let algorithm = match payload
+            .metadata
+            .get(ENCODING_METADATA_KEY)
+            .and_then(|e| CompressionAlgorithm::from_encoding(e))
+        {
+            Some(a) => a,
+            None => return Ok(()),
+        };
+        let decompressed = algorithm
+            .decompress(&payload.data)
+            .map_err(|e| anyhow!("Failed to decompress {:?} payload: {}", algorithm, e))?;
+        *payload = Payload::decode(decompressed.as_slice())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use temporal_sdk_core_protos::coresdk::AsJsonPayloadExt;
+
+    fn big_payload() -> Payload {
+        "abc".repeat(10_000).as_json_payload().unwrap().into()
+    }
+
+    #[rstest]
+    #[case::zstd(CompressionAlgorithm::Zstd)]
+    #[case::gzip(CompressionAlgorithm::Gzip)]
+    fn compresses_and_restores_large_payloads(#[case] algorithm: CompressionAlgorithm) {
+        let codec = CompressionCodec::new(algorithm, 1024);
+        let original = big_payload();
+        let mut payload = original.clone();
+        codec.encode(&mut payload);
+        assert!(payload.data.len() < original.data.len());
+        assert_eq!(
+            payload.metadata[ENCODING_METADATA_KEY],
+            algorithm.encoding().as_bytes()
+        );
+        codec.decode(&mut payload).unwrap();
+        assert_eq!(payload, original);
+    }
+
+    #[test]
+    fn decodes_payloads_compressed_with_other_algorithm() {
+        let mut payload = big_payload();
+        CompressionCodec::new(CompressionAlgorithm::Gzip, 0).encode(&mut payload);
+        CompressionCodec::default().decode(&mut payload).unwrap();
+        assert_eq!(payload, big_payload());
+    }
+
+    #[test]
+    fn small_and_foreign_payloads_untouched() {
+        let codec = CompressionCodec::default();
+        let original: Payload = "small".as_json_payload().unwrap().into();
+        let mut payload = original.clone();
+        codec.encode(&mut payload);
+        assert_eq!(payload, original);
+        codec.decode(&mut payload).unwrap();
+        assert_eq!(payload, original);
+    }
+
+    #[test]
+    fn corrupt_payload_fails_decode() {
+        let codec = CompressionCodec::default();
+        let mut payload = big_payload();
+        codec.encode(&mut payload);
+        payload.data.truncate(payload.data.len() / 2);
+        assert!(codec.decode(&mut payload).is_err());
+    }
+}
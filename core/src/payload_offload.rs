@@ -0,0 +1,152 @@
+//! Blob stores for [temporal_sdk_core_api::worker::PayloadOffloadConfig], and the format of the
+//! reference payloads which replace offloaded payloads.
+
+use crate::payload_codec::ENCODING_METADATA_KEY;
+use anyhow::{bail, Context};
+use parking_lot::RwLock;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, path::PathBuf};
+use temporal_sdk_core_api::worker::BlobStore;
+use temporal_sdk_core_protos::temporal::api::common::v1::Payload;
+
+/// The encoding recorded in the metadata of payloads which refer to an offloaded payload. Their
+/// data is the key the offloaded payload is stored under.
+pub const OFFLOADED_PAYLOAD_ENCODING: &str = "claim-check/sha256";
+
+/// Key a blob is stored under, derived from its contents
+pub(crate) fn blob_key(blob: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(blob))
+}
+
+pub(crate) fn reference_payload(key: String) -> Payload {
+    Payload {
+        metadata: HashMap::from([(
+            ENCODING_METADATA_KEY.to_string(),
+            OFFLOADED_PAYLOAD_ENCODING.as_bytes().to_vec(),
+        )]),
+        data: key.into_bytes().into(),
+    }
+}
+
+/// If `payload` refers to an offloaded payload, returns the key it is stored under
+pub(crate) fn referenced_key(payload: &Payload) -> Option<String> {
+    if payload.metadata.get(ENCODING_METADATA_KEY)?.as_slice()
+        != OFFLOADED_PAYLOAD_ENCODING.as_bytes()
+    {
+        return None;
+    }
+    std::str::from_utf8(&payload.data)
+        .ok()
+        .filter(|k| k.len() == 64 && k.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(str::to_string)
+}
+
+/// Stores each blob as a file in a directory, which may be on a filesystem shared by all workers
+#[derive(Debug, Clone)]
+pub struct FileBlobStore {
+    dir: PathBuf,
+}
+
+impl FileBlobStore {
+    /// Store blobs in `dir`, which is created when the first blob is written if it doesn't exist
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, anyhow::Error> {
+        // Keys must never be able to refer to paths outside the store
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric()) {
+            bail!("Invalid blob key {:?}", key);
+        }
+        Ok(self.dir.join(key))
+    }
+}
+
+#[async_trait::async_trait]
+impl BlobStore for FileBlobStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), anyhow::Error> {
+        let path = self.path(key)?;
+        if tokio::fs::metadata(&path).await.is_ok() {
+            // Keys are content hashes, so it's already been written
+            return Ok(());
+        }
+        tokio::fs::create_dir_all(&self.dir).await?;
+        // Write then rename, so readers never see a partially written blob
+        let tmp_path = self
+            .dir
+            .join(format!("{}.{}.tmp", key, uuid::Uuid::new_v4()));
+        tokio::fs::write(&tmp_path, data)
+            .await
+            .with_context(|| format!("Failed to write blob {:?}", tmp_path))?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, anyhow::Error> {
+        let path = self.path(key)?;
+        tokio::fs::read(&path)
+            .await
+            .with_context(|| format!("Failed to read blob {:?}", path))
+    }
+}
+
+/// Keeps blobs in memory. Only useful when every worker using a namespace runs in one process,
+/// ex: in tests.
+#[derive(Debug, Default)]
+pub struct InMemoryBlobStore {
+    blobs: RwLock<HashMap<String, Vec<u8>>>,
+}
+
+impl InMemoryBlobStore {
+    /// Number of blobs stored
+    pub fn len(&self) -> usize {
+        self.blobs.read().len()
+    }
+
+    /// True if nothing has been stored
+    pub fn is_empty(&self) -> bool {
+        self.blobs.read().is_empty()
+    }
+}
+
+#[async_trait::async_trait]
+impl BlobStore for InMemoryBlobStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), anyhow::Error> {
+        self.blobs.write().insert(key.to_string(), data);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, anyhow::Error> {
+        self.blobs
+            .read()
+            .get(key)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("No blob stored under key {}", key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn file_store_round_trips() {
+        let dir = std::env::temp_dir().join(format!("blob-store-{}", uuid::Uuid::new_v4()));
+        let store = FileBlobStore::new(&dir);
+        let key = blob_key(b"hello");
+        store.put(&key, b"hello".to_vec()).await.unwrap();
+        // Writing again is a no-op
+        store.put(&key, b"hello".to_vec()).await.unwrap();
+        assert_eq!(store.get(&key).await.unwrap(), b"hello");
+        assert!(store.get("missing").await.is_err());
+        assert!(store.get("../escape").await.is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reference_payloads_round_trip() {
+        let key = blob_key(b"data");
+        assert_eq!(referenced_key(&reference_payload(key.clone())), Some(key));
+        assert_eq!(referenced_key(&Payload::default()), None);
+    }
+}
@@ -33,7 +33,7 @@ impl PendingActivations {
     pub fn notify_needs_activation(&self, run_id: &str) {
         let mut inner = self.inner.write();
 
-        if inner.by_run_id.get(run_id).is_none() {
+        if !inner.by_run_id.contains_key(run_id) {
             let key = inner.activations.insert(PendingActInfo {
                 needs_eviction: None,
                 run_id: run_id.to_string(),
@@ -74,7 +74,7 @@ impl PendingActivations {
             inner
                 .activations
                 .get(k)
-                .map_or(false, |activation| predicate(&activation.run_id))
+                .is_some_and(|activation| predicate(&activation.run_id))
         });
 
         let maybe_key = maybe_key.map(|pos| inner.queue.remove(pos).unwrap());
@@ -97,6 +97,11 @@ impl PendingActivations {
         self.pop_first_matching(|_| true)
     }
 
+    /// Number of runs with a pending activation
+    pub fn len(&self) -> usize {
+        self.inner.read().activations.len()
+    }
+
     pub fn has_pending(&self, run_id: &str) -> bool {
         self.inner.read().by_run_id.contains_key(run_id)
     }
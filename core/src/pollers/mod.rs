@@ -1,11 +1,17 @@
 mod poll_buffer;
+mod restartable;
 
 pub(crate) use poll_buffer::{
-    new_activity_task_buffer, new_workflow_task_buffer, WorkflowTaskPoller,
+    new_activity_task_buffer, new_workflow_task_buffer, ActivityRateLimit, PollWorkflowTaskBuffer,
+    WorkflowTaskPoller,
 };
+pub(crate) use restartable::RestartablePoller;
 pub use temporal_client::{
-    Client, ClientOptions, ClientOptionsBuilder, ClientTlsConfig, RetryClient, RetryConfig,
-    TlsConfig, WorkflowClientTrait,
+    AccessToken, ApiKey, Client, ClientCredentials, ClientInitError, ClientInterceptor,
+    ClientKeepAliveConfig, ClientOptions, ClientOptionsBuilder, ClientRateLimitConfig,
+    ClientTlsConfig, FailoverConfig, HeadersProvider, OAuth2TokenProvider,
+    PollCircuitBreakerConfig, RetryClient, RetryConfig, TlsConfig, TokenHttpClient, TokenSource,
+    WorkflowClientTrait,
 };
 use temporal_sdk_core_protos::temporal::api::workflowservice::v1::{
     PollActivityTaskQueueResponse, PollWorkflowTaskQueueResponse,
@@ -29,6 +35,8 @@ where
     async fn shutdown(self);
     /// Need a separate shutdown to be able to consume boxes :(
     async fn shutdown_box(self: Box<Self>);
+    /// Change the maximum number of concurrent polls. Pollers which can't do so ignore this.
+    fn set_max_pollers(&self, _max: usize) {}
 }
 pub type BoxedPoller<T> = Box<dyn Poller<T> + Send + Sync + 'static>;
 pub type BoxedWFPoller = BoxedPoller<PollWorkflowTaskQueueResponse>;
@@ -3,6 +3,7 @@ use crate::{
     worker::client::WorkerClientBag,
 };
 use futures::{prelude::stream::FuturesUnordered, StreamExt};
+use parking_lot::RwLock;
 use std::{
     fmt::Debug,
     future::Future,
@@ -22,6 +23,88 @@ use tokio::{
     task::JoinHandle,
 };
 
+/// Information about poll responses which is used to decide how many pollers should be active
+pub(crate) trait PollOutcome {
+    /// Returns true if the poll completed without any work (the long poll timed out)
+    fn is_empty(&self) -> bool;
+    /// The server's estimate of how many tasks are backlogged on the queue, if it provides one
+    fn backlog_hint(&self) -> Option<i64> {
+        None
+    }
+}
+
+impl PollOutcome for PollWorkflowTaskQueueResponse {
+    fn is_empty(&self) -> bool {
+        self.task_token.is_empty()
+    }
+    fn backlog_hint(&self) -> Option<i64> {
+        Some(self.backlog_count_hint)
+    }
+}
+
+impl PollOutcome for PollActivityTaskQueueResponse {
+    fn is_empty(&self) -> bool {
+        self.task_token.is_empty()
+    }
+}
+
+/// Decides how many of a [LongPollBuffer]'s pollers are allowed to poll at once, between a minimum
+/// and maximum. Every poll which returns work allows one more poller, and every empty poll allows
+/// one fewer, so the number of pollers follows the ratio of successful to empty polls. If the
+/// server indicates there is a backlog at least as large as the current number of pollers, we go
+/// straight to the maximum.
+struct PollScaler {
+    min: usize,
+    /// May be lowered (and raised again, up to `spawned`) while running
+    max: AtomicUsize,
+    /// How many pollers exist
+    spawned: usize,
+    target: watch::Sender<usize>,
+}
+
+impl PollScaler {
+    fn new(min: usize, max: usize) -> Self {
+        let min = min.clamp(1, max.max(1));
+        Self {
+            min,
+            max: AtomicUsize::new(max),
+            spawned: max,
+            target: watch::channel(min).0,
+        }
+    }
+
+    fn set_max(&self, max: usize) {
+        let max = max.clamp(1, self.spawned.max(1));
+        self.max.store(max, Ordering::Release);
+        self.target.send_if_modified(|target| {
+            let changed = *target > max;
+            *target = (*target).min(max);
+            changed
+        });
+    }
+
+    fn record<T: PollOutcome>(&self, res: &pollers::Result<T>) {
+        let res = match res {
+            Ok(r) => r,
+            // Errors say nothing about how much work there is
+            Err(_) => return,
+        };
+        let max = self.max.load(Ordering::Acquire);
+        self.target.send_if_modified(|target| {
+            let new_target = if res.is_empty() {
+                target.saturating_sub(1).max(self.min.min(max))
+            } else if res.backlog_hint().unwrap_or_default() >= *target as i64 {
+                max
+            } else {
+                (*target + 1).min(max)
+            };
+            let changed = new_target != *target;
+            *target = new_target;
+            changed
+        });
+    }
+}
+
 pub struct LongPollBuffer<T> {
     buffered_polls: Mutex<Receiver<pollers::Result<T>>>,
     shutdown: watch::Sender<bool>,
@@ -30,30 +113,55 @@ pub struct LongPollBuffer<T> {
     /// means unit tests can continue to function in a predictable manner when calling mocks.
     polls_requested: Arc<Semaphore>,
     join_handles: FuturesUnordered<JoinHandle<()>>,
+    /// Called with every successful poll response, with true if it contained work
+    poll_outcome: Option<Box<dyn Fn(bool) + Send + Sync>>,
+    active_pollers: Arc<ActivePollers>,
+    scaler: Arc<PollScaler>,
+}
+
+/// Called with the number of active pollers
+type PollerCountHandler = Box<dyn Fn(usize) + Send + Sync>;
+
+/// Counts the pollers which are currently polling. Since pollers beyond the scaler's target stop
+/// polling, this follows scaling as well as how many polls have been requested.
+#[derive(Default)]
+struct ActivePollers {
+    count: AtomicUsize,
     /// Called every time the number of pollers is changed
-    num_pollers_changed: Option<Box<dyn Fn(usize) + Send + Sync>>,
-    active_pollers: Arc<AtomicUsize>,
+    changed: RwLock<Option<PollerCountHandler>>,
+}
+impl ActivePollers {
+    fn update(&self, f: impl FnOnce(&AtomicUsize) -> usize) {
+        let num = f(&self.count);
+        if let Some(fun) = self.changed.read().as_ref() {
+            fun(num);
+        }
+    }
 }
 
-struct ActiveCounter<'a>(&'a AtomicUsize);
+struct ActiveCounter<'a>(&'a ActivePollers);
 impl<'a> ActiveCounter<'a> {
-    fn new(a: &'a AtomicUsize) -> Self {
-        a.fetch_add(1, Ordering::Relaxed);
+    fn new(a: &'a ActivePollers) -> Self {
+        a.update(|c| c.fetch_add(1, Ordering::Relaxed) + 1);
         Self(a)
     }
 }
 impl Drop for ActiveCounter<'_> {
     fn drop(&mut self) {
-        self.0.fetch_sub(1, Ordering::Relaxed);
+        self.0.update(|c| c.fetch_sub(1, Ordering::Relaxed) - 1);
     }
 }
 
 impl<T> LongPollBuffer<T>
 where
-    T: Send + Debug + 'static,
+    T: PollOutcome + Send + Debug + 'static,
 {
+    /// Create a new buffer which will have between `min_pollers` and `max_pollers` polls
+    /// outstanding at once (so long as that many polls have been requested), scaling according to
+    /// whether or not polls are finding work.
     pub fn new<FT>(
         poll_fn: impl Fn() -> FT + Send + Sync + 'static,
+        min_pollers: usize,
         max_pollers: usize,
         buffer_size: usize,
     ) -> Self
@@ -62,21 +170,32 @@ where
     {
         let (tx, rx) = channel(buffer_size);
         let polls_requested = Arc::new(Semaphore::new(0));
-        let active_pollers = Arc::new(AtomicUsize::new(0));
+        let active_pollers = Arc::new(ActivePollers::default());
         let (shutdown_tx, shutdown_rx) = watch::channel(false);
         let join_handles = FuturesUnordered::new();
         let pf = Arc::new(poll_fn);
-        for _ in 0..max_pollers {
+        let scaler = Arc::new(PollScaler::new(min_pollers, max_pollers));
+        for poller_num in 0..max_pollers {
             let tx = tx.clone();
             let pf = pf.clone();
             let mut shutdown = shutdown_rx.clone();
             let polls_requested = polls_requested.clone();
             let ap = active_pollers.clone();
+            let scaler = scaler.clone();
+            let mut target = scaler.target.subscribe();
             let jh = tokio::spawn(async move {
                 loop {
                     if *shutdown.borrow() {
                         break;
                     }
+                    // Pollers beyond the currently allowed number wait until we scale up
+                    if poller_num >= *target.borrow_and_update() {
+                        tokio::select! {
+                            _ = target.changed() => {},
+                            _ = shutdown.changed() => {},
+                        };
+                        continue;
+                    }
                     let sp = tokio::select! {
                         sp = polls_requested.acquire() => sp.expect("Polls semaphore not dropped"),
                         _ = shutdown.changed() => continue,
@@ -87,7 +206,13 @@ where
                         _ = shutdown.changed() => continue,
                     };
                     sp.forget();
-                    let _ = tx.send(r).await;
+                    scaler.record(&r);
+                    // The buffer may be full if nobody is taking polls out of it any more, so we
+                    // can't wait on sending forever once shutdown has started
+                    tokio::select! {
+                        _ = tx.send(r) => {},
+                        _ = shutdown.changed() => {},
+                    };
                 }
             });
             join_handles.push(jh);
@@ -97,22 +222,28 @@ where
             shutdown: shutdown_tx,
             polls_requested,
             join_handles,
-            num_pollers_changed: None,
+            poll_outcome: None,
             active_pollers,
+            scaler,
         }
     }
 
     /// Set a function that will be called every time the number of pollers changes.
-    /// TODO: Currently a bit weird, will make more sense once we implement dynamic poller scaling.
     pub fn set_num_pollers_handler(&mut self, handler: impl Fn(usize) + Send + Sync + 'static) {
-        self.num_pollers_changed = Some(Box::new(handler));
+        *self.active_pollers.changed.write() = Some(Box::new(handler));
+    }
+
+    /// Set a function that will be called with every successful poll response, which is passed
+    /// true if the response contained work and false if the poll came back empty.
+    pub fn set_poll_outcome_handler(&mut self, handler: impl Fn(bool) + Send + Sync + 'static) {
+        self.poll_outcome = Some(Box::new(handler));
     }
 }
 
 #[async_trait::async_trait]
 impl<T> Poller<T> for LongPollBuffer<T>
 where
-    T: Send + Sync + Debug + 'static,
+    T: PollOutcome + Send + Sync + Debug + 'static,
 {
     /// Poll the buffer. Adds one permit to the polling pool - the point of this being that the
     /// buffer may support many concurrent pollers, but there is no reason to have them poll unless
@@ -127,15 +258,12 @@ where
     #[instrument(name = "long_poll", level = "trace", skip(self))]
     async fn poll(&self) -> Option<pollers::Result<T>> {
         self.polls_requested.add_permits(1);
-        if let Some(fun) = self.num_pollers_changed.as_ref() {
-            fun(self.active_pollers.load(Ordering::Relaxed));
-        }
 
         let mut locked = self.buffered_polls.lock().await;
         let res = (*locked).recv().await;
 
-        if let Some(fun) = self.num_pollers_changed.as_ref() {
-            fun(self.active_pollers.load(Ordering::Relaxed));
+        if let (Some(fun), Some(Ok(r))) = (self.poll_outcome.as_ref(), res.as_ref()) {
+            fun(!r.is_empty());
         }
 
         res
@@ -154,6 +282,11 @@ where
         let this = *self;
         this.shutdown().await;
     }
+
+    /// Can't raise the maximum beyond the one the buffer was created with
+    fn set_max_pollers(&self, max: usize) {
+        self.scaler.set_max(max);
+    }
 }
 
 /// A poller capable of polling on a sticky and a nonsticky queue simultaneously for workflow tasks.
@@ -161,6 +294,8 @@ where
 pub struct WorkflowTaskPoller {
     normal_poller: PollWorkflowTaskBuffer,
     sticky_poller: Option<PollWorkflowTaskBuffer>,
+    /// Share of the maximum number of pollers given to the normal poller when there is a sticky one
+    nonsticky_to_sticky_poll_ratio: f32,
 }
 
 #[async_trait::async_trait]
@@ -194,6 +329,18 @@ impl Poller<PollWorkflowTaskQueueResponse> for WorkflowTaskPoller {
         let this = *self;
         this.shutdown().await;
     }
+
+    fn set_max_pollers(&self, max: usize) {
+        match self.sticky_poller.as_ref() {
+            Some(sq) => {
+                let nonsticky =
+                    ((max as f32 * self.nonsticky_to_sticky_poll_ratio) as usize).max(1);
+                self.normal_poller.set_max_pollers(nonsticky);
+                sq.set_max_pollers(max.saturating_sub(nonsticky).max(1));
+            }
+            None => self.normal_poller.set_max_pollers(max),
+        }
+    }
 }
 
 pub type PollWorkflowTaskBuffer = LongPollBuffer<PollWorkflowTaskQueueResponse>;
@@ -201,7 +348,8 @@ pub(crate) fn new_workflow_task_buffer(
     client: Arc<WorkerClientBag>,
     task_queue: String,
     is_sticky: bool,
-    concurrent_pollers: usize,
+    min_pollers: usize,
+    max_pollers: usize,
     buffer_size: usize,
 ) -> PollWorkflowTaskBuffer {
     LongPollBuffer::new(
@@ -210,26 +358,34 @@ pub(crate) fn new_workflow_task_buffer(
             let task_queue = task_queue.clone();
             async move { client.poll_workflow_task(task_queue, is_sticky).await }
         },
-        concurrent_pollers,
+        min_pollers,
+        max_pollers,
         buffer_size,
     )
 }
 
+/// Activities per second the server should dispatch to a task queue, read before every poll so it
+/// may be changed while polling
+pub(crate) type ActivityRateLimit = Arc<RwLock<Option<f64>>>;
+
 pub type PollActivityTaskBuffer = LongPollBuffer<PollActivityTaskQueueResponse>;
 pub(crate) fn new_activity_task_buffer(
     client: Arc<WorkerClientBag>,
     task_queue: String,
-    concurrent_pollers: usize,
+    min_pollers: usize,
+    max_pollers: usize,
     buffer_size: usize,
-    max_tps: Option<f64>,
+    max_tps: ActivityRateLimit,
 ) -> PollActivityTaskBuffer {
     LongPollBuffer::new(
         move || {
             let client = client.clone();
             let task_queue = task_queue.clone();
+            let max_tps = *max_tps.read();
             async move { client.poll_activity_task(task_queue, max_tps).await }
         },
-        concurrent_pollers,
+        min_pollers,
+        max_pollers,
         buffer_size,
     )
 }
@@ -262,6 +418,7 @@ mod tests {
             false,
             1,
             1,
+            1,
         );
 
         // Poll a bunch of times, "interrupting" it each time, we should only actually have polled
@@ -288,4 +445,131 @@ mod tests {
         pb.poll().await.unwrap().unwrap();
         pb.shutdown().await;
     }
+
+    #[test]
+    fn scaler_follows_poll_outcomes() {
+        let scaler = PollScaler::new(1, 4);
+        let target = || *scaler.target.borrow();
+        let task = || -> pollers::Result<PollActivityTaskQueueResponse> {
+            Ok(PollActivityTaskQueueResponse {
+                task_token: vec![1],
+                ..Default::default()
+            })
+        };
+        let empty = || -> pollers::Result<PollActivityTaskQueueResponse> { Ok(Default::default()) };
+
+        scaler.record(&task());
+        scaler.record(&task());
+        assert_eq!(target(), 3);
+        // Errors don't change anything
+        scaler.record::<PollActivityTaskQueueResponse>(&Err(tonic::Status::unavailable("no")));
+        assert_eq!(target(), 3);
+        for _ in 0..3 {
+            scaler.record(&task());
+        }
+        assert_eq!(target(), 4);
+        for _ in 0..5 {
+            scaler.record(&empty());
+        }
+        assert_eq!(target(), 1);
+        // A big enough backlog jumps right to the max
+        scaler.record::<PollWorkflowTaskQueueResponse>(&Ok(PollWorkflowTaskQueueResponse {
+            task_token: vec![1],
+            backlog_count_hint: 10,
+            ..Default::default()
+        }));
+        assert_eq!(target(), 4);
+        // Lowering the max lowers the target, and it can't be raised past the pollers which exist
+        scaler.set_max(2);
+        assert_eq!(target(), 2);
+        scaler.set_max(10);
+        for _ in 0..10 {
+            scaler.record(&task());
+        }
+        assert_eq!(target(), 4);
+    }
+
+    #[tokio::test]
+    async fn num_pollers_reported_as_pollers_start_and_stop() {
+        let mut mock_client = mock_manual_workflow_client();
+        mock_client
+            .expect_poll_workflow_task()
+            .returning(|_, _| futures::future::pending().boxed());
+
+        let mut pb = new_workflow_task_buffer(
+            Arc::new(mock_client.into()),
+            "someq".to_string(),
+            false,
+            2,
+            2,
+            1,
+        );
+        let reported = Arc::new(parking_lot::Mutex::new(vec![]));
+        let r = reported.clone();
+        pb.set_num_pollers_handler(move |np| r.lock().push(np));
+        for _ in 0..2 {
+            let _ = pb.poll().now_or_never();
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(*reported.lock(), vec![1, 2]);
+        pb.shutdown().await;
+        assert_eq!(reported.lock().last(), Some(&0));
+    }
+
+    #[tokio::test]
+    async fn shutdown_cancels_in_flight_polls() {
+        let mut mock_client = mock_manual_workflow_client();
+        mock_client
+            .expect_poll_workflow_task()
+            .returning(|_, _| futures::future::pending().boxed());
+
+        let pb = new_workflow_task_buffer(
+            Arc::new(mock_client.into()),
+            "someq".to_string(),
+            false,
+            2,
+            2,
+            1,
+        );
+        let in_flight = pb.poll();
+        let shutdown = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            pb.notify_shutdown();
+        };
+        let (res, _) = tokio::time::timeout(Duration::from_secs(1), async {
+            tokio::join!(in_flight, shutdown)
+        })
+        .await
+        .expect("In-flight poll should be cancelled by shutdown");
+        assert!(res.is_none());
+        tokio::time::timeout(Duration::from_secs(1), pb.shutdown())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn shutdown_with_full_buffer_does_not_hang() {
+        let mut mock_client = mock_manual_workflow_client();
+        mock_client
+            .expect_poll_workflow_task()
+            .returning(|_, _| async { Ok(Default::default()) }.boxed());
+
+        let pb = new_workflow_task_buffer(
+            Arc::new(mock_client.into()),
+            "someq".to_string(),
+            false,
+            1,
+            1,
+            1,
+        );
+        // Request more polls than are taken out of the buffer, so the poller ends up stuck trying
+        // to send into the full buffer
+        for _ in 0..3 {
+            let _ = pb.poll().now_or_never();
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        tokio::time::timeout(Duration::from_secs(1), pb.shutdown())
+            .await
+            .expect("Shutdown should not wait on a full buffer");
+    }
 }
@@ -0,0 +1,166 @@
+use crate::pollers::{self, BoxedPoller};
+use parking_lot::{Mutex, RwLock};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use tokio::sync::watch;
+
+/// Wraps a poller so that it may be replaced by a new one without callers noticing, which is what
+/// allows workers to restart polling without shutting down.
+///
+/// While paused, the current poller is stopped and polls wait for a new one to be installed,
+/// rather than returning `None`. `None` is only returned once the wrapper itself is shut down.
+pub(crate) struct RestartablePoller<T> {
+    current: RwLock<Arc<BoxedPoller<T>>>,
+    /// Pollers which have been replaced but still had polls in progress, kept so that they can be
+    /// waited on during shutdown. Ones which have since drained are shut down at the next restart.
+    retired: Mutex<Vec<Arc<BoxedPoller<T>>>>,
+    /// True while polling is paused for a restart
+    paused: watch::Sender<bool>,
+    shutting_down: AtomicBool,
+}
+
+impl<T> RestartablePoller<T>
+where
+    T: Send + Sync + 'static,
+{
+    pub(crate) fn new(poller: BoxedPoller<T>) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(poller)),
+            retired: Mutex::new(vec![]),
+            paused: watch::channel(false).0,
+            shutting_down: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) async fn poll(&self) -> Option<pollers::Result<T>> {
+        let mut paused = self.paused.subscribe();
+        loop {
+            let poller = self.current.read().clone();
+            match poller.poll().await {
+                None if *paused.borrow_and_update()
+                    || !Arc::ptr_eq(&poller, &self.current.read()) =>
+                {
+                    // The poller was stopped for a restart rather than shutdown, wait for its
+                    // replacement
+                    while *paused.borrow_and_update() {
+                        if paused.changed().await.is_err() {
+                            return None;
+                        }
+                    }
+                }
+                r => return r,
+            }
+        }
+    }
+
+    pub(crate) fn notify_shutdown(&self) {
+        self.shutting_down.store(true, Ordering::Release);
+        self.current.read().notify_shutdown();
+        // Let anyone waiting on a restart see the (now stopped) current poller
+        self.paused.send_replace(false);
+    }
+
+    pub(crate) fn set_max_pollers(&self, max: usize) {
+        self.current.read().set_max_pollers(max);
+    }
+
+    /// Stop the current poller. Polls wait until [RestartablePoller::resume_with] is called.
+    pub(crate) fn pause(&self) {
+        if self.shutting_down.load(Ordering::Acquire) {
+            return;
+        }
+        self.paused.send_replace(true);
+        self.current.read().notify_shutdown();
+    }
+
+    /// Replace the (stopped) current poller with `poller`, and resume polling with it
+    pub(crate) fn resume_with(&self, poller: BoxedPoller<T>) {
+        if self.shutting_down.load(Ordering::Acquire) {
+            // Shutdown raced with the restart, so the new poller must not start handing out work
+            poller.notify_shutdown();
+        }
+        let old = std::mem::replace(&mut *self.current.write(), Arc::new(poller));
+        let mut retired = self.retired.lock();
+        retired.push(old);
+        // Pollers nothing else holds have no polls left in progress, so they're done for good
+        let all = std::mem::take(&mut *retired);
+        for poller in all {
+            match Arc::try_unwrap(poller) {
+                Ok(poller) => {
+                    tokio::spawn(poller.shutdown_box());
+                }
+                Err(poller) => retired.push(poller),
+            }
+        }
+        drop(retired);
+        self.paused.send_replace(false);
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    pub(crate) async fn shutdown(self) {
+        self.notify_shutdown();
+        let pollers = self
+            .retired
+            .into_inner()
+            .into_iter()
+            .chain([self.current.into_inner()]);
+        for poller in pollers {
+            // Anything else holding the poller is a poll in progress, which will end now that
+            // the poller has been told to shut down
+            if let Ok(poller) = Arc::try_unwrap(poller) {
+                poller.shutdown_box().await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_help::{mock_manual_poller, mock_poller_from_resps};
+    use futures::FutureExt;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn polls_wait_for_replacement_while_paused() {
+        let mut stopped = mock_manual_poller::<usize>();
+        stopped.expect_poll().returning(|| async { None }.boxed());
+        let poller = RestartablePoller::new(Box::new(stopped) as BoxedPoller<usize>);
+        poller.pause();
+        assert!(poller.is_paused());
+        let (res, _) = tokio::join!(poller.poll(), async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            poller.resume_with(mock_poller_from_resps([7]));
+        });
+        assert_eq!(res.unwrap().unwrap(), 7);
+        assert!(!poller.is_paused());
+    }
+
+    #[tokio::test]
+    async fn drained_pollers_are_dropped_on_restart() {
+        let poller = RestartablePoller::new(mock_poller_from_resps([1]));
+        for i in 0..5 {
+            poller.pause();
+            poller.resume_with(mock_poller_from_resps([i]));
+        }
+        assert!(poller.retired.lock().is_empty());
+        assert_eq!(poller.poll().await.unwrap().unwrap(), 4);
+        poller.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn stopped_poller_returns_none_after_shutdown() {
+        let mut stopped = mock_manual_poller::<usize>();
+        stopped.expect_poll().returning(|| async { None }.boxed());
+        let poller = RestartablePoller::new(Box::new(stopped) as BoxedPoller<usize>);
+        poller.pause();
+        poller.notify_shutdown();
+        assert!(poller.poll().await.is_none());
+        poller.shutdown().await;
+    }
+}
@@ -0,0 +1,197 @@
+use super::{ReplayFailure, WorkflowReplayer};
+use crate::{Worker, WorkerConfig};
+use futures::{Future, Stream, StreamExt};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use temporal_sdk_core_protos::temporal::api::history::v1::History;
+
+/// A history to be replayed as part of [replay_histories]
+#[derive(Debug, Clone)]
+pub struct HistoryForReplay {
+    /// Identifies the history in results, ex: the workflow id or file it came from
+    pub id: String,
+    /// The history itself
+    pub history: History,
+}
+
+/// Why replaying a history did not pass
+#[derive(Debug, Clone, PartialEq)]
+pub enum HistoryReplayError {
+    /// A workflow task failed during replay
+    Failed(ReplayFailure),
+    /// The history could not be replayed at all
+    InvalidHistory(String),
+    /// Lang's driver returned an error
+    Driver(String),
+}
+
+/// The result of replaying one history
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryReplayResult {
+    /// The [HistoryForReplay::id] of the history
+    pub id: String,
+    /// Whether replay passed
+    pub outcome: Result<(), HistoryReplayError>,
+}
+
+/// Aggregate statistics of a [replay_histories] run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayStats {
+    /// How many histories were replayed
+    pub total: usize,
+    /// How many histories replayed without any failures
+    pub passed: usize,
+    /// How many histories failed because of nondeterminism
+    pub nondeterministic: usize,
+    /// How many histories failed for any other reason
+    pub failed: usize,
+}
+
+/// Results of a [replay_histories] run
+#[derive(Debug, Clone)]
+pub struct BulkReplayResults {
+    /// Per-history results, in the order replays finished
+    pub results: Vec<HistoryReplayResult>,
+    /// Aggregate statistics over all results
+    pub stats: ReplayStats,
+    /// How long replaying all the histories took
+    pub elapsed: Duration,
+}
+
+impl BulkReplayResults {
+    /// Returns true if every history replayed successfully
+    pub fn all_passed(&self) -> bool {
+        self.stats.passed == self.stats.total
+    }
+}
+
+/// Replay many histories concurrently, running at most `parallelism` replays at once.
+///
+/// Each history gets its own replay worker, built from `config`, which is handed to `driver`. The
+/// driver is expected to run lang's workflow code against the worker until it shuts down (ex: by
+/// running an SDK worker on top of it) - see [WorkflowReplayer] for details.
+pub async fn replay_histories<F, Fut>(
+    config: WorkerConfig,
+    histories: impl Stream<Item = HistoryForReplay>,
+    parallelism: usize,
+    driver: F,
+) -> BulkReplayResults
+where
+    F: Fn(Arc<Worker>) -> Fut,
+    Fut: Future<Output = Result<(), anyhow::Error>>,
+{
+    let started = Instant::now();
+    let results: Vec<_> = histories
+        .map(|hist| {
+            let replayer = WorkflowReplayer::new(config.clone(), &hist.history);
+            let driver = &driver;
+            async move {
+                let outcome = match replayer {
+                    Ok(replayer) => {
+                        let driven = driver(replayer.worker()).await;
+                        match (replayer.failure(), driven) {
+                            (Some(failure), _) => Err(HistoryReplayError::Failed(failure)),
+                            (None, Err(e)) => Err(HistoryReplayError::Driver(format!("{:?}", e))),
+                            (None, Ok(())) => Ok(()),
+                        }
+                    }
+                    Err(e) => Err(HistoryReplayError::InvalidHistory(e.to_string())),
+                };
+                if let Err(e) = &outcome {
+                    warn!(history_id = hist.id.as_str(), error = ?e, "History failed replay");
+                }
+                HistoryReplayResult {
+                    id: hist.id,
+                    outcome,
+                }
+            }
+        })
+        .buffer_unordered(parallelism.max(1))
+        .collect()
+        .await;
+
+    let mut stats = ReplayStats {
+        total: results.len(),
+        ..Default::default()
+    };
+    for r in &results {
+        match &r.outcome {
+            Ok(()) => stats.passed += 1,
+            Err(HistoryReplayError::Failed(f)) if f.is_nondeterminism() => {
+                stats.nondeterministic += 1
+            }
+            Err(_) => stats.failed += 1,
+        }
+    }
+    BulkReplayResults {
+        results,
+        stats,
+        elapsed: started.elapsed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_help::{canned_histories, test_worker_cfg, timer_wf_driver};
+
+    #[tokio::test]
+    async fn aggregates_results() {
+        let timer_hist: History = canned_histories::single_timer("1")
+            .get_full_history_info()
+            .unwrap()
+            .into();
+        let activity_hist: History = canned_histories::single_activity("1")
+            .get_full_history_info()
+            .unwrap()
+            .into();
+        let mut histories: Vec<_> = (0..5)
+            .map(|i| HistoryForReplay {
+                id: format!("timer-{}", i),
+                history: timer_hist.clone(),
+            })
+            .collect();
+        histories.push(HistoryForReplay {
+            id: "activity".to_string(),
+            history: activity_hist,
+        });
+        histories.push(HistoryForReplay {
+            id: "empty".to_string(),
+            history: History::default(),
+        });
+
+        let res = replay_histories(
+            test_worker_cfg().build().unwrap(),
+            futures::stream::iter(histories),
+            3,
+            timer_wf_driver,
+        )
+        .await;
+
+        assert!(!res.all_passed());
+        assert_eq!(
+            res.stats,
+            ReplayStats {
+                total: 7,
+                passed: 5,
+                nondeterministic: 1,
+                failed: 1,
+            }
+        );
+        let outcome_of = |id: &str| {
+            res.results
+                .iter()
+                .find(|r| r.id == id)
+                .unwrap()
+                .outcome
+                .clone()
+        };
+        assert!(outcome_of("timer-3").is_ok());
+        assert!(matches!(
+            outcome_of("empty"),
+            Err(HistoryReplayError::InvalidHistory(_))
+        ));
+    }
+}
@@ -0,0 +1,407 @@
+use super::mock_replay_client;
+use crate::{telemetry::metrics::MetricsContext, Worker, WorkerClientBag, WorkerConfig};
+use futures::FutureExt;
+use parking_lot::Mutex;
+use prost::Message;
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::OpenOptions,
+    io::Write,
+    path::Path,
+    sync::{mpsc, Arc},
+    thread::JoinHandle,
+};
+use temporal_sdk_core_api::{
+    errors::{CompleteWfError, PollWfError},
+    worker::ActivationCaptureConfig,
+    Worker as WorkerTrait,
+};
+use temporal_sdk_core_protos::{
+    coresdk::{
+        activation_capture::{activation_capture_entry::Variant, ActivationCaptureEntry},
+        workflow_activation::WorkflowActivation,
+        workflow_completion::WorkflowActivationCompletion,
+    },
+    temporal::api::workflowservice::v1::{
+        GetWorkflowExecutionHistoryResponse, PollWorkflowTaskQueueResponse,
+        RespondQueryTaskCompletedResponse,
+    },
+    SerializedHistoryPage,
+};
+use tokio::sync::mpsc::unbounded_channel;
+
+/// Records everything needed to reproduce the processing of selected workflow runs: workflow tasks
+/// and history pages from the server, activations issued to lang, and lang's completions. See
+/// [ActivationCaptureConfig].
+pub(crate) struct ActivationCapturer {
+    config: ActivationCaptureConfig,
+    /// Runs being captured, and whether lang has been asked to evict them. A run is only captured
+    /// once one of its workflow tasks has been seen, and stops being captured once lang has
+    /// completed its eviction.
+    runs: Mutex<HashMap<String, bool>>,
+    /// Entries are written out on a dedicated thread, so the worker never waits on file I/O
+    entries_tx: Option<mpsc::Sender<(String, Vec<u8>)>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl ActivationCapturer {
+    pub(crate) fn new(config: ActivationCaptureConfig) -> Self {
+        let (entries_tx, entries_rx) = mpsc::channel();
+        let directory = config.directory.clone();
+        let writer = std::thread::Builder::new()
+            .name("activation-capture".to_string())
+            .spawn(move || write_entries(&directory, entries_rx))
+            .expect("Must be able to spawn capture writer thread");
+        Self {
+            config,
+            runs: Mutex::new(HashMap::new()),
+            entries_tx: Some(entries_tx),
+            writer: Some(writer),
+        }
+    }
+
+    pub(crate) fn workflow_task(&self, wft: &PollWorkflowTaskQueueResponse) {
+        let we = match &wft.workflow_execution {
+            Some(we) => we,
+            None => return,
+        };
+        if !self.config.workflow_ids.is_empty()
+            && !self.config.workflow_ids.contains(&we.workflow_id)
+        {
+            return;
+        }
+        self.runs.lock().entry(we.run_id.clone()).or_insert(false);
+        self.write(&we.run_id, Variant::WorkflowTask(wft.clone()));
+    }
+
+    pub(crate) fn history_page(&self, run_id: &str, page: &SerializedHistoryPage) {
+        if !self.runs.lock().contains_key(run_id) {
+            return;
+        }
+        // A page which can't be decoded fails the run's history fetch, which is what's captured
+        if let Ok(page) = page.clone().try_into() {
+            self.write(run_id, Variant::HistoryPage(page));
+        }
+    }
+
+    pub(crate) fn activation(&self, act: &WorkflowActivation) {
+        if let Some(evicting) = self.runs.lock().get_mut(&act.run_id) {
+            *evicting = act.eviction_index().is_some();
+        } else {
+            return;
+        }
+        self.write(&act.run_id, Variant::Activation(act.clone()));
+    }
+
+    pub(crate) fn completion(&self, completion: &WorkflowActivationCompletion) {
+        {
+            let mut runs = self.runs.lock();
+            match runs.get(&completion.run_id) {
+                Some(true) => {
+                    runs.remove(&completion.run_id);
+                }
+                Some(false) => {}
+                None => return,
+            }
+        }
+        self.write(&completion.run_id, Variant::Completion(completion.clone()));
+    }
+
+    fn write(&self, run_id: &str, variant: Variant) {
+        let entry = ActivationCaptureEntry {
+            variant: Some(variant),
+        };
+        if let Some(tx) = &self.entries_tx {
+            let _ = tx.send((run_id.to_string(), entry.encode_length_delimited_to_vec()));
+        }
+    }
+}
+
+impl Drop for ActivationCapturer {
+    fn drop(&mut self) {
+        // Closing the channel lets the writer finish what's queued and exit
+        self.entries_tx.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// Appends each entry to its run's capture file. Files are only open while being written to, so
+/// runs which are never evicted don't hold on to them.
+fn write_entries(directory: &Path, entries_rx: mpsc::Receiver<(String, Vec<u8>)>) {
+    for (run_id, bytes) in entries_rx {
+        let res = std::fs::create_dir_all(directory).and_then(|_| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(directory.join(format!("{}.capture", run_id)))?
+                .write_all(&bytes)
+        });
+        if let Err(e) = res {
+            warn!(run_id = run_id.as_str(), error = %e, "Couldn't write to capture file");
+        }
+    }
+}
+
+/// Read a capture file written by a worker with [WorkerConfig::activation_capture] set
+pub fn read_capture_file(
+    path: impl AsRef<Path>,
+) -> Result<Vec<ActivationCaptureEntry>, anyhow::Error> {
+    let bytes = std::fs::read(path)?;
+    let mut buf = bytes.as_slice();
+    let mut entries = vec![];
+    while !buf.is_empty() {
+        entries.push(ActivationCaptureEntry::decode_length_delimited(&mut buf)?);
+    }
+    Ok(entries)
+}
+
+/// Ways replaying a capture can diverge from what was captured
+#[derive(thiserror::Error, Debug)]
+pub enum CaptureReplayError {
+    /// Core issued a different activation than the one in the capture
+    #[error(
+        "Activation at capture entry {index} differs. Captured: {expected} Replayed: {actual}"
+    )]
+    ActivationMismatch {
+        /// Position of the activation in the capture
+        index: usize,
+        /// The captured activation
+        expected: Box<WorkflowActivation>,
+        /// The activation core issued during replay
+        actual: Box<WorkflowActivation>,
+    },
+    /// Polling for the activation at this position in the capture failed
+    #[error("Polling for activation at capture entry {index} failed: {source}")]
+    PollFailed {
+        /// Position of the activation in the capture
+        index: usize,
+        /// The error polling returned
+        source: PollWfError,
+    },
+    /// Completing the activation at this position in the capture failed
+    #[error("Completion at capture entry {index} failed: {source}")]
+    CompletionFailed {
+        /// Position of the completion in the capture
+        index: usize,
+        /// The error completing returned
+        source: CompleteWfError,
+    },
+}
+
+/// Feed a captured run back through a worker without a server or lang. Workflow tasks and history
+/// pages are served from the capture, and lang's captured completions are sent in the same order
+/// they originally were. Every activation core issues is checked against the captured one, and the
+/// first difference, or any error from the worker, is returned.
+///
+/// The config should match the one of the worker the capture was taken from, cache size in
+/// particular, since that changes which activations are issued.
+pub async fn replay_capture(
+    mut config: WorkerConfig,
+    entries: &[ActivationCaptureEntry],
+) -> Result<(), CaptureReplayError> {
+    config.max_concurrent_wft_polls = 1;
+    config.min_concurrent_wft_polls = None;
+    config.no_remote_activities = true;
+    config.activation_capture = None;
+
+    let (wft_tx, wft_rx) = unbounded_channel();
+    let wft_rx = Arc::new(tokio::sync::Mutex::new(wft_rx));
+    let pages: Arc<Mutex<VecDeque<GetWorkflowExecutionHistoryResponse>>> = Default::default();
+
+    let mut mg = mock_replay_client(
+        move || {
+            let wft_rx = wft_rx.clone();
+            async move { wft_rx.lock().await.recv().await }.boxed()
+        },
+        |_| {},
+        |_| {},
+    );
+    let pages_clone = pages.clone();
+    mg.expect_get_workflow_execution_history()
+        .returning(move |_, _, _| {
+            let page = pages_clone.lock().pop_front().map(Into::into);
+            async move {
+                page.ok_or_else(|| tonic::Status::not_found("No more history pages were captured"))
+            }
+            .boxed()
+        });
+    mg.expect_respond_legacy_query()
+        .returning(|_, _| async move { Ok(RespondQueryTaskCompletedResponse {}) }.boxed());
+    let client = WorkerClientBag::new(Box::new(mg), config.namespace.clone(), None);
+    let worker = Worker::new(config, None, Arc::new(client), MetricsContext::default());
+
+    let res = async {
+        for (index, entry) in entries.iter().enumerate() {
+            match entry.variant.clone() {
+                Some(Variant::WorkflowTask(wft)) => {
+                    let _ = wft_tx.send(wft);
+                }
+                Some(Variant::HistoryPage(page)) => pages.lock().push_back(page),
+                Some(Variant::Activation(expected)) => {
+                    let actual = worker
+                        .poll_workflow_activation()
+                        .await
+                        .map_err(|source| CaptureReplayError::PollFailed { index, source })?;
+                    if actual != expected {
+                        return Err(CaptureReplayError::ActivationMismatch {
+                            index,
+                            expected: Box::new(expected),
+                            actual: Box::new(actual),
+                        });
+                    }
+                }
+                Some(Variant::Completion(completion)) => worker
+                    .complete_workflow_activation(completion)
+                    .await
+                    .map_err(|source| CaptureReplayError::CompletionFailed { index, source })?,
+                None => {}
+            }
+        }
+        Ok(())
+    }
+    .await;
+    // The capture may end in the middle of a workflow task, so there's no waiting for the worker
+    // to drain.
+    WorkerTrait::initiate_shutdown(&worker);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        replay::mock_client_from_history,
+        test_help::{canned_histories, test_worker_cfg},
+    };
+    use assert_matches::assert_matches;
+    use std::{path::PathBuf, time::Duration};
+    use temporal_sdk_core_protos::{
+        coresdk::workflow_commands::{CompleteWorkflowExecution, ScheduleActivity, StartTimer},
+        temporal::api::history::v1::History,
+    };
+
+    fn capture_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("activation-capture-{}", uuid::Uuid::new_v4()))
+    }
+
+    /// Run the single timer workflow on a capturing worker, returning the run id
+    async fn run_captured_timer_wf(capture: ActivationCaptureConfig) -> String {
+        let history: History = canned_histories::single_timer("1")
+            .get_full_history_info()
+            .unwrap()
+            .into();
+        let mut client = mock_client_from_history(&history, "q", None);
+        client.set_activation_capture(ActivationCapturer::new(capture));
+        let worker = Worker::new(
+            test_worker_cfg()
+                .max_cached_workflows(1_usize)
+                .build()
+                .unwrap(),
+            None,
+            Arc::new(client),
+            MetricsContext::default(),
+        );
+
+        let act = worker.poll_workflow_activation().await.unwrap();
+        // Lang completes activations through the worker trait, which is where they're captured
+        WorkerTrait::complete_workflow_activation(
+            &worker,
+            WorkflowActivationCompletion::from_cmd(
+                act.run_id.clone(),
+                StartTimer {
+                    seq: 1,
+                    start_to_fire_timeout: Some(Duration::from_secs(1).into()),
+                }
+                .into(),
+            ),
+        )
+        .await
+        .unwrap();
+        let act = worker.poll_workflow_activation().await.unwrap();
+        WorkerTrait::complete_workflow_activation(
+            &worker,
+            WorkflowActivationCompletion::from_cmd(
+                act.run_id.clone(),
+                CompleteWorkflowExecution { result: None }.into(),
+            ),
+        )
+        .await
+        .unwrap();
+        // Dropping the capturer along with the worker waits for everything to be written
+        worker.shutdown().await;
+        worker.finalize_shutdown().await;
+        act.run_id
+    }
+
+    #[tokio::test]
+    async fn captured_run_replays() {
+        let directory = capture_dir();
+        let run_id = run_captured_timer_wf(ActivationCaptureConfig {
+            directory: directory.clone(),
+            workflow_ids: Default::default(),
+        })
+        .await;
+
+        let entries = read_capture_file(directory.join(format!("{}.capture", run_id))).unwrap();
+        assert_matches!(
+            entries
+                .iter()
+                .map(|e| e.variant.as_ref().unwrap())
+                .collect::<Vec<_>>()
+                .as_slice(),
+            [
+                Variant::WorkflowTask(_),
+                Variant::Activation(_),
+                Variant::Completion(_),
+                Variant::Activation(_),
+                Variant::Completion(_),
+            ]
+        );
+        replay_capture(
+            test_worker_cfg()
+                .max_cached_workflows(1_usize)
+                .build()
+                .unwrap(),
+            &entries,
+        )
+        .await
+        .unwrap();
+
+        // A lang bug which sends different commands makes replay diverge
+        let mut altered = entries;
+        altered[2].variant = Some(Variant::Completion(WorkflowActivationCompletion::from_cmd(
+            run_id,
+            ScheduleActivity {
+                seq: 1,
+                activity_id: "1".to_string(),
+                ..Default::default()
+            }
+            .into(),
+        )));
+        assert_matches!(
+            replay_capture(
+                test_worker_cfg()
+                    .max_cached_workflows(1_usize)
+                    .build()
+                    .unwrap(),
+                &altered
+            )
+            .await,
+            Err(CaptureReplayError::ActivationMismatch { index: 3, .. })
+        );
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn only_selected_workflows_are_captured() {
+        let directory = capture_dir();
+        run_captured_timer_wf(ActivationCaptureConfig {
+            directory: directory.clone(),
+            workflow_ids: ["some_other_wf".to_string()].into_iter().collect(),
+        })
+        .await;
+        assert!(!directory.exists());
+    }
+}
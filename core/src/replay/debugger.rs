@@ -0,0 +1,259 @@
+//! Support for the Temporal debugger protocol, which IDE extensions use to step through a workflow
+//! history against lang's workflow code. The extension serves the history and is told every time
+//! replay reaches a new workflow task, which it may respond to only once the user wants replay to
+//! continue, so that breakpoints on workflow tasks can be honored.
+
+use super::WorkflowReplayer;
+use crate::WorkerConfig;
+use hyper::{body::Bytes, client::HttpConnector, Body, Method, Request, StatusCode};
+use parking_lot::Mutex;
+use std::{collections::HashMap, sync::Arc};
+use temporal_sdk_core_protos::{temporal::api::history::v1::History, HistoryParseError};
+use url::Url;
+
+/// Environment variable IDE extensions set to the address lang should find them at
+pub const DEBUGGER_URL_ENV_VAR: &str = "TEMPORAL_DEBUGGER_PLUGIN_URL";
+
+/// Errors talking to a debugger extension
+#[derive(thiserror::Error, Debug)]
+pub enum DebuggerError {
+    /// The debugger's address could not be used
+    #[error("Invalid debugger URL: {0}")]
+    InvalidUrl(String),
+    /// The debugger could not be reached
+    #[error("Debugger request failed: {0}")]
+    Http(#[from] hyper::Error),
+    /// The debugger responded with an error
+    #[error("Debugger responded to {path} with {status}")]
+    Status {
+        /// The endpoint which was called
+        path: &'static str,
+        /// The status it responded with
+        status: StatusCode,
+    },
+    /// The history served by the debugger could not be parsed
+    #[error("History served by the debugger is invalid: {0}")]
+    History(#[from] HistoryParseError),
+}
+
+/// Talks to an IDE debugger extension over HTTP, using the Temporal debugger protocol
+#[derive(Debug, Clone)]
+pub struct DebuggerClient {
+    base_url: Url,
+    client: hyper::Client<HttpConnector>,
+}
+
+impl DebuggerClient {
+    /// Create a client for the debugger extension listening at `base_url`. Only plain HTTP is
+    /// supported, since extensions always listen on a local socket.
+    pub fn new(mut base_url: Url) -> Result<Self, DebuggerError> {
+        if base_url.scheme() != "http" {
+            return Err(DebuggerError::InvalidUrl(format!(
+                "{} is not an http URL",
+                base_url
+            )));
+        }
+        // Endpoints are resolved relative to the base, which must look like a directory for that
+        if !base_url.path().ends_with('/') {
+            base_url.set_path(&format!("{}/", base_url.path()));
+        }
+        Ok(Self {
+            base_url,
+            client: hyper::Client::new(),
+        })
+    }
+
+    /// Create a client for the debugger extension named by [DEBUGGER_URL_ENV_VAR], if it is set
+    pub fn from_env() -> Option<Result<Self, DebuggerError>> {
+        let url = std::env::var(DEBUGGER_URL_ENV_VAR).ok()?;
+        Some(
+            Url::parse(&url)
+                .map_err(|e| DebuggerError::InvalidUrl(format!("{}: {}", url, e)))
+                .and_then(Self::new),
+        )
+    }
+
+    /// Fetch the history the user is debugging
+    pub async fn history(&self) -> Result<History, DebuggerError> {
+        let body = self.call(Method::GET, "history", Body::empty()).await?;
+        Ok(History::parse(&body)?)
+    }
+
+    /// Tell the debugger replay has reached the workflow task started by `event_id`. Resolves
+    /// once the debugger wants replay to continue.
+    pub async fn workflow_task_started(&self, event_id: i64) -> Result<(), DebuggerError> {
+        let body = format!(r#"{{"eventId":{}}}"#, event_id);
+        self.call(Method::POST, "current-wft-started", Body::from(body))
+            .await?;
+        Ok(())
+    }
+
+    async fn call(
+        &self,
+        method: Method,
+        path: &'static str,
+        body: Body,
+    ) -> Result<Bytes, DebuggerError> {
+        let url = self
+            .base_url
+            .join(path)
+            .map_err(|e| DebuggerError::InvalidUrl(e.to_string()))?;
+        let req = Request::builder()
+            .method(method)
+            .uri(url.as_str())
+            .header("content-type", "application/json")
+            .body(body)
+            .map_err(|e| DebuggerError::InvalidUrl(e.to_string()))?;
+        let resp = self.client.request(req).await?;
+        if !resp.status().is_success() {
+            return Err(DebuggerError::Status {
+                path,
+                status: resp.status(),
+            });
+        }
+        Ok(hyper::body::to_bytes(resp.into_body()).await?)
+    }
+}
+
+/// Reports each run's workflow tasks to a debugger as replay reaches them
+pub(crate) struct DebuggerReporter {
+    client: DebuggerClient,
+    /// The started event id of the last workflow task reported for each run
+    reported: Mutex<HashMap<String, i64>>,
+}
+
+impl DebuggerReporter {
+    pub(crate) fn new(client: DebuggerClient) -> Self {
+        Self {
+            client,
+            reported: Default::default(),
+        }
+    }
+
+    /// Called before an activation for `run_id` is given to lang, with the started event id of the
+    /// workflow task it belongs to. Waits for the debugger if the task hasn't been reported yet.
+    pub(crate) async fn activation_for_task(&self, run_id: &str, started_event_id: i64) {
+        {
+            let mut reported = self.reported.lock();
+            let last = reported.entry(run_id.to_string()).or_default();
+            if started_event_id <= *last {
+                return;
+            }
+            *last = started_event_id;
+        }
+        if let Err(e) = self.client.workflow_task_started(started_event_id).await {
+            warn!(error = %e, "Could not report workflow task to debugger, continuing replay");
+        }
+    }
+
+    /// Called when `run_id` leaves the cache. If it's replayed again, its workflow tasks are
+    /// reported from the start.
+    pub(crate) fn run_evicted(&self, run_id: &str) {
+        self.reported.lock().remove(run_id);
+    }
+}
+
+impl WorkflowReplayer {
+    /// Create a replayer for the history served by a debugger extension, which pauses replay at
+    /// the start of every workflow task until the debugger lets it continue
+    pub async fn from_debugger(
+        config: WorkerConfig,
+        debugger: DebuggerClient,
+    ) -> Result<Self, anyhow::Error> {
+        let history = debugger.history().await?;
+        let mut replayer = Self::new(config, &history)?;
+        Arc::get_mut(&mut replayer.worker)
+            .expect("Nothing else has the worker yet")
+            .set_debugger(DebuggerReporter::new(debugger));
+        Ok(replayer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_help::{canned_histories, test_worker_cfg};
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Response, Server,
+    };
+    use std::{convert::Infallible, net::TcpListener, time::Duration};
+    use temporal_sdk_core_api::Worker as WorkerTrait;
+    use temporal_sdk_core_protos::coresdk::{
+        workflow_commands::StartTimer, workflow_completion::WorkflowActivationCompletion,
+    };
+    use tokio::sync::mpsc;
+
+    /// Serve `history` the way an IDE extension would, sending every reported event id to the
+    /// returned receiver
+    fn fake_debugger(history: History) -> (Url, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let history = Arc::new(history.to_proto_bytes());
+        let make_svc = make_service_fn(move |_| {
+            let history = history.clone();
+            let tx = tx.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let history = history.clone();
+                    let tx = tx.clone();
+                    async move {
+                        if req.uri().path() == "/current-wft-started" {
+                            let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                            tx.send(String::from_utf8(body.to_vec()).unwrap()).unwrap();
+                            Ok::<_, Infallible>(Response::new(Body::empty()))
+                        } else {
+                            // Serve the history in small chunks, as a real server may
+                            let (mut sender, body) = Body::channel();
+                            tokio::spawn(async move {
+                                for chunk in history.chunks(16) {
+                                    let chunk = Bytes::copy_from_slice(chunk);
+                                    if sender.send_data(chunk).await.is_err() {
+                                        break;
+                                    }
+                                }
+                            });
+                            Ok(Response::new(body))
+                        }
+                    }
+                }))
+            }
+        });
+        listener.set_nonblocking(true).unwrap();
+        tokio::spawn(Server::from_tcp(listener).unwrap().serve(make_svc));
+        (url, rx)
+    }
+
+    #[tokio::test]
+    async fn reports_each_workflow_task_to_debugger() {
+        let history: History = canned_histories::single_timer("1")
+            .get_full_history_info()
+            .unwrap()
+            .into();
+        let (url, mut reported) = fake_debugger(history);
+        let replayer = WorkflowReplayer::from_debugger(
+            test_worker_cfg().build().unwrap(),
+            DebuggerClient::new(url).unwrap(),
+        )
+        .await
+        .unwrap();
+        let worker = replayer.worker();
+
+        let act = worker.poll_workflow_activation().await.unwrap();
+        assert_eq!(reported.recv().await.unwrap(), r#"{"eventId":3}"#);
+        worker
+            .complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+                act.run_id,
+                StartTimer {
+                    seq: 1,
+                    start_to_fire_timeout: Some(Duration::from_secs(1).into()),
+                }
+                .into(),
+            ))
+            .await
+            .unwrap();
+        worker.poll_workflow_activation().await.unwrap();
+        assert_eq!(reported.recv().await.unwrap(), r#"{"eventId":8}"#);
+    }
+}
@@ -0,0 +1,324 @@
+use super::{mock_replay_client, ReplayFailure};
+use crate::{telemetry::metrics::MetricsContext, Worker, WorkerClientBag, WorkerConfig};
+use futures::{Future, FutureExt};
+use parking_lot::Mutex;
+use std::{
+    fmt::{Display, Formatter},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use temporal_sdk_core_api::Worker as WorkerTrait;
+use temporal_sdk_core_protos::{
+    temporal::api::{command::v1::Command, common::v1::WorkflowExecution, history::v1::History},
+    HistoryInfo,
+};
+use tokio::sync::watch;
+
+/// Set this environment variable to any value to have [check_golden_snapshot] (re)write snapshot
+/// files instead of comparing against them
+pub const UPDATE_GOLDEN_ENV_VAR: &str = "TEMPORAL_UPDATE_GOLDEN";
+
+/// The commands core sent to the server while running a history one workflow task at a time. See
+/// [snapshot_commands].
+///
+/// Displays as the canonical JSON of each command, grouped by workflow task, which is what
+/// snapshot files contain.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandSnapshot {
+    /// Commands sent when completing each workflow task, in order
+    pub workflow_tasks: Vec<Vec<Command>>,
+    /// Set if a workflow task was failed rather than completed, which ends the run
+    pub failure: Option<ReplayFailure>,
+}
+
+impl Display for CommandSnapshot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, commands) in self.workflow_tasks.iter().enumerate() {
+            writeln!(f, "Workflow task {}:", i + 1)?;
+            for command in commands {
+                writeln!(f, "{}", command.to_json())?;
+            }
+        }
+        if let Some(failure) = &self.failure {
+            writeln!(f, "Workflow task {} failed:", self.workflow_tasks.len() + 1)?;
+            writeln!(f, "{:#?}", failure)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run the provided history through a worker one workflow task at a time, as though it were being
+/// executed for the first time, and record the commands core sends to the server for each task.
+///
+/// Like with [super::WorkflowReplayer], `driver` should run lang's workflow code against the worker
+/// until it shuts down. The worker does not cache workflows, so the driver will see every run
+/// replayed from the start on each task. The worker shuts down after the last workflow task in the history
+/// has been completed, or any task has failed.
+pub async fn snapshot_commands<F, Fut>(
+    mut config: WorkerConfig,
+    history: &History,
+    driver: F,
+) -> Result<CommandSnapshot, anyhow::Error>
+where
+    F: FnOnce(Arc<Worker>) -> Fut,
+    Fut: Future<Output = Result<(), anyhow::Error>>,
+{
+    let full_info = HistoryInfo::new_from_history(history, None)?;
+    let wf = WorkflowExecution {
+        workflow_id: "golden_wf_id".to_string(),
+        run_id: full_info.orig_run_id().to_string(),
+    };
+    let tasks = (1..=full_info.wf_task_count())
+        .map(|n| {
+            let mut resp = HistoryInfo::new_from_history(history, Some(n))?
+                .as_poll_wft_response(config.task_queue.clone());
+            resp.workflow_execution = Some(wf.clone());
+            Ok(resp)
+        })
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+    let num_tasks = tasks.len();
+
+    let snapshot = Arc::new(Mutex::new(CommandSnapshot::default()));
+    // Each task is only handed out once the one before it is done, as the server would
+    let (reported_tx, reported_rx) = watch::channel(0);
+    let polls = AtomicUsize::new(0);
+
+    let snap = snapshot.clone();
+    let fail_snap = snapshot.clone();
+    let mg = mock_replay_client(
+        move || {
+            let task_ix = polls.fetch_add(1, Ordering::AcqRel);
+            let task = tasks.get(task_ix).cloned();
+            let mut reported_rx = reported_rx.clone();
+            async move {
+                let task = task?;
+                while *reported_rx.borrow() < task_ix {
+                    if reported_rx.changed().await.is_err() {
+                        break;
+                    }
+                }
+                Some(task)
+            }
+            .boxed()
+        },
+        move |completion| {
+            let mut snap = snap.lock();
+            snap.workflow_tasks.push(completion.commands);
+            let _ = reported_tx.send(snap.workflow_tasks.len());
+        },
+        move |failure| {
+            fail_snap.lock().failure.get_or_insert(failure);
+        },
+    );
+
+    config.max_cached_workflows = 0;
+    config.max_outstanding_workflow_tasks = 1;
+    config.max_concurrent_wft_polls = 1;
+    config.no_remote_activities = true;
+    let client = WorkerClientBag::new(Box::new(mg), config.namespace.clone(), None);
+    let mut worker = Worker::new(config, None, Arc::new(client), MetricsContext::default());
+    let hook_snap = snapshot.clone();
+    worker.set_post_activate_hook(move |worker| {
+        let snap = hook_snap.lock();
+        if snap.failure.is_some() || snap.workflow_tasks.len() >= num_tasks {
+            worker.initiate_shutdown();
+        }
+    });
+
+    driver(Arc::new(worker)).await?;
+    let snapshot = snapshot.lock().clone();
+    Ok(snapshot)
+}
+
+/// Errors returned by [check_golden_snapshot]
+#[derive(thiserror::Error, Debug)]
+pub enum GoldenSnapshotError {
+    /// There is no snapshot to compare against yet
+    #[error(
+        "No snapshot exists at {path:?}. Run with {} set to create it.",
+        UPDATE_GOLDEN_ENV_VAR
+    )]
+    Missing {
+        /// Where the snapshot was expected
+        path: PathBuf,
+    },
+    /// The commands differ from the snapshot
+    #[error(
+        "Commands differ from the snapshot at {path:?}, starting at line {line}.\n\
+         Snapshot: {expected}\n\
+         Actual:   {actual}\n\
+         If the change is intended, run with {} set to update the snapshot.",
+        UPDATE_GOLDEN_ENV_VAR
+    )]
+    Mismatch {
+        /// The snapshot file
+        path: PathBuf,
+        /// First line which differs
+        line: usize,
+        /// The snapshot's contents at that line
+        expected: String,
+        /// The actual contents at that line
+        actual: String,
+    },
+    /// The snapshot couldn't be read or written
+    #[error("Couldn't access snapshot at {path:?}: {source}")]
+    Io {
+        /// The snapshot file
+        path: PathBuf,
+        /// The underlying error
+        source: std::io::Error,
+    },
+}
+
+/// Compare the provided commands with the golden snapshot stored at `path`, failing if they differ.
+/// If the [UPDATE_GOLDEN_ENV_VAR] environment variable is set, the snapshot is written instead.
+pub fn check_golden_snapshot(
+    snapshot: &CommandSnapshot,
+    path: impl AsRef<Path>,
+) -> Result<(), GoldenSnapshotError> {
+    let update = std::env::var_os(UPDATE_GOLDEN_ENV_VAR).is_some();
+    check_snapshot(snapshot, path.as_ref(), update)
+}
+
+fn check_snapshot(
+    snapshot: &CommandSnapshot,
+    path: &Path,
+    update: bool,
+) -> Result<(), GoldenSnapshotError> {
+    let io_err = |source| GoldenSnapshotError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let actual = snapshot.to_string();
+    if update {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(io_err)?;
+        }
+        return std::fs::write(path, actual).map_err(io_err);
+    }
+    let expected = match std::fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(GoldenSnapshotError::Missing {
+                path: path.to_path_buf(),
+            })
+        }
+        Err(e) => return Err(io_err(e)),
+    };
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line = 1;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (None, None) => return Ok(()),
+            (e, a) if e == a => line += 1,
+            (e, a) => {
+                return Err(GoldenSnapshotError::Mismatch {
+                    path: path.to_path_buf(),
+                    line,
+                    expected: e.unwrap_or("<end of snapshot>").to_string(),
+                    actual: a.unwrap_or("<end of commands>").to_string(),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_help::{canned_histories, test_worker_cfg};
+    use std::time::Duration;
+    use temporal_sdk_core_api::errors::PollWfError;
+    use temporal_sdk_core_protos::coresdk::{
+        workflow_activation::workflow_activation_job::Variant,
+        workflow_commands::{CompleteWorkflowExecution, ScheduleActivity, StartTimer},
+        workflow_completion::WorkflowActivationCompletion,
+    };
+
+    /// A workflow which runs one timer or activity, then completes
+    async fn one_step_driver(worker: Arc<Worker>, activity: bool) -> Result<(), anyhow::Error> {
+        loop {
+            let act = match worker.poll_workflow_activation().await {
+                Err(PollWfError::ShutDown) => return Ok(()),
+                other => other?,
+            };
+            let cmds = match act.jobs.last().and_then(|j| j.variant.as_ref()) {
+                Some(Variant::StartWorkflow(_)) if activity => vec![ScheduleActivity {
+                    seq: 1,
+                    activity_id: "1".to_string(),
+                    activity_type: "golden_act".to_string(),
+                    start_to_close_timeout: Some(Duration::from_secs(5).into()),
+                    ..Default::default()
+                }
+                .into()],
+                Some(Variant::StartWorkflow(_)) => vec![StartTimer {
+                    seq: 1,
+                    start_to_fire_timeout: Some(Duration::from_secs(1).into()),
+                }
+                .into()],
+                Some(Variant::FireTimer(_) | Variant::ResolveActivity(_)) => {
+                    vec![CompleteWorkflowExecution { result: None }.into()]
+                }
+                _ => vec![],
+            };
+            worker
+                .complete_workflow_activation(WorkflowActivationCompletion::from_cmds(
+                    act.run_id, cmds,
+                ))
+                .await?;
+        }
+    }
+
+    fn golden_path(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../histories/golden")
+            .join(name)
+    }
+
+    #[tokio::test]
+    async fn single_timer_commands_match_golden() {
+        let history = canned_histories::single_timer("1")
+            .get_full_history_info()
+            .unwrap()
+            .into();
+        let snapshot = snapshot_commands(test_worker_cfg().build().unwrap(), &history, |w| {
+            one_step_driver(w, false)
+        })
+        .await
+        .unwrap();
+        assert_eq!(snapshot.workflow_tasks.len(), 2);
+        check_golden_snapshot(&snapshot, golden_path("single_timer.commands")).unwrap();
+    }
+
+    #[tokio::test]
+    async fn single_activity_commands_match_golden() {
+        let history = canned_histories::single_activity("1")
+            .get_full_history_info()
+            .unwrap()
+            .into();
+        let snapshot = snapshot_commands(test_worker_cfg().build().unwrap(), &history, |w| {
+            one_step_driver(w, true)
+        })
+        .await
+        .unwrap();
+        assert_eq!(snapshot.workflow_tasks.len(), 2);
+        check_golden_snapshot(&snapshot, golden_path("single_activity.commands")).unwrap();
+    }
+
+    #[test]
+    fn differences_are_reported() {
+        let path = std::env::temp_dir().join(format!("golden-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "Workflow task 1:\nsomething else\n").unwrap();
+        let snapshot = CommandSnapshot {
+            workflow_tasks: vec![vec![Command::default()]],
+            failure: None,
+        };
+        let err = check_snapshot(&snapshot, &path, false).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(err, GoldenSnapshotError::Mismatch { line: 2, .. }));
+    }
+}
@@ -2,8 +2,32 @@
 //! to replay canned histories. It should be used by Lang SDKs to provide replay capabilities to
 //! users during testing.
 
-use crate::{worker::client::mocks::mock_manual_workflow_client, WorkerClientBag};
-use futures::FutureExt;
+mod bulk;
+mod capture;
+mod debugger;
+mod golden;
+mod shadow;
+
+use crate::{
+    telemetry::metrics::MetricsContext,
+    worker::client::mocks::{mock_manual_workflow_client, MockManualWorkerClient},
+    Worker, WorkerClientBag, WorkerConfig,
+};
+pub use bulk::{
+    replay_histories, BulkReplayResults, HistoryForReplay, HistoryReplayError, HistoryReplayResult,
+    ReplayStats,
+};
+pub(crate) use capture::ActivationCapturer;
+pub use capture::{read_capture_file, replay_capture, CaptureReplayError};
+pub(crate) use debugger::DebuggerReporter;
+pub use debugger::{DebuggerClient, DebuggerError, DEBUGGER_URL_ENV_VAR};
+use futures::{future::BoxFuture, FutureExt};
+pub use golden::{
+    check_golden_snapshot, snapshot_commands, CommandSnapshot, GoldenSnapshotError,
+    UPDATE_GOLDEN_ENV_VAR,
+};
+use parking_lot::Mutex;
+pub use shadow::{ShadowingConfig, ShadowingConfigBuilder, WorkflowShadower};
 use std::{
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -11,27 +35,122 @@ use std::{
     },
     time::Duration,
 };
+use temporal_client::WorkflowTaskCompletion;
+use temporal_sdk_core_api::Worker as WorkerTrait;
 use temporal_sdk_core_protos::temporal::api::{
     common::v1::WorkflowExecution,
+    enums::v1::WorkflowTaskFailedCause,
+    failure::v1::Failure,
     history::v1::History,
     workflowservice::v1::{
-        RespondWorkflowTaskCompletedResponse, RespondWorkflowTaskFailedResponse,
+        PollWorkflowTaskQueueResponse, RespondWorkflowTaskCompletedResponse,
+        RespondWorkflowTaskFailedResponse,
     },
 };
 pub use temporal_sdk_core_protos::{
     default_wes_attribs, HistoryInfo, TestHistoryBuilder, DEFAULT_WORKFLOW_TYPE,
 };
 
+/// A workflow task failure which was reported while replaying a history
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayFailure {
+    /// Why the workflow task failed
+    pub cause: WorkflowTaskFailedCause,
+    /// Details of the failure, if any were provided
+    pub failure: Option<Failure>,
+}
+
+impl ReplayFailure {
+    /// Returns true if the workflow code did not behave the same way it did when the history was
+    /// originally produced
+    pub fn is_nondeterminism(&self) -> bool {
+        self.cause == WorkflowTaskFailedCause::NonDeterministicError
+    }
+}
+
+/// Replays a single workflow history without any connection to a server.
+///
+/// Lang drives replay through [WorkflowReplayer::worker] exactly as it would drive a normal worker,
+/// by polling for activations and completing them with the commands its workflow code produces.
+/// The worker shuts down once the entire history has been replayed, or as soon as a workflow task
+/// fails (ex: because of nondeterminism), after which [WorkflowReplayer::failure] reports what
+/// went wrong.
+pub struct WorkflowReplayer {
+    worker: Arc<Worker>,
+    failure: Arc<Mutex<Option<ReplayFailure>>>,
+}
+
+impl WorkflowReplayer {
+    /// Create a replayer for the provided history
+    pub fn new(config: WorkerConfig, history: &History) -> Result<Self, anyhow::Error> {
+        let run_id = history.extract_run_id_from_start()?.to_string();
+        let last_event = history.last_event_id();
+        let failure = Arc::new(Mutex::new(None));
+        let client = mock_client_from_history(history, &config.task_queue, Some(failure.clone()));
+        let mut worker = new_replay_worker(config, client);
+        let hook_failure = failure.clone();
+        worker.set_post_activate_hook(move |worker| {
+            if hook_failure.lock().is_some() || worker.run_reached_event(&run_id, last_event) {
+                worker.initiate_shutdown();
+            }
+        });
+        Ok(Self {
+            worker: Arc::new(worker),
+            failure,
+        })
+    }
+
+    /// Create a replayer for a history serialized as protobuf
+    pub fn from_proto(config: WorkerConfig, bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        Self::new(config, &History::from_proto_bytes(bytes)?)
+    }
+
+    /// Create a replayer for a history serialized as JSON, as exported by the Temporal CLI, web
+    /// UI, or other SDKs. See [History::from_cli_json] for the accepted shapes.
+    pub fn from_json(config: WorkerConfig, json: &str) -> Result<Self, anyhow::Error> {
+        Self::new(config, &History::from_cli_json(json)?)
+    }
+
+    /// Create a replayer for a history in any format [History::parse] understands, ex: the
+    /// contents of a file whose format isn't known
+    pub fn from_bytes(config: WorkerConfig, bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        Self::new(config, &History::parse(bytes)?)
+    }
+
+    /// The worker lang should poll and complete activations with to perform the replay
+    pub fn worker(&self) -> Arc<Worker> {
+        self.worker.clone()
+    }
+
+    /// The first workflow task failure reported during replay, if there has been one
+    pub fn failure(&self) -> Option<ReplayFailure> {
+        self.failure.lock().clone()
+    }
+}
+
+/// Create a worker which replays the history served up by the provided client
+pub(crate) fn new_replay_worker(mut config: WorkerConfig, client: WorkerClientBag) -> Worker {
+    info!(
+        task_queue = config.task_queue.as_str(),
+        "Registering replay worker"
+    );
+    config.max_cached_workflows = 1;
+    config.max_concurrent_wft_polls = 1;
+    config.no_remote_activities = true;
+    let client = client.with_payload_transforms(&config);
+    Worker::new(config, None, Arc::new(client), MetricsContext::default())
+}
+
 /// Create a mock client which can be used by a replay worker to serve up canned history.
 /// It will return the entire history in one workflow task, after that it will return default
 /// responses (with a 10s wait). If a workflow task failure is sent to the mock, it will send
-/// the complete response again.
+/// the complete response again - unless `failures` is provided, in which case the first failure is
+/// recorded there instead.
 pub(crate) fn mock_client_from_history(
     history: &History,
     task_queue: impl Into<String>,
+    failures: Option<Arc<Mutex<Option<ReplayFailure>>>>,
 ) -> WorkerClientBag {
-    let mut mg = mock_manual_workflow_client();
-
     let hist_info = HistoryInfo::new_from_history(history, None).unwrap();
     let wf = WorkflowExecution {
         workflow_id: "fake_wf_id".to_string(),
@@ -41,31 +160,155 @@ pub(crate) fn mock_client_from_history(
     let did_send = Arc::new(AtomicBool::new(false));
     let did_send_clone = did_send.clone();
     let tq = task_queue.into();
-    mg.expect_poll_workflow_task().returning(move |_, _| {
-        let hist_info = hist_info.clone();
-        let wf = wf.clone();
-        let did_send_clone = did_send_clone.clone();
-        let tq = tq.clone();
-        async move {
-            if !did_send_clone.swap(true, Ordering::AcqRel) {
-                let mut resp = hist_info.as_poll_wft_response(tq);
+    let mg = mock_replay_client(
+        move || {
+            let task = (!did_send_clone.swap(true, Ordering::AcqRel)).then(|| {
+                let mut resp = hist_info.as_poll_wft_response(tq.clone());
                 resp.workflow_execution = Some(wf.clone());
-                Ok(resp)
+                resp
+            });
+            async move { task }.boxed()
+        },
+        |_| {},
+        move |failure| {
+            if let Some(failures) = &failures {
+                failures.lock().get_or_insert(failure);
             } else {
-                tokio::time::sleep(Duration::from_secs(10)).await;
-                Ok(Default::default())
+                // We'll need to re-send the history if WFT fails
+                did_send.store(false, Ordering::Release);
+            }
+        },
+    );
+
+    WorkerClientBag::new(Box::new(mg), "fake_namespace".to_string(), None)
+}
+
+/// Create a mock client for a worker which replays canned workflow tasks. Each poll is answered
+/// with the task `next_task` resolves to, or if there is none, with an empty response after a 10s
+/// wait, like a long poll which timed out. Workflow task completions and failures always succeed,
+/// after being passed to `on_complete` or `on_failure`.
+pub(crate) fn mock_replay_client(
+    next_task: impl Fn() -> BoxFuture<'static, Option<PollWorkflowTaskQueueResponse>> + Send + 'static,
+    on_complete: impl Fn(WorkflowTaskCompletion) + Send + 'static,
+    on_failure: impl Fn(ReplayFailure) + Send + 'static,
+) -> MockManualWorkerClient {
+    let mut mg = mock_manual_workflow_client();
+    mg.expect_poll_workflow_task().returning(move |_, _| {
+        let task = next_task();
+        async move {
+            match task.await {
+                Some(task) => Ok(task),
+                None => {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    Ok(Default::default())
+                }
             }
         }
         .boxed()
     });
-
     mg.expect_complete_workflow_task()
-        .returning(|_| async move { Ok(RespondWorkflowTaskCompletedResponse::default()) }.boxed());
-    mg.expect_fail_workflow_task().returning(move |_, _, _| {
-        // We'll need to re-send the history if WFT fails
-        did_send.store(false, Ordering::Release);
-        async move { Ok(RespondWorkflowTaskFailedResponse {}) }.boxed()
-    });
+        .returning(move |completion| {
+            on_complete(completion);
+            async move { Ok(RespondWorkflowTaskCompletedResponse::default()) }.boxed()
+        });
+    mg.expect_fail_workflow_task()
+        .returning(move |_, cause, failure| {
+            on_failure(ReplayFailure { cause, failure });
+            async move { Ok(RespondWorkflowTaskFailedResponse {}) }.boxed()
+        });
+    mg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_help::{canned_histories, test_worker_cfg};
+    use assert_matches::assert_matches;
+    use temporal_sdk_core_api::errors::PollWfError;
+    use temporal_sdk_core_protos::coresdk::{
+        workflow_activation::{workflow_activation_job, WorkflowActivationJob},
+        workflow_commands::{CompleteWorkflowExecution, ScheduleActivity, StartTimer},
+        workflow_completion::WorkflowActivationCompletion,
+    };
+
+    fn timer_history() -> History {
+        canned_histories::single_timer("1")
+            .get_full_history_info()
+            .unwrap()
+            .into()
+    }
+
+    #[tokio::test]
+    async fn replays_until_history_exhausted() {
+        let replayer = WorkflowReplayer::from_proto(
+            test_worker_cfg().build().unwrap(),
+            &timer_history().to_proto_bytes(),
+        )
+        .unwrap();
+        let worker = replayer.worker();
+
+        let act = worker.poll_workflow_activation().await.unwrap();
+        worker
+            .complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+                act.run_id,
+                StartTimer {
+                    seq: 1,
+                    start_to_fire_timeout: Some(Duration::from_secs(1).into()),
+                }
+                .into(),
+            ))
+            .await
+            .unwrap();
+        let act = worker.poll_workflow_activation().await.unwrap();
+        worker
+            .complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+                act.run_id,
+                CompleteWorkflowExecution { result: None }.into(),
+            ))
+            .await
+            .unwrap();
+        assert_matches!(
+            worker.poll_workflow_activation().await,
+            Err(PollWfError::ShutDown)
+        );
+        assert_eq!(replayer.failure(), None);
+    }
+
+    #[tokio::test]
+    async fn reports_nondeterminism() {
+        let replayer =
+            WorkflowReplayer::new(test_worker_cfg().build().unwrap(), &timer_history()).unwrap();
+        let worker = replayer.worker();
 
-    WorkerClientBag::new(Box::new(mg), "fake_namespace".to_string())
+        let act = worker.poll_workflow_activation().await.unwrap();
+        worker
+            .complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+                act.run_id,
+                ScheduleActivity {
+                    seq: 1,
+                    activity_id: "1".to_string(),
+                    ..Default::default()
+                }
+                .into(),
+            ))
+            .await
+            .unwrap();
+        let act = worker.poll_workflow_activation().await.unwrap();
+        assert_matches!(
+            act.jobs.as_slice(),
+            [WorkflowActivationJob {
+                variant: Some(workflow_activation_job::Variant::RemoveFromCache(_)),
+            }]
+        );
+        worker
+            .complete_workflow_activation(WorkflowActivationCompletion::empty(act.run_id))
+            .await
+            .unwrap();
+        // History is not delivered again, replay just ends
+        assert_matches!(
+            worker.poll_workflow_activation().await,
+            Err(PollWfError::ShutDown)
+        );
+        assert!(replayer.failure().unwrap().is_nondeterminism());
+    }
 }
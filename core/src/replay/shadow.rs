@@ -0,0 +1,317 @@
+use super::{replay_histories, BulkReplayResults, HistoryForReplay, HistoryReplayError};
+use crate::{
+    telemetry::metrics::{workflow_type, MetricsContext},
+    worker_metrics, Worker, WorkerClient, WorkerConfig,
+};
+use futures::{stream, Future};
+use lru::LruCache;
+use parking_lot::Mutex;
+use rand::seq::SliceRandom;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use temporal_client::VisibilityQuery;
+use temporal_sdk_core_protos::temporal::api::{
+    history::v1::History, workflow::v1::WorkflowExecutionInfo,
+};
+use tokio_util::sync::CancellationToken;
+
+/// How many replayed runs are remembered so they aren't sampled again
+const REMEMBERED_RUNS: usize = 10_000;
+
+/// Configures a [WorkflowShadower]
+#[derive(Debug, Clone, derive_builder::Builder)]
+#[builder(setter(into))]
+pub struct ShadowingConfig {
+    /// Visibility query selecting which workflows may be sampled, ex: built with
+    /// [temporal_client::VisibilityQuery]. Only closed workflows are ever sampled. Matches every
+    /// workflow if empty.
+    #[builder(default)]
+    pub query: String,
+    /// Workflows which closed longer ago than this aren't sampled
+    #[builder(default = "Duration::from_secs(60 * 60)")]
+    pub lookback: Duration,
+    /// How many of the most recently closed matching workflows each round samples from
+    #[builder(default = "100")]
+    pub candidates: usize,
+    /// How many histories each round replays
+    #[builder(default = "10")]
+    pub sample_size: usize,
+    /// How long to wait between rounds
+    #[builder(default = "Duration::from_secs(60)")]
+    pub interval: Duration,
+    /// How many histories are replayed at once
+    #[builder(default = "1")]
+    pub parallelism: usize,
+}
+
+/// Continuously replays histories of workflows which recently closed against the current build of
+/// lang's workflow code, as a canary for nondeterministic changes before they are rolled out.
+///
+/// Each round samples closed workflows using a visibility query, fetches their histories, and
+/// replays them like [replay_histories] does. Outcomes are recorded as the
+/// `workflow_shadow_replay_passed`, `workflow_shadow_replay_nondeterministic` and
+/// `workflow_shadow_replay_failed` metrics, tagged with the workflow type, from which
+/// nondeterminism rates can be derived.
+pub struct WorkflowShadower {
+    worker_config: WorkerConfig,
+    config: ShadowingConfig,
+    client: Box<dyn WorkerClient>,
+    /// Runs which have been replayed, so they aren't sampled again
+    replayed: Mutex<LruCache<String, ()>>,
+    metrics: MetricsContext,
+}
+
+impl WorkflowShadower {
+    /// Create a shadower which finds histories using `client`, and replays them with workers built
+    /// from `worker_config`
+    pub fn new(
+        worker_config: WorkerConfig,
+        config: ShadowingConfig,
+        client: impl WorkerClient + 'static,
+    ) -> Self {
+        Self {
+            metrics: worker_metrics(&worker_config),
+            worker_config,
+            config,
+            client: Box::new(client),
+            replayed: Mutex::new(LruCache::new(REMEMBERED_RUNS)),
+        }
+    }
+
+    /// Run a round every [ShadowingConfig::interval] until `shutdown` is cancelled. `driver` runs
+    /// lang's workflow code against each replay worker, as with [replay_histories]. Rounds which
+    /// can't sample workflows are skipped.
+    pub async fn run<F, Fut>(&self, driver: F, shutdown: CancellationToken)
+    where
+        F: Fn(Arc<Worker>) -> Fut,
+        Fut: Future<Output = Result<(), anyhow::Error>>,
+    {
+        loop {
+            tokio::select! {
+                res = self.run_round(&driver) => {
+                    match res {
+                        Ok(results) => {
+                            info!(stats = ?results.stats, "Finished shadowing round")
+                        }
+                        Err(e) => warn!(error = %e, "Could not sample workflows to shadow"),
+                    }
+                }
+                _ = shutdown.cancelled() => return,
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(self.config.interval) => {}
+                _ = shutdown.cancelled() => return,
+            }
+        }
+    }
+
+    /// Sample workflows which haven't been replayed yet and replay them once. Histories which
+    /// can't be fetched are skipped.
+    pub async fn run_round<F, Fut>(&self, driver: F) -> Result<BulkReplayResults, tonic::Status>
+    where
+        F: Fn(Arc<Worker>) -> Fut,
+        Fut: Future<Output = Result<(), anyhow::Error>>,
+    {
+        let sampled = self.sample().await?;
+        let mut workflow_types = HashMap::new();
+        let mut histories = vec![];
+        for info in sampled {
+            let execution = info.execution.unwrap_or_default();
+            match self
+                .fetch_history(&execution.workflow_id, &execution.run_id)
+                .await
+            {
+                Ok(history) => {
+                    let id = format!("{}/{}", execution.workflow_id, execution.run_id);
+                    workflow_types.insert(id.clone(), info.r#type.unwrap_or_default().name);
+                    self.replayed.lock().put(execution.run_id, ());
+                    histories.push(HistoryForReplay { id, history });
+                }
+                Err(e) => warn!(
+                    workflow_id = %execution.workflow_id,
+                    run_id = %execution.run_id,
+                    error = %e,
+                    "Could not fetch history to shadow"
+                ),
+            }
+        }
+
+        let results = replay_histories(
+            self.worker_config.clone(),
+            stream::iter(histories),
+            self.config.parallelism,
+            driver,
+        )
+        .await;
+        for result in &results.results {
+            let wf_type = workflow_types.remove(&result.id).unwrap_or_default();
+            let metrics = self.metrics.with_new_attrs([workflow_type(wf_type)]);
+            match &result.outcome {
+                Ok(()) => metrics.shadow_replay_passed(),
+                Err(HistoryReplayError::Failed(f)) if f.is_nondeterminism() => {
+                    metrics.shadow_replay_nondeterministic()
+                }
+                Err(_) => metrics.shadow_replay_failed(),
+            }
+        }
+        Ok(results)
+    }
+
+    /// Pick up to [ShadowingConfig::sample_size] recently closed workflows at random, leaving out
+    /// ones already replayed
+    async fn sample(&self) -> Result<Vec<WorkflowExecutionInfo>, tonic::Status> {
+        let mut query = VisibilityQuery::new();
+        if !self.config.query.is_empty() {
+            query = query.raw(format!("({})", self.config.query));
+        }
+        // A lookback reaching past the epoch places no bound on when workflows closed, but the
+        // bound is still needed to leave out ones which haven't
+        let earliest = SystemTime::now()
+            .checked_sub(self.config.lookback)
+            .map_or(UNIX_EPOCH, |t| t.max(UNIX_EPOCH));
+        let query = query.closed_after(earliest);
+        let page_size = i32::try_from(self.config.candidates).unwrap_or(i32::MAX);
+        let resp = self
+            .client
+            .list_workflow_executions(page_size, vec![], query.to_string())
+            .await?;
+        let mut candidates: Vec<_> = {
+            let replayed = self.replayed.lock();
+            resp.executions
+                .into_iter()
+                .filter(|info| matches!(&info.execution, Some(e) if !replayed.contains(&e.run_id)))
+                .collect()
+        };
+        candidates.shuffle(&mut rand::thread_rng());
+        candidates.truncate(self.config.sample_size);
+        Ok(candidates)
+    }
+
+    async fn fetch_history(
+        &self,
+        workflow_id: &str,
+        run_id: &str,
+    ) -> Result<History, tonic::Status> {
+        let mut history = History::default();
+        let mut page_token = vec![];
+        loop {
+            let resp = self
+                .client
+                .get_workflow_execution_history(
+                    workflow_id.to_string(),
+                    Some(run_id.to_string()),
+                    page_token,
+                )
+                .await?;
+            if let Some(page) = resp.history {
+                let page = History::try_from(page).map_err(|e| {
+                    tonic::Status::data_loss(format!("Failed to decode history page: {}", e))
+                })?;
+                history.events.extend(page.events);
+            }
+            if resp.next_page_token.is_empty() {
+                return Ok(history);
+            }
+            page_token = resp.next_page_token;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_help::{canned_histories, test_worker_cfg, timer_wf_driver},
+        worker::client::mocks::mock_workflow_client,
+    };
+    use temporal_sdk_core_protos::temporal::api::{
+        common::v1::{WorkflowExecution, WorkflowType},
+        workflowservice::v1::{
+            GetWorkflowExecutionHistoryResponse, ListWorkflowExecutionsResponse,
+        },
+    };
+
+    #[tokio::test]
+    async fn replays_sampled_workflows_once() {
+        let history: History = canned_histories::single_timer("1")
+            .get_full_history_info()
+            .unwrap()
+            .into();
+        let mut mock_client = mock_workflow_client();
+        mock_client
+            .expect_list_workflow_executions()
+            .times(2)
+            .returning(|page_size, _, query| {
+                assert_eq!(page_size, 100);
+                assert!(query.starts_with("(WorkflowType = 'timer') AND CloseTime >= "));
+                Ok(ListWorkflowExecutionsResponse {
+                    executions: (0..3)
+                        .map(|i| WorkflowExecutionInfo {
+                            execution: Some(WorkflowExecution {
+                                workflow_id: "wf".to_string(),
+                                run_id: format!("run-{}", i),
+                            }),
+                            r#type: Some(WorkflowType {
+                                name: "timer".to_string(),
+                            }),
+                            ..Default::default()
+                        })
+                        .collect(),
+                    ..Default::default()
+                })
+            });
+        mock_client
+            .expect_get_workflow_execution_history()
+            .times(3)
+            .returning(move |_, _, _| {
+                Ok(GetWorkflowExecutionHistoryResponse {
+                    history: Some(history.clone()),
+                    ..Default::default()
+                }
+                .into())
+            });
+        let shadower = WorkflowShadower::new(
+            test_worker_cfg().build().unwrap(),
+            ShadowingConfigBuilder::default()
+                .query("WorkflowType = 'timer'")
+                .sample_size(2_usize)
+                .build()
+                .unwrap(),
+            mock_client,
+        );
+
+        let results = shadower.run_round(timer_wf_driver).await.unwrap();
+        assert_eq!(results.stats.total, 2);
+        assert!(results.all_passed());
+        // Only the one run not replayed yet is left to sample
+        let results = shadower.run_round(timer_wf_driver).await.unwrap();
+        assert_eq!(results.stats.total, 1);
+        assert!(results.all_passed());
+    }
+
+    #[tokio::test]
+    async fn huge_lookback_and_candidates_are_clamped() {
+        let mut mock_client = mock_workflow_client();
+        mock_client
+            .expect_list_workflow_executions()
+            .times(1)
+            .returning(|page_size, _, query| {
+                assert_eq!(page_size, i32::MAX);
+                assert_eq!(query, "CloseTime >= '1970-01-01T00:00:00Z'");
+                Ok(ListWorkflowExecutionsResponse::default())
+            });
+        let shadower = WorkflowShadower::new(
+            test_worker_cfg().build().unwrap(),
+            ShadowingConfigBuilder::default()
+                .lookback(Duration::MAX)
+                .candidates(usize::MAX)
+                .build()
+                .unwrap(),
+            mock_client,
+        );
+        assert!(shadower.sample().await.unwrap().is_empty());
+    }
+}
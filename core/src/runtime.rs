@@ -0,0 +1,185 @@
+//! A [CoreRuntime] owns the process-wide state core needs (currently telemetry). It should be
+//! created once per process and used to create every client and worker, which then share its
+//! exporters rather than each trying to set up their own.
+//!
+//! Telemetry exporters are global to the process, so any other runtimes must be created with the
+//! same telemetry options, and share the exporters of the first. Telemetry is only torn down once
+//! the last runtime using it is shut down.
+//!
+//! Workers don't need their own connections either. A client from [CoreRuntime::connect_client]
+//! may be cloned and passed to any number of workers in its namespace, which then all use the
+//! same gRPC channel. The runtime keeps track of the workers it creates, so that they can all be
+//! shut down together with [CoreRuntime::shutdown_all].
+
+use crate::{
+    init_worker,
+    pollers::{Client, ClientInitError, ClientOptions, RetryClient},
+    telemetry::{telemetry_init, GlobalTelemDat},
+    TelemetryOptions, Worker, WorkerConfig,
+};
+use futures::future::join_all;
+use parking_lot::Mutex;
+use std::sync::{Arc, Weak};
+use temporal_client::AnyClient;
+use temporal_sdk_core_api::{CoreTelemetry, Worker as WorkerTrait};
+
+/// Process-wide state shared by all clients and workers. See the [module docs](self).
+pub struct CoreRuntime {
+    telemetry: &'static GlobalTelemDat,
+    /// Workers created by this runtime. Weak so that dropped workers aren't kept alive just to be
+    /// shut down later.
+    workers: Mutex<Vec<Weak<Worker>>>,
+    /// Set by [CoreRuntime::shutdown], so that telemetry is torn down on drop if this was the last
+    /// runtime using it
+    shutdown_telemetry: bool,
+}
+
+impl CoreRuntime {
+    /// Create the runtime, initializing telemetry with the provided options.
+    ///
+    /// If telemetry was already initialized (by another runtime, or by [telemetry_init]) its
+    /// exporters are shared, and an error is returned if it was initialized with different options.
+    pub fn new(telemetry_options: &TelemetryOptions) -> Result<Self, anyhow::Error> {
+        let telemetry = telemetry_init(telemetry_options)?;
+        if telemetry.options() != telemetry_options {
+            anyhow::bail!(
+                "Telemetry was already initialized in this process with different options: {:?}",
+                telemetry.options()
+            );
+        }
+        telemetry.acquire();
+        Ok(Self {
+            telemetry,
+            workers: Mutex::new(vec![]),
+            shutdown_telemetry: false,
+        })
+    }
+
+    /// Access the telemetry this runtime exports with, ex: to fetch forwarded logs or to create
+    /// lang-defined metrics.
+    pub fn telemetry(&self) -> &dyn CoreTelemetry {
+        self.telemetry
+    }
+
+    /// Replace the filter deciding which tracing data is output and exported, without restarting.
+    /// It uses the same format as [TelemetryOptions::tracing_filter].
+    ///
+    /// Returns an error if the filter is invalid, or if telemetry was initialized without console
+    /// logging or trace export, in which case core installed no tracing subscriber to filter.
+    pub fn set_tracing_filter(&self, filter: &str) -> Result<(), anyhow::Error> {
+        self.telemetry.set_tracing_filter(filter)
+    }
+
+    /// Connect a client bound to `namespace` which records its metrics with this runtime.
+    pub async fn connect_client(
+        &self,
+        options: &ClientOptions,
+        namespace: impl Into<String>,
+    ) -> Result<RetryClient<Client>, ClientInitError> {
+        options
+            .connect(namespace, self.telemetry.get_metric_meter(), None)
+            .await
+    }
+
+    /// Initialize a worker bound to a task queue, which exports its metrics and traces with this
+    /// runtime and is shut down by [CoreRuntime::shutdown_all]. See [init_worker].
+    pub fn init_worker(&self, config: WorkerConfig, client: impl Into<AnyClient>) -> Arc<Worker> {
+        let worker = Arc::new(init_worker(config, client));
+        let mut workers = self.workers.lock();
+        workers.retain(|w| w.strong_count() > 0);
+        workers.push(Arc::downgrade(&worker));
+        worker
+    }
+
+    /// Shut down every worker created by this runtime which hasn't been dropped, resolving once
+    /// all of them have finished. Polling is stopped on all of them before waiting on any, so no
+    /// worker keeps taking new work while another drains.
+    pub async fn shutdown_workers(&self) {
+        let workers: Vec<_> = self
+            .workers
+            .lock()
+            .drain(..)
+            .filter_map(|w| w.upgrade())
+            .collect();
+        for worker in &workers {
+            worker.initiate_shutdown();
+        }
+        join_all(workers.iter().map(|w| w.shutdown())).await;
+    }
+
+    /// Shut everything down in dependency order: first all workers (see
+    /// [CoreRuntime::shutdown_workers]), then telemetry if no other runtime still uses it (see
+    /// [CoreRuntime::shutdown]), so that anything the workers record while draining is still
+    /// exported. Clients are closed once the last worker and clone using them are dropped.
+    pub async fn shutdown_all(self) {
+        self.shutdown_workers().await;
+        self.shutdown();
+    }
+
+    /// Stop using telemetry. If no other runtime is using it, flush and stop all telemetry
+    /// exporters. Workers created from this runtime should be shut down first, as anything they
+    /// record afterward may be dropped. Telemetry cannot be re-initialized within the same process
+    /// after its exporters are stopped.
+    pub fn shutdown(mut self) {
+        self.shutdown_telemetry = true;
+    }
+}
+
+impl Drop for CoreRuntime {
+    fn drop(&mut self) {
+        if self.telemetry.release() && self.shutdown_telemetry {
+            self.telemetry.shutdown();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pollers::ClientOptionsBuilder, test_help::test_worker_cfg, TelemetryOptionsBuilder,
+    };
+    use temporal_sdk_core_api::worker::ShutdownPhase;
+    use url::Url;
+
+    #[test]
+    fn runtimes_share_telemetry() {
+        let rt = CoreRuntime::new(&TelemetryOptions::default()).unwrap();
+        let rt2 = CoreRuntime::new(&TelemetryOptions::default()).unwrap();
+        assert!(std::ptr::eq(rt.telemetry, rt2.telemetry));
+        assert!(rt.telemetry().get_metric_meter().is_some());
+    }
+
+    #[test]
+    fn runtimes_with_other_telemetry_options_are_rejected() {
+        let _rt = CoreRuntime::new(&TelemetryOptions::default()).unwrap();
+        let other = TelemetryOptionsBuilder::default()
+            .tracing_filter("temporal_sdk_core=DEBUG".to_string())
+            .build()
+            .unwrap();
+        assert!(CoreRuntime::new(&other).is_err());
+    }
+
+    #[tokio::test]
+    async fn shutdown_workers_drains_every_worker() {
+        let rt = CoreRuntime::new(&TelemetryOptions::default()).unwrap();
+        let opts = ClientOptionsBuilder::default()
+            .target_url(Url::parse("http://localhost:1").unwrap())
+            .client_name("runtime-test".to_string())
+            .client_version("0.1.0".to_string())
+            .worker_binary_id("runtime-test".to_string())
+            .lazy_connect(true)
+            .build()
+            .unwrap();
+        let client = rt.connect_client(&opts, "default").await.unwrap();
+        let cfg = |tq: &str| test_worker_cfg().task_queue(tq).build().unwrap();
+        let w1 = rt.init_worker(cfg("q1"), client.clone());
+        let w2 = rt.init_worker(cfg("q2"), client.clone());
+        // Dropped workers are forgotten rather than kept alive
+        drop(rt.init_worker(cfg("q3"), client));
+        rt.shutdown_workers().await;
+        for w in [w1, w2] {
+            assert_eq!(w.status().shutdown_phase, Some(ShutdownPhase::Complete));
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+use temporal_sdk_core_api::events::CoreEvent;
+use tokio::sync::broadcast;
+
+/// How many events are kept for subscribers which aren't keeping up. Once a subscriber falls this
+/// far behind, it misses the oldest events (and is told so when it next receives).
+const EVENT_BUFFER_SIZE: usize = 1024;
+
+/// Publishes a worker's [CoreEvent]s to anyone subscribed. Cheap to clone, and emitting while
+/// nobody is subscribed does nothing.
+#[derive(Clone, Debug)]
+pub(crate) struct CoreEventEmitter {
+    tx: broadcast::Sender<CoreEvent>,
+}
+
+impl CoreEventEmitter {
+    pub(crate) fn emit(&self, event: CoreEvent) {
+        let _ = self.tx.send(event);
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<CoreEvent> {
+        self.tx.subscribe()
+    }
+
+    /// Re-emit the pauses a client's poll circuit breaker reports as [CoreEvent::PollBackoff]s,
+    /// until the client goes away
+    pub(crate) fn forward_poll_pauses(&self, mut pauses: broadcast::Receiver<Duration>) {
+        let emitter = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match pauses.recv().await {
+                    Ok(pause) => emitter.emit(CoreEvent::PollBackoff { pause }),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+}
+
+impl Default for CoreEventEmitter {
+    fn default() -> Self {
+        Self {
+            tx: broadcast::channel(EVENT_BUFFER_SIZE).0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn poll_pauses_are_forwarded() {
+        let emitter = CoreEventEmitter::default();
+        let mut events = emitter.subscribe();
+        let (pause_tx, pause_rx) = broadcast::channel(1);
+        emitter.forward_poll_pauses(pause_rx);
+        pause_tx.send(Duration::from_secs(5)).unwrap();
+        assert_eq!(
+            events.recv().await.unwrap(),
+            CoreEvent::PollBackoff {
+                pause: Duration::from_secs(5)
+            }
+        );
+    }
+}
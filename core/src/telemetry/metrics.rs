@@ -1,14 +1,30 @@
 use super::TELEM_SERVICE_NAME;
+use once_cell::sync::OnceCell;
 use opentelemetry::{
+    attributes::{AttributeSet, DefaultAttributeEncoder},
     global,
-    metrics::{Counter, Descriptor, InstrumentKind, Meter, ValueRecorder},
+    metrics::{Counter, Descriptor, InstrumentKind, Meter, Unit, ValueObserver, ValueRecorder},
     sdk::{
-        export::metrics::{Aggregator, AggregatorSelector},
-        metrics::aggregators::{histogram, last_value, sum},
+        export::metrics::{
+            Aggregator, AggregatorSelector, Count, Histogram, LastValue, Record, Sum,
+        },
+        metrics::aggregators::{
+            histogram, last_value, sum, HistogramAggregator, LastValueAggregator, SumAggregator,
+        },
     },
-    KeyValue,
+    KeyValue, Value,
+};
+use parking_lot::Mutex;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+use temporal_sdk_core_api::metrics::{
+    self as core_api_metrics, CoreMeter, CoreMetric, CoreMetricValue, MetricAttributes,
+    MetricKeyValue, MetricParameters, MetricValue,
 };
-use std::{borrow::Cow, sync::Arc, time::Duration};
 
 /// Used to track context associated with metrics, and record/update them
 ///
@@ -27,19 +43,32 @@ impl MetricsContext {
         Self { kvs: Arc::new(kvs) }
     }
 
+    /// Context for a worker in the given namespace, including any globally configured attributes
     pub(crate) fn top_level(namespace: String) -> Self {
-        Self::new(vec![KeyValue::new(KEY_NAMESPACE, namespace)])
+        Self::top_level_with_settings(namespace, metric_settings())
     }
 
-    pub(crate) fn with_task_q(mut self, tq: String) -> Self {
-        Arc::make_mut(&mut self.kvs).push(task_queue(tq));
-        self
+    fn top_level_with_settings(namespace: String, settings: &MetricSettings) -> Self {
+        let global = settings
+            .global_attributes
+            .iter()
+            .map(|(k, v)| KeyValue::new(k.clone(), v.clone()));
+        Self::new(
+            settings
+                .allowed(global.chain([KeyValue::new(KEY_NAMESPACE, namespace)]))
+                .collect(),
+        )
     }
 
-    /// Extend an existing metrics context with new attributes
+    pub(crate) fn with_task_q(self, tq: String) -> Self {
+        self.with_new_attrs([task_queue(tq)])
+    }
+
+    /// Extend an existing metrics context with new attributes. Attributes which have been
+    /// excluded by configuration are dropped.
     pub(crate) fn with_new_attrs(&self, new_kvs: impl IntoIterator<Item = KeyValue>) -> Self {
         let mut kvs = self.kvs.clone();
-        Arc::make_mut(&mut kvs).extend(new_kvs);
+        Arc::make_mut(&mut kvs).extend(metric_settings().allowed(new_kvs));
         Self { kvs }
     }
 
@@ -78,24 +107,49 @@ impl MetricsContext {
         WF_CONT_COUNTER.add(1, &self.kvs);
     }
 
-    /// Record workflow total execution time in milliseconds
+    /// A workflow run was reset because it hit nondeterminism
+    pub(crate) fn wf_nondeterminism_reset(&self) {
+        WF_NONDETERMINISM_RESET_COUNTER.add(1, &self.kvs);
+    }
+
+    /// A history sampled by a shadower replayed successfully
+    pub(crate) fn shadow_replay_passed(&self) {
+        WF_SHADOW_REPLAY_PASSED_COUNTER.add(1, &self.kvs);
+    }
+
+    /// A history sampled by a shadower failed replay because of nondeterminism
+    pub(crate) fn shadow_replay_nondeterministic(&self) {
+        WF_SHADOW_REPLAY_NONDETERMINISTIC_COUNTER.add(1, &self.kvs);
+    }
+
+    /// A history sampled by a shadower failed replay for any other reason
+    pub(crate) fn shadow_replay_failed(&self) {
+        WF_SHADOW_REPLAY_FAILED_COUNTER.add(1, &self.kvs);
+    }
+
+    /// A history event core doesn't recognize was skipped
+    pub(crate) fn wf_unknown_history_event(&self) {
+        WF_UNKNOWN_HISTORY_EVENT_COUNTER.add(1, &self.kvs);
+    }
+
+    /// Record workflow total execution time
     pub(crate) fn wf_e2e_latency(&self, dur: Duration) {
-        WF_E2E_LATENCY.record(dur.as_millis() as u64, &self.kvs);
+        WF_E2E_LATENCY.record(dur, &self.kvs);
     }
 
-    /// Record workflow task schedule to start time in millis
+    /// Record workflow task schedule to start time
     pub(crate) fn wf_task_sched_to_start_latency(&self, dur: Duration) {
-        WF_TASK_SCHED_TO_START_LATENCY.record(dur.as_millis() as u64, &self.kvs);
+        WF_TASK_SCHED_TO_START_LATENCY.record(dur, &self.kvs);
     }
 
-    /// Record workflow task execution time in milliseconds
+    /// Record workflow task execution time
     pub(crate) fn wf_task_latency(&self, dur: Duration) {
-        WF_TASK_EXECUTION_LATENCY.record(dur.as_millis() as u64, &self.kvs);
+        WF_TASK_EXECUTION_LATENCY.record(dur, &self.kvs);
     }
 
     /// Record time it takes to catch up on replaying a WFT
     pub(crate) fn wf_task_replay_latency(&self, dur: Duration) {
-        WF_TASK_REPLAY_LATENCY.record(dur.as_millis() as u64, &self.kvs);
+        WF_TASK_REPLAY_LATENCY.record(dur, &self.kvs);
     }
 
     /// An activity long poll timed out
@@ -108,15 +162,37 @@ impl MetricsContext {
         ACT_EXECUTION_FAILED.add(1, &self.kvs);
     }
 
-    /// Record activity task schedule to start time in millis
+    /// An activity task was failed without being dispatched since it had exceeded the maximum
+    /// schedule-to-start time
+    pub(crate) fn act_sched_to_start_exceeded(&self) {
+        ACT_SCHED_TO_START_EXCEEDED.add(1, &self.kvs);
+    }
+
+    /// Record activity task schedule to start time
     pub(crate) fn act_sched_to_start_latency(&self, dur: Duration) {
-        ACT_SCHED_TO_START_LATENCY.record(dur.as_millis() as u64, &self.kvs);
+        ACT_SCHED_TO_START_LATENCY.record(dur, &self.kvs);
     }
 
     /// Record time it took to complete activity execution, from the time core generated the
     /// activity task, to the time lang responded with a completion (failure or success).
     pub(crate) fn act_execution_latency(&self, dur: Duration) {
-        ACT_EXEC_LATENCY.record(dur.as_millis() as u64, &self.kvs);
+        ACT_EXEC_LATENCY.record(dur, &self.kvs);
+    }
+
+    /// Record the encoded size, in bytes, of the input payloads an activity was scheduled with
+    pub(crate) fn act_input_payload_size(&self, bytes: usize) {
+        ACT_INPUT_PAYLOAD_SIZE.record(bytes as u64, &self.kvs);
+    }
+
+    /// Record the encoded size, in bytes, of the result payload of a successful activity
+    pub(crate) fn act_result_payload_size(&self, bytes: usize) {
+        ACT_RESULT_PAYLOAD_SIZE.record(bytes as u64, &self.kvs);
+    }
+
+    /// Record the encoded size, in bytes, of the payloads of a command being sent to the server as
+    /// part of a workflow task completion. Context should include the workflow and command type.
+    pub(crate) fn wf_command_payload_size(&self, bytes: usize) {
+        WF_COMMAND_PAYLOAD_SIZE.record(bytes as u64, &self.kvs);
     }
 
     /// A worker was registered
@@ -148,6 +224,16 @@ impl MetricsContext {
     pub(crate) fn cache_size(&self, size: u64) {
         STICKY_CACHE_SIZE.record(size, &self.kvs);
     }
+
+    /// Record current number of activations queued for lang
+    pub(crate) fn pending_activations(&self, num: usize) {
+        PENDING_ACTIVATIONS.record(num as u64, &self.kvs);
+    }
+
+    /// Record current number of buffered workflow tasks ready to be applied
+    pub(crate) fn buffered_wfts(&self, num: usize) {
+        BUFFERED_WFTS.record(num as u64, &self.kvs);
+    }
 }
 
 lazy_static::lazy_static! {
@@ -177,6 +263,76 @@ macro_rules! tm {
             };
         }
     };
+    (vr_dur, $ident:ident, $name:expr) => {
+        lazy_static::lazy_static! {
+            static ref $ident: DurationRecorder = DurationRecorder::new($name);
+        }
+    };
+}
+
+/// Settings which change how core's own metrics are recorded. Set once, by
+/// [super::telemetry_init].
+#[derive(Debug, Default)]
+pub(super) struct MetricSettings {
+    pub(super) histogram_bucket_overrides: HashMap<String, Vec<f64>>,
+    pub(super) use_seconds_for_durations: bool,
+    pub(super) global_attributes: HashMap<String, String>,
+    pub(super) excluded_attributes: HashSet<String>,
+}
+
+impl MetricSettings {
+    fn allowed<'a>(
+        &'a self,
+        kvs: impl IntoIterator<Item = KeyValue> + 'a,
+    ) -> impl Iterator<Item = KeyValue> + 'a {
+        kvs.into_iter()
+            .filter(move |kv| !self.excluded_attributes.contains(kv.key.as_str()))
+    }
+}
+
+static METRIC_SETTINGS: OnceCell<MetricSettings> = OnceCell::new();
+
+/// Set how metrics are recorded. Must be called before any metric is used to have any effect, and
+/// only the first call does anything.
+pub(super) fn set_metric_settings(settings: MetricSettings) {
+    let _ = METRIC_SETTINGS.set(settings);
+}
+
+fn metric_settings() -> &'static MetricSettings {
+    METRIC_SETTINGS.get_or_init(Default::default)
+}
+
+/// Records latencies in either milliseconds or seconds, depending on [MetricSettings]
+enum DurationRecorder {
+    Millis(ValueRecorder<u64>),
+    Seconds(ValueRecorder<f64>),
+}
+
+impl DurationRecorder {
+    fn new(name: &'static str) -> Self {
+        if metric_settings().use_seconds_for_durations {
+            Self::Seconds(
+                METRIC_METER
+                    .f64_value_recorder(name)
+                    .with_unit(Unit::new("s"))
+                    .init(),
+            )
+        } else {
+            Self::Millis(
+                METRIC_METER
+                    .u64_value_recorder(name)
+                    .with_unit(Unit::new("ms"))
+                    .init(),
+            )
+        }
+    }
+
+    fn record(&self, dur: Duration, kvs: &[KeyValue]) {
+        match self {
+            Self::Millis(r) => r.record(dur.as_millis() as u64, kvs),
+            Self::Seconds(r) => r.record(dur.as_secs_f64(), kvs),
+        }
+    }
 }
 
 const KEY_NAMESPACE: &str = "namespace";
@@ -185,6 +341,7 @@ const KEY_TASK_QUEUE: &str = "task_queue";
 const KEY_ACT_TYPE: &str = "activity_type";
 const KEY_POLLER_TYPE: &str = "poller_type";
 const KEY_WORKER_TYPE: &str = "worker_type";
+const KEY_COMMAND_TYPE: &str = "command_type";
 
 pub(crate) fn workflow_poller() -> KeyValue {
     KeyValue::new(KEY_POLLER_TYPE, "workflow_task")
@@ -204,6 +361,9 @@ pub(crate) fn activity_type(ty: String) -> KeyValue {
 pub(crate) fn workflow_type(ty: String) -> KeyValue {
     KeyValue::new(KEY_WF_TYPE, ty)
 }
+pub(crate) fn command_type(ty: String) -> KeyValue {
+    KeyValue::new(KEY_COMMAND_TYPE, ty)
+}
 pub(crate) const fn workflow_worker_type() -> KeyValue {
     KeyValue {
         key: opentelemetry::Key::from_static_str(KEY_WORKER_TYPE),
@@ -227,8 +387,33 @@ tm!(ctr, WF_COMPLETED_COUNTER, "workflow_completed");
 tm!(ctr, WF_CANCELED_COUNTER, "workflow_canceled");
 tm!(ctr, WF_FAILED_COUNTER, "workflow_failed");
 tm!(ctr, WF_CONT_COUNTER, "workflow_continue_as_new");
+tm!(
+    ctr,
+    WF_NONDETERMINISM_RESET_COUNTER,
+    "workflow_nondeterminism_reset"
+);
+tm!(
+    ctr,
+    WF_UNKNOWN_HISTORY_EVENT_COUNTER,
+    "workflow_unknown_history_event"
+);
+tm!(
+    ctr,
+    WF_SHADOW_REPLAY_PASSED_COUNTER,
+    "workflow_shadow_replay_passed"
+);
+tm!(
+    ctr,
+    WF_SHADOW_REPLAY_NONDETERMINISTIC_COUNTER,
+    "workflow_shadow_replay_nondeterministic"
+);
+tm!(
+    ctr,
+    WF_SHADOW_REPLAY_FAILED_COUNTER,
+    "workflow_shadow_replay_failed"
+);
 const WF_E2E_LATENCY_NAME: &str = "workflow_endtoend_latency";
-tm!(vr_u64, WF_E2E_LATENCY, WF_E2E_LATENCY_NAME);
+tm!(vr_dur, WF_E2E_LATENCY, WF_E2E_LATENCY_NAME);
 
 tm!(
     ctr,
@@ -247,31 +432,50 @@ tm!(
 );
 const WF_TASK_SCHED_TO_START_LATENCY_NAME: &str = "workflow_task_schedule_to_start_latency";
 tm!(
-    vr_u64,
+    vr_dur,
     WF_TASK_SCHED_TO_START_LATENCY,
     WF_TASK_SCHED_TO_START_LATENCY_NAME
 );
 const WF_TASK_REPLAY_LATENCY_NAME: &str = "workflow_task_replay_latency";
-tm!(vr_u64, WF_TASK_REPLAY_LATENCY, WF_TASK_REPLAY_LATENCY_NAME);
+tm!(vr_dur, WF_TASK_REPLAY_LATENCY, WF_TASK_REPLAY_LATENCY_NAME);
 const WF_TASK_EXECUTION_LATENCY_NAME: &str = "workflow_task_execution_latency";
 tm!(
-    vr_u64,
+    vr_dur,
     WF_TASK_EXECUTION_LATENCY,
     WF_TASK_EXECUTION_LATENCY_NAME
 );
 
 tm!(ctr, ACT_POLL_NO_TASK, "activity_poll_no_task");
 tm!(ctr, ACT_EXECUTION_FAILED, "activity_execution_failed");
+tm!(
+    ctr,
+    ACT_SCHED_TO_START_EXCEEDED,
+    "activity_schedule_to_start_exceeded"
+);
 // Act task unregistered can't be known by core right now since it's not well defined as an
 // activity result. We could add a flag to the failed activity result if desired.
 const ACT_SCHED_TO_START_LATENCY_NAME: &str = "activity_schedule_to_start_latency";
 tm!(
-    vr_u64,
+    vr_dur,
     ACT_SCHED_TO_START_LATENCY,
     ACT_SCHED_TO_START_LATENCY_NAME
 );
 const ACT_EXEC_LATENCY_NAME: &str = "activity_execution_latency";
-tm!(vr_u64, ACT_EXEC_LATENCY, ACT_EXEC_LATENCY_NAME);
+tm!(vr_dur, ACT_EXEC_LATENCY, ACT_EXEC_LATENCY_NAME);
+const ACT_INPUT_PAYLOAD_SIZE_NAME: &str = "activity_input_payload_size";
+tm!(vr_u64, ACT_INPUT_PAYLOAD_SIZE, ACT_INPUT_PAYLOAD_SIZE_NAME);
+const ACT_RESULT_PAYLOAD_SIZE_NAME: &str = "activity_result_payload_size";
+tm!(
+    vr_u64,
+    ACT_RESULT_PAYLOAD_SIZE,
+    ACT_RESULT_PAYLOAD_SIZE_NAME
+);
+const WF_COMMAND_PAYLOAD_SIZE_NAME: &str = "workflow_command_payload_size";
+tm!(
+    vr_u64,
+    WF_COMMAND_PAYLOAD_SIZE,
+    WF_COMMAND_PAYLOAD_SIZE_NAME
+);
 
 // name kept as worker start for compat with old sdk / what users expect
 tm!(ctr, WORKER_REGISTERED, "worker_start");
@@ -284,6 +488,178 @@ tm!(ctr, STICKY_CACHE_HIT, "sticky_cache_hit");
 tm!(ctr, STICKY_CACHE_MISS, "sticky_cache_miss");
 const STICKY_CACHE_SIZE_NAME: &str = "sticky_cache_size";
 tm!(vr_u64, STICKY_CACHE_SIZE, STICKY_CACHE_SIZE_NAME);
+const PENDING_ACTIVATIONS_NAME: &str = "workflow_pending_activations";
+tm!(vr_u64, PENDING_ACTIVATIONS, PENDING_ACTIVATIONS_NAME);
+const BUFFERED_WFTS_NAME: &str = "workflow_buffered_tasks";
+tm!(vr_u64, BUFFERED_WFTS, BUFFERED_WFTS_NAME);
+
+/// Implements [CoreMeter] for lang, on top of the same OTel meter core's own metrics use
+#[derive(Debug)]
+pub(crate) struct CoreOtelMeter {
+    meter: &'static Meter,
+    /// Attributes core attaches to all of its own metrics, which lang's get too
+    core_attributes: Vec<MetricKeyValue>,
+}
+
+impl CoreOtelMeter {
+    pub(crate) fn new() -> Self {
+        Self::with_settings(&METRIC_METER, metric_settings())
+    }
+
+    fn with_settings(meter: &'static Meter, settings: &MetricSettings) -> Self {
+        let core_attributes = settings
+            .global_attributes
+            .iter()
+            .filter(|(k, _)| !settings.excluded_attributes.contains(k.as_str()))
+            .map(|(k, v)| MetricKeyValue::new(k.clone(), v.clone()))
+            .collect();
+        Self {
+            meter,
+            core_attributes,
+        }
+    }
+}
+
+impl CoreMeter for CoreOtelMeter {
+    fn new_attributes(&self, attributes: Vec<MetricKeyValue>) -> MetricAttributes {
+        MetricAttributes::new(self.core_attributes.clone()).with_new(attributes)
+    }
+
+    fn counter(&self, params: MetricParameters) -> Arc<dyn core_api_metrics::Counter> {
+        let mut builder = self.meter.u64_counter(params.name.into_owned());
+        if !params.description.is_empty() {
+            builder = builder.with_description(params.description);
+        }
+        if !params.unit.is_empty() {
+            builder = builder.with_unit(Unit::new(params.unit));
+        }
+        Arc::new(OtelCounter(builder.init()))
+    }
+
+    fn histogram(&self, params: MetricParameters) -> Arc<dyn core_api_metrics::Histogram> {
+        let mut builder = self.meter.u64_value_recorder(params.name.into_owned());
+        if !params.description.is_empty() {
+            builder = builder.with_description(params.description);
+        }
+        if !params.unit.is_empty() {
+            builder = builder.with_unit(Unit::new(params.unit));
+        }
+        Arc::new(OtelRecorder(builder.init()))
+    }
+
+    fn gauge(&self, params: MetricParameters) -> Arc<dyn core_api_metrics::Gauge> {
+        let values: Arc<Mutex<GaugeValues>> = Default::default();
+        let observed = values.clone();
+        let mut builder = self
+            .meter
+            .u64_value_observer(params.name.into_owned(), move |res| {
+                for (kvs, value) in observed.lock().values() {
+                    res.observe(*value, kvs);
+                }
+            });
+        if !params.description.is_empty() {
+            builder = builder.with_description(params.description);
+        }
+        if !params.unit.is_empty() {
+            builder = builder.with_unit(Unit::new(params.unit));
+        }
+        Arc::new(OtelGauge {
+            values,
+            _observer: builder.init(),
+        })
+    }
+}
+
+struct OtelCounter(Counter<u64>);
+impl core_api_metrics::Counter for OtelCounter {
+    fn add(&self, value: u64, attributes: &MetricAttributes) {
+        self.0.add(value, &otel_kvs(attributes));
+    }
+}
+
+struct OtelRecorder(ValueRecorder<u64>);
+impl core_api_metrics::Histogram for OtelRecorder {
+    fn record(&self, value: u64, attributes: &MetricAttributes) {
+        self.0.record(value, &otel_kvs(attributes));
+    }
+}
+
+/// The last value recorded to a gauge for each distinct set of attributes, keyed by their encoding
+type GaugeValues = HashMap<String, (Vec<KeyValue>, u64)>;
+
+/// Gauges are value observers, which report the last value recorded for each set of attributes
+/// every time metrics are collected
+struct OtelGauge {
+    values: Arc<Mutex<GaugeValues>>,
+    _observer: ValueObserver<u64>,
+}
+impl core_api_metrics::Gauge for OtelGauge {
+    fn record(&self, value: u64, attributes: &MetricAttributes) {
+        let kvs = otel_kvs(attributes);
+        let key = AttributeSet::from_attributes(kvs.iter().cloned())
+            .encoded(Some(&DefaultAttributeEncoder));
+        self.values.lock().insert(key, (kvs, value));
+    }
+}
+
+fn otel_kvs(attributes: &MetricAttributes) -> Vec<KeyValue> {
+    attributes
+        .kvs()
+        .iter()
+        .map(|kv| {
+            let value = match &kv.value {
+                MetricValue::String(v) => Value::from(v.clone()),
+                MetricValue::Int(v) => Value::I64(*v),
+                MetricValue::Float(v) => Value::F64(*v),
+                MetricValue::Bool(v) => Value::Bool(*v),
+            };
+            KeyValue::new(kv.key.clone(), value)
+        })
+        .collect()
+}
+
+/// Converts a collected record into the form core forwards to lang. Returns `None` if nothing has
+/// been recorded yet, or for aggregations core doesn't use.
+pub(super) fn core_metric(record: &Record<'_>) -> Option<CoreMetric> {
+    let descriptor = record.descriptor();
+    let kind = descriptor.number_kind();
+    let aggregator = record.aggregator()?.as_any();
+    let value = if let Some(agg) = aggregator.downcast_ref::<SumAggregator>() {
+        CoreMetricValue::Sum(agg.sum().ok()?.to_f64(kind))
+    } else if let Some(agg) = aggregator.downcast_ref::<LastValueAggregator>() {
+        CoreMetricValue::LastValue(agg.last_value().ok()?.0.to_f64(kind))
+    } else if let Some(agg) = aggregator.downcast_ref::<HistogramAggregator>() {
+        let buckets = agg.histogram().ok()?;
+        CoreMetricValue::Histogram {
+            count: agg.count().ok()?,
+            sum: agg.sum().ok()?.to_f64(kind),
+            bucket_boundaries: buckets.boundaries().clone(),
+            bucket_counts: buckets.counts().iter().map(|c| *c as u64).collect(),
+        }
+    } else {
+        return None;
+    };
+    let attributes = record
+        .attributes()
+        .iter()
+        .map(|(k, v)| {
+            let value = match v {
+                Value::Bool(v) => MetricValue::Bool(*v),
+                Value::I64(v) => MetricValue::Int(*v),
+                Value::F64(v) => MetricValue::Float(*v),
+                v => MetricValue::String(v.to_string()),
+            };
+            MetricKeyValue::new(k.as_str(), value)
+        })
+        .collect();
+    Some(CoreMetric {
+        name: descriptor.name().to_string(),
+        description: descriptor.description().cloned().unwrap_or_default(),
+        unit: descriptor.unit().unwrap_or_default().to_string(),
+        attributes,
+        value,
+    })
+}
 
 /// Artisanal, handcrafted latency buckets for workflow e2e latency which should expose a useful
 /// set of buckets for < 1 day runtime workflows. Beyond that, this metric probably isn't very
@@ -318,10 +694,53 @@ static ACT_EXE_MS_BUCKETS: &[f64] = &[50., 100., 500., 1000., 5000., 10_000., 60
 /// Schedule-to-start latency buckets for both WFT and AT
 static TASK_SCHED_TO_START_MS_BUCKETS: &[f64] = &[100., 500., 1000., 5000., 10_000.];
 
+/// Payload size buckets, in bytes. Topped out around the server's default 2MB blob size limit and
+/// the 4MB default gRPC message size limit, since those are what we want people to see coming.
+static PAYLOAD_SIZE_BYTES_BUCKETS: &[f64] = &[
+    128., 1024., 16_384., 65_536., 262_144., 1_048_576., // 1 MB
+    2_097_152., // 2 MB
+    4_194_304., // 4 MB
+];
+
 /// Default buckets. Should never really be used as they will be meaningless for many things, but
 /// broadly it's trying to represent latencies in millis.
 pub(super) static DEFAULT_MS_BUCKETS: &[f64] = &[50., 100., 500., 1000., 2500., 10_000.];
 
+/// Bucket boundaries for the named histogram, taking user overrides and units into account
+fn histogram_buckets<'a>(name: &str, settings: &'a MetricSettings) -> Cow<'a, [f64]> {
+    if let Some(buckets) = settings.histogram_bucket_overrides.get(name) {
+        return Cow::Borrowed(buckets);
+    }
+    let buckets = match name {
+        WF_E2E_LATENCY_NAME => WF_LATENCY_MS_BUCKETS,
+        WF_TASK_EXECUTION_LATENCY_NAME | WF_TASK_REPLAY_LATENCY_NAME => WF_TASK_MS_BUCKETS,
+        WF_TASK_SCHED_TO_START_LATENCY_NAME | ACT_SCHED_TO_START_LATENCY_NAME => {
+            TASK_SCHED_TO_START_MS_BUCKETS
+        }
+        ACT_EXEC_LATENCY_NAME => ACT_EXE_MS_BUCKETS,
+        ACT_INPUT_PAYLOAD_SIZE_NAME
+        | ACT_RESULT_PAYLOAD_SIZE_NAME
+        | WF_COMMAND_PAYLOAD_SIZE_NAME => PAYLOAD_SIZE_BYTES_BUCKETS,
+        _ => DEFAULT_MS_BUCKETS,
+    };
+    if settings.use_seconds_for_durations && is_duration_metric(name) {
+        return Cow::Owned(buckets.iter().map(|ms| ms / 1000.).collect());
+    }
+    Cow::Borrowed(buckets)
+}
+
+fn is_duration_metric(name: &str) -> bool {
+    matches!(
+        name,
+        WF_E2E_LATENCY_NAME
+            | WF_TASK_EXECUTION_LATENCY_NAME
+            | WF_TASK_REPLAY_LATENCY_NAME
+            | WF_TASK_SCHED_TO_START_LATENCY_NAME
+            | ACT_SCHED_TO_START_LATENCY_NAME
+            | ACT_EXEC_LATENCY_NAME
+    )
+}
+
 /// Chooses appropriate aggregators for our metrics
 #[derive(Debug)]
 pub struct SDKAggSelector;
@@ -336,25 +755,221 @@ impl AggregatorSelector for SDKAggSelector {
         if *descriptor.instrument_kind() == InstrumentKind::ValueRecorder {
             // Some recorders are just gauges
             match descriptor.name() {
-                STICKY_CACHE_SIZE_NAME | NUM_POLLERS_NAME | TASK_SLOTS_AVAILABLE_NAME => {
-                    return Some(Arc::new(last_value()))
-                }
+                STICKY_CACHE_SIZE_NAME
+                | NUM_POLLERS_NAME
+                | TASK_SLOTS_AVAILABLE_NAME
+                | PENDING_ACTIVATIONS_NAME
+                | BUFFERED_WFTS_NAME => return Some(Arc::new(last_value())),
                 _ => (),
             }
 
             // Other recorders will select their appropriate buckets
-            let buckets = match descriptor.name() {
-                WF_E2E_LATENCY_NAME => WF_LATENCY_MS_BUCKETS,
-                WF_TASK_EXECUTION_LATENCY_NAME | WF_TASK_REPLAY_LATENCY_NAME => WF_TASK_MS_BUCKETS,
-                WF_TASK_SCHED_TO_START_LATENCY_NAME | ACT_SCHED_TO_START_LATENCY_NAME => {
-                    TASK_SCHED_TO_START_MS_BUCKETS
-                }
-                ACT_EXEC_LATENCY_NAME => ACT_EXE_MS_BUCKETS,
-                _ => DEFAULT_MS_BUCKETS,
-            };
-            return Some(Arc::new(histogram(descriptor, buckets)));
+            let buckets = histogram_buckets(descriptor.name(), metric_settings());
+            return Some(Arc::new(histogram(descriptor, &buckets)));
         }
 
         Some(Arc::new(sum()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::{
+        metrics::{MeterProvider, NumberKind},
+        sdk::{
+            export::metrics::{CheckpointSet, ExportKindSelector},
+            metrics::controllers,
+        },
+    };
+
+    #[test]
+    fn gauges_report_last_value_with_core_attributes() {
+        let mut controller = controllers::pull(
+            Box::new(SDKAggSelector),
+            Box::new(ExportKindSelector::Cumulative),
+        )
+        .with_cache_period(Duration::ZERO)
+        .with_memory(true)
+        .build();
+        let meter = Box::leak(Box::new(controller.provider().meter("test", None)));
+        let settings = MetricSettings {
+            global_attributes: HashMap::from([("region".to_string(), "us-west".to_string())]),
+            ..Default::default()
+        };
+        let core_meter = CoreOtelMeter::with_settings(meter, &settings);
+        let gauge = core_meter.gauge("lang_custom_gauge".into());
+        let attrs = core_meter.new_attributes(vec![MetricKeyValue::new("workflow", "wf1")]);
+        gauge.record(5, &attrs);
+        gauge.record(3, &attrs);
+
+        controller.collect().unwrap();
+        let mut observed = vec![];
+        controller
+            .try_for_each(&ExportKindSelector::Cumulative, &mut |record| {
+                let attr = |key: &str| {
+                    record
+                        .attributes()
+                        .iter()
+                        .find(|(k, _)| k.as_str() == key)
+                        .map(|(_, v)| v.to_string())
+                };
+                let (value, _) = record
+                    .aggregator()
+                    .unwrap()
+                    .as_any()
+                    .downcast_ref::<LastValueAggregator>()
+                    .unwrap()
+                    .last_value()
+                    .unwrap();
+                observed.push((
+                    record.descriptor().name().to_string(),
+                    attr("region"),
+                    attr("workflow"),
+                    value.to_u64(&NumberKind::U64),
+                ));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            observed,
+            vec![(
+                "lang_custom_gauge".to_string(),
+                Some("us-west".to_string()),
+                Some("wf1".to_string()),
+                3
+            )]
+        );
+    }
+
+    #[test]
+    fn records_convert_to_core_metrics() {
+        let mut controller = controllers::pull(
+            Box::new(SDKAggSelector),
+            Box::new(ExportKindSelector::Cumulative),
+        )
+        .with_cache_period(Duration::ZERO)
+        .with_memory(true)
+        .build();
+        let meter = controller.provider().meter("test", None);
+        let kvs = [
+            KeyValue::new("namespace", "ns"),
+            KeyValue::new("attempt", 2_i64),
+        ];
+        let counter = meter.u64_counter("forwarded_counter").init();
+        counter.add(2, &kvs);
+        counter.add(3, &kvs);
+        let recorder = meter
+            .u64_value_recorder(ACT_EXEC_LATENCY_NAME)
+            .with_unit(Unit::new("ms"))
+            .init();
+        recorder.record(75, &kvs);
+        recorder.record(20_000, &kvs);
+
+        controller.collect().unwrap();
+        let mut metrics = vec![];
+        controller
+            .try_for_each(&ExportKindSelector::Cumulative, &mut |record| {
+                metrics.extend(core_metric(record));
+                Ok(())
+            })
+            .unwrap();
+        metrics.sort_by(|a, b| a.name.cmp(&b.name));
+        let attributes = vec![
+            MetricKeyValue::new("attempt", 2_i64),
+            MetricKeyValue::new("namespace", "ns"),
+        ];
+        assert_eq!(
+            metrics,
+            vec![
+                CoreMetric {
+                    name: ACT_EXEC_LATENCY_NAME.to_string(),
+                    description: "".to_string(),
+                    unit: "ms".to_string(),
+                    attributes: attributes.clone(),
+                    value: CoreMetricValue::Histogram {
+                        count: 2,
+                        sum: 20_075.,
+                        bucket_boundaries: ACT_EXE_MS_BUCKETS.to_vec(),
+                        bucket_counts: vec![0, 1, 0, 0, 0, 0, 1, 0],
+                    },
+                },
+                CoreMetric {
+                    name: "forwarded_counter".to_string(),
+                    description: "".to_string(),
+                    unit: "".to_string(),
+                    attributes,
+                    value: CoreMetricValue::Sum(5.),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn attributes_convert_to_otel() {
+        let attrs =
+            CoreOtelMeter::new().new_attributes(vec![MetricKeyValue::new("workflow", "wf1")]);
+        let attrs = attrs.with_new([MetricKeyValue::new("attempt", 2)]);
+        assert_eq!(
+            otel_kvs(&attrs),
+            vec![
+                KeyValue::new("workflow", "wf1"),
+                KeyValue::new("attempt", 2_i64)
+            ]
+        );
+    }
+    #[test]
+    fn attributes_can_be_added_and_excluded() {
+        let settings = MetricSettings {
+            global_attributes: HashMap::from([("region".to_string(), "us-west".to_string())]),
+            excluded_attributes: HashSet::from([KEY_WF_TYPE.to_string()]),
+            ..Default::default()
+        };
+        let ctx = MetricsContext::top_level_with_settings("ns".to_string(), &settings);
+        assert_eq!(
+            *ctx.kvs,
+            vec![
+                KeyValue::new("region", "us-west"),
+                KeyValue::new(KEY_NAMESPACE, "ns")
+            ]
+        );
+        let kept: Vec<_> = settings
+            .allowed([
+                workflow_type("wf".to_string()),
+                activity_type("act".to_string()),
+            ])
+            .collect();
+        assert_eq!(kept, vec![activity_type("act".to_string())]);
+    }
+
+    #[test]
+    fn histogram_buckets_respect_settings() {
+        let defaults = MetricSettings::default();
+        assert_eq!(
+            &*histogram_buckets(ACT_EXEC_LATENCY_NAME, &defaults),
+            ACT_EXE_MS_BUCKETS
+        );
+
+        let in_seconds = MetricSettings {
+            histogram_bucket_overrides: HashMap::from([(
+                WF_TASK_EXECUTION_LATENCY_NAME.to_string(),
+                vec![0.0001, 0.001],
+            )]),
+            use_seconds_for_durations: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            &*histogram_buckets(ACT_SCHED_TO_START_LATENCY_NAME, &in_seconds),
+            &[0.1, 0.5, 1., 5., 10.]
+        );
+        assert_eq!(
+            &*histogram_buckets(WF_TASK_EXECUTION_LATENCY_NAME, &in_seconds),
+            &[0.0001, 0.001]
+        );
+        // Non-duration metrics are unaffected by the unit setting
+        assert_eq!(
+            &*histogram_buckets(ACT_INPUT_PAYLOAD_SIZE_NAME, &in_seconds),
+            PAYLOAD_SIZE_BYTES_BUCKETS
+        );
+    }
+}
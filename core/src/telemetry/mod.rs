@@ -1,9 +1,16 @@
+pub(crate) mod events;
 pub(crate) mod metrics;
 mod prometheus_server;
+pub(crate) mod propagation;
 
 use crate::{
     log_export::CoreExportLogger,
-    telemetry::{metrics::SDKAggSelector, prometheus_server::PromServer},
+    telemetry::{
+        metrics::{
+            core_metric, set_metric_settings, CoreOtelMeter, MetricSettings, SDKAggSelector,
+        },
+        prometheus_server::PromServer,
+    },
     CoreLog, METRIC_METER,
 };
 use itertools::Itertools;
@@ -12,18 +19,35 @@ use once_cell::sync::OnceCell;
 use opentelemetry::{
     global,
     metrics::Meter,
-    sdk::{metrics::PushController, trace::Config, Resource},
+    sdk::{
+        export::metrics::{CheckpointSet, ExportKindSelector},
+        metrics::{controllers, PullController, PushController},
+        trace::Config,
+        Resource,
+    },
     util::tokio_interval_stream,
     KeyValue,
 };
 use opentelemetry_otlp::WithExportConfig;
 use parking_lot::{const_mutex, Mutex};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
-use std::{collections::VecDeque, net::SocketAddr, time::Duration};
-use temporal_sdk_core_api::CoreTelemetry;
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use temporal_sdk_core_api::{
+    metrics::{CoreMeter, CoreMetric},
+    CoreTelemetry,
+};
+use tokio::task::JoinHandle;
 use tonic::metadata::MetadataMap;
-use tracing_subscriber::{filter::ParseError, layer::SubscriberExt, EnvFilter};
+use tracing_subscriber::{filter::ParseError, layer::SubscriberExt, reload, EnvFilter, Registry};
 use url::Url;
 
 const TELEM_SERVICE_NAME: &str = "temporal-core-sdk";
@@ -38,35 +62,55 @@ fn default_resource_kvs() -> &'static [KeyValue] {
 fn default_resource() -> Resource {
     Resource::new(default_resource_kvs().iter().cloned())
 }
+/// The default resource, with any user-provided attributes added. User attributes take precedence.
+fn resource_with_attributes(attributes: &HashMap<String, String>) -> Resource {
+    Resource::new(
+        default_resource_kvs().iter().cloned().chain(
+            attributes
+                .iter()
+                .map(|(k, v)| KeyValue::new(k.clone(), v.clone())),
+        ),
+    )
+}
 
 /// Options for exporting to an OpenTelemetry Collector
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct OtelCollectorOptions {
     /// The url of the OTel collector to export telemetry and metrics to. Lang SDK should also
     /// export to this same collector.
     pub url: Url,
     /// Optional set of HTTP headers to send to the Collector, e.g for authentication.
     pub headers: HashMap<String, String>,
+    /// How often metrics are pushed to the collector. Defaults to once per second if unset. Must be
+    /// greater than zero. Has no effect when used for traces.
+    pub metric_periodicity: Option<Duration>,
+    /// Attributes added to the resource describing this process, e.g. `deployment.environment` or
+    /// `service.instance.id`. May override the default `service.name`.
+    pub resource_attributes: HashMap<String, String>,
 }
 
 /// Control where traces are exported
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TraceExporter {
     /// Export traces to an OpenTelemetry Collector <https://opentelemetry.io/docs/collector/>.
     Otel(OtelCollectorOptions),
 }
 
 /// Control where metrics are exported
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum MetricsExporter {
     /// Export metrics to an OpenTelemetry Collector <https://opentelemetry.io/docs/collector/>.
     Otel(OtelCollectorOptions),
-    /// Expose metrics directly via an embedded http server bound to the provided address.
+    /// Expose metrics directly via an embedded http server bound to the provided address. Metrics
+    /// are served in the Prometheus text format at `/metrics`, and include process-wide metrics
+    /// on Linux.
     Prometheus(SocketAddr),
+    /// Keep metrics in memory for lang to export itself - collectable with [fetch_global_metrics].
+    Forward,
 }
 
 /// Control where logs go
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Logger {
     /// Log directly to console.
     Console,
@@ -75,7 +119,8 @@ pub enum Logger {
 }
 
 /// Telemetry configuration options. Construct with [TelemetryOptionsBuilder]
-#[derive(Debug, Clone, derive_builder::Builder)]
+#[derive(Debug, Clone, PartialEq, derive_builder::Builder)]
+#[builder(build_fn(validate = "Self::validate"))]
 #[non_exhaustive]
 pub struct TelemetryOptions {
     /// A string in the [EnvFilter] format which specifies what tracing data is included in
@@ -93,6 +138,40 @@ pub struct TelemetryOptions {
     /// Optional metrics exporter - set as None to disable.
     #[builder(setter(into, strip_option), default)]
     pub metrics: Option<MetricsExporter>,
+    /// Overrides the bucket boundaries of histogram metrics, keyed by metric name. Boundaries are
+    /// in the unit the metric is recorded in, which for latencies depends on
+    /// `use_seconds_for_durations`.
+    #[builder(default)]
+    pub histogram_bucket_overrides: HashMap<String, Vec<f64>>,
+    /// If set, latency metrics are recorded in (fractional) seconds rather than milliseconds, and
+    /// their default bucket boundaries are scaled to match.
+    #[builder(default)]
+    pub use_seconds_for_durations: bool,
+    /// Attributes added to every metric core emits, ex: region or deployment. Per-worker
+    /// attributes may be added with `WorkerConfig::metric_attributes`.
+    #[builder(default)]
+    pub global_metric_attributes: HashMap<String, String>,
+    /// Keys of attributes core should not attach to its metrics, to limit their cardinality. Core
+    /// uses `namespace`, `task_queue`, `workflow_type`, `activity_type`, `poller_type`,
+    /// `worker_type`, and `command_type`. The highest cardinality of those are usually
+    /// `workflow_type`, `activity_type`, and `task_queue`.
+    #[builder(default)]
+    pub excluded_metric_attributes: HashSet<String>,
+}
+
+impl TelemetryOptionsBuilder {
+    fn validate(&self) -> Result<(), String> {
+        if let Some(Some(MetricsExporter::Otel(OtelCollectorOptions {
+            metric_periodicity: Some(periodicity),
+            ..
+        }))) = &self.metrics
+        {
+            if periodicity.is_zero() {
+                return Err("OTel `metric_periodicity` must be greater than zero".to_owned());
+            }
+        }
+        Ok(())
+    }
 }
 
 impl TelemetryOptions {
@@ -115,10 +194,18 @@ impl Default for TelemetryOptions {
 /// Things that need to not be dropped while telemetry is ongoing
 #[derive(Default)]
 pub struct GlobalTelemDat {
-    metric_push_controller: Option<PushController>,
+    metric_push_controller: Mutex<Option<PushController>>,
+    metric_pull_controller: Option<Mutex<PullController>>,
     core_export_logger: Option<CoreExportLogger>,
     runtime: Option<tokio::runtime::Runtime>,
     prom_srv: Option<PromServer>,
+    prom_srv_task: Mutex<Option<JoinHandle<hyper::Result<()>>>>,
+    tracing_filter_handle: Option<reload::Handle<EnvFilter, Registry>>,
+    metrics_enabled: bool,
+    /// The options telemetry was initialized with
+    options: TelemetryOptions,
+    /// How many [crate::CoreRuntime]s are using this telemetry
+    live_runtimes: AtomicUsize,
 }
 
 impl GlobalTelemDat {
@@ -127,12 +214,61 @@ impl GlobalTelemDat {
             let _ = log::set_logger(loggr);
         }
         if let Some(srv) = &self.prom_srv {
-            self.runtime
+            let task = self
+                .runtime
                 .as_ref()
                 .expect("Telemetry runtime is initted")
                 .spawn(srv.run());
+            *self.prom_srv_task.lock() = Some(task);
         }
     }
+
+    /// Replace the filter deciding which tracing data is output to the console and exported, using
+    /// the same format as [TelemetryOptions::tracing_filter]. Takes effect immediately, so it can
+    /// be used to turn on debug logging in a running process.
+    ///
+    /// Returns an error if the filter is invalid, or if telemetry was initialized without console
+    /// logging or trace export, in which case core installed no tracing subscriber to filter.
+    pub fn set_tracing_filter(&self, filter: &str) -> Result<(), anyhow::Error> {
+        let handle = self.tracing_filter_handle.as_ref().ok_or_else(|| {
+            anyhow::anyhow!("Telemetry was not initialized with console logging or trace export")
+        })?;
+        handle.reload(EnvFilter::try_new(filter)?)?;
+        Ok(())
+    }
+
+    /// The options telemetry was initialized with
+    pub(crate) fn options(&self) -> &TelemetryOptions {
+        &self.options
+    }
+
+    /// Note that another runtime is using this telemetry
+    pub(crate) fn acquire(&self) {
+        self.live_runtimes.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Note that a runtime is no longer using this telemetry. Returns true if it was the last one.
+    pub(crate) fn release(&self) -> bool {
+        self.live_runtimes.fetch_sub(1, Ordering::AcqRel) == 1
+    }
+
+    /// Flush any buffered metrics and traces, and stop serving / exporting them. Telemetry cannot
+    /// be re-initialized afterward, so this should only be called once all workers are done.
+    pub(crate) fn shutdown(&self) {
+        let push_controller = self.metric_push_controller.lock().take();
+        if let Some(task) = self.prom_srv_task.lock().take() {
+            task.abort();
+        }
+        // Dropping the push controller triggers a final export, and shutting down the tracer
+        // provider blocks until the batch exporter is flushed. Neither may happen on a thread
+        // which is driving a tokio runtime, so do them on a fresh one.
+        std::thread::spawn(move || {
+            drop(push_controller);
+            global::shutdown_tracer_provider();
+        })
+        .join()
+        .expect("Telemetry shutdown panicked");
+    }
 }
 
 impl CoreTelemetry for GlobalTelemDat {
@@ -146,6 +282,13 @@ impl CoreTelemetry for GlobalTelemDat {
         }
         None
     }
+
+    fn get_core_meter(&self) -> Option<Arc<dyn CoreMeter>> {
+        if self.metrics_enabled {
+            return Some(Arc::new(CoreOtelMeter::new()));
+        }
+        None
+    }
 }
 
 /// Initialize tracing subscribers/output and logging export. If this function is called more than
@@ -174,12 +317,22 @@ pub fn telemetry_init(opts: &TelemetryOptions) -> Result<&'static GlobalTelemDat
             // Ensure closure captures the mutex guard
             let _ = &*guard;
 
+            set_metric_settings(MetricSettings {
+                histogram_bucket_overrides: opts.histogram_bucket_overrides.clone(),
+                use_seconds_for_durations: opts.use_seconds_for_durations,
+                global_attributes: opts.global_metric_attributes.clone(),
+                excluded_attributes: opts.excluded_metric_attributes.clone(),
+            });
+
             let runtime = tokio::runtime::Builder::new_multi_thread()
                 .thread_name("telemetry")
                 .worker_threads(2)
                 .enable_all()
                 .build()?;
-            let mut globaldat = GlobalTelemDat::default();
+            let mut globaldat = GlobalTelemDat {
+                options: opts.clone(),
+                ..Default::default()
+            };
 
             if let Some(ref logger) = opts.logging {
                 match logger {
@@ -189,13 +342,13 @@ pub fn telemetry_init(opts: &TelemetryOptions) -> Result<&'static GlobalTelemDat
                             let pretty_fmt = tracing_subscriber::fmt::format()
                                 .pretty()
                                 .with_source_location(false);
-                            let reg = tracing_subscriber::registry()
-                                .with((&opts).try_get_env_filter()?)
-                                .with(
-                                    tracing_subscriber::fmt::layer()
-                                        .with_target(false)
-                                        .event_format(pretty_fmt),
-                                );
+                            let (filter, handle) = reload::Layer::new(opts.try_get_env_filter()?);
+                            globaldat.tracing_filter_handle = Some(handle);
+                            let reg = tracing_subscriber::registry().with(filter).with(
+                                tracing_subscriber::fmt::layer()
+                                    .with_target(false)
+                                    .event_format(pretty_fmt),
+                            );
                             tracing::subscriber::set_global_default(reg)?;
                         }
                     }
@@ -207,18 +360,42 @@ pub fn telemetry_init(opts: &TelemetryOptions) -> Result<&'static GlobalTelemDat
             };
 
             if let Some(ref metrics) = opts.metrics {
+                globaldat.metrics_enabled = true;
                 match metrics {
                     MetricsExporter::Prometheus(addr) => {
                         let srv = PromServer::new(*addr)?;
                         globaldat.prom_srv = Some(srv);
                     }
-                    MetricsExporter::Otel(OtelCollectorOptions { url, headers }) => {
+                    MetricsExporter::Forward => {
+                        let controller = controllers::pull(
+                            Box::new(SDKAggSelector),
+                            Box::new(ExportKindSelector::Cumulative),
+                        )
+                        .with_resource(default_resource())
+                        .with_cache_period(Duration::ZERO)
+                        .with_memory(true)
+                        .build();
+                        global::set_meter_provider(controller.provider());
+                        globaldat.metric_pull_controller = Some(Mutex::new(controller));
+                    }
+                    MetricsExporter::Otel(OtelCollectorOptions {
+                        url,
+                        headers,
+                        metric_periodicity,
+                        resource_attributes,
+                    }) => {
                         runtime.block_on(async {
                             let metrics = opentelemetry_otlp::new_pipeline()
                                 .metrics(|f| runtime.spawn(f), tokio_interval_stream)
                                 .with_aggregator_selector(SDKAggSelector)
-                                .with_period(Duration::from_secs(1))
-                                .with_resource(default_resource_kvs().iter().cloned())
+                                .with_period(
+                                    metric_periodicity.unwrap_or_else(|| Duration::from_secs(1)),
+                                )
+                                .with_resource(
+                                    resource_with_attributes(resource_attributes)
+                                        .iter()
+                                        .map(|(k, v)| KeyValue::new(k.clone(), v.clone())),
+                                )
                                 .with_exporter(
                                     // No joke exporter builder literally not cloneable for some insane
                                     // reason
@@ -231,7 +408,7 @@ pub fn telemetry_init(opts: &TelemetryOptions) -> Result<&'static GlobalTelemDat
                                 )
                                 .build()?;
                             global::set_meter_provider(metrics.provider());
-                            globaldat.metric_push_controller = Some(metrics);
+                            *globaldat.metric_push_controller.lock() = Some(metrics);
                             Result::<(), anyhow::Error>::Ok(())
                         })?;
                     }
@@ -240,9 +417,15 @@ pub fn telemetry_init(opts: &TelemetryOptions) -> Result<&'static GlobalTelemDat
 
             if let Some(ref tracing) = opts.tracing {
                 match tracing {
-                    TraceExporter::Otel(OtelCollectorOptions { url, headers }) => {
+                    TraceExporter::Otel(OtelCollectorOptions {
+                        url,
+                        headers,
+                        resource_attributes,
+                        ..
+                    }) => {
                         runtime.block_on(async {
-                            let tracer_cfg = Config::default().with_resource(default_resource());
+                            let tracer_cfg = Config::default()
+                                .with_resource(resource_with_attributes(resource_attributes));
                             let tracer = opentelemetry_otlp::new_pipeline()
                                 .tracing()
                                 .with_exporter(
@@ -258,14 +441,17 @@ pub fn telemetry_init(opts: &TelemetryOptions) -> Result<&'static GlobalTelemDat
 
                             let opentelemetry = tracing_opentelemetry::layer().with_tracer(tracer);
 
+                            let (filter, handle) = reload::Layer::new(opts.try_get_env_filter()?);
+                            globaldat.tracing_filter_handle = Some(handle);
+
                             // TODO: remove all of this duplicate code
                             if let Some(Logger::Console) = opts.logging {
                                 let pretty_fmt = tracing_subscriber::fmt::format()
                                     .pretty()
                                     .with_source_location(false);
                                 let reg = tracing_subscriber::registry()
+                                    .with(filter)
                                     .with(opentelemetry)
-                                    .with(opts.try_get_env_filter()?)
                                     .with(
                                         tracing_subscriber::fmt::layer()
                                             .with_target(false)
@@ -275,8 +461,8 @@ pub fn telemetry_init(opts: &TelemetryOptions) -> Result<&'static GlobalTelemDat
                                 tracing::subscriber::set_global_default(reg)?;
                             } else {
                                 let reg = tracing_subscriber::registry()
-                                    .with(opentelemetry)
-                                    .with(opts.try_get_env_filter()?);
+                                    .with(filter)
+                                    .with(opentelemetry);
                                 // Can't use try_init here as it will blow away our custom logger if we do
                                 tracing::subscriber::set_global_default(reg)?;
                             }
@@ -310,6 +496,31 @@ pub fn fetch_global_buffered_logs() -> Vec<CoreLog> {
     }
 }
 
+/// Returns the current value of every metric core and lang have recorded, for lang to export, if
+/// telemetry was initialized with [MetricsExporter::Forward]. Otherwise, always returns an empty
+/// vec. Values are cumulative since the process started.
+pub fn fetch_global_metrics() -> Vec<CoreMetric> {
+    let controller = match GLOBAL_TELEM_DAT
+        .get()
+        .and_then(|gd| gd.metric_pull_controller.as_ref())
+    {
+        Some(c) => c,
+        None => return vec![],
+    };
+    let mut controller = controller.lock();
+    let mut metrics = vec![];
+    let res = controller.collect().and_then(|_| {
+        controller.try_for_each(&ExportKindSelector::Cumulative, &mut |record| {
+            metrics.extend(core_metric(record));
+            Ok(())
+        })
+    });
+    if let Err(e) = res {
+        warn!("Failed to collect metrics for forwarding: {}", e);
+    }
+    metrics
+}
+
 #[allow(dead_code)] // Not always used, called to enable for debugging when needed
 #[cfg(test)]
 pub(crate) fn test_telem_console() {
@@ -318,6 +529,10 @@ pub(crate) fn test_telem_console() {
         logging: Some(Logger::Console),
         tracing: None,
         metrics: None,
+        histogram_bucket_overrides: Default::default(),
+        use_seconds_for_durations: false,
+        global_metric_attributes: Default::default(),
+        excluded_metric_attributes: Default::default(),
     })
     .unwrap();
 }
@@ -331,8 +546,14 @@ pub(crate) fn test_telem_collector() {
         tracing: Some(TraceExporter::Otel(OtelCollectorOptions {
             url: "grpc://localhost:4317".parse().unwrap(),
             headers: Default::default(),
+            metric_periodicity: None,
+            resource_attributes: Default::default(),
         })),
         metrics: None,
+        histogram_bucket_overrides: Default::default(),
+        use_seconds_for_durations: false,
+        global_metric_attributes: Default::default(),
+        excluded_metric_attributes: Default::default(),
     })
     .unwrap();
 }
@@ -362,3 +583,68 @@ where
         format!("[{}]", self.iter().format(","))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::{Key, Value};
+
+    #[test]
+    fn resource_attributes_extend_and_override_defaults() {
+        let attrs = HashMap::from([
+            ("service.name".to_string(), "my-worker".to_string()),
+            ("deployment.environment".to_string(), "prod".to_string()),
+        ]);
+        let resource = resource_with_attributes(&attrs);
+        assert_eq!(resource.len(), 2);
+        assert_eq!(
+            resource.get(Key::from_static_str("service.name")),
+            Some(Value::from("my-worker"))
+        );
+        assert_eq!(
+            resource.get(Key::from_static_str("deployment.environment")),
+            Some(Value::from("prod"))
+        );
+    }
+
+    #[test]
+    fn zero_metric_periodicity_is_rejected() {
+        let otel = |metric_periodicity| {
+            MetricsExporter::Otel(OtelCollectorOptions {
+                url: "grpc://localhost:4317".parse().unwrap(),
+                headers: Default::default(),
+                metric_periodicity,
+                resource_attributes: Default::default(),
+            })
+        };
+        assert!(TelemetryOptionsBuilder::default()
+            .metrics(otel(Some(Duration::from_secs(5))))
+            .build()
+            .is_ok());
+        assert!(TelemetryOptionsBuilder::default()
+            .metrics(otel(Some(Duration::ZERO)))
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn tracing_filter_can_be_replaced() {
+        assert!(GlobalTelemDat::default()
+            .set_tracing_filter("temporal_sdk_core=debug")
+            .is_err());
+
+        let (_filter, handle) = reload::Layer::<_, Registry>::new(EnvFilter::new(DEFAULT_FILTER));
+        let gtd = GlobalTelemDat {
+            tracing_filter_handle: Some(handle.clone()),
+            ..Default::default()
+        };
+        gtd.set_tracing_filter("temporal_sdk_core=debug").unwrap();
+        assert_eq!(
+            handle.with_current(|f| f.to_string()).unwrap(),
+            "temporal_sdk_core=debug"
+        );
+        assert!(gtd
+            .set_tracing_filter("temporal_sdk_core=notalevel")
+            .is_err());
+    }
+}
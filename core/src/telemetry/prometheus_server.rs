@@ -9,7 +9,7 @@ use hyper::{
 };
 use opentelemetry::metrics::MetricsError;
 use opentelemetry_prometheus::{ExporterBuilder, PrometheusExporter};
-use prometheus::{Encoder, TextEncoder};
+use prometheus::{Encoder, Registry, TextEncoder};
 use std::{convert::Infallible, net::SocketAddr, sync::Arc};
 
 /// Exposes prometheus metrics for scraping
@@ -27,6 +27,7 @@ impl PromServer {
             .with_port(addr.port())
             .with_resource(default_resource())
             .try_init()?;
+        register_process_metrics(exporter.registry())?;
         Ok(Self {
             exporter: Arc::new(exporter),
             addr,
@@ -46,6 +47,20 @@ impl PromServer {
     }
 }
 
+/// Adds process-wide metrics (CPU, memory, open file descriptors, etc.) to the registry, alongside
+/// the per-worker metrics core records. Only supported on Linux.
+fn register_process_metrics(registry: &Registry) -> Result<(), MetricsError> {
+    #[cfg(target_os = "linux")]
+    registry
+        .register(Box::new(
+            prometheus::process_collector::ProcessCollector::for_self(),
+        ))
+        .map_err(|e| MetricsError::Other(e.to_string()))?;
+    #[cfg(not(target_os = "linux"))]
+    let _ = registry;
+    Ok(())
+}
+
 /// Serves prometheus metrics in the expected format for scraping
 async fn metrics_req(
     req: Request<Body>,
@@ -71,3 +86,21 @@ async fn metrics_req(
     };
     Ok(response)
 }
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn process_metrics_are_registered() {
+        let registry = Registry::new();
+        register_process_metrics(&registry).unwrap();
+        let names: Vec<_> = registry
+            .gather()
+            .into_iter()
+            .map(|mf| mf.get_name().to_string())
+            .collect();
+        assert!(names.contains(&"process_cpu_seconds_total".to_string()));
+        assert!(names.contains(&"process_resident_memory_bytes".to_string()));
+    }
+}
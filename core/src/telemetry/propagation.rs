@@ -0,0 +1,123 @@
+//! Propagates OpenTelemetry trace context through workflow and activity headers, so that spans
+//! created by core (and by lang, or clients starting workflows) stitch together into one trace.
+
+use opentelemetry::{
+    propagation::TextMapPropagator, sdk::propagation::TraceContextPropagator,
+    trace::TraceContextExt, Context,
+};
+use std::collections::HashMap;
+use temporal_sdk_core_protos::coresdk::{
+    common::Payload, workflow_commands::WorkflowCommand, AsJsonPayloadExt, FromJsonPayloadExt,
+};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// The header key trace context is stored under. Matches what other Temporal SDKs use, so context
+/// can be shared with them.
+pub(crate) const TRACING_HEADER_KEY: &str = "_tracer-data";
+
+/// Write the span's trace context into the headers of any commands which start or signal other
+/// executions, so that their spans join the same trace
+pub(crate) fn inject_command_headers(span: &Span, commands: &mut [WorkflowCommand]) {
+    let cx = span.context();
+    for headers in commands
+        .iter_mut()
+        .filter_map(|c| c.variant.as_mut()?.headers_mut())
+    {
+        inject_context(&cx, headers);
+    }
+}
+
+/// If the headers carry trace context, make it the parent of the span
+pub(crate) fn set_parent_from_headers(span: &Span, headers: &HashMap<String, Payload>) {
+    if let Some(cx) = extract_context(headers) {
+        span.set_parent(cx);
+    }
+}
+
+/// Write the context into the headers, unless they already carry trace context (ex: lang
+/// propagated its own). Does nothing if the context has no valid span, ex: it isn't being exported.
+fn inject_context(cx: &Context, headers: &mut HashMap<String, Payload>) {
+    if headers.contains_key(TRACING_HEADER_KEY) || !cx.span().span_context().is_valid() {
+        return;
+    }
+    let mut carrier = HashMap::<String, String>::new();
+    TraceContextPropagator::new().inject_context(cx, &mut carrier);
+    match carrier.as_json_payload() {
+        Ok(payload) => {
+            headers.insert(TRACING_HEADER_KEY.to_string(), payload);
+        }
+        Err(e) => warn!(error=?e, "Failed to serialize trace context"),
+    }
+}
+
+/// Extract trace context from the headers, if they carry any valid context
+pub(crate) fn extract_context(headers: &HashMap<String, Payload>) -> Option<Context> {
+    let carrier = HashMap::<String, String>::from_json_payload(headers.get(TRACING_HEADER_KEY)?)
+        .map_err(|e| warn!(error=?e, "Trace context header was malformed"))
+        .ok()?;
+    let cx = TraceContextPropagator::new().extract(&carrier);
+    cx.span().span_context().is_valid().then_some(cx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{SpanContext, SpanId, TraceFlags, TraceId, TraceState};
+
+    #[test]
+    fn context_round_trips_through_headers() {
+        let span_cx = SpanContext::new(
+            TraceId::from_bytes(0xabcd_u128.to_be_bytes()),
+            SpanId::from_bytes(0x1234_u64.to_be_bytes()),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        let cx = Context::new().with_remote_span_context(span_cx.clone());
+        let mut headers = HashMap::new();
+        inject_context(&cx, &mut headers);
+        assert!(headers.contains_key(TRACING_HEADER_KEY));
+
+        let extracted = extract_context(&headers).unwrap();
+        assert_eq!(
+            extracted.span().span_context().trace_id(),
+            span_cx.trace_id()
+        );
+        assert_eq!(extracted.span().span_context().span_id(), span_cx.span_id());
+    }
+
+    #[test]
+    fn existing_context_is_not_overwritten() {
+        let mut headers = HashMap::new();
+        let existing: HashMap<String, String> = HashMap::from([(
+            "traceparent".to_string(),
+            "00-0000000000000000000000000000beef-000000000000cafe-01".to_string(),
+        )]);
+        headers.insert(
+            TRACING_HEADER_KEY.to_string(),
+            existing.as_json_payload().unwrap(),
+        );
+        let cx = Context::new().with_remote_span_context(SpanContext::new(
+            TraceId::from_bytes(0xabcd_u128.to_be_bytes()),
+            SpanId::from_bytes(0x1234_u64.to_be_bytes()),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        ));
+        inject_context(&cx, &mut headers);
+        let extracted = extract_context(&headers).unwrap();
+        assert_eq!(
+            extracted.span().span_context().trace_id(),
+            TraceId::from_bytes(0xbeef_u128.to_be_bytes())
+        );
+    }
+
+    #[test]
+    fn no_context_without_header() {
+        assert!(extract_context(&HashMap::new()).is_none());
+        let mut headers = HashMap::new();
+        inject_context(&Context::new(), &mut headers);
+        assert!(headers.is_empty());
+    }
+}
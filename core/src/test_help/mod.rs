@@ -18,11 +18,11 @@ use std::{
     sync::Arc,
     time::Duration,
 };
-use temporal_sdk_core_api::Worker as WorkerTrait;
+use temporal_sdk_core_api::{errors::PollWfError, Worker as WorkerTrait};
 use temporal_sdk_core_protos::{
     coresdk::{
-        workflow_activation::WorkflowActivation,
-        workflow_commands::workflow_command,
+        workflow_activation::{workflow_activation_job::Variant, WorkflowActivation},
+        workflow_commands::{workflow_command, CompleteWorkflowExecution, StartTimer},
         workflow_completion::{self, workflow_activation_completion, WorkflowActivationCompletion},
     },
     temporal::api::{
@@ -96,10 +96,13 @@ pub(crate) fn build_fake_worker(
 
 pub(crate) fn mock_worker(mocks: MocksHolder) -> Worker {
     let sticky_q = sticky_q_name_for_worker("unit-test", &mocks.mock_worker.config);
+    let client_bag = mocks
+        .client_bag
+        .with_payload_transforms(&mocks.mock_worker.config);
     Worker::new_with_pollers(
         mocks.mock_worker.config,
         sticky_q,
-        Arc::new(mocks.client_bag),
+        Arc::new(client_bag),
         mocks.mock_worker.wf_poller,
         mocks.mock_worker.act_poller,
         Default::default(),
@@ -272,14 +275,16 @@ pub(crate) fn single_hist_mock_sg(
     build_mock_pollers(mh)
 }
 
+type WftFailMatcher =
+    Box<dyn Fn(&TaskToken, &WorkflowTaskFailedCause, &Option<Failure>) -> bool + Send>;
+
 pub(crate) struct MockPollCfg {
     pub hists: Vec<FakeWfResponses>,
     pub enforce_correct_number_of_polls: bool,
     pub num_expected_fails: Option<usize>,
     pub mock_client: MockWorkerClient,
     /// All calls to fail WFTs must match this predicate
-    pub expect_fail_wft_matcher:
-        Box<dyn Fn(&TaskToken, &WorkflowTaskFailedCause, &Option<Failure>) -> bool + Send>,
+    pub expect_fail_wft_matcher: WftFailMatcher,
     /// If being used with the Rust SDK, this is set true. It ensures pollers will not error out
     /// early with no work, since we cannot know the exact number of times polling will happen.
     /// Instead, they will just block forever.
@@ -382,7 +387,7 @@ pub(crate) fn build_mock_pollers(mut cfg: MockPollCfg) -> MocksHolder {
             for (_, tasks) in task_q_resps.iter_mut() {
                 // Must extract run id from a workflow task associated with this workflow
                 // TODO: Case where run id changes for same workflow id is not handled here
-                if let Some(t) = tasks.get(0) {
+                if let Some(t) = tasks.front() {
                     let rid = t.workflow_execution.as_ref().unwrap().run_id.clone();
                     if !outstanding.read().contains_left(&rid) {
                         let t = tasks.pop_front().unwrap();
@@ -601,6 +606,33 @@ pub(crate) fn gen_assert_and_fail(asserter: &dyn Fn(&WorkflowActivation)) -> Ass
     )
 }
 
+/// Stands in for lang's workflow code: a workflow which waits on one timer, then completes
+pub(crate) async fn timer_wf_driver(worker: Arc<Worker>) -> Result<(), anyhow::Error> {
+    loop {
+        let act = match worker.poll_workflow_activation().await {
+            Err(PollWfError::ShutDown) => return Ok(()),
+            other => other?,
+        };
+        let cmd = match act.jobs.first().and_then(|j| j.variant.as_ref()) {
+            Some(Variant::StartWorkflow(_)) => StartTimer {
+                seq: 1,
+                start_to_fire_timeout: Some(Duration::from_secs(1).into()),
+            }
+            .into(),
+            Some(Variant::FireTimer(_)) => CompleteWorkflowExecution { result: None }.into(),
+            _ => {
+                worker
+                    .complete_workflow_activation(WorkflowActivationCompletion::empty(act.run_id))
+                    .await?;
+                continue;
+            }
+        };
+        worker
+            .complete_workflow_activation(WorkflowActivationCompletion::from_cmd(act.run_id, cmd))
+            .await?;
+    }
+}
+
 /// Generate asserts for [poll_and_reply] by passing patterns to match against the job list
 #[macro_export]
 macro_rules! job_assert {
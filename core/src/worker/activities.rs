@@ -8,40 +8,80 @@ pub(crate) use local_activities::{
 };
 
 use crate::{
-    abstractions::MeteredSemaphore,
-    pollers::BoxedActPoller,
-    telemetry::metrics::{activity_type, activity_worker_type, workflow_type, MetricsContext},
+    abstractions::{AcquirePriority, MeteredPermit, MeteredSemaphore},
+    pollers::{self, new_activity_task_buffer, BoxedActPoller, RestartablePoller},
+    telemetry::{
+        events::CoreEventEmitter,
+        metrics::{activity_type, activity_worker_type, workflow_type, MetricsContext},
+        propagation::set_parent_from_headers,
+    },
     worker::{
         activities::activity_heartbeat_manager::ActivityHeartbeatError,
         client::{WorkerClient, WorkerClientBag},
+        WorkerConfig,
     },
     CompleteActivityError, PollActivityError, TaskToken,
 };
 use activity_heartbeat_manager::ActivityHeartbeatManager;
-use dashmap::DashMap;
+use lru::LruCache;
+use parking_lot::Mutex;
+use prost::Message;
 use std::{
+    collections::{HashMap, VecDeque},
     convert::TryInto,
-    sync::Arc,
-    time::{Duration, Instant},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use temporal_sdk_core_api::{
+    errors::ActivitySlotError,
+    worker::{ActivityInterceptor, SessionActivityQueueConfig},
 };
 use temporal_sdk_core_protos::{
     coresdk::{
         activity_result::{self as ar, activity_execution_result as aer},
-        activity_task::{ActivityCancelReason, ActivityTask},
+        activity_task::{activity_task, ActivityCancelReason, ActivityTask},
         ActivityHeartbeat,
     },
     temporal::api::{
+        enums::v1::TimeoutType,
         failure::v1::{failure::FailureInfo, CanceledFailureInfo, Failure},
         workflowservice::v1::PollActivityTaskQueueResponse,
     },
 };
 use tokio::sync::Notify;
+use tracing::Span;
 
-#[derive(Debug, derive_more::Constructor)]
+#[derive(Debug)]
 struct PendingActivityCancel {
     task_token: TaskToken,
     reason: ActivityCancelReason,
+    /// True if this cancel is for an activity we are not tracking as outstanding, and should be
+    /// issued to lang regardless.
+    untracked: bool,
 }
+impl PendingActivityCancel {
+    fn new(task_token: TaskToken, reason: ActivityCancelReason) -> Self {
+        Self {
+            task_token,
+            reason,
+            untracked: false,
+        }
+    }
+    fn untracked(task_token: TaskToken) -> Self {
+        Self {
+            task_token,
+            reason: ActivityCancelReason::NotFound,
+            untracked: true,
+        }
+    }
+}
+
+/// How many task tokens of unknown activities that lang has heartbeated we remember having
+/// already issued cancels for
+const UNKNOWN_HEARTBEAT_CANCELS_REMEMBERED: usize = 1000;
 
 /// Contains minimal set of details that core needs to store while an activity is running.
 #[derive(Debug)]
@@ -49,12 +89,16 @@ struct InFlightActInfo {
     pub activity_type: String,
     pub workflow_type: String,
     start_time: Instant,
+    /// Covers the activity's execution, from dispatch to lang until completion
+    span: Span,
 }
 
 /// Augments [InFlightActInfo] with details specific to remote activities
 #[derive(Debug)]
 struct RemoteInFlightActInfo {
     pub base: InFlightActInfo,
+    /// The run of the workflow which scheduled this activity
+    pub workflow_run_id: String,
     /// Used to calculate aggregation delay between activity heartbeats.
     pub heartbeat_timeout: Option<prost_types::Duration>,
     /// Set to true if we have already issued a cancellation activation to lang for this activity
@@ -63,22 +107,33 @@ struct RemoteInFlightActInfo {
     /// we have learned from heartbeating and issued a cancel task, in which case we may simply
     /// discard the reply.
     pub known_not_found: bool,
+    /// Set to true if lang gave back this activity's slot before completing it
+    pub slot_released: bool,
+    /// Set to true if this activity was polled from the session queue, and so uses its slots
+    pub from_session: bool,
 }
 impl RemoteInFlightActInfo {
     fn new(
         activity_type: String,
         workflow_type: String,
+        workflow_run_id: String,
         heartbeat_timeout: Option<prost_types::Duration>,
+        span: Span,
+        from_session: bool,
     ) -> Self {
         Self {
             base: InFlightActInfo {
                 activity_type,
                 workflow_type,
                 start_time: Instant::now(),
+                span,
             },
+            workflow_run_id,
             heartbeat_timeout,
             issued_cancel_to_lang: false,
             known_not_found: false,
+            slot_released: false,
+            from_session,
         }
     }
 }
@@ -86,60 +141,229 @@ impl RemoteInFlightActInfo {
 pub(crate) struct WorkerActivityTasks {
     /// Centralizes management of heartbeat issuing / throttling
     heartbeat_manager: ActivityHeartbeatManager,
-    /// Activities that have been issued to lang but not yet completed
-    outstanding_activity_tasks: DashMap<TaskToken, RemoteInFlightActInfo>,
+    /// Activities that have been issued to lang but not yet completed. The lock must never be held
+    /// across an await point.
+    outstanding_activity_tasks: Mutex<HashMap<TaskToken, RemoteInFlightActInfo>>,
     /// Buffers activity task polling in the event we need to return a cancellation while a poll is
     /// ongoing.
-    poller: BoxedActPoller,
+    poller: RestartablePoller<PollActivityTaskQueueResponse>,
+    /// Used to fail activity tasks which are dropped before ever being dispatched to lang
+    client: Arc<WorkerClientBag>,
     /// Ensures we stay at or below this worker's maximum concurrent activity limit
     activities_semaphore: MeteredSemaphore,
+    /// Number of slots lang has reserved without an activity task
+    reserved_slots: AtomicUsize,
+    /// Maximum number of outstanding activities of each type which has a limit
+    max_outstanding_per_type: HashMap<String, usize>,
+    /// How many tasks of each type which has a limit are outstanding or held
+    per_type_counts: Mutex<HashMap<String, TypeCounts>>,
+    /// Activity tasks which were polled while their type was at its limit, and whether they came
+    /// from the session queue. They don't hold an activity slot, and are dispatched once an
+    /// activity of their type completes and a slot is free.
+    held_activity_tasks: Mutex<VecDeque<(PollActivityTaskQueueResponse, bool)>>,
+    /// Polling stops while this many tasks are held
+    max_held_tasks: usize,
+    /// Polls the worker's session activity queue, if it has one
+    session: Option<SessionActivities>,
     /// Wakes every time an activity is removed from the outstanding map
     complete_notify: Notify,
+    /// Activities lang has heartbeated which we weren't tracking and have already issued a cancel
+    /// for, so that repeated heartbeats don't produce repeated cancels.
+    cancelled_unknown_activities: Mutex<LruCache<TaskToken, ()>>,
+    /// When a poll for activity tasks last succeeded (including timeouts), if ever
+    last_successful_poll: Mutex<Option<SystemTime>>,
 
     metrics: MetricsContext,
 
     max_heartbeat_throttle_interval: Duration,
     default_heartbeat_throttle_interval: Duration,
+    heartbeat_throttle_interval_overrides: HashMap<String, Duration>,
+    /// Activity tasks whose current attempt was scheduled longer ago than this are failed rather
+    /// than dispatched
+    max_schedule_to_start: Option<Duration>,
+    interceptor: Option<Arc<dyn ActivityInterceptor>>,
+}
+
+/// Outstanding and held tasks of an activity type with a limit
+#[derive(Default)]
+struct TypeCounts {
+    outstanding: usize,
+    held: usize,
+}
+
+/// Polls a worker's session activity queue, which has its own slots
+struct SessionActivities {
+    poller: RestartablePoller<PollActivityTaskQueueResponse>,
+    semaphore: MeteredSemaphore,
+    config: SessionActivityQueueConfig,
+}
+
+impl SessionActivities {
+    fn make_poller(
+        client: &Arc<WorkerClientBag>,
+        config: &SessionActivityQueueConfig,
+    ) -> BoxedActPoller {
+        Box::new(new_activity_task_buffer(
+            client.clone(),
+            config.task_queue.clone(),
+            1,
+            config.max_concurrent_polls,
+            config.max_concurrent_polls * 2,
+            Default::default(),
+        ))
+    }
 }
 
 impl WorkerActivityTasks {
     pub(crate) fn new(
-        max_activity_tasks: usize,
+        config: &WorkerConfig,
         poller: BoxedActPoller,
         client: Arc<WorkerClientBag>,
         metrics: MetricsContext,
-        max_heartbeat_throttle_interval: Duration,
-        default_heartbeat_throttle_interval: Duration,
+        events: CoreEventEmitter,
     ) -> Self {
+        let session = config
+            .session_activity_queue
+            .as_ref()
+            .map(|cfg| SessionActivities {
+                poller: RestartablePoller::new(SessionActivities::make_poller(&client, cfg)),
+                semaphore: MeteredSemaphore::new(
+                    cfg.max_outstanding_activities,
+                    metrics.with_new_attrs([activity_worker_type()]),
+                    |_, _| {},
+                ),
+                config: cfg.clone(),
+            });
         Self {
-            heartbeat_manager: ActivityHeartbeatManager::new(client),
+            heartbeat_manager: ActivityHeartbeatManager::new(
+                client.clone(),
+                config.heartbeat_batch_flush_interval,
+                events,
+            ),
             outstanding_activity_tasks: Default::default(),
-            poller,
+            poller: RestartablePoller::new(poller),
+            client,
             activities_semaphore: MeteredSemaphore::new(
-                max_activity_tasks,
+                config.max_outstanding_activities,
                 metrics.with_new_attrs([activity_worker_type()]),
                 MetricsContext::available_task_slots,
             ),
+            reserved_slots: AtomicUsize::new(0),
+            max_outstanding_per_type: config.max_outstanding_activities_per_type.clone(),
+            per_type_counts: Default::default(),
+            held_activity_tasks: Default::default(),
+            max_held_tasks: config.max_outstanding_activities,
+            session,
             complete_notify: Notify::new(),
+            cancelled_unknown_activities: Mutex::new(LruCache::new(
+                UNKNOWN_HEARTBEAT_CANCELS_REMEMBERED,
+            )),
+            last_successful_poll: Mutex::new(None),
             metrics,
-            max_heartbeat_throttle_interval,
-            default_heartbeat_throttle_interval,
+            max_heartbeat_throttle_interval: config.max_heartbeat_throttle_interval,
+            default_heartbeat_throttle_interval: config.default_heartbeat_throttle_interval,
+            heartbeat_throttle_interval_overrides: config
+                .heartbeat_throttle_interval_overrides
+                .clone(),
+            max_schedule_to_start: config.max_activity_schedule_to_start,
+            interceptor: config.activity_interceptor.clone(),
         }
     }
 
     pub(crate) fn notify_shutdown(&self) {
         self.poller.notify_shutdown();
+        if let Some(session) = self.session.as_ref() {
+            session.poller.notify_shutdown();
+        }
+    }
+
+    /// Stop polling until [WorkerActivityTasks::resume_polling] provides a new poller. Polls made
+    /// in the meantime wait for polling to resume.
+    pub(crate) fn pause_polling(&self) {
+        self.poller.pause();
+        if let Some(session) = self.session.as_ref() {
+            session.poller.pause();
+        }
+    }
+
+    /// Resume polling with the provided poller. The session queue poller, if any, is recreated.
+    pub(crate) fn resume_polling(&self, poller: BoxedActPoller) {
+        self.poller.resume_with(poller);
+        if let Some(session) = self.session.as_ref() {
+            session.poller.resume_with(SessionActivities::make_poller(
+                &self.client,
+                &session.config,
+            ));
+        }
+    }
+
+    /// Change the maximum number of outstanding activity tasks
+    pub(crate) fn set_max_outstanding(&self, max: usize) {
+        info!(max, "Setting maximum outstanding activities");
+        self.activities_semaphore.resize(max);
+    }
+
+    /// Change the maximum number of concurrent polls of the worker's task queue
+    pub(crate) fn set_max_pollers(&self, max: usize) {
+        info!(max, "Setting maximum concurrent activity polls");
+        self.poller.set_max_pollers(max);
+    }
+
+    /// Take a slot without an activity task. See [crate::Worker::reserve_activity_slot].
+    pub(crate) async fn reserve_slot(&self) {
+        // Lang asked for this slot explicitly, so it shouldn't wait behind polling for new work
+        self.activities_semaphore
+            .acquire_with_priority(AcquirePriority::High)
+            .await
+            .expect("outstanding activity semaphore not closed")
+            .forget();
+        self.reserved_slots.fetch_add(1, Ordering::AcqRel);
     }
 
-    /// Wait for all outstanding activity tasks to finish
+    /// Give back a slot taken with [WorkerActivityTasks::reserve_slot]
+    pub(crate) fn release_reserved_slot(&self) -> Result<(), ActivitySlotError> {
+        self.reserved_slots
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |r| r.checked_sub(1))
+            .map_err(|_| ActivitySlotError::NoneReserved)?;
+        self.activities_semaphore.add_permit();
+        Ok(())
+    }
+
+    /// Give back the slot of an outstanding activity before it is completed
+    pub(crate) fn release_task_slot(&self, task_token: TaskToken) -> Result<(), ActivitySlotError> {
+        match self.outstanding_activity_tasks.lock().get_mut(&task_token) {
+            None => return Err(ActivitySlotError::UnknownTask(task_token)),
+            Some(info) if info.slot_released => {
+                return Err(ActivitySlotError::AlreadyReleased(task_token))
+            }
+            Some(info) => {
+                info.slot_released = true;
+                self.semaphore_for(info.from_session).add_permit();
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait for all outstanding (and held) activity tasks to finish
     pub(crate) async fn wait_all_finished(&self) {
-        while !self.outstanding_activity_tasks.is_empty() {
-            self.complete_notify.notified().await
+        loop {
+            // Register interest before checking, so a completion between the check and the await
+            // isn't missed.
+            let notified = self.complete_notify.notified();
+            if self.outstanding_activity_tasks.lock().is_empty()
+                && self.held_activity_tasks.lock().is_empty()
+            {
+                break;
+            }
+            notified.await
         }
     }
 
     pub(crate) async fn shutdown(self) {
-        self.poller.shutdown_box().await;
+        self.poller.shutdown().await;
+        if let Some(session) = self.session {
+            session.poller.shutdown().await;
+        }
         self.heartbeat_manager.shutdown().await;
     }
 
@@ -148,6 +372,7 @@ impl WorkerActivityTasks {
     /// Returns `Ok(None)` if no activity is ready and the overall polling loop should be retried.
     pub(crate) async fn poll(&self) -> Result<Option<ActivityTask>, PollActivityError> {
         let poll_with_semaphore = async {
+            self.wait_for_held_room().await;
             // Acquire and subsequently forget a permit for an outstanding activity. When they are
             // completed, we must add a new permit to the semaphore, since holding the permit the
             // entire time lang does work would be a challenge.
@@ -158,6 +383,23 @@ impl WorkerActivityTasks {
                 .expect("outstanding activity semaphore not closed");
             (self.poller.poll().await, sem)
         };
+        let poll_session = async {
+            match self.session.as_ref() {
+                Some(session) => {
+                    let sem = session
+                        .semaphore
+                        .acquire()
+                        .await
+                        .expect("session activity semaphore not closed");
+                    (session.poller.poll().await, sem)
+                }
+                None => futures::future::pending().await,
+            }
+        };
+        let poll_session = async {
+            self.wait_for_held_room().await;
+            poll_session.await
+        };
 
         tokio::select! {
             biased;
@@ -165,71 +407,348 @@ impl WorkerActivityTasks {
             cancel_task = self.next_pending_cancel_task() => {
                 cancel_task
             }
-            (work, sem) = poll_with_semaphore => {
-                match work {
-                    Some(Ok(work)) => {
-                        if work == PollActivityTaskQueueResponse::default() {
-                            // Timeout
-                            self.metrics.act_poll_timeout();
-                            return Ok(None)
-                        }
+            held_task = self.next_held_task() => held_task,
+            (work, sem) = poll_with_semaphore => self.handle_polled(work, sem, false).await,
+            (work, sem) = poll_session => self.handle_polled(work, sem, true).await,
+        }
+    }
 
-                        if let Some(dur) = work.sched_to_start() {
-                            self.metrics
-                                .act_sched_to_start_latency(dur);
-                        }
+    async fn handle_polled(
+        &self,
+        work: Option<pollers::Result<PollActivityTaskQueueResponse>>,
+        sem: MeteredPermit<'_>,
+        from_session: bool,
+    ) -> Result<Option<ActivityTask>, PollActivityError> {
+        match work {
+            Some(Ok(work)) => {
+                *self.last_successful_poll.lock() = Some(SystemTime::now());
+                if work == PollActivityTaskQueueResponse::default() {
+                    // Timeout
+                    self.metrics.act_poll_timeout();
+                    return Ok(None);
+                }
 
-                        self.outstanding_activity_tasks.insert(
-                            work.task_token.clone().into(),
-                            RemoteInFlightActInfo::new(
-                                work.activity_type.clone().unwrap_or_default().name,
-                                work.workflow_type.clone().unwrap_or_default().name,
-                                work.heartbeat_timeout.clone()
-                            ),
-                        );
-                        // Only permanently take a permit in the event the poll finished properly
-                        sem.forget();
-                        Ok(Some(ActivityTask::start_from_poll_resp(work)))
-                    }
-                    None => {
-                        Err(PollActivityError::ShutDown)
-                    }
-                    Some(Err(e)) => Err(e.into())
+                if let Some(dur) = work.sched_to_start() {
+                    self.metrics.act_sched_to_start_latency(dur);
+                }
+                if self.exceeded_schedule_to_start(&work) {
+                    // The permit is dropped (and hence returned) rather than forgotten
+                    self.drop_expired_task(work);
+                    return Ok(None);
+                }
+                let act_type = work.activity_type.clone().unwrap_or_default().name;
+                if self.must_hold(&act_type) {
+                    debug!(activity_type = %act_type,
+                           "Activity type is at its outstanding limit, holding task");
+                    // The permit is dropped, so other types may use the slot in the meantime
+                    self.per_type_counts
+                        .lock()
+                        .entry(act_type)
+                        .or_default()
+                        .held += 1;
+                    self.held_activity_tasks
+                        .lock()
+                        .push_back((work, from_session));
+                    return Ok(None);
+                }
+                let task = self.start_task(work, from_session);
+                if task.is_some() {
+                    // Only permanently take a permit in the event the poll finished properly
+                    sem.forget();
+                }
+                Ok(task)
+            }
+            None => {
+                // Polling has stopped, but lang must still be told about cancels (ex: those
+                // issued once shutdown reaches its deadline) until activities are done
+                tokio::select! {
+                    biased;
+
+                    cancel_task = self.next_pending_cancel_task() => cancel_task,
+                    held_task = self.next_held_task() => held_task,
+                    _ = self.wait_all_finished() => Err(PollActivityError::ShutDown),
                 }
             }
+            Some(Err(e)) => Err(e.into()),
         }
     }
 
+    /// Turn a polled task into one for lang and start tracking it. Returns `None` if the
+    /// interceptor vetoed it, in which case it has been failed instead.
+    fn start_task(
+        &self,
+        work: PollActivityTaskQueueResponse,
+        from_session: bool,
+    ) -> Option<ActivityTask> {
+        let act_type = work.activity_type.clone().unwrap_or_default().name;
+        let wf_type = work.workflow_type.clone().unwrap_or_default().name;
+        let wf_run_id = work.workflow_execution.clone().unwrap_or_default().run_id;
+        let heartbeat_timeout = work.heartbeat_timeout.clone();
+        if let Some(input) = work.input.as_ref() {
+            self.metrics
+                .with_new_attrs([
+                    activity_type(act_type.clone()),
+                    workflow_type(wf_type.clone()),
+                ])
+                .act_input_payload_size(input.encoded_len());
+        }
+
+        let mut task = ActivityTask::start_from_poll_resp(work);
+        if let Some(interceptor) = self.interceptor.as_ref() {
+            if let Err(failure) = interceptor.on_dispatch(&mut task) {
+                warn!(activity_type = %act_type,
+                      "Activity task dispatch was vetoed by interceptor");
+                self.fail_undispatched(TaskToken(task.task_token), Some(failure));
+                return None;
+            }
+        }
+
+        let span = info_span!("activity_task", activity_type = %act_type,
+                              workflow_type = %wf_type, run_id = %wf_run_id,
+                              outcome = tracing::field::Empty);
+        if let Some(activity_task::Variant::Start(start)) = task.variant.as_ref() {
+            set_parent_from_headers(&span, &start.header_fields);
+        }
+        if self.max_outstanding_per_type.contains_key(&act_type) {
+            self.per_type_counts
+                .lock()
+                .entry(act_type.clone())
+                .or_default()
+                .outstanding += 1;
+        }
+        self.outstanding_activity_tasks.lock().insert(
+            task.task_token.clone().into(),
+            RemoteInFlightActInfo::new(
+                act_type,
+                wf_type,
+                wf_run_id,
+                heartbeat_timeout,
+                span,
+                from_session,
+            ),
+        );
+        Some(task)
+    }
+
+    /// True if a task of this type can't be dispatched yet, because its type is at its limit or
+    /// tasks of its type are already waiting
+    fn must_hold(&self, act_type: &str) -> bool {
+        let max = match self.max_outstanding_per_type.get(act_type) {
+            Some(&max) => max,
+            None => return false,
+        };
+        self.per_type_counts
+            .lock()
+            .get(act_type)
+            .map(|c| c.held > 0 || c.outstanding >= max)
+            .unwrap_or_default()
+    }
+
+    fn type_at_limit(&self, act_type: &str) -> bool {
+        match self.max_outstanding_per_type.get(act_type) {
+            Some(&max) => {
+                self.per_type_counts
+                    .lock()
+                    .get(act_type)
+                    .map(|c| c.outstanding)
+                    .unwrap_or_default()
+                    >= max
+            }
+            None => false,
+        }
+    }
+
+    /// Position of the first held task whose type is below its limit
+    fn ready_held_task(
+        &self,
+        held: &VecDeque<(PollActivityTaskQueueResponse, bool)>,
+    ) -> Option<usize> {
+        held.iter().position(|(t, _)| {
+            !self.type_at_limit(
+                t.activity_type
+                    .as_ref()
+                    .map(|t| t.name.as_str())
+                    .unwrap_or_default(),
+            )
+        })
+    }
+
+    /// Wait while the maximum number of tasks are held, so tasks of a limited type can't pile up
+    /// without bound while their server-side timeouts run
+    async fn wait_for_held_room(&self) {
+        loop {
+            let notified = self.complete_notify.notified();
+            if self.held_activity_tasks.lock().len() < self.max_held_tasks {
+                return;
+            }
+            notified.await
+        }
+    }
+
+    /// Wait until a held task's type is below its limit and a slot is free, and then dispatch it.
+    /// Held tasks get slots ahead of new polls.
+    async fn next_held_task(&self) -> Result<Option<ActivityTask>, PollActivityError> {
+        loop {
+            let notified = self.complete_notify.notified();
+            let ready_from_session = {
+                let held = self.held_activity_tasks.lock();
+                self.ready_held_task(&held).map(|i| held[i].1)
+            };
+            let from_session = match ready_from_session {
+                Some(fs) => fs,
+                None => {
+                    notified.await;
+                    continue;
+                }
+            };
+            let sem = self
+                .semaphore_for(from_session)
+                .acquire_with_priority(AcquirePriority::High)
+                .await
+                .expect("activity semaphore not closed");
+            // Things may have changed while waiting for the slot
+            let ready = {
+                let mut held = self.held_activity_tasks.lock();
+                self.ready_held_task(&held)
+                    .filter(|&i| held[i].1 == from_session)
+                    .and_then(|i| held.remove(i))
+            };
+            let (work, from_session) = match ready {
+                Some(r) => r,
+                None => continue,
+            };
+            let act_type = work.activity_type.clone().unwrap_or_default().name;
+            if let Some(counts) = self.per_type_counts.lock().get_mut(&act_type) {
+                counts.held -= 1;
+            }
+            if self.exceeded_schedule_to_start(&work) {
+                self.drop_expired_task(work);
+                // Polling may have been waiting for room
+                self.complete_notify.notify_waiters();
+                continue;
+            }
+            let task = self.start_task(work, from_session);
+            if task.is_some() {
+                sem.forget();
+            }
+            return Ok(task);
+        }
+    }
+
+    /// The semaphore tracking slots for activities from the session queue, or the main one
+    fn semaphore_for(&self, from_session: bool) -> &MeteredSemaphore {
+        match self.session.as_ref() {
+            Some(session) if from_session => &session.semaphore,
+            _ => &self.activities_semaphore,
+        }
+    }
+
+    /// Returns true if the task's current attempt was scheduled longer ago than the configured
+    /// maximum schedule-to-start time. Uses the local clock, since the task could have sat in the
+    /// worker for a while after the server handed it out.
+    fn exceeded_schedule_to_start(&self, work: &PollActivityTaskQueueResponse) -> bool {
+        let (max, scheduled) = match (
+            self.max_schedule_to_start,
+            work.current_attempt_scheduled_time.clone(),
+        ) {
+            (Some(max), Some(scheduled)) => (max, scheduled),
+            _ => return false,
+        };
+        let scheduled: SystemTime = match scheduled.try_into() {
+            Ok(s) => s,
+            Err(_) => return false,
+        };
+        SystemTime::now()
+            .duration_since(scheduled)
+            .map(|waited| waited > max)
+            .unwrap_or_default()
+    }
+
+    /// Fail an activity task which is past its schedule-to-start deadline without ever giving it
+    /// to lang, so the server may retry it right away instead of waiting for it to time out.
+    fn drop_expired_task(&self, work: PollActivityTaskQueueResponse) {
+        let act_type = work.activity_type.unwrap_or_default().name;
+        warn!(activity_id = %work.activity_id, activity_type = %act_type,
+              "Activity task exceeded maximum schedule-to-start time before being dispatched, \
+               failing it");
+        self.metrics
+            .with_new_attrs([
+                activity_type(act_type),
+                workflow_type(work.workflow_type.unwrap_or_default().name),
+            ])
+            .act_sched_to_start_exceeded();
+        self.fail_undispatched(
+            TaskToken(work.task_token),
+            ar::Failure::timeout(TimeoutType::ScheduleToStart).failure,
+        );
+    }
+
+    /// Report an activity task which was never given to lang as failed, in the background
+    fn fail_undispatched(&self, task_token: TaskToken, failure: Option<Failure>) {
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            if let Err(e) = client.fail_activity_task(task_token.clone(), failure).await {
+                warn!(task_token = %task_token, error = ?e,
+                      "Failed to fail activity task which was not dispatched");
+            }
+        });
+    }
+
     pub(crate) async fn complete(
         &self,
         task_token: TaskToken,
-        status: aer::Status,
+        mut status: aer::Status,
         client: &dyn WorkerClient,
     ) -> Result<(), CompleteActivityError> {
-        if let Some((_, act_info)) = self.outstanding_activity_tasks.remove(&task_token) {
+        let maybe_act_info = self.outstanding_activity_tasks.lock().remove(&task_token);
+        if let Some(act_info) = maybe_act_info {
+            if let Some(counts) = self
+                .per_type_counts
+                .lock()
+                .get_mut(&act_info.base.activity_type)
+            {
+                counts.outstanding -= 1;
+            }
+            if let Some(interceptor) = self.interceptor.as_ref() {
+                interceptor.on_complete(&task_token.0, &act_info.base.activity_type, &mut status);
+            }
             let act_metrics = self.metrics.with_new_attrs([
                 activity_type(act_info.base.activity_type.clone()),
                 workflow_type(act_info.base.workflow_type.clone()),
             ]);
             act_metrics.act_execution_latency(act_info.base.start_time.elapsed());
-            self.activities_semaphore.add_permit();
+            act_info.base.span.record(
+                "outcome",
+                match &status {
+                    aer::Status::Completed(_) => "completed",
+                    aer::Status::Failed(_) => "failed",
+                    aer::Status::Cancelled(_) => "cancelled",
+                    aer::Status::WillCompleteAsync(_) => "will_complete_async",
+                },
+            );
+            if !act_info.slot_released {
+                self.semaphore_for(act_info.from_session).add_permit();
+            }
             self.heartbeat_manager.evict(task_token.clone()).await;
-            let known_not_found = act_info.known_not_found;
-            drop(act_info); // TODO: Get rid of dashmap. If we hold ref across await, bad stuff.
             self.complete_notify.notify_waiters();
 
             // No need to report activities which we already know the server doesn't care about
-            if !known_not_found {
+            if !act_info.known_not_found {
                 let maybe_net_err = match status {
                     aer::Status::WillCompleteAsync(_) => None,
-                    aer::Status::Completed(ar::Success { result }) => client
-                        .complete_activity_task(task_token.clone(), result.map(Into::into))
-                        .await
-                        .err(),
+                    aer::Status::Completed(ar::Success { result }) => {
+                        if let Some(r) = result.as_ref() {
+                            act_metrics.act_result_payload_size(r.encoded_len());
+                        }
+                        client
+                            .complete_activity_task(task_token.clone(), result.map(Into::into))
+                            .await
+                            .err()
+                    }
                     aer::Status::Failed(ar::Failure { failure }) => {
                         act_metrics.act_execution_failed();
                         client
-                            .fail_activity_task(task_token.clone(), failure.map(Into::into))
+                            .fail_activity_task(task_token.clone(), failure)
                             .await
                             .err()
                     }
@@ -247,7 +766,7 @@ impl WorkerActivityTasks {
                             None
                         };
                         client
-                            .cancel_activity_task(task_token.clone(), details.map(Into::into))
+                            .cancel_activity_task(task_token.clone(), details)
                             .await
                             .err()
                     }
@@ -272,19 +791,43 @@ impl WorkerActivityTasks {
         Ok(())
     }
 
-    /// Attempt to record an activity heartbeat
+    /// Attempt to record an activity heartbeat. If the activity is not one we are tracking, a
+    /// cancel with reason [ActivityCancelReason::NotFound] is queued up for it, so that lang learns
+    /// it should stop working on it.
     pub(crate) fn record_heartbeat(
         &self,
         details: ActivityHeartbeat,
     ) -> Result<(), ActivityHeartbeatError> {
-        // TODO: Propagate these back as cancels. Silent fails is too nonobvious
-        let heartbeat_timeout: Duration = self
+        let task_token = TaskToken(details.task_token.clone());
+        let maybe_hb_info = self
             .outstanding_activity_tasks
-            .get(&TaskToken(details.task_token.clone()))
-            .ok_or(ActivityHeartbeatError::UnknownActivity)?
-            .heartbeat_timeout
-            .clone()
-            // We treat None as 0 (even though heartbeat_timeout is never set to None by the server)
+            .lock()
+            .get(&task_token)
+            .map(|info| {
+                (
+                    info.heartbeat_timeout.clone(),
+                    self.heartbeat_throttle_interval_overrides
+                        .get(&info.base.activity_type)
+                        .copied(),
+                )
+            });
+        let (heartbeat_timeout, throttle_override) = match maybe_hb_info {
+            Some(hbi) => hbi,
+            None => {
+                if self
+                    .cancelled_unknown_activities
+                    .lock()
+                    .put(task_token.clone(), ())
+                    .is_none()
+                {
+                    self.heartbeat_manager
+                        .issue_cancel(PendingActivityCancel::untracked(task_token));
+                }
+                return Err(ActivityHeartbeatError::UnknownActivity);
+            }
+        };
+        // We treat None as 0 (even though heartbeat_timeout is never set to None by the server)
+        let heartbeat_timeout: Duration = heartbeat_timeout
             .unwrap_or_default()
             .try_into()
             // This technically should never happen since prost duration should be directly mappable
@@ -294,25 +837,84 @@ impl WorkerActivityTasks {
         // There is a bug in the server that translates non-set heartbeat timeouts into 0 duration.
         // That's why we treat 0 the same way as None, otherwise we wouldn't know which aggregation
         // delay to use, and using 0 is not a good idea as SDK would hammer the server too hard.
-        let throttle_interval = if heartbeat_timeout.as_millis() == 0 {
-            self.default_heartbeat_throttle_interval
-        } else {
-            heartbeat_timeout.mul_f64(0.8)
+        let throttle_interval = match (heartbeat_timeout.as_millis() == 0, throttle_override) {
+            (true, Some(overridden)) => overridden,
+            (true, None) => self.default_heartbeat_throttle_interval,
+            (false, Some(overridden)) => std::cmp::min(overridden, heartbeat_timeout.mul_f64(0.8)),
+            (false, None) => heartbeat_timeout.mul_f64(0.8),
         };
         let throttle_interval =
             std::cmp::min(throttle_interval, self.max_heartbeat_throttle_interval);
         self.heartbeat_manager.record(details, throttle_interval)
     }
 
+    /// Issue cancels for all outstanding activities scheduled by the provided workflow run, which
+    /// core knows has finished. The server would not accept their results anyway, so there's no
+    /// point in waiting for lang to find out by heartbeating.
+    pub(crate) fn cancel_activities_for_run(&self, run_id: &str) {
+        let to_cancel: Vec<_> = self
+            .outstanding_activity_tasks
+            .lock()
+            .iter()
+            .filter(|(_, info)| {
+                info.workflow_run_id == run_id
+                    && !info.issued_cancel_to_lang
+                    && !info.known_not_found
+            })
+            .map(|(tt, _)| tt.clone())
+            .collect();
+        for tt in to_cancel {
+            debug!(task_token = %tt, run_id, "Cancelling activity of finished workflow run");
+            self.heartbeat_manager
+                .issue_cancel(PendingActivityCancel::new(
+                    tt,
+                    ActivityCancelReason::NotFound,
+                ));
+        }
+    }
+
+    /// Issue cancels for all outstanding activities because the worker is shutting down and has
+    /// given up on waiting for them to finish on their own
+    pub(crate) fn cancel_all_for_shutdown(&self) {
+        let to_cancel: Vec<_> = self
+            .outstanding_activity_tasks
+            .lock()
+            .iter()
+            .filter(|(_, info)| !info.issued_cancel_to_lang && !info.known_not_found)
+            .map(|(tt, _)| tt.clone())
+            .collect();
+        for tt in to_cancel {
+            debug!(task_token = %tt, "Cancelling activity because worker is shutting down");
+            self.heartbeat_manager
+                .issue_cancel(PendingActivityCancel::new(
+                    tt,
+                    ActivityCancelReason::WorkerShutdown,
+                ));
+        }
+    }
+
+    /// Number of activity tasks which have been issued to lang but not yet completed
+    pub(crate) fn num_outstanding(&self) -> usize {
+        self.outstanding_activity_tasks.lock().len()
+    }
+
     async fn next_pending_cancel_task(&self) -> Result<Option<ActivityTask>, PollActivityError> {
         let next_pc = self.heartbeat_manager.next_pending_cancel().await;
         // Issue cancellations for anything we noticed was cancelled during heartbeating
-        if let Some(PendingActivityCancel { task_token, reason }) = next_pc {
+        if let Some(PendingActivityCancel {
+            task_token,
+            reason,
+            untracked,
+        }) = next_pc
+        {
+            if untracked {
+                return Ok(Some(ActivityTask::cancel_from_ids(task_token.0, reason)));
+            }
             // It's possible that activity has been completed and we no longer have an
             // outstanding activity task. This is fine because it means that we no
             // longer need to cancel this activity, so we'll just ignore such orphaned
             // cancellations.
-            if let Some(mut details) = self.outstanding_activity_tasks.get_mut(&task_token) {
+            if let Some(details) = self.outstanding_activity_tasks.lock().get_mut(&task_token) {
                 if details.issued_cancel_to_lang {
                     // Don't double-issue cancellations
                     return Ok(None);
@@ -336,7 +938,11 @@ impl WorkerActivityTasks {
         }
     }
 
-    #[cfg(test)]
+    /// When a poll for activity tasks last succeeded, if ever
+    pub(crate) fn last_successful_poll(&self) -> Option<SystemTime> {
+        *self.last_successful_poll.lock()
+    }
+
     pub(crate) fn remaining_activity_capacity(&self) -> usize {
         self.activities_semaphore.sem.available_permits()
     }
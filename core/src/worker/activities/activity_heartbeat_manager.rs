@@ -1,13 +1,16 @@
 use crate::{
+    telemetry::events::CoreEventEmitter,
     worker::{activities::PendingActivityCancel, client::WorkerClientBag},
     TaskToken,
 };
 use futures::StreamExt;
 use std::{
     collections::{hash_map::Entry, HashMap},
+    future,
     sync::Arc,
     time::{self, Duration, Instant},
 };
+use temporal_sdk_core_api::events::CoreEvent;
 use temporal_sdk_core_protos::{
     coresdk::{activity_task::ActivityCancelReason, common, ActivityHeartbeat, IntoPayloadsExt},
     temporal::api::workflowservice::v1::RecordActivityTaskHeartbeatResponse,
@@ -18,6 +21,7 @@ use tokio::{
         Mutex, Notify,
     },
     task::JoinHandle,
+    time::{interval, Interval, MissedTickBehavior},
 };
 use tokio_util::sync::CancellationToken;
 
@@ -42,6 +46,9 @@ enum HeartbeatAction {
     },
     CompleteReport(TaskToken),
     CompleteThrottle(TaskToken),
+    IssueCancel(PendingActivityCancel),
+    /// The batch flush interval elapsed, report all heartbeats whose throttle interval is over
+    FlushBatch,
 }
 
 #[derive(Debug)]
@@ -60,6 +67,8 @@ enum HeartbeatExecutorAction {
         task_token: TaskToken,
         details: Vec<common::Payload>,
     },
+    /// Issue a cancel to lang which did not originate from a heartbeat response
+    IssueCancel(PendingActivityCancel),
 }
 
 /// Errors thrown when heartbeating
@@ -119,6 +128,13 @@ impl ActivityHeartbeatManager {
         completed.notified().await;
     }
 
+    /// Queue a cancel which core decided on itself rather than learning of it from a heartbeat
+    /// response. Ex: lang heartbeated an activity which already completed, or the workflow which
+    /// scheduled the activity has finished.
+    pub(super) fn issue_cancel(&self, cancel: PendingActivityCancel) {
+        let _ = self.heartbeat_tx.send(HeartbeatAction::IssueCancel(cancel));
+    }
+
     /// Returns a future that resolves any time there is a new activity cancel that must be
     /// dispatched to lang
     pub(super) async fn next_pending_cancel(&self) -> Option<PendingActivityCancel> {
@@ -129,7 +145,7 @@ impl ActivityHeartbeatManager {
     /// Initiates shutdown procedure by stopping lifecycle loop and awaiting for all in-flight
     /// heartbeat requests to be flushed to the server.
     pub(super) async fn shutdown(&self) {
-        let _ = self.shutdown_token.cancel();
+        self.shutdown_token.cancel();
         let mut handle = self.join_handle.lock().await;
         if let Some(h) = handle.take() {
             h.await.expect("shutdown should exit cleanly");
@@ -170,18 +186,32 @@ struct HeartbeatStreamState {
     /// Token that can be used to cancel the entire stream.
     /// Requests to the server are not cancelled with this token.
     cancellation_token: CancellationToken,
+    /// If set, throttled heartbeats are not each given their own timer. Instead, all heartbeats
+    /// whose throttle interval has elapsed are reported together every time this ticks.
+    batch_flush_ticker: Option<Interval>,
+    events: CoreEventEmitter,
 }
 
 impl HeartbeatStreamState {
-    fn new() -> (Self, UnboundedSender<HeartbeatAction>, CancellationToken) {
+    fn new(
+        batch_flush_interval: Option<Duration>,
+        events: CoreEventEmitter,
+    ) -> (Self, UnboundedSender<HeartbeatAction>, CancellationToken) {
         let (heartbeat_tx, incoming_hbs) = unbounded_channel();
         let cancellation_token = CancellationToken::new();
+        let batch_flush_ticker = batch_flush_interval.map(|i| {
+            let mut ticker = interval(i);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            ticker
+        });
         (
             Self {
                 cancellation_token: cancellation_token.clone(),
                 tt_to_state: Default::default(),
                 tt_needs_flush: Default::default(),
                 incoming_hbs,
+                batch_flush_ticker,
+                events,
             },
             heartbeat_tx,
             cancellation_token,
@@ -211,6 +241,9 @@ impl HeartbeatStreamState {
             Entry::Occupied(mut o) => {
                 let state = o.get_mut();
                 state.last_recorded_details = Some(hb.details);
+                self.events.emit(CoreEvent::HeartbeatThrottled {
+                    task_token: hb.task_token,
+                });
                 None
             }
         }
@@ -223,6 +256,10 @@ impl HeartbeatStreamState {
         }
         if let Some(st) = self.tt_to_state.get_mut(&tt) {
             st.is_record_in_flight = false;
+            if self.batch_flush_ticker.is_some() {
+                // The next batch flush will take care of this activity once its throttle is over
+                return None;
+            }
             let cancellation_token = self.cancellation_token.child_token();
             st.throttled_cancellation_token = Some(cancellation_token.clone());
             // Always sleep for simplicity even if the duration is 0
@@ -261,6 +298,29 @@ impl HeartbeatStreamState {
         }
     }
 
+    /// Batch flush interval elapsed. Report every activity with recorded details whose throttle
+    /// interval is over, and forget about those which had nothing new to report.
+    fn flush_batch(&mut self) -> Vec<HeartbeatExecutorAction> {
+        let mut actions = vec![];
+        self.tt_to_state.retain(|tt, state| {
+            if state.is_record_in_flight || state.get_throttle_sleep_duration() > Duration::ZERO {
+                return true;
+            }
+            if let Some(details) = state.last_recorded_details.take() {
+                state.last_send_requested = Instant::now();
+                state.is_record_in_flight = true;
+                actions.push(HeartbeatExecutorAction::Report {
+                    task_token: tt.clone(),
+                    details,
+                });
+                true
+            } else {
+                false
+            }
+        });
+        actions
+    }
+
     /// Activity should not be tracked anymore, cancel throttle timer if running.
     ///
     /// Will return a report action if there are recorded details present, to ensure we flush the
@@ -272,7 +332,7 @@ impl HeartbeatStreamState {
     ) -> Option<HeartbeatExecutorAction> {
         if let Some(state) = self.tt_to_state.remove(&tt) {
             if let Some(cancel_tok) = state.throttled_cancellation_token {
-                let _ = cancel_tok.cancel();
+                cancel_tok.cancel();
             }
             if let Some(last_deets) = state.last_recorded_details {
                 self.tt_needs_flush.insert(tt.clone(), on_complete);
@@ -294,9 +354,17 @@ impl HeartbeatStreamState {
 impl ActivityHeartbeatManager {
     /// Creates a new instance of an activity heartbeat manager and returns a handle to the user,
     /// which allows to send new heartbeats and initiate the shutdown.
-    pub fn new(client: Arc<WorkerClientBag>) -> Self {
+    ///
+    /// If `batch_flush_interval` is set, throttled heartbeats for all activities are flushed
+    /// together on that interval rather than each on their own timer. See
+    /// [temporal_sdk_core_api::worker::WorkerConfig::heartbeat_batch_flush_interval].
+    pub fn new(
+        client: Arc<WorkerClientBag>,
+        batch_flush_interval: Option<Duration>,
+        events: CoreEventEmitter,
+    ) -> Self {
         let (heartbeat_stream_state, heartbeat_tx_source, shutdown_token) =
-            HeartbeatStreamState::new();
+            HeartbeatStreamState::new(batch_flush_interval, events);
         let (cancels_tx, cancels_rx) = unbounded_channel();
         let heartbeat_tx = heartbeat_tx_source.clone();
 
@@ -306,31 +374,39 @@ impl ActivityHeartbeatManager {
             // depending on its delay and when we last issued a heartbeat for it.
             futures::stream::unfold(heartbeat_stream_state, move |mut hb_states| {
                 async move {
+                    let cancellation_token = &hb_states.cancellation_token;
+                    let incoming_hbs = &mut hb_states.incoming_hbs;
+                    let batch_flush_ticker = &mut hb_states.batch_flush_ticker;
                     let hb = tokio::select! {
                         biased;
 
-                        _ = hb_states.cancellation_token.cancelled() => {
+                        _ = cancellation_token.cancelled() => {
                             return None
                         }
-                        hb = hb_states.incoming_hbs.recv() => match hb {
+                        hb = incoming_hbs.recv() => match hb {
                             None => return None,
                             Some(hb) => hb,
-                        }
+                        },
+                        _ = async {
+                            match batch_flush_ticker {
+                                Some(t) => { t.tick().await; }
+                                None => future::pending::<()>().await,
+                            }
+                        } => HeartbeatAction::FlushBatch,
                     };
 
-                    Some((
-                        match hb {
-                            HeartbeatAction::SendHeartbeat(hb) => hb_states.record(hb),
-                            HeartbeatAction::CompleteReport(tt) => hb_states.handle_report_completed(tt),
-                            HeartbeatAction::CompleteThrottle(tt) => hb_states.handle_throttle_completed(tt),
-                            HeartbeatAction::Evict{ token, on_complete } => hb_states.evict(token, on_complete),
-                        },
-                        hb_states,
-                    ))
+                    let actions: Vec<_> = match hb {
+                        HeartbeatAction::SendHeartbeat(hb) => hb_states.record(hb).into_iter().collect(),
+                        HeartbeatAction::CompleteReport(tt) => hb_states.handle_report_completed(tt).into_iter().collect(),
+                        HeartbeatAction::CompleteThrottle(tt) => hb_states.handle_throttle_completed(tt).into_iter().collect(),
+                        HeartbeatAction::Evict{ token, on_complete } => hb_states.evict(token, on_complete).into_iter().collect(),
+                        HeartbeatAction::IssueCancel(c) => vec![HeartbeatExecutorAction::IssueCancel(c)],
+                        HeartbeatAction::FlushBatch => hb_states.flush_batch(),
+                    };
+                    Some((actions, hb_states))
                 }
             })
-            // Filters out `None`s
-            .filter_map(|opt| async { opt })
+            .flat_map(futures::stream::iter)
             .for_each_concurrent(None, move |action| {
                 let heartbeat_tx = heartbeat_tx_source.clone();
                 let sg = client.clone();
@@ -381,6 +457,11 @@ impl ActivityHeartbeatManager {
                             };
                             let _ = heartbeat_tx.send(HeartbeatAction::CompleteReport(tt));
                         }
+                        HeartbeatExecutorAction::IssueCancel(c) => {
+                            cancels_tx
+                                .send(c)
+                                .expect("Receive half of heartbeat cancels not blocked");
+                        }
                     }
                 }
             }),
@@ -400,7 +481,10 @@ mod test {
     use super::*;
 
     use crate::worker::client::mocks::mock_workflow_client;
-    use std::time::Duration;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
     use temporal_sdk_core_protos::{
         coresdk::common::Payload,
         temporal::api::workflowservice::v1::RecordActivityTaskHeartbeatResponse,
@@ -416,7 +500,9 @@ mod test {
             .expect_record_activity_heartbeat()
             .returning(|_, _| Ok(RecordActivityTaskHeartbeatResponse::default()))
             .times(2);
-        let hm = ActivityHeartbeatManager::new(Arc::new(mock_client.into()));
+        let events = CoreEventEmitter::default();
+        let mut event_rx = events.subscribe();
+        let hm = ActivityHeartbeatManager::new(Arc::new(mock_client.into()), None, events);
         let fake_task_token = vec![1, 2, 3];
         // Send 2 heartbeat requests for 20ms apart.
         // The first heartbeat should be sent right away, and
@@ -425,6 +511,12 @@ mod test {
             record_heartbeat(&hm, fake_task_token.clone(), i, Duration::from_millis(50));
             sleep(Duration::from_millis(20)).await;
         }
+        assert_eq!(
+            event_rx.try_recv().unwrap(),
+            CoreEvent::HeartbeatThrottled {
+                task_token: TaskToken(fake_task_token)
+            }
+        );
         // sleep again to let heartbeats be flushed
         sleep(Duration::from_millis(20)).await;
         hm.shutdown().await;
@@ -437,7 +529,8 @@ mod test {
             .expect_record_activity_heartbeat()
             .returning(|_, _| Ok(RecordActivityTaskHeartbeatResponse::default()))
             .times(3);
-        let hm = ActivityHeartbeatManager::new(Arc::new(mock_client.into()));
+        let hm =
+            ActivityHeartbeatManager::new(Arc::new(mock_client.into()), None, Default::default());
         let fake_task_token = vec![1, 2, 3];
         // Heartbeats always get sent if recorded less frequently than the throttle intreval
         for i in 0_u8..3 {
@@ -457,7 +550,8 @@ mod test {
             .expect_record_activity_heartbeat()
             .returning(|_, _| Ok(RecordActivityTaskHeartbeatResponse::default()))
             .times(1);
-        let hm = ActivityHeartbeatManager::new(Arc::new(mock_client.into()));
+        let hm =
+            ActivityHeartbeatManager::new(Arc::new(mock_client.into()), None, Default::default());
         let fake_task_token = vec![1, 2, 3];
         // Send a whole bunch of heartbeats very fast. We should still only send one total.
         for i in 0_u8..50 {
@@ -476,7 +570,8 @@ mod test {
             .expect_record_activity_heartbeat()
             .returning(|_, _| Ok(RecordActivityTaskHeartbeatResponse::default()))
             .times(2);
-        let hm = ActivityHeartbeatManager::new(Arc::new(mock_client.into()));
+        let hm =
+            ActivityHeartbeatManager::new(Arc::new(mock_client.into()), None, Default::default());
         let fake_task_token = vec![1, 2, 3];
         record_heartbeat(&hm, fake_task_token.clone(), 0, Duration::from_millis(100));
         sleep(Duration::from_millis(500)).await;
@@ -486,6 +581,41 @@ mod test {
         hm.shutdown().await;
     }
 
+    /// With batching enabled, throttled heartbeats for different activities wait for the next
+    /// flush tick and are then all sent together, rather than each at the end of its own throttle.
+    #[tokio::test]
+    async fn batched_heartbeats_flush_together() {
+        let sent = Arc::new(AtomicUsize::new(0));
+        let sent_clone = sent.clone();
+        let mut mock_client = mock_workflow_client();
+        mock_client
+            .expect_record_activity_heartbeat()
+            .returning(move |_, _| {
+                sent_clone.fetch_add(1, Ordering::SeqCst);
+                Ok(RecordActivityTaskHeartbeatResponse::default())
+            })
+            .times(6);
+        let hm = ActivityHeartbeatManager::new(
+            Arc::new(mock_client.into()),
+            Some(Duration::from_millis(200)),
+            Default::default(),
+        );
+        // First heartbeats are sent right away, second ones are throttled
+        for round in 0_u8..2 {
+            for tt in 0_u8..3 {
+                record_heartbeat(&hm, vec![tt], round, Duration::from_millis(50));
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(sent.load(Ordering::SeqCst), 3);
+        // Well past the throttle interval, but the flush tick hasn't come yet
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(sent.load(Ordering::SeqCst), 3);
+        sleep(Duration::from_millis(150)).await;
+        assert_eq!(sent.load(Ordering::SeqCst), 6);
+        hm.shutdown().await;
+    }
+
     #[tokio::test]
     async fn evict_works() {
         let mut mock_client = mock_workflow_client();
@@ -493,7 +623,8 @@ mod test {
             .expect_record_activity_heartbeat()
             .returning(|_, _| Ok(RecordActivityTaskHeartbeatResponse::default()))
             .times(2);
-        let hm = ActivityHeartbeatManager::new(Arc::new(mock_client.into()));
+        let hm =
+            ActivityHeartbeatManager::new(Arc::new(mock_client.into()), None, Default::default());
         let fake_task_token = vec![1, 2, 3];
         record_heartbeat(&hm, fake_task_token.clone(), 0, Duration::from_millis(100));
         // Let it propagate
@@ -513,7 +644,8 @@ mod test {
             .expect_record_activity_heartbeat()
             .returning(|_, _| Ok(RecordActivityTaskHeartbeatResponse::default()))
             .times(1);
-        let hm = ActivityHeartbeatManager::new(Arc::new(mock_client.into()));
+        let hm =
+            ActivityHeartbeatManager::new(Arc::new(mock_client.into()), None, Default::default());
         let fake_task_token = vec![1, 2, 3];
         record_heartbeat(&hm, fake_task_token.clone(), 0, Duration::from_millis(100));
         hm.evict(fake_task_token.clone().into()).await;
@@ -528,7 +660,8 @@ mod test {
             .expect_record_activity_heartbeat()
             .returning(|_, _| Ok(RecordActivityTaskHeartbeatResponse::default()))
             .times(0);
-        let hm = ActivityHeartbeatManager::new(Arc::new(mock_client.into()));
+        let hm =
+            ActivityHeartbeatManager::new(Arc::new(mock_client.into()), None, Default::default());
         hm.shutdown().await;
         match hm.record(
             ActivityHeartbeat {
@@ -560,7 +693,7 @@ mod test {
                 task_token,
                 details: vec![Payload {
                     metadata: Default::default(),
-                    data: vec![payload_data],
+                    data: vec![payload_data].into(),
                 }],
             },
             // Mimic the same delay we would apply in activity task manager
@@ -1,6 +1,6 @@
 use crate::{
     abstractions::MeteredSemaphore, protosext::ValidScheduleLA, retry_logic::RetryPolicyExt,
-    MetricsContext, TaskToken,
+    telemetry::propagation::set_parent_from_headers, MetricsContext, TaskToken,
 };
 use parking_lot::Mutex;
 use std::{
@@ -14,7 +14,7 @@ use temporal_sdk_core_protos::{
         activity_task::{activity_task, ActivityCancelReason, ActivityTask, Cancel, Start},
         common::WorkflowExecution,
     },
-    temporal::api::enums::v1::TimeoutType,
+    temporal::api::{enums::v1::TimeoutType, failure::v1::Failure},
 };
 use tokio::{
     sync::{
@@ -25,6 +25,7 @@ use tokio::{
     time::sleep,
 };
 use tokio_util::sync::CancellationToken;
+use tracing::Span;
 
 #[allow(clippy::large_enum_variant)] // Timeouts are relatively rare
 #[derive(Debug)]
@@ -44,6 +45,8 @@ pub(crate) struct LocalInFlightActInfo {
     pub la_info: NewLocalAct,
     pub dispatch_time: Instant,
     pub attempt: u32,
+    /// Covers this attempt's execution, from dispatch to lang until completion
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -285,7 +288,7 @@ impl LocalActivityManager {
                                     run_id: run_id.clone(),
                                     seq_num: resolution.seq,
                                 })
-                                .map(Clone::clone);
+                                .cloned();
                             if let Some(task_token) = tt {
                                 self.complete(&task_token, &resolution.result);
                                 Some(ActivityTask {
@@ -313,12 +316,18 @@ impl LocalActivityManager {
 
         // It is important that there are no await points after receiving from the channel, as
         // it would mean dropping this future would cause us to drop the activity request.
-        let (new_la, attempt) = match new_or_retry {
+        let (new_la, attempt, attempt_schedule_time, last_failure) = match new_or_retry {
             NewOrRetry::New(n) => {
                 let explicit_attempt_num_or_1 = n.schedule_cmd.attempt.max(1);
-                (n, explicit_attempt_num_or_1)
+                let sched_time = n.schedule_time;
+                (n, explicit_attempt_num_or_1, sched_time, None)
             }
-            NewOrRetry::Retry { in_flight, attempt } => (in_flight, attempt),
+            NewOrRetry::Retry {
+                in_flight,
+                attempt,
+                attempt_schedule_time,
+                last_failure,
+            } => (in_flight, attempt, attempt_schedule_time, last_failure),
         };
         let orig = new_la.clone();
         let id = ExecutingLAId {
@@ -357,12 +366,16 @@ impl LocalActivityManager {
             .get(&id)
             .expect("Task token must exist")
             .clone();
+        let span = info_span!("local_activity_task", activity_type = %sa.activity_type,
+                              run_id = %id.run_id, attempt, outcome = tracing::field::Empty);
+        set_parent_from_headers(&span, &sa.headers);
         dat.outstanding_activity_tasks.insert(
             tt.clone(),
             LocalInFlightActInfo {
                 la_info: orig,
                 dispatch_time: Instant::now(),
                 attempt,
+                span,
             },
         );
         if let Some(to) = dat.timeout_tasks.get_mut(&id) {
@@ -382,7 +395,7 @@ impl LocalActivityManager {
                 input: sa.arguments,
                 heartbeat_details: vec![],
                 scheduled_time: Some(new_la.schedule_time.into()),
-                current_attempt_scheduled_time: Some(new_la.schedule_time.into()),
+                current_attempt_scheduled_time: Some(attempt_schedule_time.into()),
                 started_time: Some(SystemTime::now().into()),
                 attempt,
                 schedule_to_close_timeout: schedule_to_close.map(Into::into),
@@ -390,6 +403,7 @@ impl LocalActivityManager {
                 heartbeat_timeout: None,
                 retry_policy: Some(sa.retry_policy),
                 is_local: true,
+                last_failure,
             })),
         }))
     }
@@ -408,6 +422,15 @@ impl LocalActivityManager {
             };
             dlock.id_to_tt.remove(&exec_id);
             self.semaphore.add_permit();
+            info.span.record(
+                "outcome",
+                match status {
+                    LocalActivityExecutionResult::Completed(_) => "completed",
+                    LocalActivityExecutionResult::Failed(_) => "failed",
+                    LocalActivityExecutionResult::TimedOut(_) => "timed_out",
+                    LocalActivityExecutionResult::Cancelled(_) => "cancelled",
+                },
+            );
 
             match status {
                 LocalActivityExecutionResult::Completed(_)
@@ -447,6 +470,7 @@ impl LocalActivityManager {
 
                         // Send the retry request after waiting the backoff duration
                         let send_chan = self.act_req_tx.clone();
+                        let last_failure = f.failure.clone();
                         let jh = tokio::spawn(async move {
                             tokio::time::sleep(backoff_dur).await;
 
@@ -454,6 +478,8 @@ impl LocalActivityManager {
                                 .send(NewOrRetry::Retry {
                                     in_flight: info.la_info,
                                     attempt: info.attempt + 1,
+                                    attempt_schedule_time: SystemTime::now(),
+                                    last_failure,
                                 })
                                 .expect("Receive half of LA request channel cannot be dropped");
                         });
@@ -493,11 +519,16 @@ pub(crate) enum LACompleteAction {
 }
 
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 enum NewOrRetry {
     New(NewLocalAct),
     Retry {
         in_flight: NewLocalAct,
         attempt: u32,
+        /// When the retry was scheduled, which is when the backoff elapsed
+        attempt_schedule_time: SystemTime,
+        /// The failure which caused the retry
+        last_failure: Option<Failure>,
     },
 }
 
@@ -916,6 +947,54 @@ mod tests {
         assert_eq!(lam.num_outstanding(), 1);
     }
 
+    #[tokio::test]
+    async fn local_retry_includes_attempt_and_last_failure() {
+        let lam = LocalActivityManager::test(1);
+        let orig_sched_time = SystemTime::now();
+        lam.enqueue([NewLocalAct {
+            schedule_cmd: ValidScheduleLA {
+                seq: 1,
+                activity_id: 1.to_string(),
+                attempt: 1,
+                retry_policy: RetryPolicy {
+                    initial_interval: Some(Duration::from_millis(10).into()),
+                    backoff_coefficient: 1.0,
+                    ..Default::default()
+                },
+                local_retry_threshold: Duration::from_secs(500),
+                ..Default::default()
+            },
+            workflow_type: "".to_string(),
+            workflow_exec_info: WorkflowExecution {
+                workflow_id: "".to_string(),
+                run_id: "run_id".to_string(),
+            },
+            schedule_time: orig_sched_time,
+        }
+        .into()]);
+
+        let next = lam.next_pending().await.unwrap().unwrap();
+        let tt = TaskToken(next.task_token);
+        let failure = Failure::application_failure("oh no".to_string(), false);
+        lam.complete(
+            &tt,
+            &LocalActivityExecutionResult::Failed(ActFail {
+                failure: Some(failure.clone()),
+            }),
+        );
+        let retry = lam.next_pending().await.unwrap().unwrap();
+        assert_matches!(
+            retry.variant.unwrap(),
+            activity_task::Variant::Start(Start {
+                attempt: 2,
+                last_failure: Some(f),
+                scheduled_time: Some(st),
+                current_attempt_scheduled_time: Some(cst),
+                ..
+            }) if f == failure && st == orig_sched_time.into() && cst != st
+        );
+    }
+
     #[tokio::test]
     async fn sched_to_start_timeout() {
         let lam = LocalActivityManager::test(1);
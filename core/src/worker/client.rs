@@ -1,37 +1,154 @@
 //! Worker-specific client needs
 
+mod codec;
+mod failures;
+mod limits;
 pub(crate) mod mocks;
+mod offload;
 
+use crate::{replay::ActivationCapturer, worker::payload_limits::PayloadLimits};
+use codec::CodecClient;
+use failures::FailureConvertingClient;
+use limits::PayloadLimitingClient;
+use offload::OffloadingClient;
+
+pub(crate) use limits::as_payload_too_large;
 use std::{
     borrow::Borrow,
     ops::{Deref, DerefMut},
+    sync::Arc,
 };
 use temporal_client::{WorkflowClientTrait, WorkflowTaskCompletion};
+use temporal_sdk_core_api::worker::{
+    FailureConverter, PayloadCodec, PayloadOffloadConfig, WorkerConfig,
+};
 use temporal_sdk_core_protos::{
     coresdk::workflow_commands::QueryResult,
     temporal::api::{
-        common::v1::Payloads, enums::v1::WorkflowTaskFailedCause, failure::v1::Failure,
+        common::v1::Payloads,
+        enums::v1::{ResetReapplyType, WorkflowTaskFailedCause},
+        failure::v1::Failure,
         workflowservice::v1::*,
     },
-    TaskToken,
+    SerializedHistoryPage, TaskToken,
 };
 
 type Result<T, E = tonic::Status> = std::result::Result<T, E>;
 
+/// Returns the server's capabilities, if they are known yet
+type CapabilitiesSource =
+    Box<dyn Fn() -> Option<get_system_info_response::Capabilities> + Send + Sync>;
+
 /// Contains everything a worker needs to interact with the server
 pub(crate) struct WorkerClientBag {
     client: Box<dyn WorkerClient>,
     namespace: String,
+    /// The identity the worker reports to the server
+    identity: String,
+    capabilities: CapabilitiesSource,
+    activation_capture: Option<ActivationCapturer>,
 }
 
 impl WorkerClientBag {
-    pub fn new(client: Box<dyn WorkerClient>, namespace: String) -> Self {
-        Self { client, namespace }
+    pub fn new(
+        client: Box<dyn WorkerClient>,
+        namespace: String,
+        capabilities: Option<get_system_info_response::Capabilities>,
+    ) -> Self {
+        Self {
+            client,
+            namespace,
+            identity: String::new(),
+            capabilities: Box::new(move || capabilities.clone()),
+            activation_capture: None,
+        }
+    }
+
+    /// Read the server's capabilities from `source` whenever they're needed, rather than using the
+    /// ones known when the bag was created. Lazily connected clients only learn them once they
+    /// have connected.
+    pub fn with_capabilities_source(
+        self,
+        source: impl Fn() -> Option<get_system_info_response::Capabilities> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            capabilities: Box::new(source),
+            ..self
+        }
     }
 
     pub fn namespace(&self) -> &str {
         &self.namespace
     }
+
+    pub fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    pub fn set_identity(&mut self, identity: String) {
+        self.identity = identity;
+    }
+
+    /// The server's capabilities, if they are known yet. Behavior which depends on server support
+    /// should check these rather than failing against older servers.
+    pub fn capabilities(&self) -> Option<get_system_info_response::Capabilities> {
+        (self.capabilities)()
+    }
+
+    /// Record what the worker receives from the server and exchanges with lang for the runs the
+    /// capturer selects
+    pub fn set_activation_capture(&mut self, capturer: ActivationCapturer) {
+        self.activation_capture = Some(capturer);
+    }
+
+    pub fn activation_capture(&self) -> Option<&ActivationCapturer> {
+        self.activation_capture.as_ref()
+    }
+
+    /// Apply the failure converter, payload codec, offloading, and payload size limits `config`
+    /// asks for, if any. Limits are enforced closest to the server, on payloads as they are sent.
+    /// Offloading happens next, so offloaded blobs hold encoded payloads, and failures are
+    /// converted before payloads are encoded.
+    pub fn with_payload_transforms(mut self, config: &WorkerConfig) -> Self {
+        if let Some(limits) = PayloadLimits::new(config) {
+            self.client = Box::new(PayloadLimitingClient::new(self.client, limits));
+        }
+        if let Some(offload) = &config.payload_offload {
+            self = self.with_payload_offload(offload.clone());
+        }
+        if let Some(codec) = &config.payload_codec {
+            self = self.with_payload_codec(codec.clone());
+        }
+        if let Some(converter) = &config.failure_converter {
+            self = self.with_failure_converter(converter.clone());
+        }
+        self
+    }
+
+    /// Offload large payloads the worker sends to a blob store, and resolve references to
+    /// offloaded payloads in what it receives
+    pub fn with_payload_offload(self, config: PayloadOffloadConfig) -> Self {
+        Self {
+            client: Box::new(OffloadingClient::new(self.client, config)),
+            ..self
+        }
+    }
+
+    /// Run every payload the worker sends or receives through `codec`
+    pub fn with_payload_codec(self, codec: Arc<dyn PayloadCodec>) -> Self {
+        Self {
+            client: Box::new(CodecClient::new(self.client, codec)),
+            ..self
+        }
+    }
+
+    /// Run every failure the worker sends or receives through `converter`
+    pub fn with_failure_converter(self, converter: Arc<dyn FailureConverter>) -> Self {
+        Self {
+            client: Box::new(FailureConvertingClient::new(self.client, converter)),
+            ..self
+        }
+    }
 }
 impl Deref for WorkerClientBag {
     type Target = dyn WorkerClient;
@@ -53,66 +170,97 @@ where
     fn from(c: T) -> Self {
         use temporal_sdk_core_test_utils::NAMESPACE;
 
-        WorkerClientBag::new(Box::new(c), NAMESPACE.to_string())
+        WorkerClientBag::new(Box::new(c), NAMESPACE.to_string(), None)
     }
 }
 
 /// This trait contains everything workers need to interact with Temporal, and hence provides a
 /// minimal mocking surface. Delegates to [WorkflowClientTrait] so see that for details.
-#[cfg_attr(test, mockall::automock)]
+///
+/// Any [WorkflowClientTrait] implementor is a [WorkerClient]. Alternate implementations, like the
+/// provided [MockWorkerClient] test double, can be used to run a worker without a server via
+/// [crate::init_worker_with_client].
+#[mockall::automock]
 #[async_trait::async_trait]
-pub(crate) trait WorkerClient: Sync + Send {
+pub trait WorkerClient: Sync + Send {
+    /// Poll for a workflow task on the normal or sticky task queue
     async fn poll_workflow_task(
         &self,
         task_queue: String,
         is_sticky: bool,
     ) -> Result<PollWorkflowTaskQueueResponse>;
+    /// Poll for an activity task
     async fn poll_activity_task(
         &self,
         task_queue: String,
         max_tasks_per_sec: Option<f64>,
     ) -> Result<PollActivityTaskQueueResponse>;
+    /// Complete a workflow task, sending the commands it produced
     async fn complete_workflow_task(
         &self,
         request: WorkflowTaskCompletion,
     ) -> Result<RespondWorkflowTaskCompletedResponse>;
+    /// Complete an activity task successfully
     async fn complete_activity_task(
         &self,
         task_token: TaskToken,
         result: Option<Payloads>,
     ) -> Result<RespondActivityTaskCompletedResponse>;
+    /// Record an activity heartbeat
     async fn record_activity_heartbeat(
         &self,
         task_token: TaskToken,
         details: Option<Payloads>,
     ) -> Result<RecordActivityTaskHeartbeatResponse>;
+    /// Report an activity as cancelled
     async fn cancel_activity_task(
         &self,
         task_token: TaskToken,
         details: Option<Payloads>,
     ) -> Result<RespondActivityTaskCanceledResponse>;
+    /// Fail an activity task
     async fn fail_activity_task(
         &self,
         task_token: TaskToken,
         failure: Option<Failure>,
     ) -> Result<RespondActivityTaskFailedResponse>;
+    /// Fail a workflow task
     async fn fail_workflow_task(
         &self,
         task_token: TaskToken,
         cause: WorkflowTaskFailedCause,
         failure: Option<Failure>,
     ) -> Result<RespondWorkflowTaskFailedResponse>;
+    /// Fetch a page of a workflow's history, used when a workflow task's history is paginated.
+    /// The page's events are left serialized, to be decoded as they're consumed.
     async fn get_workflow_execution_history(
         &self,
         workflow_id: String,
         run_id: Option<String>,
         page_token: Vec<u8>,
-    ) -> Result<GetWorkflowExecutionHistoryResponse>;
+    ) -> Result<SerializedHistoryPage>;
+    /// Respond to a legacy query-only workflow task
     async fn respond_legacy_query(
         &self,
         task_token: TaskToken,
         query_result: QueryResult,
     ) -> Result<RespondQueryTaskCompletedResponse>;
+    /// Reset a specific run. The reset is deduplicated per run and reset point.
+    async fn reset_workflow_execution(
+        &self,
+        workflow_id: String,
+        run_id: String,
+        workflow_task_finish_event_id: i64,
+        reason: String,
+        reset_reapply_type: ResetReapplyType,
+    ) -> Result<ResetWorkflowExecutionResponse>;
+    /// List workflow executions matching a visibility query, one page at a time
+    async fn list_workflow_executions(
+        &self,
+        page_size: i32,
+        next_page_token: Vec<u8>,
+        query: String,
+    ) -> Result<ListWorkflowExecutionsResponse>;
 }
 
 #[async_trait::async_trait]
@@ -189,8 +337,8 @@ where
         workflow_id: String,
         run_id: Option<String>,
         page_token: Vec<u8>,
-    ) -> Result<GetWorkflowExecutionHistoryResponse> {
-        WorkflowClientTrait::get_workflow_execution_history(
+    ) -> Result<SerializedHistoryPage> {
+        WorkflowClientTrait::get_serialized_workflow_execution_history(
             self.borrow(),
             workflow_id,
             run_id,
@@ -206,4 +354,61 @@ where
     ) -> Result<RespondQueryTaskCompletedResponse> {
         WorkflowClientTrait::respond_legacy_query(self.borrow(), task_token, query_result).await
     }
+
+    async fn reset_workflow_execution(
+        &self,
+        workflow_id: String,
+        run_id: String,
+        workflow_task_finish_event_id: i64,
+        reason: String,
+        reset_reapply_type: ResetReapplyType,
+    ) -> Result<ResetWorkflowExecutionResponse> {
+        let request_id = format!("{}-reset-{}", run_id, workflow_task_finish_event_id);
+        WorkflowClientTrait::reset_workflow_execution(
+            self.borrow(),
+            workflow_id,
+            Some(run_id),
+            workflow_task_finish_event_id,
+            reason,
+            reset_reapply_type,
+            request_id,
+        )
+        .await
+    }
+
+    async fn list_workflow_executions(
+        &self,
+        page_size: i32,
+        next_page_token: Vec<u8>,
+        query: String,
+    ) -> Result<ListWorkflowExecutionsResponse> {
+        WorkflowClientTrait::list_workflow_executions(
+            self.borrow(),
+            page_size,
+            next_page_token,
+            query,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mocks::mock_workflow_client, *};
+    use once_cell::sync::OnceCell;
+
+    #[test]
+    fn capabilities_learned_after_creation_are_seen() {
+        let cell = Arc::new(OnceCell::new());
+        let source = cell.clone();
+        let bag = WorkerClientBag::from(mock_workflow_client())
+            .with_capabilities_source(move || source.get().cloned());
+        assert!(bag.capabilities().is_none());
+        cell.set(get_system_info_response::Capabilities {
+            internal_error_differentiation: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(bag.capabilities().unwrap().internal_error_differentiation);
+    }
 }
@@ -0,0 +1,404 @@
+use crate::worker::client::{Result, WorkerClient};
+use std::sync::Arc;
+use temporal_client::WorkflowTaskCompletion;
+use temporal_sdk_core_api::worker::PayloadCodec;
+use temporal_sdk_core_protos::{
+    coresdk::workflow_commands::QueryResult,
+    temporal::api::{
+        common::v1::Payloads,
+        enums::v1::{ResetReapplyType, WorkflowTaskFailedCause},
+        failure::v1::Failure,
+        workflowservice::v1::*,
+    },
+    SerializedHistoryPage, TaskToken, VisitPayloads,
+};
+use tracing::warn;
+
+/// Wraps a worker's client, encoding payloads in everything sent to the server and decoding them
+/// in everything received from it with the worker's [PayloadCodec]
+pub(crate) struct CodecClient {
+    inner: Box<dyn WorkerClient>,
+    codec: Arc<dyn PayloadCodec>,
+}
+
+impl CodecClient {
+    pub(crate) fn new(inner: Box<dyn WorkerClient>, codec: Arc<dyn PayloadCodec>) -> Self {
+        Self { inner, codec }
+    }
+
+    fn encode<T: VisitPayloads>(&self, mut msg: T) -> T {
+        msg.visit_payloads_mut(&mut |p| self.codec.encode(p));
+        msg
+    }
+
+    /// Decodes every payload in `msg`. Fails if any can't be decoded, since handing lang a mix of
+    /// decoded and encoded payloads would only produce more confusing errors later.
+    fn decode<T: VisitPayloads>(&self, mut msg: T) -> Result<T> {
+        let mut failure = None;
+        msg.visit_payloads_mut(&mut |p| {
+            if failure.is_none() {
+                failure = self.codec.decode(p).err();
+            }
+        });
+        match failure {
+            None => Ok(msg),
+            Some(e) => Err(tonic::Status::data_loss(format!(
+                "Payload codec failed to decode payload: {:?}",
+                e
+            ))),
+        }
+    }
+
+    /// Decodes a polled workflow task. If it can't be decoded the task is failed with the server,
+    /// so it is retried (possibly by a worker which can decode it) rather than failing the poll,
+    /// and `None` is returned.
+    async fn decode_wft(
+        &self,
+        task: PollWorkflowTaskQueueResponse,
+    ) -> Result<Option<PollWorkflowTaskQueueResponse>> {
+        let task_token = TaskToken(task.task_token.clone());
+        match self.decode(task) {
+            Ok(task) => Ok(Some(task)),
+            Err(e) => {
+                warn!(error = %e.message(), "Failing workflow task whose payloads can't be decoded");
+                self.inner
+                    .fail_workflow_task(
+                        task_token,
+                        WorkflowTaskFailedCause::Unspecified,
+                        Some(Failure::application_failure(e.message().to_string(), false)),
+                    )
+                    .await?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Completions may come back with the next workflow task for the run attached. If it can't be
+    /// decoded it is failed and dropped, the completion itself still succeeded.
+    async fn decode_completion_response(
+        &self,
+        mut resp: RespondWorkflowTaskCompletedResponse,
+    ) -> Result<RespondWorkflowTaskCompletedResponse> {
+        resp.workflow_task = match resp.workflow_task.take() {
+            Some(t) => self.decode_wft(t).await?,
+            None => None,
+        };
+        Ok(resp)
+    }
+}
+
+#[async_trait::async_trait]
+impl WorkerClient for CodecClient {
+    async fn poll_workflow_task(
+        &self,
+        task_queue: String,
+        is_sticky: bool,
+    ) -> Result<PollWorkflowTaskQueueResponse> {
+        loop {
+            let resp = self
+                .inner
+                .poll_workflow_task(task_queue.clone(), is_sticky)
+                .await?;
+            if let Some(resp) = self.decode_wft(resp).await? {
+                return Ok(resp);
+            }
+        }
+    }
+
+    async fn poll_activity_task(
+        &self,
+        task_queue: String,
+        max_tasks_per_sec: Option<f64>,
+    ) -> Result<PollActivityTaskQueueResponse> {
+        // Activities which can't be decoded are failed, as retryable so they may be picked up by a
+        // worker which can decode them, and polling carries on
+        loop {
+            let resp = self
+                .inner
+                .poll_activity_task(task_queue.clone(), max_tasks_per_sec)
+                .await?;
+            let task_token = TaskToken(resp.task_token.clone());
+            match self.decode(resp) {
+                Ok(resp) => return Ok(resp),
+                Err(e) => {
+                    warn!(error = %e.message(), "Failing activity task whose payloads can't be decoded");
+                    self.inner
+                        .fail_activity_task(
+                            task_token,
+                            Some(Failure::application_failure(e.message().to_string(), false)),
+                        )
+                        .await?;
+                }
+            }
+        }
+    }
+
+    async fn complete_workflow_task(
+        &self,
+        mut request: WorkflowTaskCompletion,
+    ) -> Result<RespondWorkflowTaskCompletedResponse> {
+        request.commands = self.encode(request.commands);
+        request.query_responses = self.encode(request.query_responses);
+        let resp = self.inner.complete_workflow_task(request).await?;
+        self.decode_completion_response(resp).await
+    }
+
+    async fn complete_activity_task(
+        &self,
+        task_token: TaskToken,
+        result: Option<Payloads>,
+    ) -> Result<RespondActivityTaskCompletedResponse> {
+        self.inner
+            .complete_activity_task(task_token, self.encode(result))
+            .await
+    }
+
+    async fn record_activity_heartbeat(
+        &self,
+        task_token: TaskToken,
+        details: Option<Payloads>,
+    ) -> Result<RecordActivityTaskHeartbeatResponse> {
+        self.inner
+            .record_activity_heartbeat(task_token, self.encode(details))
+            .await
+    }
+
+    async fn cancel_activity_task(
+        &self,
+        task_token: TaskToken,
+        details: Option<Payloads>,
+    ) -> Result<RespondActivityTaskCanceledResponse> {
+        self.inner
+            .cancel_activity_task(task_token, self.encode(details))
+            .await
+    }
+
+    async fn fail_activity_task(
+        &self,
+        task_token: TaskToken,
+        failure: Option<Failure>,
+    ) -> Result<RespondActivityTaskFailedResponse> {
+        self.inner
+            .fail_activity_task(task_token, self.encode(failure))
+            .await
+    }
+
+    async fn fail_workflow_task(
+        &self,
+        task_token: TaskToken,
+        cause: WorkflowTaskFailedCause,
+        failure: Option<Failure>,
+    ) -> Result<RespondWorkflowTaskFailedResponse> {
+        self.inner
+            .fail_workflow_task(task_token, cause, self.encode(failure))
+            .await
+    }
+
+    async fn get_workflow_execution_history(
+        &self,
+        workflow_id: String,
+        run_id: Option<String>,
+        page_token: Vec<u8>,
+    ) -> Result<SerializedHistoryPage> {
+        let resp = self
+            .inner
+            .get_workflow_execution_history(workflow_id, run_id, page_token)
+            .await?;
+        self.decode(resp)
+    }
+
+    async fn respond_legacy_query(
+        &self,
+        task_token: TaskToken,
+        query_result: QueryResult,
+    ) -> Result<RespondQueryTaskCompletedResponse> {
+        self.inner
+            .respond_legacy_query(task_token, self.encode(query_result))
+            .await
+    }
+
+    async fn reset_workflow_execution(
+        &self,
+        workflow_id: String,
+        run_id: String,
+        workflow_task_finish_event_id: i64,
+        reason: String,
+        reset_reapply_type: ResetReapplyType,
+    ) -> Result<ResetWorkflowExecutionResponse> {
+        self.inner
+            .reset_workflow_execution(
+                workflow_id,
+                run_id,
+                workflow_task_finish_event_id,
+                reason,
+                reset_reapply_type,
+            )
+            .await
+    }
+
+    async fn list_workflow_executions(
+        &self,
+        page_size: i32,
+        next_page_token: Vec<u8>,
+        query: String,
+    ) -> Result<ListWorkflowExecutionsResponse> {
+        self.inner
+            .list_workflow_executions(page_size, next_page_token, query)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        payload_codec::{CompressionCodec, ENCODING_METADATA_KEY},
+        worker::client::mocks::mock_workflow_client,
+    };
+    use temporal_sdk_core_protos::{
+        coresdk::AsJsonPayloadExt,
+        temporal::api::{
+            command::v1::{command, Command, CompleteWorkflowExecutionCommandAttributes},
+            common::v1::Payload,
+            query::v1::WorkflowQuery,
+        },
+    };
+
+    fn big_payload() -> Payload {
+        "abc".repeat(10_000).as_json_payload().unwrap().into()
+    }
+
+    fn payloads(payload: Payload) -> Payloads {
+        Payloads {
+            payloads: vec![payload],
+        }
+    }
+
+    #[tokio::test]
+    async fn encodes_outgoing_and_decodes_incoming_payloads() {
+        let mut mock_client = mock_workflow_client();
+        mock_client
+            .expect_complete_workflow_task()
+            .times(1)
+            .returning(|req| {
+                let mut encodings = vec![];
+                let mut commands = req.commands;
+                commands.visit_payloads_mut(&mut |p| {
+                    encodings.push(p.metadata.get(ENCODING_METADATA_KEY).cloned())
+                });
+                assert_eq!(encodings, vec![Some(b"binary/zstd".to_vec())]);
+                Ok(RespondWorkflowTaskCompletedResponse::default())
+            });
+        mock_client
+            .expect_complete_activity_task()
+            .times(1)
+            .returning(|_, _| Ok(RespondActivityTaskCompletedResponse::default()));
+        mock_client.expect_poll_activity_task().returning(|_, _| {
+            let mut input = Some(payloads(big_payload()));
+            input.visit_payloads_mut(&mut |p| CompressionCodec::default().encode(p));
+            Ok(PollActivityTaskQueueResponse {
+                input,
+                ..Default::default()
+            })
+        });
+        let client = CodecClient::new(Box::new(mock_client), Arc::new(CompressionCodec::default()));
+
+        client
+            .complete_workflow_task(WorkflowTaskCompletion {
+                task_token: TaskToken(vec![1]),
+                commands: vec![Command {
+                    attributes: Some(
+                        command::Attributes::CompleteWorkflowExecutionCommandAttributes(
+                            CompleteWorkflowExecutionCommandAttributes {
+                                result: Some(payloads(big_payload())),
+                            },
+                        ),
+                    ),
+                    ..Default::default()
+                }],
+                sticky_attributes: None,
+                query_responses: vec![],
+                return_new_workflow_task: false,
+                force_create_new_workflow_task: false,
+            })
+            .await
+            .unwrap();
+        // Small payloads are left alone
+        client
+            .complete_activity_task(
+                TaskToken(vec![2]),
+                Some("small".as_json_payload().unwrap().into()),
+            )
+            .await
+            .unwrap();
+        let task = client
+            .poll_activity_task("q".to_string(), None)
+            .await
+            .unwrap();
+        assert_eq!(task.input.unwrap().payloads, vec![big_payload()]);
+    }
+
+    #[tokio::test]
+    async fn undecodable_tasks_are_failed_and_polling_continues() {
+        let mut mock_client = mock_workflow_client();
+        let mut polls = 0;
+        mock_client
+            .expect_poll_activity_task()
+            .returning(move |_, _| {
+                polls += 1;
+                let mut payload = big_payload();
+                CompressionCodec::default().encode(&mut payload);
+                if polls == 1 {
+                    payload.data.truncate(10);
+                }
+                Ok(PollActivityTaskQueueResponse {
+                    task_token: vec![polls],
+                    input: Some(payloads(payload)),
+                    ..Default::default()
+                })
+            });
+        mock_client
+            .expect_fail_activity_task()
+            .withf(|tt, _| tt == &TaskToken(vec![1]))
+            .times(1)
+            .returning(|_, _| Ok(RespondActivityTaskFailedResponse::default()));
+        mock_client
+            .expect_poll_workflow_task()
+            .times(1)
+            .returning(|_, _| {
+                let mut payload = big_payload();
+                CompressionCodec::default().encode(&mut payload);
+                payload.data.truncate(10);
+                Ok(PollWorkflowTaskQueueResponse {
+                    task_token: vec![3],
+                    query: Some(WorkflowQuery {
+                        query_args: Some(payloads(payload)),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })
+            });
+        mock_client
+            .expect_poll_workflow_task()
+            .times(1)
+            .returning(|_, _| Ok(PollWorkflowTaskQueueResponse::default()));
+        mock_client
+            .expect_fail_workflow_task()
+            .withf(|tt, _, _| tt == &TaskToken(vec![3]))
+            .times(1)
+            .returning(|_, _, _| Ok(RespondWorkflowTaskFailedResponse::default()));
+        let client = CodecClient::new(Box::new(mock_client), Arc::new(CompressionCodec::default()));
+
+        let task = client
+            .poll_activity_task("q".to_string(), None)
+            .await
+            .unwrap();
+        assert_eq!(task.task_token, vec![2]);
+        assert_eq!(task.input.unwrap().payloads, vec![big_payload()]);
+        let task = client
+            .poll_workflow_task("q".to_string(), false)
+            .await
+            .unwrap();
+        assert_eq!(task, PollWorkflowTaskQueueResponse::default());
+    }
+}
@@ -0,0 +1,267 @@
+use crate::worker::client::{Result, WorkerClient};
+use std::sync::Arc;
+use temporal_client::WorkflowTaskCompletion;
+use temporal_sdk_core_api::worker::FailureConverter;
+use temporal_sdk_core_protos::{
+    coresdk::workflow_commands::QueryResult,
+    temporal::api::{
+        common::v1::Payloads,
+        enums::v1::{ResetReapplyType, WorkflowTaskFailedCause},
+        failure::v1::Failure,
+        workflowservice::v1::*,
+    },
+    SerializedHistoryPage, TaskToken, VisitFailures,
+};
+
+/// Wraps a worker's client, converting failures in everything sent to the server and converting
+/// them back in everything received from it with the worker's [FailureConverter]
+pub(crate) struct FailureConvertingClient {
+    inner: Box<dyn WorkerClient>,
+    converter: Arc<dyn FailureConverter>,
+}
+
+impl FailureConvertingClient {
+    pub(crate) fn new(inner: Box<dyn WorkerClient>, converter: Arc<dyn FailureConverter>) -> Self {
+        Self { inner, converter }
+    }
+
+    fn encode_to_server<T: VisitFailures>(&self, mut msg: T) -> T {
+        msg.visit_failures_mut(&mut |f| self.converter.encode_to_server(f));
+        msg
+    }
+
+    /// Converts every failure in `msg` back. Fails if any can't be, for the same reasons payloads
+    /// which can't be decoded fail.
+    fn decode_from_server<T: VisitFailures>(&self, mut msg: T) -> Result<T> {
+        let mut err = None;
+        msg.visit_failures_mut(&mut |f| {
+            if err.is_none() {
+                err = self.converter.decode_from_server(f).err();
+            }
+        });
+        match err {
+            None => Ok(msg),
+            Some(e) => Err(tonic::Status::data_loss(format!(
+                "Failure converter failed to convert failure: {:?}",
+                e
+            ))),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl WorkerClient for FailureConvertingClient {
+    async fn poll_workflow_task(
+        &self,
+        task_queue: String,
+        is_sticky: bool,
+    ) -> Result<PollWorkflowTaskQueueResponse> {
+        let resp = self.inner.poll_workflow_task(task_queue, is_sticky).await?;
+        self.decode_from_server(resp)
+    }
+
+    async fn poll_activity_task(
+        &self,
+        task_queue: String,
+        max_tasks_per_sec: Option<f64>,
+    ) -> Result<PollActivityTaskQueueResponse> {
+        self.inner
+            .poll_activity_task(task_queue, max_tasks_per_sec)
+            .await
+    }
+
+    async fn complete_workflow_task(
+        &self,
+        mut request: WorkflowTaskCompletion,
+    ) -> Result<RespondWorkflowTaskCompletedResponse> {
+        request.commands = self.encode_to_server(request.commands);
+        request.query_responses = self.encode_to_server(request.query_responses);
+        let mut resp = self.inner.complete_workflow_task(request).await?;
+        resp.workflow_task = resp
+            .workflow_task
+            .map(|t| self.decode_from_server(t))
+            .transpose()?;
+        Ok(resp)
+    }
+
+    async fn complete_activity_task(
+        &self,
+        task_token: TaskToken,
+        result: Option<Payloads>,
+    ) -> Result<RespondActivityTaskCompletedResponse> {
+        self.inner.complete_activity_task(task_token, result).await
+    }
+
+    async fn record_activity_heartbeat(
+        &self,
+        task_token: TaskToken,
+        details: Option<Payloads>,
+    ) -> Result<RecordActivityTaskHeartbeatResponse> {
+        self.inner
+            .record_activity_heartbeat(task_token, details)
+            .await
+    }
+
+    async fn cancel_activity_task(
+        &self,
+        task_token: TaskToken,
+        details: Option<Payloads>,
+    ) -> Result<RespondActivityTaskCanceledResponse> {
+        self.inner.cancel_activity_task(task_token, details).await
+    }
+
+    async fn fail_activity_task(
+        &self,
+        task_token: TaskToken,
+        failure: Option<Failure>,
+    ) -> Result<RespondActivityTaskFailedResponse> {
+        self.inner
+            .fail_activity_task(task_token, self.encode_to_server(failure))
+            .await
+    }
+
+    async fn fail_workflow_task(
+        &self,
+        task_token: TaskToken,
+        cause: WorkflowTaskFailedCause,
+        failure: Option<Failure>,
+    ) -> Result<RespondWorkflowTaskFailedResponse> {
+        self.inner
+            .fail_workflow_task(task_token, cause, self.encode_to_server(failure))
+            .await
+    }
+
+    async fn get_workflow_execution_history(
+        &self,
+        workflow_id: String,
+        run_id: Option<String>,
+        page_token: Vec<u8>,
+    ) -> Result<SerializedHistoryPage> {
+        let resp = self
+            .inner
+            .get_workflow_execution_history(workflow_id, run_id, page_token)
+            .await?;
+        self.decode_from_server(resp)
+    }
+
+    async fn respond_legacy_query(
+        &self,
+        task_token: TaskToken,
+        query_result: QueryResult,
+    ) -> Result<RespondQueryTaskCompletedResponse> {
+        self.inner
+            .respond_legacy_query(task_token, self.encode_to_server(query_result))
+            .await
+    }
+
+    async fn reset_workflow_execution(
+        &self,
+        workflow_id: String,
+        run_id: String,
+        workflow_task_finish_event_id: i64,
+        reason: String,
+        reset_reapply_type: ResetReapplyType,
+    ) -> Result<ResetWorkflowExecutionResponse> {
+        self.inner
+            .reset_workflow_execution(
+                workflow_id,
+                run_id,
+                workflow_task_finish_event_id,
+                reason,
+                reset_reapply_type,
+            )
+            .await
+    }
+
+    async fn list_workflow_executions(
+        &self,
+        page_size: i32,
+        next_page_token: Vec<u8>,
+        query: String,
+    ) -> Result<ListWorkflowExecutionsResponse> {
+        self.inner
+            .list_workflow_executions(page_size, next_page_token, query)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::client::mocks::mock_workflow_client;
+    use temporal_sdk_core_protos::temporal::api::history::v1::{
+        history_event, ActivityTaskFailedEventAttributes, History, HistoryEvent,
+    };
+
+    /// Reverses failure messages on their way to the server
+    #[derive(Debug)]
+    struct ReversingConverter;
+
+    impl FailureConverter for ReversingConverter {
+        fn encode_to_server(&self, failure: &mut Failure) {
+            failure.message = failure.message.chars().rev().collect();
+        }
+
+        fn decode_from_server(&self, failure: &mut Failure) -> Result<(), anyhow::Error> {
+            self.encode_to_server(failure);
+            Ok(())
+        }
+    }
+
+    fn failure() -> Failure {
+        Failure {
+            message: "oh no".to_string(),
+            stack_trace: "at activity()".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn converts_outgoing_and_incoming_failures() {
+        let converter = Arc::new(ReversingConverter);
+        let mut mock_client = mock_workflow_client();
+        mock_client
+            .expect_fail_activity_task()
+            .times(1)
+            .returning(|_, f| {
+                let f = f.unwrap();
+                assert_eq!(f.message, "on ho");
+                Ok(Default::default())
+            });
+        let mut converted = failure();
+        converter.encode_to_server(&mut converted);
+        mock_client
+            .expect_poll_workflow_task()
+            .returning(move |_, _| {
+                Ok(PollWorkflowTaskQueueResponse {
+                    history: Some(History {
+                        events: vec![HistoryEvent {
+                            attributes: Some(
+                                history_event::Attributes::ActivityTaskFailedEventAttributes(
+                                    ActivityTaskFailedEventAttributes {
+                                        failure: Some(converted.clone()),
+                                        ..Default::default()
+                                    },
+                                ),
+                            ),
+                            ..Default::default()
+                        }],
+                    }),
+                    ..Default::default()
+                })
+            });
+        let client = FailureConvertingClient::new(Box::new(mock_client), converter);
+
+        client
+            .fail_activity_task(TaskToken(vec![1]), Some(failure()))
+            .await
+            .unwrap();
+        let mut resp = client
+            .poll_workflow_task("q".to_string(), false)
+            .await
+            .unwrap();
+        let mut seen = vec![];
+        resp.visit_failures_mut(&mut |f| seen.push(f.clone()));
+        assert_eq!(seen, vec![failure()]);
+    }
+}
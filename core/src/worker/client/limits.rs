@@ -0,0 +1,310 @@
+use crate::worker::{
+    client::{Result, WorkerClient},
+    payload_limits::PayloadLimits,
+};
+use temporal_client::WorkflowTaskCompletion;
+use temporal_sdk_core_api::errors::PayloadTooLarge;
+use temporal_sdk_core_protos::{
+    coresdk::workflow_commands::QueryResult,
+    temporal::api::{
+        common::v1::Payloads,
+        enums::v1::{ResetReapplyType, WorkflowTaskFailedCause},
+        failure::v1::Failure,
+        workflowservice::v1::*,
+    },
+    SerializedHistoryPage, TaskToken,
+};
+
+/// Wraps a worker's client closest to the server, enforcing [PayloadLimits] on what is sent once
+/// every other payload transform has been applied.
+///
+/// Workflow task completions which are too large are failed instead, and the error returned is
+/// [payload_too_large_status] so the worker evicts the run. Activity results which are too large
+/// fail the activity instead.
+pub(crate) struct PayloadLimitingClient {
+    inner: Box<dyn WorkerClient>,
+    limits: PayloadLimits,
+}
+
+impl PayloadLimitingClient {
+    pub(crate) fn new(inner: Box<dyn WorkerClient>, limits: PayloadLimits) -> Self {
+        Self { inner, limits }
+    }
+}
+
+/// The error returned for completions whose payloads are too large, after the task was failed
+pub(crate) fn payload_too_large_status(err: &PayloadTooLarge) -> tonic::Status {
+    tonic::Status::failed_precondition(err.to_string())
+}
+
+/// Returns the message of errors made by [payload_too_large_status]
+pub(crate) fn as_payload_too_large(status: &tonic::Status) -> Option<&str> {
+    Some(status.message()).filter(|m| {
+        status.code() == tonic::Code::FailedPrecondition && m.starts_with("PayloadTooLarge")
+    })
+}
+
+#[async_trait::async_trait]
+impl WorkerClient for PayloadLimitingClient {
+    async fn poll_workflow_task(
+        &self,
+        task_queue: String,
+        is_sticky: bool,
+    ) -> Result<PollWorkflowTaskQueueResponse> {
+        self.inner.poll_workflow_task(task_queue, is_sticky).await
+    }
+
+    async fn poll_activity_task(
+        &self,
+        task_queue: String,
+        max_tasks_per_sec: Option<f64>,
+    ) -> Result<PollActivityTaskQueueResponse> {
+        self.inner
+            .poll_activity_task(task_queue, max_tasks_per_sec)
+            .await
+    }
+
+    async fn complete_workflow_task(
+        &self,
+        mut request: WorkflowTaskCompletion,
+    ) -> Result<RespondWorkflowTaskCompletedResponse> {
+        if let Err(e) = self.limits.check_commands(&mut request.commands) {
+            warn!(error=%e, "Failing workflow task instead of sending commands");
+            self.inner
+                .fail_workflow_task(
+                    request.task_token,
+                    WorkflowTaskFailedCause::Unspecified,
+                    Some(Failure::application_failure(e.to_string(), false)),
+                )
+                .await?;
+            return Err(payload_too_large_status(&e));
+        }
+        request
+            .query_responses
+            .iter_mut()
+            .for_each(|qr| self.limits.limit_query_response(qr));
+        self.inner.complete_workflow_task(request).await
+    }
+
+    async fn complete_activity_task(
+        &self,
+        task_token: TaskToken,
+        mut result: Option<Payloads>,
+    ) -> Result<RespondActivityTaskCompletedResponse> {
+        if let Err(e) = self
+            .limits
+            .check(&mut result, || "activity result".to_string())
+        {
+            warn!(task_token=%task_token, error=%e, "Failing activity instead of reporting its result");
+            self.inner
+                .fail_activity_task(
+                    task_token,
+                    Some(Failure::application_failure(e.to_string(), false)),
+                )
+                .await?;
+            return Ok(RespondActivityTaskCompletedResponse::default());
+        }
+        self.inner.complete_activity_task(task_token, result).await
+    }
+
+    async fn record_activity_heartbeat(
+        &self,
+        task_token: TaskToken,
+        details: Option<Payloads>,
+    ) -> Result<RecordActivityTaskHeartbeatResponse> {
+        self.inner
+            .record_activity_heartbeat(task_token, details)
+            .await
+    }
+
+    async fn cancel_activity_task(
+        &self,
+        task_token: TaskToken,
+        details: Option<Payloads>,
+    ) -> Result<RespondActivityTaskCanceledResponse> {
+        self.inner.cancel_activity_task(task_token, details).await
+    }
+
+    async fn fail_activity_task(
+        &self,
+        task_token: TaskToken,
+        failure: Option<Failure>,
+    ) -> Result<RespondActivityTaskFailedResponse> {
+        self.inner.fail_activity_task(task_token, failure).await
+    }
+
+    async fn fail_workflow_task(
+        &self,
+        task_token: TaskToken,
+        cause: WorkflowTaskFailedCause,
+        failure: Option<Failure>,
+    ) -> Result<RespondWorkflowTaskFailedResponse> {
+        self.inner
+            .fail_workflow_task(task_token, cause, failure)
+            .await
+    }
+
+    async fn get_workflow_execution_history(
+        &self,
+        workflow_id: String,
+        run_id: Option<String>,
+        page_token: Vec<u8>,
+    ) -> Result<SerializedHistoryPage> {
+        self.inner
+            .get_workflow_execution_history(workflow_id, run_id, page_token)
+            .await
+    }
+
+    async fn respond_legacy_query(
+        &self,
+        task_token: TaskToken,
+        mut query_result: QueryResult,
+    ) -> Result<RespondQueryTaskCompletedResponse> {
+        self.limits.limit_query_response(&mut query_result);
+        self.inner
+            .respond_legacy_query(task_token, query_result)
+            .await
+    }
+
+    async fn reset_workflow_execution(
+        &self,
+        workflow_id: String,
+        run_id: String,
+        workflow_task_finish_event_id: i64,
+        reason: String,
+        reset_reapply_type: ResetReapplyType,
+    ) -> Result<ResetWorkflowExecutionResponse> {
+        self.inner
+            .reset_workflow_execution(
+                workflow_id,
+                run_id,
+                workflow_task_finish_event_id,
+                reason,
+                reset_reapply_type,
+            )
+            .await
+    }
+
+    async fn list_workflow_executions(
+        &self,
+        page_size: i32,
+        next_page_token: Vec<u8>,
+        query: String,
+    ) -> Result<ListWorkflowExecutionsResponse> {
+        self.inner
+            .list_workflow_executions(page_size, next_page_token, query)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        payload_codec::{CompressionAlgorithm, CompressionCodec},
+        payload_offload::InMemoryBlobStore,
+        test_help::test_worker_cfg,
+        worker::client::{mocks::mock_workflow_client, WorkerClientBag},
+    };
+    use std::sync::Arc;
+    use temporal_sdk_core_api::worker::{PayloadOffloadConfig, WorkerConfig};
+    use temporal_sdk_core_protos::{
+        coresdk::AsJsonPayloadExt,
+        temporal::api::command::v1::{command, Command, ScheduleActivityTaskCommandAttributes},
+    };
+
+    fn schedule_act_cmd(input_len: usize) -> Command {
+        Command {
+            command_type: 0,
+            attributes: Some(command::Attributes::ScheduleActivityTaskCommandAttributes(
+                ScheduleActivityTaskCommandAttributes {
+                    activity_id: "act-1".to_string(),
+                    input: Some(Payloads {
+                        payloads: vec!["a".repeat(input_len).as_json_payload().unwrap().into()],
+                    }),
+                    ..Default::default()
+                },
+            )),
+        }
+    }
+
+    fn completion(cmd: Command) -> WorkflowTaskCompletion {
+        WorkflowTaskCompletion {
+            task_token: TaskToken(vec![1]),
+            commands: vec![cmd],
+            sticky_attributes: None,
+            query_responses: vec![],
+            return_new_workflow_task: false,
+            force_create_new_workflow_task: false,
+        }
+    }
+
+    /// Completes a workflow task with an activity taking `input_len` bytes of input, through every
+    /// transform `mutator` configures, returning whether it was sent
+    async fn sent_through_transforms(
+        input_len: usize,
+        mutator: impl FnOnce(&mut WorkerConfig),
+    ) -> bool {
+        let mut cfg = test_worker_cfg().build().unwrap();
+        cfg.payload_size_limit_bytes = Some(10_000);
+        mutator(&mut cfg);
+        let mut mock_client = mock_workflow_client();
+        mock_client
+            .expect_complete_workflow_task()
+            .returning(|_| Ok(Default::default()));
+        mock_client
+            .expect_fail_workflow_task()
+            .returning(|_, _, _| Ok(Default::default()));
+        let client = WorkerClientBag::from(mock_client).with_payload_transforms(&cfg);
+        match client
+            .complete_workflow_task(completion(schedule_act_cmd(input_len)))
+            .await
+        {
+            Ok(_) => true,
+            Err(e) => {
+                assert!(as_payload_too_large(&e).is_some());
+                false
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_completions_fail_wft() {
+        assert!(!sent_through_transforms(20_000, |_| {}).await);
+        assert!(sent_through_transforms(100, |_| {}).await);
+    }
+
+    #[tokio::test]
+    async fn measured_after_codec() {
+        // Compresses far below the limit
+        assert!(
+            sent_through_transforms(100_000, |c| {
+                c.payload_codec = Some(Arc::new(CompressionCodec::new(
+                    CompressionAlgorithm::Zstd,
+                    1024,
+                )));
+            })
+            .await
+        );
+    }
+
+    #[rstest::rstest]
+    #[case::offloaded(100_000, true)]
+    #[case::below_offload_threshold(15_000, false)]
+    #[tokio::test]
+    async fn offloaded_payloads_measured_as_references(
+        #[case] input_len: usize,
+        #[case] sent: bool,
+    ) {
+        assert_eq!(
+            sent_through_transforms(input_len, |c| {
+                c.payload_offload = Some(PayloadOffloadConfig {
+                    store: Arc::new(InMemoryBlobStore::default()),
+                    threshold_bytes: 20_000,
+                });
+            })
+            .await,
+            sent
+        );
+    }
+}
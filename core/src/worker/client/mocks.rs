@@ -73,7 +73,7 @@ mockall::mock! {
             workflow_id: String,
             run_id: Option<String>,
             page_token: Vec<u8>
-        ) -> impl Future<Output = Result<GetWorkflowExecutionHistoryResponse>> + Send + 'b
+        ) -> impl Future<Output = Result<SerializedHistoryPage>> + Send + 'b
             where 'a: 'b, Self: 'b;
 
         fn respond_legacy_query<'a, 'b>(
@@ -82,5 +82,23 @@ mockall::mock! {
             query_result: QueryResult,
         ) -> impl Future<Output = Result<RespondQueryTaskCompletedResponse>> + Send + 'b
             where 'a: 'b, Self: 'b;
+
+        fn reset_workflow_execution<'a, 'b>(
+            &self,
+            workflow_id: String,
+            run_id: String,
+            workflow_task_finish_event_id: i64,
+            reason: String,
+            reset_reapply_type: ResetReapplyType,
+        ) -> impl Future<Output = Result<ResetWorkflowExecutionResponse>> + Send + 'b
+            where 'a: 'b, Self: 'b;
+
+        fn list_workflow_executions<'a, 'b>(
+            &self,
+            page_size: i32,
+            next_page_token: Vec<u8>,
+            query: String,
+        ) -> impl Future<Output = Result<ListWorkflowExecutionsResponse>> + Send + 'b
+            where 'a: 'b, Self: 'b;
     }
 }
@@ -0,0 +1,322 @@
+use crate::{
+    payload_offload::{blob_key, reference_payload, referenced_key},
+    worker::client::{Result, WorkerClient},
+};
+use futures::future::try_join_all;
+use prost::Message;
+use std::collections::HashMap;
+use temporal_client::WorkflowTaskCompletion;
+use temporal_sdk_core_api::worker::PayloadOffloadConfig;
+use temporal_sdk_core_protos::{
+    coresdk::workflow_commands::QueryResult,
+    temporal::api::{
+        common::v1::{Payload, Payloads},
+        enums::v1::{ResetReapplyType, WorkflowTaskFailedCause},
+        failure::v1::Failure,
+        workflowservice::v1::*,
+    },
+    SerializedHistoryPage, TaskToken, VisitPayloads,
+};
+
+/// Wraps a worker's client, offloading large payloads in everything sent to the server to a blob
+/// store, and resolving references to offloaded payloads in everything received from it. See
+/// [PayloadOffloadConfig].
+pub(crate) struct OffloadingClient {
+    inner: Box<dyn WorkerClient>,
+    config: PayloadOffloadConfig,
+}
+
+impl OffloadingClient {
+    pub(crate) fn new(inner: Box<dyn WorkerClient>, config: PayloadOffloadConfig) -> Self {
+        Self { inner, config }
+    }
+
+    /// Replace large payloads in `msg` with references, once they have been stored
+    async fn offload<T: VisitPayloads>(&self, mut msg: T) -> Result<T> {
+        let mut blobs = HashMap::new();
+        msg.visit_payloads_mut(&mut |p| {
+            if p.encoded_len() < self.config.threshold_bytes {
+                return;
+            }
+            let blob = p.encode_to_vec();
+            let key = blob_key(&blob);
+            *p = reference_payload(key.clone());
+            blobs.insert(key, blob);
+        });
+        try_join_all(
+            blobs
+                .into_iter()
+                .map(|(key, blob)| async move { self.config.store.put(&key, blob).await }),
+        )
+        .await
+        .map_err(|e| {
+            tonic::Status::unavailable(format!("Failed to offload payload to blob store: {:?}", e))
+        })?;
+        Ok(msg)
+    }
+
+    /// Replace references in `msg` with the payloads they refer to
+    async fn resolve<T: VisitPayloads>(&self, mut msg: T) -> Result<T> {
+        let mut keys = vec![];
+        msg.visit_payloads_mut(&mut |p| keys.extend(referenced_key(p)));
+        if keys.is_empty() {
+            return Ok(msg);
+        }
+        keys.sort();
+        keys.dedup();
+        let fetched = try_join_all(keys.into_iter().map(|key| async move {
+            let blob = self.config.store.get(&key).await?;
+            let payload = Payload::decode(blob.as_slice())?;
+            Ok::<_, anyhow::Error>((key, payload))
+        }))
+        .await
+        .map_err(|e| {
+            tonic::Status::unavailable(format!(
+                "Failed to fetch offloaded payload from blob store: {:?}",
+                e
+            ))
+        })?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+        msg.visit_payloads_mut(&mut |p| {
+            if let Some(payload) = referenced_key(p).and_then(|k| fetched.get(&k)) {
+                *p = payload.clone();
+            }
+        });
+        Ok(msg)
+    }
+}
+
+#[async_trait::async_trait]
+impl WorkerClient for OffloadingClient {
+    async fn poll_workflow_task(
+        &self,
+        task_queue: String,
+        is_sticky: bool,
+    ) -> Result<PollWorkflowTaskQueueResponse> {
+        let resp = self.inner.poll_workflow_task(task_queue, is_sticky).await?;
+        self.resolve(resp).await
+    }
+
+    async fn poll_activity_task(
+        &self,
+        task_queue: String,
+        max_tasks_per_sec: Option<f64>,
+    ) -> Result<PollActivityTaskQueueResponse> {
+        let resp = self
+            .inner
+            .poll_activity_task(task_queue, max_tasks_per_sec)
+            .await?;
+        self.resolve(resp).await
+    }
+
+    async fn complete_workflow_task(
+        &self,
+        mut request: WorkflowTaskCompletion,
+    ) -> Result<RespondWorkflowTaskCompletedResponse> {
+        request.commands = self.offload(request.commands).await?;
+        request.query_responses = self.offload(request.query_responses).await?;
+        let mut resp = self.inner.complete_workflow_task(request).await?;
+        if let Some(wft) = resp.workflow_task.take() {
+            resp.workflow_task = Some(self.resolve(wft).await?);
+        }
+        Ok(resp)
+    }
+
+    async fn complete_activity_task(
+        &self,
+        task_token: TaskToken,
+        result: Option<Payloads>,
+    ) -> Result<RespondActivityTaskCompletedResponse> {
+        let result = self.offload(result).await?;
+        self.inner.complete_activity_task(task_token, result).await
+    }
+
+    async fn record_activity_heartbeat(
+        &self,
+        task_token: TaskToken,
+        details: Option<Payloads>,
+    ) -> Result<RecordActivityTaskHeartbeatResponse> {
+        let details = self.offload(details).await?;
+        self.inner
+            .record_activity_heartbeat(task_token, details)
+            .await
+    }
+
+    async fn cancel_activity_task(
+        &self,
+        task_token: TaskToken,
+        details: Option<Payloads>,
+    ) -> Result<RespondActivityTaskCanceledResponse> {
+        let details = self.offload(details).await?;
+        self.inner.cancel_activity_task(task_token, details).await
+    }
+
+    async fn fail_activity_task(
+        &self,
+        task_token: TaskToken,
+        failure: Option<Failure>,
+    ) -> Result<RespondActivityTaskFailedResponse> {
+        let failure = self.offload(failure).await?;
+        self.inner.fail_activity_task(task_token, failure).await
+    }
+
+    async fn fail_workflow_task(
+        &self,
+        task_token: TaskToken,
+        cause: WorkflowTaskFailedCause,
+        failure: Option<Failure>,
+    ) -> Result<RespondWorkflowTaskFailedResponse> {
+        let failure = self.offload(failure).await?;
+        self.inner
+            .fail_workflow_task(task_token, cause, failure)
+            .await
+    }
+
+    async fn get_workflow_execution_history(
+        &self,
+        workflow_id: String,
+        run_id: Option<String>,
+        page_token: Vec<u8>,
+    ) -> Result<SerializedHistoryPage> {
+        let resp = self
+            .inner
+            .get_workflow_execution_history(workflow_id, run_id, page_token)
+            .await?;
+        self.resolve(resp).await
+    }
+
+    async fn respond_legacy_query(
+        &self,
+        task_token: TaskToken,
+        query_result: QueryResult,
+    ) -> Result<RespondQueryTaskCompletedResponse> {
+        let query_result = self.offload(query_result).await?;
+        self.inner
+            .respond_legacy_query(task_token, query_result)
+            .await
+    }
+
+    async fn reset_workflow_execution(
+        &self,
+        workflow_id: String,
+        run_id: String,
+        workflow_task_finish_event_id: i64,
+        reason: String,
+        reset_reapply_type: ResetReapplyType,
+    ) -> Result<ResetWorkflowExecutionResponse> {
+        self.inner
+            .reset_workflow_execution(
+                workflow_id,
+                run_id,
+                workflow_task_finish_event_id,
+                reason,
+                reset_reapply_type,
+            )
+            .await
+    }
+
+    async fn list_workflow_executions(
+        &self,
+        page_size: i32,
+        next_page_token: Vec<u8>,
+        query: String,
+    ) -> Result<ListWorkflowExecutionsResponse> {
+        self.inner
+            .list_workflow_executions(page_size, next_page_token, query)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{payload_offload::InMemoryBlobStore, worker::client::mocks::mock_workflow_client};
+    use std::sync::Arc;
+    use temporal_sdk_core_protos::coresdk::AsJsonPayloadExt;
+
+    fn payloads(len: usize) -> Payloads {
+        Payloads {
+            payloads: vec!["a".repeat(len).as_json_payload().unwrap().into()],
+        }
+    }
+
+    #[tokio::test]
+    async fn large_payloads_offloaded_and_resolved() {
+        let store = Arc::new(InMemoryBlobStore::default());
+        let mut mock_client = mock_workflow_client();
+        mock_client
+            .expect_complete_activity_task()
+            .times(2)
+            .returning(|_, res| {
+                let payload = &res.unwrap().payloads[0];
+                assert!(payload.data.len() < 100);
+                Ok(RespondActivityTaskCompletedResponse::default())
+            });
+        // The server hands back whatever reference it was given
+        let reference = Arc::new(parking_lot::Mutex::new(None));
+        let reference_c = reference.clone();
+        mock_client
+            .expect_poll_activity_task()
+            .returning(move |_, _| {
+                Ok(PollActivityTaskQueueResponse {
+                    input: reference_c.lock().clone(),
+                    heartbeat_details: Some(payloads(10)),
+                    ..Default::default()
+                })
+            });
+        let client = OffloadingClient::new(
+            Box::new(mock_client),
+            PayloadOffloadConfig {
+                store: store.clone(),
+                threshold_bytes: 1024,
+            },
+        );
+
+        let offloaded = client.offload(Some(payloads(2048))).await.unwrap();
+        assert_eq!(store.len(), 1);
+        *reference.lock() = offloaded;
+        client
+            .complete_activity_task(TaskToken(vec![1]), Some(payloads(10)))
+            .await
+            .unwrap();
+        // Identical payloads share a blob
+        client
+            .complete_activity_task(TaskToken(vec![2]), Some(payloads(2048)))
+            .await
+            .unwrap();
+        assert_eq!(store.len(), 1);
+
+        let task = client
+            .poll_activity_task("q".to_string(), None)
+            .await
+            .unwrap();
+        assert_eq!(task.input, Some(payloads(2048)));
+        assert_eq!(task.heartbeat_details, Some(payloads(10)));
+    }
+
+    #[tokio::test]
+    async fn missing_blob_fails_poll() {
+        let mut mock_client = mock_workflow_client();
+        mock_client.expect_poll_activity_task().returning(|_, _| {
+            Ok(PollActivityTaskQueueResponse {
+                input: Some(Payloads {
+                    payloads: vec![reference_payload(blob_key(b"gone"))],
+                }),
+                ..Default::default()
+            })
+        });
+        let client = OffloadingClient::new(
+            Box::new(mock_client),
+            PayloadOffloadConfig {
+                store: Arc::new(InMemoryBlobStore::default()),
+                threshold_bytes: 1024,
+            },
+        );
+        let err = client
+            .poll_activity_task("q".to_string(), None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unavailable);
+    }
+}
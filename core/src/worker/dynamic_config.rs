@@ -0,0 +1,91 @@
+use parking_lot::Mutex;
+use std::{sync::Arc, time::Duration};
+use temporal_sdk_core_api::worker::{DynamicConfig, DynamicConfigValues};
+use tokio::sync::{watch, Notify};
+use tokio_util::sync::CancellationToken;
+
+/// Tracks the values reported by a worker's [DynamicConfig], and which of them the worker has
+/// applied. A background task reads the provider, and the worker applies whatever changed the next
+/// time it polls, since that's where the things being tuned live.
+pub(crate) struct DynamicConfigState {
+    latest: watch::Receiver<DynamicConfigValues>,
+    applied: Mutex<DynamicConfigValues>,
+}
+
+impl DynamicConfigState {
+    /// Starts watching the provider until `shutdown` is cancelled. `changed` is notified every
+    /// time the provider reports new values, so that blocked polls can apply them.
+    pub(crate) fn start(
+        provider: Arc<dyn DynamicConfig>,
+        poll_interval: Duration,
+        changed: Arc<Notify>,
+        shutdown: CancellationToken,
+    ) -> Self {
+        let (tx, latest) = watch::channel(DynamicConfigValues::default());
+        tokio::spawn(async move {
+            loop {
+                let current = provider.current();
+                if tx.send_if_modified(|vals| {
+                    let differs = *vals != current;
+                    *vals = current;
+                    differs
+                }) {
+                    debug!(values = ?*tx.borrow(), "Dynamic config changed");
+                    changed.notify_waiters();
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(poll_interval) => {}
+                    _ = provider.changed() => {}
+                    _ = shutdown.cancelled() => break,
+                }
+            }
+        });
+        Self {
+            latest,
+            applied: Default::default(),
+        }
+    }
+
+    /// Returns the values which changed since the last call, with unchanged ones unset, or `None`
+    /// if nothing changed
+    pub(crate) fn take_changes(&self) -> Option<DynamicConfigValues> {
+        let latest = self.latest.borrow().clone();
+        let mut applied = self.applied.lock();
+        if *applied == latest {
+            return None;
+        }
+        fn diff<T: PartialEq + Copy>(new: Option<T>, old: Option<T>) -> Option<T> {
+            new.filter(|n| Some(*n) != old)
+        }
+        let changes = DynamicConfigValues {
+            max_outstanding_workflow_tasks: diff(
+                latest.max_outstanding_workflow_tasks,
+                applied.max_outstanding_workflow_tasks,
+            ),
+            max_outstanding_activities: diff(
+                latest.max_outstanding_activities,
+                applied.max_outstanding_activities,
+            ),
+            max_cached_workflows: diff(latest.max_cached_workflows, applied.max_cached_workflows),
+            max_concurrent_wft_polls: diff(
+                latest.max_concurrent_wft_polls,
+                applied.max_concurrent_wft_polls,
+            ),
+            max_concurrent_at_polls: diff(
+                latest.max_concurrent_at_polls,
+                applied.max_concurrent_at_polls,
+            ),
+            max_task_queue_activities_per_second: diff(
+                latest.max_task_queue_activities_per_second,
+                applied.max_task_queue_activities_per_second,
+            ),
+        };
+        *applied = latest;
+        Some(changes)
+    }
+
+    /// The values most recently applied
+    pub(crate) fn applied(&self) -> DynamicConfigValues {
+        self.applied.lock().clone()
+    }
+}
@@ -1,5 +1,7 @@
 mod activities;
 pub(crate) mod client;
+mod dynamic_config;
+pub(crate) mod payload_limits;
 mod wft_delivery;
 
 pub use temporal_sdk_core_api::worker::{WorkerConfig, WorkerConfigBuilder};
@@ -13,25 +15,29 @@ use crate::{
     abstractions::MeteredSemaphore,
     errors::CompleteWfError,
     pollers::{
-        new_activity_task_buffer, new_workflow_task_buffer, BoxedActPoller, BoxedWFPoller, Poller,
-        WorkflowTaskPoller,
+        new_activity_task_buffer, new_workflow_task_buffer, ActivityRateLimit, BoxedActPoller,
+        BoxedWFPoller, PollWorkflowTaskBuffer, Poller, WorkflowTaskPoller,
     },
     protosext::{legacy_query_failure, ValidPollWFTQResponse},
+    replay::DebuggerReporter,
     telemetry::{
+        events::CoreEventEmitter,
         metrics::{
             activity_poller, local_activity_worker_type, workflow_poller, workflow_sticky_poller,
-            workflow_worker_type, MetricsContext,
+            workflow_type, workflow_worker_type, MetricsContext,
         },
+        propagation::inject_command_headers,
         VecDisplayer,
     },
     worker::{
         activities::{DispatchOrTimeoutLA, LACompleteAction, LocalActivityManager},
-        client::WorkerClientBag,
+        client::{as_payload_too_large, WorkerClientBag},
+        dynamic_config::DynamicConfigState,
         wft_delivery::WFTSource,
     },
     workflow::{
         workflow_tasks::{
-            ActivationAction, FailedActivationOutcome, NewWfTaskOutcome,
+            ActivationAction, FailedActivationOutcome, NewWfTaskOutcome, NondeterminismResetPoint,
             ServerCommandsWithWorkflowInfo, WorkflowTaskManager,
         },
         EmptyWorkflowCommandErr, LocalResolution, WFMachinesError, WorkflowCachingPolicy,
@@ -40,8 +46,22 @@ use crate::{
 };
 use activities::{LocalInFlightActInfo, WorkerActivityTasks};
 use futures::{Future, TryFutureExt};
-use std::{convert::TryInto, future, sync::Arc};
+use parking_lot::{Mutex, RwLock};
+use std::{
+    convert::TryInto,
+    future,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
 use temporal_client::WorkflowTaskCompletion;
+use temporal_sdk_core_api::{
+    errors::{ActivitySlotError, WorkerRestartError},
+    events::CoreEvent,
+    worker::{
+        NondeterminismReset, PollingState, ShutdownOptions, ShutdownPhase, ShutdownProgress,
+        WorkerStatus,
+    },
+};
 use temporal_sdk_core_protos::{
     coresdk::{
         activity_result::activity_execution_result,
@@ -51,14 +71,14 @@ use temporal_sdk_core_protos::{
         ActivityTaskCompletion,
     },
     temporal::api::{
-        enums::v1::{TaskQueueKind, WorkflowTaskFailedCause},
+        enums::v1::{CommandType, TaskQueueKind, WorkflowTaskFailedCause},
         failure::v1::Failure,
         taskqueue::v1::{StickyExecutionAttributes, TaskQueue},
         workflowservice::v1::{PollActivityTaskQueueResponse, PollWorkflowTaskQueueResponse},
     },
     TaskToken,
 };
-use tokio::sync::Notify;
+use tokio::sync::{broadcast, Notify};
 use tokio_util::sync::CancellationToken;
 use tonic::Code;
 use tracing_futures::Instrument;
@@ -86,6 +106,7 @@ pub struct Worker {
     local_act_mgr: LocalActivityManager,
     /// Ensures we stay at or below this worker's maximum concurrent workflow limit
     workflows_semaphore: MeteredSemaphore,
+    /// Checked before sending workflow task completions
     /// Used to wake blocked workflow task polling when there is some change to workflow activations
     /// that should cause us to restart the loop
     pending_activations_notify: Arc<Notify>,
@@ -95,20 +116,59 @@ pub struct Worker {
     /// Has shutdown been called?
     shutdown_token: CancellationToken,
     /// Will be called at the end of each activation completion
-    post_activate_hook: Option<Box<dyn Fn(&Self) + Send + Sync>>,
+    post_activate_hook: Option<PostActivateHook>,
+    /// If set, replay waits for a debugger at the start of every workflow task
+    debugger: Option<DebuggerReporter>,
 
     metrics: MetricsContext,
+    /// Core events this worker emits, which anyone may subscribe to
+    events: CoreEventEmitter,
+    /// When a poll for workflow tasks last succeeded (including timeouts), if ever
+    last_successful_wft_poll: Mutex<Option<SystemTime>>,
+    /// How far along shutdown is, if it has started
+    shutdown_phase: Mutex<Option<ShutdownPhase>>,
+    /// Creates new pollers when the worker is restarted, if it can be
+    poller_factory: Option<PollerFactory>,
+    /// Held while restarting, so concurrent restarts happen one after the other
+    restart_lock: tokio::sync::Mutex<()>,
+    /// Activities per second the server should dispatch to this worker's task queue, which the
+    /// activity pollers read before every poll
+    activity_rate_limit: ActivityRateLimit,
+    /// Tuning values changed at runtime, if the worker has a [WorkerConfig::dynamic_config]
+    dynamic_config: Option<DynamicConfigState>,
 }
 
+type PostActivateHook = Box<dyn Fn(&Worker) + Send + Sync>;
+type PollerFactory = Box<dyn Fn() -> (BoxedWFPoller, Option<BoxedActPoller>) + Send + Sync>;
+
 #[async_trait::async_trait]
 impl WorkerTrait for Worker {
     async fn poll_workflow_activation(&self) -> Result<WorkflowActivation, PollWfError> {
-        self.next_workflow_activation().await
+        let mut res = self.next_workflow_activation().await;
+        if let (Ok(act), Some(interceptor)) = (&mut res, &self.config.header_interceptor) {
+            act.jobs
+                .iter_mut()
+                .filter_map(|j| j.variant.as_mut()?.headers_mut())
+                .for_each(|h| interceptor.on_inbound(h));
+        }
+        if let (Ok(act), Some(capture)) = (&res, self.wf_client.activation_capture()) {
+            capture.activation(act);
+        }
+        if let (Ok(act), Some(debugger)) = (&res, &self.debugger) {
+            if act.eviction_index().is_some() {
+                debugger.run_evicted(&act.run_id);
+            } else if let Some(started_id) =
+                self.wft_manager.current_wft_started_event_id(&act.run_id)
+            {
+                debugger.activation_for_task(&act.run_id, started_id).await;
+            }
+        }
+        res
     }
 
     #[instrument(level = "debug", skip(self))]
     async fn poll_activity_task(&self) -> Result<ActivityTask, PollActivityError> {
-        loop {
+        let mut res = loop {
             match self.activity_poll().await.transpose() {
                 Some(r) => break r,
                 None => {
@@ -116,13 +176,22 @@ impl WorkerTrait for Worker {
                     continue;
                 }
             }
+        };
+        if let (Ok(task), Some(interceptor)) = (&mut res, &self.config.header_interceptor) {
+            if let Some(headers) = task.headers_mut() {
+                interceptor.on_inbound(headers);
+            }
         }
+        res
     }
 
     async fn complete_workflow_activation(
         &self,
         completion: WorkflowActivationCompletion,
     ) -> Result<(), CompleteWfError> {
+        if let Some(capture) = self.wf_client.activation_capture() {
+            capture.completion(&completion);
+        }
         self.complete_workflow_activation(completion).await
     }
 
@@ -157,12 +226,71 @@ impl WorkerTrait for Worker {
         );
     }
 
+    fn set_max_outstanding_activities(&self, max: usize) {
+        if let Some(atm) = self.at_task_mgr.as_ref() {
+            atm.set_max_outstanding(max);
+        }
+    }
+
+    async fn reserve_activity_slot(&self) -> Result<(), ActivitySlotError> {
+        let atm = self
+            .at_task_mgr
+            .as_ref()
+            .ok_or(ActivitySlotError::NoActivitySlots)?;
+        tokio::select! {
+            _ = atm.reserve_slot() => Ok(()),
+            _ = self.shutdown_token.cancelled() => Err(ActivitySlotError::ShuttingDown),
+        }
+    }
+
+    fn release_activity_slot(&self) -> Result<(), ActivitySlotError> {
+        self.at_task_mgr
+            .as_ref()
+            .ok_or(ActivitySlotError::NoActivitySlots)?
+            .release_reserved_slot()
+    }
+
+    fn release_activity_task_slot(&self, task_token: &[u8]) -> Result<(), ActivitySlotError> {
+        self.at_task_mgr
+            .as_ref()
+            .ok_or(ActivitySlotError::NoActivitySlots)?
+            .release_task_slot(TaskToken(task_token.to_vec()))
+    }
+
+    fn set_max_outstanding_workflow_tasks(&self, max: usize) {
+        let max_cached_workflows = self.wft_manager.max_cached_workflows();
+        let max = if max_cached_workflows > 0 && max > max_cached_workflows {
+            warn!(
+                requested = max,
+                max_cached_workflows,
+                "Maximum outstanding workflow tasks cannot exceed the maximum number of cached \
+                 workflows, clamping"
+            );
+            max_cached_workflows
+        } else {
+            max
+        };
+        info!(max, "Setting maximum outstanding workflow tasks");
+        self.workflows_semaphore.resize(max);
+    }
+
     fn get_config(&self) -> &WorkerConfig {
         &self.config
     }
 
+    fn status(&self) -> WorkerStatus {
+        self.status()
+    }
+
+    async fn soft_restart(&self) -> Result<(), WorkerRestartError> {
+        self.soft_restart().await
+    }
+
     /// Begins the shutdown process, tells pollers they should stop. Is idempotent.
     fn initiate_shutdown(&self) {
+        self.shutdown_phase
+            .lock()
+            .get_or_insert(ShutdownPhase::Draining);
         self.shutdown_token.cancel();
         // First, we want to stop polling of both activity and workflow tasks
         if let Some(atm) = self.at_task_mgr.as_ref() {
@@ -176,6 +304,10 @@ impl WorkerTrait for Worker {
         self.shutdown().await
     }
 
+    async fn shutdown_with_deadline(&self, options: ShutdownOptions) -> ShutdownProgress {
+        self.shutdown_with_deadline(options).await
+    }
+
     async fn finalize_shutdown(self) {
         self.shutdown().await;
         self.finalize_shutdown().await
@@ -189,65 +321,50 @@ impl Worker {
         client: Arc<WorkerClientBag>,
         metrics: MetricsContext,
     ) -> Self {
-        info!(task_queue = %config.task_queue, "Initializing worker");
+        info!(
+            task_queue = %config.task_queue,
+            identity = %client.identity(),
+            server_capabilities = ?client.capabilities(),
+            "Initializing worker"
+        );
         metrics.worker_registered();
 
-        let max_nonsticky_polls = if sticky_queue_name.is_some() {
-            config.max_nonsticky_polls()
-        } else {
-            config.max_concurrent_wft_polls
-        };
-        let max_sticky_polls = config.max_sticky_polls();
-        let wft_metrics = metrics.with_new_attrs([workflow_poller()]);
-        let mut wf_task_poll_buffer = new_workflow_task_buffer(
-            client.clone(),
-            config.task_queue.clone(),
-            false,
-            max_nonsticky_polls,
-            max_nonsticky_polls * 2,
+        let activity_rate_limit =
+            Arc::new(RwLock::new(config.max_task_queue_activities_per_second));
+        let (wf_task_poll_buffer, act_poll_buffer) = build_pollers(
+            &config,
+            sticky_queue_name.as_deref(),
+            &client,
+            &metrics,
+            &activity_rate_limit,
         );
-        wf_task_poll_buffer.set_num_pollers_handler(move |np| wft_metrics.record_num_pollers(np));
-        let sticky_queue_poller = sticky_queue_name.as_ref().map(|sqn| {
-            let sticky_metrics = metrics.with_new_attrs([workflow_sticky_poller()]);
-            let mut sp = new_workflow_task_buffer(
-                client.clone(),
-                sqn.clone(),
-                true,
-                max_sticky_polls,
-                max_sticky_polls * 2,
-            );
-            sp.set_num_pollers_handler(move |np| sticky_metrics.record_num_pollers(np));
-            sp
-        });
-        let act_poll_buffer = if config.no_remote_activities {
-            None
-        } else {
-            let mut ap = new_activity_task_buffer(
-                client.clone(),
-                config.task_queue.clone(),
-                config.max_concurrent_at_polls,
-                config.max_concurrent_at_polls * 2,
-                config.max_task_queue_activities_per_second,
-            );
-            let act_metrics = metrics.with_new_attrs([activity_poller()]);
-            ap.set_num_pollers_handler(move |np| act_metrics.record_num_pollers(np));
-            Some(Box::from(ap)
-                as Box<
-                    dyn Poller<PollActivityTaskQueueResponse> + Send + Sync,
-                >)
+        let poller_factory = {
+            let config = config.clone();
+            let sticky_queue_name = sticky_queue_name.clone();
+            let client = client.clone();
+            let metrics = metrics.clone();
+            let activity_rate_limit = activity_rate_limit.clone();
+            move || {
+                build_pollers(
+                    &config,
+                    sticky_queue_name.as_deref(),
+                    &client,
+                    &metrics,
+                    &activity_rate_limit,
+                )
+            }
         };
-        let wf_task_poll_buffer = Box::new(WorkflowTaskPoller::new(
-            wf_task_poll_buffer,
-            sticky_queue_poller,
-        ));
-        Self::new_with_pollers(
+        let mut worker = Self::new_with_pollers(
             config,
             sticky_queue_name,
             client,
             wf_task_poll_buffer,
             act_poll_buffer,
             metrics,
-        )
+        );
+        worker.set_poller_factory(poller_factory);
+        worker.activity_rate_limit = activity_rate_limit;
+        worker
     }
 
     #[cfg(test)]
@@ -260,6 +377,15 @@ impl Worker {
         self.wft_manager.cached_workflows()
     }
 
+    /// Set the function used to create new pollers when the worker is restarted. Workers without
+    /// one can't be restarted.
+    pub(crate) fn set_poller_factory(
+        &mut self,
+        factory: impl Fn() -> (BoxedWFPoller, Option<BoxedActPoller>) + Send + Sync + 'static,
+    ) {
+        self.poller_factory = Some(Box::new(factory));
+    }
+
     pub(crate) fn new_with_pollers(
         config: WorkerConfig,
         sticky_queue_name: Option<String>,
@@ -277,19 +403,36 @@ impl Worker {
         };
         let pa_notif = Arc::new(Notify::new());
         let wfts_drained_notify = Arc::new(Notify::new());
+        let events = CoreEventEmitter::default();
+        let shutdown_token = CancellationToken::new();
+        let dynamic_config = config.dynamic_config.clone().map(|dc| {
+            DynamicConfigState::start(
+                dc,
+                config.dynamic_config_poll_interval,
+                pa_notif.clone(),
+                shutdown_token.clone(),
+            )
+        });
         Self {
             wf_client: client.clone(),
             sticky_name: sticky_queue_name,
             wf_task_source: WFTSource::new(wft_poller),
-            wft_manager: WorkflowTaskManager::new(pa_notif.clone(), cache_policy, metrics.clone()),
+            wft_manager: WorkflowTaskManager::new(
+                pa_notif.clone(),
+                cache_policy,
+                config.max_pending_activations,
+                config.unknown_history_event_policy,
+                config.wft_failure_backoff.clone(),
+                metrics.clone(),
+                events.clone(),
+            ),
             at_task_mgr: act_poller.map(|ap| {
                 WorkerActivityTasks::new(
-                    config.max_outstanding_activities,
+                    &config,
                     ap,
                     client.clone(),
                     metrics.clone(),
-                    config.max_heartbeat_throttle_interval,
-                    config.default_heartbeat_throttle_interval,
+                    events.clone(),
                 )
             }),
             local_act_mgr: LocalActivityManager::new(
@@ -302,15 +445,124 @@ impl Worker {
                 metrics.with_new_attrs([workflow_worker_type()]),
                 MetricsContext::available_task_slots,
             ),
+            activity_rate_limit: Arc::new(RwLock::new(config.max_task_queue_activities_per_second)),
             config,
-            shutdown_token: CancellationToken::new(),
+            shutdown_token,
             post_activate_hook: None,
+            debugger: None,
             pending_activations_notify: pa_notif,
             wfts_drained_notify,
             metrics,
+            events,
+            last_successful_wft_poll: Mutex::new(None),
+            shutdown_phase: Mutex::new(None),
+            poller_factory: None,
+            restart_lock: Default::default(),
+            dynamic_config,
         }
     }
 
+    /// Applies whatever changed in the worker's [WorkerConfig::dynamic_config] since it was last
+    /// called
+    fn apply_dynamic_config(&self) {
+        let changes = match self
+            .dynamic_config
+            .as_ref()
+            .and_then(|dc| dc.take_changes())
+        {
+            Some(c) => c,
+            None => return,
+        };
+        info!(changes = ?changes, "Applying dynamic config changes");
+        // The cache comes first, since the number of outstanding workflow tasks is limited by it
+        if let Some(max) = changes.max_cached_workflows {
+            if max > 0 {
+                self.wft_manager.set_max_cached_workflows(max);
+            }
+        }
+        if let Some(max) = changes.max_outstanding_workflow_tasks {
+            WorkerTrait::set_max_outstanding_workflow_tasks(self, max);
+        }
+        if let Some(max) = changes.max_outstanding_activities {
+            WorkerTrait::set_max_outstanding_activities(self, max);
+        }
+        if let Some(max) = changes.max_concurrent_wft_polls {
+            self.wf_task_source.set_max_pollers(max);
+        }
+        if let Some(max) = changes.max_concurrent_at_polls {
+            if let Some(atm) = self.at_task_mgr.as_ref() {
+                atm.set_max_pollers(max);
+            }
+        }
+        if let Some(tps) = changes.max_task_queue_activities_per_second {
+            *self.activity_rate_limit.write() = Some(tps);
+        }
+    }
+
+    /// Pollers created when restarting start out with the configured maximums, so any changed by
+    /// the dynamic config need setting again
+    fn reapply_dynamic_poller_limits(&self) {
+        let applied = match self.dynamic_config.as_ref() {
+            Some(dc) => dc.applied(),
+            None => return,
+        };
+        if let Some(max) = applied.max_concurrent_wft_polls {
+            self.wf_task_source.set_max_pollers(max);
+        }
+        if let (Some(max), Some(atm)) = (applied.max_concurrent_at_polls, self.at_task_mgr.as_ref())
+        {
+            atm.set_max_pollers(max);
+        }
+    }
+
+    /// Subscribe to this worker's [CoreEvent]s. Subscribers only receive events emitted after they
+    /// subscribe, and miss the oldest ones if they fall too far behind.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<CoreEvent> {
+        self.events.subscribe()
+    }
+
+    /// Emit a [CoreEvent::PollBackoff] every time the client this worker polls with pauses polling
+    pub(crate) fn forward_poll_pauses(&self, pauses: broadcast::Receiver<Duration>) {
+        self.events.forward_poll_pauses(pauses);
+    }
+
+    /// Restart polling without dropping cached workflows. See [WorkerTrait::soft_restart].
+    pub(crate) async fn soft_restart(&self) -> Result<(), WorkerRestartError> {
+        let factory = self
+            .poller_factory
+            .as_ref()
+            .ok_or(WorkerRestartError::Unsupported)?;
+        let _restarting = self.restart_lock.lock().await;
+        if self.shutdown_token.is_cancelled() {
+            return Err(WorkerRestartError::ShuttingDown);
+        }
+        info!("Restarting worker");
+        self.wf_task_source.pause_pollers();
+        if let Some(atm) = self.at_task_mgr.as_ref() {
+            atm.pause_polling();
+        }
+        // Wait for all outstanding work, the same way shutdown does (minus local activities,
+        // which keep their workflow task outstanding anyway)
+        self.wf_task_source
+            .wait_for_tasks_from_complete_to_drain()
+            .await;
+        self.all_wfts_drained().await;
+        if let Some(atm) = self.at_task_mgr.as_ref() {
+            atm.wait_all_finished().await;
+        }
+        let (wft_poller, act_poller) = factory();
+        self.wf_task_source.resume_pollers(wft_poller);
+        if let (Some(atm), Some(act_poller)) = (self.at_task_mgr.as_ref(), act_poller) {
+            atm.resume_polling(act_poller);
+        }
+        self.reapply_dynamic_poller_limits();
+        if self.shutdown_token.is_cancelled() {
+            return Err(WorkerRestartError::ShuttingDown);
+        }
+        info!("Worker restarted");
+        Ok(())
+    }
+
     /// Will shutdown the worker. Does not resolve until all outstanding workflow tasks have been
     /// completed
     pub(crate) async fn shutdown(&self) {
@@ -329,6 +581,105 @@ impl Worker {
         if let Some(acts) = self.at_task_mgr.as_ref() {
             acts.wait_all_finished().await;
         }
+        *self.shutdown_phase.lock() = Some(ShutdownPhase::Complete);
+    }
+
+    /// Shut down, escalating from waiting, to cancelling activities, to giving up as portions of
+    /// the deadline elapse. See [WorkerTrait::shutdown_with_deadline].
+    pub(crate) async fn shutdown_with_deadline(
+        &self,
+        options: ShutdownOptions,
+    ) -> ShutdownProgress {
+        let start = Instant::now();
+        let cancel_at = start + options.deadline.mul_f64(options.cancel_activities_after);
+        let abort_at = start + options.deadline;
+        let report = |phase| {
+            let progress = self.shutdown_progress(phase);
+            if let Some(l) = options.progress_listener.as_ref() {
+                l.on_progress(&progress);
+            }
+            progress
+        };
+
+        let drained = self.shutdown();
+        tokio::pin!(drained);
+        let mut progress_ticker = tokio::time::interval(options.progress_interval);
+        let mut phase = ShutdownPhase::Draining;
+        loop {
+            tokio::select! {
+                biased;
+
+                _ = &mut drained => {
+                    info!("Worker shut down before the deadline");
+                    return report(ShutdownPhase::Complete);
+                }
+                _ = tokio::time::sleep_until(abort_at.into()) => {
+                    *self.shutdown_phase.lock() = Some(ShutdownPhase::Aborted);
+                    let progress = report(ShutdownPhase::Aborted);
+                    warn!(?progress, "Shutdown deadline elapsed, abandoning outstanding work");
+                    return progress;
+                }
+                _ = tokio::time::sleep_until(cancel_at.into()),
+                    if phase == ShutdownPhase::Draining => {
+                    phase = ShutdownPhase::CancellingActivities;
+                    *self.shutdown_phase.lock() = Some(phase);
+                    info!("Cancelling outstanding activities so shutdown can finish");
+                    if let Some(atm) = self.at_task_mgr.as_ref() {
+                        atm.cancel_all_for_shutdown();
+                    }
+                    report(phase);
+                }
+                _ = progress_ticker.tick() => {
+                    report(phase);
+                }
+            }
+        }
+    }
+
+    /// See [WorkerTrait::status]
+    pub(crate) fn status(&self) -> WorkerStatus {
+        let last_act_poll = self
+            .at_task_mgr
+            .as_ref()
+            .and_then(|atm| atm.last_successful_poll());
+        WorkerStatus {
+            polling: if self.shutdown_token.is_cancelled() {
+                PollingState::Stopped
+            } else if self.wf_task_source.pollers_paused() {
+                PollingState::Restarting
+            } else {
+                PollingState::Polling
+            },
+            outstanding_workflow_tasks: self.outstanding_workflow_tasks(),
+            outstanding_activities: self
+                .at_task_mgr
+                .as_ref()
+                .map(|atm| atm.num_outstanding())
+                .unwrap_or_default(),
+            outstanding_local_activities: self.local_act_mgr.num_outstanding(),
+            available_workflow_task_slots: self.workflows_semaphore.sem.available_permits(),
+            available_activity_slots: self
+                .at_task_mgr
+                .as_ref()
+                .map(|atm| atm.remaining_activity_capacity()),
+            cached_workflows: self.cached_workflows(),
+            max_cached_workflows: self.config.max_cached_workflows,
+            last_successful_poll: (*self.last_successful_wft_poll.lock()).max(last_act_poll),
+            shutdown_phase: *self.shutdown_phase.lock(),
+        }
+    }
+
+    fn shutdown_progress(&self, phase: ShutdownPhase) -> ShutdownProgress {
+        ShutdownProgress {
+            phase,
+            outstanding_workflow_tasks: self.outstanding_workflow_tasks(),
+            outstanding_activities: self
+                .at_task_mgr
+                .as_ref()
+                .map(|atm| atm.num_outstanding())
+                .unwrap_or_default(),
+            outstanding_local_activities: self.local_act_mgr.num_outstanding(),
+        }
     }
 
     /// Finish shutting down by consuming the background pollers and freeing all resources
@@ -355,6 +706,7 @@ impl Worker {
     /// Returns `Ok(None)` in the event of a poll timeout or if the polling loop should otherwise
     /// be restarted
     async fn activity_poll(&self) -> Result<Option<ActivityTask>, PollActivityError> {
+        self.apply_dynamic_config();
         let act_mgr_poll = async {
             if let Some(ref act_mgr) = self.at_task_mgr {
                 act_mgr.poll().await
@@ -377,7 +729,12 @@ impl Worker {
                     },
                     None => {
                         if self.shutdown_token.is_cancelled() {
-                            return Err(PollActivityError::ShutDown);
+                            // Local activities are done, but remote ones may still need cancels
+                            // delivered before we can report being shut down
+                            return match self.at_task_mgr.as_ref() {
+                                Some(atm) => atm.poll().await,
+                                None => Err(PollActivityError::ShutDown),
+                            };
                         }
                         Ok(None)
                     }
@@ -443,14 +800,28 @@ impl Worker {
         // The poll needs to be in a loop because we can't guarantee tail call optimization in Rust
         // (simply) and we really, really need that for long-poll retries.
         loop {
+            self.apply_dynamic_config();
             // We must first check if there are pending workflow activations for workflows that are
             // currently replaying or otherwise need immediate jobs, and issue those before
             // bothering the server.
+            self.wft_manager.record_queue_depths();
             if let Some(pa) = self.wft_manager.next_pending_activation() {
                 debug!(activation=%pa, "Sending pending activation to lang");
                 return Ok(pa);
             }
 
+            // Runs still waiting on local activities as their WFT is about to time out must
+            // heartbeat it, which we do as if lang had completed an empty activation
+            if let Some(run_id) = self.wft_manager.next_due_heartbeat() {
+                debug!(run_id=%run_id, "Heartbeating WFT while local activities are running");
+                self.process_activation_completion(WorkflowActivationCompletion {
+                    run_id,
+                    status: Some(workflow_completion::Success::from_variants(vec![]).into()),
+                })
+                .await?;
+                continue;
+            }
+
             if self.config.max_cached_workflows > 0 {
                 if let Some(cache_cap_fut) = self.wft_manager.wait_for_cache_capacity() {
                     tokio::select! {
@@ -475,6 +846,20 @@ impl Worker {
                 }
             }
 
+            // Everything pending is waiting on lang to complete an activation for its run. Don't
+            // take on more work from the server until it does, so the queue can't grow without
+            // bound. Completions always wake this.
+            // Register interest before checking, so a completion between the check and the await
+            // isn't missed.
+            let notified = self.pending_activations_notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if self.wft_manager.is_overloaded() {
+                debug!("Too many pending activations, not polling for new workflow tasks");
+                notified.await;
+                continue;
+            }
+
             let selected_f = tokio::select! {
                 biased;
 
@@ -482,12 +867,11 @@ impl Worker {
                 // the loop right away to provide any potential new pending activation.
                 // Continue here means that we unnecessarily add another permit to the poll buffer,
                 // this will go away when polling is done in the background.
-                _ = self.pending_activations_notify.notified() => continue,
+                _ = &mut notified => continue,
                 r = self.workflow_poll_or_wfts_drained() => r,
             }?;
 
-            if let Some(work) = selected_f {
-                self.metrics.wf_tq_poll_ok();
+            if let Some(work) = selected_f.and_then(|w| self.wft_manager.backoff_if_retry(w)) {
                 if let Some(a) = self.apply_server_work(work).await? {
                     return Ok(a);
                 }
@@ -499,9 +883,30 @@ impl Worker {
         }
     }
 
+    pub(crate) async fn complete_workflow_activation(
+        &self,
+        completion: WorkflowActivationCompletion,
+    ) -> Result<(), CompleteWfError> {
+        // Multi-threaded lang runtimes can complete an activation after the run was evicted, or
+        // complete it twice. Processing such a completion would act on a workflow task which is no
+        // longer ours, so lang is told it was ignored instead.
+        if !self
+            .wft_manager
+            .has_outstanding_activation(&completion.run_id)
+        {
+            debug!(run_id=%completion.run_id, "Ignoring completion for run without activation");
+            return Err(CompleteWfError::ActivationNotOutstanding {
+                run_id: completion.run_id,
+            });
+        }
+        self.process_activation_completion(completion).await
+    }
+
+    /// Handle the completion of an activation, which is either lang's or one core completed on its
+    /// own for a run lang has nothing to do for
     #[instrument(level = "debug", skip(self, completion),
     fields(completion=%&completion, run_id=%completion.run_id))]
-    pub(crate) async fn complete_workflow_activation(
+    async fn process_activation_completion(
         &self,
         completion: WorkflowActivationCompletion,
     ) -> Result<(), CompleteWfError> {
@@ -529,6 +934,10 @@ impl Worker {
             }
         }?;
 
+        // Runs whose history shows they ended (ex: they were terminated) are about to be evicted
+        if self.wft_manager.run_has_ended(&completion.run_id) {
+            self.cancel_activities_for_run(&completion.run_id);
+        }
         self.wft_manager
             .after_wft_report(&completion.run_id, report_outcome.reported_to_server);
         if report_outcome.reported_to_server || report_outcome.failed {
@@ -546,6 +955,13 @@ impl Worker {
         Ok(())
     }
 
+    /// Cancel the outstanding activities of a workflow run which has ended
+    fn cancel_activities_for_run(&self, run_id: &str) {
+        if let Some(atm) = self.at_task_mgr.as_ref() {
+            atm.cancel_activities_for_run(run_id);
+        }
+    }
+
     /// Tell the worker a workflow task has completed, for tracking max outstanding WFTs
     pub(crate) fn return_workflow_task_permit(&self) {
         self.workflows_semaphore.add_permit();
@@ -566,6 +982,11 @@ impl Worker {
     }
 
     /// Sets a function to be called at the end of each activation completion
+    /// Pause at the start of every workflow task until the debugger lets replay continue
+    pub(crate) fn set_debugger(&mut self, debugger: DebuggerReporter) {
+        self.debugger = Some(debugger);
+    }
+
     pub(crate) fn set_post_activate_hook(
         &mut self,
         callback: impl Fn(&Self) + Send + Sync + 'static,
@@ -577,17 +998,20 @@ impl Worker {
     /// given event number
     pub(crate) fn set_shutdown_on_run_reaches_event(&mut self, run_id: String, last_event: i64) {
         self.set_post_activate_hook(move |worker| {
-            if worker
-                .wft_manager
-                .most_recently_processed_event(&run_id)
-                .unwrap_or_default()
-                >= last_event
-            {
+            if worker.run_reached_event(&run_id, last_event) {
                 worker.initiate_shutdown();
             }
         });
     }
 
+    /// Returns true if the given run has processed history up to at least the given event number
+    pub(crate) fn run_reached_event(&self, run_id: &str, event_id: i64) -> bool {
+        self.wft_manager
+            .most_recently_processed_event(run_id)
+            .unwrap_or_default()
+            >= event_id
+    }
+
     /// Resolves with WFT poll response or `PollWfError::ShutDown` if WFTs have been drained
     async fn workflow_poll_or_wfts_drained(
         &self,
@@ -650,14 +1074,18 @@ impl Worker {
             .next_wft()
             .await
             .ok_or(PollWfError::ShutDown)??;
+        *self.last_successful_wft_poll.lock() = Some(SystemTime::now());
 
         if res == PollWorkflowTaskQueueResponse::default() {
             // We get the default proto in the event that the long poll times out.
             debug!("Poll wft timeout");
-            self.metrics.wf_tq_poll_empty();
             return Ok(None);
         }
 
+        if let Some(capture) = self.wf_client.activation_capture() {
+            capture.workflow_task(&res);
+        }
+
         if let Some(dur) = res.sched_to_start() {
             self.metrics.wf_task_sched_to_start_latency(dur);
         }
@@ -718,7 +1146,7 @@ impl Worker {
             NewWfTaskOutcome::Autocomplete | NewWfTaskOutcome::LocalActsOutstanding => {
                 debug!(workflow_execution=?we,
                        "No new work for lang to perform after polling server");
-                self.complete_workflow_activation(WorkflowActivationCompletion {
+                self.process_activation_completion(WorkflowActivationCompletion {
                     run_id: we.run_id,
                     status: Some(workflow_completion::Success::from_variants(vec![]).into()),
                 })
@@ -748,8 +1176,18 @@ impl Worker {
     async fn wf_activation_success(
         &self,
         run_id: &str,
-        success: workflow_completion::Success,
+        mut success: workflow_completion::Success,
     ) -> Result<WFTReportOutcome, CompleteWfError> {
+        if let Some(span) = self.wft_manager.activation_span(run_id) {
+            inject_command_headers(&span, &mut success.commands);
+        }
+        if let Some(interceptor) = &self.config.header_interceptor {
+            success
+                .commands
+                .iter_mut()
+                .filter_map(|c| c.variant.as_mut()?.headers_mut())
+                .for_each(|h| interceptor.on_outbound(h));
+        }
         // Convert to wf commands
         let cmds = success
             .commands
@@ -784,6 +1222,17 @@ impl Worker {
                         query_responses.display()
                     );
                 }
+                let closes_run = commands.iter().any(|c| {
+                    matches!(
+                        CommandType::from_i32(c.command_type),
+                        Some(
+                            CommandType::CompleteWorkflowExecution
+                                | CommandType::FailWorkflowExecution
+                                | CommandType::CancelWorkflowExecution
+                                | CommandType::ContinueAsNewWorkflowExecution
+                        )
+                    )
+                });
                 let mut completion = WorkflowTaskCompletion {
                     task_token,
                     commands,
@@ -809,6 +1258,9 @@ impl Worker {
                     if let Some(wft) = maybe_wft.workflow_task {
                         self.wf_task_source.add_wft_from_completion(wft);
                     }
+                    if closes_run {
+                        self.cancel_activities_for_run(run_id);
+                    }
                     Ok(())
                 })
                 .await?;
@@ -836,20 +1288,81 @@ impl Worker {
             }),
             Err(update_err) => {
                 // Automatically fail the workflow task in the event we couldn't update machines
-                let fail_cause = if matches!(&update_err.source, WFMachinesError::Nondeterminism(_))
-                {
+                let is_nondeterminism =
+                    matches!(&update_err.source, WFMachinesError::Nondeterminism(_));
+                let fail_cause = if is_nondeterminism {
                     WorkflowTaskFailedCause::NonDeterministicError
                 } else {
                     WorkflowTaskFailedCause::Unspecified
                 };
+                // Must be determined before failing, since that evicts the run
+                let reset_point =
+                    if is_nondeterminism && self.config.reset_on_nondeterminism.is_some() {
+                        self.wft_manager.nondeterminism_reset_point(run_id)
+                    } else {
+                        None
+                    };
                 let wft_fail_str = format!("{:?}", update_err);
-                self.wf_activation_failed(
-                    run_id,
-                    fail_cause,
-                    update_err.evict_reason(),
-                    Failure::application_failure(wft_fail_str.clone(), false).into(),
-                )
-                .await
+                let outcome = self
+                    .wf_activation_failed(
+                        run_id,
+                        fail_cause,
+                        update_err.evict_reason(),
+                        Failure::application_failure(wft_fail_str.clone(), false).into(),
+                    )
+                    .await?;
+                // Failures are only reported on the first attempt, so this resets each run once
+                if let Some(point) = reset_point.filter(|_| outcome.reported_to_server) {
+                    self.reset_nondeterministic_run(run_id, point, update_err.source.to_string())
+                        .await;
+                }
+                Ok(outcome)
+            }
+        }
+    }
+
+    /// Reset a run which hit nondeterminism, see [WorkerConfig::reset_on_nondeterminism]. Failing
+    /// to reset is logged but otherwise ignored, since the task will be retried as usual.
+    async fn reset_nondeterministic_run(
+        &self,
+        run_id: &str,
+        point: NondeterminismResetPoint,
+        cause: String,
+    ) {
+        let cfg = match self.config.reset_on_nondeterminism.as_ref() {
+            Some(cfg) => cfg,
+            None => return,
+        };
+        let res = self
+            .wf_client
+            .reset_workflow_execution(
+                point.workflow_id.clone(),
+                run_id.to_string(),
+                point.workflow_task_finish_event_id,
+                format!("Worker detected nondeterminism: {}", cause),
+                cfg.reapply_type,
+            )
+            .await;
+        match res {
+            Ok(resp) => {
+                warn!(run_id, new_run_id=%resp.run_id, reset_to=point.workflow_task_finish_event_id,
+                      "Reset workflow run after nondeterminism");
+                self.metrics
+                    .with_new_attrs([workflow_type(point.workflow_type.clone())])
+                    .wf_nondeterminism_reset();
+                if let Some(listener) = cfg.listener.as_ref() {
+                    listener.on_reset(&NondeterminismReset {
+                        workflow_id: point.workflow_id,
+                        workflow_type: point.workflow_type,
+                        run_id: run_id.to_string(),
+                        new_run_id: resp.run_id,
+                        workflow_task_finish_event_id: point.workflow_task_finish_event_id,
+                        cause,
+                    });
+                }
+            }
+            Err(e) => {
+                warn!(run_id, error=%e, "Failed to reset workflow run after nondeterminism");
             }
         }
     }
@@ -874,7 +1387,7 @@ impl Worker {
                     warn!(run_id, failure=?failure, "Failing workflow activation");
                     self.handle_wft_reporting_errs(run_id, || async {
                         self.wf_client
-                            .fail_workflow_task(tt, cause, failure.failure.map(Into::into))
+                            .fail_workflow_task(tt, cause, failure.failure)
                             .await
                     })
                     .await?;
@@ -912,6 +1425,7 @@ impl Worker {
         Fut: Future<Output = Result<T, tonic::Status>>,
     {
         let mut should_evict = None;
+        let mut eviction_message = "Error reporting WFT to server".to_string();
         let res = match completer().await {
             Err(err) => {
                 match err.code() {
@@ -927,13 +1441,19 @@ impl Worker {
                         should_evict = Some(EvictionReason::TaskNotFound);
                         Ok(())
                     }
+                    // The client already failed the task, since its payloads were too large
+                    _ if as_payload_too_large(&err).is_some() => {
+                        should_evict = Some(EvictionReason::Fatal);
+                        eviction_message = err.message().to_string();
+                        Ok(())
+                    }
                     _ => Err(err),
                 }
             }
             _ => Ok(()),
         };
         if let Some(reason) = should_evict {
-            self.request_wf_eviction(run_id, "Error reporting WFT to server", reason);
+            self.request_wf_eviction(run_id, eviction_message, reason);
         }
         res.map_err(Into::into)
     }
@@ -1002,10 +1522,95 @@ struct WFTReportOutcome {
     failed: bool,
 }
 
+/// Create the workflow and activity task pollers for a worker
+fn build_pollers(
+    config: &WorkerConfig,
+    sticky_queue_name: Option<&str>,
+    client: &Arc<WorkerClientBag>,
+    metrics: &MetricsContext,
+    activity_rate_limit: &ActivityRateLimit,
+) -> (BoxedWFPoller, Option<BoxedActPoller>) {
+    let (min_nonsticky_polls, max_nonsticky_polls) = if sticky_queue_name.is_some() {
+        (config.min_nonsticky_polls(), config.max_nonsticky_polls())
+    } else {
+        (
+            config
+                .min_concurrent_wft_polls
+                .unwrap_or(config.max_concurrent_wft_polls),
+            config.max_concurrent_wft_polls,
+        )
+    };
+    let max_sticky_polls = config.max_sticky_polls();
+    let wft_metrics = metrics.with_new_attrs([workflow_poller()]);
+    let mut wf_task_poll_buffer = new_workflow_task_buffer(
+        client.clone(),
+        config.task_queue.clone(),
+        false,
+        min_nonsticky_polls,
+        max_nonsticky_polls,
+        max_nonsticky_polls * 2,
+    );
+    record_wft_poller_metrics(&mut wf_task_poll_buffer, wft_metrics);
+    let sticky_queue_poller = sticky_queue_name.map(|sqn| {
+        let sticky_metrics = metrics.with_new_attrs([workflow_sticky_poller()]);
+        let mut sp = new_workflow_task_buffer(
+            client.clone(),
+            sqn.to_owned(),
+            true,
+            config.min_sticky_polls(),
+            max_sticky_polls,
+            max_sticky_polls * 2,
+        );
+        record_wft_poller_metrics(&mut sp, sticky_metrics);
+        sp
+    });
+    let act_poll_buffer = if config.no_remote_activities {
+        None
+    } else {
+        let mut ap = new_activity_task_buffer(
+            client.clone(),
+            config.task_queue.clone(),
+            config
+                .min_concurrent_at_polls
+                .unwrap_or(config.max_concurrent_at_polls),
+            config.max_concurrent_at_polls,
+            config.max_concurrent_at_polls * 2,
+            activity_rate_limit.clone(),
+        );
+        let act_metrics = metrics.with_new_attrs([activity_poller()]);
+        ap.set_num_pollers_handler(move |np| act_metrics.record_num_pollers(np));
+        Some(Box::from(ap)
+            as Box<
+                dyn Poller<PollActivityTaskQueueResponse> + Send + Sync,
+            >)
+    };
+    let wf_task_poll_buffer = Box::new(WorkflowTaskPoller::new(
+        wf_task_poll_buffer,
+        sticky_queue_poller,
+        config.nonsticky_to_sticky_poll_ratio,
+    ));
+    (wf_task_poll_buffer, act_poll_buffer)
+}
+
+/// Record the number of pollers and poll outcomes of a workflow task poll buffer, using metrics
+/// which are already tagged with the buffer's poller type.
+fn record_wft_poller_metrics(buffer: &mut PollWorkflowTaskBuffer, metrics: MetricsContext) {
+    let outcome_metrics = metrics.clone();
+    buffer.set_num_pollers_handler(move |np| metrics.record_num_pollers(np));
+    buffer.set_poll_outcome_handler(move |had_work| {
+        if had_work {
+            outcome_metrics.wf_tq_poll_ok();
+        } else {
+            outcome_metrics.wf_tq_poll_empty();
+        }
+    });
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{test_help::test_worker_cfg, worker::client::mocks::mock_workflow_client};
+    use temporal_sdk_core_api::worker::{DynamicConfig, DynamicConfigValues};
     use temporal_sdk_core_protos::temporal::api::workflowservice::v1::PollActivityTaskQueueResponse;
 
     #[tokio::test]
@@ -1074,6 +1679,54 @@ mod tests {
         assert_eq!(worker.workflows_semaphore.sem.available_permits(), 5);
     }
 
+    #[tokio::test]
+    async fn activity_limit_can_be_lowered_at_runtime() {
+        let mut mock_client = mock_workflow_client();
+        mock_client
+            .expect_poll_activity_task()
+            .returning(|_, _| Ok(PollActivityTaskQueueResponse::default()));
+
+        let cfg = test_worker_cfg()
+            .max_outstanding_activities(5_usize)
+            .build()
+            .unwrap();
+        let worker = Worker::new_test(cfg, mock_client);
+        worker.set_max_outstanding_activities(2);
+        assert_eq!(worker.activity_poll().await.unwrap(), None);
+        assert_eq!(worker.at_task_mgr.unwrap().remaining_activity_capacity(), 2);
+    }
+
+    #[tokio::test]
+    async fn status_reports_polls_and_shutdown() {
+        let mut mock_client = mock_workflow_client();
+        mock_client
+            .expect_poll_workflow_task()
+            .returning(|_, _| Ok(PollWorkflowTaskQueueResponse::default()));
+
+        let cfg = test_worker_cfg()
+            .max_outstanding_workflow_tasks(5_usize)
+            .max_outstanding_activities(3_usize)
+            .max_cached_workflows(5_usize)
+            .build()
+            .unwrap();
+        let worker = Worker::new_test(cfg, mock_client);
+        let status = worker.status();
+        assert_eq!(status.polling, PollingState::Polling);
+        assert_eq!(status.last_successful_poll, None);
+        assert_eq!(status.available_workflow_task_slots, 5);
+        assert_eq!(status.available_activity_slots, Some(3));
+        assert_eq!(status.max_cached_workflows, 5);
+        assert_eq!(status.shutdown_phase, None);
+
+        assert_eq!(worker.workflow_poll().await.unwrap(), None);
+        assert!(worker.status().last_successful_poll.is_some());
+
+        worker.shutdown().await;
+        let status = worker.status();
+        assert_eq!(status.polling, PollingState::Stopped);
+        assert_eq!(status.shutdown_phase, Some(ShutdownPhase::Complete));
+    }
+
     #[test]
     fn max_polls_calculated_properly() {
         let cfg = test_worker_cfg().build().unwrap();
@@ -1081,6 +1734,17 @@ mod tests {
         assert_eq!(cfg.max_sticky_polls(), 4);
     }
 
+    #[test]
+    fn sticky_and_nonsticky_polls_can_be_set_independently() {
+        let cfg = test_worker_cfg()
+            .max_concurrent_sticky_wft_polls(8_usize)
+            .max_concurrent_nonsticky_wft_polls(2_usize)
+            .build()
+            .unwrap();
+        assert_eq!(cfg.max_nonsticky_polls(), 2);
+        assert_eq!(cfg.max_sticky_polls(), 8);
+    }
+
     #[test]
     fn max_polls_zero_is_err() {
         assert!(test_worker_cfg()
@@ -1088,4 +1752,83 @@ mod tests {
             .build()
             .is_err());
     }
+
+    #[derive(Debug, Default)]
+    struct TestDynamicConfig {
+        values: Mutex<DynamicConfigValues>,
+        changed: Notify,
+    }
+
+    #[async_trait::async_trait]
+    impl DynamicConfig for TestDynamicConfig {
+        fn current(&self) -> DynamicConfigValues {
+            self.values.lock().clone()
+        }
+
+        async fn changed(&self) {
+            self.changed.notified().await
+        }
+    }
+
+    #[tokio::test]
+    async fn dynamic_config_changes_are_applied() {
+        let mock_client = mock_workflow_client();
+        let dc = Arc::new(TestDynamicConfig::default());
+        let cfg = test_worker_cfg()
+            .max_outstanding_workflow_tasks(5_usize)
+            .max_cached_workflows(5_usize)
+            .max_outstanding_activities(5_usize)
+            .dynamic_config(dc.clone() as Arc<dyn DynamicConfig>)
+            .dynamic_config_poll_interval(Duration::from_secs(1000))
+            .build()
+            .unwrap();
+        let worker = Worker::new_test(cfg, mock_client);
+        *dc.values.lock() = DynamicConfigValues {
+            max_outstanding_workflow_tasks: Some(2),
+            max_outstanding_activities: Some(3),
+            max_task_queue_activities_per_second: Some(7.0),
+            ..Default::default()
+        };
+        dc.changed.notify_waiters();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while worker.available_wft_permits() != 2 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                worker.apply_dynamic_config();
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            worker
+                .at_task_mgr
+                .as_ref()
+                .unwrap()
+                .remaining_activity_capacity(),
+            3
+        );
+        assert_eq!(*worker.activity_rate_limit.read(), Some(7.0));
+
+        // Unset values leave the current setting alone
+        *dc.values.lock() = DynamicConfigValues {
+            max_outstanding_activities: Some(4),
+            ..Default::default()
+        };
+        dc.changed.notify_waiters();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while worker
+                .at_task_mgr
+                .as_ref()
+                .unwrap()
+                .remaining_activity_capacity()
+                != 4
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                worker.apply_dynamic_config();
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(worker.available_wft_permits(), 2);
+        assert_eq!(*worker.activity_rate_limit.read(), Some(7.0));
+    }
 }
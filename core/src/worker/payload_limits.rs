@@ -0,0 +1,306 @@
+use crate::{errors::PayloadTooLarge, payload_codec::ENCODING_METADATA_KEY};
+use prost::Message;
+use temporal_sdk_core_api::worker::{
+    OversizedQueryResponsePolicy, WorkerConfig, TRUNCATED_QUERY_RESPONSE_ENCODING,
+    TRUNCATED_QUERY_RESPONSE_METADATA_KEY,
+};
+use temporal_sdk_core_protos::{
+    coresdk::workflow_commands::{query_result, QueryResult, QuerySuccess},
+    temporal::api::{
+        command::v1::{command, Command},
+        enums::v1::CommandType,
+        failure::v1::Failure,
+    },
+    VisitPayloads,
+};
+
+/// Enforces [WorkerConfig::payload_size_warning_bytes], [WorkerConfig::payload_size_limit_bytes],
+/// and [WorkerConfig::max_query_response_bytes] on what the worker sends to the server. Messages
+/// are measured as they are about to be sent, after the payload codec and offloading, see
+/// [crate::worker::client::WorkerClientBag::with_payload_transforms].
+pub(crate) struct PayloadLimits {
+    warning_bytes: Option<usize>,
+    limit_bytes: Option<usize>,
+    query_limit_bytes: Option<usize>,
+    query_policy: OversizedQueryResponsePolicy,
+}
+
+impl PayloadLimits {
+    /// Returns `None` if `config` doesn't limit anything
+    pub(crate) fn new(config: &WorkerConfig) -> Option<Self> {
+        if config.payload_size_warning_bytes.is_none()
+            && config.payload_size_limit_bytes.is_none()
+            && config.max_query_response_bytes.is_none()
+        {
+            return None;
+        }
+        Some(Self {
+            warning_bytes: config.payload_size_warning_bytes,
+            limit_bytes: config.payload_size_limit_bytes,
+            query_limit_bytes: config.max_query_response_bytes,
+            query_policy: config.oversized_query_response_policy,
+        })
+    }
+
+    /// Check every command in a workflow task completion, returning an error for the first one
+    /// whose payloads are too large
+    pub(crate) fn check_commands(&self, commands: &mut [Command]) -> Result<(), PayloadTooLarge> {
+        for (ix, cmd) in commands.iter_mut().enumerate() {
+            let size = payloads_size(cmd);
+            self.check_size(size, || describe_command(ix, cmd))?;
+        }
+        Ok(())
+    }
+
+    /// Check a message, with `origin` describing it in warnings and errors
+    pub(crate) fn check<T: VisitPayloads>(
+        &self,
+        msg: &mut T,
+        origin: impl FnOnce() -> String,
+    ) -> Result<(), PayloadTooLarge> {
+        self.check_size(payloads_size(msg), origin)
+    }
+
+    fn check_size(
+        &self,
+        size: usize,
+        origin: impl FnOnce() -> String,
+    ) -> Result<(), PayloadTooLarge> {
+        let threshold = match self.warning_bytes.or(self.limit_bytes) {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+        if size < threshold {
+            return Ok(());
+        }
+        let origin = origin();
+        if let Some(limit) = self.limit_bytes.filter(|l| size >= *l) {
+            return Err(PayloadTooLarge {
+                origin,
+                size,
+                limit,
+            });
+        }
+        warn!(size, origin = %origin, "Payloads are approaching the configured size limit");
+        Ok(())
+    }
+
+    /// Make sure a query response isn't too large to send, failing or truncating it if it is
+    pub(crate) fn limit_query_response(&self, response: &mut QueryResult) {
+        let limit = match self.query_limit_bytes {
+            Some(l) => l,
+            None => return,
+        };
+        let size = payloads_size(response);
+        if size < limit {
+            return;
+        }
+        if self.query_policy == OversizedQueryResponsePolicy::Truncate
+            && truncate_query_response(response, size, limit)
+        {
+            warn!(query_id = %response.query_id, size, limit,
+                  "Truncating query response which is too large");
+            return;
+        }
+        let err = PayloadTooLarge {
+            origin: format!("response to query {}", response.query_id),
+            size,
+            limit,
+        };
+        warn!(error = %err, "Failing query instead of sending its response");
+        response.variant = Some(query_result::Variant::Failed(Failure::application_failure(
+            err.to_string(),
+            false,
+        )));
+    }
+}
+
+/// Total encoded size of all payloads in `msg`
+pub(crate) fn payloads_size<T: VisitPayloads>(msg: &mut T) -> usize {
+    let mut size = 0;
+    msg.visit_payloads_mut(&mut |p| size += p.encoded_len());
+    size
+}
+
+/// Cuts enough data off a successful query response's payload that it fits within the limit, if
+/// possible. Returns false, leaving the response alone, if it isn't.
+///
+/// What remains is marked with [TRUNCATED_QUERY_RESPONSE_METADATA_KEY], and its encoding becomes
+/// [TRUNCATED_QUERY_RESPONSE_ENCODING], since the partial data can no longer be decoded as it was
+/// encoded (ex: as JSON, or compressed by the payload codec).
+fn truncate_query_response(response: &mut QueryResult, size: usize, limit: usize) -> bool {
+    let payload = match &mut response.variant {
+        Some(query_result::Variant::Succeeded(QuerySuccess {
+            response: Some(payload),
+        })) => payload,
+        _ => return false,
+    };
+    let orig_len = payload.data.len().to_string();
+    // Generous room for the marker, the encoding, and the length prefixes they add
+    let marker_len = TRUNCATED_QUERY_RESPONSE_METADATA_KEY.len()
+        + orig_len.len()
+        + TRUNCATED_QUERY_RESPONSE_ENCODING.len()
+        + 8;
+    let keep = match payload
+        .data
+        .len()
+        .checked_sub(size - limit + 1 + marker_len)
+    {
+        Some(k) => k,
+        None => return false,
+    };
+    let mut truncated = payload.clone();
+    truncated.data = truncated.data.slice(..keep);
+    truncated.metadata.insert(
+        TRUNCATED_QUERY_RESPONSE_METADATA_KEY.to_string(),
+        orig_len.into_bytes(),
+    );
+    truncated.metadata.insert(
+        ENCODING_METADATA_KEY.to_string(),
+        TRUNCATED_QUERY_RESPONSE_ENCODING.as_bytes().to_vec(),
+    );
+    if size - payload.encoded_len() + truncated.encoded_len() >= limit {
+        return false;
+    }
+    *payload = truncated;
+    true
+}
+
+fn describe_command(ix: usize, cmd: &Command) -> String {
+    let cmd_type = CommandType::from_i32(cmd.command_type).unwrap_or(CommandType::Unspecified);
+    let id = match &cmd.attributes {
+        Some(command::Attributes::ScheduleActivityTaskCommandAttributes(a)) => {
+            Some(format!("activity_id {}", a.activity_id))
+        }
+        Some(command::Attributes::StartChildWorkflowExecutionCommandAttributes(a)) => {
+            Some(format!("workflow_id {}", a.workflow_id))
+        }
+        Some(command::Attributes::SignalExternalWorkflowExecutionCommandAttributes(a)) => {
+            Some(format!("signal_name {}", a.signal_name))
+        }
+        Some(command::Attributes::RecordMarkerCommandAttributes(a)) => {
+            Some(format!("marker_name {}", a.marker_name))
+        }
+        _ => None,
+    };
+    match id {
+        Some(id) => format!("command #{} ({:?}, {})", ix, cmd_type, id),
+        None => format!("command #{} ({:?})", ix, cmd_type),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_help::test_worker_cfg;
+    use temporal_sdk_core_protos::{
+        coresdk::AsJsonPayloadExt,
+        temporal::api::{command::v1::ScheduleActivityTaskCommandAttributes, common::v1::Payloads},
+    };
+
+    fn schedule_act_cmd(input_len: usize) -> Command {
+        Command {
+            command_type: CommandType::ScheduleActivityTask as i32,
+            attributes: Some(command::Attributes::ScheduleActivityTaskCommandAttributes(
+                ScheduleActivityTaskCommandAttributes {
+                    activity_id: "act-1".to_string(),
+                    input: Some(Payloads {
+                        payloads: vec!["a".repeat(input_len).as_json_payload().unwrap().into()],
+                    }),
+                    ..Default::default()
+                },
+            )),
+        }
+    }
+
+    fn limits(mutator: impl FnOnce(&mut WorkerConfig)) -> PayloadLimits {
+        let mut cfg = test_worker_cfg().build().unwrap();
+        mutator(&mut cfg);
+        PayloadLimits::new(&cfg).unwrap()
+    }
+
+    #[test]
+    fn error_names_offending_command() {
+        let limits = limits(|c| {
+            c.payload_size_warning_bytes = Some(500);
+            c.payload_size_limit_bytes = Some(1000);
+        });
+        let mut cmds = [
+            schedule_act_cmd(10),
+            schedule_act_cmd(600),
+            schedule_act_cmd(2000),
+        ];
+        let err = limits.check_commands(&mut cmds).unwrap_err();
+        assert_eq!(err.limit, 1000);
+        assert!(err.size >= 2000);
+        assert_eq!(
+            err.origin,
+            "command #2 (ScheduleActivityTask, activity_id act-1)"
+        );
+        assert!(err.to_string().starts_with("PayloadTooLarge"));
+    }
+
+    #[test]
+    fn no_limits_checks_nothing() {
+        assert!(PayloadLimits::new(&test_worker_cfg().build().unwrap()).is_none());
+    }
+
+    fn query_response(query_id: &str, data_len: usize) -> QueryResult {
+        QueryResult {
+            query_id: query_id.to_string(),
+            variant: Some(query_result::Variant::Succeeded(QuerySuccess {
+                response: Some("a".repeat(data_len).as_json_payload().unwrap()),
+            })),
+        }
+    }
+
+    #[rstest::rstest]
+    #[case::fail(OversizedQueryResponsePolicy::Fail)]
+    #[case::truncate(OversizedQueryResponsePolicy::Truncate)]
+    fn small_query_responses_untouched(#[case] policy: OversizedQueryResponsePolicy) {
+        let limits = limits(|c| {
+            c.max_query_response_bytes = Some(1000);
+            c.oversized_query_response_policy = policy;
+        });
+        let mut response = query_response("q1", 100);
+        limits.limit_query_response(&mut response);
+        assert_eq!(response, query_response("q1", 100));
+    }
+
+    #[test]
+    fn oversized_query_responses_fail() {
+        let limits = limits(|c| c.max_query_response_bytes = Some(1000));
+        let mut response = query_response("q1", 2000);
+        limits.limit_query_response(&mut response);
+        assert_matches!(
+            response.variant,
+            Some(query_result::Variant::Failed(f))
+                if f.message.starts_with("PayloadTooLarge: payloads of response to query q1")
+        );
+    }
+
+    #[test]
+    fn oversized_query_responses_truncated_with_marker() {
+        let limits = limits(|c| {
+            c.max_query_response_bytes = Some(1000);
+            c.oversized_query_response_policy = OversizedQueryResponsePolicy::Truncate;
+        });
+        let mut response = query_response("q1", 2000);
+        limits.limit_query_response(&mut response);
+        assert!(payloads_size(&mut response) < 1000);
+        let payload = assert_matches!(
+            response.variant,
+            Some(query_result::Variant::Succeeded(QuerySuccess { response: Some(p) })) => p
+        );
+        // The original data is the JSON string, quotes included
+        assert_eq!(
+            payload.metadata[TRUNCATED_QUERY_RESPONSE_METADATA_KEY],
+            b"2002".to_vec()
+        );
+        // What's left is no longer valid JSON
+        assert_eq!(
+            payload.metadata[ENCODING_METADATA_KEY],
+            TRUNCATED_QUERY_RESPONSE_ENCODING.as_bytes()
+        );
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{pollers, pollers::BoxedWFPoller};
+use crate::{
+    pollers,
+    pollers::{BoxedWFPoller, RestartablePoller},
+};
 use crossbeam::queue::SegQueue;
 use temporal_sdk_core_protos::temporal::api::workflowservice::v1::PollWorkflowTaskQueueResponse;
 use tokio::sync::Notify;
@@ -7,7 +10,7 @@ use tokio::sync::Notify;
 /// This struct allows fetching WFTs to be centralized while prioritizing tasks from completes.
 pub(crate) struct WFTSource {
     from_completions: SegQueue<PollWorkflowTaskQueueResponse>,
-    poll_buffer: BoxedWFPoller,
+    poll_buffer: RestartablePoller<PollWorkflowTaskQueueResponse>,
     task_taken_notifier: Notify,
 }
 
@@ -15,7 +18,7 @@ impl WFTSource {
     pub fn new(poller: BoxedWFPoller) -> Self {
         Self {
             from_completions: SegQueue::new(),
-            poll_buffer: poller,
+            poll_buffer: RestartablePoller::new(poller),
             task_taken_notifier: Notify::new(),
         }
     }
@@ -40,6 +43,27 @@ impl WFTSource {
         self.poll_buffer.notify_shutdown();
     }
 
+    /// Stops the pollers until [WFTSource::resume_pollers] provides new ones. Polls made in the
+    /// meantime wait for polling to resume.
+    pub fn pause_pollers(&self) {
+        self.poll_buffer.pause();
+    }
+
+    /// Resume polling with a new poller after [WFTSource::pause_pollers]
+    pub fn resume_pollers(&self, poller: BoxedWFPoller) {
+        self.poll_buffer.resume_with(poller);
+    }
+
+    /// Change the maximum number of concurrent polls, split between sticky and nonsticky queues
+    pub fn set_max_pollers(&self, max: usize) {
+        self.poll_buffer.set_max_pollers(max);
+    }
+
+    /// Returns true if polling is paused by [WFTSource::pause_pollers]
+    pub fn pollers_paused(&self) -> bool {
+        self.poll_buffer.is_paused()
+    }
+
     /// Returns true if there are tasks from completion buffered which need to be handled
     pub fn has_tasks_from_complete(&self) -> bool {
         !self.from_completions.is_empty()
@@ -54,7 +78,7 @@ impl WFTSource {
 
     /// Wait for poll shutdown to complete
     pub async fn shutdown(self) {
-        self.poll_buffer.shutdown_box().await;
+        self.poll_buffer.shutdown().await;
     }
 }
 
@@ -1,8 +1,7 @@
-use crate::{
-    replay::{HistoryInfo, TestHistoryBuilder},
-    worker::client::WorkerClientBag,
-};
+use crate::{replay::HistoryInfo, worker::client::WorkerClientBag};
+use anyhow::bail;
 use futures::{future::BoxFuture, stream, stream::BoxStream, FutureExt, Stream, StreamExt};
+use prost::{DecodeError, Message};
 use std::{
     collections::VecDeque,
     future::Future,
@@ -10,10 +9,13 @@ use std::{
     sync::Arc,
     task::{Context, Poll},
 };
-use temporal_sdk_core_protos::temporal::api::{
-    enums::v1::EventType,
-    history::v1::{History, HistoryEvent},
-    workflowservice::v1::GetWorkflowExecutionHistoryResponse,
+use temporal_sdk_core_protos::{
+    temporal::api::{
+        common::v1::DataBlob,
+        enums::v1::{EncodingType, EventType},
+        history::v1::{History, HistoryEvent},
+    },
+    SerializedHistory, SerializedHistoryPage,
 };
 
 /// A slimmed down version of a poll workflow task response which includes just the info needed
@@ -29,19 +31,78 @@ pub struct HistoryUpdate {
     pub previous_started_event_id: i64,
 }
 
+/// Events from fetched history pages are kept serialized until they are consumed, since decoded
+/// events take several times as much memory. This matters most when replaying very large histories
+/// after a cache miss, where the initial task's events are held until every page before them has
+/// been fetched. Events which arrive already decoded with the workflow task are queued as they are.
 pub struct HistoryPaginator {
     // Potentially this could actually be a ref w/ lifetime here
     client: Arc<WorkerClientBag>,
-    event_queue: VecDeque<HistoryEvent>,
+    event_queue: VecDeque<QueuedEvent>,
     wf_id: String,
     run_id: String,
     next_page_token: NextPageToken,
-    open_history_request:
-        Option<BoxFuture<'static, Result<GetWorkflowExecutionHistoryResponse, tonic::Status>>>,
+    open_history_request: Option<BoxFuture<'static, Result<SerializedHistoryPage, tonic::Status>>>,
     /// These are events that should be returned once pagination has finished. This only happens
     /// during cache misses, where we got a partial task but need to fetch history from the start.
     /// We use this to apply any
-    final_events: Vec<HistoryEvent>,
+    final_events: Vec<QueuedEvent>,
+}
+
+/// A history event waiting to be consumed from the paginator
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)] // Boxing would cost an allocation per decoded event
+enum QueuedEvent {
+    Decoded(HistoryEvent),
+    Serialized(SerializedEvent),
+}
+
+impl QueuedEvent {
+    fn event_id(&self) -> i64 {
+        match self {
+            QueuedEvent::Decoded(e) => e.event_id,
+            QueuedEvent::Serialized(e) => e.event_id,
+        }
+    }
+}
+
+/// A history event which has not been decoded yet
+#[derive(Debug)]
+struct SerializedEvent {
+    event_id: i64,
+    bytes: Vec<u8>,
+}
+
+/// Decodes just the id of a history event, skipping all its other fields
+#[derive(Clone, PartialEq, Message)]
+struct EventId {
+    #[prost(int64, tag = "1")]
+    event_id: i64,
+}
+
+impl SerializedEvent {
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            event_id: EventId::decode(bytes.as_slice())?.event_id,
+            bytes,
+        })
+    }
+
+    fn decode(&self) -> Result<HistoryEvent, DecodeError> {
+        HistoryEvent::decode(self.bytes.as_slice())
+    }
+}
+
+/// Events in a raw history blob, which the server may send instead of history if it is configured
+/// to. The blob is a serialized [History], which is split into its events without decoding them.
+fn events_from_raw_history(blob: DataBlob) -> Result<Vec<Vec<u8>>, anyhow::Error> {
+    if blob.encoding_type != EncodingType::Proto3 as i32 {
+        bail!(
+            "Raw history has unsupported encoding {:?}",
+            EncodingType::from_i32(blob.encoding_type)
+        );
+    }
+    Ok(SerializedHistory::decode(blob.data.as_slice())?.events)
 }
 
 #[derive(Clone, Debug)]
@@ -74,11 +135,16 @@ impl HistoryPaginator {
         client: Arc<WorkerClientBag>,
     ) -> Self {
         let next_page_token = next_page_token.into();
+        let initial_events: Vec<_> = initial_history
+            .events
+            .into_iter()
+            .map(QueuedEvent::Decoded)
+            .collect();
         let (event_queue, final_events) =
             if matches!(next_page_token, NextPageToken::FetchFromStart) {
-                (VecDeque::new(), initial_history.events)
+                (VecDeque::new(), initial_events)
             } else {
-                (initial_history.events.into(), vec![])
+                (initial_events.into(), vec![])
             };
         Self {
             client,
@@ -91,22 +157,50 @@ impl HistoryPaginator {
         }
     }
 
-    fn extend_queue_with_new_page(&mut self, resp: GetWorkflowExecutionHistoryResponse) {
+    fn extend_queue_with_new_page(
+        &mut self,
+        resp: SerializedHistoryPage,
+    ) -> Result<(), anyhow::Error> {
         self.next_page_token = resp.next_page_token.into();
-        self.event_queue
-            .extend(resp.history.map(|h| h.events).unwrap_or_default());
+        let events = match resp.history {
+            Some(history) => history.events,
+            None => {
+                let mut events = vec![];
+                for blob in resp.raw_history {
+                    events.extend(events_from_raw_history(blob)?);
+                }
+                events
+            }
+        };
+        for bytes in events {
+            self.event_queue
+                .push_back(QueuedEvent::Serialized(SerializedEvent::from_bytes(bytes)?));
+        }
         if matches!(&self.next_page_token, NextPageToken::Done) {
             // If finished, we need to extend the queue with the final events, skipping any
             // which are already present.
-            if let Some(last_event_id) = self.event_queue.back().map(|e| e.event_id) {
+            if let Some(last_event_id) = self.event_queue.back().map(QueuedEvent::event_id) {
                 let final_events = std::mem::take(&mut self.final_events);
                 self.event_queue.extend(
                     final_events
                         .into_iter()
-                        .skip_while(|e2| e2.event_id <= last_event_id),
+                        .skip_while(|e2| e2.event_id() <= last_event_id),
                 );
             }
         };
+        Ok(())
+    }
+
+    fn pop_queued_event(&mut self) -> Option<Result<HistoryEvent, tonic::Status>> {
+        Some(match self.event_queue.pop_front()? {
+            QueuedEvent::Decoded(event) => Ok(event),
+            QueuedEvent::Serialized(event) => event.decode().map_err(|e| {
+                tonic::Status::data_loss(format!(
+                    "Failed to decode history event {}: {}",
+                    event.event_id, e
+                ))
+            }),
+        })
     }
 }
 
@@ -114,8 +208,8 @@ impl Stream for HistoryPaginator {
     type Item = Result<HistoryEvent, tonic::Status>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        if let Some(e) = self.event_queue.pop_front() {
-            return Poll::Ready(Some(Ok(e)));
+        if let Some(e) = self.pop_queued_event() {
+            return Poll::Ready(Some(e));
         }
         let history_req = if let Some(req) = self.open_history_request.as_mut() {
             req
@@ -135,19 +229,27 @@ impl Stream for HistoryPaginator {
             self.open_history_request.insert(resp_fut.boxed())
         };
 
-        return match Future::poll(history_req.as_mut(), cx) {
+        match Future::poll(history_req.as_mut(), cx) {
             Poll::Ready(resp) => {
                 self.open_history_request = None;
                 match resp {
                     Err(neterr) => Poll::Ready(Some(Err(neterr))),
                     Ok(resp) => {
-                        self.extend_queue_with_new_page(resp);
-                        Poll::Ready(self.event_queue.pop_front().map(Ok))
+                        if let Some(capture) = self.client.activation_capture() {
+                            capture.history_page(&self.run_id, &resp);
+                        }
+                        if let Err(e) = self.extend_queue_with_new_page(resp) {
+                            return Poll::Ready(Some(Err(tonic::Status::data_loss(format!(
+                                "Failed to read history page: {:?}",
+                                e
+                            )))));
+                        }
+                        Poll::Ready(self.pop_queued_event())
                     }
                 }
             }
             Poll::Pending => Poll::Pending,
-        };
+        }
     }
 }
 
@@ -291,11 +393,13 @@ impl From<HistoryInfo> for HistoryUpdate {
     }
 }
 
+#[cfg(test)]
 pub trait TestHBExt {
     fn as_history_update(&self) -> HistoryUpdate;
 }
 
-impl TestHBExt for TestHistoryBuilder {
+#[cfg(test)]
+impl TestHBExt for crate::replay::TestHistoryBuilder {
     fn as_history_update(&self) -> HistoryUpdate {
         self.get_full_history_info().unwrap().into()
     }
@@ -305,6 +409,7 @@ impl TestHBExt for TestHistoryBuilder {
 pub mod tests {
     use super::*;
     use crate::{test_help::canned_histories, worker::client::mocks::mock_workflow_client};
+    use temporal_sdk_core_protos::temporal::api::workflowservice::v1::GetWorkflowExecutionHistoryResponse;
 
     #[tokio::test]
     async fn consumes_standard_wft_sequence() {
@@ -374,7 +479,8 @@ pub mod tests {
                     raw_history: vec![],
                     next_page_token: vec![npt],
                     archived: false,
-                })
+                }
+                .into())
             });
 
         let mut update = HistoryUpdate::new(
@@ -419,7 +525,7 @@ pub mod tests {
         let mut mock_client = mock_workflow_client();
         mock_client
             .expect_get_workflow_execution_history()
-            .returning(move |_, _, _| Ok(history_from_get.clone()));
+            .returning(move |_, _, _| Ok(history_from_get.clone().into()));
 
         let mut update = HistoryUpdate::new(
             HistoryPaginator::new(
@@ -441,4 +547,57 @@ pub mod tests {
         // end of the event iteration after fetching the old history.
         assert_eq!(seq.last().unwrap().event_id, 8);
     }
+
+    #[tokio::test]
+    async fn reads_raw_history_pages() {
+        let timer_hist = canned_histories::single_timer("t");
+        let full_hist: History = timer_hist.get_full_history_info().unwrap().into();
+        let (first_half, second_half) = full_hist.events.split_at(4);
+        let blobs = [first_half, second_half]
+            .into_iter()
+            .map(|events| DataBlob {
+                encoding_type: EncodingType::Proto3 as i32,
+                data: History {
+                    events: events.to_vec(),
+                }
+                .encode_to_vec(),
+            })
+            .collect::<Vec<_>>();
+        let mut mock_client = mock_workflow_client();
+        mock_client
+            .expect_get_workflow_execution_history()
+            .returning(move |_, _, _| {
+                Ok(GetWorkflowExecutionHistoryResponse {
+                    history: None,
+                    raw_history: blobs.clone(),
+                    next_page_token: vec![],
+                    archived: false,
+                }
+                .into())
+            });
+
+        let mut update = HistoryUpdate::new(
+            HistoryPaginator::new(
+                History::default(),
+                "wfid".to_string(),
+                "runid".to_string(),
+                NextPageToken::FetchFromStart,
+                Arc::new(mock_client.into()),
+            ),
+            0,
+        );
+        let mut events = update.take_next_wft_sequence(0).await.unwrap();
+        events.extend(update.take_next_wft_sequence(3).await.unwrap());
+        assert_eq!(events, full_hist.events);
+    }
+
+    #[test]
+    fn unsupported_raw_history_encoding_rejected() {
+        let err = events_from_raw_history(DataBlob {
+            encoding_type: EncodingType::Json as i32,
+            data: b"{}".to_vec(),
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("unsupported encoding"));
+    }
 }
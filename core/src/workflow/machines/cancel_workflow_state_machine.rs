@@ -26,9 +26,6 @@ fsm! {
         --> CancelWorkflowCommandRecorded;
 }
 
-#[derive(thiserror::Error, Debug)]
-pub(super) enum CancelWorkflowMachineError {}
-
 #[derive(Debug, derive_more::Display)]
 pub(super) enum CancelWorkflowCommand {}
 
@@ -104,9 +104,6 @@ impl Created {
     }
 }
 
-#[derive(thiserror::Error, Debug)]
-pub(super) enum CompleteWorkflowMachineError {}
-
 #[derive(Default, Clone)]
 pub(super) struct CompleteWorkflowCommandCreated {}
 
@@ -185,14 +185,14 @@ mod tests {
                 (
                     String::from(k1),
                     Payload {
-                        data: vec![0x01],
+                        data: vec![0x01].into(),
                         ..Default::default()
                     },
                 ),
                 (
                     String::from(k2),
                     Payload {
-                        data: vec![0x02],
+                        data: vec![0x02].into(),
                         ..Default::default()
                     },
                 ),
@@ -29,12 +29,13 @@ use crate::{
 use siphasher::sip::SipHasher13;
 use slotmap::SlotMap;
 use std::{
-    borrow::{Borrow, BorrowMut},
+    borrow::BorrowMut,
     collections::{HashMap, VecDeque},
     convert::TryInto,
     hash::{Hash, Hasher},
     time::{Duration, Instant, SystemTime},
 };
+use temporal_sdk_core_api::worker::UnknownHistoryEventPolicy;
 use temporal_sdk_core_protos::{
     coresdk::{
         common::NamespacedWorkflowExecution,
@@ -124,6 +125,9 @@ pub(crate) struct WorkflowMachines {
 
     /// Metrics context
     pub metrics: MetricsContext,
+
+    /// What to do with history events of types we don't recognize
+    pub unknown_event_policy: UnknownHistoryEventPolicy,
 }
 
 #[derive(Debug, derive_more::Display)]
@@ -227,6 +231,7 @@ impl WorkflowMachines {
             encountered_change_markers: Default::default(),
             local_activity_data: LocalActivityData::default(),
             have_seen_terminal_event: false,
+            unknown_event_policy: Default::default(),
         }
     }
 
@@ -300,6 +305,11 @@ impl WorkflowMachines {
         self.local_activity_data.outstanding_la_count()
     }
 
+    /// Returns the event id of the last workflow task started event which has been handled
+    pub(crate) fn current_started_event_id(&self) -> i64 {
+        self.current_started_event_id
+    }
+
     /// Returns start info for the workflow if it has started
     pub(crate) fn get_started_info(&self) -> Option<&WorkflowStartedInfo> {
         self.drive_me.get_started_info()
@@ -315,6 +325,9 @@ impl WorkflowMachines {
     /// invalid state.
     #[instrument(level = "debug", skip(self, event), fields(event=%event))]
     fn handle_event(&mut self, event: HistoryEvent, has_next_event: bool) -> Result<()> {
+        if EventType::from_i32(event.event_type).is_none() {
+            return self.handle_unknown_event(event);
+        }
         if event.is_final_wf_execution_event() {
             self.have_seen_terminal_event = true;
         }
@@ -360,6 +373,35 @@ impl WorkflowMachines {
         Ok(())
     }
 
+    /// Handle an event whose type we don't recognize, most likely because it was added in a newer
+    /// server version, according to [WorkflowMachines::unknown_event_policy]
+    fn handle_unknown_event(&mut self, event: HistoryEvent) -> Result<()> {
+        match self.unknown_event_policy {
+            UnknownHistoryEventPolicy::FailTask => Err(WFMachinesError::Fatal(format!(
+                "Encountered history event of unknown type {}: {}",
+                event.event_type, event
+            ))),
+            UnknownHistoryEventPolicy::Skip => {
+                debug!(
+                    event_type = event.event_type,
+                    event_id = event.event_id,
+                    "Skipping history event of unknown type"
+                );
+                Ok(())
+            }
+            UnknownHistoryEventPolicy::SkipWithWarning => {
+                warn!(
+                    event_type = event.event_type,
+                    event_id = event.event_id,
+                    run_id = %self.run_id,
+                    "Skipping history event of unknown type"
+                );
+                self.metrics.wf_unknown_history_event();
+                Ok(())
+            }
+        }
+    }
+
     /// Called when a workflow task started event has triggered. Ensures we are tracking the ID
     /// of the current started event as well as workflow time properly.
     fn task_started(&mut self, task_started_event_id: i64, time: SystemTime) -> Result<()> {
@@ -565,6 +607,8 @@ impl WorkflowMachines {
             is_replaying: self.replaying,
             run_id: self.run_id.clone(),
             jobs,
+            // Filled in by the workflow task manager, which knows about the task
+            workflow_task_attempt: 0,
         }
     }
 
@@ -573,7 +617,7 @@ impl WorkflowMachines {
     }
 
     fn set_current_time(&mut self, time: SystemTime) -> SystemTime {
-        if self.current_wf_time.map_or(true, |t| t < time) {
+        if self.current_wf_time.is_none_or(|t| t < time) {
             self.current_wf_time = Some(time);
         }
         self.current_wf_time
@@ -601,7 +645,7 @@ impl WorkflowMachines {
     /// any events that need to be replayed until caught up to the newest WFT.
     pub(crate) async fn apply_next_wft_from_history(&mut self) -> Result<usize> {
         // A much higher-up span (ex: poll) may want this field filled
-        tracing::Span::current().record("run_id", &self.run_id.as_str());
+        tracing::Span::current().record("run_id", self.run_id.as_str());
 
         // If we have already seen the terminal event for the entire workflow in a previous WFT,
         // then we don't need to do anything here, and in fact we need to avoid re-applying the
@@ -1044,10 +1088,7 @@ impl WorkflowMachines {
     }
 
     fn machine(&self, m: MachineKey) -> &Machines {
-        self.all_machines
-            .get(m)
-            .expect("Machine must exist")
-            .borrow()
+        self.all_machines.get(m).expect("Machine must exist")
     }
 
     fn machine_mut(&mut self, m: MachineKey) -> &mut Machines {
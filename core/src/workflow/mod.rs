@@ -16,6 +16,7 @@ use crate::{
 };
 use machines::WorkflowMachines;
 use std::{result, sync::mpsc::Sender, time::Duration};
+use temporal_sdk_core_api::worker::UnknownHistoryEventPolicy;
 use temporal_sdk_core_protos::{
     coresdk::{workflow_activation::WorkflowActivation, workflow_commands::*},
     temporal::api::command::v1::Command as ProtoCommand,
@@ -44,9 +45,10 @@ impl WorkflowManager {
         workflow_type: String,
         run_id: String,
         metrics: MetricsContext,
+        unknown_event_policy: UnknownHistoryEventPolicy,
     ) -> Self {
         let (wfb, cmd_sink) = WorkflowBridge::new();
-        let state_machines = WorkflowMachines::new(
+        let mut state_machines = WorkflowMachines::new(
             namespace,
             workflow_id,
             workflow_type,
@@ -55,6 +57,7 @@ impl WorkflowManager {
             Box::new(wfb).into(),
             metrics,
         );
+        state_machines.unknown_event_policy = unknown_event_policy;
         Self {
             machines: state_machines,
             command_sink: Some(cmd_sink),
@@ -356,6 +359,7 @@ pub mod managed_wf {
                 TEST_Q.to_string(),
                 args,
                 completions_tx,
+                None,
             );
             let spawned = tokio::spawn(wff);
             let driver = WFFutureDriver { completions_rx };
@@ -79,7 +79,7 @@ impl WorkflowCacheManager {
         } else {
             let maybe_got_evicted = self.cache.peek_lru().map(|r| r.0.clone());
             let not_cached = self.cache.put(run_id.to_owned(), ()).is_none();
-            not_cached.then(|| maybe_got_evicted).flatten()
+            not_cached.then_some(maybe_got_evicted).flatten()
         };
 
         self.size_changed();
@@ -97,6 +97,28 @@ impl WorkflowCacheManager {
         self.size_changed();
     }
 
+    /// Changes the maximum number of cached runs. If the cache holds more runs than the new
+    /// capacity, the least recently used ones are dropped and returned so they can be evicted.
+    /// Caches which were created with no capacity (non-sticky) can't be resized.
+    pub fn set_capacity(&mut self, cap: usize) -> Vec<String> {
+        if self.cache.cap() == 0 || cap == 0 {
+            return vec![];
+        }
+        let mut evicted = vec![];
+        while self.cache.len() > cap {
+            if let Some((run_id, _)) = self.cache.pop_lru() {
+                evicted.push(run_id);
+            }
+        }
+        self.cache.resize(cap);
+        self.size_changed();
+        evicted
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.cache.cap()
+    }
+
     fn size_changed(&self) {
         let size = self.cache.len();
         self.metrics.cache_size(size as u64);
@@ -168,6 +190,21 @@ mod tests {
         });
     }
 
+    #[test]
+    fn shrinking_evicts_least_recently_used() {
+        let mut cm = WorkflowCacheManager::new_test(WorkflowCachingPolicy::Sticky {
+            max_cached_workflows: 3,
+        });
+        cm.insert("1");
+        cm.insert("2");
+        cm.insert("3");
+        cm.touch("1");
+        assert_eq!(cm.set_capacity(1), vec!["2".to_string(), "3".to_string()]);
+        assert_eq!(cm.capacity(), 1);
+        assert!(cm.set_capacity(5).is_empty());
+        assert_eq!(cm.insert("2"), None);
+    }
+
     #[test]
     fn zero_cache_size() {
         let mut wcm = WorkflowCacheManager::new_test(WorkflowCachingPolicy::Sticky {
@@ -1,32 +1,55 @@
 use crate::{
     protosext::ValidPollWFTQResponse,
-    telemetry::metrics::{workflow_type, MetricsContext},
+    telemetry::{
+        metrics::{workflow_type, MetricsContext},
+        propagation::extract_context,
+    },
     workflow::{
         workflow_tasks::{OutstandingActivation, OutstandingTask, WorkflowMissingError},
         HistoryUpdate, Result, WFMachinesError, WorkflowManager,
     },
 };
 use futures::future::{BoxFuture, FutureExt};
+use opentelemetry::Context;
 use parking_lot::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use std::{
-    collections::HashMap,
+    collections::{hash_map::RandomState, HashMap},
     fmt::Debug,
+    hash::BuildHasher,
     ops::{Deref, DerefMut},
     sync::Arc,
 };
-use temporal_sdk_core_protos::coresdk::workflow_activation::WorkflowActivation;
+use temporal_sdk_core_api::worker::UnknownHistoryEventPolicy;
+use temporal_sdk_core_protos::coresdk::workflow_activation::{
+    workflow_activation_job, WorkflowActivation,
+};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Number of shards the run map is split into. Runs are assigned to shards by hashing their id, so
+/// that accessing one run never contends with accessing a run in another shard.
+const RUN_SHARDS: usize = 32;
+
+type RunMap = HashMap<String, ManagedRun>;
 
 /// Provides a thread-safe way to access workflow machines for specific workflow runs
 pub(crate) struct WorkflowConcurrencyManager {
-    /// Maps run id -> data about and machines for that run
-    runs: RwLock<HashMap<String, ManagedRun>>,
+    /// Maps run id -> data about and machines for that run, sharded by run id
+    shards: Vec<RwLock<RunMap>>,
+    hasher: RandomState,
+    /// Given to the machines of every run created
+    unknown_event_policy: UnknownHistoryEventPolicy,
 }
 
 struct ManagedRun {
     wfm: Arc<Mutex<WorkflowManager>>,
     wft: Option<OutstandingTask>,
     activation: Option<OutstandingActivation>,
+    /// Covers the round trip of the outstanding activation, from issue to completion
+    activation_span: Option<Span>,
     metrics: MetricsContext,
+    /// Trace context from the workflow's start headers, which spans for this run are parented to
+    trace_parent: Option<Context>,
     /// If set, it indicates there is a buffered poll response from the server that applies to this
     /// run. This can happen when lang takes too long to complete a task and the task times out, for
     /// example. Upon next completion, the buffered response will be removed and can be made ready
@@ -35,31 +58,40 @@ struct ManagedRun {
 }
 
 impl ManagedRun {
-    fn new(wfm: WorkflowManager, metrics: MetricsContext) -> Self {
+    fn new(wfm: WorkflowManager, metrics: MetricsContext, trace_parent: Option<Context>) -> Self {
         Self {
             wfm: Arc::new(Mutex::new(wfm)),
             wft: None,
             activation: None,
+            activation_span: None,
             metrics,
+            trace_parent,
             buffered_resp: None,
         }
     }
 }
 
 impl WorkflowConcurrencyManager {
-    pub fn new() -> Self {
+    pub fn new(unknown_event_policy: UnknownHistoryEventPolicy) -> Self {
         Self {
-            runs: Default::default(),
+            shards: (0..RUN_SHARDS).map(|_| Default::default()).collect(),
+            hasher: RandomState::new(),
+            unknown_event_policy,
         }
     }
 
+    /// The shard of the run map which `run_id` belongs to
+    fn runs(&self, run_id: &str) -> &RwLock<RunMap> {
+        &self.shards[self.hasher.hash_one(run_id) as usize % RUN_SHARDS]
+    }
+
     /// Allows access to outstanding task for a run. Returns `None` if there is no knowledge of
     /// the run at all, or if the run exists but there is no outstanding workflow task.
     pub(crate) fn get_task(
         &self,
         run_id: &str,
     ) -> Option<impl Deref<Target = OutstandingTask> + '_> {
-        let readlock = self.runs.read();
+        let readlock = self.runs(run_id).read();
         if let Some(run) = readlock.get(run_id) {
             if run.wft.is_some() {
                 Some(RwLockReadGuard::map(readlock, |hm| {
@@ -77,7 +109,7 @@ impl WorkflowConcurrencyManager {
     /// Allows access to outstanding activation slot for a run. Returns `None` if there is no
     /// knowledge of the run at all, or if the run exists but there is no outstanding activation.
     pub(crate) fn get_activation(&self, run_id: &str) -> Option<OutstandingActivation> {
-        let readlock = self.runs.read();
+        let readlock = self.runs(run_id).read();
         if readlock.contains_key(run_id) {
             readlock.get(run_id).unwrap().activation
         } else {
@@ -90,7 +122,7 @@ impl WorkflowConcurrencyManager {
         &self,
         run_id: &str,
     ) -> Result<impl DerefMut<Target = Option<OutstandingTask>> + '_, WorkflowMissingError> {
-        let writelock = self.runs.write();
+        let writelock = self.runs(run_id).write();
         if writelock.contains_key(run_id) {
             Ok(RwLockWriteGuard::map(writelock, |hm| {
                 // Unwrap is safe because we hold the lock and just ensured run is in the map
@@ -103,12 +135,28 @@ impl WorkflowConcurrencyManager {
         }
     }
 
+    /// Trace context the run's spans should be parented to, if its start headers carried any
+    pub(crate) fn run_trace_parent(&self, run_id: &str) -> Option<Context> {
+        self.runs(run_id)
+            .read()
+            .get(run_id)
+            .and_then(|run| run.trace_parent.clone())
+    }
+
+    /// The span covering the run's outstanding activation, if there is one
+    pub(crate) fn activation_span(&self, run_id: &str) -> Option<Span> {
+        self.runs(run_id)
+            .read()
+            .get(run_id)
+            .and_then(|run| run.activation_span.clone())
+    }
+
     /// Fetch metrics context for a run
     pub(crate) fn run_metrics(
         &self,
         run_id: &str,
     ) -> Option<impl Deref<Target = MetricsContext> + '_> {
-        let readlock = self.runs.read();
+        let readlock = self.runs(run_id).read();
         if readlock.get(run_id).is_some() {
             Some(RwLockReadGuard::map(readlock, |hm| {
                 // Unwraps are safe because we hold the lock and just ensured run is in the map
@@ -125,9 +173,9 @@ impl WorkflowConcurrencyManager {
         &self,
         work: ValidPollWFTQResponse,
     ) -> Option<ValidPollWFTQResponse> {
-        let mut writelock = self.runs.write();
         let run_id = &work.workflow_execution.run_id;
-        if let Some(mut run) = writelock.get_mut(run_id) {
+        let mut writelock = self.runs(run_id).write();
+        if let Some(run) = writelock.get_mut(run_id) {
             if run.wft.is_some() || run.activation.is_some() {
                 debug!(run_id = %run_id, "Got new WFT for a run with outstanding work");
                 run.buffered_resp = Some(work);
@@ -186,9 +234,19 @@ impl WorkflowConcurrencyManager {
         run_id: &str,
         activation: OutstandingActivation,
     ) -> Result<Option<OutstandingActivation>, WorkflowMissingError> {
-        let mut writelock = self.runs.write();
+        let mut writelock = self.runs(run_id).write();
         let machine_ref = writelock.get_mut(run_id);
         if let Some(run) = machine_ref {
+            let span = if let Some(wft) = run.wft.as_ref() {
+                info_span!(parent: &wft.span, "workflow_activation", %run_id)
+            } else {
+                let span = info_span!(parent: None, "workflow_activation", %run_id);
+                if let Some(cx) = run.trace_parent.clone() {
+                    span.set_parent(cx);
+                }
+                span
+            };
+            run.activation_span = Some(span);
             Ok(run.activation.replace(activation))
         } else {
             Err(WorkflowMissingError {
@@ -198,13 +256,16 @@ impl WorkflowConcurrencyManager {
     }
 
     pub fn delete_activation(&self, run_id: &str) -> Option<OutstandingActivation> {
-        let mut writelock = self.runs.write();
+        let mut writelock = self.runs(run_id).write();
         let machine_ref = writelock.get_mut(run_id);
-        machine_ref.and_then(|run| run.activation.take())
+        machine_ref.and_then(|run| {
+            run.activation_span = None;
+            run.activation.take()
+        })
     }
 
     pub fn exists(&self, run_id: &str) -> bool {
-        self.runs.read().get(run_id).is_some()
+        self.runs(run_id).read().get(run_id).is_some()
     }
 
     /// Create or update some workflow's machines. Borrowed arguments are cloned in the case of a
@@ -220,7 +281,7 @@ impl WorkflowConcurrencyManager {
     ) -> Result<WorkflowActivation> {
         let span = debug_span!("create_or_update machines", %run_id);
 
-        if self.runs.read().contains_key(run_id) {
+        if self.runs(run_id).read().contains_key(run_id) {
             let activation = self
                 .access(run_id, move |wfm: &mut WorkflowManager| {
                     async move {
@@ -243,6 +304,7 @@ impl WorkflowConcurrencyManager {
                 wf_type.to_owned(),
                 run_id.to_owned(),
                 metrics.clone(),
+                self.unknown_event_policy,
             );
             match wfm.get_next_activation().await {
                 Ok(activation) => {
@@ -251,9 +313,16 @@ impl WorkflowConcurrencyManager {
                             "Machines created with no jobs".to_string(),
                         ))
                     } else {
-                        self.runs
-                            .write()
-                            .insert(run_id.to_string(), ManagedRun::new(wfm, metrics));
+                        let trace_parent = activation.jobs.iter().find_map(|j| match &j.variant {
+                            Some(workflow_activation_job::Variant::StartWorkflow(sw)) => {
+                                extract_context(&sw.headers)
+                            }
+                            _ => None,
+                        });
+                        self.runs(run_id).write().insert(
+                            run_id.to_string(),
+                            ManagedRun::new(wfm, metrics, trace_parent),
+                        );
                         Ok(activation)
                     }
                 }
@@ -262,6 +331,7 @@ impl WorkflowConcurrencyManager {
         }
     }
 
+    #[allow(clippy::await_holding_lock)]
     pub async fn access<F, Fout>(&self, run_id: &str, mutator: F) -> Result<Fout>
     where
         F: for<'a> FnOnce(&'a mut WorkflowManager) -> BoxFuture<Result<Fout>>,
@@ -272,7 +342,7 @@ impl WorkflowConcurrencyManager {
         //  We should restructure things to avoid the top-level lock on the map.
 
         let wfm = {
-            let readlock = self.runs.read();
+            let readlock = self.runs(run_id).read();
             let m = readlock
                 .get(run_id)
                 .ok_or_else(|| WFMachinesError::Fatal("Missing workflow machines".to_string()))?;
@@ -292,7 +362,7 @@ impl WorkflowConcurrencyManager {
         F: for<'a> FnOnce(&'a mut WorkflowManager) -> Fout,
         Fout: Send + Debug,
     {
-        let readlock = self.runs.read();
+        let readlock = self.runs(run_id).read();
         let m = readlock.get(run_id).ok_or_else(|| WorkflowMissingError {
             run_id: run_id.to_string(),
         })?;
@@ -302,37 +372,44 @@ impl WorkflowConcurrencyManager {
 
     /// Remove the workflow with the provided run id from management
     pub fn evict(&self, run_id: &str) -> Option<ValidPollWFTQResponse> {
-        let val = self.runs.write().remove(run_id);
+        let val = self.runs(run_id).write().remove(run_id);
         val.and_then(|v| v.buffered_resp)
     }
 
     /// Clear and return any buffered polling response for this run ID
     pub fn take_buffered_poll(&self, run_id: &str) -> Option<ValidPollWFTQResponse> {
-        let mut writelock = self.runs.write();
+        let mut writelock = self.runs(run_id).write();
         let val = writelock.get_mut(run_id);
         val.and_then(|v| v.buffered_resp.take())
     }
 
     /// Sounds the total number of outstanding workflow tasks
     pub fn outstanding_wft(&self) -> usize {
-        self.runs
-            .read()
+        self.shards
             .iter()
-            .filter(|(_, run)| run.wft.is_some())
-            .count()
+            .map(|shard| {
+                shard
+                    .read()
+                    .values()
+                    .filter(|run| run.wft.is_some())
+                    .count()
+            })
+            .sum()
     }
 
     /// Returns number of currently cached workflows
     pub fn cached_workflows(&self) -> usize {
-        self.runs.read().len()
+        self.shards.iter().map(|shard| shard.read().len()).sum()
     }
 
     /// Returns true if any outstanding activation contains an eviction
     pub fn are_outstanding_evictions(&self) -> bool {
-        self.runs
-            .read()
-            .values()
-            .any(|mr| mr.activation.map(|a| a.has_eviction()).unwrap_or_default())
+        self.shards.iter().any(|shard| {
+            shard
+                .read()
+                .values()
+                .any(|mr| mr.activation.map(|a| a.has_eviction()).unwrap_or_default())
+        })
     }
 }
 
@@ -348,7 +425,7 @@ mod tests {
 
     #[tokio::test]
     async fn returns_errors_on_creation() {
-        let mgr = WorkflowConcurrencyManager::new();
+        let mgr = WorkflowConcurrencyManager::new(Default::default());
         let res = mgr
             .create_or_update(
                 "some_run_id",
@@ -373,7 +450,7 @@ mod tests {
         let access_barr: &'static Barrier = Box::leak(Box::new(Barrier::new(2)));
         let wft = timer_hist.get_history_info(1).unwrap();
 
-        let mgr = WorkflowConcurrencyManager::new();
+        let mgr = WorkflowConcurrencyManager::new(Default::default());
         mgr.create_or_update(
             run_id,
             wft.clone().into(),
@@ -415,4 +492,31 @@ mod tests {
         let (r1, _) = tokio::join!(access_fut, write_fut);
         r1.unwrap();
     }
+
+    #[tokio::test]
+    async fn counts_runs_across_shards() {
+        let timer_hist = canned_histories::single_timer("t");
+        let wft = timer_hist.get_history_info(1).unwrap();
+        let mgr = WorkflowConcurrencyManager::new(Default::default());
+        let run_ids: Vec<_> = (0..RUN_SHARDS * 2).map(|i| format!("run_{}", i)).collect();
+        for run_id in &run_ids {
+            mgr.create_or_update(
+                run_id,
+                wft.clone().into(),
+                "fake_wf_id",
+                "fake_namespace",
+                "fake_wf_type",
+                &Default::default(),
+            )
+            .await
+            .unwrap();
+        }
+        assert_eq!(mgr.cached_workflows(), run_ids.len());
+        assert!(run_ids.iter().all(|r| mgr.exists(r)));
+        for run_id in &run_ids[..RUN_SHARDS] {
+            mgr.evict(run_id);
+        }
+        assert_eq!(mgr.cached_workflows(), RUN_SHARDS);
+        assert!(!mgr.exists(&run_ids[0]));
+    }
 }
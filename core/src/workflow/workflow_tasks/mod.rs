@@ -6,8 +6,14 @@ mod concurrency_manager;
 use crate::{
     pending_activations::PendingActivations,
     protosext::{ValidPollWFTQResponse, WorkflowActivationExt},
-    telemetry::metrics::MetricsContext,
-    worker::{client::WorkerClientBag, LocalActRequest, LocalActivityResolution},
+    telemetry::{
+        events::CoreEventEmitter,
+        metrics::{command_type, MetricsContext},
+    },
+    worker::{
+        client::WorkerClientBag, payload_limits::payloads_size, LocalActRequest,
+        LocalActivityResolution,
+    },
     workflow::{
         history_update::NextPageToken,
         machines::WFMachinesError,
@@ -28,6 +34,10 @@ use std::{
     sync::Arc,
     time::{Duration, Instant},
 };
+use temporal_sdk_core_api::{
+    events::CoreEvent,
+    worker::{UnknownHistoryEventPolicy, WftFailureBackoffConfig},
+};
 use temporal_sdk_core_protos::{
     coresdk::{
         workflow_activation::{
@@ -36,10 +46,18 @@ use temporal_sdk_core_protos::{
         },
         workflow_commands::QueryResult,
     },
-    temporal::api::command::v1::Command as ProtoCommand,
+    temporal::api::{
+        command::v1::Command as ProtoCommand, enums::v1::CommandType,
+        history::v1::history_event::Attributes,
+    },
     TaskToken,
 };
-use tokio::{sync::Notify, time::timeout_at};
+use tokio::{
+    sync::Notify,
+    time::{sleep, sleep_until},
+};
+use tracing::{Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 /// What percentage of a WFT timeout we are willing to wait before sending a WFT heartbeat when
 /// necessary.
@@ -60,9 +78,18 @@ pub struct WorkflowTaskManager {
     /// Activations may only be added here for runs which do not have other pending activations.
     pending_queries: SegQueue<WorkflowActivation>,
     /// Holds poll wft responses from the server that need to be applied
-    ready_buffered_wft: SegQueue<ValidPollWFTQResponse>,
+    ready_buffered_wft: Arc<SegQueue<ValidPollWFTQResponse>>,
+    /// Runs (and the task token of their WFT) whose heartbeat deadline has arrived while they were
+    /// waiting on local activities. See [WorkflowTaskManager::next_due_heartbeat].
+    heartbeats_due: Arc<SegQueue<(String, TaskToken)>>,
     /// Used to wake blocked workflow task polling
     pending_activations_notifier: Arc<Notify>,
+    /// Once this many activations are pending, no new work should be polled for. See
+    /// [WorkflowTaskManager::is_overloaded].
+    max_pending_activations: usize,
+    /// If set, retried workflow tasks are held back before being applied. See
+    /// [WorkflowTaskManager::backoff_if_retry].
+    wft_failure_backoff: Option<WftFailureBackoffConfig>,
     /// Lock guarded cache manager, which is the authority for limit-based workflow machine eviction
     /// from the cache.
     // TODO: Also should be moved inside concurrency manager, but there is some complexity around
@@ -70,6 +97,7 @@ pub struct WorkflowTaskManager {
     cache_manager: Mutex<WorkflowCacheManager>,
 
     metrics: MetricsContext,
+    events: CoreEventEmitter,
 }
 
 #[derive(Clone, Debug)]
@@ -78,6 +106,14 @@ pub(crate) struct OutstandingTask {
     /// Set if the outstanding task has quer(ies) which must be fulfilled upon finishing replay
     pub pending_queries: Vec<QueryWorkflow>,
     start_time: Instant,
+    /// Covers the workflow task, from when it is applied until it is reported to the server
+    pub span: Span,
+    /// True while the task's completion is held back because local activities are running
+    awaiting_local_acts: bool,
+    /// True once a watcher has been spawned to request a heartbeat at the task's deadline
+    heartbeat_scheduled: bool,
+    /// Query responses produced while the completion was held back, sent along with it
+    deferred_query_responses: Vec<QueryResult>,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -144,6 +180,14 @@ pub enum FailedActivationOutcome {
     ReportLegacyQueryFailure(TaskToken),
 }
 
+/// Where a run which hit nondeterminism should be reset to
+#[derive(Debug)]
+pub(crate) struct NondeterminismResetPoint {
+    pub workflow_id: String,
+    pub workflow_type: String,
+    pub workflow_task_finish_event_id: i64,
+}
+
 #[derive(Debug)]
 pub(crate) struct ServerCommandsWithWorkflowInfo {
     pub task_token: TaskToken,
@@ -186,16 +230,24 @@ impl WorkflowTaskManager {
     pub(crate) fn new(
         pending_activations_notifier: Arc<Notify>,
         eviction_policy: WorkflowCachingPolicy,
+        max_pending_activations: usize,
+        unknown_event_policy: UnknownHistoryEventPolicy,
+        wft_failure_backoff: Option<WftFailureBackoffConfig>,
         metrics: MetricsContext,
+        events: CoreEventEmitter,
     ) -> Self {
         Self {
-            workflow_machines: WorkflowConcurrencyManager::new(),
+            workflow_machines: WorkflowConcurrencyManager::new(unknown_event_policy),
             pending_activations: Default::default(),
             pending_queries: Default::default(),
             ready_buffered_wft: Default::default(),
+            heartbeats_due: Default::default(),
             pending_activations_notifier,
+            max_pending_activations,
+            wft_failure_backoff,
             cache_manager: Mutex::new(WorkflowCacheManager::new(eviction_policy, metrics.clone())),
             metrics,
+            events,
         }
     }
 
@@ -204,6 +256,23 @@ impl WorkflowTaskManager {
         self.workflow_machines.cached_workflows()
     }
 
+    /// Change the maximum number of cached workflows, evicting the least recently used runs if
+    /// there are now too many. Has no effect if caching is disabled.
+    pub fn set_max_cached_workflows(&self, max: usize) {
+        let evicted = self.cache_manager.lock().set_capacity(max);
+        for run_id in evicted {
+            self.events.emit(CoreEvent::CacheFull {
+                evicting_run_id: run_id.clone(),
+            });
+            self.request_eviction(&run_id, "Workflow cache full", EvictionReason::CacheFull);
+        }
+    }
+
+    /// The maximum number of cached workflows, which is zero if caching is disabled
+    pub fn max_cached_workflows(&self) -> usize {
+        self.cache_manager.lock().capacity()
+    }
+
     /// Resolves once there is either capacity in the cache, or there are no pending evictions.
     /// Inversely: Waits while there are pending evictions and the cache is full.
     /// Waiting while there are no pending evictions must be avoided because it would block forever,
@@ -238,6 +307,9 @@ impl WorkflowTaskManager {
         let maybe_evicted = self.cache_manager.lock().insert(run_id);
 
         if let Some(evicted_run_id) = maybe_evicted {
+            self.events.emit(CoreEvent::CacheFull {
+                evicting_run_id: evicted_run_id.clone(),
+            });
             self.request_eviction(
                 &evicted_run_id,
                 "Workflow cache full",
@@ -246,6 +318,9 @@ impl WorkflowTaskManager {
             debug!(run_id=%poll_resp.workflow_execution.run_id,
                    "Received a WFT for a new run while at the cache limit. Buffering the task.");
             // Buffer the task
+            self.events.emit(CoreEvent::WftBuffered {
+                run_id: run_id.clone(),
+            });
             if let Some(not_buffered) = self
                 .workflow_machines
                 .buffer_resp_if_outstanding_work(poll_resp)
@@ -259,6 +334,30 @@ impl WorkflowTaskManager {
         Some(poll_resp)
     }
 
+    /// The span covering the run's outstanding activation, if there is one. Commands lang sends in
+    /// response carry this span's trace context in their headers.
+    pub(crate) fn activation_span(&self, run_id: &str) -> Option<Span> {
+        self.workflow_machines.activation_span(run_id)
+    }
+
+    /// Number of activations waiting to be issued to lang, including legacy query activations
+    pub(crate) fn pending_activation_count(&self) -> usize {
+        self.pending_activations.len() + self.pending_queries.len()
+    }
+
+    /// Returns true if lang has fallen far enough behind that no new workflow tasks should be
+    /// polled for, since they would only add to the activations waiting on it
+    pub(crate) fn is_overloaded(&self) -> bool {
+        self.pending_activation_count() >= self.max_pending_activations
+    }
+
+    /// Record the depth of the pending activation and buffered task queues
+    pub(crate) fn record_queue_depths(&self) {
+        self.metrics
+            .pending_activations(self.pending_activation_count());
+        self.metrics.buffered_wfts(self.ready_buffered_wft.len());
+    }
+
     pub(crate) fn next_pending_activation(&self) -> Option<WorkflowActivation> {
         // Dispatch pending queries first
         if let leg_q @ Some(_) = self.pending_queries.pop() {
@@ -277,6 +376,11 @@ impl WorkflowTaskManager {
                 .workflow_machines
                 .access_sync(&pending_info.run_id, |wfm| wfm.machines.get_wf_activation())
                 .and_then(|mut act| {
+                    act.workflow_task_attempt = self
+                        .workflow_machines
+                        .get_task(&act.run_id)
+                        .map(|ot| ot.info.attempt)
+                        .unwrap_or_default();
                     // Only evict workflows after all other pending work is complete.
                     if act.jobs.is_empty() {
                         if let Some(reason) = pending_info.needs_eviction {
@@ -313,6 +417,62 @@ impl WorkflowTaskManager {
         self.ready_buffered_wft.pop()
     }
 
+    /// Returns the polled task back if it should be applied right away. Otherwise it is a retry of
+    /// a failed task, which is held back according to the configured backoff and then buffered,
+    /// waking polling once it is ready. It keeps its workflow task permit in the meantime.
+    ///
+    /// The task's timeout is already running while it is held, so the backoff is capped at a
+    /// fraction of it, leaving lang most of the timeout to process the task.
+    pub(crate) fn backoff_if_retry(
+        &self,
+        work: ValidPollWFTQResponse,
+    ) -> Option<ValidPollWFTQResponse> {
+        let backoff = self
+            .wft_failure_backoff
+            .as_ref()
+            .map(|b| {
+                b.backoff_for(work.attempt)
+                    .min(wft_timeout_of(&work) / MAX_BACKOFF_TIMEOUT_DIVISOR)
+            })
+            .unwrap_or_default();
+        if backoff.is_zero() {
+            return Some(work);
+        }
+        debug!(run_id = %work.workflow_execution.run_id, attempt = work.attempt,
+               backoff = ?backoff, "Backing off before applying retried workflow task");
+        let buffer = self.ready_buffered_wft.clone();
+        let notifier = self.pending_activations_notifier.clone();
+        tokio::spawn(async move {
+            sleep(backoff).await;
+            buffer.push(work);
+            notifier.notify_waiters();
+        });
+        None
+    }
+
+    /// Returns a run whose WFT must be heartbeated now, by completing an empty activation for it,
+    /// because its local activities are still running as the WFT's timeout approaches.
+    ///
+    /// Runs which have since moved on are skipped: their WFT was completed or evicted, or they have
+    /// an activation outstanding or pending. In the latter case that activation's completion will
+    /// heartbeat if it is still needed.
+    pub(crate) fn next_due_heartbeat(&self) -> Option<String> {
+        while let Some((run_id, task_token)) = self.heartbeats_due.pop() {
+            let still_awaiting = self
+                .workflow_machines
+                .get_task(&run_id)
+                .map(|ot| ot.awaiting_local_acts && ot.info.task_token == task_token)
+                .unwrap_or_default();
+            if still_awaiting
+                && self.workflow_machines.get_activation(&run_id).is_none()
+                && !self.pending_activations.has_pending(&run_id)
+            {
+                return Some(run_id);
+            }
+        }
+        None
+    }
+
     pub(crate) fn outstanding_wft(&self) -> usize {
         self.workflow_machines.outstanding_wft()
     }
@@ -326,6 +486,43 @@ impl WorkflowTaskManager {
             .access_sync(run_id, |wfm| wfm.machines.last_processed_event)
     }
 
+    /// Returns true if the history of the provided run shows it has ended, ex: because it was
+    /// terminated or timed out
+    pub(crate) fn run_has_ended(&self, run_id: &str) -> bool {
+        self.workflow_machines
+            .access_sync(run_id, |wfm| wfm.machines.have_seen_terminal_event)
+            .unwrap_or_default()
+    }
+
+    /// Returns the started event id of the workflow task the provided run is processing, or last
+    /// processed. `None` if the run isn't known or hasn't started a workflow task.
+    pub(crate) fn current_wft_started_event_id(&self, run_id: &str) -> Option<i64> {
+        self.workflow_machines
+            .access_sync(run_id, |wfm| wfm.machines.current_started_event_id())
+            .ok()
+            .filter(|id| *id > 0)
+    }
+
+    /// Returns where the provided run should be reset to if it hit nondeterminism. That's the end
+    /// of the workflow task whose commands were being matched against history, so the task is
+    /// redone by the new run. `None` if the run isn't known or hasn't handled a workflow task.
+    pub(crate) fn nondeterminism_reset_point(
+        &self,
+        run_id: &str,
+    ) -> Option<NondeterminismResetPoint> {
+        self.workflow_machines
+            .access_sync(run_id, |wfm| {
+                let started_id = wfm.machines.current_started_event_id();
+                (started_id > 0).then(|| NondeterminismResetPoint {
+                    workflow_id: wfm.machines.workflow_id.clone(),
+                    workflow_type: wfm.machines.workflow_type.clone(),
+                    workflow_task_finish_event_id: started_id + 1,
+                })
+            })
+            .ok()
+            .flatten()
+    }
+
     /// Request a workflow eviction. This will queue up an activation to evict the workflow from
     /// the lang side. Workflow will not *actually* be evicted until lang replies to that activation
     ///
@@ -344,6 +541,11 @@ impl WorkflowTaskManager {
             if !self.activation_has_eviction(run_id) {
                 let message = message.into();
                 debug!(%run_id, %message, "Eviction requested");
+                self.events.emit(CoreEvent::EvictionRequested {
+                    run_id: run_id.to_string(),
+                    reason,
+                    message: message.clone(),
+                });
                 // Queue up an eviction activation
                 self.pending_activations
                     .notify_needs_eviction(run_id, message, reason);
@@ -386,10 +588,12 @@ impl WorkflowTaskManager {
         work: ValidPollWFTQResponse,
         client: Arc<WorkerClientBag>,
     ) -> NewWfTaskOutcome {
+        let run_id = work.workflow_execution.run_id.clone();
         let mut work = if let Some(w) = self.workflow_machines.buffer_resp_if_outstanding_work(work)
         {
             w
         } else {
+            self.events.emit(CoreEvent::WftBuffered { run_id });
             return NewWfTaskOutcome::TaskBuffered;
         };
 
@@ -403,6 +607,11 @@ impl WorkflowTaskManager {
             "Applying new workflow task from server"
         );
         let task_start_time = Instant::now();
+        let span = info_span!("workflow_task",
+                              workflow_id = %work.workflow_execution.workflow_id,
+                              run_id = %work.workflow_execution.run_id,
+                              workflow_type = %work.workflow_type,
+                              attempt = work.attempt);
 
         // Check if there is a legacy query we either need to immediately issue an activation for
         // (if there is no more replay work to do) or we need to store for later answering.
@@ -411,13 +620,16 @@ impl WorkflowTaskManager {
             .take()
             .map(|q| query_to_job(LEGACY_QUERY_ID.to_string(), q));
 
-        let (info, mut next_activation, mut pending_queries) =
-            match self.instantiate_or_update_workflow(work, client).await {
-                Ok(res) => res,
-                Err(e) => {
-                    return NewWfTaskOutcome::Evict(e);
-                }
-            };
+        let (info, mut next_activation, mut pending_queries) = match self
+            .instantiate_or_update_workflow(work, client)
+            .instrument(span.clone())
+            .await
+        {
+            Ok(res) => res,
+            Err(e) => {
+                return NewWfTaskOutcome::Evict(e);
+            }
+        };
 
         if !pending_queries.is_empty() && legacy_query.is_some() {
             error!(
@@ -444,6 +656,14 @@ impl WorkflowTaskManager {
             }
         }
 
+        // Only known once the machines exist, since it comes from the workflow's start headers
+        if let Some(cx) = self
+            .workflow_machines
+            .run_trace_parent(&next_activation.run_id)
+        {
+            span.set_parent(cx);
+        }
+        next_activation.workflow_task_attempt = info.attempt;
         self.workflow_machines
             .insert_wft(
                 &next_activation.run_id,
@@ -451,6 +671,10 @@ impl WorkflowTaskManager {
                     info,
                     pending_queries,
                     start_time: task_start_time,
+                    span,
+                    awaiting_local_acts: false,
+                    heartbeat_scheduled: false,
+                    deferred_query_responses: vec![],
                 },
             )
             .expect("Workflow machines must exist, we just created/updated them");
@@ -601,11 +825,16 @@ impl WorkflowTaskManager {
             // The heartbeat deadline is 80% of the WFT timeout
             let wft_heartbeat_deadline =
                 start_time.add(wft_timeout.mul_f32(WFT_HEARTBEAT_TIMEOUT_FRACTION));
-            // Wait on local activities to resolve if there are any, or for the WFT timeout to
-            // be about to expire, in which case we will need to send a WFT heartbeat.
-            let must_heartbeat = self
-                .wait_for_local_acts_or_heartbeat(run_id, wft_heartbeat_deadline)
-                .await;
+            let outstanding_las = self
+                .workflow_machines
+                .access_sync(run_id, |wfm| {
+                    wfm.machines.outstanding_local_activity_count()
+                })
+                .expect("Workflow machines must exist, we just updated them");
+            // If local activities are still running once the WFT timeout is about to expire, we
+            // must send a WFT heartbeat.
+            let must_heartbeat = outstanding_las > 0 && Instant::now() >= wft_heartbeat_deadline;
+            query_responses.splice(0..0, self.take_deferred_query_responses(run_id));
             let has_query_responses = !query_responses.is_empty();
             let is_query_playback = has_pending_query && !has_query_responses;
 
@@ -617,11 +846,13 @@ impl WorkflowTaskManager {
             // either.
             let no_commands_and_evicting =
                 server_cmds.commands.is_empty() && activation_was_only_eviction;
-            let to_be_sent = ServerCommandsWithWorkflowInfo {
+            // A closed workflow can't have any more workflow tasks, so there's no point forcing
+            // one just to heartbeat
+            let closes_workflow = server_cmds.commands.iter().any(closes_workflow);
+            let mut to_be_sent = ServerCommandsWithWorkflowInfo {
                 task_token,
                 action: ActivationAction::WftComplete {
-                    // TODO: Don't force if also sending complete execution cmd
-                    force_new_wft: must_heartbeat,
+                    force_new_wft: must_heartbeat && !closes_workflow,
                     commands: server_cmds.commands,
                     query_responses,
                 },
@@ -630,7 +861,26 @@ impl WorkflowTaskManager {
                 || server_cmds.replaying
                 || is_query_playback
                 || no_commands_and_evicting);
+            if should_respond && outstanding_las > 0 && !must_heartbeat {
+                // Give the local activities a chance to finish before completing the WFT. Their
+                // resolutions produce a new activation, whose completion will respond instead.
+                if let ActivationAction::WftComplete {
+                    query_responses, ..
+                } = to_be_sent.action
+                {
+                    self.defer_wft_completion(
+                        run_id,
+                        to_be_sent.task_token,
+                        query_responses,
+                        wft_heartbeat_deadline,
+                    );
+                }
+                return Ok(None);
+            }
             if should_respond || has_query_responses {
+                if let ActivationAction::WftComplete { commands, .. } = &mut to_be_sent.action {
+                    self.record_command_sizes(run_id, commands);
+                }
                 Some(to_be_sent)
             } else {
                 None
@@ -639,6 +889,58 @@ impl WorkflowTaskManager {
         Ok(ret)
     }
 
+    /// Holds back completing the run's WFT while its local activities run. The first time this
+    /// happens for a WFT, a watcher is spawned which queues a heartbeat for the run once the
+    /// heartbeat deadline arrives, in case the local activities haven't resolved by then.
+    fn defer_wft_completion(
+        &self,
+        run_id: &str,
+        task_token: TaskToken,
+        query_responses: Vec<QueryResult>,
+        heartbeat_deadline: Instant,
+    ) {
+        let spawn_watcher =
+            if let Ok(Some(ot)) = self.workflow_machines.get_task_mut(run_id).as_deref_mut() {
+                ot.awaiting_local_acts = true;
+                ot.deferred_query_responses.extend(query_responses);
+                !std::mem::replace(&mut ot.heartbeat_scheduled, true)
+            } else {
+                false
+            };
+        if spawn_watcher {
+            let heartbeats_due = self.heartbeats_due.clone();
+            let notifier = self.pending_activations_notifier.clone();
+            let run_id = run_id.to_string();
+            tokio::spawn(async move {
+                sleep_until(heartbeat_deadline.into()).await;
+                heartbeats_due.push((run_id, task_token));
+                notifier.notify_waiters();
+            });
+        }
+    }
+
+    /// Takes any query responses held back with the run's WFT completion, which is no longer
+    /// considered to be awaiting local activities.
+    fn take_deferred_query_responses(&self, run_id: &str) -> Vec<QueryResult> {
+        if let Ok(Some(ot)) = self.workflow_machines.get_task_mut(run_id).as_deref_mut() {
+            ot.awaiting_local_acts = false;
+            std::mem::take(&mut ot.deferred_query_responses)
+        } else {
+            vec![]
+        }
+    }
+
+    /// Record the encoded size of the payloads of each command about to be sent to the server for
+    /// the run
+    fn record_command_sizes(&self, run_id: &str, commands: &mut [ProtoCommand]) {
+        if let Some(m) = self.workflow_machines.run_metrics(run_id) {
+            for cmd in commands {
+                m.with_new_attrs([command_type(cmd.to_string())])
+                    .wf_command_payload_size(payloads_size(cmd));
+            }
+        }
+    }
+
     /// Record that an activation failed, returns enum that indicates if failure should be reported
     /// to the server
     pub(crate) fn failed_activation(
@@ -703,7 +1005,7 @@ impl WorkflowTaskManager {
         let poll_resp_is_incremental = poll_wf_resp
             .history
             .events
-            .get(0)
+            .first()
             .map(|ev| ev.event_id > 1)
             .unwrap_or_default();
         let poll_resp_is_incremental =
@@ -789,12 +1091,15 @@ impl WorkflowTaskManager {
         };
 
         // Workflows with no more pending activations (IE: They have completed a WFT) must be
-        // removed from the outstanding tasks map
-        if !self.pending_activations.has_pending(run_id) && !just_evicted {
-            if let Some(ref mut ot) = &mut *self
-                .workflow_machines
-                .get_task_mut(run_id)
-                .expect("Machine must exist")
+        // removed from the outstanding tasks map. Runs whose WFT completion is being held back
+        // for local activities haven't completed it yet.
+        let awaiting_local_acts = self
+            .workflow_machines
+            .get_task(run_id)
+            .map(|ot| ot.awaiting_local_acts)
+            .unwrap_or_default();
+        if !self.pending_activations.has_pending(run_id) && !just_evicted && !awaiting_local_acts {
+            if let Ok(Some(ref mut ot)) = self.workflow_machines.get_task_mut(run_id).as_deref_mut()
             {
                 // Check if there was a pending query which must be fulfilled, and if there is
                 // create a new pending activation for it.
@@ -912,6 +1217,11 @@ impl WorkflowTaskManager {
             .unwrap_or_default()
     }
 
+    /// Returns true if lang has been issued an activation for the run which it has yet to complete
+    pub(crate) fn has_outstanding_activation(&self, run_id: &str) -> bool {
+        self.workflow_machines.get_activation(run_id).is_some()
+    }
+
     fn activation_has_eviction(&self, run_id: &str) -> bool {
         self.workflow_machines
             .get_activation(run_id)
@@ -923,38 +1233,41 @@ impl WorkflowTaskManager {
         self.pending_activations.notify_needs_activation(run_id);
         self.pending_activations_notifier.notify_waiters();
     }
+}
 
-    /// Wait for either all local activities to resolve, or for 80% of the WFT timeout, in which
-    /// case we will "heartbeat" by completing the WFT, even if there are no commands to send.
-    ///
-    /// Returns true if we must heartbeat
-    async fn wait_for_local_acts_or_heartbeat(
-        &self,
-        run_id: &str,
-        wft_heartbeat_deadline: Instant,
-    ) -> bool {
-        loop {
-            let la_count = self
-                .workflow_machines
-                .access_sync(run_id, |wfm| {
-                    wfm.machines.outstanding_local_activity_count()
-                })
-                .expect("Workflow cannot go missing while we are waiting on LAs");
-            if la_count == 0 {
-                return false;
-            } else if Instant::now() >= wft_heartbeat_deadline {
-                // We must heartbeat b/c there are still pending local activities
-                return true;
-            }
-            // Since an LA resolution always results in a new pending activation, we can wait on
-            // notifications of that to re-check if they're all resolved.
-            let _ = timeout_at(
-                wft_heartbeat_deadline.into(),
-                self.pending_activations_notifier.notified(),
-            )
-            .await;
-        }
-    }
+/// True if the command closes the workflow (including continuing it as new)
+fn closes_workflow(cmd: &ProtoCommand) -> bool {
+    matches!(
+        CommandType::from_i32(cmd.command_type),
+        Some(
+            CommandType::CompleteWorkflowExecution
+                | CommandType::FailWorkflowExecution
+                | CommandType::CancelWorkflowExecution
+                | CommandType::ContinueAsNewWorkflowExecution
+        )
+    )
+}
+
+/// Retried workflow tasks are held back for at most this fraction of their timeout
+const MAX_BACKOFF_TIMEOUT_DIVISOR: u32 = 5;
+/// The server's default workflow task timeout, assumed if the task's history doesn't say
+const DEFAULT_WFT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The start-to-close timeout of a polled workflow task, from the last task scheduled event in
+/// its history
+fn wft_timeout_of(work: &ValidPollWFTQResponse) -> Duration {
+    work.history
+        .events
+        .iter()
+        .rev()
+        .find_map(|e| match &e.attributes {
+            Some(Attributes::WorkflowTaskScheduledEventAttributes(a)) => a
+                .start_to_close_timeout
+                .clone()
+                .and_then(|t| t.try_into().ok()),
+            _ => None,
+        })
+        .unwrap_or(DEFAULT_WFT_TIMEOUT)
 }
 
 #[derive(Debug)]
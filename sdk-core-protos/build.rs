@@ -1,9 +1,13 @@
+use std::{env, path::PathBuf};
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("cargo:rerun-if-changed=../protos");
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
     tonic_build::configure()
-        // We don't actually want to build the grpc definitions - we don't need them (for now).
-        // Just build the message structs.
-        .build_server(false)
+        // Servers are only needed for core's own service (see `CoreService` in bridge.proto).
+        // Tonic can't be told to skip generating the Temporal API's, so compile them out.
+        .build_server(true)
+        .server_mod_attribute("temporal.api.workflowservice.v1", "#[cfg(any())]")
         .build_client(true)
         // Make conversions easier for some types
         .type_attribute(
@@ -88,10 +92,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             "coresdk.external_data.LocalActivityMarkerData.backoff",
             "#[serde(with = \"opt_duration\")]",
         )
-        .compile(
+        // Descriptors are used to translate between protobuf and its canonical JSON form
+        .file_descriptor_set_path(out_dir.join("descriptors.bin"))
+        .compile_with_config(
+            {
+                let mut config = prost_build::Config::new();
+                // Payload data may be large, and is moved between many messages on its way to and
+                // from lang. Bytes makes cloning it cheap.
+                config.bytes([
+                    ".temporal.api.common.v1.Payload.data",
+                    ".coresdk.common.Payload.data",
+                ]);
+                config
+            },
             &[
                 "../protos/local/temporal/sdk/core/core_interface.proto",
                 "../protos/local/temporal/sdk/core/bridge/bridge.proto",
+                "../protos/local/temporal/sdk/core/activation_capture/activation_capture.proto",
                 "../protos/api_upstream/temporal/api/workflowservice/v1/service.proto",
             ],
             &["../protos/api_upstream", "../protos/local"],
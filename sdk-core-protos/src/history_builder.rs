@@ -21,10 +21,21 @@ use prost_types::Timestamp;
 use std::time::{Duration, SystemTime};
 use uuid::Uuid;
 
+/// The workflow type used by histories created with [default_wes_attribs]
 pub static DEFAULT_WORKFLOW_TYPE: &str = "default_wf_type";
 
 type Result<T, E = anyhow::Error> = std::result::Result<T, E>;
 
+/// Constructs valid workflow histories event by event, for use as replay fixtures or as canned
+/// responses when testing against core without a server.
+///
+/// Events are assigned sequential ids as they're added. The builder keeps track of the most recent
+/// workflow task, so helpers like [TestHistoryBuilder::add_full_wf_task] and the ones adding
+/// command events fill in the ids linking events together. Helpers which add events other events
+/// need to refer to return the new event's id. A history usually starts with
+/// `add_by_type(EventType::WorkflowExecutionStarted)` followed by `add_full_wf_task()`, and once
+/// built can be turned into a [History] with [TestHistoryBuilder::as_history] or validated and
+/// split into workflow tasks with [TestHistoryBuilder::get_history_info].
 #[derive(Default, Clone, Debug)]
 pub struct TestHistoryBuilder {
     events: Vec<HistoryEvent>,
@@ -72,16 +83,19 @@ impl TestHistoryBuilder {
         self.add_workflow_task_completed();
     }
 
+    /// Adds a workflow task scheduled event followed by its started event
     pub fn add_workflow_task_scheduled_and_started(&mut self) {
         self.add_workflow_task_scheduled();
         self.add_workflow_task_started();
     }
 
+    /// Adds a workflow task scheduled event, which subsequent workflow task events will refer to
     pub fn add_workflow_task_scheduled(&mut self) {
         self.workflow_task_scheduled_event_id =
             self.add_get_event_id(EventType::WorkflowTaskScheduled, None);
     }
 
+    /// Adds a started event for the most recently scheduled workflow task
     pub fn add_workflow_task_started(&mut self) {
         let attrs = WorkflowTaskStartedEventAttributes {
             scheduled_event_id: self.workflow_task_scheduled_event_id,
@@ -91,6 +105,8 @@ impl TestHistoryBuilder {
             self.add_get_event_id(EventType::WorkflowTaskStarted, Some(attrs.into()));
     }
 
+    /// Adds a completed event for the most recently scheduled workflow task. Command events added
+    /// afterward are attributed to it.
     pub fn add_workflow_task_completed(&mut self) {
         let attrs = WorkflowTaskCompletedEventAttributes {
             scheduled_event_id: self.workflow_task_scheduled_event_id,
@@ -100,6 +116,7 @@ impl TestHistoryBuilder {
         self.previous_task_completed_id = id;
     }
 
+    /// Adds a timed out event for the most recently scheduled workflow task
     pub fn add_workflow_task_timed_out(&mut self) {
         let attrs = WorkflowTaskTimedOutEventAttributes {
             scheduled_event_id: self.workflow_task_scheduled_event_id,
@@ -108,6 +125,7 @@ impl TestHistoryBuilder {
         self.build_and_push_event(EventType::WorkflowTaskTimedOut, attrs.into());
     }
 
+    /// Adds a workflow execution completed event
     pub fn add_workflow_execution_completed(&mut self) {
         let attrs = WorkflowExecutionCompletedEventAttributes {
             workflow_task_completed_event_id: self.previous_task_completed_id,
@@ -116,6 +134,7 @@ impl TestHistoryBuilder {
         self.build_and_push_event(EventType::WorkflowExecutionCompleted, attrs.into());
     }
 
+    /// Adds a workflow execution failed event
     pub fn add_workflow_execution_failed(&mut self) {
         let attrs = WorkflowExecutionFailedEventAttributes {
             workflow_task_completed_event_id: self.previous_task_completed_id,
@@ -124,21 +143,40 @@ impl TestHistoryBuilder {
         self.build_and_push_event(EventType::WorkflowExecutionFailed, attrs.into());
     }
 
+    /// Adds a workflow execution continued as new event
     pub fn add_continued_as_new(&mut self) {
         let attrs = WorkflowExecutionContinuedAsNewEventAttributes::default();
         self.build_and_push_event(EventType::WorkflowExecutionContinuedAsNew, attrs.into());
     }
 
+    /// Adds a workflow execution cancel requested event
     pub fn add_cancel_requested(&mut self) {
         let attrs = WorkflowExecutionCancelRequestedEventAttributes::default();
         self.build_and_push_event(EventType::WorkflowExecutionCancelRequested, attrs.into());
     }
 
+    /// Adds a workflow execution canceled event
     pub fn add_cancelled(&mut self) {
         let attrs = WorkflowExecutionCanceledEventAttributes::default();
         self.build_and_push_event(EventType::WorkflowExecutionCanceled, attrs.into());
     }
 
+    /// Adds a timer started event, returning its id
+    pub fn add_timer_started(&mut self, timer_id: String) -> i64 {
+        self.add_get_event_id(
+            EventType::TimerStarted,
+            Some(
+                TimerStartedEventAttributes {
+                    timer_id,
+                    workflow_task_completed_event_id: self.previous_task_completed_id,
+                    ..Default::default()
+                }
+                .into(),
+            ),
+        )
+    }
+
+    /// Adds an activity task scheduled event, returning its id
     pub fn add_activity_task_scheduled(&mut self, activity_id: impl Into<String>) -> i64 {
         self.add_get_event_id(
             EventType::ActivityTaskScheduled,
@@ -152,6 +190,8 @@ impl TestHistoryBuilder {
             ),
         )
     }
+
+    /// Adds an activity task started event, returning its id
     pub fn add_activity_task_started(&mut self, scheduled_event_id: i64) -> i64 {
         self.add_get_event_id(
             EventType::ActivityTaskStarted,
@@ -166,6 +206,7 @@ impl TestHistoryBuilder {
         )
     }
 
+    /// Adds an activity task completed event with the provided result
     pub fn add_activity_task_completed(
         &mut self,
         scheduled_event_id: i64,
@@ -185,6 +226,39 @@ impl TestHistoryBuilder {
         );
     }
 
+    /// Adds an activity task failed event
+    pub fn add_activity_task_failed(
+        &mut self,
+        scheduled_event_id: i64,
+        started_event_id: i64,
+        failure: Failure,
+    ) {
+        let attrs = ActivityTaskFailedEventAttributes {
+            scheduled_event_id,
+            started_event_id,
+            failure: Some(failure),
+            ..Default::default()
+        };
+        self.build_and_push_event(EventType::ActivityTaskFailed, attrs.into());
+    }
+
+    /// Adds an activity task timed out event
+    pub fn add_activity_task_timed_out(
+        &mut self,
+        scheduled_event_id: i64,
+        started_event_id: i64,
+        failure: Failure,
+    ) {
+        let attrs = ActivityTaskTimedOutEventAttributes {
+            scheduled_event_id,
+            started_event_id,
+            failure: Some(failure),
+            ..Default::default()
+        };
+        self.build_and_push_event(EventType::ActivityTaskTimedOut, attrs.into());
+    }
+
+    /// Adds an activity task cancel requested event
     pub fn add_activity_task_cancel_requested(&mut self, scheduled_event_id: i64) {
         let attrs = ActivityTaskCancelRequestedEventAttributes {
             scheduled_event_id,
@@ -193,6 +267,7 @@ impl TestHistoryBuilder {
         self.build_and_push_event(EventType::ActivityTaskCancelRequested, attrs.into());
     }
 
+    /// Adds a failed event for the most recently scheduled workflow task
     pub fn add_workflow_task_failed_with_failure(
         &mut self,
         cause: WorkflowTaskFailedCause,
@@ -207,6 +282,8 @@ impl TestHistoryBuilder {
         self.build_and_push_event(EventType::WorkflowTaskFailed, attrs.into());
     }
 
+    /// Adds a failed event for the most recently scheduled workflow task which resets the workflow
+    /// to a new run
     pub fn add_workflow_task_failed_new_id(
         &mut self,
         cause: WorkflowTaskFailedCause,
@@ -221,6 +298,7 @@ impl TestHistoryBuilder {
         self.build_and_push_event(EventType::WorkflowTaskFailed, attrs.into());
     }
 
+    /// Adds a timer fired event for a previously started timer
     pub fn add_timer_fired(&mut self, timer_started_evt_id: i64, timer_id: String) {
         self.add(
             EventType::TimerFired,
@@ -231,6 +309,7 @@ impl TestHistoryBuilder {
         );
     }
 
+    /// Adds a workflow execution signaled event
     pub fn add_we_signaled(&mut self, signal_name: &str, payloads: Vec<Payload>) {
         let attrs = WorkflowExecutionSignaledEventAttributes {
             signal_name: signal_name.to_string(),
@@ -404,6 +483,74 @@ impl TestHistoryBuilder {
         );
     }
 
+    /// Adds a start child workflow execution initiated event, returning its id
+    pub fn add_start_child_workflow_initiated(
+        &mut self,
+        workflow_id: impl Into<String>,
+        workflow_type: impl Into<String>,
+    ) -> i64 {
+        let attrs = StartChildWorkflowExecutionInitiatedEventAttributes {
+            workflow_id: workflow_id.into(),
+            workflow_type: Some(WorkflowType {
+                name: workflow_type.into(),
+            }),
+            workflow_task_completed_event_id: self.previous_task_completed_id,
+            ..Default::default()
+        };
+        self.add_get_event_id(
+            EventType::StartChildWorkflowExecutionInitiated,
+            Some(attrs.into()),
+        )
+    }
+
+    /// Adds a child workflow execution started event for a previously initiated child, returning
+    /// its id
+    pub fn add_child_workflow_started(
+        &mut self,
+        initiated_event_id: i64,
+        execution: WorkflowExecution,
+    ) -> i64 {
+        let attrs = ChildWorkflowExecutionStartedEventAttributes {
+            initiated_event_id,
+            workflow_execution: Some(execution),
+            ..Default::default()
+        };
+        self.add_get_event_id(EventType::ChildWorkflowExecutionStarted, Some(attrs.into()))
+    }
+
+    /// Adds a child workflow execution completed event with the provided result
+    pub fn add_child_workflow_completed(
+        &mut self,
+        initiated_event_id: i64,
+        started_event_id: i64,
+        result: Option<Payloads>,
+    ) {
+        let attrs = ChildWorkflowExecutionCompletedEventAttributes {
+            initiated_event_id,
+            started_event_id,
+            result,
+            ..Default::default()
+        };
+        self.build_and_push_event(EventType::ChildWorkflowExecutionCompleted, attrs.into());
+    }
+
+    /// Adds a child workflow execution failed event
+    pub fn add_child_workflow_failed(
+        &mut self,
+        initiated_event_id: i64,
+        started_event_id: i64,
+        failure: Failure,
+    ) {
+        let attrs = ChildWorkflowExecutionFailedEventAttributes {
+            initiated_event_id,
+            started_event_id,
+            failure: Some(failure),
+            ..Default::default()
+        };
+        self.build_and_push_event(EventType::ChildWorkflowExecutionFailed, attrs.into());
+    }
+
+    /// The run id of the workflow, as set in its workflow execution started event
     pub fn get_orig_run_id(&self) -> &str {
         &self.original_run_id
     }
@@ -420,6 +567,8 @@ impl TestHistoryBuilder {
         HistoryInfo::new_from_history(&self.events.clone().into(), None)
     }
 
+    /// Returns a [HistoryInfo] containing only the events of the provided workflow task, as would be
+    /// delivered to a worker which has the preceding history cached
     pub fn get_one_wft(&self, from_wft_number: usize) -> Result<HistoryInfo, anyhow::Error> {
         let mut histinfo =
             HistoryInfo::new_from_history(&self.events.clone().into(), Some(from_wft_number))?;
@@ -427,6 +576,12 @@ impl TestHistoryBuilder {
         Ok(histinfo)
     }
 
+    /// All the events added so far, as a [History]. Unlike [TestHistoryBuilder::get_history_info],
+    /// the history is not validated.
+    pub fn as_history(&self) -> History {
+        self.events.clone().into()
+    }
+
     /// Return most recent wft start time or panic if unset
     pub fn wft_start_time(&self) -> prost_types::Timestamp {
         self.events[(self.workflow_task_scheduled_event_id + 1) as usize]
@@ -466,6 +621,8 @@ fn default_attribs(et: EventType) -> Result<Attributes> {
     })
 }
 
+/// Workflow execution started attributes with a fresh run id, [DEFAULT_WORKFLOW_TYPE] as the type,
+/// and a workflow task timeout set
 pub fn default_wes_attribs() -> WorkflowExecutionStartedEventAttributes {
     WorkflowExecutionStartedEventAttributes {
         original_execution_run_id: Uuid::new_v4().to_string(),
@@ -476,3 +633,47 @@ pub fn default_wes_attribs() -> WorkflowExecutionStartedEventAttributes {
         ..Default::default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_valid_child_and_activity_history() {
+        let mut t = TestHistoryBuilder::default();
+        t.add_by_type(EventType::WorkflowExecutionStarted);
+        t.add_full_wf_task();
+        let timer_id = t.add_timer_started("1".to_string());
+        let child_initiated = t.add_start_child_workflow_initiated("child", "child_wf");
+        let act_scheduled = t.add_activity_task_scheduled("act");
+        t.add_timer_fired(timer_id, "1".to_string());
+        let child_started = t.add_child_workflow_started(
+            child_initiated,
+            WorkflowExecution {
+                workflow_id: "child".to_string(),
+                run_id: "child_run".to_string(),
+            },
+        );
+        let act_started = t.add_activity_task_started(act_scheduled);
+        t.add_full_wf_task();
+        t.add_activity_task_failed(act_scheduled, act_started, Failure::default());
+        t.add_child_workflow_completed(child_initiated, child_started, None);
+        t.add_full_wf_task();
+        t.add_workflow_execution_completed();
+
+        let history = t.as_history();
+        assert_eq!(history.events.len(), 19);
+        assert_eq!(history.last_event_id(), 19);
+        match &history.events[5].attributes {
+            Some(Attributes::StartChildWorkflowExecutionInitiatedEventAttributes(a)) => {
+                // Command events refer to the workflow task which produced them
+                assert_eq!(a.workflow_task_completed_event_id, 4);
+                assert_eq!(a.workflow_type.as_ref().unwrap().name, "child_wf");
+            }
+            other => panic!("Unexpected attributes {:?}", other),
+        }
+        let info = t.get_full_history_info().unwrap();
+        assert_eq!(info.events().len(), 19);
+        assert_eq!(info.previous_started_event_id(), 17);
+    }
+}
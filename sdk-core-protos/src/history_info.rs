@@ -37,7 +37,7 @@ impl HistoryInfo {
         let mut wf_task_count = 0;
         let mut history = events.iter().peekable();
 
-        let wf_type = match &events.get(0).unwrap().attributes {
+        let wf_type = match &events.first().unwrap().attributes {
             Some(history_event::Attributes::WorkflowExecutionStartedEventAttributes(attrs)) => {
                 attrs
                     .workflow_type
@@ -57,10 +57,9 @@ impl HistoryInfo {
             let next_event = history.peek();
 
             if event.event_type == EventType::WorkflowTaskStarted as i32 {
-                let next_is_completed = next_event.map_or(false, |ne| {
-                    ne.event_type == EventType::WorkflowTaskCompleted as i32
-                });
-                let next_is_failed_or_timeout = next_event.map_or(false, |ne| {
+                let next_is_completed = next_event
+                    .is_some_and(|ne| ne.event_type == EventType::WorkflowTaskCompleted as i32);
+                let next_is_failed_or_timeout = next_event.is_some_and(|ne| {
                     ne.event_type == EventType::WorkflowTaskFailed as i32
                         || ne.event_type == EventType::WorkflowTaskTimedOut as i32
                 });
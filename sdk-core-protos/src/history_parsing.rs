@@ -0,0 +1,239 @@
+//! Reads workflow histories in the formats standard tools produce, so they can be replayed or
+//! otherwise inspected no matter where they were exported from.
+
+use crate::{
+    json::{decode_json_value, JsonDecodeError},
+    temporal::api::{enums::v1::EventType, history::v1::History},
+};
+use prost::Message;
+use serde_json::{Map, Value};
+
+const HISTORY_TYPE_NAME: &str = ".temporal.api.history.v1.History";
+
+/// Errors that can occur when parsing a history
+#[derive(thiserror::Error, Debug)]
+pub enum HistoryParseError {
+    /// The history could not be read from JSON
+    #[error(transparent)]
+    Json(#[from] JsonDecodeError),
+    /// The history could not be decoded from the protobuf wire format
+    #[error("History could not be decoded: {0}")]
+    Decode(#[from] prost::DecodeError),
+    /// The history has no events at all
+    #[error("History has no events")]
+    Empty,
+    /// The first event isn't the start of a workflow execution
+    #[error("History must begin with a workflow execution started event, not {0:?}")]
+    MissingStart(EventType),
+    /// Event ids don't count up from one
+    #[error("Event at index {index} has id {found}, but {expected} was expected")]
+    OutOfOrder {
+        /// Position of the event in the history
+        index: usize,
+        /// The id the event should have
+        expected: i64,
+        /// The id it has
+        found: i64,
+    },
+}
+
+impl From<serde_json::Error> for HistoryParseError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e.into())
+    }
+}
+
+impl History {
+    /// Read a history in any supported format: the protobuf wire format, canonical proto JSON, or
+    /// an export from the Temporal CLI (see [History::from_cli_json]). The wire format is tried
+    /// first, since an encoded history can begin with bytes that look like the start of JSON.
+    pub fn parse(bytes: &[u8]) -> Result<Self, HistoryParseError> {
+        let from_proto = Self::from_proto_bytes(bytes);
+        if from_proto.is_ok() {
+            return from_proto;
+        }
+        let first = bytes.iter().find(|b| !b.is_ascii_whitespace());
+        if !matches!(first, Some(b'{' | b'[')) {
+            return from_proto;
+        }
+        let json = std::str::from_utf8(bytes).map_err(|e| {
+            HistoryParseError::Json(JsonDecodeError::InvalidValue {
+                path: String::new(),
+                reason: format!("not valid UTF-8: {}", e),
+            })
+        })?;
+        Self::from_cli_json(json)
+    }
+
+    /// Read a history serialized with the protobuf wire format, validating its events
+    pub fn from_proto_bytes(bytes: &[u8]) -> Result<Self, HistoryParseError> {
+        let history = Self::decode(bytes)?;
+        history.validate()?;
+        Ok(history)
+    }
+
+    /// Serialize the history with the protobuf wire format
+    pub fn to_proto_bytes(&self) -> Vec<u8> {
+        self.encode_to_vec()
+    }
+
+    /// Read a history exported by `temporal workflow show` or similar tools, validating its
+    /// events. Accepts canonical proto JSON of the history, a workflow history response holding
+    /// it under `history`, a bare array of events, or one event per line. Enum values may be
+    /// given without their prefix in any casing, as older tools write them.
+    ///
+    /// Histories written by [History::to_json] can be read back with this.
+    pub fn from_cli_json(json: &str) -> Result<Self, HistoryParseError> {
+        let mut values = serde_json::Deserializer::from_str(json)
+            .into_iter::<Value>()
+            .collect::<Result<Vec<_>, _>>()?;
+        let history = match values.as_mut_slice() {
+            [Value::Object(o)] if o.contains_key("events") => Value::Object(std::mem::take(o)),
+            [Value::Object(o)] if matches!(o.get("history"), Some(Value::Object(_))) => {
+                o.remove("history").expect("Just checked")
+            }
+            [Value::Array(events)] => events_to_history(std::mem::take(events)),
+            _ => events_to_history(values),
+        };
+        let history: History = decode_json_value(HISTORY_TYPE_NAME, &history)?;
+        history.validate()?;
+        Ok(history)
+    }
+
+    /// Check that the history is one which can be replayed: it must begin with the start of a
+    /// workflow execution, and event ids must count up from one without gaps.
+    pub fn validate(&self) -> Result<(), HistoryParseError> {
+        let first = self.events.first().ok_or(HistoryParseError::Empty)?;
+        if first.event_type() != EventType::WorkflowExecutionStarted {
+            return Err(HistoryParseError::MissingStart(first.event_type()));
+        }
+        for (index, event) in self.events.iter().enumerate() {
+            let expected = index as i64 + 1;
+            if event.event_id != expected {
+                return Err(HistoryParseError::OutOfOrder {
+                    index,
+                    expected,
+                    found: event.event_id,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn events_to_history(events: Vec<Value>) -> Value {
+    let mut history = Map::new();
+    history.insert("events".to_string(), Value::Array(events));
+    Value::Object(history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temporal::api::history::v1::{
+        history_event::Attributes, HistoryEvent, WorkflowExecutionStartedEventAttributes,
+    };
+
+    fn history() -> History {
+        History {
+            events: vec![
+                HistoryEvent {
+                    event_id: 1,
+                    event_type: EventType::WorkflowExecutionStarted as i32,
+                    attributes: Some(Attributes::WorkflowExecutionStartedEventAttributes(
+                        WorkflowExecutionStartedEventAttributes {
+                            original_execution_run_id: "run".to_string(),
+                            ..Default::default()
+                        },
+                    )),
+                    ..Default::default()
+                },
+                HistoryEvent {
+                    event_id: 2,
+                    event_type: EventType::WorkflowTaskScheduled as i32,
+                    ..Default::default()
+                },
+            ],
+        }
+    }
+
+    const STARTED: &str = r#"{"eventId": "1", "eventType": "WorkflowExecutionStarted",
+        "workflowExecutionStartedEventAttributes": {"originalExecutionRunId": "run"}}"#;
+    const SCHEDULED: &str =
+        r#"{"eventId": "2", "eventType": "EVENT_TYPE_WORKFLOW_TASK_SCHEDULED"}"#;
+
+    #[test]
+    fn parses_every_format() {
+        let expected = history();
+        let inputs = [
+            expected.to_proto_bytes(),
+            expected.to_json().into_bytes(),
+            format!(r#"{{"events": [{}, {}]}}"#, STARTED, SCHEDULED).into_bytes(),
+            format!(
+                r#"{{"history": {{"events": [{}, {}]}}}}"#,
+                STARTED, SCHEDULED
+            )
+            .into_bytes(),
+            format!("[{}, {}]", STARTED, SCHEDULED).into_bytes(),
+            format!(
+                "{}\n{}\n",
+                STARTED.replace('\n', ""),
+                SCHEDULED.replace('\n', "")
+            )
+            .into_bytes(),
+        ];
+        for input in inputs {
+            assert_eq!(History::parse(&input).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn parses_proto_that_looks_like_json() {
+        // The first byte of an encoded history is a newline, and the second is the length of the
+        // first event, so an event of the right size makes the bytes begin like a JSON object.
+        let mut expected = history();
+        let mut run_id = String::new();
+        let bytes = loop {
+            let bytes = expected.to_proto_bytes();
+            if bytes[1] == b'{' {
+                break bytes;
+            }
+            run_id.push('r');
+            if let Some(Attributes::WorkflowExecutionStartedEventAttributes(a)) =
+                expected.events[0].attributes.as_mut()
+            {
+                a.original_execution_run_id = run_id.clone();
+            }
+        };
+        assert_eq!(History::parse(&bytes).unwrap(), expected);
+    }
+
+    #[test]
+    fn rejects_invalid_histories() {
+        let mut h = history();
+        h.events[1].event_id = 3;
+        assert!(matches!(
+            History::from_proto_bytes(&h.to_proto_bytes()),
+            Err(HistoryParseError::OutOfOrder {
+                index: 1,
+                expected: 2,
+                found: 3
+            })
+        ));
+        h.events.remove(0);
+        assert!(matches!(
+            History::parse(h.to_json().as_bytes()),
+            Err(HistoryParseError::MissingStart(
+                EventType::WorkflowTaskScheduled
+            ))
+        ));
+        assert!(matches!(
+            History::parse(b"[]"),
+            Err(HistoryParseError::Empty)
+        ));
+        assert!(matches!(
+            History::parse(b"{\"events\": "),
+            Err(HistoryParseError::Json(_))
+        ));
+    }
+}
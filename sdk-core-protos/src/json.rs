@@ -0,0 +1,1012 @@
+//! Reads and writes protobuf messages in their canonical JSON form, which is how other Temporal
+//! SDKs and tools export workflow histories. Since the generated message structs don't know how to
+//! speak JSON themselves, translation is driven by the descriptors emitted at build time: JSON is
+//! transcoded to and from the protobuf wire format, which the structs are decoded from or encoded
+//! to as usual.
+
+use crate::{
+    coresdk::{
+        activity_task::ActivityTask, workflow_activation::WorkflowActivation,
+        workflow_completion::WorkflowActivationCompletion, ActivityHeartbeat,
+        ActivityTaskCompletion,
+    },
+    utilities::{format_nanos, format_timestamp, parse_nanos, parse_timestamp},
+};
+use once_cell::sync::Lazy;
+use prost::Message;
+use prost_types::{
+    field_descriptor_proto::{Label, Type},
+    DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorSet,
+};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+static DESCRIPTOR_BYTES: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/descriptors.bin"));
+static DESCRIPTORS: Lazy<Descriptors> = Lazy::new(|| {
+    let set = FileDescriptorSet::decode(DESCRIPTOR_BYTES)
+        .expect("Descriptors generated at build time must be valid");
+    let mut descriptors = Descriptors::default();
+    for file in set.file {
+        let scope = format!(".{}", file.package());
+        descriptors.add_messages(&scope, file.message_type);
+        descriptors.add_enums(&scope, file.enum_type);
+    }
+    descriptors
+});
+
+const WIRE_VARINT: u64 = 0;
+const WIRE_FIXED64: u64 = 1;
+const WIRE_LEN: u64 = 2;
+const WIRE_FIXED32: u64 = 5;
+
+/// Errors that can occur when reading a protobuf message from JSON
+#[derive(thiserror::Error, Debug)]
+pub enum JsonDecodeError {
+    /// The input could not be parsed as JSON at all
+    #[error("Input is not valid JSON: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+    /// The JSON does not match the shape of the message
+    #[error("Invalid value at `{path}`: {reason}")]
+    InvalidValue {
+        /// Where in the input the bad value is
+        path: String,
+        /// What's wrong with it
+        reason: String,
+    },
+    /// The translated message could not be decoded
+    #[error("Message could not be decoded: {0}")]
+    Decode(#[from] prost::DecodeError),
+}
+
+type Result<T, E = JsonDecodeError> = std::result::Result<T, E>;
+
+/// Decode a message of the provided fully qualified type (ex: `.temporal.api.history.v1.History`)
+/// from its JSON representation.
+pub(crate) fn decode_json<M: Message + Default>(type_name: &str, json: &str) -> Result<M> {
+    decode_json_value(type_name, &serde_json::from_str(json)?)
+}
+
+/// Decode a message of the provided fully qualified type from already parsed JSON
+pub(crate) fn decode_json_value<M: Message + Default>(type_name: &str, value: &Value) -> Result<M> {
+    let mut buf = vec![];
+    encode_message(type_name, value, "", &mut buf)?;
+    Ok(M::decode(buf.as_slice())?)
+}
+
+/// Conversion to and from canonical proto JSON for the messages exchanged between core and lang,
+/// for bridges which hand them across a JSON boundary (ex: to an embedded scripting runtime)
+/// rather than using the protobuf encoding.
+pub trait ProtoJson: Message + Default {
+    /// Fully qualified protobuf type name of the message
+    const TYPE_NAME: &'static str;
+
+    /// Serialize the message to compact canonical JSON. Map fields are ordered by key, so the
+    /// output is stable.
+    fn to_json(&self) -> String {
+        encode_json(Self::TYPE_NAME, self).to_string()
+    }
+
+    /// Read the message from its canonical JSON form. Unknown fields are ignored.
+    fn from_json(json: &str) -> Result<Self> {
+        decode_json(Self::TYPE_NAME, json)
+    }
+}
+
+macro_rules! impl_proto_json {
+    ($($t:ty => $name:literal),+ $(,)?) => {
+        $(
+            impl ProtoJson for $t {
+                const TYPE_NAME: &'static str = $name;
+            }
+        )+
+    };
+}
+
+impl_proto_json!(
+    WorkflowActivation => ".coresdk.workflow_activation.WorkflowActivation",
+    WorkflowActivationCompletion => ".coresdk.workflow_completion.WorkflowActivationCompletion",
+    ActivityTask => ".coresdk.activity_task.ActivityTask",
+    ActivityTaskCompletion => ".coresdk.ActivityTaskCompletion",
+    ActivityHeartbeat => ".coresdk.ActivityHeartbeat",
+);
+
+#[derive(Default)]
+struct Descriptors {
+    messages: HashMap<String, DescriptorProto>,
+    enums: HashMap<String, EnumDescriptorProto>,
+}
+
+impl Descriptors {
+    fn add_messages(&mut self, scope: &str, messages: Vec<DescriptorProto>) {
+        for mut msg in messages {
+            let name = format!("{}.{}", scope, msg.name());
+            self.add_messages(&name, std::mem::take(&mut msg.nested_type));
+            self.add_enums(&name, std::mem::take(&mut msg.enum_type));
+            self.messages.insert(name, msg);
+        }
+    }
+
+    fn add_enums(&mut self, scope: &str, enums: Vec<EnumDescriptorProto>) {
+        for e in enums {
+            self.enums.insert(format!("{}.{}", scope, e.name()), e);
+        }
+    }
+
+    fn message(&self, name: &str, path: &str) -> Result<&DescriptorProto> {
+        self.messages
+            .get(name)
+            .ok_or_else(|| invalid(path, format!("unknown message type {}", name)))
+    }
+}
+
+fn invalid(path: &str, reason: impl Into<String>) -> JsonDecodeError {
+    JsonDecodeError::InvalidValue {
+        path: path.to_string(),
+        reason: reason.into(),
+    }
+}
+
+fn encode_message(type_name: &str, value: &Value, path: &str, buf: &mut Vec<u8>) -> Result<()> {
+    match type_name {
+        ".google.protobuf.Timestamp" => {
+            let (seconds, nanos) = value
+                .as_str()
+                .and_then(parse_timestamp)
+                .ok_or_else(|| invalid(path, "expected an RFC 3339 timestamp"))?;
+            encode_seconds_and_nanos(seconds, nanos, buf);
+            return Ok(());
+        }
+        ".google.protobuf.Duration" => {
+            let (seconds, nanos) = value
+                .as_str()
+                .and_then(parse_duration)
+                .ok_or_else(|| invalid(path, "expected a duration like \"1.5s\""))?;
+            encode_seconds_and_nanos(seconds, nanos, buf);
+            return Ok(());
+        }
+        ".google.protobuf.Any"
+        | ".google.protobuf.Struct"
+        | ".google.protobuf.Value"
+        | ".google.protobuf.ListValue"
+        | ".google.protobuf.FieldMask" => {
+            return Err(invalid(path, format!("{} is not supported", type_name)));
+        }
+        _ => {}
+    }
+    let desc = DESCRIPTORS.message(type_name, path)?;
+    // Wrapper types are represented by their bare value
+    if type_name.starts_with(".google.protobuf.") && type_name.ends_with("Value") {
+        return encode_field(&desc.field[0], value, path, buf);
+    }
+    let obj = value
+        .as_object()
+        .ok_or_else(|| invalid(path, "expected an object"))?;
+    for (key, val) in obj {
+        if val.is_null() {
+            continue;
+        }
+        // Unknown fields are skipped, since they may come from newer versions of the protos
+        if let Some(field) = desc.field.iter().find(|f| field_matches(f, key)) {
+            encode_field(field, val, &join_path(path, key), buf)?;
+        }
+    }
+    Ok(())
+}
+
+fn field_matches(field: &FieldDescriptorProto, key: &str) -> bool {
+    field.name() == key
+        || match &field.json_name {
+            Some(json_name) => json_name == key,
+            None => lower_camel(field.name()) == key,
+        }
+}
+
+fn lower_camel(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut upper_next = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper_next = true;
+        } else if upper_next {
+            out.extend(c.to_uppercase());
+            upper_next = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn encode_field(
+    field: &FieldDescriptorProto,
+    value: &Value,
+    path: &str,
+    buf: &mut Vec<u8>,
+) -> Result<()> {
+    if field.label() != Label::Repeated {
+        return encode_single(field, value, path, buf);
+    }
+    if field.r#type() == Type::Message {
+        let entry = DESCRIPTORS.message(field.type_name(), path)?;
+        if entry.options.as_ref().and_then(|o| o.map_entry) == Some(true) {
+            let obj = value
+                .as_object()
+                .ok_or_else(|| invalid(path, "expected an object"))?;
+            return encode_map(field, entry, obj, path, buf);
+        }
+    }
+    let items = value
+        .as_array()
+        .ok_or_else(|| invalid(path, "expected an array"))?;
+    for (i, item) in items.iter().enumerate() {
+        encode_single(field, item, &format!("{}[{}]", path, i), buf)?;
+    }
+    Ok(())
+}
+
+fn encode_map(
+    field: &FieldDescriptorProto,
+    entry: &DescriptorProto,
+    obj: &Map<String, Value>,
+    path: &str,
+    buf: &mut Vec<u8>,
+) -> Result<()> {
+    let (key_field, val_field) = match entry.field.as_slice() {
+        [k, v] => (k, v),
+        _ => return Err(invalid(path, "map entry must have a key and a value")),
+    };
+    for (key, val) in obj {
+        let entry_path = join_path(path, key);
+        let key_val = match key_field.r#type() {
+            Type::Bool => Value::Bool(
+                key.parse()
+                    .map_err(|_| invalid(&entry_path, "expected a boolean map key"))?,
+            ),
+            _ => Value::String(key.clone()),
+        };
+        let mut entry_buf = vec![];
+        encode_single(key_field, &key_val, &entry_path, &mut entry_buf)?;
+        encode_single(val_field, val, &entry_path, &mut entry_buf)?;
+        encode_key(field.number(), WIRE_LEN, buf);
+        encode_bytes(&entry_buf, buf);
+    }
+    Ok(())
+}
+
+fn encode_single(
+    field: &FieldDescriptorProto,
+    value: &Value,
+    path: &str,
+    buf: &mut Vec<u8>,
+) -> Result<()> {
+    let num = field.number();
+    match field.r#type() {
+        Type::Double => {
+            encode_key(num, WIRE_FIXED64, buf);
+            buf.extend_from_slice(&parse_f64(value, path)?.to_le_bytes());
+        }
+        Type::Float => {
+            encode_key(num, WIRE_FIXED32, buf);
+            buf.extend_from_slice(&(parse_f64(value, path)? as f32).to_le_bytes());
+        }
+        Type::Int64 | Type::Int32 => {
+            let v = match field.r#type() {
+                Type::Int64 => parse_i64(value, path)?,
+                _ => parse_i32(value, path)?.into(),
+            };
+            encode_key(num, WIRE_VARINT, buf);
+            encode_varint(v as u64, buf);
+        }
+        Type::Uint64 | Type::Uint32 => {
+            let v = match field.r#type() {
+                Type::Uint64 => parse_u64(value, path)?,
+                _ => parse_u32(value, path)?.into(),
+            };
+            encode_key(num, WIRE_VARINT, buf);
+            encode_varint(v, buf);
+        }
+        Type::Sint64 | Type::Sint32 => {
+            let v = match field.r#type() {
+                Type::Sint64 => parse_i64(value, path)?,
+                _ => parse_i32(value, path)?.into(),
+            };
+            encode_key(num, WIRE_VARINT, buf);
+            encode_varint(((v << 1) ^ (v >> 63)) as u64, buf);
+        }
+        Type::Fixed64 | Type::Sfixed64 => {
+            let v = match field.r#type() {
+                Type::Fixed64 => parse_u64(value, path)?,
+                _ => parse_i64(value, path)? as u64,
+            };
+            encode_key(num, WIRE_FIXED64, buf);
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+        Type::Fixed32 | Type::Sfixed32 => {
+            let v = match field.r#type() {
+                Type::Fixed32 => parse_u32(value, path)?,
+                _ => parse_i32(value, path)? as u32,
+            };
+            encode_key(num, WIRE_FIXED32, buf);
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+        Type::Bool => {
+            let v = value
+                .as_bool()
+                .ok_or_else(|| invalid(path, "expected a boolean"))?;
+            encode_key(num, WIRE_VARINT, buf);
+            encode_varint(v as u64, buf);
+        }
+        Type::Enum => {
+            let v = parse_enum(field.type_name(), value, path)?;
+            encode_key(num, WIRE_VARINT, buf);
+            encode_varint(v as i64 as u64, buf);
+        }
+        Type::String => {
+            let v = value
+                .as_str()
+                .ok_or_else(|| invalid(path, "expected a string"))?;
+            encode_key(num, WIRE_LEN, buf);
+            encode_bytes(v.as_bytes(), buf);
+        }
+        Type::Bytes => {
+            let v = value
+                .as_str()
+                .and_then(parse_base64)
+                .ok_or_else(|| invalid(path, "expected base64 encoded bytes"))?;
+            encode_key(num, WIRE_LEN, buf);
+            encode_bytes(&v, buf);
+        }
+        Type::Message => {
+            let mut msg_buf = vec![];
+            encode_message(field.type_name(), value, path, &mut msg_buf)?;
+            encode_key(num, WIRE_LEN, buf);
+            encode_bytes(&msg_buf, buf);
+        }
+        Type::Group => return Err(invalid(path, "groups are not supported")),
+    }
+    Ok(())
+}
+
+// Integers may be written as floats with no fractional part, which must fit the target type
+// rather than saturating when cast. 2^63 and 2^64 are exactly representable as floats.
+const I64_FLOAT_BOUND: f64 = 9_223_372_036_854_775_808.0;
+const U64_FLOAT_BOUND: f64 = 18_446_744_073_709_551_616.0;
+
+fn parse_i64(value: &Value, path: &str) -> Result<i64> {
+    match value {
+        Value::Number(n) => n.as_i64().or_else(|| {
+            n.as_f64()
+                .filter(|f| f.fract() == 0.0 && (-I64_FLOAT_BOUND..I64_FLOAT_BOUND).contains(f))
+                .map(|f| f as i64)
+        }),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| invalid(path, "expected an integer"))
+}
+
+fn parse_u64(value: &Value, path: &str) -> Result<u64> {
+    match value {
+        Value::Number(n) => n.as_u64().or_else(|| {
+            n.as_f64()
+                .filter(|f| f.fract() == 0.0 && (0.0..U64_FLOAT_BOUND).contains(f))
+                .map(|f| f as u64)
+        }),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| invalid(path, "expected an unsigned integer"))
+}
+
+fn parse_i32(value: &Value, path: &str) -> Result<i32> {
+    i32::try_from(parse_i64(value, path)?)
+        .map_err(|_| invalid(path, "integer out of range for int32"))
+}
+
+fn parse_u32(value: &Value, path: &str) -> Result<u32> {
+    u32::try_from(parse_u64(value, path)?)
+        .map_err(|_| invalid(path, "integer out of range for uint32"))
+}
+
+fn parse_f64(value: &Value, path: &str) -> Result<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => match s.as_str() {
+            "NaN" => Some(f64::NAN),
+            "Infinity" => Some(f64::INFINITY),
+            "-Infinity" => Some(f64::NEG_INFINITY),
+            s => s.parse().ok(),
+        },
+        _ => None,
+    }
+    .ok_or_else(|| invalid(path, "expected a number"))
+}
+
+/// Enum values may be given by number, by their full name (`EVENT_TYPE_TIMER_STARTED`), or by the
+/// name without the enum's prefix in any casing (`TimerStarted`), which older tools produce.
+fn parse_enum(type_name: &str, value: &Value, path: &str) -> Result<i32> {
+    let name = match value {
+        Value::String(s) => s,
+        _ => return parse_i32(value, path),
+    };
+    let desc = DESCRIPTORS
+        .enums
+        .get(type_name)
+        .ok_or_else(|| invalid(path, format!("unknown enum type {}", type_name)))?;
+    let normalize = |s: &str| s.replace('_', "").to_lowercase();
+    let prefix = normalize(desc.name());
+    let wanted = normalize(name);
+    desc.value
+        .iter()
+        .find(|v| {
+            let candidate = normalize(v.name());
+            v.name() == name
+                || candidate == wanted
+                || candidate.strip_prefix(&prefix) == Some(wanted.as_str())
+        })
+        .map(|v| v.number())
+        .ok_or_else(|| invalid(path, format!("{} is not a value of {}", name, type_name)))
+}
+
+fn parse_base64(s: &str) -> Option<Vec<u8>> {
+    let trimmed = s.trim_end_matches('=');
+    base64::decode_config(trimmed, base64::STANDARD_NO_PAD)
+        .or_else(|_| base64::decode_config(trimmed, base64::URL_SAFE_NO_PAD))
+        .ok()
+}
+
+/// The largest number of seconds a protobuf duration may hold, about 10,000 years
+const MAX_DURATION_SECONDS: i64 = 315_576_000_000;
+
+/// Parses durations like `1.5s` into seconds and nanoseconds
+fn parse_duration(s: &str) -> Option<(i64, i32)> {
+    let s = s.strip_suffix('s')?;
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+    let (secs, frac) = s.split_once('.').unwrap_or((s, ""));
+    if secs.is_empty() || !secs.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let seconds: i64 = secs.parse().ok().filter(|s| *s <= MAX_DURATION_SECONDS)?;
+    let nanos = parse_nanos(frac)?;
+    Some(if negative {
+        (-seconds, -nanos)
+    } else {
+        (seconds, nanos)
+    })
+}
+
+fn encode_seconds_and_nanos(seconds: i64, nanos: i32, buf: &mut Vec<u8>) {
+    if seconds != 0 {
+        encode_key(1, WIRE_VARINT, buf);
+        encode_varint(seconds as u64, buf);
+    }
+    if nanos != 0 {
+        encode_key(2, WIRE_VARINT, buf);
+        encode_varint(nanos as i64 as u64, buf);
+    }
+}
+
+fn encode_key(field_num: i32, wire_type: u64, buf: &mut Vec<u8>) {
+    encode_varint(((field_num as u64) << 3) | wire_type, buf);
+}
+
+fn encode_bytes(bytes: &[u8], buf: &mut Vec<u8>) {
+    encode_varint(bytes.len() as u64, buf);
+    buf.extend_from_slice(bytes);
+}
+
+fn encode_varint(mut value: u64, buf: &mut Vec<u8>) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Encode a message of the provided fully qualified type as JSON
+pub(crate) fn encode_json<M: Message>(type_name: &str, msg: &M) -> Value {
+    message_to_json(type_name, &msg.encode_to_vec())
+        .expect("Messages encoded by prost must be readable using their descriptors")
+}
+
+/// A single value read from the protobuf wire format
+enum WireValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Len(&'a [u8]),
+    Fixed32(u32),
+}
+
+struct WireReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> WireReader<'a> {
+    fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    fn read_varint(&mut self) -> Option<u64> {
+        let mut value = 0;
+        for (i, b) in self.buf.iter().enumerate().take(10) {
+            value |= ((b & 0x7F) as u64) << (7 * i);
+            if b & 0x80 == 0 {
+                self.buf = &self.buf[i + 1..];
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.buf.len() < len {
+            return None;
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Some(bytes)
+    }
+
+    fn read_field(&mut self) -> Option<(i32, WireValue<'a>)> {
+        let key = self.read_varint()?;
+        let value = match key & 0x7 {
+            WIRE_VARINT => WireValue::Varint(self.read_varint()?),
+            WIRE_FIXED64 => {
+                WireValue::Fixed64(u64::from_le_bytes(self.read_bytes(8)?.try_into().ok()?))
+            }
+            WIRE_LEN => {
+                let len = self.read_varint()? as usize;
+                WireValue::Len(self.read_bytes(len)?)
+            }
+            WIRE_FIXED32 => {
+                WireValue::Fixed32(u32::from_le_bytes(self.read_bytes(4)?.try_into().ok()?))
+            }
+            _ => return None,
+        };
+        Some(((key >> 3) as i32, value))
+    }
+}
+
+fn message_to_json(type_name: &str, bytes: &[u8]) -> Option<Value> {
+    match type_name {
+        ".google.protobuf.Timestamp" => {
+            let (seconds, nanos) = read_seconds_and_nanos(bytes)?;
+            return Some(Value::String(format_timestamp(seconds, nanos)));
+        }
+        ".google.protobuf.Duration" => {
+            let (seconds, nanos) = read_seconds_and_nanos(bytes)?;
+            return Some(Value::String(format_duration(seconds, nanos)));
+        }
+        _ => {}
+    }
+    let desc = DESCRIPTORS.messages.get(type_name)?;
+    let is_wrapper = type_name.starts_with(".google.protobuf.") && type_name.ends_with("Value");
+    let mut obj = Map::new();
+    let mut reader = WireReader { buf: bytes };
+    while !reader.is_empty() {
+        let (num, wire_val) = reader.read_field()?;
+        // Unknown fields are skipped
+        let field = match desc.field.iter().find(|f| f.number() == num) {
+            Some(f) => f,
+            None => continue,
+        };
+        let key = field
+            .json_name
+            .clone()
+            .unwrap_or_else(|| lower_camel(field.name()));
+        if field.label() != Label::Repeated {
+            obj.insert(key, single_to_json(field, wire_val)?);
+            continue;
+        }
+        if field.r#type() == Type::Message {
+            let entry = DESCRIPTORS.messages.get(field.type_name())?;
+            if entry.options.as_ref().and_then(|o| o.map_entry) == Some(true) {
+                let (k, v) = map_entry_to_json(entry, wire_val)?;
+                if let Value::Object(map) =
+                    obj.entry(key).or_insert_with(|| Value::Object(Map::new()))
+                {
+                    map.insert(k, v);
+                }
+                continue;
+            }
+        }
+        let items = match wire_val {
+            WireValue::Len(packed) if is_packable(field.r#type()) => unpack_to_json(field, packed)?,
+            other => vec![single_to_json(field, other)?],
+        };
+        if let Value::Array(arr) = obj.entry(key).or_insert_with(|| Value::Array(vec![])) {
+            arr.extend(items);
+        }
+    }
+    if is_wrapper {
+        return obj.into_iter().next().map(|(_, v)| v).or_else(|| {
+            // Wrappers holding a default value have nothing on the wire
+            Some(match desc.field.first()?.r#type() {
+                Type::Bool => Value::Bool(false),
+                Type::String | Type::Bytes => Value::String("".to_string()),
+                Type::Int64 | Type::Uint64 => Value::String("0".to_string()),
+                _ => Value::from(0),
+            })
+        });
+    }
+    Some(Value::Object(obj))
+}
+
+fn map_entry_to_json(entry: &DescriptorProto, wire_val: WireValue) -> Option<(String, Value)> {
+    let bytes = match wire_val {
+        WireValue::Len(b) => b,
+        _ => return None,
+    };
+    let mut key = None;
+    let mut val = None;
+    let mut reader = WireReader { buf: bytes };
+    while !reader.is_empty() {
+        let (num, field_val) = reader.read_field()?;
+        let field = entry.field.iter().find(|f| f.number() == num)?;
+        let json = single_to_json(field, field_val)?;
+        if num == 1 {
+            key = Some(match json {
+                Value::String(s) => s,
+                other => other.to_string(),
+            });
+        } else {
+            val = Some(json);
+        }
+    }
+    // Entries with default keys or values may leave them off the wire
+    let val_field = entry.field.get(1)?;
+    let val = match val {
+        Some(v) => v,
+        None if val_field.r#type() == Type::Message => Value::Object(Map::new()),
+        None => single_to_json(val_field, default_wire_value(val_field.r#type()))?,
+    };
+    Some((key.unwrap_or_default(), val))
+}
+
+fn default_wire_value(t: Type) -> WireValue<'static> {
+    match t {
+        Type::Double | Type::Fixed64 | Type::Sfixed64 => WireValue::Fixed64(0),
+        Type::Float | Type::Fixed32 | Type::Sfixed32 => WireValue::Fixed32(0),
+        Type::String | Type::Bytes | Type::Message | Type::Group => WireValue::Len(&[]),
+        _ => WireValue::Varint(0),
+    }
+}
+
+fn is_packable(t: Type) -> bool {
+    !matches!(t, Type::String | Type::Bytes | Type::Message | Type::Group)
+}
+
+fn unpack_to_json(field: &FieldDescriptorProto, packed: &[u8]) -> Option<Vec<Value>> {
+    let mut reader = WireReader { buf: packed };
+    let mut items = vec![];
+    while !reader.is_empty() {
+        let val = match field.r#type() {
+            Type::Double | Type::Fixed64 | Type::Sfixed64 => {
+                WireValue::Fixed64(u64::from_le_bytes(reader.read_bytes(8)?.try_into().ok()?))
+            }
+            Type::Float | Type::Fixed32 | Type::Sfixed32 => {
+                WireValue::Fixed32(u32::from_le_bytes(reader.read_bytes(4)?.try_into().ok()?))
+            }
+            _ => WireValue::Varint(reader.read_varint()?),
+        };
+        items.push(single_to_json(field, val)?);
+    }
+    Some(items)
+}
+
+fn single_to_json(field: &FieldDescriptorProto, wire_val: WireValue) -> Option<Value> {
+    Some(match (field.r#type(), wire_val) {
+        (Type::Double, WireValue::Fixed64(v)) => float_to_json(f64::from_bits(v)),
+        (Type::Float, WireValue::Fixed32(v)) => float_to_json(f32::from_bits(v) as f64),
+        (Type::Int64, WireValue::Varint(v)) => Value::String((v as i64).to_string()),
+        (Type::Uint64, WireValue::Varint(v)) => Value::String(v.to_string()),
+        (Type::Int32, WireValue::Varint(v)) => Value::from(v as i64 as i32),
+        (Type::Uint32, WireValue::Varint(v)) => Value::from(v as u32),
+        (Type::Sint64, WireValue::Varint(v)) => {
+            Value::String((((v >> 1) as i64) ^ -((v & 1) as i64)).to_string())
+        }
+        (Type::Sint32, WireValue::Varint(v)) => {
+            Value::from((((v >> 1) as i64) ^ -((v & 1) as i64)) as i32)
+        }
+        (Type::Fixed64, WireValue::Fixed64(v)) => Value::String(v.to_string()),
+        (Type::Sfixed64, WireValue::Fixed64(v)) => Value::String((v as i64).to_string()),
+        (Type::Fixed32, WireValue::Fixed32(v)) => Value::from(v),
+        (Type::Sfixed32, WireValue::Fixed32(v)) => Value::from(v as i32),
+        (Type::Bool, WireValue::Varint(v)) => Value::Bool(v != 0),
+        (Type::Enum, WireValue::Varint(v)) => {
+            let num = v as i64 as i32;
+            DESCRIPTORS
+                .enums
+                .get(field.type_name())
+                .and_then(|e| e.value.iter().find(|ev| ev.number() == num))
+                .map(|ev| Value::String(ev.name().to_string()))
+                .unwrap_or_else(|| Value::from(num))
+        }
+        (Type::String, WireValue::Len(b)) => Value::String(String::from_utf8_lossy(b).into()),
+        (Type::Bytes, WireValue::Len(b)) => Value::String(base64::encode(b)),
+        (Type::Message, WireValue::Len(b)) => message_to_json(field.type_name(), b)?,
+        _ => return None,
+    })
+}
+
+fn float_to_json(v: f64) -> Value {
+    if v.is_nan() {
+        Value::String("NaN".to_string())
+    } else if v.is_infinite() {
+        Value::String(if v > 0.0 { "Infinity" } else { "-Infinity" }.to_string())
+    } else {
+        Value::from(v)
+    }
+}
+
+fn read_seconds_and_nanos(bytes: &[u8]) -> Option<(i64, i32)> {
+    let mut reader = WireReader { buf: bytes };
+    let (mut seconds, mut nanos) = (0, 0);
+    while !reader.is_empty() {
+        match reader.read_field()? {
+            (1, WireValue::Varint(v)) => seconds = v as i64,
+            (2, WireValue::Varint(v)) => nanos = v as i64 as i32,
+            _ => {}
+        }
+    }
+    Some((seconds, nanos))
+}
+
+fn format_duration(seconds: i64, nanos: i32) -> String {
+    let sign = if seconds < 0 || nanos < 0 { "-" } else { "" };
+    format!(
+        "{}{}{}s",
+        sign,
+        seconds.unsigned_abs(),
+        format_nanos(nanos.abs())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coresdk::{
+        workflow_activation::{workflow_activation_job, FireTimer},
+        workflow_commands::{workflow_command, StartTimer},
+    };
+    use crate::temporal::api::{
+        common::v1::{Payload, Payloads},
+        enums::v1::EventType,
+        history::v1::{
+            history_event::Attributes, History, HistoryEvent, TimerStartedEventAttributes,
+            WorkflowExecutionStartedEventAttributes,
+        },
+    };
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn decodes_history_json() {
+        let json = r#"{
+          "events": [
+            {
+              "eventId": "1",
+              "eventTime": "2021-05-04T17:01:05.123456Z",
+              "eventType": "EVENT_TYPE_WORKFLOW_EXECUTION_STARTED",
+              "workflowExecutionStartedEventAttributes": {
+                "workflowType": { "name": "my_wf" },
+                "taskQueue": { "name": "q", "kind": "Normal" },
+                "input": {
+                  "payloads": [
+                    { "metadata": { "encoding": "anNvbi9wbGFpbg==" }, "data": "MQ==" }
+                  ]
+                },
+                "workflowRunTimeout": "1.5s",
+                "attempt": 1,
+                "original_execution_run_id": "run",
+                "someFieldFromTheFuture": true
+              }
+            },
+            {
+              "eventId": 2,
+              "eventType": "WorkflowTaskScheduled",
+              "workflowTaskScheduledEventAttributes": null
+            }
+          ]
+        }"#;
+        let history: History = decode_json(".temporal.api.history.v1.History", json).unwrap();
+        assert_eq!(history.events.len(), 2);
+        let started = &history.events[0];
+        assert_eq!(started.event_id, 1);
+        assert_eq!(
+            started.event_type,
+            EventType::WorkflowExecutionStarted as i32
+        );
+        let event_time: SystemTime = started.event_time.clone().unwrap().try_into().unwrap();
+        assert_eq!(
+            event_time,
+            UNIX_EPOCH + Duration::from_secs(1_620_147_665) + Duration::from_micros(123_456)
+        );
+        let attrs = match started.attributes.clone().unwrap() {
+            Attributes::WorkflowExecutionStartedEventAttributes(a) => a,
+            _ => panic!("Wrong attributes"),
+        };
+        assert_eq!(attrs.workflow_type.unwrap().name, "my_wf");
+        assert_eq!(attrs.task_queue.unwrap().kind, 1);
+        let payload = &attrs.input.unwrap().payloads[0];
+        assert_eq!(payload.metadata["encoding"], b"json/plain");
+        assert_eq!(payload.data, b"1".as_slice());
+        let timeout: Duration = attrs.workflow_run_timeout.unwrap().try_into().unwrap();
+        assert_eq!(timeout, Duration::from_millis(1500));
+        assert_eq!(attrs.original_execution_run_id, "run");
+        assert_eq!(
+            history.events[1].event_type,
+            EventType::WorkflowTaskScheduled as i32
+        );
+    }
+
+    #[test]
+    fn bad_values_report_path() {
+        let err = decode_json::<History>(
+            ".temporal.api.history.v1.History",
+            r#"{"events": [{"eventType": "NotAnEvent"}]}"#,
+        )
+        .unwrap_err();
+        assert!(
+            matches!(&err, JsonDecodeError::InvalidValue { path, .. } if path == "events[0].eventType"),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn history_round_trips() {
+        let history = History {
+            events: vec![
+                HistoryEvent {
+                    event_id: 1,
+                    event_time: Some(prost_types::Timestamp {
+                        seconds: 1_620_147_665,
+                        nanos: 123_456_789,
+                    }),
+                    event_type: EventType::WorkflowExecutionStarted as i32,
+                    attributes: Some(Attributes::WorkflowExecutionStartedEventAttributes(
+                        WorkflowExecutionStartedEventAttributes {
+                            workflow_type: Some("wf".to_string().into()),
+                            input: Some(Payloads {
+                                payloads: vec![Payload {
+                                    metadata: HashMap::from([(
+                                        "encoding".to_string(),
+                                        b"json/plain".to_vec(),
+                                    )]),
+                                    data: b"[1, 2]".to_vec().into(),
+                                }],
+                            }),
+                            workflow_task_timeout: Some(Duration::from_millis(10_500).into()),
+                            attempt: 1,
+                            ..Default::default()
+                        },
+                    )),
+                    ..Default::default()
+                },
+                HistoryEvent {
+                    event_id: 2,
+                    event_type: EventType::TimerStarted as i32,
+                    attributes: Some(Attributes::TimerStartedEventAttributes(
+                        TimerStartedEventAttributes {
+                            timer_id: "1".to_string(),
+                            start_to_fire_timeout: Some(Duration::from_secs(0).into()),
+                            workflow_task_completed_event_id: -4,
+                        },
+                    )),
+                    ..Default::default()
+                },
+            ],
+        };
+        let json = encode_json(".temporal.api.history.v1.History", &history);
+        assert_eq!(json["events"][0]["eventId"], "1");
+        assert_eq!(
+            json["events"][0]["eventType"],
+            "EVENT_TYPE_WORKFLOW_EXECUTION_STARTED"
+        );
+        let decoded: History =
+            decode_json(".temporal.api.history.v1.History", &json.to_string()).unwrap();
+        assert_eq!(decoded, history);
+    }
+
+    #[test]
+    fn lang_messages_round_trip() {
+        let activation = WorkflowActivation {
+            run_id: "run".to_string(),
+            timestamp: Some(prost_types::Timestamp {
+                seconds: 1_620_147_665,
+                nanos: 0,
+            }),
+            jobs: vec![workflow_activation_job::Variant::FireTimer(FireTimer { seq: 3 }).into()],
+            ..Default::default()
+        };
+        let json = activation.to_json();
+        assert!(json.contains(r#""runId":"run""#), "{}", json);
+        assert!(json.contains(r#""fireTimer":{"seq":3}"#), "{}", json);
+        assert_eq!(WorkflowActivation::from_json(&json).unwrap(), activation);
+
+        let completion = WorkflowActivationCompletion::from_cmd(
+            "run".to_string(),
+            workflow_command::Variant::StartTimer(StartTimer {
+                seq: 1,
+                start_to_fire_timeout: Some(Duration::from_millis(1500).into()),
+            }),
+        );
+        let json = completion.to_json();
+        assert!(
+            json.contains(r#""startToFireTimeout":"1.500s""#),
+            "{}",
+            json
+        );
+        assert_eq!(
+            WorkflowActivationCompletion::from_json(&json).unwrap(),
+            completion
+        );
+    }
+
+    #[test]
+    fn timestamps_round_trip() {
+        for (seconds, nanos) in [
+            (0, 0),
+            (-1, 0),
+            (1_620_147_665, 123_000_000),
+            (951_782_400, 7),
+        ] {
+            let formatted = format_timestamp(seconds, nanos);
+            assert_eq!(
+                parse_timestamp(&formatted),
+                Some((seconds, nanos)),
+                "{}",
+                formatted
+            );
+        }
+        assert_eq!(
+            format_timestamp(1_620_147_665, 123_000_000),
+            "2021-05-04T17:01:05.123Z"
+        );
+        assert_eq!(format_duration(-1, -500_000_000), "-1.500s");
+        assert_eq!(parse_duration("-1.500s"), Some((-1, -500_000_000)));
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        assert_eq!(parse_duration("315576000001s"), None);
+        assert_eq!(parse_duration("--1s"), None);
+        assert_eq!(parse_timestamp("123456789-01-01T00:00:00Z"), None);
+        let too_big = r#"{"seq": 4294967296}"#;
+        assert!(WorkflowActivationCompletion::from_json(&format!(
+            r#"{{"runId": "r", "successful": {{"commands": [{{"startTimer": {}}}]}}}}"#,
+            too_big
+        ))
+        .is_err());
+        assert!(WorkflowActivationCompletion::from_json(
+            r#"{"runId": "r", "successful": {"commands": [{"startTimer": {"seq": 1e300}}]}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn timestamps_with_offsets() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some((0, 0)));
+        assert_eq!(
+            parse_timestamp("1970-01-01T01:00:00.5+01:00"),
+            Some((0, 500_000_000))
+        );
+        assert_eq!(parse_timestamp("1969-12-31T23:59:59Z"), Some((-1, 0)));
+        assert_eq!(
+            parse_timestamp("2000-02-29T00:00:00Z"),
+            Some((951_782_400, 0))
+        );
+        assert_eq!(parse_timestamp("not a time"), None);
+    }
+}
@@ -9,15 +9,30 @@ pub mod utilities;
 mod history_builder;
 #[cfg(feature = "history_builders")]
 mod history_info;
+mod history_parsing;
+mod json;
+mod payload_visitor;
+mod search_attributes;
+mod serialized_history;
 mod task_token;
 
 #[cfg(feature = "history_builders")]
 pub use history_builder::{default_wes_attribs, TestHistoryBuilder, DEFAULT_WORKFLOW_TYPE};
 #[cfg(feature = "history_builders")]
 pub use history_info::HistoryInfo;
+pub use history_parsing::HistoryParseError;
+pub use json::{JsonDecodeError, ProtoJson};
+pub use payload_visitor::{VisitFailures, VisitPayloads};
+pub use search_attributes::{
+    SearchAttributeError, SearchAttributeRegistry, SearchAttributeType, SearchAttributeValue,
+    TypedSearchAttributes,
+};
+pub use serialized_history::{SerializedHistory, SerializedHistoryPage};
 pub use task_token::TaskToken;
 
-#[allow(clippy::large_enum_variant)]
+// Some comments in the coresdk protos start with `//`, which leaves them with 4 slashes once
+// generated
+#[allow(clippy::large_enum_variant, clippy::four_forward_slashes)]
 // I'd prefer not to do this, but there are some generated things that just don't need it.
 #[allow(missing_docs)]
 pub mod coresdk {
@@ -45,11 +60,25 @@ pub mod coresdk {
 
     #[allow(clippy::module_inception)]
     pub mod activity_task {
-        use crate::{coresdk::ActivityTaskCompletion, task_token::fmt_tt};
-        use std::fmt::{Display, Formatter};
+        use crate::{
+            coresdk::{common::Payload, ActivityTaskCompletion},
+            task_token::fmt_tt,
+        };
+        use std::{
+            collections::HashMap,
+            fmt::{Display, Formatter},
+        };
         tonic::include_proto!("coresdk.activity_task");
 
         impl ActivityTask {
+            /// The header fields of the task, if it starts an activity
+            pub fn headers_mut(&mut self) -> Option<&mut HashMap<String, Payload>> {
+                match self.variant.as_mut() {
+                    Some(activity_task::Variant::Start(s)) => Some(&mut s.header_fields),
+                    _ => None,
+                }
+            }
+
             pub fn cancel_from_ids(task_token: Vec<u8>, reason: ActivityCancelReason) -> Self {
                 Self {
                     task_token,
@@ -249,6 +278,10 @@ pub mod coresdk {
         }
     }
 
+    pub mod activation_capture {
+        tonic::include_proto!("coresdk.activation_capture");
+    }
+
     pub mod bridge {
         tonic::include_proto!("coresdk.bridge");
     }
@@ -276,7 +309,7 @@ pub mod coresdk {
                 metadata.insert("encoding".to_string(), b"binary/plain".to_vec());
                 Self {
                     metadata,
-                    data: v.as_ref().to_vec(),
+                    data: prost::bytes::Bytes::copy_from_slice(v.as_ref()),
                 }
             }
         }
@@ -284,14 +317,14 @@ pub mod coresdk {
         impl Payload {
             // Is it's own function b/c asref causes implementation conflicts
             pub fn as_slice(&self) -> &[u8] {
-                self.data.as_slice()
+                &self.data
             }
         }
 
         impl Display for Payload {
             fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
                 if self.data.len() > 64 {
-                    let mut windows = self.data.as_slice().windows(32);
+                    let mut windows = self.data.windows(32);
                     write!(
                         f,
                         "[{}..{}]",
@@ -318,8 +351,9 @@ pub mod coresdk {
         pub fn decode_change_marker_details(
             details: &HashMap<String, Payloads>,
         ) -> Option<(String, bool)> {
-            let name = std::str::from_utf8(&details.get("patch_id")?.payloads.get(0)?.data).ok()?;
-            let deprecated = *details.get("deprecated")?.payloads.get(0)?.data.get(0)? != 0;
+            let name =
+                std::str::from_utf8(&details.get("patch_id")?.payloads.first()?.data).ok()?;
+            let deprecated = *details.get("deprecated")?.payloads.first()?.data.first()? != 0;
             Some((name.to_string(), deprecated))
         }
 
@@ -346,7 +380,7 @@ pub mod coresdk {
         ) -> Option<LocalActivityMarkerData> {
             details
                 .get("data")
-                .and_then(|p| p.payloads.get(0))
+                .and_then(|p| p.payloads.first())
                 .and_then(|p| std::str::from_utf8(&p.data).ok())
                 .and_then(|s| serde_json::from_str(s).ok())
         }
@@ -451,6 +485,7 @@ pub mod coresdk {
                 },
                 query::v1::WorkflowQuery,
             },
+            SearchAttributeRegistry, TypedSearchAttributes,
         };
         use std::{
             collections::HashMap,
@@ -459,6 +494,19 @@ pub mod coresdk {
 
         tonic::include_proto!("coresdk.workflow_activation");
 
+        impl workflow_activation_job::Variant {
+            /// The headers the job carries from whoever started, signaled, or queried the
+            /// workflow, if it is a job which can carry headers
+            pub fn headers_mut(&mut self) -> Option<&mut HashMap<String, Payload>> {
+                match self {
+                    workflow_activation_job::Variant::StartWorkflow(j) => Some(&mut j.headers),
+                    workflow_activation_job::Variant::SignalWorkflow(j) => Some(&mut j.headers),
+                    workflow_activation_job::Variant::QueryWorkflow(j) => Some(&mut j.headers),
+                    _ => None,
+                }
+            }
+        }
+
         pub fn create_evict_activation(
             run_id: String,
             message: String,
@@ -468,6 +516,7 @@ pub mod coresdk {
                 timestamp: None,
                 run_id,
                 is_replaying: false,
+                workflow_task_attempt: 0,
                 jobs: vec![WorkflowActivationJob::from(
                     workflow_activation_job::Variant::RemoveFromCache(RemoveFromCache {
                         message,
@@ -485,6 +534,7 @@ pub mod coresdk {
                 timestamp: None,
                 run_id,
                 is_replaying: false,
+                workflow_task_attempt: 0,
                 jobs: queries
                     .into_iter()
                     .map(|qr| workflow_activation_job::Variant::QueryWorkflow(qr).into())
@@ -650,6 +700,30 @@ pub mod coresdk {
             }
         }
 
+        impl StartWorkflow {
+            /// Decode the search attributes the workflow was started with. Attributes which aren't
+            /// tagged with their type have it looked up in `registry` if one is provided, and
+            /// inferred otherwise. Attributes which can't be decoded are left out, since they may
+            /// have been set by other SDKs in ways this one doesn't understand.
+            pub fn typed_search_attributes(
+                &self,
+                registry: Option<&SearchAttributeRegistry>,
+            ) -> TypedSearchAttributes {
+                let fields = match &self.search_attributes {
+                    Some(sa) => sa
+                        .indexed_fields
+                        .iter()
+                        .map(|(k, v)| (k.clone(), v.clone().into()))
+                        .collect(),
+                    None => return TypedSearchAttributes::default(),
+                };
+                match registry {
+                    Some(registry) => registry.decode_lossy(&fields),
+                    None => TypedSearchAttributes::decode_lossy(&fields),
+                }
+            }
+        }
+
         impl From<WorkflowExecutionCancelRequestedEventAttributes> for CancelWorkflow {
             fn from(_a: WorkflowExecutionCancelRequestedEventAttributes) -> Self {
                 Self { details: vec![] }
@@ -728,8 +802,35 @@ pub mod coresdk {
     pub mod workflow_commands {
         tonic::include_proto!("coresdk.workflow_commands");
 
-        use crate::temporal::api::{common::v1::Payloads, enums::v1::QueryResultType};
-        use std::fmt::{Display, Formatter};
+        use crate::{
+            coresdk::common::Payload,
+            temporal::api::{common::v1::Payloads, enums::v1::QueryResultType},
+        };
+        use std::{
+            collections::HashMap,
+            fmt::{Display, Formatter},
+        };
+
+        impl workflow_command::Variant {
+            /// The headers to send along with the command, if it starts or signals another
+            /// execution
+            pub fn headers_mut(&mut self) -> Option<&mut HashMap<String, Payload>> {
+                match self {
+                    workflow_command::Variant::ScheduleActivity(c) => Some(&mut c.headers),
+                    workflow_command::Variant::ScheduleLocalActivity(c) => Some(&mut c.headers),
+                    workflow_command::Variant::StartChildWorkflowExecution(c) => {
+                        Some(&mut c.headers)
+                    }
+                    workflow_command::Variant::ContinueAsNewWorkflowExecution(c) => {
+                        Some(&mut c.headers)
+                    }
+                    workflow_command::Variant::SignalExternalWorkflowExecution(c) => {
+                        Some(&mut c.headers)
+                    }
+                    _ => None,
+                }
+            }
+        }
 
         impl Display for WorkflowCommand {
             fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -1128,6 +1229,7 @@ pub mod coresdk {
                         heartbeat_timeout: r.heartbeat_timeout,
                         retry_policy: r.retry_policy.map(Into::into),
                         is_local: false,
+                        last_failure: None,
                     },
                 )),
             }
@@ -1318,7 +1420,7 @@ pub mod coresdk {
             metadata.insert("encoding".to_string(), b"binary/plain".to_vec());
             Self {
                 metadata,
-                data: v.as_ref().to_vec(),
+                data: prost::bytes::Bytes::copy_from_slice(v.as_ref()),
             }
         }
     }
@@ -1359,7 +1461,7 @@ pub mod coresdk {
             metadata.insert("encoding".to_string(), b"json/plain".to_vec());
             Ok(Payload {
                 metadata,
-                data: as_json.into_bytes(),
+                data: as_json.into_bytes().into(),
             })
         }
     }
@@ -1482,6 +1584,18 @@ pub mod temporal {
                     }
                 }
 
+                impl Command {
+                    /// Serialize the command to its canonical JSON representation. Map fields
+                    /// (ex: headers) are ordered by key, so the output is stable.
+                    pub fn to_json(&self) -> String {
+                        serde_json::to_string_pretty(&crate::json::encode_json(
+                            ".temporal.api.command.v1.Command",
+                            self,
+                        ))
+                        .expect("JSON values always serialize")
+                    }
+                }
+
                 impl From<workflow_commands::StartTimer> for command::Attributes {
                     fn from(s: workflow_commands::StartTimer) -> Self {
                         Self::StartTimerCommandAttributes(StartTimerCommandAttributes {
@@ -1714,6 +1828,22 @@ pub mod temporal {
                 tonic::include_proto!("temporal.api.history.v1");
 
                 impl History {
+                    /// Read a history from its JSON representation, as exported by the Temporal
+                    /// CLI, web UI, or other SDKs
+                    pub fn from_json(json: &str) -> Result<Self, crate::JsonDecodeError> {
+                        crate::json::decode_json(".temporal.api.history.v1.History", json)
+                    }
+
+                    /// Serialize the history to the same JSON representation used by the Temporal
+                    /// CLI, web UI, and other SDKs
+                    pub fn to_json(&self) -> String {
+                        serde_json::to_string_pretty(&crate::json::encode_json(
+                            ".temporal.api.history.v1.History",
+                            self,
+                        ))
+                        .expect("JSON values always serialize")
+                    }
+
                     pub fn extract_run_id_from_start(&self) -> Result<&str, anyhow::Error> {
                         if let Some(
                             history_event::Attributes::WorkflowExecutionStartedEventAttributes(wes),
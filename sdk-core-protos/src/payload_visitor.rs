@@ -0,0 +1,497 @@
+//! Lets code which needs to inspect or transform every payload or failure inside a message (ex:
+//! payload codecs) do so without knowing where payloads and failures live in each message type.
+
+use crate::{
+    coresdk::{
+        common,
+        workflow_commands::{query_result, QueryResult},
+    },
+    serialized_history::{SerializedHistory, SerializedHistoryPage},
+    temporal::api::{
+        command::v1::{command, Command, *},
+        common::v1::{DataBlob, Memo, Payload, Payloads},
+        enums::v1::EncodingType,
+        failure::v1::{failure::FailureInfo, Failure},
+        history::v1::{history_event, History, HistoryEvent, *},
+        query::v1::WorkflowQuery,
+        workflowservice::v1::{
+            GetWorkflowExecutionHistoryResponse, PollActivityTaskQueueResponse,
+            PollWorkflowTaskQueueResponse,
+        },
+    },
+};
+use prost::Message;
+use std::collections::HashMap;
+
+/// Implemented by messages which may contain payloads holding user data, to visit each of them.
+///
+/// Search attributes (which the server needs to be able to read) and headers (which carry
+/// context for interceptors rather than user data) are not visited.
+pub trait VisitPayloads {
+    /// Call `visitor` with every payload in the message, which it may modify
+    fn visit_payloads_mut(&mut self, visitor: &mut dyn FnMut(&mut Payload));
+}
+
+/// Implemented by messages which may contain failures, to visit each of them. Only outermost
+/// failures are visited, visitors are responsible for any causes.
+pub trait VisitFailures {
+    /// Call `visitor` with every failure in the message, which it may modify
+    fn visit_failures_mut(&mut self, visitor: &mut dyn FnMut(&mut Failure));
+}
+
+impl VisitPayloads for Payload {
+    fn visit_payloads_mut(&mut self, visitor: &mut dyn FnMut(&mut Payload)) {
+        visitor(self)
+    }
+}
+
+impl VisitPayloads for common::Payload {
+    fn visit_payloads_mut(&mut self, visitor: &mut dyn FnMut(&mut Payload)) {
+        let mut api_payload = Payload::from(std::mem::take(self));
+        visitor(&mut api_payload);
+        *self = api_payload.into();
+    }
+}
+
+impl VisitFailures for Payload {
+    fn visit_failures_mut(&mut self, _: &mut dyn FnMut(&mut Failure)) {}
+}
+
+impl VisitFailures for common::Payload {
+    fn visit_failures_mut(&mut self, _: &mut dyn FnMut(&mut Failure)) {}
+}
+
+impl<T: VisitPayloads> VisitPayloads for Option<T> {
+    fn visit_payloads_mut(&mut self, visitor: &mut dyn FnMut(&mut Payload)) {
+        if let Some(v) = self {
+            v.visit_payloads_mut(visitor)
+        }
+    }
+}
+
+impl<T: VisitPayloads> VisitPayloads for Box<T> {
+    fn visit_payloads_mut(&mut self, visitor: &mut dyn FnMut(&mut Payload)) {
+        self.as_mut().visit_payloads_mut(visitor)
+    }
+}
+
+impl<T: VisitPayloads> VisitPayloads for Vec<T> {
+    fn visit_payloads_mut(&mut self, visitor: &mut dyn FnMut(&mut Payload)) {
+        for v in self {
+            v.visit_payloads_mut(visitor)
+        }
+    }
+}
+
+impl<T: VisitPayloads> VisitPayloads for HashMap<String, T> {
+    fn visit_payloads_mut(&mut self, visitor: &mut dyn FnMut(&mut Payload)) {
+        for v in self.values_mut() {
+            v.visit_payloads_mut(visitor)
+        }
+    }
+}
+
+impl<T: VisitFailures> VisitFailures for Option<T> {
+    fn visit_failures_mut(&mut self, visitor: &mut dyn FnMut(&mut Failure)) {
+        if let Some(v) = self {
+            v.visit_failures_mut(visitor)
+        }
+    }
+}
+
+impl<T: VisitFailures> VisitFailures for Box<T> {
+    fn visit_failures_mut(&mut self, visitor: &mut dyn FnMut(&mut Failure)) {
+        self.as_mut().visit_failures_mut(visitor)
+    }
+}
+
+impl<T: VisitFailures> VisitFailures for Vec<T> {
+    fn visit_failures_mut(&mut self, visitor: &mut dyn FnMut(&mut Failure)) {
+        for v in self {
+            v.visit_failures_mut(visitor)
+        }
+    }
+}
+
+impl<T: VisitFailures> VisitFailures for HashMap<String, T> {
+    fn visit_failures_mut(&mut self, visitor: &mut dyn FnMut(&mut Failure)) {
+        for v in self.values_mut() {
+            v.visit_failures_mut(visitor)
+        }
+    }
+}
+
+/// Implements [VisitPayloads] and [VisitFailures] for a message by visiting each of the listed
+/// fields
+macro_rules! visit_fields {
+    ($($msg:ty => [$($field:ident),+ $(,)?]),+ $(,)?) => {
+        $(
+            impl VisitPayloads for $msg {
+                fn visit_payloads_mut(&mut self, visitor: &mut dyn FnMut(&mut Payload)) {
+                    $(self.$field.visit_payloads_mut(visitor);)+
+                }
+            }
+            impl VisitFailures for $msg {
+                fn visit_failures_mut(&mut self, visitor: &mut dyn FnMut(&mut Failure)) {
+                    $(self.$field.visit_failures_mut(visitor);)+
+                }
+            }
+        )+
+    };
+}
+
+/// Implements [VisitPayloads] and [VisitFailures] for a message with a oneof of attributes by
+/// visiting whichever of the listed variants is set
+macro_rules! visit_attributes {
+    ($msg:ty, $attrs:path => [$($variant:ident),+ $(,)?]) => {
+        impl VisitPayloads for $msg {
+            fn visit_payloads_mut(&mut self, visitor: &mut dyn FnMut(&mut Payload)) {
+                use $attrs::*;
+                match &mut self.attributes {
+                    $(Some($variant(a)) => a.visit_payloads_mut(visitor),)+
+                    _ => {}
+                }
+            }
+        }
+        impl VisitFailures for $msg {
+            fn visit_failures_mut(&mut self, visitor: &mut dyn FnMut(&mut Failure)) {
+                use $attrs::*;
+                match &mut self.attributes {
+                    $(Some($variant(a)) => a.visit_failures_mut(visitor),)+
+                    _ => {}
+                }
+            }
+        }
+    };
+}
+
+visit_fields!(
+    Payloads => [payloads],
+    Memo => [fields],
+    History => [events],
+    WorkflowQuery => [query_args],
+    PollWorkflowTaskQueueResponse => [history, query, queries],
+    PollActivityTaskQueueResponse => [input, heartbeat_details],
+    // History events
+    WorkflowExecutionStartedEventAttributes =>
+        [input, continued_failure, last_completion_result, memo],
+    WorkflowExecutionCompletedEventAttributes => [result],
+    WorkflowExecutionFailedEventAttributes => [failure],
+    WorkflowExecutionContinuedAsNewEventAttributes =>
+        [input, failure, last_completion_result, memo],
+    WorkflowTaskFailedEventAttributes => [failure],
+    ActivityTaskScheduledEventAttributes => [input],
+    ActivityTaskStartedEventAttributes => [last_failure],
+    ActivityTaskCompletedEventAttributes => [result],
+    ActivityTaskFailedEventAttributes => [failure],
+    ActivityTaskTimedOutEventAttributes => [failure],
+    ActivityTaskCanceledEventAttributes => [details],
+    WorkflowExecutionCanceledEventAttributes => [details],
+    MarkerRecordedEventAttributes => [details, failure],
+    WorkflowExecutionSignaledEventAttributes => [input],
+    WorkflowExecutionTerminatedEventAttributes => [details],
+    SignalExternalWorkflowExecutionInitiatedEventAttributes => [input],
+    StartChildWorkflowExecutionInitiatedEventAttributes => [input, memo],
+    ChildWorkflowExecutionCompletedEventAttributes => [result],
+    ChildWorkflowExecutionFailedEventAttributes => [failure],
+    ChildWorkflowExecutionCanceledEventAttributes => [details],
+    // Commands
+    ScheduleActivityTaskCommandAttributes => [input],
+    CompleteWorkflowExecutionCommandAttributes => [result],
+    FailWorkflowExecutionCommandAttributes => [failure],
+    CancelWorkflowExecutionCommandAttributes => [details],
+    SignalExternalWorkflowExecutionCommandAttributes => [input],
+    RecordMarkerCommandAttributes => [details, failure],
+    ContinueAsNewWorkflowExecutionCommandAttributes =>
+        [input, failure, last_completion_result, memo],
+    StartChildWorkflowExecutionCommandAttributes => [input, memo],
+);
+
+/// Raw history blobs are serialized [History], which must be decoded to be visited and are
+/// re-encoded afterwards. Blobs which are not proto encoded, or fail to decode, are left alone for
+/// whatever reads the history to reject.
+fn visit_raw_history(blob: &mut DataBlob, visit: impl FnOnce(&mut History)) {
+    if blob.encoding_type != EncodingType::Proto3 as i32 {
+        return;
+    }
+    if let Ok(mut history) = History::decode(blob.data.as_slice()) {
+        visit(&mut history);
+        blob.data = history.encode_to_vec();
+    }
+}
+
+impl VisitPayloads for GetWorkflowExecutionHistoryResponse {
+    fn visit_payloads_mut(&mut self, visitor: &mut dyn FnMut(&mut Payload)) {
+        self.history.visit_payloads_mut(visitor);
+        for blob in &mut self.raw_history {
+            visit_raw_history(blob, |h| h.visit_payloads_mut(visitor));
+        }
+    }
+}
+
+impl VisitFailures for GetWorkflowExecutionHistoryResponse {
+    fn visit_failures_mut(&mut self, visitor: &mut dyn FnMut(&mut Failure)) {
+        self.history.visit_failures_mut(visitor);
+        for blob in &mut self.raw_history {
+            visit_raw_history(blob, |h| h.visit_failures_mut(visitor));
+        }
+    }
+}
+
+/// Serialized history events must be decoded to be visited and are re-encoded afterwards. Events
+/// which fail to decode are left alone for whatever reads the history to reject.
+fn visit_serialized_event(event: &mut Vec<u8>, visit: impl FnOnce(&mut HistoryEvent)) {
+    if let Ok(mut decoded) = HistoryEvent::decode(event.as_slice()) {
+        visit(&mut decoded);
+        *event = decoded.encode_to_vec();
+    }
+}
+
+impl VisitPayloads for SerializedHistory {
+    fn visit_payloads_mut(&mut self, visitor: &mut dyn FnMut(&mut Payload)) {
+        for event in &mut self.events {
+            visit_serialized_event(event, |e| e.visit_payloads_mut(visitor));
+        }
+    }
+}
+
+impl VisitFailures for SerializedHistory {
+    fn visit_failures_mut(&mut self, visitor: &mut dyn FnMut(&mut Failure)) {
+        for event in &mut self.events {
+            visit_serialized_event(event, |e| e.visit_failures_mut(visitor));
+        }
+    }
+}
+
+impl VisitPayloads for SerializedHistoryPage {
+    fn visit_payloads_mut(&mut self, visitor: &mut dyn FnMut(&mut Payload)) {
+        self.history.visit_payloads_mut(visitor);
+        for blob in &mut self.raw_history {
+            visit_raw_history(blob, |h| h.visit_payloads_mut(visitor));
+        }
+    }
+}
+
+impl VisitFailures for SerializedHistoryPage {
+    fn visit_failures_mut(&mut self, visitor: &mut dyn FnMut(&mut Failure)) {
+        self.history.visit_failures_mut(visitor);
+        for blob in &mut self.raw_history {
+            visit_raw_history(blob, |h| h.visit_failures_mut(visitor));
+        }
+    }
+}
+
+impl VisitPayloads for Failure {
+    fn visit_payloads_mut(&mut self, visitor: &mut dyn FnMut(&mut Payload)) {
+        match &mut self.failure_info {
+            Some(FailureInfo::ApplicationFailureInfo(i)) => i.details.visit_payloads_mut(visitor),
+            Some(FailureInfo::TimeoutFailureInfo(i)) => {
+                i.last_heartbeat_details.visit_payloads_mut(visitor)
+            }
+            Some(FailureInfo::CanceledFailureInfo(i)) => i.details.visit_payloads_mut(visitor),
+            Some(FailureInfo::ResetWorkflowFailureInfo(i)) => {
+                i.last_heartbeat_details.visit_payloads_mut(visitor)
+            }
+            _ => {}
+        }
+        self.cause.visit_payloads_mut(visitor);
+    }
+}
+
+impl VisitFailures for Failure {
+    fn visit_failures_mut(&mut self, visitor: &mut dyn FnMut(&mut Failure)) {
+        visitor(self)
+    }
+}
+
+visit_attributes!(HistoryEvent, history_event::Attributes => [
+    WorkflowExecutionStartedEventAttributes,
+    WorkflowExecutionCompletedEventAttributes,
+    WorkflowExecutionFailedEventAttributes,
+    WorkflowExecutionContinuedAsNewEventAttributes,
+    WorkflowTaskFailedEventAttributes,
+    ActivityTaskScheduledEventAttributes,
+    ActivityTaskStartedEventAttributes,
+    ActivityTaskCompletedEventAttributes,
+    ActivityTaskFailedEventAttributes,
+    ActivityTaskTimedOutEventAttributes,
+    ActivityTaskCanceledEventAttributes,
+    WorkflowExecutionCanceledEventAttributes,
+    MarkerRecordedEventAttributes,
+    WorkflowExecutionSignaledEventAttributes,
+    WorkflowExecutionTerminatedEventAttributes,
+    SignalExternalWorkflowExecutionInitiatedEventAttributes,
+    StartChildWorkflowExecutionInitiatedEventAttributes,
+    ChildWorkflowExecutionCompletedEventAttributes,
+    ChildWorkflowExecutionFailedEventAttributes,
+    ChildWorkflowExecutionCanceledEventAttributes,
+]);
+
+visit_attributes!(Command, command::Attributes => [
+    ScheduleActivityTaskCommandAttributes,
+    CompleteWorkflowExecutionCommandAttributes,
+    FailWorkflowExecutionCommandAttributes,
+    CancelWorkflowExecutionCommandAttributes,
+    SignalExternalWorkflowExecutionCommandAttributes,
+    RecordMarkerCommandAttributes,
+    ContinueAsNewWorkflowExecutionCommandAttributes,
+    StartChildWorkflowExecutionCommandAttributes,
+]);
+
+impl VisitPayloads for QueryResult {
+    fn visit_payloads_mut(&mut self, visitor: &mut dyn FnMut(&mut Payload)) {
+        match &mut self.variant {
+            Some(query_result::Variant::Succeeded(s)) => s.response.visit_payloads_mut(visitor),
+            Some(query_result::Variant::Failed(f)) => f.visit_payloads_mut(visitor),
+            None => {}
+        }
+    }
+}
+
+impl VisitFailures for QueryResult {
+    fn visit_failures_mut(&mut self, visitor: &mut dyn FnMut(&mut Failure)) {
+        if let Some(query_result::Variant::Failed(f)) = &mut self.variant {
+            f.visit_failures_mut(visitor)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{coresdk::AsJsonPayloadExt, temporal::api::failure::v1::ApplicationFailureInfo};
+
+    #[test]
+    fn visits_nested_failure_and_marker_payloads() {
+        let mut cmd = Command {
+            attributes: Some(command::Attributes::RecordMarkerCommandAttributes(
+                RecordMarkerCommandAttributes {
+                    details: HashMap::from([(
+                        "data".to_string(),
+                        "a".as_json_payload().unwrap().into(),
+                    )]),
+                    failure: Some(Failure {
+                        cause: Some(Box::new(Failure {
+                            failure_info: Some(FailureInfo::ApplicationFailureInfo(
+                                ApplicationFailureInfo {
+                                    details: Some("b".as_json_payload().unwrap().into()),
+                                    ..Default::default()
+                                },
+                            )),
+                            ..Default::default()
+                        })),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            )),
+            ..Default::default()
+        };
+        let mut seen = 0;
+        cmd.visit_payloads_mut(&mut |p| {
+            seen += 1;
+            p.data.clear();
+        });
+        assert_eq!(seen, 2);
+        cmd.visit_payloads_mut(&mut |p| assert!(p.data.is_empty()));
+    }
+
+    #[test]
+    fn visits_outermost_failures_only() {
+        let failure = |msg: &str| Failure {
+            message: msg.to_string(),
+            ..Default::default()
+        };
+        let mut history = History {
+            events: vec![
+                HistoryEvent {
+                    attributes: Some(
+                        history_event::Attributes::ActivityTaskFailedEventAttributes(
+                            ActivityTaskFailedEventAttributes {
+                                failure: Some(Failure {
+                                    cause: Some(Box::new(failure("cause"))),
+                                    ..failure("activity")
+                                }),
+                                ..Default::default()
+                            },
+                        ),
+                    ),
+                    ..Default::default()
+                },
+                HistoryEvent {
+                    attributes: Some(
+                        history_event::Attributes::WorkflowExecutionFailedEventAttributes(
+                            WorkflowExecutionFailedEventAttributes {
+                                failure: Some(failure("workflow")),
+                                ..Default::default()
+                            },
+                        ),
+                    ),
+                    ..Default::default()
+                },
+            ],
+        };
+        let mut seen = vec![];
+        history.visit_failures_mut(&mut |f| seen.push(f.message.clone()));
+        assert_eq!(seen, vec!["activity", "workflow"]);
+    }
+
+    #[test]
+    fn visits_raw_history_payloads() {
+        let history = History {
+            events: vec![HistoryEvent {
+                attributes: Some(
+                    history_event::Attributes::WorkflowExecutionCompletedEventAttributes(
+                        WorkflowExecutionCompletedEventAttributes {
+                            result: Some("a".as_json_payload().unwrap().into()),
+                            ..Default::default()
+                        },
+                    ),
+                ),
+                ..Default::default()
+            }],
+        };
+        let mut resp = GetWorkflowExecutionHistoryResponse {
+            raw_history: vec![DataBlob {
+                encoding_type: EncodingType::Proto3 as i32,
+                data: history.encode_to_vec(),
+            }],
+            ..Default::default()
+        };
+        let mut seen = 0;
+        resp.visit_payloads_mut(&mut |p| {
+            seen += 1;
+            p.data.clear();
+        });
+        assert_eq!(seen, 1);
+        resp.visit_payloads_mut(&mut |p| assert!(p.data.is_empty()));
+    }
+
+    #[test]
+    fn visits_serialized_history_payloads() {
+        let history = History {
+            events: vec![HistoryEvent {
+                attributes: Some(
+                    history_event::Attributes::WorkflowExecutionCompletedEventAttributes(
+                        WorkflowExecutionCompletedEventAttributes {
+                            result: Some("a".as_json_payload().unwrap().into()),
+                            ..Default::default()
+                        },
+                    ),
+                ),
+                ..Default::default()
+            }],
+        };
+        let mut page = SerializedHistoryPage {
+            history: Some(history.into()),
+            ..Default::default()
+        };
+        let mut seen = 0;
+        page.visit_payloads_mut(&mut |p| {
+            seen += 1;
+            p.data.clear();
+        });
+        assert_eq!(seen, 1);
+        page.visit_payloads_mut(&mut |p| assert!(p.data.is_empty()));
+    }
+}
@@ -0,0 +1,680 @@
+//! A typed layer over search attributes, which the API otherwise represents as maps of names to
+//! payloads. Values are encoded the same way other Temporal SDKs encode them, so attributes set by
+//! workflows using core can be queried and read by any SDK.
+
+use crate::{
+    coresdk::common::Payload,
+    temporal::api::{
+        common::v1::SearchAttributes, enums::v1::IndexedValueType,
+        workflowservice::v1::GetSearchAttributesResponse,
+    },
+    utilities::{parse_rfc3339, rfc3339},
+};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    time::SystemTime,
+};
+
+static TYPE_METADATA_KEY: &str = "type";
+static JSON_ENCODING: &[u8] = b"json/plain";
+
+/// The types search attribute values may have
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum SearchAttributeType {
+    /// A string which is tokenized for full-text search
+    Text,
+    /// A string which is matched exactly
+    Keyword,
+    /// A 64 bit signed integer
+    Int,
+    /// A 64 bit floating point number
+    Double,
+    /// A boolean
+    Bool,
+    /// A point in time
+    Datetime,
+    /// A list of strings which are each matched exactly. Registered on the server as a
+    /// [IndexedValueType::Keyword] attribute.
+    KeywordList,
+}
+
+impl SearchAttributeType {
+    /// The name of the type as it appears in the `type` metadata of encoded values
+    pub fn name(&self) -> &'static str {
+        match self {
+            SearchAttributeType::Text => "Text",
+            SearchAttributeType::Keyword => "Keyword",
+            SearchAttributeType::Int => "Int",
+            SearchAttributeType::Double => "Double",
+            SearchAttributeType::Bool => "Bool",
+            SearchAttributeType::Datetime => "Datetime",
+            SearchAttributeType::KeywordList => "KeywordList",
+        }
+    }
+
+    /// Look up a type by its name, as returned by [SearchAttributeType::name]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "Text" => SearchAttributeType::Text,
+            "Keyword" => SearchAttributeType::Keyword,
+            "Int" => SearchAttributeType::Int,
+            "Double" => SearchAttributeType::Double,
+            "Bool" => SearchAttributeType::Bool,
+            "Datetime" => SearchAttributeType::Datetime,
+            "KeywordList" => SearchAttributeType::KeywordList,
+            _ => return None,
+        })
+    }
+
+    /// Returns true if values of this type may be stored in an attribute the server has
+    /// registered with the provided indexed type
+    pub fn is_compatible_with(&self, indexed: IndexedValueType) -> bool {
+        matches!(
+            (self, indexed),
+            (SearchAttributeType::Text, IndexedValueType::Text)
+                | (SearchAttributeType::Keyword, IndexedValueType::Keyword)
+                | (SearchAttributeType::KeywordList, IndexedValueType::Keyword)
+                | (SearchAttributeType::Int, IndexedValueType::Int)
+                | (SearchAttributeType::Double, IndexedValueType::Double)
+                | (SearchAttributeType::Bool, IndexedValueType::Bool)
+                | (SearchAttributeType::Datetime, IndexedValueType::Datetime)
+        )
+    }
+
+    /// The type values of an attribute registered with the provided indexed type are decoded as,
+    /// if they don't say otherwise
+    fn for_indexed(indexed: IndexedValueType) -> Option<Self> {
+        Some(match indexed {
+            IndexedValueType::Unspecified => return None,
+            IndexedValueType::Text => SearchAttributeType::Text,
+            IndexedValueType::Keyword => SearchAttributeType::Keyword,
+            IndexedValueType::Int => SearchAttributeType::Int,
+            IndexedValueType::Double => SearchAttributeType::Double,
+            IndexedValueType::Bool => SearchAttributeType::Bool,
+            IndexedValueType::Datetime => SearchAttributeType::Datetime,
+        })
+    }
+}
+
+impl Display for SearchAttributeType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A typed search attribute value
+#[derive(Clone, Debug, PartialEq)]
+pub enum SearchAttributeValue {
+    /// See [SearchAttributeType::Text]
+    Text(String),
+    /// See [SearchAttributeType::Keyword]
+    Keyword(String),
+    /// See [SearchAttributeType::Int]
+    Int(i64),
+    /// See [SearchAttributeType::Double]
+    Double(f64),
+    /// See [SearchAttributeType::Bool]
+    Bool(bool),
+    /// See [SearchAttributeType::Datetime]. Encoded as an RFC 3339 timestamp in UTC.
+    Datetime(SystemTime),
+    /// See [SearchAttributeType::KeywordList]
+    KeywordList(Vec<String>),
+}
+
+impl SearchAttributeValue {
+    /// The type of this value
+    pub fn attribute_type(&self) -> SearchAttributeType {
+        match self {
+            SearchAttributeValue::Text(_) => SearchAttributeType::Text,
+            SearchAttributeValue::Keyword(_) => SearchAttributeType::Keyword,
+            SearchAttributeValue::Int(_) => SearchAttributeType::Int,
+            SearchAttributeValue::Double(_) => SearchAttributeType::Double,
+            SearchAttributeValue::Bool(_) => SearchAttributeType::Bool,
+            SearchAttributeValue::Datetime(_) => SearchAttributeType::Datetime,
+            SearchAttributeValue::KeywordList(_) => SearchAttributeType::KeywordList,
+        }
+    }
+
+    /// Encode the value as a JSON payload tagged with its type
+    pub fn to_payload(&self) -> Payload {
+        let json = match self {
+            SearchAttributeValue::Text(s) | SearchAttributeValue::Keyword(s) => {
+                Value::from(s.as_str())
+            }
+            SearchAttributeValue::Int(i) => Value::from(*i),
+            SearchAttributeValue::Double(d) => Value::from(*d),
+            SearchAttributeValue::Bool(b) => Value::from(*b),
+            SearchAttributeValue::Datetime(t) => Value::from(rfc3339(*t)),
+            SearchAttributeValue::KeywordList(l) => Value::from(l.clone()),
+        };
+        let metadata = HashMap::from([
+            ("encoding".to_string(), JSON_ENCODING.to_vec()),
+            (
+                TYPE_METADATA_KEY.to_string(),
+                self.attribute_type().name().as_bytes().to_vec(),
+            ),
+        ]);
+        Payload {
+            metadata,
+            data: json.to_string().into_bytes().into(),
+        }
+    }
+
+    /// Decode a value from a payload. The type is taken from the payload's `type` metadata if it
+    /// has any, then from `expected`, and is otherwise inferred from the JSON value.
+    pub fn from_payload(
+        payload: &Payload,
+        expected: Option<SearchAttributeType>,
+    ) -> Result<Self, SearchAttributeError> {
+        let undecodable = |reason: &str| SearchAttributeError::Undecodable(reason.to_string());
+        if let Some(encoding) = payload.metadata.get("encoding") {
+            if encoding.as_slice() != JSON_ENCODING {
+                return Err(undecodable("payload is not JSON encoded"));
+            }
+        }
+        let tagged = match payload.metadata.get(TYPE_METADATA_KEY) {
+            Some(name) => Some(
+                std::str::from_utf8(name)
+                    .ok()
+                    .and_then(SearchAttributeType::from_name)
+                    .ok_or_else(|| undecodable("payload has an unknown type"))?,
+            ),
+            None => None,
+        };
+        let json: Value = serde_json::from_slice(&payload.data)
+            .map_err(|e| SearchAttributeError::Undecodable(e.to_string()))?;
+
+        let attr_type = match tagged.or(expected) {
+            Some(t) => t,
+            None => match &json {
+                Value::String(_) => SearchAttributeType::Keyword,
+                Value::Number(n) if n.is_i64() => SearchAttributeType::Int,
+                Value::Number(_) => SearchAttributeType::Double,
+                Value::Bool(_) => SearchAttributeType::Bool,
+                Value::Array(_) => SearchAttributeType::KeywordList,
+                _ => return Err(undecodable("value is not a supported JSON type")),
+            },
+        };
+        let mismatch =
+            || SearchAttributeError::Undecodable(format!("value is not of type {}", attr_type));
+        Ok(match (attr_type, json) {
+            (SearchAttributeType::Text, Value::String(s)) => SearchAttributeValue::Text(s),
+            (SearchAttributeType::Keyword, Value::String(s)) => SearchAttributeValue::Keyword(s),
+            (SearchAttributeType::Int, Value::Number(n)) => {
+                SearchAttributeValue::Int(n.as_i64().ok_or_else(mismatch)?)
+            }
+            (SearchAttributeType::Double, Value::Number(n)) => {
+                SearchAttributeValue::Double(n.as_f64().ok_or_else(mismatch)?)
+            }
+            (SearchAttributeType::Bool, Value::Bool(b)) => SearchAttributeValue::Bool(b),
+            (SearchAttributeType::Datetime, Value::String(s)) => {
+                SearchAttributeValue::Datetime(parse_rfc3339(&s).ok_or_else(mismatch)?)
+            }
+            // Keyword attributes may hold a list of keywords
+            (SearchAttributeType::Keyword | SearchAttributeType::KeywordList, Value::Array(l)) => {
+                SearchAttributeValue::KeywordList(
+                    l.into_iter()
+                        .map(|v| match v {
+                            Value::String(s) => Ok(s),
+                            _ => Err(mismatch()),
+                        })
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            (SearchAttributeType::KeywordList, Value::String(s)) => {
+                SearchAttributeValue::KeywordList(vec![s])
+            }
+            _ => return Err(mismatch()),
+        })
+    }
+
+    /// Returns the string if this is a text or keyword value
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            SearchAttributeValue::Text(s) | SearchAttributeValue::Keyword(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer if this is an int value
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            SearchAttributeValue::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Returns the number if this is a double value
+    pub fn as_double(&self) -> Option<f64> {
+        match self {
+            SearchAttributeValue::Double(d) => Some(*d),
+            _ => None,
+        }
+    }
+
+    /// Returns the boolean if this is a bool value
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            SearchAttributeValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Returns the time if this is a datetime value
+    pub fn as_datetime(&self) -> Option<SystemTime> {
+        match self {
+            SearchAttributeValue::Datetime(t) => Some(*t),
+            _ => None,
+        }
+    }
+
+    /// Returns the keywords if this is a keyword list value
+    pub fn as_keyword_list(&self) -> Option<&[String]> {
+        match self {
+            SearchAttributeValue::KeywordList(l) => Some(l),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for SearchAttributeValue {
+    fn from(s: &str) -> Self {
+        Self::Keyword(s.to_string())
+    }
+}
+impl From<String> for SearchAttributeValue {
+    fn from(s: String) -> Self {
+        Self::Keyword(s)
+    }
+}
+impl From<i64> for SearchAttributeValue {
+    fn from(i: i64) -> Self {
+        Self::Int(i)
+    }
+}
+impl From<f64> for SearchAttributeValue {
+    fn from(d: f64) -> Self {
+        Self::Double(d)
+    }
+}
+impl From<bool> for SearchAttributeValue {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+impl From<SystemTime> for SearchAttributeValue {
+    fn from(t: SystemTime) -> Self {
+        Self::Datetime(t)
+    }
+}
+impl From<Vec<String>> for SearchAttributeValue {
+    fn from(l: Vec<String>) -> Self {
+        Self::KeywordList(l)
+    }
+}
+
+/// A set of typed search attributes, keyed by name
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TypedSearchAttributes {
+    values: HashMap<String, SearchAttributeValue>,
+}
+
+impl TypedSearchAttributes {
+    /// Create an empty set of attributes
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return these attributes with the provided one added, replacing any existing value
+    pub fn with(mut self, name: impl Into<String>, value: impl Into<SearchAttributeValue>) -> Self {
+        self.insert(name, value);
+        self
+    }
+
+    /// Add an attribute, returning the value it replaced if there was one
+    pub fn insert(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<SearchAttributeValue>,
+    ) -> Option<SearchAttributeValue> {
+        self.values.insert(name.into(), value.into())
+    }
+
+    /// Get the value of an attribute
+    pub fn get(&self, name: &str) -> Option<&SearchAttributeValue> {
+        self.values.get(name)
+    }
+
+    /// Remove an attribute, returning its value if it was set
+    pub fn remove(&mut self, name: &str) -> Option<SearchAttributeValue> {
+        self.values.remove(name)
+    }
+
+    /// Iterate over the names and values of the attributes
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &SearchAttributeValue)> {
+        self.values.iter()
+    }
+
+    /// The number of attributes
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns true if there are no attributes
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Copy all the attributes in `other` into this set, replacing any with the same name
+    pub fn merge(&mut self, other: TypedSearchAttributes) {
+        self.values.extend(other.values)
+    }
+
+    /// Encode the attributes as payloads, as used by commands and requests
+    pub fn encode(&self) -> HashMap<String, Payload> {
+        self.values
+            .iter()
+            .map(|(k, v)| (k.clone(), v.to_payload()))
+            .collect()
+    }
+
+    /// Decode attributes from payloads, inferring the type of any which aren't tagged with one.
+    /// Use [SearchAttributeRegistry::decode] to use the types the server has registered instead.
+    pub fn decode(fields: &HashMap<String, Payload>) -> Result<Self, SearchAttributeError> {
+        decode_fields(fields, |_| None)
+    }
+
+    /// Decode attributes like [TypedSearchAttributes::decode] does, but leave out any which can't
+    /// be decoded rather than failing, ex: ones set by other SDKs with encodings this one doesn't
+    /// understand
+    pub fn decode_lossy(fields: &HashMap<String, Payload>) -> Self {
+        decode_fields_lossy(fields, |_| None)
+    }
+}
+
+impl From<TypedSearchAttributes> for HashMap<String, Payload> {
+    fn from(attrs: TypedSearchAttributes) -> Self {
+        attrs.encode()
+    }
+}
+
+impl From<TypedSearchAttributes> for SearchAttributes {
+    fn from(attrs: TypedSearchAttributes) -> Self {
+        attrs.encode().into()
+    }
+}
+
+impl TryFrom<&SearchAttributes> for TypedSearchAttributes {
+    type Error = SearchAttributeError;
+
+    fn try_from(attrs: &SearchAttributes) -> Result<Self, Self::Error> {
+        let fields = attrs
+            .indexed_fields
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone().into()))
+            .collect();
+        Self::decode(&fields)
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for TypedSearchAttributes
+where
+    K: Into<String>,
+    V: Into<SearchAttributeValue>,
+{
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        Self {
+            values: iter
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        }
+    }
+}
+
+/// The search attributes registered on the server and their types, as returned by the
+/// `GetSearchAttributes` call. Used to check attributes before they are sent to the server, and to
+/// decode attributes whose payloads don't say what type they are.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchAttributeRegistry {
+    types: HashMap<String, IndexedValueType>,
+}
+
+impl SearchAttributeRegistry {
+    /// Create a registry from names of attributes and the types they are registered with
+    pub fn new(types: HashMap<String, IndexedValueType>) -> Self {
+        Self { types }
+    }
+
+    /// The type the attribute is registered with, if it is registered
+    pub fn indexed_type(&self, name: &str) -> Option<IndexedValueType> {
+        self.types.get(name).copied()
+    }
+
+    /// Check every attribute is registered, with a type its value is compatible with
+    pub fn validate(&self, attrs: &TypedSearchAttributes) -> Result<(), SearchAttributeError> {
+        for (name, value) in attrs.iter() {
+            let indexed = self
+                .indexed_type(name)
+                .ok_or_else(|| SearchAttributeError::Unregistered(name.clone()))?;
+            if !value.attribute_type().is_compatible_with(indexed) {
+                return Err(SearchAttributeError::TypeMismatch {
+                    name: name.clone(),
+                    registered: indexed,
+                    actual: value.attribute_type(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Decode attributes from payloads, using the registered type of any which aren't tagged
+    /// with one
+    pub fn decode(
+        &self,
+        fields: &HashMap<String, Payload>,
+    ) -> Result<TypedSearchAttributes, SearchAttributeError> {
+        decode_fields(fields, |name| self.expected_type(name))
+    }
+
+    /// Decode attributes like [SearchAttributeRegistry::decode] does, but leave out any which
+    /// can't be decoded rather than failing
+    pub fn decode_lossy(&self, fields: &HashMap<String, Payload>) -> TypedSearchAttributes {
+        decode_fields_lossy(fields, |name| self.expected_type(name))
+    }
+
+    fn expected_type(&self, name: &str) -> Option<SearchAttributeType> {
+        self.indexed_type(name)
+            .and_then(SearchAttributeType::for_indexed)
+    }
+}
+
+impl From<GetSearchAttributesResponse> for SearchAttributeRegistry {
+    fn from(resp: GetSearchAttributesResponse) -> Self {
+        Self::new(
+            resp.keys
+                .into_iter()
+                .filter_map(|(k, v)| IndexedValueType::from_i32(v).map(|t| (k, t)))
+                .collect(),
+        )
+    }
+}
+
+fn decode_fields(
+    fields: &HashMap<String, Payload>,
+    expected: impl Fn(&str) -> Option<SearchAttributeType>,
+) -> Result<TypedSearchAttributes, SearchAttributeError> {
+    let values = fields
+        .iter()
+        .map(|(name, payload)| {
+            SearchAttributeValue::from_payload(payload, expected(name))
+                .map(|v| (name.clone(), v))
+                .map_err(|e| match e {
+                    SearchAttributeError::Undecodable(reason) => {
+                        SearchAttributeError::Undecodable(format!("{}: {}", name, reason))
+                    }
+                    e => e,
+                })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(TypedSearchAttributes { values })
+}
+
+fn decode_fields_lossy(
+    fields: &HashMap<String, Payload>,
+    expected: impl Fn(&str) -> Option<SearchAttributeType>,
+) -> TypedSearchAttributes {
+    let values = fields
+        .iter()
+        .filter_map(|(name, payload)| {
+            SearchAttributeValue::from_payload(payload, expected(name))
+                .ok()
+                .map(|v| (name.clone(), v))
+        })
+        .collect();
+    TypedSearchAttributes { values }
+}
+
+/// Errors encoding, decoding, or validating search attributes
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum SearchAttributeError {
+    /// The attribute is not registered on the server
+    #[error("Search attribute {0} is not registered on the server")]
+    Unregistered(String),
+    /// The attribute's value does not have a type the server has registered it with
+    #[error("Search attribute {name} is registered as {registered:?} but was given a {actual}")]
+    TypeMismatch {
+        /// The name of the attribute
+        name: String,
+        /// The type the server has registered the attribute with
+        registered: IndexedValueType,
+        /// The type of the value the attribute was given
+        actual: SearchAttributeType,
+    },
+    /// A payload could not be decoded as a search attribute value
+    #[error("Search attribute could not be decoded: {0}")]
+    Undecodable(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn values_round_trip_through_payloads() {
+        let attrs = TypedSearchAttributes::new()
+            .with("Keyword", "hi")
+            .with(
+                "Text",
+                SearchAttributeValue::Text("hello there".to_string()),
+            )
+            .with("Int", 5_i64)
+            .with("Double", 1.5)
+            .with("Bool", true)
+            .with("Datetime", UNIX_EPOCH + Duration::from_secs(1_654_041_599))
+            .with("KeywordList", vec!["a".to_string(), "b".to_string()]);
+        let encoded = attrs.encode();
+        assert_eq!(&encoded["Datetime"].data[..], br#""2022-05-31T23:59:59Z""#);
+        assert_eq!(encoded["Int"].metadata["type"], b"Int");
+        assert_eq!(TypedSearchAttributes::decode(&encoded).unwrap(), attrs);
+    }
+
+    #[test]
+    fn untagged_values_use_registered_types() {
+        let untagged = |json: &str| Payload {
+            metadata: HashMap::from([("encoding".to_string(), b"json/plain".to_vec())]),
+            data: json.as_bytes().to_vec().into(),
+        };
+        let fields = HashMap::from([
+            (
+                "CustomDatetimeField".to_string(),
+                untagged(r#""1970-01-01T00:00:00Z""#),
+            ),
+            ("CustomDoubleField".to_string(), untagged("3")),
+            ("CustomKeywordField".to_string(), untagged(r#"["a"]"#)),
+        ]);
+
+        let inferred = TypedSearchAttributes::decode(&fields).unwrap();
+        assert_eq!(
+            inferred.get("CustomDatetimeField").unwrap().as_str(),
+            Some("1970-01-01T00:00:00Z")
+        );
+        assert_eq!(inferred.get("CustomDoubleField").unwrap().as_int(), Some(3));
+
+        let registry = SearchAttributeRegistry::new(HashMap::from([
+            (
+                "CustomDatetimeField".to_string(),
+                IndexedValueType::Datetime,
+            ),
+            ("CustomDoubleField".to_string(), IndexedValueType::Double),
+            ("CustomKeywordField".to_string(), IndexedValueType::Keyword),
+        ]));
+        let decoded = registry.decode(&fields).unwrap();
+        assert_eq!(
+            decoded.get("CustomDatetimeField").unwrap().as_datetime(),
+            Some(UNIX_EPOCH)
+        );
+        assert_eq!(
+            decoded.get("CustomDoubleField").unwrap().as_double(),
+            Some(3.0)
+        );
+        assert_eq!(
+            decoded.get("CustomKeywordField").unwrap().as_keyword_list(),
+            Some(["a".to_string()].as_slice())
+        );
+        assert!(registry.validate(&decoded).is_ok());
+    }
+
+    #[test]
+    fn validation_catches_unregistered_and_mistyped_attributes() {
+        let registry = SearchAttributeRegistry::from(GetSearchAttributesResponse {
+            keys: HashMap::from([("CustomIntField".to_string(), IndexedValueType::Int as i32)]),
+        });
+        assert_eq!(
+            registry.validate(&TypedSearchAttributes::new().with("Nope", 1_i64)),
+            Err(SearchAttributeError::Unregistered("Nope".to_string()))
+        );
+        assert_eq!(
+            registry.validate(&TypedSearchAttributes::new().with("CustomIntField", "1")),
+            Err(SearchAttributeError::TypeMismatch {
+                name: "CustomIntField".to_string(),
+                registered: IndexedValueType::Int,
+                actual: SearchAttributeType::Keyword,
+            })
+        );
+    }
+
+    #[test]
+    fn bad_payloads_are_reported() {
+        let bad = Payload {
+            metadata: HashMap::from([
+                ("encoding".to_string(), b"json/plain".to_vec()),
+                ("type".to_string(), b"Int".to_vec()),
+            ]),
+            data: br#""five""#.to_vec().into(),
+        };
+        let err = TypedSearchAttributes::decode(&HashMap::from([("Count".to_string(), bad)]))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            SearchAttributeError::Undecodable("Count: value is not of type Int".to_string())
+        );
+    }
+
+    #[test]
+    fn lossy_decoding_skips_bad_payloads() {
+        let binary = Payload {
+            metadata: HashMap::from([("encoding".to_string(), b"binary/plain".to_vec())]),
+            data: vec![0xff, 0x00].into(),
+        };
+        let mut fields = TypedSearchAttributes::new().with("Keyword", "hi").encode();
+        fields.insert("Binary".to_string(), binary);
+        assert!(TypedSearchAttributes::decode(&fields).is_err());
+
+        let decoded = TypedSearchAttributes::decode_lossy(&fields);
+        assert_eq!(decoded, TypedSearchAttributes::new().with("Keyword", "hi"));
+    }
+}
@@ -0,0 +1,107 @@
+use crate::temporal::api::{
+    common::v1::DataBlob, history::v1::History,
+    workflowservice::v1::GetWorkflowExecutionHistoryResponse,
+};
+use prost::{DecodeError, Message};
+
+/// A [History] whose events are left serialized. It has the same wire format as [History], so
+/// either may be decoded from the bytes of the other.
+#[derive(Clone, PartialEq, Message)]
+pub struct SerializedHistory {
+    /// Each of the history's events, serialized
+    #[prost(bytes = "vec", repeated, tag = "1")]
+    pub events: Vec<Vec<u8>>,
+}
+
+/// A [GetWorkflowExecutionHistoryResponse] whose history events are left serialized, so they can
+/// be decoded one at a time as they're needed rather than all at once when the page arrives. It
+/// has the same wire format as the response, which it is decoded from directly.
+#[derive(Clone, PartialEq, Message)]
+pub struct SerializedHistoryPage {
+    /// The page's events, if the server sent them as history rather than raw history
+    #[prost(message, optional, tag = "1")]
+    pub history: Option<SerializedHistory>,
+    /// Blobs of serialized [History], which the server sends instead of `history` if it is
+    /// configured to
+    #[prost(message, repeated, tag = "2")]
+    pub raw_history: Vec<DataBlob>,
+    /// Set if there are more history events than were included in this page
+    #[prost(bytes = "vec", tag = "3")]
+    pub next_page_token: Vec<u8>,
+    /// Whether the history was read from the archival store
+    #[prost(bool, tag = "4")]
+    pub archived: bool,
+}
+
+impl From<History> for SerializedHistory {
+    fn from(h: History) -> Self {
+        Self {
+            events: h.events.iter().map(Message::encode_to_vec).collect(),
+        }
+    }
+}
+
+impl TryFrom<SerializedHistory> for History {
+    type Error = DecodeError;
+
+    fn try_from(h: SerializedHistory) -> Result<Self, Self::Error> {
+        History::decode(h.encode_to_vec().as_slice())
+    }
+}
+
+impl From<GetWorkflowExecutionHistoryResponse> for SerializedHistoryPage {
+    fn from(r: GetWorkflowExecutionHistoryResponse) -> Self {
+        Self {
+            history: r.history.map(Into::into),
+            raw_history: r.raw_history,
+            next_page_token: r.next_page_token,
+            archived: r.archived,
+        }
+    }
+}
+
+impl TryFrom<SerializedHistoryPage> for GetWorkflowExecutionHistoryResponse {
+    type Error = DecodeError;
+
+    fn try_from(p: SerializedHistoryPage) -> Result<Self, Self::Error> {
+        Ok(Self {
+            history: p.history.map(TryInto::try_into).transpose()?,
+            raw_history: p.raw_history,
+            next_page_token: p.next_page_token,
+            archived: p.archived,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temporal::api::history::v1::HistoryEvent;
+
+    #[test]
+    fn decodes_from_response_bytes() {
+        let resp = GetWorkflowExecutionHistoryResponse {
+            history: Some(History {
+                events: vec![
+                    HistoryEvent {
+                        event_id: 1,
+                        ..Default::default()
+                    },
+                    HistoryEvent {
+                        event_id: 2,
+                        ..Default::default()
+                    },
+                ],
+            }),
+            next_page_token: vec![1, 2, 3],
+            ..Default::default()
+        };
+        let page = SerializedHistoryPage::decode(resp.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(page, resp.clone().into());
+        assert_eq!(page.history.as_ref().unwrap().events.len(), 2);
+        assert_eq!(
+            GetWorkflowExecutionHistoryResponse::try_from(page).unwrap(),
+            resp
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 pub trait TryIntoOrNone<F, T> {
     /// Turn an option of something into an option of another thing, trying to convert along the way
     /// and returning `None` if that conversion fails
@@ -12,3 +14,198 @@ where
         self.map(TryInto::try_into).transpose().ok().flatten()
     }
 }
+
+/// Format a time as an RFC 3339 timestamp in UTC
+pub fn rfc3339(time: SystemTime) -> String {
+    let (seconds, nanos) = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => (d.as_secs() as i64, d.subsec_nanos() as i32),
+        Err(e) => {
+            // Before the epoch, the seconds are negative, but nanos still count forward
+            let before = e.duration();
+            let seconds = -(before.as_secs() as i64);
+            match before.subsec_nanos() {
+                0 => (seconds, 0),
+                n => (seconds - 1, 1_000_000_000 - n as i32),
+            }
+        }
+    };
+    format_timestamp(seconds, nanos)
+}
+
+/// Parse an RFC 3339 timestamp, as produced by [rfc3339] or the server, into a time. Returns
+/// `None` if the timestamp is malformed.
+pub fn parse_rfc3339(timestamp: &str) -> Option<SystemTime> {
+    let (seconds, nanos) = parse_timestamp(timestamp)?;
+    let whole = if seconds >= 0 {
+        UNIX_EPOCH.checked_add(Duration::from_secs(seconds as u64))?
+    } else {
+        UNIX_EPOCH.checked_sub(Duration::from_secs(seconds.unsigned_abs()))?
+    };
+    whole.checked_add(Duration::from_nanos(nanos as u64))
+}
+
+/// Parses RFC 3339 timestamps like `2021-05-04T17:01:05.123Z` into seconds and nanoseconds since
+/// the unix epoch. Only years 1 through 9999 are accepted, as for protobuf timestamps.
+pub(crate) fn parse_timestamp(s: &str) -> Option<(i64, i32)> {
+    fn num(s: &str) -> Option<i64> {
+        if s.is_empty() || s.len() > 4 || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        s.parse().ok()
+    }
+
+    let (date, time) = s.split_once(['T', 't', ' '])?;
+    let mut date_parts = date.splitn(3, '-');
+    let year = num(date_parts.next()?)?;
+    let month = num(date_parts.next()?)?;
+    let day = num(date_parts.next()?)?;
+    if year < 1 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let (time, offset_secs) = if let Some(t) = time.strip_suffix(['Z', 'z']) {
+        (t, 0)
+    } else {
+        let sign_at = time.rfind(['+', '-'])?;
+        let (t, offset) = time.split_at(sign_at);
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let (hours, minutes) = offset[1..].split_once(':')?;
+        let (hours, minutes) = (num(hours)?, num(minutes)?);
+        if hours > 23 || minutes > 59 {
+            return None;
+        }
+        (t, sign * (hours * 3600 + minutes * 60))
+    };
+    let (hms, frac) = time.split_once('.').unwrap_or((time, ""));
+    let mut time_parts = hms.splitn(3, ':');
+    let hour = num(time_parts.next()?)?;
+    let minute = num(time_parts.next()?)?;
+    let second = num(time_parts.next()?)?;
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let nanos = parse_nanos(frac)?;
+
+    let seconds = days_from_civil(year, month, day)
+        .checked_mul(86400)?
+        .checked_add(hour * 3600 + minute * 60 + second - offset_secs)?;
+    Some((seconds, nanos))
+}
+
+/// Formats seconds and nanoseconds since the unix epoch as an RFC 3339 timestamp in UTC
+pub(crate) fn format_timestamp(seconds: i64, nanos: i32) -> String {
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+    let secs_of_day = seconds.rem_euclid(86400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        format_nanos(nanos)
+    )
+}
+
+/// Parses the digits after a decimal point as nanoseconds
+pub(crate) fn parse_nanos(frac: &str) -> Option<i32> {
+    if frac.len() > 9 || !frac.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    if frac.is_empty() {
+        return Some(0);
+    }
+    Some(frac.parse::<i32>().ok()? * 10_i32.pow(9 - frac.len() as u32))
+}
+
+/// Formats nanoseconds as a fraction with 0, 3, 6, or 9 digits, as the JSON mapping prefers
+pub(crate) fn format_nanos(nanos: i32) -> String {
+    if nanos == 0 {
+        "".to_string()
+    } else if nanos % 1_000_000 == 0 {
+        format!(".{:03}", nanos / 1_000_000)
+    } else if nanos % 1_000 == 0 {
+        format!(".{:06}", nanos / 1_000)
+    } else {
+        format!(".{:09}", nanos)
+    }
+}
+
+/// Days since the unix epoch of a date in the proleptic Gregorian calendar. See
+/// <http://howardhinnant.github.io/date_algorithms.html#days_from_civil>
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The date in the proleptic Gregorian calendar of some number of days since the unix epoch. See
+/// <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_times() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            rfc3339(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29T00:00:00Z"
+        );
+        assert_eq!(
+            rfc3339(UNIX_EPOCH + Duration::new(1_654_041_599, 5_000)),
+            "2022-05-31T23:59:59.000005Z"
+        );
+        assert_eq!(
+            rfc3339(UNIX_EPOCH - Duration::new(1, 500_000_000)),
+            "1969-12-31T23:59:58.500Z"
+        );
+    }
+
+    #[test]
+    fn parses_times() {
+        for time in [
+            UNIX_EPOCH,
+            UNIX_EPOCH + Duration::from_secs(951_782_400),
+            UNIX_EPOCH + Duration::new(1_654_041_599, 5_000),
+            UNIX_EPOCH - Duration::new(86_400 * 365, 250),
+        ] {
+            assert_eq!(parse_rfc3339(&rfc3339(time)), Some(time));
+        }
+        assert_eq!(
+            parse_rfc3339("2022-06-01T01:29:59.5+01:30"),
+            Some(UNIX_EPOCH + Duration::new(1_654_041_599, 500_000_000))
+        );
+        assert_eq!(
+            parse_rfc3339("1969-12-31T23:59:59Z"),
+            UNIX_EPOCH.checked_sub(Duration::from_secs(1))
+        );
+        assert_eq!(parse_rfc3339("2022-13-01T00:00:00Z"), None);
+        assert_eq!(parse_rfc3339("2022-06-01"), None);
+        assert_eq!(parse_rfc3339("yesterday"), None);
+        // Years which can't be represented are rejected rather than overflowing
+        assert_eq!(parse_rfc3339("99999999999999-01-01T00:00:00Z"), None);
+        assert_eq!(
+            parse_rfc3339("2022-06-01T00:00:00+9999999999999999:00"),
+            None
+        );
+    }
+}
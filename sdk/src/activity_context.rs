@@ -11,8 +11,9 @@ use temporal_sdk_core_protos::{
     coresdk::{
         activity_task,
         common::{Payload, RetryPolicy, WorkflowExecution},
-        ActivityHeartbeat,
+        ActivityHeartbeat, FromJsonPayloadExt, PayloadDeserializeErr,
     },
+    temporal::api::failure::v1::Failure,
     utilities::TryIntoOrNone,
 };
 use tokio_util::sync::CancellationToken;
@@ -51,6 +52,9 @@ pub struct ActivityInfo {
     pub current_attempt_scheduled_time: Option<SystemTime>,
     pub retry_policy: Option<RetryPolicy>,
     pub is_local: bool,
+    /// The failure which caused the previous attempt to be retried, if known. Only available for
+    /// local activities retried inside core.
+    pub last_failure: Option<Failure>,
 }
 
 impl ActContext {
@@ -82,6 +86,7 @@ impl ActContext {
             heartbeat_timeout,
             retry_policy,
             is_local,
+            last_failure,
         } = task;
         let deadline = calculate_deadline(
             scheduled_time.as_ref(),
@@ -116,6 +121,7 @@ impl ActContext {
                         .try_into_or_none(),
                     retry_policy,
                     is_local,
+                    last_failure,
                 },
             },
             first_arg,
@@ -145,6 +151,14 @@ impl ActContext {
         &self.heartbeat_details
     }
 
+    /// Deserialize the first heartbeat detail recorded by a previous attempt, if there was one.
+    /// Useful for resuming progress from where the last attempt left off.
+    pub fn get_heartbeat_details_as<T: FromJsonPayloadExt>(
+        &self,
+    ) -> Option<Result<T, PayloadDeserializeErr>> {
+        self.heartbeat_details.first().map(T::from_json_payload)
+    }
+
     /// RecordHeartbeat sends heartbeat for the currently executing activity
     pub fn record_heartbeat(&self, details: Vec<Payload>) {
         self.worker.record_activity_heartbeat(ActivityHeartbeat {
@@ -37,6 +37,7 @@ mod activity_context;
 mod app_data;
 mod conversions;
 pub mod interceptors;
+#[allow(dead_code)]
 mod payload_converter;
 mod workflow_context;
 mod workflow_future;
@@ -84,7 +85,7 @@ use temporal_sdk_core_protos::{
         ActivityTaskCompletion, AsJsonPayloadExt, FromJsonPayloadExt,
     },
     temporal::api::failure::v1::Failure,
-    TaskToken,
+    SearchAttributeRegistry, TaskToken,
 };
 use tokio::{
     sync::{
@@ -131,6 +132,8 @@ struct WorkflowHalf {
     workflows: RefCell<HashMap<String, WorkflowData>>,
     /// Maps workflow type to the function for executing workflow runs with that ID
     workflow_fns: RefCell<HashMap<String, WorkflowFunction>>,
+    /// Given to workflows to check and decode typed search attributes with
+    search_attribute_registry: Option<Arc<SearchAttributeRegistry>>,
 }
 struct WorkflowData {
     /// Channel used to send the workflow activations
@@ -161,6 +164,7 @@ impl Worker {
             workflow_half: WorkflowHalf {
                 workflows: Default::default(),
                 workflow_fns: Default::default(),
+                search_attribute_registry: None,
             },
             activity_half: ActivityHalf {
                 activity_fns: Default::default(),
@@ -195,6 +199,14 @@ impl Worker {
             .insert(workflow_type.into(), wf_function.into());
     }
 
+    /// Give workflows the search attributes registered on the server, as fetched with
+    /// [temporal_client::WorkflowClientTrait::get_search_attributes]. Typed search attributes
+    /// workflows upsert are then checked against it, and ones they were started with are decoded
+    /// as their registered types.
+    pub fn set_search_attribute_registry(&mut self, registry: SearchAttributeRegistry) {
+        self.workflow_half.search_attribute_registry = Some(Arc::new(registry));
+    }
+
     /// Register an Activity function to invoke when the Worker is asked to run an activity of
     /// `activity_type`
     pub fn register_activity<A, R>(
@@ -366,6 +378,7 @@ impl Worker {
 }
 
 impl WorkflowHalf {
+    #[allow(clippy::type_complexity)]
     fn workflow_activation_handler(
         &self,
         common: &CommonWorker,
@@ -383,7 +396,7 @@ impl WorkflowHalf {
         // using the function associated with that workflow id
         if let Some(WorkflowActivationJob {
             variant: Some(Variant::StartWorkflow(sw)),
-        }) = activation.jobs.get(0)
+        }) = activation.jobs.first()
         {
             let workflow_type = &sw.workflow_type;
             let wf_fns_borrow = self.workflow_fns.borrow();
@@ -397,6 +410,7 @@ impl WorkflowHalf {
                 // NOTE: Don't clone args if this gets ported to be a non-test rust worker
                 sw.arguments.clone(),
                 completions_tx.clone(),
+                self.search_attribute_registry.clone(),
             );
             let jh = tokio::spawn(async move {
                 tokio::select! {
@@ -25,17 +25,20 @@ use std::{
     task::Poll,
     time::{Duration, SystemTime},
 };
-use temporal_sdk_core_protos::coresdk::{
-    activity_result::{activity_resolution, ActivityResolution},
-    child_workflow::ChildWorkflowResult,
-    common::{NamespacedWorkflowExecution, Payload},
-    workflow_activation::resolve_child_workflow_execution_start::Status as ChildWorkflowStartStatus,
-    workflow_commands::{
-        request_cancel_external_workflow_execution as cancel_we,
-        signal_external_workflow_execution as sig_we, workflow_command,
-        RequestCancelExternalWorkflowExecution, SetPatchMarker, SignalExternalWorkflowExecution,
-        StartTimer, UpsertWorkflowSearchAttributes,
+use temporal_sdk_core_protos::{
+    coresdk::{
+        activity_result::{activity_resolution, ActivityResolution},
+        child_workflow::ChildWorkflowResult,
+        common::{NamespacedWorkflowExecution, Payload},
+        workflow_activation::resolve_child_workflow_execution_start::Status as ChildWorkflowStartStatus,
+        workflow_commands::{
+            request_cancel_external_workflow_execution as cancel_we,
+            signal_external_workflow_execution as sig_we, workflow_command,
+            RequestCancelExternalWorkflowExecution, SetPatchMarker,
+            SignalExternalWorkflowExecution, StartTimer, UpsertWorkflowSearchAttributes,
+        },
     },
+    SearchAttributeError, SearchAttributeRegistry, TypedSearchAttributes,
 };
 use tokio::sync::{mpsc, oneshot, watch};
 use tokio_stream::wrappers::UnboundedReceiverStream;
@@ -101,6 +104,9 @@ pub struct WfContextSharedData {
     pub changes: HashMap<String, bool>,
     pub is_replaying: bool,
     pub wf_time: Option<SystemTime>,
+    pub search_attributes: TypedSearchAttributes,
+    /// If set, typed search attributes are checked against it before being upserted
+    pub search_attribute_registry: Option<Arc<SearchAttributeRegistry>>,
 }
 
 // TODO: Dataconverter type interface to replace Payloads here. Possibly just use serde
@@ -113,6 +119,7 @@ impl WfContext {
         task_queue: String,
         args: Vec<Payload>,
         am_cancelled: watch::Receiver<bool>,
+        search_attribute_registry: Option<Arc<SearchAttributeRegistry>>,
     ) -> (Self, Receiver<RustWfCmd>) {
         // We need to use a normal std channel since our receiving side is non-async
         let (chan, rx) = crossbeam::channel::unbounded();
@@ -123,7 +130,10 @@ impl WfContext {
                 args,
                 chan,
                 am_cancelled,
-                shared: Arc::new(RwLock::new(Default::default())),
+                shared: Arc::new(RwLock::new(WfContextSharedData {
+                    search_attribute_registry,
+                    ..Default::default()
+                })),
                 seq_nums: RwLock::new(WfCtxProtectedDat {
                     next_timer_sequence_number: 1,
                     next_activity_sequence_number: 1,
@@ -152,6 +162,12 @@ impl WfContext {
         self.shared.read().wf_time
     }
 
+    /// Return the workflow's search attributes, as it was started with and as since upserted by
+    /// [WfContext::upsert_typed_search_attributes]
+    pub fn search_attributes(&self) -> TypedSearchAttributes {
+        self.shared.read().search_attributes.clone()
+    }
+
     pub(crate) fn get_shared_data(&self) -> Arc<RwLock<WfContextSharedData>> {
         self.shared.clone()
     }
@@ -292,12 +308,35 @@ impl WfContext {
             workflow_command::Variant::UpsertWorkflowSearchAttributesCommandAttributes(
                 UpsertWorkflowSearchAttributes {
                     seq: self.seq_nums.write().next_upsert_search_attrs_wf_seq(),
-                    search_attributes: HashMap::from_iter(attr_iter.into_iter()),
+                    search_attributes: HashMap::from_iter(attr_iter),
                 },
             ),
         ))
     }
 
+    /// Add or update a set of typed search attributes. They are also reflected in
+    /// [WfContext::search_attributes].
+    ///
+    /// If the worker was given a registry with [crate::Worker::set_search_attribute_registry], the
+    /// attributes are checked against it first, and nothing is upserted if any are unregistered
+    /// or of the wrong type.
+    pub fn upsert_typed_search_attributes(
+        &self,
+        attrs: TypedSearchAttributes,
+    ) -> Result<(), SearchAttributeError> {
+        let encoded = {
+            let mut shared = self.shared.write();
+            if let Some(registry) = &shared.search_attribute_registry {
+                registry.validate(&attrs)?;
+            }
+            let encoded = attrs.encode();
+            shared.search_attributes.merge(attrs);
+            encoded
+        };
+        self.upsert_search_attributes(encoded);
+        Ok(())
+    }
+
     /// Return a stream that produces values when the named signal is sent to this workflow
     pub fn make_signal_channel(
         &self,
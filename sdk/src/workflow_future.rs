@@ -35,6 +35,7 @@ use temporal_sdk_core_protos::{
     },
     temporal::api::failure::v1::Failure,
     utilities::TryIntoOrNone,
+    SearchAttributeRegistry,
 };
 use tokio::sync::{
     mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
@@ -51,12 +52,19 @@ impl WorkflowFunction {
         task_queue: String,
         args: Vec<Payload>,
         outgoing_completions: UnboundedSender<WorkflowActivationCompletion>,
+        search_attribute_registry: Option<Arc<SearchAttributeRegistry>>,
     ) -> (
         impl Future<Output = WorkflowResult<()>>,
         UnboundedSender<WorkflowActivation>,
     ) {
         let (cancel_tx, cancel_rx) = watch::channel(false);
-        let (wf_context, cmd_receiver) = WfContext::new(namespace, task_queue, args, cancel_rx);
+        let (wf_context, cmd_receiver) = WfContext::new(
+            namespace,
+            task_queue,
+            args,
+            cancel_rx,
+            search_attribute_registry,
+        );
         let (tx, incoming_activations) = unbounded_channel();
         (
             WorkflowFuture {
@@ -159,8 +167,11 @@ impl WorkflowFuture {
     fn handle_job(&mut self, variant: Option<Variant>) -> Result<bool, Error> {
         if let Some(v) = variant {
             match v {
-                Variant::StartWorkflow(_) => {
+                Variant::StartWorkflow(sw) => {
                     // TODO: Can assign randomness seed whenever needed
+                    let mut shared = self.ctx_shared.write();
+                    shared.search_attributes =
+                        sw.typed_search_attributes(shared.search_attribute_registry.as_deref());
                 }
                 Variant::FireTimer(FireTimer { seq }) => {
                     self.unblock(UnblockEvent::Timer(seq, TimerResult::Fired))?
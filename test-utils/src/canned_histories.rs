@@ -255,7 +255,7 @@ pub fn cancel_scheduled_activity(activity_id: &str, signal_id: &str) -> TestHist
         signal_id,
         vec![Payload {
             metadata: Default::default(),
-            data: b"hello ".to_vec(),
+            data: b"hello ".to_vec().into(),
         }],
     );
     t.add_full_wf_task();
@@ -328,7 +328,7 @@ pub fn scheduled_cancelled_activity_timeout(
         signal_id,
         vec![Payload {
             metadata: Default::default(),
-            data: b"hello ".to_vec(),
+            data: b"hello ".to_vec().into(),
         }],
     );
     t.add_full_wf_task();
@@ -436,7 +436,7 @@ pub fn cancel_scheduled_activity_abandon(activity_id: &str, signal_id: &str) ->
         signal_id,
         vec![Payload {
             metadata: Default::default(),
-            data: b"hello ".to_vec(),
+            data: b"hello ".to_vec().into(),
         }],
     );
     t.add_full_wf_task();
@@ -485,7 +485,7 @@ pub fn cancel_started_activity_abandon(activity_id: &str, signal_id: &str) -> Te
         signal_id,
         vec![Payload {
             metadata: Default::default(),
-            data: b"hello ".to_vec(),
+            data: b"hello ".to_vec().into(),
         }],
     );
     t.add_full_wf_task();
@@ -534,7 +534,7 @@ pub fn cancel_scheduled_activity_with_signal_and_activity_task_cancel(
         signal_id,
         vec![Payload {
             metadata: Default::default(),
-            data: b"hello ".to_vec(),
+            data: b"hello ".to_vec().into(),
         }],
     );
     t.add_full_wf_task();
@@ -551,7 +551,7 @@ pub fn cancel_scheduled_activity_with_signal_and_activity_task_cancel(
         signal_id,
         vec![Payload {
             metadata: Default::default(),
-            data: b"hello ".to_vec(),
+            data: b"hello ".to_vec().into(),
         }],
     );
     t.add_full_wf_task();
@@ -622,7 +622,7 @@ pub fn cancel_started_activity_with_signal_and_activity_task_cancel(
         signal_id,
         vec![Payload {
             metadata: Default::default(),
-            data: b"hello ".to_vec(),
+            data: b"hello ".to_vec().into(),
         }],
     );
     t.add_full_wf_task();
@@ -639,7 +639,7 @@ pub fn cancel_started_activity_with_signal_and_activity_task_cancel(
         signal_id,
         vec![Payload {
             metadata: Default::default(),
-            data: b"hello ".to_vec(),
+            data: b"hello ".to_vec().into(),
         }],
     );
     t.add_full_wf_task();
@@ -694,7 +694,7 @@ pub fn cancel_scheduled_activity_with_activity_task_cancel(
         signal_id,
         vec![Payload {
             metadata: Default::default(),
-            data: b"hello ".to_vec(),
+            data: b"hello ".to_vec().into(),
         }],
     );
     t.add_full_wf_task();
@@ -770,7 +770,7 @@ pub fn cancel_started_activity_with_activity_task_cancel(
         signal_id,
         vec![Payload {
             metadata: Default::default(),
-            data: b"hello ".to_vec(),
+            data: b"hello ".to_vec().into(),
         }],
     );
     t.add_full_wf_task();
@@ -814,14 +814,14 @@ pub fn two_signals(sig_1_id: &str, sig_2_id: &str) -> TestHistoryBuilder {
         sig_1_id,
         vec![Payload {
             metadata: Default::default(),
-            data: b"hello ".to_vec(),
+            data: b"hello ".to_vec().into(),
         }],
     );
     t.add_we_signaled(
         sig_2_id,
         vec![Payload {
             metadata: Default::default(),
-            data: b"world".to_vec(),
+            data: b"world".to_vec().into(),
         }],
     );
     t.add_workflow_task_scheduled_and_started();
@@ -871,7 +871,7 @@ pub fn lots_of_big_signals(num_tasks: usize) -> TestHistoryBuilder {
                 "bigsig",
                 vec![Payload {
                     metadata: Default::default(),
-                    data: dat.into(),
+                    data: dat.to_vec().into(),
                 }],
             );
         }
@@ -960,7 +960,7 @@ pub fn cancel_not_sent_when_also_complete_repro() -> TestHistoryBuilder {
         "sig-1",
         vec![Payload {
             metadata: Default::default(),
-            data: b"hello ".to_vec(),
+            data: b"hello ".to_vec().into(),
         }],
     );
     t.add_full_wf_task();
@@ -1028,7 +1028,7 @@ pub fn wft_timeout_repro() -> TestHistoryBuilder {
         "at-started",
         vec![Payload {
             metadata: Default::default(),
-            data: b"hello ".to_vec(),
+            data: b"hello ".to_vec().into(),
         }],
     );
     t.add_workflow_task_scheduled();
@@ -1036,7 +1036,7 @@ pub fn wft_timeout_repro() -> TestHistoryBuilder {
         "at-completed",
         vec![Payload {
             metadata: Default::default(),
-            data: b"hello ".to_vec(),
+            data: b"hello ".to_vec().into(),
         }],
     );
     let started_event_id = t.add_get_event_id(
@@ -1264,31 +1264,13 @@ fn start_child_wf_preamble(child_wf_id: &str) -> (TestHistoryBuilder, i64, i64)
     let mut t = TestHistoryBuilder::default();
     t.add_by_type(EventType::WorkflowExecutionStarted);
     t.add_full_wf_task();
-    let initiated_event_id = t.add_get_event_id(
-        EventType::StartChildWorkflowExecutionInitiated,
-        Some(
-            history_event::Attributes::StartChildWorkflowExecutionInitiatedEventAttributes(
-                StartChildWorkflowExecutionInitiatedEventAttributes {
-                    workflow_id: child_wf_id.to_owned(),
-                    ..Default::default()
-                },
-            ),
-        ),
-    );
-    let started_event_id = t.add_get_event_id(
-        EventType::ChildWorkflowExecutionStarted,
-        Some(
-            history_event::Attributes::ChildWorkflowExecutionStartedEventAttributes(
-                ChildWorkflowExecutionStartedEventAttributes {
-                    initiated_event_id,
-                    workflow_execution: Some(WorkflowExecution {
-                        workflow_id: child_wf_id.to_owned(),
-                        ..Default::default()
-                    }),
-                    ..Default::default()
-                },
-            ),
-        ),
+    let initiated_event_id = t.add_start_child_workflow_initiated(child_wf_id, "");
+    let started_event_id = t.add_child_workflow_started(
+        initiated_event_id,
+        WorkflowExecution {
+            workflow_id: child_wf_id.to_owned(),
+            ..Default::default()
+        },
     );
     t.add_full_wf_task();
     (t, initiated_event_id, started_event_id)
@@ -1,11 +1,11 @@
-//! Use this binary to fetch histories as proto-encoded binary. The first argument must be a
-//! workflow ID. A run id may optionally be provided as the second arg. The history is written to
-//! `{workflow_id}_history.bin`.
+//! Use this binary to fetch histories as proto-encoded binary and JSON. The first argument must be
+//! a workflow ID. A run id may optionally be provided as the second arg. The history is written to
+//! `{workflow_id}_history.bin` and `{workflow_id}_history.json`.
 //!
 //! We can use `clap` if this needs more arguments / other stuff later on.
 
 use prost::Message;
-use temporal_client::WorkflowClientTrait;
+use temporal_client::WfClientExt;
 use temporal_sdk_core_test_utils::get_integ_server_options;
 
 #[tokio::main]
@@ -15,14 +15,14 @@ async fn main() -> Result<(), anyhow::Error> {
     let wf_id = std::env::args()
         .nth(1)
         .expect("must provide workflow id as only argument");
-    let run_id = std::env::args().nth(2);
+    let run_id = std::env::args().nth(2).unwrap_or_default();
     let hist = client
-        .get_workflow_execution_history(wf_id.clone(), run_id, vec![])
-        .await?
-        .history
-        .expect("history field must be populated");
-    // Serialize history to file
+        .get_untyped_workflow_handle(wf_id.clone(), run_id)
+        .fetch_history()
+        .await?;
+    // Serialize history to files
     let byteified = hist.encode_to_vec();
     tokio::fs::write(format!("{}_history.bin", wf_id), &byteified).await?;
+    tokio::fs::write(format!("{}_history.json", wf_id), hist.to_json()).await?;
     Ok(())
 }
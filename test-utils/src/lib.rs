@@ -24,7 +24,7 @@ use std::{
     time::Duration,
 };
 use temporal_client::{
-    Client, RetryClient, WorkflowClientTrait, WorkflowExecutionInfo, WorkflowOptions,
+    Client, RetryClient, WfClientExt, WorkflowClientTrait, WorkflowExecutionInfo, WorkflowOptions,
 };
 use temporal_sdk::{interceptors::WorkerInterceptor, IntoActivityFunc, Worker, WorkflowFunction};
 use temporal_sdk_core::{
@@ -87,6 +87,15 @@ pub async fn history_from_proto_binary(path_from_root: &str) -> Result<History,
     Ok(History::decode(&*bytes)?)
 }
 
+/// Load history from a file containing its JSON representation
+pub async fn history_from_json(path_from_root: &str) -> Result<History, anyhow::Error> {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("..");
+    path.push(path_from_root);
+    let json = tokio::fs::read_to_string(path).await?;
+    Ok(History::from_json(&json)?)
+}
+
 /// Implements a builder pattern to help integ tests initialize core and create workflows
 pub struct CoreWfStarter {
     /// Used for both the task queue and workflow id
@@ -186,10 +195,9 @@ impl CoreWfStarter {
         let history = self
             .get_client()
             .await
-            .get_workflow_execution_history(wf_id.into(), Some(run_id.into()), vec![])
-            .await?
-            .history
-            .expect("history field must be populated");
+            .get_untyped_workflow_handle(wf_id, run_id)
+            .fetch_history()
+            .await?;
         let (replay_worker, _) = init_core_replay_preloaded(worker.task_queue(), &history);
         worker.with_new_core_worker(replay_worker);
         worker.run().await.unwrap();
@@ -442,6 +450,8 @@ pub fn get_integ_telem_options() -> TelemetryOptions {
         ob.tracing(TraceExporter::Otel(OtelCollectorOptions {
             url,
             headers: Default::default(),
+            metric_periodicity: None,
+            resource_attributes: Default::default(),
         }));
     }
     if let Some(addr) = env::var(PROM_ENABLE_ENV_VAR)
@@ -1,7 +1,19 @@
-use std::time::Duration;
-use temporal_client::{RetryClient, WorkflowClientTrait, WorkflowService};
-use temporal_sdk_core_protos::temporal::api::workflowservice::v1::DescribeNamespaceRequest;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use temporal_client::{
+    ClientInterceptor, RegisterNamespaceOptions, RetryClient, UpdateNamespaceOptions,
+    WorkflowClientTrait, WorkflowService,
+};
+use temporal_sdk_core_protos::temporal::api::{
+    enums::v1::TaskQueueType, workflowservice::v1::DescribeNamespaceRequest,
+};
 use temporal_sdk_core_test_utils::{get_integ_server_options, CoreWfStarter, NAMESPACE};
+use uuid::Uuid;
 
 #[tokio::test]
 async fn can_use_retry_client() {
@@ -28,9 +40,140 @@ async fn can_use_retry_raw_client() {
         .unwrap();
 }
 
+#[tokio::test]
+async fn manages_namespaces() {
+    let mut core = CoreWfStarter::new("manages_namespaces");
+    let client = core.get_client().await;
+    let namespace = format!("manages-namespaces-{}", Uuid::new_v4());
+    client
+        .register_namespace(RegisterNamespaceOptions {
+            namespace: namespace.clone(),
+            description: "before".to_string(),
+            workflow_execution_retention_period: Some(Duration::from_secs(60 * 60 * 24)),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    client
+        .update_namespace(
+            namespace.clone(),
+            UpdateNamespaceOptions {
+                description: Some("after".to_string()),
+                workflow_execution_retention_period: Some(Duration::from_secs(60 * 60 * 48)),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    let desc = client.describe_namespace(namespace.clone()).await.unwrap();
+    assert_eq!(desc.namespace_info.unwrap().description, "after");
+    assert_eq!(
+        desc.config.unwrap().workflow_execution_retention_ttl,
+        Some(Duration::from_secs(60 * 60 * 48).into())
+    );
+    client.deprecate_namespace(namespace).await.unwrap();
+}
+
 #[tokio::test]
 async fn calls_get_system_info() {
     let opts = get_integ_server_options();
     let raw_client = opts.connect_no_namespace(None, None).await.unwrap();
     assert!(raw_client.get_client().capabilities().is_some());
 }
+
+#[tokio::test]
+async fn lazy_client_connects_on_first_call() {
+    let mut opts = get_integ_server_options();
+    opts.lazy_connect = true;
+    let mut raw_client = opts.connect_no_namespace(None, None).await.unwrap();
+    assert!(raw_client.get_client().capabilities().is_none());
+    raw_client
+        .describe_namespace(DescribeNamespaceRequest {
+            namespace: NAMESPACE.to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert!(raw_client.get_client().capabilities().is_some());
+}
+
+#[tokio::test]
+async fn lazy_client_does_not_need_server_to_connect() {
+    let mut opts = get_integ_server_options();
+    opts.target_url = "http://localhost:1".parse().unwrap();
+    opts.lazy_connect = true;
+    opts.retry_config.max_retries = 1;
+    let client = opts.connect(NAMESPACE, None, None).await.unwrap();
+    assert!(client.list_namespaces().await.is_err());
+}
+
+#[tokio::test]
+async fn client_identity_can_be_overridden() {
+    let mut opts = get_integ_server_options();
+    opts.lazy_connect = true;
+    let client = opts.connect(NAMESPACE, None, None).await.unwrap();
+    let overridden = client.with_identity("pod-a".to_string()).unwrap();
+    assert_eq!(overridden.get_options().identity, "pod-a");
+    assert_eq!(client.get_options().identity, opts.identity);
+}
+
+#[tokio::test]
+async fn can_get_task_queue_backlog_stats() {
+    let mut starter = CoreWfStarter::new("task_queue_backlog_stats");
+    let client = starter.get_client().await;
+    starter.start_wf().await;
+    let stats = client
+        .task_queue_backlog_stats(
+            starter.get_task_queue().to_string(),
+            TaskQueueType::Workflow,
+        )
+        .await
+        .unwrap();
+    assert!(stats.approximate_backlog_count >= 0);
+}
+
+#[derive(Debug, Default)]
+struct CountingInterceptor {
+    calls: AtomicUsize,
+}
+impl ClientInterceptor for CountingInterceptor {
+    fn on_request(
+        &self,
+        _call_name: &str,
+        _request: &mut tonic::Request<()>,
+    ) -> Result<(), tonic::Status> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn interceptor_sees_calls() {
+    let interceptor = Arc::new(CountingInterceptor::default());
+    let mut opts = get_integ_server_options();
+    opts.interceptor = Some(interceptor.clone());
+    let client = opts.connect(NAMESPACE, None, None).await.unwrap();
+    // Connecting calls get_system_info
+    let calls_after_connect = interceptor.calls.load(Ordering::Relaxed);
+    assert!(calls_after_connect > 0);
+    client.list_namespaces().await.unwrap();
+    assert_eq!(
+        interceptor.calls.load(Ordering::Relaxed),
+        calls_after_connect + 1
+    );
+}
+
+#[tokio::test]
+async fn can_reload_tls_config_without_recreating_client() {
+    let opts = get_integ_server_options();
+    let mut client = opts.connect(NAMESPACE, None, None).await.unwrap();
+    let clone = client.clone();
+    client
+        .get_client_mut()
+        .reload_tls_config(opts.tls_cfg.clone())
+        .await
+        .unwrap();
+    client.list_namespaces().await.unwrap();
+    // Clones made before the reload use the new connection too
+    clone.list_namespaces().await.unwrap();
+}
@@ -57,7 +57,7 @@ async fn activity_heartbeat() {
     }
 
     let response_payload = Payload {
-        data: b"hello ".to_vec(),
+        data: b"hello ".to_vec().into(),
         metadata: Default::default(),
     };
     // Complete activity successfully.
@@ -102,7 +102,7 @@ async fn simple_query_legacy() {
     };
     let (q_resp, _) = tokio::join!(query_fut, workflow_completions_future);
     // Ensure query response is as expected
-    assert_eq!(&q_resp.unwrap()[0].data, query_resp);
+    assert_eq!(q_resp.unwrap()[0].data, query_resp.as_slice());
 }
 
 #[rstest::rstest]
@@ -198,7 +198,7 @@ async fn query_after_execution_complete(#[case] do_evict: bool) {
                 .await
                 .unwrap();
             // Ensure query response is as expected
-            assert_eq!(q_resp.unwrap()[0].data, query_resp);
+            assert_eq!(q_resp.unwrap()[0].data, query_resp.as_slice());
         };
 
         query_futs.push(query_fut.boxed());
@@ -328,8 +328,8 @@ async fn repros_query_dropped_on_floor() {
     };
     let (q1_res, q2_res, _) = tokio::join!(q1_fut, q2_fut, workflow_completions_future);
     // Ensure query responses are as expected
-    assert_eq!(&q1_res.unwrap()[0].data, q1_resp);
-    assert_eq!(&q2_res.unwrap()[0].data, q2_resp);
+    assert_eq!(q1_res.unwrap()[0].data, q1_resp.as_slice());
+    assert_eq!(q2_res.unwrap()[0].data, q2_resp.as_slice());
 }
 
 #[tokio::test]
@@ -1,6 +1,8 @@
 use assert_matches::assert_matches;
 use std::time::Duration;
-use temporal_client::{WfClientExt, WorkflowClientTrait, WorkflowExecutionResult, WorkflowOptions};
+use temporal_client::{
+    ActivityIdentifier, WfClientExt, WorkflowClientTrait, WorkflowExecutionResult, WorkflowOptions,
+};
 use temporal_sdk::{ActContext, ActivityOptions, WfContext, WorkflowResult};
 use temporal_sdk_core_protos::{
     coresdk::{
@@ -99,7 +101,7 @@ async fn activity_workflow() {
         }
     );
     let response_payload = Payload {
-        data: b"hello ".to_vec(),
+        data: b"hello ".to_vec().into(),
         metadata: Default::default(),
     };
     // Complete activity successfully.
@@ -250,7 +252,7 @@ async fn activity_retry() {
     );
     // Complete activity successfully
     let response_payload = Payload {
-        data: b"hello ".to_vec(),
+        data: b"hello ".to_vec().into(),
         metadata: Default::default(),
     };
     core.complete_activity_task(ActivityTaskCompletion {
@@ -647,7 +649,7 @@ async fn async_activity_completion_workflow() {
         }
     );
     let response_payload = Payload {
-        data: b"hello ".to_vec(),
+        data: b"hello ".to_vec().into(),
         metadata: Default::default(),
     };
     // Complete activity asynchronously.
@@ -689,6 +691,79 @@ async fn async_activity_completion_workflow() {
     core.complete_execution(&task.run_id).await;
 }
 
+#[tokio::test]
+async fn async_activity_completion_by_id() {
+    let mut starter = init_core_and_create_wf("async_activity_completion_by_id").await;
+    let core = starter.get_worker().await;
+    let task_q = starter.get_task_queue();
+    let task = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(
+        schedule_activity_cmd(
+            0,
+            task_q,
+            "act-1",
+            ActivityCancellationType::TryCancel,
+            Duration::from_secs(60),
+            Duration::from_secs(60),
+        )
+        .into_completion(task.run_id),
+    )
+    .await
+    .unwrap();
+    let task = core.poll_activity_task().await.unwrap();
+    let identifier = assert_matches!(
+        task.variant,
+        Some(act_task::Variant::Start(start_activity)) => {
+            let execution = start_activity.workflow_execution.unwrap();
+            ActivityIdentifier::ById {
+                workflow_id: execution.workflow_id,
+                run_id: execution.run_id,
+                activity_id: start_activity.activity_id,
+            }
+        }
+    );
+    core.complete_activity_task(ActivityTaskCompletion {
+        task_token: task.task_token,
+        result: Some(ActivityExecutionResult::will_complete_async()),
+    })
+    .await
+    .unwrap();
+
+    let response_payload = Payload {
+        data: b"hello ".to_vec().into(),
+        metadata: Default::default(),
+    };
+    let handle = starter
+        .get_client()
+        .await
+        .get_async_activity_handle(identifier);
+    assert!(!handle.heartbeat(None).await.unwrap());
+    handle
+        .complete(Some(Payloads {
+            payloads: vec![response_payload.clone().into()],
+        }))
+        .await
+        .unwrap();
+
+    let task = core.poll_workflow_activation().await.unwrap();
+    assert_matches!(
+        task.jobs.as_slice(),
+        [
+            WorkflowActivationJob {
+                variant: Some(workflow_activation_job::Variant::ResolveActivity(
+                    ResolveActivity {seq, result: Some(ActivityResolution {
+                    status: Some(act_res::Status::Completed(activity_result::Success{result: Some(r)})),
+                     ..})}
+                )),
+            },
+        ] => {
+            assert_eq!(*seq, 0);
+            assert_eq!(r, &response_payload);
+        }
+    );
+    core.complete_execution(&task.run_id).await;
+}
+
 #[tokio::test]
 async fn activity_cancelled_after_heartbeat_times_out() {
     let mut starter = init_core_and_create_wf("activity_cancelled_after_heartbeat_times_out").await;
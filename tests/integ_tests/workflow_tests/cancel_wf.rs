@@ -1,5 +1,6 @@
+use assert_matches::assert_matches;
 use std::time::Duration;
-use temporal_client::{WorkflowClientTrait, WorkflowOptions};
+use temporal_client::{WfClientExt, WorkflowClientTrait, WorkflowExecutionResult, WorkflowOptions};
 use temporal_sdk::{WfContext, WfExitValue, WorkflowResult};
 use temporal_sdk_core_protos::temporal::api::enums::v1::WorkflowExecutionStatus;
 use temporal_sdk_core_test_utils::CoreWfStarter;
@@ -56,3 +57,43 @@ async fn cancel_during_timer() {
         WorkflowExecutionStatus::Canceled as i32
     );
 }
+
+#[tokio::test]
+async fn cancel_and_describe_through_handle() {
+    let wf_name = "cancel_and_describe_through_handle";
+    let mut starter = CoreWfStarter::new(wf_name);
+    let mut worker = starter.worker().await;
+    let client = starter.get_client().await;
+    worker.register_wf(wf_name.to_string(), cancelled_wf);
+
+    let run_id = worker
+        .submit_wf(
+            wf_name.to_owned(),
+            wf_name.to_owned(),
+            vec![],
+            WorkflowOptions::default(),
+        )
+        .await
+        .unwrap();
+    let handle = client.get_untyped_workflow_handle(wf_name, run_id.clone());
+
+    let canceller = async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let desc = handle.describe().await.unwrap();
+        assert_eq!(desc.status, WorkflowExecutionStatus::Running);
+        assert_eq!(desc.workflow_type, wf_name);
+        handle.cancel().await.unwrap();
+    };
+
+    let (_, res) = tokio::join!(canceller, worker.run_until_done());
+    res.unwrap();
+    let desc = handle.describe().await.unwrap();
+    assert_eq!(desc.status, WorkflowExecutionStatus::Canceled);
+    assert_eq!(desc.run_id, run_id);
+    assert!(desc.close_time.is_some());
+    let res = handle
+        .get_workflow_result(Default::default())
+        .await
+        .unwrap();
+    assert_matches!(res, WorkflowExecutionResult::Cancelled(_));
+}
@@ -1,7 +1,8 @@
 use assert_matches::assert_matches;
 use std::time::Duration;
+use temporal_client::{WfClientExt, WorkflowOptions};
 use temporal_sdk::{WfContext, Worker, WorkflowFunction};
-use temporal_sdk_core::telemetry_init;
+use temporal_sdk_core::{replay::WorkflowReplayer, telemetry_init, WorkerConfigBuilder};
 use temporal_sdk_core_api::errors::{PollActivityError, PollWfError};
 use temporal_sdk_core_protos::{
     coresdk::{
@@ -12,8 +13,8 @@ use temporal_sdk_core_protos::{
     DEFAULT_WORKFLOW_TYPE,
 };
 use temporal_sdk_core_test_utils::{
-    canned_histories, get_integ_telem_options, history_from_proto_binary,
-    init_core_replay_preloaded, WorkerTestHelpers,
+    canned_histories, get_integ_telem_options, history_from_json, history_from_proto_binary,
+    init_core_replay_preloaded, CoreWfStarter, WorkerTestHelpers, NAMESPACE,
 };
 use tokio::join;
 
@@ -112,6 +113,66 @@ async fn replay_using_wf_function() {
     worker.run().await.unwrap();
 }
 
+#[tokio::test]
+async fn replay_json_history() {
+    telemetry_init(&get_integ_telem_options()).unwrap();
+    let history = history_from_json("histories/timer_workflow_history.json")
+        .await
+        .unwrap();
+    let replayer = WorkflowReplayer::new(
+        WorkerConfigBuilder::default()
+            .namespace(NAMESPACE)
+            .task_queue("timer_workflow_replay")
+            .build()
+            .unwrap(),
+        &history,
+    )
+    .unwrap();
+    let mut worker = Worker::new_from_core(replayer.worker(), "timer_workflow_replay".to_string());
+    worker.register_wf("timer_wf", timers_wf(1));
+    worker.run().await.unwrap();
+    assert_eq!(replayer.failure(), None);
+}
+
+#[tokio::test]
+async fn replay_fetched_history_from_json() {
+    let wf_name = "replay_fetched_history_from_json";
+    let mut starter = CoreWfStarter::new(wf_name);
+    let mut worker = starter.worker().await;
+    worker.register_wf(wf_name.to_owned(), timers_wf(2));
+    let run_id = worker
+        .submit_wf(
+            wf_name.to_owned(),
+            wf_name.to_owned(),
+            vec![],
+            WorkflowOptions::default(),
+        )
+        .await
+        .unwrap();
+    worker.run_until_done().await.unwrap();
+
+    let history = starter
+        .get_client()
+        .await
+        .get_untyped_workflow_handle(wf_name, run_id)
+        .fetch_history()
+        .await
+        .unwrap();
+    let replayer = WorkflowReplayer::from_json(
+        WorkerConfigBuilder::default()
+            .namespace(NAMESPACE)
+            .task_queue(starter.get_task_queue())
+            .build()
+            .unwrap(),
+        &history.to_json(),
+    )
+    .unwrap();
+    let mut worker = Worker::new_from_core(replayer.worker(), starter.get_task_queue().to_owned());
+    worker.register_wf(wf_name.to_owned(), timers_wf(2));
+    worker.run().await.unwrap();
+    assert_eq!(replayer.failure(), None);
+}
+
 fn timers_wf(num_timers: u32) -> WorkflowFunction {
     WorkflowFunction::new(move |ctx: WfContext| async move {
         for _ in 1..=num_timers {
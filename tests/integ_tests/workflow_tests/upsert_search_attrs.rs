@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use temporal_client::{WorkflowClientTrait, WorkflowOptions};
 use temporal_sdk::{WfContext, WorkflowResult};
-use temporal_sdk_core_protos::coresdk::AsJsonPayloadExt;
+use temporal_sdk_core_protos::{coresdk::AsJsonPayloadExt, TypedSearchAttributes};
 use temporal_sdk_core_test_utils::CoreWfStarter;
 use uuid::Uuid;
 
@@ -9,6 +9,7 @@ use uuid::Uuid;
 // use for integration tests.
 static TXT_ATTR: &str = "CustomTextField";
 static INT_ATTR: &str = "CustomIntField";
+static KEYWORD_ATTR: &str = "CustomKeywordField";
 
 async fn search_attr_updater(ctx: WfContext) -> WorkflowResult<()> {
     ctx.upsert_search_attributes([
@@ -18,6 +19,22 @@ async fn search_attr_updater(ctx: WfContext) -> WorkflowResult<()> {
     Ok(().into())
 }
 
+async fn typed_search_attr_updater(ctx: WfContext) -> WorkflowResult<()> {
+    // Derive the upserted value from the one the workflow was started with, so the test also
+    // checks the start attributes were decoded
+    let started_with = ctx
+        .search_attributes()
+        .get(INT_ATTR)
+        .and_then(|v| v.as_int())
+        .unwrap_or_default();
+    ctx.upsert_typed_search_attributes(
+        TypedSearchAttributes::new()
+            .with(KEYWORD_ATTR, "goodbye")
+            .with(INT_ATTR, started_with + 97),
+    )?;
+    Ok(().into())
+}
+
 #[tokio::test]
 async fn sends_upsert() {
     let wf_name = "sends_upsert_search_attrs";
@@ -64,3 +81,54 @@ async fn sends_upsert() {
     assert_eq!("\"goodbye\"", txt_attr_payload.to_string());
     assert_eq!("98", int_attr_payload.to_string());
 }
+
+#[tokio::test]
+async fn sends_typed_upsert() {
+    let wf_name = "sends_typed_upsert_search_attrs";
+    let wf_id = Uuid::new_v4();
+    let mut starter = CoreWfStarter::new(wf_name);
+    let mut worker = starter.worker().await;
+    worker.register_wf(wf_name, typed_search_attr_updater);
+    let client = starter.get_client().await;
+    let registry = client.get_search_attributes().await.unwrap();
+    let start_attrs = TypedSearchAttributes::new()
+        .with(KEYWORD_ATTR, "hello")
+        .with(INT_ATTR, 1_i64);
+    registry.validate(&start_attrs).unwrap();
+    let run_id = worker
+        .submit_wf(
+            wf_id.to_string(),
+            wf_name,
+            vec![],
+            WorkflowOptions {
+                search_attributes: Some(start_attrs.into()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    worker.run_until_done().await.unwrap();
+
+    let search_attrs = client
+        .describe_workflow_execution(wf_id.to_string(), Some(run_id))
+        .await
+        .unwrap()
+        .workflow_execution_info
+        .unwrap()
+        .search_attributes
+        .unwrap();
+    let search_attrs = registry
+        .decode(
+            &search_attrs
+                .indexed_fields
+                .into_iter()
+                .map(|(k, v)| (k, v.into()))
+                .collect(),
+        )
+        .unwrap();
+    assert_eq!(
+        search_attrs.get(KEYWORD_ATTR).unwrap().as_str(),
+        Some("goodbye")
+    );
+    assert_eq!(search_attrs.get(INT_ATTR).unwrap().as_int(), Some(98));
+}
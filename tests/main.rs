@@ -9,13 +9,10 @@ mod integ_tests {
     mod workflow_tests;
 
     use std::str::FromStr;
-    use temporal_client::WorkflowService;
     use temporal_sdk_core::{
-        init_worker, telemetry_init, ClientOptionsBuilder, ClientTlsConfig, TlsConfig,
-        WorkflowClientTrait,
+        ClientOptionsBuilder, ClientTlsConfig, CoreRuntime, TlsConfig, WorkflowClientTrait,
     };
-    use temporal_sdk_core_api::{worker::WorkerConfigBuilder, CoreTelemetry};
-    use temporal_sdk_core_protos::temporal::api::workflowservice::v1::ListNamespacesRequest;
+    use temporal_sdk_core_api::worker::WorkerConfigBuilder;
     use temporal_sdk_core_test_utils::{
         get_integ_server_options, get_integ_telem_options, NAMESPACE,
     };
@@ -26,13 +23,10 @@ mod integ_tests {
     #[ignore] // Really a compile time check more than anything
     async fn lang_bridge_example() {
         let opts = get_integ_server_options();
-        let telem_d = telemetry_init(&get_integ_telem_options()).unwrap();
-        let mut retrying_client = opts
-            .connect_no_namespace(telem_d.get_metric_meter(), None)
-            .await
-            .unwrap();
+        let runtime = CoreRuntime::new(&get_integ_telem_options()).unwrap();
+        let retrying_client = runtime.connect_client(&opts, "default").await.unwrap();
 
-        let _worker = init_worker(
+        let _worker = runtime.init_worker(
             WorkerConfigBuilder::default()
                 .namespace("default")
                 .task_queue("Wheee!")
@@ -44,9 +38,10 @@ mod integ_tests {
         );
 
         // Do things with worker or client
-        let _ = retrying_client
-            .list_namespaces(ListNamespacesRequest::default())
-            .await;
+        let _ = retrying_client.list_namespaces().await;
+
+        // Drain every worker the runtime created, then flush telemetry
+        runtime.shutdown_all().await;
     }
 
     // TODO: Currently ignored because starting up the docker image with TLS requires some hoop